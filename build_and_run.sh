@@ -25,9 +25,9 @@ echo "📦 Installing dependencies..."
 uv sync --dev
 echo ""
 
-# Build the Rust extension
+# Build the Rust extension (with the optional async API, for the tests)
 echo "🔧 Building Rust extension..."
-uv run maturin develop
+uv run maturin develop --features async
 echo ""
 
 # Run tests
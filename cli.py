@@ -1,6 +1,12 @@
 #!/usr/bin/env python3
 """
 Command-line interface for hierarchical config merging.
+
+`cli.py <base_dir> <target_path>` merges with the Python implementation, the
+default. With `--implementation rust`, and for `cli.py docs` and
+`cli.py validate`, it runs the `hier-config` command instead: the merge is
+`hier-config merge`, `--output` being `--format`, and any other option of
+`hier-config merge` is accepted.
 """
 
 import argparse
@@ -14,9 +20,34 @@ sys.path.append(str(Path(__file__).parent / "src"))
 
 import hierarchical_config_merging as hcm
 
+COMMANDS = {"merge", "get", "explain", "report", "docs", "check-equivalent", "validate"}
+
+
+def hier_config_args(argv):
+    """The `hier-config` arguments of a `cli.py` command line."""
+    if argv[:1] and argv[0] in COMMANDS:
+        return argv
+    args = ["merge"]
+    argv = iter(argv)
+    for arg in argv:
+        flag, _, value = arg.partition("=")
+        if flag == "--implementation":
+            if not value:
+                next(argv, None)
+        elif flag == "--output":
+            args.append("--format" + arg[len(flag):])
+        else:
+            args.append(arg)
+    return args
+
+
 def main():
+    if sys.argv[1:2] and sys.argv[1] in COMMANDS:
+        sys.exit(hcm.rust_cli_main(sys.argv[1:]))
+
     parser = argparse.ArgumentParser(
-        description="Hierarchical YAML config merger"
+        description="Hierarchical YAML config merger",
+        epilog="With --implementation rust, every option of `hier-config merge` is accepted."
     )
     parser.add_argument(
         "base_dir",
@@ -33,34 +64,80 @@ def main():
         help="Implementation to use (python or rust)"
     )
     parser.add_argument(
-        "--output",
-        choices=["json", "yaml"],
+        "--output", "--format",
+        dest="output",
+        choices=["json", "yaml", "properties", "env"],
         default="json",
-        help="Output format (json or yaml)"
+        help="Output format (json, yaml, Java .properties or shell export lines)"
+    )
+    parser.add_argument(
+        "--prefix",
+        default="",
+        help="Prefix of the variable names with --format env, e.g. APP"
+    )
+    parser.add_argument(
+        "--separator",
+        default="_",
+        help="Separator of the variable name parts with --format env"
+    )
+    parser.add_argument(
+        "--strict-env",
+        action="store_true",
+        help="Fail on values with no shell form with --format env, instead of skipping them"
+    )
+    parser.add_argument(
+        "--redact",
+        action="store_true",
+        help="Mask values at key paths like *password*, *secret* and *token* (json and yaml output)"
     )
-    
-    args = parser.parse_args()
-    
-    base_dir = Path(args.base_dir)
-    target_path = Path(args.target_path)
-    
-    if args.implementation == "python":
-        merged_config, errors = hcm.merge_hierarchical_configs(base_dir, target_path)
-    else:  # rust
-        merged_config, errors = hcm.rust_merge_hierarchical_configs(
-            str(base_dir), str(target_path)
-        )
-    
+    parser.add_argument(
+        "--preview",
+        action="store_true",
+        help="Print an indented, truncated view of the merged config with value types instead of the config; secrets are masked"
+    )
+    parser.add_argument(
+        "--print-hash",
+        action="store_true",
+        help="Print the SHA-256 of the merged config instead of the config; it ignores key order and formatting"
+    )
+
+    args, rust_args = parser.parse_known_args()
+    if args.implementation == "rust":
+        sys.exit(hcm.rust_cli_main(hier_config_args(sys.argv[1:])))
+    if rust_args:
+        parser.error(f"{' '.join(rust_args)} needs --implementation rust")
+
+    merged_config, errors = hcm.merge_hierarchical_configs(Path(args.base_dir), Path(args.target_path))
     # Print any errors
     for error in errors:
         print(f"⚠️  {error}", file=sys.stderr)
-    
+
+    output(merged_config, args)
+
+
+def output(merged_config, args):
+    if args.print_hash:
+        print(hcm.rust_config_hash(merged_config))
+        return
+
+    if args.preview:
+        print(hcm.rust_render_tree(merged_config), end="")
+        return
+
+    if args.redact:
+        merged_config = hcm.rust_redact(merged_config)
+
     # Output the merged config
     if args.output == "json":
         print(json.dumps(merged_config, indent=2, ensure_ascii=False))
+    elif args.output == "properties":
+        print(hcm.rust_to_properties(merged_config), end="")
+    elif args.output == "env":
+        env_options = dict(prefix=args.prefix, separator=args.separator, strict_values=args.strict_env)
+        print(hcm.rust_to_env(merged_config, **env_options), end="")
     else:  # yaml
         print(yaml.dump(merged_config, default_flow_style=False, sort_keys=False))
 
+
 if __name__ == "__main__":
-    import sys
-    main()
\ No newline at end of file
+    main()
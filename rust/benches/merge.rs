@@ -0,0 +1,45 @@
+//! Benchmarks `merge_configs_by_depth` and `merge_interned` on a synthetic
+//! 5-level hierarchy of about 50k keys, against a fold of `deep_merge` that
+//! copies the merged config at every layer. Run with
+//! `cargo bench --features test-util`.
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use hierarchical_config_merging::testing::synthetic_configs;
+use hierarchical_config_merging::{deep_merge, merge_configs_by_depth, merge_interned, ConfigValue, MergeOptions};
+
+const LEVELS: usize = 5;
+const KEYS_PER_LEVEL: usize = 10_000;
+
+fn bench_merge(c: &mut Criterion) {
+    let configs = synthetic_configs(LEVELS, KEYS_PER_LEVEL);
+    let mut ordered: Vec<_> = configs.iter().collect();
+    ordered.sort_by_key(|(path, _)| (path.components().count(), path.as_path()));
+
+    let mut group = c.benchmark_group(format!("{} keys over {LEVELS} levels", LEVELS * KEYS_PER_LEVEL));
+    // Each merge takes tens of milliseconds; the default 100 samples would
+    // make a run last minutes.
+    group.sample_size(10);
+    group.bench_function("deep_merge fold (copying)", |b| {
+        b.iter(|| {
+            ordered
+                .iter()
+                .fold(ConfigValue::Mapping(Default::default()), |merged, (_, config)| deep_merge(&merged, config))
+        })
+    });
+    group.bench_function("merge_interned (copying)", |b| {
+        b.iter(|| merge_interned(ordered.iter().map(|(_, config)| *config), &MergeOptions::default()))
+    });
+    group.bench_function("merge_configs_by_depth", |b| {
+        b.iter_batched(
+            || configs.clone(),
+            |configs| merge_configs_by_depth(black_box(configs)).expect("synthetic configs merge"),
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_merge);
+criterion_main!(benches);
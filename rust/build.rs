@@ -0,0 +1,20 @@
+fn main() {
+    #[cfg(feature = "ffi")]
+    write_ffi_header();
+}
+
+/// Generates the C header of `src/ffi.rs` into `OUT_DIR`; the test of
+/// `src/ffi.rs` checks that `include/hierarchical_config_merging.h` matches it.
+#[cfg(feature = "ffi")]
+fn write_ffi_header() {
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    let config = cbindgen::Config::from_file(format!("{crate_dir}/cbindgen.toml")).unwrap();
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(format!("{crate_dir}/src/ffi.rs"))
+        .generate()
+        .expect("Failed to generate the C header")
+        .write_to_file(format!("{}/hierarchical_config_merging.h", std::env::var("OUT_DIR").unwrap()));
+}
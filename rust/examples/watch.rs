@@ -0,0 +1,47 @@
+//! Watches a hierarchy and prints every change to the merged config.
+//!
+//! ```text
+//! cargo run --example watch --features watch -- <base_dir> <target_path>
+//! ```
+
+use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use hierarchical_config_merging::{Change, MergeOptions, Watcher};
+
+fn main() -> Result<()> {
+    let mut args = env::args_os().skip(1);
+    let (Some(base_dir), Some(target_path)) = (args.next(), args.next()) else {
+        return Err(anyhow!("usage: watch <base_dir> <target_path>"));
+    };
+    let (base_dir, target_path) = (PathBuf::from(base_dir), PathBuf::from(target_path));
+
+    let (handle, _watcher) = Watcher::spawn(
+        &base_dir,
+        &target_path,
+        MergeOptions::default(),
+        Duration::from_millis(500),
+    )?;
+    print!("{}", serde_yaml::to_string(&*handle.current())?);
+
+    for event in handle.subscribe() {
+        for entry in &event.changes {
+            match &entry.change {
+                Change::Added(value) => println!("+ {}: {}", entry.path_string(), inline(value)),
+                Change::Removed(value) => println!("- {}: {}", entry.path_string(), inline(value)),
+                Change::Modified { old, new } => {
+                    println!("~ {}: {} -> {}", entry.path_string(), inline(old), inline(new))
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn inline(value: &serde_yaml::Value) -> String {
+    serde_yaml::to_string(value)
+        .map(|s| s.trim_end().replace('\n', " "))
+        .unwrap_or_default()
+}
@@ -0,0 +1,86 @@
+#ifndef HIERARCHICAL_CONFIG_MERGING_H
+#define HIERARCHICAL_CONFIG_MERGING_H
+
+/* Generated by cbindgen from src/ffi.rs; do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Outcome of an `hcm_*` call.
+ */
+typedef enum HcmStatus {
+  HCM_STATUS_OK = 0,
+  /**
+   * A required pointer argument was NULL.
+   */
+  HCM_STATUS_NULL_ARGUMENT = 1,
+  /**
+   * A string argument was not valid UTF-8.
+   */
+  HCM_STATUS_INVALID_UTF8 = 2,
+  /**
+   * A config file is not valid YAML.
+   */
+  HCM_STATUS_PARSE_ERROR = 3,
+  /**
+   * The base or target path is missing, unexpandable, or the target lies
+   * outside the base.
+   */
+  HCM_STATUS_HIERARCHY_ERROR = 4,
+  /**
+   * Keys collided with a collision policy of `error`, or a strict merge
+   * reported warnings.
+   */
+  HCM_STATUS_COLLISION_ERROR = 5,
+  /**
+   * Any other failure, such as an unreadable file.
+   */
+  HCM_STATUS_ERROR = 6,
+  /**
+   * The merge panicked; nothing was returned.
+   */
+  HCM_STATUS_PANIC = 7,
+} HcmStatus;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Merges the hierarchy from `base_dir` down to `target_path` with the
+ * default options.
+ *
+ * On success, `*out_json` receives the merged config as a JSON object and
+ * `*out_warnings` a JSON array of warning strings. On failure, `*out_err`
+ * receives the error message and the other outputs stay NULL. `out_warnings`
+ * and `out_err` may be NULL when the caller does not want them.
+ *
+ * # Safety
+ *
+ * `base_dir` and `target_path` must be NULL or valid NUL-terminated strings;
+ * each `out_*` pointer must be NULL or valid for writes.
+ */
+enum HcmStatus hcm_merge(const char *base_dir,
+                         const char *target_path,
+                         char **out_json,
+                         char **out_warnings,
+                         char **out_err);
+
+/**
+ * Releases a string returned by this library. NULL is ignored.
+ *
+ * # Safety
+ *
+ * `text` must be NULL or a string returned by an `hcm_*` function that was
+ * not freed yet.
+ */
+void hcm_free(char *text);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* HIERARCHICAL_CONFIG_MERGING_H */
@@ -0,0 +1,9 @@
+//! The `hier-config` command, see [`hierarchical_config_merging::cli`].
+
+use std::io;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let status = hierarchical_config_merging::cli::run(&args, &mut io::stdout().lock(), &mut io::stderr().lock());
+    std::process::exit(status);
+}
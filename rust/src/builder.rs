@@ -0,0 +1,407 @@
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+
+use crate::cancel::arm;
+use crate::error::ConfigError;
+use crate::keypath::set_path;
+use crate::limits::measure;
+use crate::metadata::without_metadata;
+use crate::options::MergeOptions;
+use crate::paths::{standard_layers, standard_layers_with};
+use crate::report::MergeReport;
+use crate::{deep_merge_with, find_layer_files, merge_hierarchy, ConfigValue};
+
+/// Where a layer of a [`ConfigBuilder`] came from.
+///
+/// Displays as `defaults`, `file:<path>`, `env:<variable>` or
+/// `override:<key path>`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LayerSource {
+    Defaults,
+    File(PathBuf),
+    Env(String),
+    Override(String),
+}
+
+impl fmt::Display for LayerSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LayerSource::Defaults => f.write_str("defaults"),
+            LayerSource::File(path) => write!(f, "file:{}", path.display()),
+            LayerSource::Env(variable) => write!(f, "env:{variable}"),
+            LayerSource::Override(path) => write!(f, "override:{path}"),
+        }
+    }
+}
+
+enum Layer {
+    Defaults(ConfigValue),
+    Hierarchy { base_dir: PathBuf, target_path: PathBuf },
+    /// `vars` is `None` for the process environment, read at build time.
+    Standard { app_name: String, vars: Option<Vec<(String, PathBuf)>> },
+    /// `vars` is `None` for the process environment, read at build time.
+    Env { prefix: String, vars: Option<Vec<(String, String)>> },
+    Overrides(Vec<(String, ConfigValue)>),
+}
+
+type Validator = Box<dyn Fn(&ConfigValue) -> Result<()>>;
+
+/// Composes a config from several layers, applied in declaration order: each
+/// layer is merged on top of everything declared before it.
+///
+/// ```no_run
+/// # use hierarchical_config_merging::ConfigBuilder;
+/// # fn main() -> anyhow::Result<()> {
+/// let (config, report) = ConfigBuilder::new()
+///     .defaults(serde_yaml::from_str("server: {port: 80}")?)
+///     .hierarchy("configs", "configs/prod/eu")
+///     .env_prefix("APP")
+///     .overrides([("server.port", 8080)])
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Defaults and hierarchies are deep-merged like the files of a hierarchy.
+/// Environment variables and overrides address a single key path and replace
+/// the value found there.
+#[derive(Default)]
+pub struct ConfigBuilder {
+    layers: Vec<Layer>,
+    options: MergeOptions,
+    validators: Vec<Validator>,
+}
+
+impl ConfigBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Options for every hierarchy layer, also used to merge the layers with
+    /// each other. `strict` and `fail_on_empty` apply to each hierarchy on its
+    /// own.
+    pub fn options(mut self, options: MergeOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// A programmatic layer, typically declared first.
+    pub fn defaults(mut self, value: ConfigValue) -> Self {
+        self.layers.push(Layer::Defaults(value));
+        self
+    }
+
+    /// The merged hierarchy from `base_dir` down to `target_path`.
+    pub fn hierarchy(mut self, base_dir: impl Into<PathBuf>, target_path: impl Into<PathBuf>) -> Self {
+        self.layers.push(Layer::Hierarchy {
+            base_dir: base_dir.into(),
+            target_path: target_path.into(),
+        });
+        self
+    }
+
+    /// The files of the system and user config directories of `app_name`,
+    /// see [`standard_layers`], each directory a layer on top of the one
+    /// before. Directories that do not exist or hold no config file are
+    /// skipped. Declared before [`ConfigBuilder::hierarchy`], the project
+    /// hierarchy overrides them. The locations are read from the process
+    /// environment when the config is built.
+    pub fn standard_layers(mut self, app_name: impl Into<String>) -> Self {
+        self.layers.push(Layer::Standard {
+            app_name: app_name.into(),
+            vars: None,
+        });
+        self
+    }
+
+    /// Like [`ConfigBuilder::standard_layers`], looking up `XDG_CONFIG_DIRS`,
+    /// `XDG_CONFIG_HOME` and `HOME` in `vars`.
+    pub fn standard_layers_from<I, K, V>(mut self, app_name: impl Into<String>, vars: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<PathBuf>,
+    {
+        self.layers.push(Layer::Standard {
+            app_name: app_name.into(),
+            vars: Some(vars.into_iter().map(|(k, v)| (k.into(), v.into())).collect()),
+        });
+        self
+    }
+
+    /// Process environment variables named `<prefix>_<path>`, read when the
+    /// config is built. See [`ConfigBuilder::env_vars`] for the naming rules.
+    pub fn env_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.layers.push(Layer::Env {
+            prefix: prefix.into(),
+            vars: None,
+        });
+        self
+    }
+
+    /// Like [`ConfigBuilder::env_prefix`], taking the variables from `vars`.
+    ///
+    /// `APP_SERVER__PORT=8080` with prefix `APP` sets `server.port`: the part
+    /// after the prefix is lowercased and split at double underscores. Values
+    /// are read as YAML, so `8080` is a number and `true` a boolean. Variables
+    /// are applied in name order.
+    pub fn env_vars<I, K, V>(mut self, prefix: impl Into<String>, vars: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.layers.push(Layer::Env {
+            prefix: prefix.into(),
+            vars: Some(vars.into_iter().map(|(k, v)| (k.into(), v.into())).collect()),
+        });
+        self
+    }
+
+    /// Values set at dotted key paths such as `server.tls.enabled`.
+    pub fn overrides<I, K, V>(mut self, pairs: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<ConfigValue>,
+    {
+        self.layers.push(Layer::Overrides(
+            pairs.into_iter().map(|(k, v)| (k.into(), v.into())).collect(),
+        ));
+        self
+    }
+
+    /// Checks the final config; [`ConfigBuilder::build`] fails with the first
+    /// error returned.
+    pub fn validate(mut self, validator: impl Fn(&ConfigValue) -> Result<()> + 'static) -> Self {
+        self.validators.push(Box::new(validator));
+        self
+    }
+
+    /// Applies every layer and runs the validators. The report collects the
+    /// entries and files of all hierarchy layers, in declaration order.
+    pub fn build(&self) -> Result<(ConfigValue, MergeReport), ConfigError> {
+        let options = &arm(&self.options);
+        let mut config = ConfigValue::Mapping(serde_yaml::Mapping::new());
+        let mut report = MergeReport::default();
+
+        for layer in &self.layers {
+            match layer {
+                Layer::Defaults(value) => config = deep_merge_with(&config, value, options),
+                Layer::Hierarchy { base_dir, target_path } => {
+                    self.merge_hierarchy_layer(&mut config, &mut report, base_dir, target_path, options)?;
+                }
+                Layer::Standard { app_name, vars } => {
+                    let dirs = match vars {
+                        Some(vars) => standard_layers_with(app_name, |name| {
+                            vars.iter().find(|(key, _)| key == name).map(|(_, value)| value.clone())
+                        }),
+                        None => standard_layers(app_name),
+                    };
+                    for dir in dirs {
+                        if dir.is_dir() && !find_layer_files(&dir, &dir, options)?.is_empty() {
+                            self.merge_hierarchy_layer(&mut config, &mut report, &dir, &dir, options)?;
+                        }
+                    }
+                }
+                Layer::Env { prefix, vars } => {
+                    let mut vars = match vars {
+                        Some(vars) => vars.clone(),
+                        None => env::vars().collect(),
+                    };
+                    vars.sort();
+                    for (name, raw) in &vars {
+                        if let Some(path) = env_key_path(prefix, name) {
+                            set_path(&mut config, &path, env_value(raw))
+                                .with_context(|| format!("Invalid layer {}", LayerSource::Env(name.clone())))?;
+                        }
+                    }
+                }
+                Layer::Overrides(pairs) => {
+                    for (path, value) in pairs {
+                        set_path(&mut config, path, value.clone())
+                            .with_context(|| format!("Invalid layer {}", LayerSource::Override(path.clone())))?;
+                    }
+                }
+            }
+        }
+
+        let validated = without_metadata(&config, options);
+        for validator in &self.validators {
+            validator(&validated).map_err(|source| ConfigError::Validation { source })?;
+        }
+        report.stats = measure(&config);
+        Ok((config, report))
+    }
+
+    /// Merges the hierarchy from `base_dir` down to `target_path` on top of
+    /// `config`, adding its entries and files to `report`.
+    fn merge_hierarchy_layer(
+        &self,
+        config: &mut ConfigValue,
+        report: &mut MergeReport,
+        base_dir: &Path,
+        target_path: &Path,
+        options: &MergeOptions,
+    ) -> Result<(), ConfigError> {
+        let (merged, layer_report) = merge_hierarchy(base_dir, target_path, options)?;
+        *config = deep_merge_with(config, &merged, options);
+        report.entries.extend(layer_report.entries);
+        report.files.extend(layer_report.files);
+        Ok(())
+    }
+
+    /// Builds and deserializes the config into `T`.
+    pub fn build_as<T: DeserializeOwned>(&self) -> Result<T, ConfigError> {
+        let (config, _) = self.build()?;
+        Ok(serde_yaml::from_value(config).context("Failed to deserialize the built config")?)
+    }
+}
+
+/// Key path addressed by `name` under `prefix`, if the variable belongs to it.
+fn env_key_path(prefix: &str, name: &str) -> Option<String> {
+    let rest = name.strip_prefix(prefix)?.strip_prefix('_')?;
+    if rest.is_empty() {
+        return None;
+    }
+    Some(rest.to_lowercase().split("__").collect::<Vec<_>>().join("."))
+}
+
+fn env_value(raw: &str) -> ConfigValue {
+    serde_yaml::from_str(raw).unwrap_or_else(|_| ConfigValue::String(raw.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use serde::Deserialize;
+
+    fn yaml(text: &str) -> ConfigValue {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    fn hierarchy() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("prod")).unwrap();
+        fs::write(dir.path().join("config.yaml"), "server:\n  host: base\n  port: 80\n").unwrap();
+        fs::write(dir.path().join("prod/config.yaml"), "server:\n  host: prod\n").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_layers_apply_in_declaration_order() {
+        let dir = hierarchy();
+        let (config, report) = ConfigBuilder::new()
+            .defaults(yaml("server:\n  host: default\n  workers: 4\nlog: info\n"))
+            .hierarchy(dir.path(), dir.path().join("prod"))
+            .env_vars("APP", [("APP_SERVER__PORT", "9090"), ("APP_LOG", "debug"), ("OTHER_LOG", "x")])
+            .overrides([("server.port", 8080)])
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            config,
+            yaml("server:\n  host: prod\n  workers: 4\n  port: 8080\nlog: debug\n")
+        );
+        assert_eq!(report.files.len(), 2);
+
+        // Declared first, the overrides lose against everything after them.
+        let (config, _) = ConfigBuilder::new()
+            .overrides([("server.port", 8080)])
+            .hierarchy(dir.path(), dir.path().join("prod"))
+            .env_vars("APP", [("APP_SERVER__PORT", "9090")])
+            .build()
+            .unwrap();
+        assert_eq!(config["server"]["port"], 9090);
+    }
+
+    #[test]
+    fn test_build_as_and_validation() {
+        #[derive(Deserialize)]
+        struct Server {
+            host: String,
+            port: u16,
+        }
+        #[derive(Deserialize)]
+        struct App {
+            server: Server,
+        }
+
+        let dir = hierarchy();
+        let builder = ConfigBuilder::new().hierarchy(dir.path(), dir.path().join("prod"));
+        let app: App = builder.build_as().unwrap();
+        assert_eq!((app.server.host.as_str(), app.server.port), ("prod", 80));
+
+        let err = builder
+            .validate(|config| match config["server"]["port"].as_u64() {
+                Some(port) if port >= 1024 => Ok(()),
+                _ => Err(anyhow::anyhow!("server.port must be unprivileged")),
+            })
+            .build()
+            .unwrap_err();
+        match err {
+            ConfigError::Validation { source } => assert_eq!(source.to_string(), "server.port must be unprivileged"),
+            other => panic!("Expected Validation, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_project_hierarchy_overrides_standard_layers() {
+        let project = hierarchy();
+        let home = tempfile::tempdir().unwrap();
+        let xdg_home = home.path().join("xdg");
+        fs::create_dir_all(xdg_home.join("app")).unwrap();
+        fs::write(xdg_home.join("app/config.yaml"), "server:\n  port: 7000\n  tls: true\n").unwrap();
+        let system = home.path().join("system");
+        fs::create_dir_all(system.join("app")).unwrap();
+        fs::write(system.join("app/config.yaml"), "server:\n  tls: false\n  workers: 2\n").unwrap();
+        // Present but empty, and missing: both skipped without a report entry.
+        fs::create_dir_all(home.path().join("empty/app")).unwrap();
+        let dirs = std::env::join_paths([system.clone(), home.path().join("empty"), home.path().join("missing")]).unwrap();
+
+        let vars = [
+            ("XDG_CONFIG_HOME", xdg_home.clone()),
+            ("XDG_CONFIG_DIRS", PathBuf::from(dirs)),
+            ("HOME", home.path().to_path_buf()),
+        ];
+        let options = MergeOptions {
+            strict: true,
+            fail_on_empty: true,
+            ..MergeOptions::default()
+        };
+        let (config, report) = ConfigBuilder::new()
+            .options(options)
+            .standard_layers_from("app", vars.clone())
+            .hierarchy(project.path(), project.path().join("prod"))
+            .build()
+            .unwrap();
+        assert_eq!(config, yaml("server:\n  tls: true\n  workers: 2\n  port: 80\n  host: prod\n"));
+        assert_eq!(report.files.len(), 4);
+        assert!(report.files[1].path.ends_with("xdg/app/config.yaml"));
+
+        // Declared last, the user directory wins.
+        let (config, _) = ConfigBuilder::new()
+            .hierarchy(project.path(), project.path().join("prod"))
+            .standard_layers_from("app", vars)
+            .build()
+            .unwrap();
+        assert_eq!(config["server"]["port"], 7000);
+    }
+
+    #[test]
+    fn test_invalid_layer_names_its_source() {
+        let err = ConfigBuilder::new()
+            .defaults(yaml("list: [a]\n"))
+            .overrides([("list[3]", "x")])
+            .build()
+            .unwrap_err();
+        assert!(err.to_string().contains("override:list[3]"));
+        assert_eq!(LayerSource::File(PathBuf::from("a/b.yaml")).to_string(), "file:a/b.yaml");
+        assert_eq!(env_key_path("APP", "APP_DB__POOL_SIZE").as_deref(), Some("db.pool_size"));
+        assert_eq!(env_key_path("APP", "APPLE"), None);
+    }
+}
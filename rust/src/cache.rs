@@ -0,0 +1,322 @@
+//! Parsed files shared between the mergers of many hierarchies, see
+//! [`ParseCache`].
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::cancel::check_cancelled;
+use crate::compose::finish_loading;
+use crate::limits::measure;
+use crate::options::MergeOptions;
+use crate::report::ReportEntry;
+use crate::source::{parse_yaml_bytes, sha256_hex, ConfigSource};
+use crate::{trace, ConfigValue, HierarchyMerger};
+
+// Mergers and the caches they share move between the threads of a service.
+const _: fn() = || {
+    fn send_sync<T: Send + Sync>() {}
+    send_sync::<ParseCache>();
+    send_sync::<HierarchyMerger>();
+};
+
+/// Files parsed by any of the [`HierarchyMerger`]s sharing the cache, see
+/// [`HierarchyMerger::with_parse_cache`].
+///
+/// Entries are keyed by the SHA-256 of the bytes parsed, not by path: a file
+/// is still read by every merger, through its own source, but contents
+/// parsed once are not parsed again, whichever tenant, source or path they
+/// come from. A merger never sees a value parsed from other contents than
+/// those it read, so mergers with different sources and options can share a
+/// cache. Loading the parsed value, conditions and features included, is
+/// left to each merger.
+///
+/// With [`ParseCache::max_cache_bytes`] set, the entries least recently
+/// used are evicted once the estimated size of the parsed values, counted as
+/// for [`crate::MergeStats::bytes_estimate`], passes it; a value larger than
+/// the bound on its own is never kept. Mergers keep what they parsed for the
+/// files of their own targets, so the bound is on what the cache holds for
+/// later parses.
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use hierarchical_config_merging::{HierarchyMerger, MergeOptions, ParseCache};
+/// # let dir = tempfile::tempdir().unwrap();
+/// # std::fs::write(dir.path().join("config.yaml"), "port: 80\n").unwrap();
+/// let cache = Arc::new(ParseCache::new(Some(64 * 1024 * 1024)));
+/// let mut tenant_a = HierarchyMerger::new(dir.path(), MergeOptions::default()).with_parse_cache(cache.clone());
+/// let mut tenant_b = HierarchyMerger::new(dir.path(), MergeOptions::default()).with_parse_cache(cache.clone());
+/// assert_eq!(tenant_a.merge(dir.path()).unwrap(), tenant_b.merge(dir.path()).unwrap());
+/// let stats = cache.stats();
+/// assert_eq!((stats.entries, stats.hits, stats.misses), (1, 1, 1));
+/// ```
+#[derive(Debug, Default)]
+pub struct ParseCache {
+    max_cache_bytes: Option<usize>,
+    state: Mutex<State>,
+}
+
+/// What a [`ParseCache`] holds and how it was used since it was created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct CacheStats {
+    pub entries: usize,
+    /// Estimated size of the values held.
+    pub bytes: usize,
+    /// Contents found parsed.
+    pub hits: u64,
+    /// Contents parsed, or failing to parse.
+    pub misses: u64,
+    /// Entries dropped to stay within [`ParseCache::max_cache_bytes`].
+    pub evictions: u64,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    entries: HashMap<String, Entry>,
+    /// Hash of each entry by the time it was last used, the least recently
+    /// used first.
+    recency: BTreeMap<u64, String>,
+    clock: u64,
+    stats: CacheStats,
+}
+
+#[derive(Debug)]
+struct Entry {
+    config: Arc<ConfigValue>,
+    bytes: usize,
+    used: u64,
+}
+
+impl ParseCache {
+    /// An empty cache, holding at most about `max_cache_bytes` of parsed
+    /// values when set.
+    pub fn new(max_cache_bytes: Option<usize>) -> Self {
+        Self {
+            max_cache_bytes,
+            state: Mutex::default(),
+        }
+    }
+
+    pub fn max_cache_bytes(&self) -> Option<usize> {
+        self.max_cache_bytes
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.lock().stats
+    }
+
+    /// Drops every entry, keeping the counts of hits, misses and evictions.
+    pub fn clear(&self) {
+        let mut state = self.lock();
+        state.entries.clear();
+        state.recency.clear();
+        state.stats.entries = 0;
+        state.stats.bytes = 0;
+    }
+
+    /// Reads and loads `path` like [`crate::compose::load_config_file`],
+    /// parsing its contents only when they are not cached. A file loading as
+    /// it was parsed shares the cached value.
+    pub(crate) fn load(
+        &self,
+        source: &dyn ConfigSource,
+        path: &Path,
+        options: &MergeOptions,
+        entries: &mut Vec<ReportEntry>,
+    ) -> Result<(Arc<ConfigValue>, String)> {
+        check_cancelled(options)?;
+        let (parsed, sha256) = self.parse(source, path)?;
+        let config = finish_loading(source, path, ConfigValue::clone(&parsed), options, entries)?;
+        let config = if config == *parsed { parsed } else { Arc::new(config) };
+        Ok((config, sha256))
+    }
+
+    /// The parsed contents of `path` and their hash. The lock is not held
+    /// while parsing: mergers parsing the same contents at once both parse
+    /// them, and the first to finish fills the entry.
+    fn parse(&self, source: &dyn ConfigSource, path: &Path) -> Result<(Arc<ConfigValue>, String)> {
+        let timer = trace::Timer::start();
+        let bytes = source.read(path)?;
+        let sha256 = sha256_hex(&bytes);
+        if let Some(config) = self.lock().get(&sha256) {
+            return Ok((config, sha256));
+        }
+        let config = Arc::new(parse_yaml_bytes(path, &bytes)?);
+        trace::parsed_file(path, bytes.len(), timer);
+        self.lock().insert(&sha256, &config, self.max_cache_bytes);
+        Ok((config, sha256))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        // Entries are only changed whole: a panic leaves no partial one.
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl State {
+    fn get(&mut self, sha256: &str) -> Option<Arc<ConfigValue>> {
+        let Some(entry) = self.entries.get_mut(sha256) else {
+            self.stats.misses += 1;
+            return None;
+        };
+        self.stats.hits += 1;
+        self.clock += 1;
+        let key = self.recency.remove(&entry.used).expect("every entry has a use");
+        entry.used = self.clock;
+        self.recency.insert(self.clock, key);
+        Some(entry.config.clone())
+    }
+
+    fn insert(&mut self, sha256: &str, config: &Arc<ConfigValue>, max_cache_bytes: Option<usize>) {
+        let bytes = measure(config).bytes_estimate;
+        if self.entries.contains_key(sha256) || max_cache_bytes.is_some_and(|max| bytes > max) {
+            return;
+        }
+        self.clock += 1;
+        self.entries.insert(
+            sha256.to_string(),
+            Entry {
+                config: config.clone(),
+                bytes,
+                used: self.clock,
+            },
+        );
+        self.recency.insert(self.clock, sha256.to_string());
+        self.stats.bytes += bytes;
+        while max_cache_bytes.is_some_and(|max| self.stats.bytes > max)
+            && let Some((_, key)) = self.recency.pop_first()
+        {
+            let evicted = self.entries.remove(&key).expect("every use has an entry");
+            self.stats.bytes -= evicted.bytes;
+            self.stats.evictions += 1;
+        }
+        self.stats.entries = self.entries.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::thread;
+
+    use crate::testing::fixture_tree;
+
+    #[test]
+    fn test_entries_are_shared_by_contents_and_evicted_least_recently_used() {
+        let dir = fixture_tree(&[
+            ("a/config.yaml", "name: same\n"),
+            ("b/config.yaml", "name: same\n"),
+            ("c/config.yaml", "name: diff\n"),
+        ]);
+        // Room for two entries of up to 8 bytes.
+        let cache = Arc::new(ParseCache::new(Some(16)));
+        let mut merger = HierarchyMerger::new(dir.path(), MergeOptions::default()).with_parse_cache(cache.clone());
+        assert_eq!(merger.merge(&dir.path().join("a")).unwrap(), merger.merge(&dir.path().join("b")).unwrap());
+        let stats = CacheStats {
+            entries: 1,
+            bytes: 8,
+            hits: 1,
+            misses: 1,
+            evictions: 0,
+        };
+        assert_eq!(cache.stats(), stats);
+
+        merger.merge(&dir.path().join("c")).unwrap();
+        fs::write(dir.path().join("a/config.yaml"), "name: cc\n").unwrap();
+        merger.merge(&dir.path().join("a")).unwrap();
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.bytes, stats.misses, stats.evictions), (2, 14, 3, 1));
+        // `name: same` was used least recently: another merger parses it again.
+        let mut other = HierarchyMerger::new(dir.path(), MergeOptions::default()).with_parse_cache(cache.clone());
+        other.merge(&dir.path().join("b")).unwrap();
+        assert_eq!((cache.stats().misses, cache.stats().evictions), (4, 2));
+        other.merge(&dir.path().join("a")).unwrap();
+        assert_eq!(cache.stats().hits, 2);
+
+        let (options, mut entries) = (MergeOptions::default(), Vec::new());
+        let load = |file: &str, entries: &mut Vec<_>| cache.load(&crate::FsSource, &dir.path().join(file), &options, entries);
+        let (a, a_hash) = load("c/config.yaml", &mut entries).unwrap();
+        let (b, b_hash) = load("c/config.yaml", &mut entries).unwrap();
+        assert!(Arc::ptr_eq(&a, &b), "files loading as parsed share the cached value");
+        assert_eq!(a_hash, b_hash);
+
+        let tiny = ParseCache::new(Some(4));
+        let (config, _) = tiny.parse(&crate::FsSource, &dir.path().join("c/config.yaml")).unwrap();
+        assert_eq!(*config, serde_yaml::from_str::<ConfigValue>("name: diff\n").unwrap());
+        assert_eq!(tiny.stats().entries, 0);
+        cache.clear();
+        assert_eq!((cache.stats().entries, cache.stats().bytes, cache.stats().hits), (0, 0, 3));
+    }
+
+    #[test]
+    fn test_loading_stays_with_each_merger() {
+        let dir = fixture_tree(&[("config.yaml", "tracing: {__if_feature__: otel, endpoint: x}\nname: api\n")]);
+        let cache = Arc::new(ParseCache::default());
+        let with_otel = MergeOptions {
+            features: ["otel".to_string()].into(),
+            ..MergeOptions::default()
+        };
+        let mut enabled = HierarchyMerger::new(dir.path(), with_otel).with_parse_cache(cache.clone());
+        let mut disabled = HierarchyMerger::new(dir.path(), MergeOptions::default()).with_parse_cache(cache.clone());
+        let yaml = |text| serde_yaml::from_str::<ConfigValue>(text).unwrap();
+        assert_eq!(*enabled.merge(dir.path()).unwrap(), yaml("tracing: {endpoint: x}\nname: api\n"));
+        let (config, report) = disabled.merge_with_report(dir.path()).unwrap();
+        assert_eq!(*config, yaml("name: api\n"));
+        assert_eq!(report.entries.len(), 1);
+        assert_eq!((cache.stats().hits, cache.stats().misses), (1, 1));
+    }
+
+    #[test]
+    fn test_mergers_on_many_threads_match_uncached_merges() {
+        let mut files = vec![("config.yaml".to_string(), "service: {name: base, replicas: 1}\n".to_string())];
+        let mut targets = Vec::new();
+        for region in ["eu", "us", "ap"] {
+            files.push((format!("{region}/config.yaml"), format!("service: {{region: {region}}}\n")));
+            for env in ["dev", "prod"] {
+                let target = format!("{region}/{env}");
+                files.push((format!("{target}/config.yaml"), format!("service: {{replicas: 3}}\nenv: {env}\n")));
+                files.push((format!("{target}/extra.yaml"), "limits: {cpu: 2}\n".to_string()));
+                targets.push(PathBuf::from(target));
+            }
+        }
+        let files: Vec<(&str, &str)> = files.iter().map(|(path, text)| (path.as_str(), text.as_str())).collect();
+        let dir = fixture_tree(&files);
+        let base = dir.path();
+        let expected: Vec<_> = targets
+            .iter()
+            .map(|target| crate::merge_hierarchy(base, &base.join(target), &MergeOptions::default()).unwrap())
+            .collect();
+
+        // Small enough to evict while the threads run.
+        let cache = Arc::new(ParseCache::new(Some(48)));
+        let shared = Mutex::new(HierarchyMerger::new(base, MergeOptions::default()).with_parse_cache(cache.clone()));
+        thread::scope(|scope| {
+            for thread in 0..8 {
+                let (cache, shared, targets, expected) = (cache.clone(), &shared, &targets, &expected);
+                scope.spawn(move || {
+                    let mut own = HierarchyMerger::new(base, MergeOptions::default()).with_parse_cache(cache);
+                    for round in 0..50 {
+                        let index = (thread * 7 + round) % targets.len();
+                        let target = base.join(&targets[index]);
+                        let (config, report) = match round % 3 {
+                            0 => shared.lock().unwrap().merge_with_report(&target).unwrap(),
+                            _ => own.merge_with_report(&target).unwrap(),
+                        };
+                        assert_eq!((&*config, &report), (&expected[index].0, &expected[index].1));
+                        if round % 10 == 0 {
+                            own.clear();
+                        }
+                    }
+                });
+            }
+        });
+        let stats = cache.stats();
+        assert!(stats.bytes <= 48, "{stats:?}");
+        assert!(stats.hits > 0 && stats.evictions > 0, "{stats:?}");
+    }
+}
@@ -0,0 +1,285 @@
+//! Cooperative cancellation of merges, see [`MergeOptions::cancel`] and
+//! [`MergeOptions::timeout`].
+
+use std::borrow::Cow;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use crate::error::ConfigError;
+use crate::options::MergeOptions;
+
+/// A flag cancelling the merges whose options hold the token, or a clone of
+/// it. Merges look at it between the entries of the directory walk, before
+/// parsing each file and between layers, and fail with
+/// [`ConfigError::Cancelled`] once it is set: a merge stuck reading a file
+/// stops when that read returns.
+///
+/// A token made from an `Arc<AtomicBool>` is cancelled by setting that flag
+/// too.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    flag: Arc<AtomicBool>,
+    /// The caller's token, for the token of one merge with a timeout.
+    parent: Option<Box<CancelToken>>,
+    /// The timeout setting `flag`, for such a token.
+    timeout: Option<Duration>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the merges holding this token, and every later one.
+    pub fn cancel(&self) {
+        self.flag.store(true, Ordering::Release);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cause().is_some()
+    }
+
+    /// `None` while not cancelled, otherwise the timeout that cancelled the
+    /// token, if it was one.
+    fn cause(&self) -> Option<Option<Duration>> {
+        if self.flag.load(Ordering::Acquire) {
+            return Some(self.timeout);
+        }
+        self.parent.as_ref()?.cause()
+    }
+}
+
+/// Tokens are equal when they are clones of one another, which cancelling
+/// one cancels alike.
+impl PartialEq for CancelToken {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.flag, &other.flag) && self.parent == other.parent && self.timeout == other.timeout
+    }
+}
+
+impl From<Arc<AtomicBool>> for CancelToken {
+    fn from(flag: Arc<AtomicBool>) -> Self {
+        Self {
+            flag,
+            parent: None,
+            timeout: None,
+        }
+    }
+}
+
+/// Fails with [`ConfigError::Cancelled`] once the token of `options` is
+/// cancelled.
+pub(crate) fn check_cancelled(options: &MergeOptions) -> Result<(), ConfigError> {
+    match options.cancel.as_ref().and_then(CancelToken::cause) {
+        Some(timeout) => Err(ConfigError::Cancelled { timeout }),
+        None => Ok(()),
+    }
+}
+
+/// The options of one merge, see [`arm`].
+pub(crate) struct Armed<'a> {
+    options: Cow<'a, MergeOptions>,
+    _timer: Option<Timer>,
+}
+
+impl Deref for Armed<'_> {
+    type Target = MergeOptions;
+
+    fn deref(&self) -> &MergeOptions {
+        &self.options
+    }
+}
+
+/// `options` for one merge: as they are without a timeout. With one, they
+/// hold a token of their own instead, cancelled with the caller's token or
+/// by a timer thread once the timeout elapses, and no timeout, so that the
+/// merges they are handed on to do not start another timer. The timer stops
+/// when the result is dropped.
+pub(crate) fn arm(options: &MergeOptions) -> Armed<'_> {
+    let Some(timeout) = options.timeout else {
+        return Armed {
+            options: Cow::Borrowed(options),
+            _timer: None,
+        };
+    };
+    let token = CancelToken {
+        flag: Arc::default(),
+        parent: options.cancel.clone().map(Box::new),
+        timeout: Some(timeout),
+    };
+    let timer = Timer::start(timeout, token.flag.clone());
+    let options = MergeOptions {
+        cancel: Some(token),
+        timeout: None,
+        ..options.clone()
+    };
+    Armed {
+        options: Cow::Owned(options),
+        _timer: Some(timer),
+    }
+}
+
+/// Sets a flag once a timeout elapses, unless dropped before.
+struct Timer {
+    stopped: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl Timer {
+    fn start(timeout: Duration, flag: Arc<AtomicBool>) -> Self {
+        let stopped = Arc::new((Mutex::new(false), Condvar::new()));
+        let state = stopped.clone();
+        thread::spawn(move || {
+            let (lock, condvar) = &*state;
+            let guard = lock.lock().unwrap_or_else(PoisonError::into_inner);
+            let (stopped, _) = condvar
+                .wait_timeout_while(guard, timeout, |stopped| !*stopped)
+                .unwrap_or_else(PoisonError::into_inner);
+            if !*stopped {
+                flag.store(true, Ordering::Release);
+            }
+        });
+        Self { stopped }
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        let (lock, condvar) = &*self.stopped;
+        *lock.lock().unwrap_or_else(PoisonError::into_inner) = true;
+        condvar.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use std::sync::atomic::AtomicUsize;
+    use std::time::Instant;
+
+    use anyhow::Result;
+
+    use crate::source::{ConfigSource, Fingerprint, FsSource};
+    use crate::testing::fixture_tree;
+    use crate::{ConfigValue, HierarchyMerger};
+
+    /// Serves the files of a hierarchy from memory, calling `delay` before
+    /// every read.
+    struct SlowSource<F> {
+        files: Vec<(std::path::PathBuf, String)>,
+        reads: AtomicUsize,
+        delay: F,
+    }
+
+    impl<F: Fn(usize) + Send + Sync> ConfigSource for SlowSource<F> {
+        fn read_to_string(&self, path: &Path) -> Result<String> {
+            (self.delay)(self.reads.fetch_add(1, Ordering::SeqCst));
+            let (_, text) = self.files.iter().find(|(file, _)| file == path).expect("a file of the hierarchy");
+            Ok(text.clone())
+        }
+
+        fn fingerprint(&self, path: &Path) -> Result<Fingerprint> {
+            FsSource.fingerprint(path)
+        }
+    }
+
+    /// A hierarchy of `levels` nested directories, discovered on disk and
+    /// read from memory through `delay`.
+    fn slow_hierarchy<F>(levels: usize, delay: F) -> (tempfile::TempDir, std::path::PathBuf, SlowSource<F>) {
+        let mut names = Vec::new();
+        let mut dir = String::new();
+        for level in 0..levels {
+            names.push(format!("{dir}config.yaml"));
+            dir.push_str(&format!("level{level}/"));
+        }
+        let files: Vec<(&str, &str)> = names.iter().map(|name| (name.as_str(), "")).collect();
+        let tree = fixture_tree(&files);
+        let base = tree.path().canonicalize().unwrap();
+        let files = names
+            .iter()
+            .enumerate()
+            .map(|(level, name)| (base.join(name), format!("level: {level}\n")))
+            .collect();
+        let target = base.join(dir.trim_end_matches('/')).parent().unwrap().to_path_buf();
+        let source = SlowSource {
+            files,
+            reads: AtomicUsize::new(0),
+            delay,
+        };
+        (tree, target, source)
+    }
+
+    #[test]
+    fn test_cancel_mid_walk_stops_before_the_next_parse() {
+        let token = CancelToken::new();
+        let cancel = token.clone();
+        let (dir, target, source) = slow_hierarchy(6, move |read| {
+            if read == 2 {
+                cancel.cancel();
+            }
+        });
+        let options = MergeOptions {
+            cancel: Some(token.clone()),
+            ..MergeOptions::default()
+        };
+        let mut merger = HierarchyMerger::with_source(dir.path(), options, source);
+        let err = merger.merge(&target).unwrap_err();
+        assert!(matches!(err, ConfigError::Cancelled { timeout: None }), "{err:?}");
+        assert_eq!(err.to_string(), "Merge cancelled");
+
+        // Cancelled before it starts, the walk stops at its first entry.
+        let err = crate::merge_hierarchy(dir.path(), &target, merger.options()).unwrap_err();
+        assert!(matches!(err, ConfigError::Cancelled { .. }), "{err:?}");
+        let layers = vec![crate::Layer::new("a", 0, ConfigValue::Null)];
+        assert!(matches!(crate::merge_layered(layers, merger.options()), Err(ConfigError::Cancelled { .. })));
+
+        // A shared flag works as a token.
+        let flag = Arc::new(AtomicBool::new(true));
+        let options = MergeOptions {
+            cancel: Some(flag.clone().into()),
+            ..MergeOptions::default()
+        };
+        assert!(crate::merge_hierarchy(dir.path(), &target, &options).is_err());
+        flag.store(false, Ordering::Release);
+        assert!(crate::merge_hierarchy(dir.path(), &target, &options).is_ok());
+    }
+
+    #[test]
+    fn test_timeout_cancels_a_slow_merge() {
+        let (dir, target, source) = slow_hierarchy(8, |_| thread::sleep(Duration::from_millis(50)));
+        let timeout = Duration::from_millis(60);
+        let options = MergeOptions {
+            timeout: Some(timeout),
+            ..MergeOptions::default()
+        };
+        let mut merger = HierarchyMerger::with_source(dir.path(), options, source);
+        let started = Instant::now();
+        let err = merger.merge(&target).unwrap_err();
+        // Eight reads would take 400ms; the one under way when the timer
+        // fires is the last.
+        assert!(started.elapsed() < Duration::from_millis(300), "{:?}", started.elapsed());
+        assert!(matches!(err, ConfigError::Cancelled { timeout: Some(t) } if t == timeout), "{err:?}");
+        assert_eq!(err.to_string(), "Merge cancelled after the timeout of 60ms");
+        // The merger keeps the caller's options.
+        assert_eq!(merger.options().timeout, Some(timeout));
+        assert!(merger.options().cancel.is_none());
+
+        // A merge done in time is left alone, and so are the caller's token
+        // and the merges after it.
+        let token = CancelToken::new();
+        let options = MergeOptions {
+            timeout: Some(Duration::from_millis(10)),
+            cancel: Some(token.clone()),
+            ..MergeOptions::default()
+        };
+        let dir = fixture_tree(&[("config.yaml", "level: 0\n")]);
+        let (config, _) = crate::merge_hierarchy(dir.path(), dir.path(), &options).unwrap();
+        assert_eq!(config["level"], 0);
+        thread::sleep(Duration::from_millis(30));
+        assert!(!token.is_cancelled());
+        assert!(crate::merge_hierarchy(dir.path(), dir.path(), &options).is_ok());
+    }
+}
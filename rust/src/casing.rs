@@ -0,0 +1,294 @@
+//! One spelling convention for every key of a config, see
+//! [`convert_key_case`].
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::keypath::{format_key_path, key_to_string, PathSegment};
+use crate::options::{parse_named, CollisionPolicy, MergeOptions, UnknownOptionValue};
+use crate::report::{ReportEntry, ReportKind};
+use crate::{deep_merge_into, ConfigValue};
+
+/// A convention for spelling keys of several words.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyCase {
+    /// `max_connections`
+    Snake,
+    /// `maxConnections`
+    Camel,
+    /// `max-connections`
+    Kebab,
+}
+
+impl KeyCase {
+    pub const NAMES: &'static [&'static str] = &["snake_case", "camelCase", "kebab-case"];
+
+    /// `key` in this convention. Words are separated by `_`, `-` and
+    /// whitespace, and where a lowercase letter or digit is followed by an
+    /// uppercase letter, or an uppercase letter by one starting a word, as in
+    /// `HTTPServer`. Leading and trailing separators, as in `_internal`, are
+    /// kept as they are; a key of separators only is left alone.
+    ///
+    /// ```
+    /// # use hierarchical_config_merging::KeyCase;
+    /// assert_eq!(KeyCase::Snake.convert("MaxConnections"), "max_connections");
+    /// assert_eq!(KeyCase::Camel.convert("http-server_URL"), "httpServerUrl");
+    /// assert_eq!(KeyCase::Kebab.convert("ipv4Address"), "ipv4-address");
+    /// ```
+    pub fn convert(self, key: &str) -> String {
+        let is_separator = |c: char| c == '_' || c == '-' || c.is_whitespace();
+        let Some(start) = key.find(|c| !is_separator(c)) else {
+            return key.to_string();
+        };
+        let end = key.trim_end_matches(is_separator).len();
+        let words = words(&key[start..end], is_separator);
+
+        let mut converted = key[..start].to_string();
+        for (index, word) in words.iter().enumerate() {
+            match self {
+                KeyCase::Snake | KeyCase::Kebab => {
+                    if index > 0 {
+                        converted.push(if self == KeyCase::Snake { '_' } else { '-' });
+                    }
+                    converted.push_str(&word.to_lowercase());
+                }
+                KeyCase::Camel if index == 0 => converted.push_str(&word.to_lowercase()),
+                KeyCase::Camel => {
+                    let mut chars = word.chars();
+                    converted.extend(chars.next().into_iter().flat_map(char::to_uppercase));
+                    converted.push_str(&chars.as_str().to_lowercase());
+                }
+            }
+        }
+        converted.push_str(&key[end..]);
+        converted
+    }
+}
+
+/// The words of `key`, which has no leading or trailing separator.
+fn words(key: &str, is_separator: impl Fn(char) -> bool) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = key.char_indices().collect();
+    let mut words = Vec::new();
+    let mut start = None;
+    for (index, &(offset, c)) in chars.iter().enumerate() {
+        if is_separator(c) {
+            words.extend(start.take().map(|start| &key[start..offset]));
+            continue;
+        }
+        let previous = index.checked_sub(1).map(|index| chars[index].1);
+        let next = chars.get(index + 1).map(|(_, c)| *c);
+        let boundary = c.is_uppercase()
+            && start.is_some()
+            && previous.is_some_and(|previous| {
+                previous.is_lowercase()
+                    || previous.is_numeric()
+                    || (previous.is_uppercase() && next.is_some_and(char::is_lowercase))
+            });
+        if boundary {
+            words.extend(start.take().map(|start| &key[start..offset]));
+        }
+        start.get_or_insert(offset);
+    }
+    words.extend(start.map(|start| &key[start..]));
+    words
+}
+
+impl fmt::Display for KeyCase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let index = match self {
+            KeyCase::Snake => 0,
+            KeyCase::Camel => 1,
+            KeyCase::Kebab => 2,
+        };
+        f.write_str(Self::NAMES[index])
+    }
+}
+
+impl FromStr for KeyCase {
+    type Err = UnknownOptionValue;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        use KeyCase::*;
+        parse_named("key_case", Self::NAMES, &[Snake, Camel, Kebab], value)
+    }
+}
+
+/// Respells every string key of `config`, at any depth, in `case`. Keys of
+/// one mapping that become the same key are a conflict: the value of the
+/// key written last merges into that of the first, deeply, and a
+/// [`ReportKind::Collision`] entry is returned for it. Other keys, such as
+/// numbers, are left alone.
+///
+/// ```
+/// # use hierarchical_config_merging::{convert_key_case, ConfigValue, KeyCase};
+/// let mut config: ConfigValue = serde_yaml::from_str("maxConnections: 10\nPoolSize: 2\n").unwrap();
+/// let conflicts = convert_key_case(&mut config, KeyCase::Snake);
+/// assert_eq!(config, serde_yaml::from_str::<ConfigValue>("max_connections: 10\npool_size: 2\n").unwrap());
+/// assert!(conflicts.is_empty());
+/// ```
+pub fn convert_key_case(config: &mut ConfigValue, case: KeyCase) -> Vec<ReportEntry> {
+    let mut entries = Vec::new();
+    convert_below(config, case, &MergeOptions::default(), &mut Vec::new(), &mut entries);
+    entries
+}
+
+fn convert_below(
+    value: &mut ConfigValue,
+    case: KeyCase,
+    options: &MergeOptions,
+    path: &mut Vec<PathSegment>,
+    entries: &mut Vec<ReportEntry>,
+) {
+    match value {
+        ConfigValue::Mapping(map) => {
+            let mut converted = serde_yaml::Mapping::with_capacity(map.len());
+            // The original spelling of each converted key.
+            let mut originals: HashMap<String, String> = HashMap::new();
+            for (key, mut item) in std::mem::take(map) {
+                let ConfigValue::String(original) = key else {
+                    path.push(PathSegment::Key(key_to_string(&key)));
+                    convert_below(&mut item, case, options, path, entries);
+                    path.pop();
+                    converted.insert(key, item);
+                    continue;
+                };
+                let key = case.convert(&original);
+                path.push(PathSegment::Key(key.clone()));
+                convert_below(&mut item, case, options, path, entries);
+                match converted.get_mut(key.as_str()) {
+                    Some(first) => {
+                        entries.push(ReportEntry {
+                            kind: ReportKind::Collision,
+                            key_path: Some(format_key_path(path)),
+                            files: Vec::new(),
+                            message: format!(
+                                "Keys '{}' and '{original}' both become '{}' in {case}",
+                                originals[&key],
+                                format_key_path(path)
+                            ),
+                        });
+                        deep_merge_into(first, Cow::Owned(item), options);
+                    }
+                    None => {
+                        originals.insert(key.clone(), original);
+                        converted.insert(ConfigValue::String(key), item);
+                    }
+                }
+                path.pop();
+            }
+            *map = converted;
+        }
+        ConfigValue::Sequence(items) => {
+            for (index, item) in items.iter_mut().enumerate() {
+                path.push(PathSegment::Index(index));
+                convert_below(item, case, options, path, entries);
+                path.pop();
+            }
+        }
+        ConfigValue::Tagged(tagged) => convert_below(&mut tagged.value, case, options, path, entries),
+        _ => {}
+    }
+}
+
+/// [`convert_key_case`] with [`MergeOptions::key_case`], if set, merging
+/// conflicting keys with `options` and reporting them unless collisions are
+/// ignored.
+pub(crate) fn case_merged_keys(mut config: ConfigValue, options: &MergeOptions, entries: &mut Vec<ReportEntry>) -> ConfigValue {
+    if let Some(case) = options.key_case {
+        let mut conflicts = Vec::new();
+        convert_below(&mut config, case, options, &mut Vec::new(), &mut conflicts);
+        if options.collision_policy != CollisionPolicy::Ignore {
+            entries.extend(conflicts);
+        }
+    }
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn yaml(text: &str) -> ConfigValue {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    const KEYS: &[&str] = &["maxConnections", "max_connections", "MaxConnections", "max-connections", "MAX_CONNECTIONS"];
+
+    #[test]
+    fn test_each_convention() {
+        for (case, expected) in [
+            (KeyCase::Snake, "max_connections"),
+            (KeyCase::Camel, "maxConnections"),
+            (KeyCase::Kebab, "max-connections"),
+        ] {
+            for key in KEYS {
+                assert_eq!(case.convert(key), expected, "{key} in {case}");
+            }
+            // Converting again changes nothing.
+            assert_eq!(case.convert(expected), expected);
+        }
+        assert_eq!(KeyCase::Snake.convert("HTTPServer2Port"), "http_server2_port");
+        assert_eq!(KeyCase::Camel.convert("user ID"), "userId");
+        assert_eq!(KeyCase::Kebab.convert("_privateKey__"), "_private-key__");
+        assert_eq!(KeyCase::Snake.convert("__"), "__");
+        assert_eq!("Kebab-Case".parse::<KeyCase>(), Ok(KeyCase::Kebab));
+        assert!("pascal".parse::<KeyCase>().is_err());
+
+        let mut config = yaml("serverConfig: {Port: 80, hostNames: [{primaryHost: a}]}\n1: {innerKey: x}\n");
+        assert!(convert_key_case(&mut config, KeyCase::Kebab).is_empty());
+        assert_eq!(config, yaml("server-config: {port: 80, host-names: [{primary-host: a}]}\n1: {inner-key: x}\n"));
+    }
+
+    #[test]
+    fn test_keys_becoming_one_conflict() {
+        let mut config = yaml("db: {maxConnections: 10, max_connections: 20, pool: {a: 1}, Pool: {b: 2}}\n");
+        let conflicts = convert_key_case(&mut config, KeyCase::Snake);
+        assert_eq!(config, yaml("db: {max_connections: 20, pool: {a: 1, b: 2}}\n"));
+        let messages: Vec<_> = conflicts.iter().map(|entry| (entry.key_path.as_deref(), entry.message.as_str())).collect();
+        assert_eq!(
+            messages,
+            [
+                (
+                    Some("db.max_connections"),
+                    "Keys 'maxConnections' and 'max_connections' both become 'db.max_connections' in snake_case"
+                ),
+                (Some("db.pool"), "Keys 'pool' and 'Pool' both become 'db.pool' in snake_case"),
+            ]
+        );
+        assert!(conflicts.iter().all(|entry| entry.kind == ReportKind::Collision));
+
+        // Merges follow the collision policy.
+        let dir = crate::testing::fixture_tree(&[
+            ("config.yaml", "maxConnections: 10\n"),
+            ("prod/config.yaml", "max_connections: 20\nretryCount: 3\n"),
+        ]);
+        let prod = dir.path().join("prod");
+        let options = MergeOptions {
+            key_case: Some(KeyCase::Snake),
+            ..MergeOptions::default()
+        };
+        let (config, report) = crate::merge_hierarchy(dir.path(), &prod, &options).unwrap();
+        assert_eq!(config, yaml("max_connections: 20\nretry_count: 3\n"));
+        assert_eq!(report.entries.len(), 1);
+        assert_eq!(report.entries[0].kind, ReportKind::Collision);
+
+        let ignore = MergeOptions {
+            collision_policy: CollisionPolicy::Ignore,
+            ..options.clone()
+        };
+        assert!(crate::merge_hierarchy(dir.path(), &prod, &ignore).unwrap().1.entries.is_empty());
+        let error = MergeOptions {
+            collision_policy: CollisionPolicy::Error,
+            ..options.clone()
+        };
+        let err = crate::merge_hierarchy(dir.path(), &prod, &error).unwrap_err();
+        assert!(matches!(err, crate::ConfigError::Collision { .. }), "{err:?}");
+
+        let mut merger = crate::HierarchyMerger::new(dir.path(), options.clone());
+        assert_eq!(*merger.merge(&prod).unwrap(), config);
+        let (traced, _, _) = crate::merge_hierarchy_with_provenance(dir.path(), &prod, &options).unwrap();
+        assert_eq!(traced, config);
+    }
+}
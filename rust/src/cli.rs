@@ -0,0 +1,960 @@
+//! The `hier-config` command line, shared by the `hier-config` binary and
+//! the console script of the Python package, so that both read the same
+//! arguments the same way. See [`run`].
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::anyhow;
+use serde::Serialize;
+
+use crate::diff::{semantic_diff, Equivalence};
+use crate::docs::generate_docs;
+use crate::error::ConfigError;
+use crate::hash::config_hash;
+use crate::keypath::{format_key_path, get_path, parse_key_path};
+use crate::options::{CollisionPolicy, MergeOptions, TypeCheck};
+use crate::output::{to_env_exports, to_json, to_properties_string, to_yaml, EnvOptions};
+use crate::paths::resolve_target_within_base;
+use crate::report::{ContributingFile, JsonEntry, MergeStats};
+use crate::review::render_html_report;
+use crate::source::{parse_yaml_file, FsSource};
+use crate::tree::{DisplayTree, TreeOptions};
+use crate::{merge_hierarchy, merge_hierarchy_with_provenance, ConfigValue, MergeReport, Severity};
+
+/// What `hier-config --help` prints.
+pub const USAGE: &str = "\
+Usage: hier-config <command> <base_dir> <target_path> [options]
+       hier-config check-equivalent <old_base> <new_base> [--target <path>] [options]
+       hier-config validate --base <dir> (--target <path>... | --all-targets) [options]
+
+Commands:
+  merge                 Print the merged config
+  get <key.path>        Print the value at a dotted key path; scalars are printed as they are
+  explain [<key.path>]  Print the file each value comes from, under a key path if given
+  report                Write an HTML page for reviewing the merge, see --html
+  docs                  Print Markdown documentation of every key of the merged config
+  check-equivalent      Fail, printing the differences, unless both bases merge the target to equivalent configs:
+                        the same whatever the key order, null and empty mappings standing for missing keys
+  validate              Merge every target and check it; fail unless all pass
+
+Options:
+  --format <format>             Output format: json (default), yaml, properties or env
+  --print-hash                  With merge, print the SHA-256 of the merged config, whatever its key order
+  --preview                     With merge, print an indented, truncated view of the merged config with
+                                value types; secrets are masked
+  --redact                      With merge, mask the values at key paths like *password*, *secret* and *token*
+  --report-format <format>      Merge report on stderr: text (default), a summary, or json, the whole report
+  --report-file <file>          File the JSON report is written to instead of stderr; with validate, the
+                                reports of all targets
+  --html <file>                 File report writes the page to
+  --compare-to <file>           Merged config report marks the changes against
+  -o, --output-file <file>      File docs writes the Markdown to instead of stdout
+  --base <dir>                  Base directory of validate
+  --target <path>               Target of check-equivalent, relative to both bases (default: the bases);
+                                with validate, a target to check, repeatable
+  --all-targets                 With validate, check every leaf directory under the base, hidden ones left out
+  --require <key.path>          With validate, a key path every merged config must set; repeatable
+  --numbers-by-value            With check-equivalent, take 1 and 1.0 as equal
+  --strict-missing              With check-equivalent, tell null and empty mappings from missing keys
+  --prefix <prefix>             Prefix of the variable names with --format env
+  --separator <separator>       Separator of the variable name parts with --format env
+  --strict-env                  With --format env, fail on values with no shell form instead of leaving them out
+  --profile <name>              Activate a profile; repeatable, later ones take precedence
+  --selector <name=value>       Selector of __when__ conditions; repeatable
+  --feature <name>              Enable the __if_feature__ sections of a feature; repeatable
+  --filename <name>             Merge only the files of that name
+  --extension <ext>             Config file extension; repeatable
+  --max-depth <n>               Merge only the first n directory levels
+  --timeout <seconds>           Give up on a merge still running after that long
+  --sequence-strategy <name>    replace (default), append, prepend or union
+  --collision-policy <name>     warn (default, error with validate), ignore or error
+  --type-check <name>           Check values against the types.yaml of the base: off (default, merged with
+                                validate), merged or each_layer
+  --target-mode <name>          exact (default) or all_descendants
+  --key-case <name>             Respell every key in snake_case, camelCase or kebab-case
+  --null-deletes                A null in an overriding file removes the key
+  --interpolate                 Resolve ${...} references
+  --strict                      Fail on any warning of the merge report
+  --no-expand-paths             Take the paths literally, without expanding ~ and $VARS
+  -v, --verbose                 Print every report entry, not just the summary
+  -h, --help                    Print this help
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Merge,
+    Get,
+    Explain,
+    Report,
+    Docs,
+    CheckEquivalent,
+    Validate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Json,
+    Yaml,
+    Properties,
+    Env,
+}
+
+/// A parsed command line.
+#[derive(Debug)]
+struct Invocation {
+    command: Command,
+    base_dir: PathBuf,
+    target_path: PathBuf,
+    key_path: Option<String>,
+    format: Format,
+    env: EnvOptions,
+    /// What `merge` prints instead of the config, and whether it masks
+    /// secrets in the config.
+    print_hash: bool,
+    preview: bool,
+    redact: bool,
+    /// Whether the merge report is written as JSON, and the file it goes to
+    /// instead of stderr.
+    report_json: bool,
+    report_file: Option<PathBuf>,
+    /// Where `report` writes its page, and the config it compares against.
+    html: Option<PathBuf>,
+    compare_to: Option<PathBuf>,
+    /// Where `docs` writes the Markdown instead of `out`.
+    output_file: Option<PathBuf>,
+    /// The target of `check-equivalent`, whose bases are `base_dir` and
+    /// `target_path`, or the targets of `validate`, and what
+    /// `check-equivalent` takes as equivalent.
+    targets: Vec<PathBuf>,
+    equivalence: Equivalence,
+    /// Whether `validate` checks every leaf directory, and the key paths it
+    /// requires.
+    all_targets: bool,
+    required: Vec<String>,
+    options: MergeOptions,
+    verbose: bool,
+}
+
+/// Runs the command line `args`, program name left out, writing the output
+/// to `out` and the report and errors to `err`. Returns the exit status: 0
+/// on success, 1 when the merge fails and 2 for invalid arguments.
+///
+/// ```
+/// # use hierarchical_config_merging::cli;
+/// let mut out = Vec::new();
+/// let mut err = Vec::new();
+/// assert_eq!(cli::run(&["merge".to_string()], &mut out, &mut err), 2);
+/// assert!(String::from_utf8(err).unwrap().starts_with("hier-config: missing"));
+/// ```
+pub fn run(args: &[String], out: &mut dyn Write, err: &mut dyn Write) -> i32 {
+    let invocation = match parse_args(args) {
+        Ok(Some(invocation)) => invocation,
+        Ok(None) => {
+            return match out.write_all(USAGE.as_bytes()) {
+                Ok(()) => 0,
+                Err(_) => 1,
+            };
+        }
+        Err(message) => {
+            let _ = writeln!(err, "hier-config: {message}\nRun 'hier-config --help' for usage.");
+            return 2;
+        }
+    };
+    match execute(&invocation, out, err) {
+        Ok(()) => 0,
+        Err(e) => {
+            let _ = writeln!(err, "hier-config: {e}");
+            1
+        }
+    }
+}
+
+/// The invocation `args` describe, `None` when they ask for help.
+fn parse_args(args: &[String]) -> Result<Option<Invocation>, String> {
+    let mut options = MergeOptions {
+        expand_paths: true,
+        ..MergeOptions::default()
+    };
+    let mut format = Format::Json;
+    let mut env = EnvOptions::default();
+    let (mut print_hash, mut preview, mut redact) = (false, false, false);
+    let (mut report_json, mut report_file) = (false, None);
+    let (mut html, mut compare_to, mut output_file) = (None, None, None);
+    let (mut base, mut targets, mut all_targets, mut required) = (None, Vec::new(), false, Vec::new());
+    let mut equivalence = Equivalence::default();
+    let (mut collision_policy, mut type_check) = (None, None);
+    let mut verbose = false;
+    let mut positional = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag, Some(value)),
+            _ => (arg.as_str(), None),
+        };
+        let mut value = || match inline {
+            Some(value) => Ok(value.to_string()),
+            None => args.next().cloned().ok_or_else(|| format!("{flag} expects a value")),
+        };
+        let is_switch = matches!(
+            flag,
+            "-h" | "--help"
+                | "-v"
+                | "--verbose"
+                | "--print-hash"
+                | "--preview"
+                | "--redact"
+                | "--all-targets"
+                | "--strict-env"
+                | "--null-deletes"
+                | "--interpolate"
+                | "--strict"
+                | "--no-expand-paths"
+                | "--numbers-by-value"
+                | "--strict-missing"
+        );
+        if is_switch && inline.is_some() {
+            return Err(format!("{flag} takes no value"));
+        }
+        match flag {
+            "-h" | "--help" => return Ok(None),
+            "-v" | "--verbose" => verbose = true,
+            "--format" => {
+                format = match value()?.as_str() {
+                    "json" => Format::Json,
+                    "yaml" => Format::Yaml,
+                    "properties" => Format::Properties,
+                    "env" => Format::Env,
+                    other => {
+                        return Err(format!("Invalid format '{other}': expected one of json, yaml, properties, env"));
+                    }
+                }
+            }
+            "--print-hash" => print_hash = true,
+            "--preview" => preview = true,
+            "--redact" => redact = true,
+            "--report-format" => {
+                report_json = match value()?.as_str() {
+                    "text" => false,
+                    "json" => true,
+                    other => return Err(format!("Invalid report format '{other}': expected one of text, json")),
+                }
+            }
+            "--report-file" => report_file = Some(PathBuf::from(value()?)),
+            "--html" => html = Some(PathBuf::from(value()?)),
+            "--compare-to" => compare_to = Some(PathBuf::from(value()?)),
+            "-o" | "--output-file" => output_file = Some(PathBuf::from(value()?)),
+            "--base" => base = Some(PathBuf::from(value()?)),
+            "--target" => targets.push(PathBuf::from(value()?)),
+            "--all-targets" => all_targets = true,
+            "--require" => required.push(value()?),
+            "--numbers-by-value" => equivalence.numbers_by_value = true,
+            "--strict-missing" => {
+                equivalence.null_is_missing = false;
+                equivalence.empty_is_missing = false;
+            }
+            "--prefix" => env.prefix = value()?,
+            "--separator" => env.separator = value()?,
+            "--strict-env" => env.strict = true,
+            "--profile" => options.profiles.push(value()?),
+            "--selector" => {
+                let selector = value()?;
+                let Some((name, wanted)) = selector.split_once('=') else {
+                    return Err(format!("Invalid selector '{selector}': expected name=value"));
+                };
+                options.selectors.push((name.to_string(), wanted.to_string()));
+            }
+            "--feature" => {
+                options.features.insert(value()?);
+            }
+            "--filename" => options.filename = Some(value()?),
+            "--extension" => options.extensions.get_or_insert_with(Vec::new).push(value()?),
+            "--max-depth" => {
+                let depth = value()?;
+                let depth = depth.parse().map_err(|_| format!("Invalid depth '{depth}': expected a number"))?;
+                options.max_merge_depth = Some(depth);
+            }
+            "--timeout" => {
+                let timeout = value()?;
+                let seconds = timeout.parse().ok().and_then(|seconds| Duration::try_from_secs_f64(seconds).ok());
+                options.timeout = Some(seconds.ok_or_else(|| format!("Invalid timeout '{timeout}': expected seconds"))?);
+            }
+            "--sequence-strategy" => options.sequence_strategy = value()?.parse().map_err(|e| format!("{e}"))?,
+            "--collision-policy" => collision_policy = Some(value()?.parse().map_err(|e| format!("{e}"))?),
+            "--type-check" => type_check = Some(value()?.parse().map_err(|e| format!("{e}"))?),
+            "--target-mode" => options.target_mode = value()?.parse().map_err(|e| format!("{e}"))?,
+            "--key-case" => options.key_case = Some(value()?.parse().map_err(|e| format!("{e}"))?),
+            "--null-deletes" => options.null_deletes = true,
+            "--interpolate" => options.interpolate = true,
+            "--strict" => options.strict = true,
+            "--no-expand-paths" => options.expand_paths = false,
+            flag if flag.starts_with('-') && flag != "-" => return Err(format!("unknown option '{flag}'")),
+            _ => positional.push(arg.as_str()),
+        }
+    }
+
+    let (command, arity) = match positional.first() {
+        Some(&"merge") => (Command::Merge, 3..=3),
+        Some(&"get") => (Command::Get, 4..=4),
+        Some(&"explain") => (Command::Explain, 3..=4),
+        Some(&"report") => (Command::Report, 3..=3),
+        Some(&"docs") => (Command::Docs, 3..=3),
+        Some(&"check-equivalent") => (Command::CheckEquivalent, 3..=3),
+        Some(&"validate") => (Command::Validate, 1..=1),
+        Some(other) => return Err(format!("unknown command '{other}'")),
+        None => {
+            return Err(
+                "missing command: expected merge, get, explain, report, docs, check-equivalent or validate".to_string(),
+            );
+        }
+    };
+    if positional.len() < 3 && command == Command::CheckEquivalent {
+        return Err("missing old and new base directories for check-equivalent".to_string());
+    }
+    if positional.len() < 3 && command != Command::Validate {
+        return Err(format!("missing base directory and target path for {}", positional[0]));
+    }
+    if !arity.contains(&positional.len()) {
+        return Err(match command {
+            Command::Get if positional.len() == 3 => "missing key path for get".to_string(),
+            _ => format!("unexpected argument '{}'", positional[*arity.end()]),
+        });
+    }
+    if command == Command::Report && html.is_none() {
+        return Err("missing --html <file> for report".to_string());
+    }
+    if command == Command::CheckEquivalent && targets.len() > 1 {
+        return Err("check-equivalent takes a single --target".to_string());
+    }
+    if report_json && matches!(command, Command::Docs | Command::CheckEquivalent | Command::Validate) {
+        return Err(format!("--report-format json does not apply to {}", positional[0]));
+    }
+    if report_file.is_some() && !report_json && command != Command::Validate {
+        return Err("--report-file needs --report-format json".to_string());
+    }
+    let (base_dir, target_path) = match command {
+        Command::Validate => {
+            let base = base.ok_or("missing --base <dir> for validate")?;
+            match (targets.is_empty(), all_targets) {
+                (true, false) => return Err("missing --target <path> or --all-targets for validate".to_string()),
+                (false, true) => return Err("--target and --all-targets exclude each other".to_string()),
+                _ => {}
+            }
+            (base, PathBuf::new())
+        }
+        _ if base.is_some() => return Err("--base only applies to validate".to_string()),
+        _ => (positional[1].into(), positional[2].into()),
+    };
+    // validate checks for more than a merge does by default.
+    let validating = command == Command::Validate;
+    if let Some(policy) = collision_policy.or(validating.then_some(CollisionPolicy::Error)) {
+        options.collision_policy = policy;
+    }
+    if let Some(check) = type_check.or(validating.then_some(TypeCheck::Merged)) {
+        options.type_check = check;
+    }
+    Ok(Some(Invocation {
+        command,
+        base_dir,
+        target_path,
+        key_path: positional.get(3).map(|path| path.to_string()),
+        format,
+        env,
+        print_hash,
+        preview,
+        redact,
+        report_json,
+        report_file,
+        html,
+        compare_to,
+        output_file,
+        targets,
+        equivalence,
+        all_targets,
+        required,
+        options,
+        verbose,
+    }))
+}
+
+fn execute(invocation: &Invocation, out: &mut dyn Write, err: &mut dyn Write) -> Result<(), ConfigError> {
+    let (base_dir, target_path, options) = (&invocation.base_dir, &invocation.target_path, &invocation.options);
+    let text = match invocation.command {
+        Command::Merge => {
+            let (config, report) =
+                merge_hierarchy(base_dir, target_path, options).map_err(|e| report_failure(e, invocation, err))?;
+            print_report(&report, invocation, err)?;
+            shown(&config, invocation)?
+        }
+        Command::Get => {
+            let (config, report) =
+                merge_hierarchy(base_dir, target_path, options).map_err(|e| report_failure(e, invocation, err))?;
+            print_report(&report, invocation, err)?;
+            let path = invocation.key_path.as_deref().unwrap_or_default();
+            match get_path(&config, path)? {
+                Some(ConfigValue::String(text)) => format!("{text}\n"),
+                Some(ConfigValue::Number(number)) => format!("{number}\n"),
+                Some(ConfigValue::Bool(flag)) => format!("{flag}\n"),
+                Some(ConfigValue::Null) => "null\n".to_string(),
+                Some(value) => formatted(value, invocation)?,
+                None => return Err(ConfigError::MissingKey { path: path.to_string() }),
+            }
+        }
+        Command::Explain => {
+            let (_, report, provenance) = merge_hierarchy_with_provenance(base_dir, target_path, options)
+                .map_err(|e| report_failure(e, invocation, err))?;
+            print_report(&report, invocation, err)?;
+            let prefix = match &invocation.key_path {
+                Some(path) => parse_key_path(path)?,
+                None => Vec::new(),
+            };
+            let mut text = String::new();
+            for (path, file) in provenance.iter() {
+                if parse_key_path(&path)?.starts_with(&prefix) {
+                    text.push_str(&format!("{path}: {}\n", file.display()));
+                }
+            }
+            if text.is_empty() && !prefix.is_empty() {
+                // Within a leaf, such as an item of a sequence
+                match provenance.source_of(&prefix) {
+                    Some(file) => text = format!("{}: {}\n", format_key_path(&prefix), file.display()),
+                    None => return Err(ConfigError::MissingKey { path: format_key_path(&prefix) }),
+                }
+            }
+            text
+        }
+        Command::Report => {
+            let (config, report, provenance) = merge_hierarchy_with_provenance(base_dir, target_path, options)
+                .map_err(|e| report_failure(e, invocation, err))?;
+            print_report(&report, invocation, err)?;
+            let redact = |config: &ConfigValue| options.redaction.apply(config, &mut Vec::new());
+            let previous = match &invocation.compare_to {
+                Some(path) => Some(redact(&parse_yaml_file(&FsSource, path)?)),
+                None => None,
+            };
+            let outcome = (redact(&config), report, provenance).into();
+            let html = render_html_report(&outcome, previous.as_ref());
+            let path = invocation.html.as_deref().expect("report requires --html");
+            std::fs::write(path, html).map_err(ConfigError::io("Failed to write file", path))?;
+            String::new()
+        }
+        Command::Docs => {
+            let docs = generate_docs(base_dir, target_path, options)?;
+            match &invocation.output_file {
+                Some(path) => {
+                    std::fs::write(path, docs).map_err(ConfigError::io("Failed to write file", path))?;
+                    String::new()
+                }
+                None => docs,
+            }
+        }
+        Command::CheckEquivalent => {
+            let mut merged = Vec::new();
+            let target = invocation.targets.first().cloned().unwrap_or_default();
+            for base in [base_dir, target_path] {
+                let target = resolve_target_within_base(base, &target)?.path();
+                let (config, report) = merge_hierarchy(base, &target, options)?;
+                print_report(&report, invocation, err)?;
+                merged.push(config);
+            }
+            let entries = semantic_diff(&merged[0], &merged[1], &invocation.equivalence);
+            if !entries.is_empty() {
+                let text: String = entries
+                    .iter()
+                    .map(|entry| format!("{}\n", entry.redacted(&options.redaction)))
+                    .collect();
+                out.write_all(text.as_bytes()).map_err(|e| ConfigError::Other(e.into()))?;
+                return Err(ConfigError::Other(anyhow!(
+                    "{} and {} merge to different configs: {} difference(s)",
+                    base_dir.display(),
+                    target_path.display(),
+                    entries.len()
+                )));
+            }
+            String::new()
+        }
+        Command::Validate => return validate(invocation, out),
+    };
+    out.write_all(text.as_bytes()).map_err(|e| ConfigError::Other(e.into()))
+}
+
+/// What `merge` prints of `config`: its hash, its preview, or the config in
+/// the output format of `invocation`.
+fn shown(config: &ConfigValue, invocation: &Invocation) -> Result<String, ConfigError> {
+    if invocation.print_hash {
+        return Ok(format!("{}\n", config_hash(config)));
+    }
+    if invocation.preview {
+        let options = TreeOptions {
+            redaction: invocation.options.redaction.clone(),
+            ..TreeOptions::default()
+        };
+        return Ok(DisplayTree::with_options(config, options).to_string());
+    }
+    if invocation.redact {
+        return formatted(&invocation.options.redaction.apply(config, &mut Vec::new()), invocation);
+    }
+    formatted(config, invocation)
+}
+
+/// `value` in the output format of `invocation`, ending with a newline.
+fn formatted(value: &ConfigValue, invocation: &Invocation) -> Result<String, ConfigError> {
+    let mut text = match invocation.format {
+        Format::Json => to_json(value, true)?,
+        Format::Yaml => to_yaml(value)?,
+        Format::Properties => to_properties_string(value)?,
+        Format::Env => to_env_exports(value, &invocation.env)?,
+    };
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+/// The JSON report of `report`, or its summary after every entry when
+/// verbose, as the Python `cli.py` printed them.
+fn print_report(report: &MergeReport, invocation: &Invocation, err: &mut dyn Write) -> Result<(), ConfigError> {
+    if invocation.report_json {
+        return write_json_report(&report.to_json()?, invocation, err);
+    }
+    let write = |err: &mut dyn Write, line: String| writeln!(err, "{line}").map_err(|e| ConfigError::Other(e.into()));
+    if invocation.verbose {
+        for entry in &report.entries {
+            let marker = match entry.kind.severity() {
+                Severity::Info => "info",
+                _ => "warning",
+            };
+            write(err, format!("{marker}: {}", entry.message))?;
+        }
+    }
+    if !report.is_empty() {
+        write(err, format!("Merge report: {}", report.summary()))?;
+    }
+    Ok(())
+}
+
+/// `error`, once its JSON report is written if `invocation` asks for one;
+/// the error of writing it instead if that fails.
+fn report_failure(error: ConfigError, invocation: &Invocation, err: &mut dyn Write) -> ConfigError {
+    if !invocation.report_json {
+        return error;
+    }
+    match MergeReport::failure_json(&error).and_then(|json| write_json_report(&json, invocation, err)) {
+        Ok(()) => error,
+        Err(e) => e,
+    }
+}
+
+/// Writes `json` to the report file of `invocation`, or else to `err`.
+fn write_json_report(json: &str, invocation: &Invocation, err: &mut dyn Write) -> Result<(), ConfigError> {
+    match &invocation.report_file {
+        Some(path) => std::fs::write(path, format!("{json}\n")).map_err(ConfigError::io("Failed to write file", path)),
+        None => writeln!(err, "{json}").map_err(|e| ConfigError::Other(e.into())),
+    }
+}
+
+/// A target `validate` merged.
+struct Checked {
+    target: PathBuf,
+    /// The report of the merge, or the error it failed with and its message.
+    merged: Result<MergeReport, (ConfigError, String)>,
+    /// The required key paths the merged config does not set, each with its
+    /// message.
+    missing: Vec<(String, String)>,
+}
+
+impl Checked {
+    fn passed(&self) -> bool {
+        self.merged.is_ok() && self.missing.is_empty()
+    }
+
+    /// The entries of the JSON report of the target: those of its merge
+    /// report, then the missing keys, as errors; or those of the failure.
+    fn entries(&self) -> Vec<JsonEntry<'_>> {
+        let report = match &self.merged {
+            Ok(report) => report,
+            Err((error, message)) => return MergeReport::failure_entries(error, message),
+        };
+        let missing = self.missing.iter().map(|(path, message)| JsonEntry {
+            kind: "missing_key",
+            severity: Severity::Error,
+            path: Some(path),
+            files: &[],
+            message,
+        });
+        report.entries.iter().map(|entry| entry.json(entry.kind.severity())).chain(missing).collect()
+    }
+}
+
+/// `{"passed", "targets": [{"target", "passed", "report"}]}`, what
+/// `validate` writes to the report file.
+#[derive(Serialize)]
+struct Validation<'a> {
+    passed: bool,
+    targets: Vec<ValidatedTarget<'a>>,
+}
+
+#[derive(Serialize)]
+struct ValidatedTarget<'a> {
+    target: &'a Path,
+    passed: bool,
+    report: ValidatedReport<'a>,
+}
+
+/// A report in the schema of [`MergeReport::to_json`]; without stats when
+/// the merge failed.
+#[derive(Serialize)]
+struct ValidatedReport<'a> {
+    entries: Vec<JsonEntry<'a>>,
+    files: &'a [ContributingFile],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stats: Option<&'a MergeStats>,
+}
+
+/// Merges every target of `validate` below its base, printing a line per
+/// target and its errors, and fails unless all pass.
+fn validate(invocation: &Invocation, out: &mut dyn Write) -> Result<(), ConfigError> {
+    let targets = if invocation.all_targets {
+        let mut leaves = Vec::new();
+        leaf_directories(&invocation.base_dir, &mut leaves)?;
+        leaves
+    } else {
+        invocation.targets.clone()
+    };
+    let checked: Vec<_> = targets
+        .into_iter()
+        .map(|target| match merge_hierarchy(&invocation.base_dir, &target, &invocation.options) {
+            Ok((config, report)) => {
+                let missing = invocation
+                    .required
+                    .iter()
+                    .filter(|path| !matches!(get_path(&config, path), Ok(Some(_))))
+                    .map(|path| (path.clone(), format!("Required key '{path}' is not set")))
+                    .collect();
+                Checked { target, merged: Ok(report), missing }
+            }
+            Err(e) => {
+                let message = e.to_string();
+                Checked { target, merged: Err((e, message)), missing: Vec::new() }
+            }
+        })
+        .collect();
+
+    let mut text = String::new();
+    let mut targets = Vec::new();
+    for checked in &checked {
+        let entries = checked.entries();
+        let warnings = entries.iter().filter(|entry| entry.severity == Severity::Warning).count();
+        let verdict = if checked.passed() { "PASS" } else { "FAIL" };
+        let plural = if warnings == 1 { "" } else { "s" };
+        text.push_str(&format!("{verdict} {} ({warnings} warning{plural})\n", checked.target.display()));
+        for entry in entries.iter().filter(|entry| entry.severity == Severity::Error) {
+            text.push_str(&format!("  {}\n", entry.message));
+        }
+        let report = checked.merged.as_ref().ok();
+        targets.push(ValidatedTarget {
+            target: &checked.target,
+            passed: checked.passed(),
+            report: ValidatedReport {
+                entries,
+                files: report.map(|report| report.files.as_slice()).unwrap_or_default(),
+                stats: report.map(|report| &report.stats),
+            },
+        });
+    }
+    let failed = checked.iter().filter(|checked| !checked.passed()).count();
+    text.push_str(&format!("{} of {} targets passed\n", checked.len() - failed, checked.len()));
+    out.write_all(text.as_bytes()).map_err(|e| ConfigError::Other(e.into()))?;
+
+    if let Some(path) = &invocation.report_file {
+        let validation = Validation { passed: failed == 0, targets };
+        let json = serde_json::to_string_pretty(&validation).map_err(|e| ConfigError::Other(e.into()))?;
+        std::fs::write(path, format!("{json}\n")).map_err(ConfigError::io("Failed to write file", path))?;
+    }
+    if failed > 0 {
+        return Err(ConfigError::Other(anyhow!("{failed} of {} targets failed validation", checked.len())));
+    }
+    Ok(())
+}
+
+/// Adds the directories under `dir`, itself included, that have no
+/// subdirectories to `leaves`, in path order; hidden directories are left
+/// out.
+fn leaf_directories(dir: &Path, leaves: &mut Vec<PathBuf>) -> Result<(), ConfigError> {
+    let mut subdirectories = Vec::new();
+    for entry in std::fs::read_dir(dir).map_err(ConfigError::io("Failed to read directory", dir))? {
+        let entry = entry.map_err(ConfigError::io("Failed to read directory", dir))?;
+        let is_dir = entry.file_type().is_ok_and(|kind| kind.is_dir());
+        if is_dir && !entry.file_name().to_string_lossy().starts_with('.') {
+            subdirectories.push(entry.path());
+        }
+    }
+    if subdirectories.is_empty() {
+        leaves.push(dir.to_path_buf());
+    }
+    subdirectories.sort();
+    for subdirectory in subdirectories {
+        leaf_directories(&subdirectory, leaves)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixture_tree;
+
+    /// The exit status, output and error output of `args`.
+    fn hier_config(args: &[&str]) -> (i32, String, String) {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let status = run(&args, &mut out, &mut err);
+        (status, String::from_utf8(out).unwrap(), String::from_utf8(err).unwrap())
+    }
+
+    #[test]
+    fn test_merge_get_and_explain() {
+        let dir = fixture_tree(&[
+            ("config.yaml", "server: {port: 80, host: a}\nname: base\n"),
+            ("prod/config.yaml", "server: {port: 8080}\n"),
+            ("prod/config@eu.yaml", "name: eu\n"),
+        ]);
+        let base = dir.path().to_str().unwrap();
+        let prod = dir.path().join("prod");
+        let prod = prod.to_str().unwrap();
+
+        let (status, out, err) = hier_config(&["merge", base, prod, "--format", "yaml", "--profile=eu"]);
+        assert_eq!((status, err.as_str()), (0, ""));
+        let merged: ConfigValue = serde_yaml::from_str(&out).unwrap();
+        assert_eq!(merged, serde_yaml::from_str::<ConfigValue>("server: {port: 8080, host: a}\nname: eu\n").unwrap());
+
+        assert_eq!(hier_config(&["get", base, prod, "server.port"]).1, "8080\n");
+        assert_eq!(hier_config(&["get", base, prod, "name"]).1, "base\n");
+        assert_eq!(hier_config(&["get", base, prod, "server", "--format=env"]).1, "export PORT='8080'\nexport HOST='a'\n");
+        let (status, _, err) = hier_config(&["get", base, prod, "server.missing"]);
+        assert_eq!((status, err.as_str()), (1, "hier-config: No value at 'server.missing'\n"));
+
+        let canonical = dir.path().canonicalize().unwrap();
+        let (status, out, _) = hier_config(&["explain", base, prod, "server"]);
+        assert_eq!(status, 0);
+        assert_eq!(
+            out,
+            format!(
+                "server.host: {}\nserver.port: {}\n",
+                canonical.join("config.yaml").display(),
+                canonical.join("prod/config.yaml").display()
+            )
+        );
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        let (status, out, _) = hier_config(&["--help"]);
+        assert_eq!((status, out.as_str()), (0, USAGE));
+        for (args, message) in [
+            (&["merge", "a"][..], "missing base directory and target path for merge"),
+            (&["get", "a", "b"], "missing key path for get"),
+            (&["merge", "a", "b", "c"], "unexpected argument 'c'"),
+            (&["build", "a", "b"], "unknown command 'build'"),
+            (&["merge", "a", "b", "--frobnicate"], "unknown option '--frobnicate'"),
+            (&["merge", "a", "b", "--format"], "--format expects a value"),
+            (&["merge", "a", "b", "--strict=yes"], "--strict takes no value"),
+            (&["merge", "a", "b", "--selector", "env"], "Invalid selector 'env': expected name=value"),
+            (&["report", "a", "b"], "missing --html <file> for report"),
+            (&["check-equivalent", "a"], "missing old and new base directories for check-equivalent"),
+            (&["check-equivalent", "a", "b", "--target", "x", "--target", "y"], "check-equivalent takes a single --target"),
+            (&["validate", "--target", "a"], "missing --base <dir> for validate"),
+            (&["validate", "--base", "a"], "missing --target <path> or --all-targets for validate"),
+            (&["validate", "--base", "a", "--target", "b", "--all-targets"], "--target and --all-targets exclude each other"),
+            (&["validate", "a", "--all-targets"], "unexpected argument 'a'"),
+            (&["merge", "a", "b", "--base", "c"], "--base only applies to validate"),
+            (&["merge", "a", "b", "--report-file", "r.json"], "--report-file needs --report-format json"),
+            (&["docs", "a", "b", "--report-format", "json"], "--report-format json does not apply to docs"),
+            (&["merge", "a", "b", "--report-format", "xml"], "Invalid report format 'xml': expected one of text, json"),
+            (
+                &["merge", "a", "b", "--sequence-strategy", "zip"],
+                "Invalid sequence_strategy 'zip': expected one of replace, append, prepend, union",
+            ),
+        ] {
+            let (status, out, err) = hier_config(args);
+            assert_eq!((status, out.as_str()), (2, ""), "{args:?}");
+            assert!(err.starts_with(&format!("hier-config: {message}\n")), "{args:?}: {err}");
+        }
+    }
+
+    #[test]
+    fn test_report_writes_an_html_page() {
+        let dir = fixture_tree(&[
+            ("config.yaml", "server: {port: 80}\ndb: {password: hunter2}\n"),
+            ("prod/config.yaml", "server: {port: 8080}\n"),
+        ]);
+        let out = fixture_tree(&[("previous.yaml", "server: {port: 80}\n")]);
+        let base = dir.path().to_str().unwrap();
+        let prod = dir.path().join("prod");
+        let page = out.path().join("review.html");
+        let previous = out.path().join("previous.yaml");
+        let args = ["report", base, prod.to_str().unwrap(), "--html", page.to_str().unwrap(), "--compare-to", previous.to_str().unwrap()];
+        assert_eq!(hier_config(&args), (0, String::new(), String::new()));
+        let html = std::fs::read_to_string(&page).unwrap();
+        assert!(html.contains("<span class=\"was\">was 80</span>"), "{html}");
+        assert!(html.contains("<li class=\"node added\">"), "{html}");
+        // Passwords are masked, as in every human-readable output.
+        assert!(!html.contains("hunter2") && html.contains("&lt;redacted&gt;"), "{html}");
+
+        let missing = out.path().join("missing.yaml");
+        let (status, _, err) = hier_config(&["report", base, base, "--html", page.to_str().unwrap(), "--compare-to", missing.to_str().unwrap()]);
+        assert_eq!(status, 1);
+        assert!(err.ends_with(&format!("hier-config: Failed to read file: {}\n", missing.display())), "{err}");
+    }
+
+    #[test]
+    fn test_check_equivalent() {
+        let dir = fixture_tree(&[
+            ("old/config.yaml", "server: {port: 80, host: a}\nratio: 2\n"),
+            ("old/prod/config.yaml", "server: {port: 8080}\ndb: {password: old}\n"),
+            // The same effective configs, spread differently.
+            ("new/config.yaml", "ratio: 2.0\nserver: {host: a}\nextra: null\n"),
+            ("new/prod/config.yaml", "server: {port: 8080}\ndb: {password: new}\nlegacy: {}\n"),
+        ]);
+        let (old, new) = (dir.path().join("old"), dir.path().join("new"));
+        let (old, new) = (old.to_str().unwrap(), new.to_str().unwrap());
+
+        let (status, out, err) = hier_config(&["check-equivalent", old, new, "--numbers-by-value"]);
+        assert_eq!((status, out.as_str()), (1, "- server.port: 80\n"), "{err}");
+        assert!(err.ends_with("merge to different configs: 1 difference(s)\n"), "{err}");
+
+        let (status, out, _) = hier_config(&["check-equivalent", old, new, "--target", "prod", "--numbers-by-value"]);
+        assert_eq!(status, 1);
+        // Values are redacted; the difference still shows.
+        assert_eq!(out, "~ db.password: \"<redacted>\" -> \"<redacted>\"\n");
+
+        let dir = fixture_tree(&[
+            ("old/config.yaml", "server: {port: 80, host: a}\nratio: 2\n"),
+            ("new/config.yaml", "ratio: 2.0\nserver: {host: a, port: 80}\nextra: null\n"),
+        ]);
+        let (old, new) = (dir.path().join("old"), dir.path().join("new"));
+        let (old, new) = (old.to_str().unwrap(), new.to_str().unwrap());
+        assert_eq!(hier_config(&["check-equivalent", old, new, "--numbers-by-value"]), (0, String::new(), String::new()));
+        // Without the option an integer and a float differ.
+        let (status, out, _) = hier_config(&["check-equivalent", old, new]);
+        assert_eq!((status, out.as_str()), (1, "~ ratio: 2 -> 2.0\n"));
+        let (status, out, _) = hier_config(&["check-equivalent", old, new, "--numbers-by-value", "--strict-missing"]);
+        assert_eq!((status, out.as_str()), (1, "+ extra: null\n"));
+        let (status, _, err) = hier_config(&["check-equivalent", old, new, "--target", "../new"]);
+        assert_eq!(status, 1);
+        assert_eq!(err, format!("hier-config: Target path ../new is not within base directory {old}\n"));
+    }
+
+    #[test]
+    fn test_report_goes_to_stderr() {
+        let dir = fixture_tree(&[("a.yaml", "port: 1\n"), ("b.yaml", "port: 2\n")]);
+        let base = dir.path().to_str().unwrap();
+        let (status, out, err) = hier_config(&["merge", base, base, "-v"]);
+        assert_eq!(status, 0);
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&out).unwrap(), serde_json::json!({"port": 2}));
+        assert!(err.starts_with("warning: Key collision"), "{err}");
+        assert!(err.ends_with("Merge report: 1 warning (collision: 1)\n"), "{err}");
+
+        let (status, _, err) = hier_config(&["merge", base, base, "--collision-policy", "error"]);
+        assert_eq!(status, 1);
+        assert!(err.starts_with("hier-config: Found 1 key collision(s)"), "{err}");
+    }
+
+    #[test]
+    fn test_hash_preview_redact_and_strict_env() {
+        let dir = fixture_tree(&[("config.yaml", "db: {host: a, password: hunter2}\nhosts: [{name: a}]\n")]);
+        let base = dir.path().to_str().unwrap();
+        let config = crate::merge_hierarchy(dir.path(), dir.path(), &MergeOptions::default()).unwrap().0;
+
+        assert_eq!(hier_config(&["merge", base, base, "--print-hash"]).1, format!("{}\n", config_hash(&config)));
+        let (status, out, _) = hier_config(&["merge", base, base, "--preview"]);
+        assert_eq!((status, out), (0, DisplayTree::new(&config).to_string()));
+        let (status, out, _) = hier_config(&["merge", base, base, "--redact", "--format", "yaml"]);
+        assert_eq!(status, 0);
+        assert!(out.contains("password: <redacted>") && !out.contains("hunter2"), "{out}");
+
+        let (status, out, _) = hier_config(&["merge", base, base, "--format", "env"]);
+        assert_eq!((status, out.as_str()), (0, "export DB_HOST='a'\nexport DB_PASSWORD='hunter2'\n"));
+        let (status, _, err) = hier_config(&["merge", base, base, "--format", "env", "--strict-env"]);
+        assert_eq!(status, 1);
+        assert!(err.starts_with("hier-config: ") && err.contains("hosts"), "{err}");
+    }
+
+    #[test]
+    fn test_json_report() {
+        let dir = fixture_tree(&[("a.yaml", "port: 1\n"), ("b.yaml", "port: 2\n")]);
+        let base = dir.path().to_str().unwrap();
+        let (_, report) = crate::merge_hierarchy(dir.path(), dir.path(), &MergeOptions::default()).unwrap();
+
+        let (status, out, err) = hier_config(&["merge", base, base, "--report-format", "json"]);
+        assert_eq!((status, out.as_str(), err), (0, "{\n  \"port\": 2\n}\n", format!("{}\n", report.to_json().unwrap())));
+
+        let report_file = dir.path().join("report.json");
+        let report_arg = report_file.to_str().unwrap();
+        let args = ["get", base, base, "port", "--report-format=json", "--report-file", report_arg];
+        assert_eq!(hier_config(&args), (0, "2\n".to_string(), String::new()));
+        assert_eq!(std::fs::read_to_string(&report_file).unwrap(), format!("{}\n", report.to_json().unwrap()));
+
+        // A failed merge writes its failure report.
+        std::fs::remove_file(&report_file).unwrap();
+        let args = ["merge", base, base, "--report-format", "json", "--report-file", report_arg, "--collision-policy", "error"];
+        let (status, _, err) = hier_config(&args);
+        assert_eq!(status, 1);
+        assert!(err.starts_with("hier-config: Found 1 key collision(s)"), "{err}");
+        let written: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&report_file).unwrap()).unwrap();
+        assert_eq!((&written["entries"][0]["kind"], &written["entries"][0]["severity"]), (&"collision".into(), &"error".into()));
+    }
+
+    #[test]
+    fn test_docs() {
+        let dir = fixture_tree(&[("config.yaml", "server: {port: 80}\n"), ("prod/config.yaml", "server: {port: 8080}\n")]);
+        let base = dir.path().to_str().unwrap();
+        let prod = dir.path().join("prod");
+        let docs = generate_docs(dir.path(), &prod, &MergeOptions::default()).unwrap();
+
+        assert_eq!(hier_config(&["docs", base, prod.to_str().unwrap()]), (0, docs.clone(), String::new()));
+        let page = dir.path().join("CONFIG.md");
+        assert_eq!(hier_config(&["docs", base, prod.to_str().unwrap(), "-o", page.to_str().unwrap()]), (0, String::new(), String::new()));
+        assert_eq!(std::fs::read_to_string(&page).unwrap(), docs);
+    }
+
+    #[test]
+    fn test_validate() {
+        let dir = fixture_tree(&[
+            ("config.yaml", "service: api\n"),
+            ("good/config.yaml", "port: 80\n"),
+            ("bad/a.yaml", "port: 80\n"),
+            ("bad/b.yaml", "port: 81\n"),
+            (".git/config.yaml", "x: 1\n"),
+        ]);
+        let base = dir.path().to_str().unwrap();
+        let (good, bad) = (dir.path().join("good"), dir.path().join("bad"));
+        let report_file = dir.path().join("report.json");
+
+        let args = ["validate", "--base", base, "--all-targets", "--require", "port", "--report-file", report_file.to_str().unwrap()];
+        let (status, out, err) = hier_config(&args);
+        assert_eq!(status, 1);
+        // Leaf directories in path order, hidden ones left out; collisions fail by default.
+        assert!(out.starts_with(&format!("FAIL {} (0 warnings)\n  Key collision at depth", bad.display())), "{out}");
+        assert!(out.ends_with(&format!("PASS {} (0 warnings)\n1 of 2 targets passed\n", good.display())), "{out}");
+        assert_eq!(err, "hier-config: 1 of 2 targets failed validation\n");
+        let report: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&report_file).unwrap()).unwrap();
+        assert_eq!(report["passed"], false);
+        let targets: Vec<_> = report["targets"].as_array().unwrap().iter().map(|target| (target["target"].clone(), target["passed"].clone())).collect();
+        assert_eq!(targets, [(bad.to_str().into(), false.into()), (good.to_str().into(), true.into())]);
+        let entry = &report["targets"][0]["report"]["entries"][0];
+        assert_eq!((&entry["kind"], &entry["path"]), (&"collision".into(), &"port".into()));
+        assert_eq!(report["targets"][1]["report"]["files"].as_array().unwrap().len(), 2);
+
+        let good = good.to_str().unwrap();
+        assert_eq!(hier_config(&["validate", "--base", base, "--target", good, "--require", "port"]).0, 0);
+        let (status, out, _) = hier_config(&["validate", "--base", base, "--target", good, "--require", "server.host"]);
+        assert_eq!(status, 1);
+        assert_eq!(out, format!("FAIL {good} (0 warnings)\n  Required key 'server.host' is not set\n0 of 1 targets passed\n"));
+        // As a merge, but warning of collisions.
+        let args = ["validate", "--base", base, "--target", bad.to_str().unwrap(), "--collision-policy", "warn"];
+        assert!(hier_config(&args).1.starts_with(&format!("PASS {} (1 warning)\n", bad.display())));
+    }
+}
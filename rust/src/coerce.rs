@@ -0,0 +1,238 @@
+//! Coercion of string overrides to the type of the value they override, see
+//! [`MergeOptions::coerce_types`].
+
+use std::borrow::Cow;
+use std::path::Path;
+use anyhow::Result;
+
+use crate::error::ConfigError;
+use crate::keypath::{format_key_path, key_to_string, PathSegment};
+use crate::options::{CoercionFailure, MergeOptions};
+use crate::report::{ReportEntry, ReportKind};
+use crate::ConfigValue;
+
+/// `r#override`, about to be merged onto `base`, with every string that
+/// overrides a number or a bool parsed into that type.
+///
+/// Only values at the same key path in both are compared: mappings are
+/// walked key by key, sequences and tags are left alone. A coerced value adds
+/// a [`ReportKind::Coercion`] entry. A string that does not parse adds a
+/// [`ReportKind::TypeConflict`] entry and is handled by
+/// [`MergeOptions::coercion_failure`]. `file` names the overriding file, if
+/// any, in the entries.
+pub(crate) fn coerce_override<'a>(
+    base: &ConfigValue,
+    r#override: &'a ConfigValue,
+    file: Option<&Path>,
+    options: &MergeOptions,
+    entries: &mut Vec<ReportEntry>,
+) -> Result<Cow<'a, ConfigValue>> {
+    if !options.coerce_types {
+        return Ok(Cow::Borrowed(r#override));
+    }
+    let mut coercer = Coercer { file, options, entries };
+    let coerced = coercer.coerce(base, r#override, &mut Vec::new())?;
+    Ok(coerced.map_or(Cow::Borrowed(r#override), Cow::Owned))
+}
+
+struct Coercer<'e, 'f> {
+    file: Option<&'f Path>,
+    options: &'e MergeOptions,
+    entries: &'e mut Vec<ReportEntry>,
+}
+
+impl Coercer<'_, '_> {
+    /// The coerced `r#override`, `None` when nothing in it changes.
+    fn coerce(
+        &mut self,
+        base: &ConfigValue,
+        r#override: &ConfigValue,
+        path: &mut Vec<PathSegment>,
+    ) -> Result<Option<ConfigValue>> {
+        match (base, r#override) {
+            (ConfigValue::Mapping(base_map), ConfigValue::Mapping(override_map)) => {
+                let mut coerced: Option<serde_yaml::Mapping> = None;
+                for (key, value) in override_map {
+                    let Some(base_value) = base_map.get(key) else {
+                        continue;
+                    };
+                    path.push(PathSegment::Key(key_to_string(key)));
+                    let value = self.coerce(base_value, value, path)?;
+                    path.pop();
+                    if let Some(value) = value {
+                        coerced.get_or_insert_with(|| override_map.clone()).insert(key.clone(), value);
+                    }
+                }
+                Ok(coerced.map(ConfigValue::Mapping))
+            }
+            (ConfigValue::Number(_) | ConfigValue::Bool(_), ConfigValue::String(text)) => {
+                let (type_name, parsed) = parse_like(base, text);
+                match parsed {
+                    Some(value) => {
+                        let message = format!(
+                            "Coerced {} at '{}'{} to {type_name}",
+                            self.shown(r#override, path),
+                            format_key_path(path),
+                            self.in_file(),
+                        );
+                        self.push(ReportKind::Coercion, path, message);
+                        Ok(Some(value))
+                    }
+                    None => self.conflict(base, r#override, type_name, path),
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn conflict(
+        &mut self,
+        base: &ConfigValue,
+        r#override: &ConfigValue,
+        type_name: &str,
+        path: &[PathSegment],
+    ) -> Result<Option<ConfigValue>> {
+        let (outcome, value) = match self.options.coercion_failure {
+            CoercionFailure::UseString => ("using the string", None),
+            CoercionFailure::KeepBase => ("keeping the overridden value", Some(base.clone())),
+            CoercionFailure::Error => ("", None),
+        };
+        let message = format!(
+            "Type conflict at '{}'{}: {} is not {type_name}",
+            format_key_path(path),
+            self.in_file(),
+            self.shown(r#override, path),
+        );
+        if self.options.coercion_failure == CoercionFailure::Error {
+            return Err(ConfigError::TypeConflict {
+                path: format_key_path(path),
+                file: self.file.map(Path::to_path_buf),
+                message,
+            }
+            .into());
+        }
+        self.push(ReportKind::TypeConflict, path, format!("{message}; {outcome}"));
+        Ok(value)
+    }
+
+    /// `value` as inline text for a message, unless its path is redacted.
+    fn shown(&self, value: &ConfigValue, path: &[PathSegment]) -> String {
+        let value = self.options.redaction.apply(value, &mut path.to_vec());
+        serde_json::to_string(&value).unwrap_or_else(|_| "?".to_string())
+    }
+
+    fn in_file(&self) -> String {
+        self.file.map(|file| format!(" in {}", file.display())).unwrap_or_default()
+    }
+
+    fn push(&mut self, kind: ReportKind, path: &[PathSegment], message: String) {
+        self.entries.push(ReportEntry {
+            kind,
+            key_path: Some(format_key_path(path)),
+            files: self.file.map(Path::to_path_buf).into_iter().collect(),
+            message,
+        });
+    }
+}
+
+/// The name of `base`'s type and `text` parsed as that type, if it parses.
+fn parse_like(base: &ConfigValue, text: &str) -> (&'static str, Option<ConfigValue>) {
+    let text = text.trim();
+    match base {
+        ConfigValue::Bool(_) => {
+            let parsed = match text.to_ascii_lowercase().as_str() {
+                "true" | "yes" | "on" => Some(true),
+                "false" | "no" | "off" => Some(false),
+                _ => None,
+            };
+            ("a bool", parsed.map(ConfigValue::Bool))
+        }
+        ConfigValue::Number(n) if n.is_f64() => ("a float", text.parse::<f64>().ok().map(ConfigValue::from)),
+        _ => {
+            let parsed = match text.parse::<i64>() {
+                Ok(i) => Some(ConfigValue::from(i)),
+                Err(_) => text.parse::<u64>().ok().map(ConfigValue::from),
+            };
+            ("an integer", parsed)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn yaml(text: &str) -> ConfigValue {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    fn coercing(coercion_failure: CoercionFailure) -> MergeOptions {
+        MergeOptions {
+            coerce_types: true,
+            coercion_failure,
+            ..MergeOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_strings_take_the_overridden_type() {
+        let base = yaml("port: 8080\nratio: 0.5\ndebug: false\nname: api\nnested: {workers: 4}\nlist: [1]\n");
+        let over = yaml("port: '9090'\nratio: ' 1.25 '\ndebug: 'True'\nname: '42'\nnested: {workers: '8'}\nlist: ['2']\nnew: '1'\n");
+        let mut entries = Vec::new();
+
+        let coerced = coerce_override(&base, &over, Some(Path::new("env.yaml")), &coercing(CoercionFailure::UseString), &mut entries).unwrap();
+        assert_eq!(
+            *coerced,
+            yaml("port: 9090\nratio: 1.25\ndebug: true\nname: '42'\nnested: {workers: 8}\nlist: ['2']\nnew: '1'\n")
+        );
+        assert_eq!(entries.len(), 4);
+        assert!(entries.iter().all(|entry| entry.kind == ReportKind::Coercion));
+        assert_eq!(entries[0].message, r#"Coerced "9090" at 'port' in env.yaml to an integer"#);
+
+        let mut entries = Vec::new();
+        let untouched = coerce_override(&base, &over, None, &MergeOptions::default(), &mut entries).unwrap();
+        assert!(matches!(untouched, Cow::Borrowed(_)));
+        assert!(entries.is_empty());
+    }
+
+    #[test]
+    fn test_failures_follow_the_policy() {
+        let base = yaml("port: 8080\ndebug: false\ndb: {password: 1234}\n");
+        let over = yaml("port: http\ndebug: maybe\ndb: {password: hunter2}\n");
+        let coerce = |policy| {
+            let mut entries = Vec::new();
+            coerce_override(&base, &over, None, &coercing(policy), &mut entries).map(|config| (config.into_owned(), entries))
+        };
+
+        let (config, entries) = coerce(CoercionFailure::UseString).unwrap();
+        assert_eq!(config, over);
+        assert_eq!(entries[0].kind, ReportKind::TypeConflict);
+        assert_eq!(entries[0].message, r#"Type conflict at 'port': "http" is not an integer; using the string"#);
+        assert_eq!(entries[2].message, r#"Type conflict at 'db.password': "<redacted>" is not an integer; using the string"#);
+
+        let (config, entries) = coerce(CoercionFailure::KeepBase).unwrap();
+        assert_eq!(config, base);
+        assert_eq!(entries.len(), 3);
+
+        let err = coerce(CoercionFailure::Error).unwrap_err();
+        assert!(matches!(err.downcast_ref::<ConfigError>(), Some(ConfigError::TypeConflict { path, .. }) if path == "port"));
+    }
+
+    #[test]
+    fn test_every_layer_is_coerced() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("a/b")).unwrap();
+        std::fs::write(dir.path().join("config.yaml"), "port: 80\ntls: false\n").unwrap();
+        std::fs::write(dir.path().join("a/config.yaml"), "port: '8080'\n").unwrap();
+        std::fs::write(dir.path().join("a/b/config.yaml"), "tls: 'on'\nport: '8443'\n").unwrap();
+        let options = coercing(CoercionFailure::UseString);
+
+        let (config, report) = crate::merge_hierarchy(dir.path(), &dir.path().join("a/b"), &options).unwrap();
+        assert_eq!(config, yaml("port: 8443\ntls: true\n"));
+        assert_eq!(report.entries.len(), 3);
+        // Coercions are informational: neither warnings nor strict failures.
+        assert!(report.warnings().is_empty());
+        let strict = MergeOptions { strict: true, ..options };
+        assert!(crate::merge_hierarchy(dir.path(), &dir.path().join("a/b"), &strict).is_ok());
+    }
+}
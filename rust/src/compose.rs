@@ -0,0 +1,272 @@
+//! Hydra-style `defaults` lists composing a file from named configs of group
+//! directories, see [`MergeOptions::compose_defaults`].
+
+use std::path::{Path, PathBuf};
+use anyhow::{bail, Result};
+
+use crate::cancel::check_cancelled;
+use crate::conditional::resolve_conditionals;
+use crate::error::ConfigError;
+use crate::feature::drop_disabled_features;
+use crate::keypath::key_to_string;
+use crate::options::MergeOptions;
+use crate::priority::check_priority;
+use crate::report::ReportEntry;
+use crate::source::{load_yaml_file, ConfigSource};
+use crate::{deep_merge_with, ConfigValue};
+
+/// Key of the defaults list and the entry standing for the file's own content.
+const DEFAULTS_KEY: &str = "defaults";
+const SELF_ENTRY: &str = "_self_";
+
+/// Extensions tried, in order, for the config named by a defaults entry.
+const DEFAULT_EXTENSIONS: &[&str] = &["yaml", "yml"];
+
+/// Reads and parses `path` like [`load_yaml_file`], composing its defaults
+/// list when `compose_defaults` is set, then resolving its conditional
+/// values and leaving out the sections of disabled features, with an entry
+/// for each in `entries`. The hash is that of `path` alone.
+/// Fails on a [`crate::PRIORITY_KEY`] that is not an integer, and when the
+/// merge is cancelled before the file is read.
+pub(crate) fn load_config_file(
+    source: &dyn ConfigSource,
+    path: &Path,
+    options: &MergeOptions,
+    entries: &mut Vec<ReportEntry>,
+) -> Result<(ConfigValue, String)> {
+    check_cancelled(options)?;
+    let (config, sha256) = load_yaml_file(source, path)?;
+    Ok((finish_loading(source, path, config, options, entries)?, sha256))
+}
+
+/// `config`, just parsed from `path`, as [`load_config_file`] returns it.
+pub(crate) fn finish_loading(
+    source: &dyn ConfigSource,
+    path: &Path,
+    config: ConfigValue,
+    options: &MergeOptions,
+    entries: &mut Vec<ReportEntry>,
+) -> Result<ConfigValue> {
+    check_priority(path, &config)?;
+    let config = match options.compose_defaults {
+        true => compose(source, path, config, options, &mut vec![path.to_path_buf()])?,
+        false => config,
+    };
+    let config = resolve_conditionals(path, config, options)?;
+    drop_disabled_features(path, config, options, entries)
+}
+
+/// `config`, read from `path`, with its top-level `defaults` list replaced by
+/// the configs it names.
+///
+/// An entry `group: name` loads `<group>/<name>.yaml` (or `.yml`) next to
+/// `path` and nests it under `group`; a `/` in the group nests deeper. A bare
+/// `name` loads `<name>.yaml` and merges it at the top level, and `group: null`
+/// selects nothing. Entries merge in order, the selected configs composing
+/// their own defaults lists first. The rest of the file merges at the
+/// position of `_self_`, last when the list has none. `stack` holds the files
+/// being composed, to catch cycles.
+fn compose(
+    source: &dyn ConfigSource,
+    path: &Path,
+    config: ConfigValue,
+    options: &MergeOptions,
+    stack: &mut Vec<PathBuf>,
+) -> Result<ConfigValue> {
+    let ConfigValue::Mapping(mut own) = config else {
+        return Ok(config);
+    };
+    let Some(defaults) = own.shift_remove(DEFAULTS_KEY) else {
+        return Ok(ConfigValue::Mapping(own));
+    };
+    let ConfigValue::Sequence(entries) = defaults else {
+        bail!("Invalid defaults list in {}: expected a sequence", path.display());
+    };
+    let own = ConfigValue::Mapping(own);
+    let dir = path.parent().unwrap_or(Path::new(""));
+
+    let mut composed = ConfigValue::Mapping(serde_yaml::Mapping::new());
+    let mut merged_self = false;
+    for entry in &entries {
+        let (group, name) = match entry {
+            ConfigValue::String(name) if name == SELF_ENTRY => {
+                composed = deep_merge_with(&composed, &own, options);
+                merged_self = true;
+                continue;
+            }
+            ConfigValue::String(name) => (None, name.as_str()),
+            ConfigValue::Mapping(map) if map.len() == 1 => match map.iter().next() {
+                Some((_, ConfigValue::Null)) => continue,
+                Some((group, ConfigValue::String(name))) => (Some(key_to_string(group)), name.as_str()),
+                _ => bail!("Invalid defaults entry in {}: expected `group: name`", path.display()),
+            },
+            _ => bail!(
+                "Invalid defaults entry in {}: expected a config name or `group: name`",
+                path.display()
+            ),
+        };
+
+        let stem = match &group {
+            Some(group) => dir.join(group).join(name),
+            None => dir.join(name),
+        };
+        let (file, selected) = load_default(source, &stem, path, group.as_deref(), name)?;
+        if stack.contains(&file) {
+            bail!("Defaults cycle: {} lists {}, which is being composed", path.display(), file.display());
+        }
+        stack.push(file.clone());
+        let selected = compose(source, &file, selected, options, stack)?;
+        stack.pop();
+
+        let nested = match &group {
+            Some(group) => group.rsplit('/').fold(selected, |value, key| {
+                let mut map = serde_yaml::Mapping::new();
+                map.insert(ConfigValue::String(key.to_string()), value);
+                ConfigValue::Mapping(map)
+            }),
+            None => selected,
+        };
+        composed = deep_merge_with(&composed, &nested, options);
+    }
+    if !merged_self {
+        composed = deep_merge_with(&composed, &own, options);
+    }
+    Ok(composed)
+}
+
+/// Parses the first existing file among `stem` with each default extension.
+fn load_default(
+    source: &dyn ConfigSource,
+    stem: &Path,
+    listed_in: &Path,
+    group: Option<&str>,
+    name: &str,
+) -> Result<(PathBuf, ConfigValue)> {
+    for extension in DEFAULT_EXTENSIONS {
+        let file = stem.with_extension(extension);
+        match load_yaml_file(source, &file) {
+            Ok((config, _)) => return Ok((file, config)),
+            // Missing files are expected; anything else, such as invalid YAML,
+            // is not.
+            Err(e) if matches!(e.downcast_ref::<ConfigError>(), None | Some(ConfigError::Io { .. })) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(ConfigError::MissingDefault {
+        entry: match group {
+            Some(group) => format!("{group}: {name}"),
+            None => name.to_string(),
+        },
+        file: listed_in.to_path_buf(),
+        expected: stem.with_extension(DEFAULT_EXTENSIONS[0]),
+    }
+    .into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn write(path: &Path, text: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, text).unwrap();
+    }
+
+    fn yaml(text: &str) -> ConfigValue {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    fn composing() -> MergeOptions {
+        MergeOptions {
+            compose_defaults: true,
+            ..MergeOptions::default()
+        }
+    }
+
+    /// The `db` group of the Hydra tutorial.
+    fn hydra_tutorial(config: &str) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        write(&dir.path().join("config.yaml"), config);
+        write(&dir.path().join("db/mysql.yaml"), "driver: mysql\nuser: omry\npassword: secret\n");
+        write(&dir.path().join("db/postgresql.yml"), "driver: postgresql\nuser: postgres_user\npassword: drowssap\ntimeout: 10\n");
+        dir
+    }
+
+    fn merge(dir: &tempfile::TempDir) -> Result<ConfigValue> {
+        Ok(crate::merge_hierarchy(dir.path(), dir.path(), &composing())?.0)
+    }
+
+    #[test]
+    fn test_selecting_default_configs() {
+        let dir = hydra_tutorial("defaults:\n  - db: mysql\n");
+        assert_eq!(
+            merge(&dir).unwrap(),
+            yaml("db: {driver: mysql, user: omry, password: secret}\n")
+        );
+
+        let dir = hydra_tutorial("defaults:\n  - db: postgresql\n");
+        assert_eq!(merge(&dir).unwrap()["db"]["timeout"], ConfigValue::from(10));
+
+        // Without the option the list is an ordinary key.
+        let (config, _) = crate::merge_hierarchy(dir.path(), dir.path(), &MergeOptions::default()).unwrap();
+        assert_eq!(config, yaml("defaults: [{db: postgresql}]\n"));
+    }
+
+    #[test]
+    fn test_self_position_decides_precedence() {
+        let dir = hydra_tutorial("defaults:\n  - db: mysql\ndb:\n  user: root\n");
+        assert_eq!(merge(&dir).unwrap()["db"]["user"], ConfigValue::from("root"));
+
+        let dir = hydra_tutorial("defaults:\n  - _self_\n  - db: mysql\ndb:\n  user: root\n  port: 3306\n");
+        assert_eq!(
+            merge(&dir).unwrap(),
+            yaml("db: {user: omry, port: 3306, driver: mysql, password: secret}\n")
+        );
+    }
+
+    #[test]
+    fn test_nested_groups_and_plain_entries() {
+        let dir = hydra_tutorial("defaults:\n  - common\n  - server/db: mysql\n  - cache: null\n");
+        write(&dir.path().join("common.yaml"), "defaults:\n  - db: postgresql\nname: app\n");
+        write(&dir.path().join("server/db/mysql.yaml"), "driver: mysql\n");
+
+        assert_eq!(
+            merge(&dir).unwrap(),
+            yaml(
+                "db: {driver: postgresql, user: postgres_user, password: drowssap, timeout: 10}\n\
+                 name: app\n\
+                 server: {db: {driver: mysql}}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_missing_config_names_the_listing_file() {
+        let dir = hydra_tutorial("defaults:\n  - db: oracle\n");
+        let err = merge(&dir).unwrap_err();
+        match err.downcast_ref::<ConfigError>() {
+            Some(ConfigError::MissingDefault { entry, file, expected }) => {
+                assert_eq!(entry, "db: oracle");
+                assert_eq!(file, &dir.path().canonicalize().unwrap().join("config.yaml"));
+                assert!(expected.ends_with("db/oracle.yaml"));
+            }
+            other => panic!("Expected MissingDefault, got {other:?}"),
+        }
+        assert!(err.to_string().contains("config.yaml"), "{err}");
+
+        let dir = hydra_tutorial("defaults:\n  - loop\n");
+        write(&dir.path().join("loop.yaml"), "defaults: [loop]\n");
+        assert!(merge(&dir).unwrap_err().to_string().contains("Defaults cycle"));
+    }
+
+    #[test]
+    fn test_in_memory_hierarchies_compose_too() {
+        let files = [
+            (PathBuf::from("config.yaml"), "defaults: [{db: mysql}]\n".to_string()),
+            (PathBuf::from("db/mysql.yaml"), "driver: mysql\n".to_string()),
+        ];
+        let (config, _) = crate::merge_yaml_strings(&files, Path::new(""), &composing()).unwrap();
+        assert_eq!(config, yaml("db: {driver: mysql}\n"));
+    }
+}
@@ -0,0 +1,211 @@
+//! Values chosen by selectors, written `{__when__: {env: prod}, value: …,
+//! else: …}`, see [`WHEN_KEY`].
+
+use std::path::Path;
+
+use anyhow::{bail, Result};
+
+use crate::error::ConfigError;
+use crate::keypath::{format_key_path, key_to_string, PathSegment};
+use crate::options::MergeOptions;
+use crate::ConfigValue;
+
+/// Key marking a conditional value: a mapping `{__when__: <condition>,
+/// value: <then>, else: <otherwise>}` is replaced by `value` when the
+/// condition holds and by `else` otherwise, or removed when it has no
+/// `else`. The condition maps selector names to the value wanted, or a list
+/// of values any of which will do; every selector must match.
+///
+/// Selectors are the pairs of [`MergeOptions::selectors`], then `profile`,
+/// matching any of [`MergeOptions::profiles`], and `os`, the
+/// [`std::env::consts::OS`] of the running program. Conditions are resolved
+/// in every file as it is loaded, so the merge only ever sees the chosen
+/// values.
+///
+/// ```yaml
+/// replicas:
+///   __when__: {env: prod}
+///   value: 3
+///   else: 1
+/// ```
+pub const WHEN_KEY: &str = "__when__";
+
+const VALUE_KEY: &str = "value";
+const ELSE_KEY: &str = "else";
+
+/// `config`, read from `path`, with its conditional values resolved. Fails
+/// on a selector that is not known and on a malformed conditional.
+pub(crate) fn resolve_conditionals(path: &Path, config: ConfigValue, options: &MergeOptions) -> Result<ConfigValue> {
+    Ok(resolve(path, config, options, &mut Vec::new())?.unwrap_or(ConfigValue::Null))
+}
+
+/// `value` with its conditionals resolved, `None` when it is a conditional
+/// without a value to take.
+fn resolve(
+    file: &Path,
+    value: ConfigValue,
+    options: &MergeOptions,
+    path: &mut Vec<PathSegment>,
+) -> Result<Option<ConfigValue>> {
+    match value {
+        ConfigValue::Mapping(mut map) => {
+            if let Some(condition) = map.remove(WHEN_KEY) {
+                if let Some(key) = map.keys().find(|key| !matches!(key.as_str(), Some(VALUE_KEY | ELSE_KEY))) {
+                    bail!(
+                        "Unexpected key '{}' in the conditional value at '{}' in {}",
+                        key_to_string(key),
+                        format_key_path(path),
+                        file.display()
+                    );
+                }
+                let chosen = match holds(file, &condition, options, path)? {
+                    true => map.remove(VALUE_KEY),
+                    false => map.remove(ELSE_KEY),
+                };
+                return match chosen {
+                    Some(chosen) => resolve(file, chosen, options, path),
+                    None => Ok(None),
+                };
+            }
+            let mut resolved = serde_yaml::Mapping::with_capacity(map.len());
+            for (key, item) in map {
+                path.push(PathSegment::Key(key_to_string(&key)));
+                let item = resolve(file, item, options, path)?;
+                path.pop();
+                if let Some(item) = item {
+                    resolved.insert(key, item);
+                }
+            }
+            Ok(Some(ConfigValue::Mapping(resolved)))
+        }
+        ConfigValue::Sequence(items) => {
+            let mut resolved = Vec::with_capacity(items.len());
+            for (index, item) in items.into_iter().enumerate() {
+                path.push(PathSegment::Index(index));
+                resolved.extend(resolve(file, item, options, path)?);
+                path.pop();
+            }
+            Ok(Some(ConfigValue::Sequence(resolved)))
+        }
+        value => Ok(Some(value)),
+    }
+}
+
+/// Whether every selector of `condition` matches.
+fn holds(file: &Path, condition: &ConfigValue, options: &MergeOptions, path: &[PathSegment]) -> Result<bool> {
+    let Some(condition) = condition.as_mapping() else {
+        bail!(
+            "Invalid condition at '{}' in {}: expected a mapping of selectors",
+            format_key_path(path),
+            file.display()
+        );
+    };
+    for (selector, wanted) in condition {
+        let selector = key_to_string(selector);
+        let wanted: Vec<String> = match wanted {
+            ConfigValue::Sequence(items) => items.iter().map(key_to_string).collect(),
+            wanted => vec![key_to_string(wanted)],
+        };
+        let matches = match options.selectors.iter().find(|(name, _)| *name == selector) {
+            Some((_, actual)) => wanted.contains(actual),
+            None if selector == "profile" => options.profiles.iter().any(|profile| wanted.contains(profile)),
+            None if selector == "os" => wanted.iter().any(|os| os == std::env::consts::OS),
+            None => {
+                return Err(ConfigError::UnknownSelector {
+                    selector,
+                    file: file.to_path_buf(),
+                    path: format_key_path(path),
+                }
+                .into());
+            }
+        };
+        if !matches {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolved(text: &str, options: &MergeOptions) -> Result<ConfigValue> {
+        resolve_conditionals(Path::new("config.yaml"), serde_yaml::from_str(text).unwrap(), options)
+    }
+
+    fn yaml(text: &str) -> ConfigValue {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    fn prod() -> MergeOptions {
+        MergeOptions {
+            selectors: vec![("env".to_string(), "prod".to_string())],
+            ..MergeOptions::default()
+        }
+    }
+
+    const REPLICAS: &str = "replicas: {__when__: {env: prod}, value: 3, else: 1}\n\
+                            debug: {__when__: {env: [dev, test]}, value: true}\n\
+                            name: api\n";
+
+    #[test]
+    fn test_matching_condition_takes_the_value() {
+        assert_eq!(resolved(REPLICAS, &prod()).unwrap(), yaml("replicas: 3\nname: api\n"));
+
+        // Each file is resolved as it is loaded, before the merge.
+        let dir = crate::testing::fixture_tree(&[("config.yaml", REPLICAS), ("prod/config.yaml", "name: web\n")]);
+        let (config, _) = crate::merge_hierarchy(dir.path(), &dir.path().join("prod"), &prod()).unwrap();
+        assert_eq!(config, yaml("replicas: 3\nname: web\n"));
+    }
+
+    #[test]
+    fn test_failing_condition_takes_else_or_removes_the_key() {
+        let dev = MergeOptions {
+            selectors: vec![("env".to_string(), "dev".to_string())],
+            ..MergeOptions::default()
+        };
+        assert_eq!(resolved(REPLICAS, &dev).unwrap(), yaml("replicas: 1\ndebug: true\nname: api\n"));
+
+        // Removed from sequences too, and resolved again within the value taken.
+        let text = "hosts: [a, {__when__: {env: dev}, value: b}]\n\
+                    tls: {__when__: {env: prod}, value: {cert: {__when__: {env: dev}, value: x}, on: true}}\n";
+        assert_eq!(resolved(text, &prod()).unwrap(), yaml("hosts: [a]\ntls: {on: true}\n"));
+    }
+
+    #[test]
+    fn test_profile_and_os_selectors() {
+        let options = MergeOptions {
+            profiles: vec!["eu".to_string(), "canary".to_string()],
+            ..MergeOptions::default()
+        };
+        let text = format!(
+            "region: {{__when__: {{profile: eu}}, value: eu, else: us}}\n\
+             shell: {{__when__: {{os: {}, profile: [us, canary]}}, value: native, else: other}}\n",
+            std::env::consts::OS
+        );
+        assert_eq!(resolved(&text, &options).unwrap(), yaml("region: eu\nshell: native\n"));
+
+        // Caller selectors come first.
+        let options = MergeOptions {
+            selectors: vec![("os".to_string(), "plan9".to_string())],
+            ..options
+        };
+        assert_eq!(resolved(&text, &options).unwrap(), yaml("region: eu\nshell: other\n"));
+    }
+
+    #[test]
+    fn test_unknown_selectors_and_malformed_conditionals_fail() {
+        let err = ConfigError::from(resolved("a: {b: {__when__: {region: eu}, value: 1}}\n", &prod()).unwrap_err());
+        assert!(
+            matches!(&err, ConfigError::UnknownSelector { selector, path, .. } if selector == "region" && path == "a.b"),
+            "{err:?}"
+        );
+        assert_eq!(err.to_string(), "Unknown selector 'region' in the condition at 'a.b' in config.yaml");
+
+        let err = resolved("a: {__when__: {env: prod}, value: 1, other: 2}\n", &prod()).unwrap_err();
+        assert!(err.to_string().contains("Unexpected key 'other'"), "{err}");
+        let err = resolved("a: {__when__: prod, value: 1}\n", &prod()).unwrap_err();
+        assert!(err.to_string().contains("expected a mapping of selectors"), "{err}");
+    }
+}
@@ -0,0 +1,250 @@
+//! [`Config`], a merged config with typed lookups by key path.
+
+use std::borrow::Cow;
+use std::path::Path;
+use serde::Deserialize;
+
+use crate::error::ConfigError;
+use crate::keypath::{format_key_path, get_segments, key_to_string, leaf_paths, parse_key_path, PathSegment};
+use crate::options::MergeOptions;
+use crate::report::MergeReport;
+use crate::{merge_hierarchy, ConfigValue};
+
+/// A merged config, owned or borrowed from a larger one, read by dotted key
+/// paths such as `service.ports[0]`.
+///
+/// ```
+/// # use hierarchical_config_merging::Config;
+/// let config = Config::new(serde_yaml::from_str("server: {port: 8080, hosts: [a, b]}").unwrap());
+/// let port: u16 = config.get("server.port").unwrap();
+/// assert_eq!(port, 8080);
+/// assert_eq!(config.get_or("server.workers", 4).unwrap(), 4);
+///
+/// let server = config.subtree("server").unwrap();
+/// assert_eq!(server.keys_at("").unwrap(), ["port", "hosts"]);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config<'a> {
+    value: Cow<'a, ConfigValue>,
+}
+
+impl Config<'static> {
+    pub fn new(value: ConfigValue) -> Self {
+        Config { value: Cow::Owned(value) }
+    }
+}
+
+impl<'a> Config<'a> {
+    /// A config reading `value` in place.
+    pub fn borrowed(value: &'a ConfigValue) -> Self {
+        Config { value: Cow::Borrowed(value) }
+    }
+
+    pub fn value(&self) -> &ConfigValue {
+        &self.value
+    }
+
+    pub fn into_value(self) -> ConfigValue {
+        self.value.into_owned()
+    }
+
+    /// An owned copy, independent of the config this one borrows from.
+    pub fn into_owned(self) -> Config<'static> {
+        Config::new(self.into_value())
+    }
+
+    /// The value at `path` deserialized into `T`. Fails with
+    /// [`ConfigError::MissingKey`] when there is no value at `path` and with
+    /// [`ConfigError::WrongType`] when it is not a `T`. `T` may borrow from
+    /// the config, e.g. a `&str`.
+    pub fn get<'de, T: Deserialize<'de>>(&'de self, path: &str) -> Result<T, ConfigError> {
+        let segments = parse_key_path(path)?;
+        match get_segments(&self.value, &segments) {
+            Some(value) => deserialize(value, &segments),
+            None => Err(ConfigError::MissingKey { path: path.to_string() }),
+        }
+    }
+
+    /// Like [`Config::get`], returning `default` when there is no value at
+    /// `path`.
+    pub fn get_or<'de, T: Deserialize<'de>>(&'de self, path: &str, default: T) -> Result<T, ConfigError> {
+        let segments = parse_key_path(path)?;
+        match get_segments(&self.value, &segments) {
+            Some(value) => deserialize(value, &segments),
+            None => Ok(default),
+        }
+    }
+
+    /// Whether there is a value, null included, at `path`. An invalid path
+    /// has none.
+    pub fn has(&self, path: &str) -> bool {
+        parse_key_path(path).is_ok_and(|segments| get_segments(&self.value, &segments).is_some())
+    }
+
+    /// The keys of the mapping at `path`, in order. An empty path lists the
+    /// top-level keys.
+    pub fn keys_at(&self, path: &str) -> Result<Vec<String>, ConfigError> {
+        match self.lookup(path)? {
+            ConfigValue::Mapping(map) => Ok(map.keys().map(key_to_string).collect()),
+            _ => Err(ConfigError::WrongType {
+                path: path.to_string(),
+                expected: "a mapping".to_string(),
+                source: None,
+            }),
+        }
+    }
+
+    /// The config at `path`, borrowed from this one.
+    pub fn subtree(&self, path: &str) -> Result<Config<'_>, ConfigError> {
+        Ok(Config::borrowed(self.lookup(path)?))
+    }
+
+    /// Every leaf with its dotted key path, as [`leaf_paths`] yields them.
+    pub fn iter_leaves(&self) -> impl Iterator<Item = (String, &ConfigValue)> {
+        leaf_paths(self.value())
+    }
+
+    fn lookup(&self, path: &str) -> Result<&ConfigValue, ConfigError> {
+        get_segments(&self.value, &parse_key_path(path)?).ok_or_else(|| ConfigError::MissingKey { path: path.to_string() })
+    }
+}
+
+impl From<ConfigValue> for Config<'static> {
+    fn from(value: ConfigValue) -> Self {
+        Config::new(value)
+    }
+}
+
+fn deserialize<'a, T: Deserialize<'a>>(value: &'a ConfigValue, segments: &[PathSegment]) -> Result<T, ConfigError> {
+    T::deserialize(value).map_err(|source| ConfigError::WrongType {
+        path: format_key_path(segments),
+        expected: std::any::type_name::<T>().to_string(),
+        source: Some(source),
+    })
+}
+
+/// [`merge_hierarchy`] returning the merged config as a [`Config`].
+pub fn merge_hierarchy_config(
+    base_dir: &Path,
+    target_path: &Path,
+    options: &MergeOptions,
+) -> Result<(Config<'static>, MergeReport), ConfigError> {
+    let (config, report) = merge_hierarchy(base_dir, target_path, options)?;
+    Ok((Config::new(config), report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn config(text: &str) -> Config<'static> {
+        Config::new(serde_yaml::from_str(text).unwrap())
+    }
+
+    const SERVICE: &str = "\
+name: api
+server:
+  port: 8080
+  debug: false
+  hosts: [a, b]
+  tls: null
+limits: {}
+";
+
+    #[test]
+    fn test_typed_getters() {
+        let config = config(SERVICE);
+        assert_eq!(config.get::<u16>("server.port").unwrap(), 8080);
+        assert_eq!(config.get::<&str>("name").unwrap(), "api");
+        assert_eq!(config.get::<Vec<String>>("server.hosts").unwrap(), ["a", "b"]);
+        assert_eq!(config.get::<String>("server.hosts[1]").unwrap(), "b");
+        assert_eq!(config.get::<Option<String>>("server.tls").unwrap(), None);
+
+        assert_eq!(config.get_or("server.workers", 4).unwrap(), 4);
+        assert_eq!(config.get_or("server.port", 1).unwrap(), 8080);
+        assert!(config.get_or("server.debug", 1).is_err());
+    }
+
+    #[test]
+    fn test_errors_name_the_path_and_type() {
+        let config = config(SERVICE);
+        let err = config.get::<u16>("server.workers").unwrap_err();
+        assert!(matches!(&err, ConfigError::MissingKey { path } if path == "server.workers"));
+        assert_eq!(err.to_string(), "No value at 'server.workers'");
+
+        let err = config.get::<u8>("server.port").unwrap_err();
+        assert!(matches!(&err, ConfigError::WrongType { path, expected, source: Some(_) } if path == "server.port" && expected == "u8"));
+        assert_eq!(err.to_string(), "Expected u8 at 'server.port'");
+
+        let err = config.keys_at("name").unwrap_err();
+        assert!(matches!(&err, ConfigError::WrongType { expected, source: None, .. } if expected == "a mapping"));
+        assert!(matches!(config.get::<u16>("server..port"), Err(ConfigError::Other(_))));
+    }
+
+    #[test]
+    fn test_has_and_keys_at() {
+        let config = config(SERVICE);
+        assert!(config.has("server.tls"));
+        assert!(config.has("server.hosts[0]"));
+        assert!(!config.has("server.hosts[2]"));
+        assert!(!config.has("server..tls"));
+        assert_eq!(config.keys_at("").unwrap(), ["name", "server", "limits"]);
+        assert_eq!(config.keys_at("server").unwrap(), ["port", "debug", "hosts", "tls"]);
+        assert!(config.keys_at("limits").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_subtrees_borrow_from_their_config() {
+        let config = config(SERVICE);
+        let server = config.subtree("server").unwrap();
+        assert!(std::ptr::eq(server.value(), &config.value()["server"]));
+        assert_eq!(server.get::<u16>("port").unwrap(), 8080);
+
+        let hosts = server.subtree("hosts").unwrap();
+        assert_eq!(hosts.get::<&str>("[0]").unwrap(), "a");
+        assert!(std::ptr::eq(hosts.value(), &config.value()["server"]["hosts"]));
+
+        // An owned copy outlives the config it came from.
+        let owned = {
+            let config = self::config(SERVICE);
+            config.subtree("server").unwrap().into_owned()
+        };
+        assert!(!owned.get::<bool>("debug").unwrap());
+        assert!(matches!(config.subtree("missing"), Err(ConfigError::MissingKey { .. })));
+    }
+
+    #[test]
+    fn test_leaves_in_document_order() {
+        let config = config(SERVICE);
+        let leaves: Vec<(String, &ConfigValue)> = config.iter_leaves().collect();
+        let paths: Vec<&str> = leaves.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(
+            paths,
+            ["name", "server.port", "server.debug", "server.hosts[0]", "server.hosts[1]", "server.tls", "limits"]
+        );
+        assert_eq!(leaves[1].1, &ConfigValue::from(8080));
+
+        let scalar = Config::new(ConfigValue::from(1));
+        assert_eq!(scalar.iter_leaves().collect::<Vec<_>>(), [(String::new(), &ConfigValue::from(1))]);
+
+        // Every leaf reads back at its path.
+        let by_path: BTreeMap<String, ConfigValue> = config.iter_leaves().map(|(path, value)| (path, value.clone())).collect();
+        for (path, value) in by_path {
+            assert_eq!(config.get::<ConfigValue>(&path).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn test_merge_returns_a_config() {
+        let dir = crate::testing::fixture_tree(&[
+            ("config.yaml", "server: {port: 80, host: localhost}\n"),
+            ("a/config.yaml", "server: {port: 8080}\n"),
+        ]);
+
+        let (config, report) = merge_hierarchy_config(dir.path(), &dir.path().join("a"), &MergeOptions::default()).unwrap();
+        assert_eq!(config.get::<u16>("server.port").unwrap(), 8080);
+        assert_eq!(config.get::<String>("server.host").unwrap(), "localhost");
+        assert_eq!(report.files.len(), 2);
+    }
+}
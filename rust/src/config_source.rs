@@ -0,0 +1,190 @@
+//! A [`config`] source, built with the `config-rs` feature.
+
+use std::path::PathBuf;
+use config::{Map, Source, Value, ValueKind};
+
+use crate::keypath::{key_to_string, PathSegment};
+use crate::options::MergeOptions;
+use crate::provenance::{merge_hierarchy_with_provenance, Provenance};
+use crate::ConfigValue;
+
+/// The merged hierarchy from `base_dir` down to `target_path` as a `config`
+/// crate source. The merge runs on every [`Source::collect`].
+///
+/// ```no_run
+/// # use hierarchical_config_merging::HierarchySource;
+/// let settings = config::Config::builder()
+///     .add_source(HierarchySource::new("configs", "configs/prod/eu"))
+///     .add_source(config::Environment::with_prefix("APP").separator("__"))
+///     .build()?;
+/// # Ok::<(), config::ConfigError>(())
+/// ```
+///
+/// Each value's origin is the file it was taken from. Mapping keys become
+/// strings and tags are dropped. Merge failures are returned as
+/// [`config::ConfigError::Foreign`], wrapping the [`crate::ConfigError`].
+#[derive(Debug, Clone)]
+pub struct HierarchySource {
+    base_dir: PathBuf,
+    target_path: PathBuf,
+    options: MergeOptions,
+}
+
+impl HierarchySource {
+    pub fn new(base_dir: impl Into<PathBuf>, target_path: impl Into<PathBuf>) -> Self {
+        Self {
+            base_dir: base_dir.into(),
+            target_path: target_path.into(),
+            options: MergeOptions::default(),
+        }
+    }
+
+    pub fn options(mut self, options: MergeOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Fails the collection on any warning, such as a key collision; see
+    /// [`MergeOptions::strict`].
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.options.strict = strict;
+        self
+    }
+}
+
+impl Source for HierarchySource {
+    fn clone_into_box(&self) -> Box<dyn Source + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn collect(&self) -> Result<Map<String, Value>, config::ConfigError> {
+        let (config, _, provenance) = merge_hierarchy_with_provenance(&self.base_dir, &self.target_path, &self.options)
+            .map_err(|e| config::ConfigError::Foreign(Box::new(e)))?;
+        match to_config_value(&config, &mut Vec::new(), &provenance).kind {
+            ValueKind::Table(table) => Ok(table),
+            _ => Ok(Map::new()),
+        }
+    }
+}
+
+fn to_config_value(value: &ConfigValue, path: &mut Vec<PathSegment>, provenance: &Provenance) -> Value {
+    let origin = provenance
+        .source_of(path)
+        .map(|source| source.display().to_string());
+    let kind = match value {
+        ConfigValue::Null => ValueKind::Nil,
+        ConfigValue::Bool(b) => ValueKind::Boolean(*b),
+        ConfigValue::Number(n) => match (n.as_i64(), n.as_u64()) {
+            (Some(i), _) => ValueKind::I64(i),
+            (None, Some(u)) => ValueKind::U64(u),
+            _ => ValueKind::Float(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        ConfigValue::String(s) => ValueKind::String(s.clone()),
+        ConfigValue::Sequence(items) => ValueKind::Array(
+            items
+                .iter()
+                .enumerate()
+                .map(|(index, item)| {
+                    path.push(PathSegment::Index(index));
+                    let item = to_config_value(item, path, provenance);
+                    path.pop();
+                    item
+                })
+                .collect(),
+        ),
+        ConfigValue::Mapping(map) => ValueKind::Table(
+            map.iter()
+                .map(|(key, item)| {
+                    let key = key_to_string(key);
+                    path.push(PathSegment::Key(key.clone()));
+                    let item = to_config_value(item, path, provenance);
+                    path.pop();
+                    (key, item)
+                })
+                .collect(),
+        ),
+        ConfigValue::Tagged(tagged) => return to_config_value(&tagged.value, path, provenance),
+    };
+    Value::new(origin.as_ref(), kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ConfigError;
+    use std::fs;
+    use config::{Config, Environment};
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Server {
+        host: String,
+        port: u16,
+        ratio: f64,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct App {
+        server: Server,
+        tags: Vec<String>,
+        debug: bool,
+    }
+
+    fn hierarchy() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("svc")).unwrap();
+        fs::write(
+            dir.path().join("config.yaml"),
+            "server:\n  host: base\n  port: 80\n  ratio: 0.5\ntags: [a, b]\ndebug: false\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("svc/config.yaml"), "server:\n  port: 8080\n").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_env_overrides_the_hierarchy() {
+        let dir = hierarchy();
+        // SAFETY: the variable name is unique to this test.
+        unsafe { std::env::set_var("HCM_CONFIG_RS_TEST__DEBUG", "true") };
+
+        let source = HierarchySource::new(dir.path(), dir.path().join("svc"));
+        let settings = Config::builder()
+            .add_source(source.clone())
+            .add_source(Environment::with_prefix("HCM_CONFIG_RS_TEST").prefix_separator("__").try_parsing(true))
+            .build()
+            .unwrap();
+        let app: App = settings.try_deserialize().unwrap();
+
+        assert_eq!(
+            app,
+            App {
+                server: Server {
+                    host: "base".to_string(),
+                    port: 8080,
+                    ratio: 0.5,
+                },
+                tags: vec!["a".to_string(), "b".to_string()],
+                debug: true,
+            }
+        );
+        let server = source.collect().unwrap().remove("server").unwrap().into_table().unwrap();
+        let leaf = dir.path().canonicalize().unwrap().join("svc/config.yaml");
+        assert_eq!(server["port"].origin(), Some(leaf.display().to_string().as_str()));
+    }
+
+    #[test]
+    fn test_strict_turns_collisions_into_errors() {
+        let dir = hierarchy();
+        fs::write(dir.path().join("other.yaml"), "debug: true\n").unwrap();
+        let source = HierarchySource::new(dir.path(), dir.path().join("svc"));
+
+        assert!(source.collect().is_ok());
+        match source.strict(true).collect() {
+            Err(config::ConfigError::Foreign(e)) => {
+                assert!(matches!(e.downcast_ref(), Some(ConfigError::Strict { .. })), "{e:?}");
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+}
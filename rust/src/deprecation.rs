@@ -0,0 +1,261 @@
+//! Renamed and deprecated keys, see [`MergeOptions::aliases`] and
+//! [`MergeOptions::deprecated_paths`], and values tagged `!deprecated`.
+
+use std::borrow::Cow;
+use std::path::Path;
+use anyhow::{Context, Result};
+
+use crate::keypath::{
+    format_key_path, get_segments, key_to_string, parse_key_path, remove_segments, set_segments, PathSegment,
+};
+use crate::options::MergeOptions;
+use crate::report::{ReportEntry, ReportKind};
+use crate::{deep_merge_with, ConfigValue};
+
+/// `config`, read from `file`, with its aliased keys moved to their canonical
+/// paths, adding a deprecation entry for every aliased or deprecated key it
+/// uses.
+pub(crate) fn apply_deprecations<'a>(
+    config: &'a ConfigValue,
+    file: &Path,
+    options: &MergeOptions,
+    entries: &mut Vec<ReportEntry>,
+) -> Result<Cow<'a, ConfigValue>> {
+    let mut config = Cow::Borrowed(config);
+    for (old, canonical) in &options.aliases {
+        let old_segments = parse_key_path(old).with_context(|| format!("Invalid alias '{old}'"))?;
+        let canonical_segments =
+            parse_key_path(canonical).with_context(|| format!("Invalid alias target '{canonical}'"))?;
+        if get_segments(&config, &old_segments).is_none() {
+            continue;
+        }
+        let renamed = config.to_mut();
+        let Some(value) = remove_segments(renamed, &old_segments) else {
+            continue;
+        };
+        let value = match get_segments(renamed, &canonical_segments) {
+            Some(existing) => deep_merge_with(&value, existing, options),
+            None => value,
+        };
+        set_segments(renamed, &canonical_segments, value)
+            .with_context(|| format!("Cannot move '{old}' to '{canonical}' in {}", file.display()))?;
+        entries.push(deprecation(old, file, format!("; use '{canonical}' instead")));
+    }
+    for path in &options.deprecated_paths {
+        let segments = parse_key_path(path).with_context(|| format!("Invalid deprecated path '{path}'"))?;
+        if get_segments(&config, &segments).is_some() {
+            entries.push(deprecation(path, file, String::new()));
+        }
+    }
+    Ok(config)
+}
+
+/// The configs of one layer after [`apply_deprecations`].
+pub(crate) fn apply_layer_deprecations<'a>(
+    depth_configs: &[(&'a Path, &'a ConfigValue)],
+    options: &MergeOptions,
+    entries: &mut Vec<ReportEntry>,
+) -> Result<Vec<(&'a Path, Cow<'a, ConfigValue>)>> {
+    depth_configs
+        .iter()
+        .map(|(path, config)| Ok((*path, apply_deprecations(config, path, options, entries)?)))
+        .collect()
+}
+
+/// Tag marking a value as deprecated in the file defining it:
+/// `old_flag: !deprecated true`.
+const DEPRECATED_TAG: &str = "deprecated";
+
+/// Removes the `!deprecated` tags left in a merged config, adding a
+/// deprecation entry for each value still carrying one. A value a deeper
+/// layer overrode or deleted has lost its tag and goes unreported.
+///
+/// The entry names the last of `files`, given in merge order, that tags the
+/// value at its path.
+pub(crate) fn strip_deprecated_tags(
+    mut config: ConfigValue,
+    files: &[(&Path, &ConfigValue)],
+    entries: &mut Vec<ReportEntry>,
+) -> ConfigValue {
+    let mut tagged = Vec::new();
+    strip_below(&mut config, &mut Vec::new(), &mut tagged);
+    for segments in tagged {
+        let path = format_key_path(&segments);
+        let file = files.iter().rev().find(|(_, config)| is_deprecated(get_segments(config, &segments)));
+        entries.push(ReportEntry {
+            kind: ReportKind::Deprecation,
+            message: match file {
+                Some((file, _)) => format!("Deprecated value at '{path}' from {} is still in use", file.display()),
+                None => format!("Deprecated value at '{path}' is still in use"),
+            },
+            key_path: Some(path),
+            files: file.map(|(file, _)| file.to_path_buf()).into_iter().collect(),
+        });
+    }
+    config
+}
+
+/// Whether [`strip_deprecated_tags`] has anything to strip in `value`.
+pub(crate) fn has_deprecated_tags(value: &ConfigValue) -> bool {
+    match value {
+        ConfigValue::Tagged(tagged) => tagged.tag == DEPRECATED_TAG || has_deprecated_tags(&tagged.value),
+        ConfigValue::Mapping(map) => map.values().any(has_deprecated_tags),
+        ConfigValue::Sequence(items) => items.iter().any(has_deprecated_tags),
+        _ => false,
+    }
+}
+
+fn strip_below(value: &mut ConfigValue, path: &mut Vec<PathSegment>, tagged: &mut Vec<Vec<PathSegment>>) {
+    match value {
+        ConfigValue::Tagged(inner) if inner.tag == DEPRECATED_TAG => {
+            tagged.push(path.clone());
+            *value = std::mem::take(&mut inner.value);
+            strip_below(value, path, tagged);
+        }
+        ConfigValue::Tagged(inner) => strip_below(&mut inner.value, path, tagged),
+        ConfigValue::Mapping(map) => {
+            for (key, item) in map.iter_mut() {
+                path.push(PathSegment::Key(key_to_string(key)));
+                strip_below(item, path, tagged);
+                path.pop();
+            }
+        }
+        ConfigValue::Sequence(items) => {
+            for (index, item) in items.iter_mut().enumerate() {
+                path.push(PathSegment::Index(index));
+                strip_below(item, path, tagged);
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+fn is_deprecated(value: Option<&ConfigValue>) -> bool {
+    matches!(value, Some(ConfigValue::Tagged(tagged)) if tagged.tag == DEPRECATED_TAG)
+}
+
+fn deprecation(path: &str, file: &Path, hint: String) -> ReportEntry {
+    ReportEntry {
+        kind: ReportKind::Deprecation,
+        key_path: Some(path.to_string()),
+        files: vec![file.to_path_buf()],
+        message: format!("Deprecated key '{path}' in {}{hint}", file.display()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixture_tree;
+
+    fn yaml(text: &str) -> ConfigValue {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn test_alias_merges_under_canonical_path() {
+        let options = MergeOptions {
+            aliases: vec![
+                ("db".to_string(), "database".to_string()),
+                ("service.timeout_ms".to_string(), "service.timeout".to_string()),
+            ],
+            ..MergeOptions::default()
+        };
+        let config = yaml("db: {host: old, port: 5432}\ndatabase: {host: new}\nservice: {timeout_ms: 30, name: api}\n");
+        let mut entries = Vec::new();
+        let renamed = apply_deprecations(&config, Path::new("a.yaml"), &options, &mut entries).unwrap();
+
+        assert_eq!(
+            *renamed,
+            yaml("database: {host: new, port: 5432}\nservice: {name: api, timeout: 30}\n")
+        );
+        assert_eq!(
+            entries.iter().map(|entry| entry.message.as_str()).collect::<Vec<_>>(),
+            vec![
+                "Deprecated key 'db' in a.yaml; use 'database' instead",
+                "Deprecated key 'service.timeout_ms' in a.yaml; use 'service.timeout' instead",
+            ]
+        );
+
+        let untouched = yaml("database: {host: new}\n");
+        let mut entries = Vec::new();
+        let result = apply_deprecations(&untouched, Path::new("b.yaml"), &options, &mut entries).unwrap();
+        assert!(matches!(result, Cow::Borrowed(_)));
+        assert!(entries.is_empty());
+    }
+
+    #[test]
+    fn test_deprecated_paths_only_warn() {
+        let dir = fixture_tree(&[
+            ("config.yaml", "database: {host: base}\nlegacy: {mode: 1}\n"),
+            ("svc/config.yaml", "db: {host: svc}\n"),
+        ]);
+        let options = MergeOptions {
+            aliases: vec![("db".to_string(), "database".to_string())],
+            deprecated_paths: vec!["legacy.mode".to_string()],
+            ..MergeOptions::default()
+        };
+
+        let (config, report) = crate::merge_hierarchy(dir.path(), &dir.path().join("svc"), &options).unwrap();
+        assert_eq!(config, yaml("database: {host: svc}\nlegacy: {mode: 1}\n"));
+        let base = dir.path().canonicalize().unwrap();
+        assert_eq!(
+            report.entries.iter().map(|entry| (entry.kind, entry.key_path.as_deref(), entry.files.clone())).collect::<Vec<_>>(),
+            vec![
+                (ReportKind::Deprecation, Some("legacy.mode"), vec![base.join("config.yaml")]),
+                (ReportKind::Deprecation, Some("db"), vec![base.join("svc/config.yaml")]),
+            ]
+        );
+
+        let strict = MergeOptions { strict: true, ..options };
+        assert!(crate::merge_hierarchy(dir.path(), &dir.path().join("svc"), &strict).is_err());
+    }
+
+    #[test]
+    fn test_deprecated_tag_warns_while_the_value_survives() {
+        let dir = fixture_tree(&[
+            (
+                "config.yaml",
+                "old_flag: !deprecated true\nlegacy: !deprecated {mode: 1}\nlimits: {rate: !deprecated 10}\n",
+            ),
+            ("svc/config.yaml", "legacy: {mode: 2}\nlimits: {burst: 5}\n"),
+        ]);
+        let base_file = dir.path().canonicalize().unwrap().join("config.yaml");
+
+        let (config, report) =
+            crate::merge_hierarchy(dir.path(), &dir.path().join("svc"), &MergeOptions::default()).unwrap();
+        assert_eq!(config, yaml("old_flag: true\nlegacy: {mode: 2}\nlimits: {rate: 10, burst: 5}\n"));
+        assert_eq!(
+            report.entries.iter().map(|entry| (entry.kind, entry.key_path.as_deref(), entry.files.clone())).collect::<Vec<_>>(),
+            vec![
+                (ReportKind::Deprecation, Some("old_flag"), vec![base_file.clone()]),
+                (ReportKind::Deprecation, Some("limits.rate"), vec![base_file.clone()]),
+            ]
+        );
+        assert_eq!(
+            report.entries[0].message,
+            format!("Deprecated value at 'old_flag' from {} is still in use", base_file.display())
+        );
+
+        let (_, traced, _) =
+            crate::merge_hierarchy_with_provenance(dir.path(), &dir.path().join("svc"), &MergeOptions::default()).unwrap();
+        assert_eq!(traced.entries, report.entries);
+    }
+
+    #[test]
+    fn test_overridden_or_deleted_deprecated_values_do_not_warn() {
+        let dir = fixture_tree(&[
+            ("config.yaml", "old_flag: !deprecated true\ngone: !deprecated 1\nkept: 1\n"),
+            ("svc/config.yaml", "old_flag: false\ngone: null\n"),
+        ]);
+        let options = MergeOptions {
+            null_deletes: true,
+            ..MergeOptions::default()
+        };
+
+        let (config, report) = crate::merge_hierarchy(dir.path(), &dir.path().join("svc"), &options).unwrap();
+        assert_eq!(config, yaml("old_flag: false\nkept: 1\n"));
+        assert!(report.entries.is_empty());
+    }
+}
@@ -0,0 +1,556 @@
+use std::fmt;
+use anyhow::{anyhow, bail, Result};
+use serde::Serialize;
+
+use crate::error::ConfigError;
+use crate::keypath::{format_key_path, key_to_string, PathSegment};
+use crate::options::{MergeMode, MergeOptions, SequenceStrategy};
+use crate::redact::Redaction;
+use crate::ConfigValue;
+
+/// How the value at one path differs between two configs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Added(ConfigValue),
+    Removed(ConfigValue),
+    Modified { old: ConfigValue, new: ConfigValue },
+}
+
+/// One difference between two configs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffEntry {
+    pub path: Vec<PathSegment>,
+    pub change: Change,
+}
+
+impl DiffEntry {
+    /// The path in dotted form, e.g. `server.port`.
+    pub fn path_string(&self) -> String {
+        format_key_path(&self.path)
+    }
+
+    /// This entry with its masked values replaced by
+    /// [`REDACTED`](crate::redact::REDACTED), for display.
+    pub fn redacted(&self, redaction: &Redaction) -> DiffEntry {
+        let mut path = self.path.clone();
+        let mut apply = |value: &ConfigValue| redaction.apply(value, &mut path);
+        let change = match &self.change {
+            Change::Added(value) => Change::Added(apply(value)),
+            Change::Removed(value) => Change::Removed(apply(value)),
+            Change::Modified { old, new } => Change::Modified {
+                old: apply(old),
+                new: apply(new),
+            },
+        };
+        DiffEntry {
+            path: self.path.clone(),
+            change,
+        }
+    }
+}
+
+/// One line per entry: `+ path: value`, `- path: value` or
+/// `~ path: old -> new`, values as inline YAML. See [`DiffEntry::redacted`]
+/// before showing it to anyone.
+impl fmt::Display for DiffEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inline = |value: &ConfigValue| serde_json::to_string(value).unwrap_or_else(|_| "?".to_string());
+        let path = self.path_string();
+        match &self.change {
+            Change::Added(value) => write!(f, "+ {path}: {}", inline(value)),
+            Change::Removed(value) => write!(f, "- {path}: {}", inline(value)),
+            Change::Modified { old, new } => write!(f, "~ {path}: {} -> {}", inline(old), inline(new)),
+        }
+    }
+}
+
+/// Differences turning `old` into `new`.
+///
+/// Mappings are compared key by key, recursively; every other value, sequences
+/// included, is compared as a whole. Entries for keys of `old` come first, in
+/// `old`'s key order, followed by keys only present in `new`.
+pub fn diff(old: &ConfigValue, new: &ConfigValue) -> Vec<DiffEntry> {
+    let mut entries = Vec::new();
+    diff_into(old, new, &mut Vec::new(), &mut entries);
+    entries
+}
+
+/// What [`semantic_eq_with`] and [`semantic_diff`] take as the same config.
+/// Mapping keys never depend on their order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Equivalence {
+    /// Numbers compare by value, so that `1` equals `1.0`; otherwise an
+    /// integer and a float always differ.
+    pub numbers_by_value: bool,
+    /// A key set to `null` is as good as a missing key.
+    pub null_is_missing: bool,
+    /// A key set to an empty mapping, or to a mapping with nothing left once
+    /// these rules apply, is as good as a missing key.
+    pub empty_is_missing: bool,
+}
+
+/// Numbers of different types differ; `null` and empty mappings stand for
+/// missing keys.
+impl Default for Equivalence {
+    fn default() -> Self {
+        Self {
+            numbers_by_value: false,
+            null_is_missing: true,
+            empty_is_missing: true,
+        }
+    }
+}
+
+impl Equivalence {
+    /// `value` in the form every equivalent value shares: without the keys
+    /// standing for missing ones, and with integral floats as integers when
+    /// numbers compare by value. A top level standing for a missing key is
+    /// an empty mapping.
+    fn canonical(&self, value: &ConfigValue) -> ConfigValue {
+        self.canonical_below(value)
+            .unwrap_or_else(|| ConfigValue::Mapping(Default::default()))
+    }
+
+    /// `value` in canonical form, `None` when it stands for a missing key.
+    fn canonical_below(&self, value: &ConfigValue) -> Option<ConfigValue> {
+        match value {
+            ConfigValue::Null if self.null_is_missing => None,
+            ConfigValue::Mapping(map) => {
+                let map: serde_yaml::Mapping = map
+                    .iter()
+                    .filter_map(|(key, item)| Some((self.canonical_number(key), self.canonical_below(item)?)))
+                    .collect();
+                (!map.is_empty() || !self.empty_is_missing).then_some(ConfigValue::Mapping(map))
+            }
+            ConfigValue::Sequence(items) => Some(ConfigValue::Sequence(
+                items
+                    .iter()
+                    .map(|item| self.canonical_below(item).unwrap_or(ConfigValue::Null))
+                    .collect(),
+            )),
+            ConfigValue::Tagged(tagged) => {
+                let mut tagged = tagged.clone();
+                tagged.value = self.canonical_below(&tagged.value).unwrap_or(ConfigValue::Null);
+                Some(ConfigValue::Tagged(tagged))
+            }
+            value => Some(self.canonical_number(value)),
+        }
+    }
+
+    /// An integral float as an integer, when numbers compare by value.
+    fn canonical_number(&self, value: &ConfigValue) -> ConfigValue {
+        match value {
+            ConfigValue::Number(number) if self.numbers_by_value && number.is_f64() => {
+                let float = number.as_f64().unwrap_or(f64::NAN);
+                if float.fract() == 0.0 && float >= i64::MIN as f64 && float < i64::MAX as f64 {
+                    ConfigValue::from(float as i64)
+                } else if float.fract() == 0.0 && float >= 0.0 && float < u64::MAX as f64 {
+                    ConfigValue::from(float as u64)
+                } else {
+                    value.clone()
+                }
+            }
+            value => value.clone(),
+        }
+    }
+}
+
+/// Whether `a` and `b` are the same config under [`Equivalence::default`]:
+/// whatever the order of their keys, and with `null` and empty mappings
+/// standing for missing keys.
+///
+/// ```
+/// # use hierarchical_config_merging::{semantic_eq, ConfigValue};
+/// let a: ConfigValue = serde_yaml::from_str("port: 80\nhost: a\ntls: null\nextra: {}").unwrap();
+/// let b: ConfigValue = serde_yaml::from_str("host: a\nport: 80").unwrap();
+/// assert!(semantic_eq(&a, &b));
+/// ```
+pub fn semantic_eq(a: &ConfigValue, b: &ConfigValue) -> bool {
+    semantic_eq_with(a, b, &Equivalence::default())
+}
+
+/// Like [`semantic_eq`], under the rules of `equivalence`.
+pub fn semantic_eq_with(a: &ConfigValue, b: &ConfigValue, equivalence: &Equivalence) -> bool {
+    equivalence.canonical(a) == equivalence.canonical(b)
+}
+
+/// The [`diff`] of `old` and `new` once both are in the form equivalent
+/// configs share, see [`Equivalence`]: empty exactly when they are
+/// equivalent. Keys standing for missing ones show as added or removed, and
+/// numbers compared by value show as integers when integral.
+pub fn semantic_diff(old: &ConfigValue, new: &ConfigValue, equivalence: &Equivalence) -> Vec<DiffEntry> {
+    diff(&equivalence.canonical(old), &equivalence.canonical(new))
+}
+
+/// An RFC 6902 JSON Patch operation. Serializes to its JSON form, e.g.
+/// `{"op": "replace", "path": "/server/port", "value": 8080}`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOp {
+    Add { path: String, value: ConfigValue },
+    Remove { path: String },
+    Replace { path: String, value: ConfigValue },
+}
+
+/// A JSON Patch turning `old` into `new`, one operation per [`diff`] entry,
+/// with JSON Pointer paths.
+///
+/// Sequences are not diffed item by item: a changed sequence is replaced as a
+/// whole. Mapping keys are written as strings, so both configs need string
+/// keys for the patch to apply to their JSON form.
+pub fn diff_as_json_patch(old: &ConfigValue, new: &ConfigValue) -> Vec<PatchOp> {
+    diff(old, new)
+        .into_iter()
+        .map(|entry| {
+            let path = json_pointer(&entry.path);
+            match entry.change {
+                Change::Added(value) => PatchOp::Add { path, value },
+                Change::Removed(_) => PatchOp::Remove { path },
+                Change::Modified { new, .. } => PatchOp::Replace { path, value: new },
+            }
+        })
+        .collect()
+}
+
+/// `path` as an RFC 6901 JSON Pointer, `~` and `/` escaped as `~0` and `~1`.
+fn json_pointer(path: &[PathSegment]) -> String {
+    path.iter()
+        .map(|segment| match segment {
+            PathSegment::Key(key) => format!("/{}", key.replace('~', "~0").replace('/', "~1")),
+            PathSegment::Index(index) => format!("/{index}"),
+        })
+        .collect()
+}
+
+/// The smallest override that, merged onto `base` by
+/// [`deep_merge_with`](crate::deep_merge_with) with `options`, gives `desired`:
+/// the starting point of a new leaf-level override file.
+///
+/// Mappings are compared key by key and only keys whose value differs are
+/// kept. A key missing from `desired` becomes a `null` with `null_deletes`
+/// (or in JSON Merge Patch mode). A differing sequence is written whole with
+/// [`SequenceStrategy::Replace`]; the other strategies keep only the items
+/// they add. Fails, naming the key path, when no override can produce
+/// `desired`: a removed key without `null_deletes`, a `null` value with it,
+/// or a sequence the strategy cannot turn into the desired one. Strategic
+/// merge patch mode is not supported.
+pub fn compute_override(base: &ConfigValue, desired: &ConfigValue, options: &MergeOptions) -> Result<ConfigValue, ConfigError> {
+    let (null_deletes, strategy) = match options.mode {
+        MergeMode::Deep => (options.null_deletes, options.sequence_strategy),
+        MergeMode::JsonMergePatch => (true, SequenceStrategy::Replace),
+        MergeMode::StrategicMergePatch { .. } => {
+            return Err(anyhow!("Cannot compute an override for a strategic merge patch").into());
+        }
+    };
+    match override_into(base, desired, null_deletes, strategy, &mut Vec::new())? {
+        Some(minimal) => Ok(minimal),
+        None if base.is_mapping() => Ok(ConfigValue::Mapping(serde_yaml::Mapping::new())),
+        None => Ok(desired.clone()),
+    }
+}
+
+/// The override for the value at `path`, `None` when `base` already is
+/// `desired`.
+fn override_into(
+    base: &ConfigValue,
+    desired: &ConfigValue,
+    null_deletes: bool,
+    strategy: SequenceStrategy,
+    path: &mut Vec<PathSegment>,
+) -> Result<Option<ConfigValue>> {
+    if base == desired {
+        return Ok(None);
+    }
+    let at = |path: &[PathSegment]| format_key_path(path);
+    match (base, desired) {
+        (ConfigValue::Mapping(base_map), ConfigValue::Mapping(desired_map)) => {
+            let mut result = serde_yaml::Mapping::new();
+            for (key, desired_value) in desired_map {
+                path.push(PathSegment::Key(key_to_string(key)));
+                let value = match base_map.get(key) {
+                    Some(base_value) => override_into(base_value, desired_value, null_deletes, strategy, path)?,
+                    None => {
+                        check_no_nulls(desired_value, null_deletes, path)?;
+                        Some(desired_value.clone())
+                    }
+                };
+                path.pop();
+                if let Some(value) = value {
+                    result.insert(key.clone(), value);
+                }
+            }
+            for key in base_map.keys().filter(|key| !desired_map.contains_key(*key)) {
+                path.push(PathSegment::Key(key_to_string(key)));
+                if !null_deletes {
+                    bail!("Cannot remove '{}' without null_deletes", at(path));
+                }
+                path.pop();
+                result.insert(key.clone(), ConfigValue::Null);
+            }
+            Ok(Some(ConfigValue::Mapping(result)))
+        }
+        (ConfigValue::Sequence(base_items), ConfigValue::Sequence(desired_items)) => {
+            let added = match strategy {
+                SequenceStrategy::Replace => Some(desired_items.clone()),
+                SequenceStrategy::Append => desired_items.strip_prefix(base_items.as_slice()).map(<[_]>::to_vec),
+                SequenceStrategy::Prepend => desired_items.strip_suffix(base_items.as_slice()).map(<[_]>::to_vec),
+                SequenceStrategy::Union => desired_items
+                    .strip_prefix(base_items.as_slice())
+                    .filter(|added| {
+                        added.iter().enumerate().all(|(index, item)| !base_items.contains(item) && !added[..index].contains(item))
+                    })
+                    .map(<[_]>::to_vec),
+            };
+            match added {
+                Some(items) => Ok(Some(ConfigValue::Sequence(items))),
+                None => bail!("Cannot turn the sequence at '{}' into the desired one with {strategy:?}", at(path)),
+            }
+        }
+        _ => {
+            check_no_nulls(desired, null_deletes, path)?;
+            Ok(Some(desired.clone()))
+        }
+    }
+}
+
+/// With `null_deletes`, a `null` in an override removes its key, so nulls
+/// cannot be set.
+fn check_no_nulls(value: &ConfigValue, null_deletes: bool, path: &mut Vec<PathSegment>) -> Result<()> {
+    if !null_deletes {
+        return Ok(());
+    }
+    match value {
+        ConfigValue::Null => bail!("Cannot set '{}' to null with null_deletes", format_key_path(path)),
+        ConfigValue::Mapping(map) => {
+            for (key, item) in map {
+                path.push(PathSegment::Key(key_to_string(key)));
+                check_no_nulls(item, null_deletes, path)?;
+                path.pop();
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn diff_into(
+    old: &ConfigValue,
+    new: &ConfigValue,
+    path: &mut Vec<PathSegment>,
+    entries: &mut Vec<DiffEntry>,
+) {
+    match (old, new) {
+        (ConfigValue::Mapping(old_map), ConfigValue::Mapping(new_map)) => {
+            for (key, old_value) in old_map {
+                path.push(PathSegment::Key(key_to_string(key)));
+                match new_map.get(key) {
+                    Some(new_value) => diff_into(old_value, new_value, path, entries),
+                    None => entries.push(DiffEntry {
+                        path: path.clone(),
+                        change: Change::Removed(old_value.clone()),
+                    }),
+                }
+                path.pop();
+            }
+            for (key, new_value) in new_map {
+                if !old_map.contains_key(key) {
+                    path.push(PathSegment::Key(key_to_string(key)));
+                    entries.push(DiffEntry {
+                        path: path.clone(),
+                        change: Change::Added(new_value.clone()),
+                    });
+                    path.pop();
+                }
+            }
+        }
+        _ if old == new => {}
+        _ => entries.push(DiffEntry {
+            path: path.clone(),
+            change: Change::Modified {
+                old: old.clone(),
+                new: new.clone(),
+            },
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn yaml(text: &str) -> ConfigValue {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn test_diff_reports_leaf_changes() {
+        let old = yaml("server:\n  port: 80\n  host: a\nlist: [1, 2]\nremoved: x\n");
+        let new = yaml("server:\n  port: 8080\n  host: a\n  tls: true\nlist: [1, 3]\n");
+
+        let entries = diff(&old, &new);
+        let summary: Vec<(String, &Change)> =
+            entries.iter().map(|e| (e.path_string(), &e.change)).collect();
+
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    "server.port".to_string(),
+                    &Change::Modified { old: yaml("80"), new: yaml("8080") }
+                ),
+                ("server.tls".to_string(), &Change::Added(yaml("true"))),
+                (
+                    "list".to_string(),
+                    &Change::Modified { old: yaml("[1, 2]"), new: yaml("[1, 3]") }
+                ),
+                ("removed".to_string(), &Change::Removed(yaml("x"))),
+            ]
+        );
+        assert!(diff(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn test_diff_display_redacts() {
+        let old = yaml("db: {host: a, password: hunter2}\n");
+        let new = yaml("db: {host: b, password: swordfish}\napi: {token: t0k}\n");
+        let lines: Vec<String> = diff(&old, &new)
+            .iter()
+            .map(|entry| entry.redacted(&Redaction::default()).to_string())
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                r#"~ db.host: "a" -> "b""#,
+                r#"~ db.password: "<redacted>" -> "<redacted>""#,
+                r#"+ api: {"token":"<redacted>"}"#,
+            ]
+        );
+    }
+
+    fn apply_json_patch(old: &ConfigValue, ops: &[PatchOp]) -> serde_json::Value {
+        let patch: json_patch::Patch = serde_json::from_value(serde_json::to_value(ops).unwrap()).unwrap();
+        let mut doc = serde_json::to_value(old).unwrap();
+        json_patch::patch(&mut doc, &patch).unwrap();
+        doc
+    }
+
+    #[test]
+    fn test_json_patch_transforms_old_into_new() {
+        let old = yaml("server:\n  port: 80\n  host: a\nlist: [1, 2]\nremoved: {x: 1}\n'a/b': 1\n'm~n': {'~/': 1}\n");
+        let new = yaml("server:\n  port: 8080\n  host: a\n  tls: {on: true}\nlist: [1, 3]\n'a/b': 2\n'm~n': {'~/': null}\n");
+
+        let ops = diff_as_json_patch(&old, &new);
+        assert_eq!(
+            serde_json::to_value(&ops).unwrap(),
+            serde_json::json!([
+                {"op": "replace", "path": "/server/port", "value": 8080},
+                {"op": "add", "path": "/server/tls", "value": {"on": true}},
+                {"op": "replace", "path": "/list", "value": [1, 3]},
+                {"op": "remove", "path": "/removed"},
+                {"op": "replace", "path": "/a~1b", "value": 2},
+                {"op": "replace", "path": "/m~0n/~0~1", "value": null},
+            ])
+        );
+        assert_eq!(apply_json_patch(&old, &ops), serde_json::to_value(&new).unwrap());
+
+        for (old, new) in [("{a: 1}", "[1]"), ("[1, 2]", "[2]"), ("{}", "{a: {b: [1]}}"), ("{a: 1}", "{a: 1}")] {
+            let (old, new) = (yaml(old), yaml(new));
+            assert_eq!(apply_json_patch(&old, &diff_as_json_patch(&old, &new)), serde_json::to_value(&new).unwrap());
+        }
+        assert_eq!(diff_as_json_patch(&yaml("1"), &yaml("2")), vec![PatchOp::Replace { path: String::new(), value: yaml("2") }]);
+    }
+
+    #[test]
+    fn test_compute_override_reproduces_desired() {
+        let fixtures = [
+            ("{a: 1, b: {c: 2, d: 3}}", "{a: 1, b: {c: 2, d: 4}}"),
+            ("{a: 1}", "{a: 1, b: {new: [1, 2]}}"),
+            ("{a: {b: 1}}", "{a: 5}"),
+            ("{a: 5}", "{a: {b: 1}}"),
+            ("{list: [1, 2]}", "{list: [1, 2, 3]}"),
+            ("{list: [1, 2]}", "{list: [2]}"),
+            ("{a: 1, keep: {x: 1}}", "{a: 1, keep: {x: 1}}"),
+            ("{tagged: !vault a}", "{tagged: !vault b, plain: null}"),
+            ("{}", "{a: {b: {c: 1}}}"),
+        ];
+        let deletions = [("{a: 1, b: {c: 2, d: 3}}", "{b: {c: 2}}"), ("{a: {b: [1]}}", "{}")];
+        let appends = [("{list: [1, 2]}", "{list: [1, 2, 3]}"), ("{a: {list: []}}", "{a: {list: [x]}}")];
+
+        let cases = [
+            (MergeOptions::default(), &fixtures[..]),
+            (MergeOptions { null_deletes: true, ..MergeOptions::default() }, &fixtures[..7]),
+            (MergeOptions { null_deletes: true, ..MergeOptions::default() }, &deletions[..]),
+            (MergeOptions { mode: MergeMode::JsonMergePatch, ..MergeOptions::default() }, &deletions[..]),
+            (MergeOptions { sequence_strategy: SequenceStrategy::Append, ..MergeOptions::default() }, &appends[..]),
+            (MergeOptions { sequence_strategy: SequenceStrategy::Union, ..MergeOptions::default() }, &appends[..]),
+        ];
+        for (options, pairs) in cases {
+            for (base, desired) in pairs {
+                let (base, desired) = (yaml(base), yaml(desired));
+                let minimal = compute_override(&base, &desired, &options).unwrap();
+                assert_eq!(crate::deep_merge_with(&base, &minimal, &options), desired, "{options:?}: {minimal:?}");
+            }
+        }
+
+        let options = MergeOptions { null_deletes: true, ..MergeOptions::default() };
+        let minimal = compute_override(&yaml("{a: 1, b: {c: 2, d: 3}, e: [1]}"), &yaml("{a: 1, b: {c: 5}, e: [1, 2]}"), &options);
+        assert_eq!(minimal.unwrap(), yaml("{b: {c: 5, d: null}, e: [1, 2]}"));
+        let append = MergeOptions { sequence_strategy: SequenceStrategy::Append, ..MergeOptions::default() };
+        assert_eq!(compute_override(&yaml("{l: [1]}"), &yaml("{l: [1, 2]}"), &append).unwrap(), yaml("{l: [2]}"));
+    }
+
+    #[test]
+    fn test_compute_override_names_impossible_paths() {
+        let err = |base: &str, desired: &str, options: MergeOptions| {
+            compute_override(&yaml(base), &yaml(desired), &options).unwrap_err().to_string()
+        };
+        assert_eq!(err("{a: {b: 1}}", "{a: {}}", MergeOptions::default()), "Cannot remove 'a.b' without null_deletes");
+        let null_deletes = MergeOptions { null_deletes: true, ..MergeOptions::default() };
+        assert_eq!(err("{a: 1}", "{a: 1, b: {c: null}}", null_deletes), "Cannot set 'b.c' to null with null_deletes");
+        let append = MergeOptions { sequence_strategy: SequenceStrategy::Append, ..MergeOptions::default() };
+        assert!(err("{l: [1, 2]}", "{l: [2]}", append).contains("'l'"));
+    }
+
+    #[test]
+    fn test_semantic_eq_ignores_order_and_missing_keys() {
+        let a = yaml("server: {port: 80, host: a}\nlogging: {level: null, sinks: {}}\nlist: [1, null]\n");
+        let b = yaml("server: {host: a, port: 80}\nlist: [1, null]\n");
+        assert!(semantic_eq(&a, &b));
+        assert!(semantic_diff(&a, &b, &Equivalence::default()).is_empty());
+        // Sequences keep their order.
+        assert!(!semantic_eq(&a, &yaml("server: {host: a, port: 80}\nlist: [null, 1]\n")));
+        assert!(semantic_eq(&yaml("null"), &yaml("{a: {b: {}}}")));
+
+        let strict = Equivalence {
+            null_is_missing: false,
+            empty_is_missing: false,
+            ..Equivalence::default()
+        };
+        assert!(!semantic_eq_with(&a, &b, &strict));
+        let entries: Vec<String> = semantic_diff(&a, &b, &strict).iter().map(ToString::to_string).collect();
+        assert_eq!(entries, ["- logging: {\"level\":null,\"sinks\":{}}"]);
+        let nulls_only = Equivalence {
+            empty_is_missing: false,
+            ..Equivalence::default()
+        };
+        let entries: Vec<String> = semantic_diff(&a, &b, &nulls_only).iter().map(ToString::to_string).collect();
+        assert_eq!(entries, ["- logging: {\"sinks\":{}}"]);
+    }
+
+    #[test]
+    fn test_semantic_eq_compares_numbers_by_value_on_request() {
+        let (a, b) = (yaml("{port: 1, ratio: 2.0, big: 1e3, 3: x}"), yaml("{port: 1.0, ratio: 2, big: 1000, 3.0: x}"));
+        let by_value = Equivalence {
+            numbers_by_value: true,
+            ..Equivalence::default()
+        };
+        assert!(semantic_eq_with(&a, &b, &by_value));
+        assert!(semantic_diff(&a, &b, &by_value).is_empty());
+        assert!(!semantic_eq_with(&yaml("{ratio: 2.5}"), &yaml("{ratio: 2}"), &by_value));
+
+        assert!(!semantic_eq(&a, &b));
+        let paths: Vec<String> = semantic_diff(&a, &b, &Equivalence::default()).iter().map(DiffEntry::path_string).collect();
+        assert_eq!(paths, ["port", "ratio", "big", "3", "3\\.0"]);
+        assert!(semantic_eq(&yaml("{ratio: 2.0}"), &yaml("{ratio: 2.00}")));
+    }
+}
@@ -0,0 +1,181 @@
+//! Markdown documentation of a merged config, see [`generate_docs`].
+
+use std::path::Path;
+
+use crate::error::ConfigError;
+use crate::keypath::{format_key_path, get_segments, key_to_string, PathSegment};
+use crate::options::MergeOptions;
+use crate::provenance::{merge_layers_traced, Provenance};
+use crate::redact::REDACTED;
+use crate::{empty_merge, load_hierarchy, ConfigValue, FsSource};
+
+/// Documents the merge of `target_path` as Markdown: the files merged, then
+/// one section per top-level key with a table of its key paths, their
+/// effective values, the file defining each and every file setting it.
+///
+/// Values at key paths matching [`MergeOptions::redaction`] are masked and
+/// sequences are summarized by their length. Paths are relative to
+/// `base_dir`, so the output only changes with the configs themselves.
+pub fn generate_docs(base_dir: &Path, target_path: &Path, options: &MergeOptions) -> Result<String, ConfigError> {
+    let base_dir = &options.input_path(base_dir)?;
+    let target_path = &options.input_path(target_path)?;
+    let canonical_base = base_dir.canonicalize().unwrap_or_else(|_| base_dir.to_path_buf());
+    let relative = |path: &Path| path.strip_prefix(&canonical_base).unwrap_or(path).display().to_string();
+
+    let target = target_path.canonicalize().unwrap_or_else(|_| target_path.to_path_buf());
+    let mut text = match relative(&target).as_str() {
+        "" => "# Configuration\n\n".to_string(),
+        target => format!("# Configuration of `{target}`\n\n"),
+    };
+
+    let Some(loaded) = load_hierarchy(&FsSource, base_dir, target_path, options)? else {
+        let (config, _) = empty_merge(base_dir, target_path, options)?;
+        text.push_str("No config files.\n");
+        write_sections(&mut text, &config, &Provenance::of_defaults(options), &[], options, &relative);
+        return Ok(text);
+    };
+    let (config, mut report, provenance) = merge_layers_traced(loaded.layers(), options)?;
+    let files: Vec<(&Path, &ConfigValue)> = loaded.layers().map(|(_, path, config)| (path, config)).collect();
+    write_sections(&mut text, &config, &provenance, &files, options, &relative);
+    loaded.check_types(&config, &mut report);
+    loaded.complete_report(&mut report);
+    options.check_report(&report)?;
+
+    let mut header = String::from("Merged from, in order:\n\n");
+    for file in &report.files {
+        header.push_str(&format!("- `{}`\n", relative(&file.path)));
+    }
+    let position = text.find("\n\n").map_or(text.len(), |position| position + 2);
+    text.insert_str(position, &header);
+    Ok(text)
+}
+
+fn write_sections(
+    text: &mut String,
+    config: &ConfigValue,
+    provenance: &Provenance,
+    files: &[(&Path, &ConfigValue)],
+    options: &MergeOptions,
+    relative: &dyn Fn(&Path) -> String,
+) {
+    let ConfigValue::Mapping(map) = config else {
+        return;
+    };
+    for (key, value) in map {
+        let mut path = vec![PathSegment::Key(key_to_string(key))];
+        text.push_str(&format!("\n## `{}`\n\n", format_key_path(&path)));
+        text.push_str("| Key path | Value | Defined in | Set by |\n");
+        text.push_str("| --- | --- | --- | --- |\n");
+        write_rows(text, value, &mut path, provenance, files, options, relative);
+    }
+}
+
+/// One row per leaf below `path`, sequences included as a whole.
+fn write_rows(
+    text: &mut String,
+    value: &ConfigValue,
+    path: &mut Vec<PathSegment>,
+    provenance: &Provenance,
+    files: &[(&Path, &ConfigValue)],
+    options: &MergeOptions,
+    relative: &dyn Fn(&Path) -> String,
+) {
+    let redacted = options.redaction.matches(path);
+    if let ConfigValue::Mapping(map) = value
+        && !map.is_empty()
+        && !redacted
+    {
+        for (key, item) in map {
+            path.push(PathSegment::Key(key_to_string(key)));
+            write_rows(text, item, path, provenance, files, options, relative);
+            path.pop();
+        }
+        return;
+    }
+
+    let shown = if redacted {
+        format!("`{REDACTED}`")
+    } else {
+        describe(value)
+    };
+    let defined_in = provenance
+        .source_of(path)
+        .map(|source| format!("`{}`", relative(source)))
+        .unwrap_or_default();
+    let set_by: Vec<String> = files
+        .iter()
+        .filter(|(_, config)| get_segments(config, path).is_some())
+        .map(|(file, _)| format!("`{}`", relative(file)))
+        .collect();
+    text.push_str(&format!(
+        "| `{}` | {} | {} | {} |\n",
+        escape(&format_key_path(path)),
+        shown,
+        defined_in,
+        set_by.join(", ")
+    ));
+}
+
+/// A value for a table cell: inline YAML for scalars, the length of a
+/// sequence.
+fn describe(value: &ConfigValue) -> String {
+    match value {
+        ConfigValue::Sequence(items) if items.len() == 1 => "sequence of 1 item".to_string(),
+        ConfigValue::Sequence(items) => format!("sequence of {} items", items.len()),
+        ConfigValue::Tagged(tagged) => format!("`{}` {}", tagged.tag, describe(&tagged.value)),
+        value => {
+            let yaml = serde_yaml::to_string(value).unwrap_or_default();
+            let yaml = yaml.trim_end().replace('\n', " ");
+            format!("`{}`", escape(&yaml))
+        }
+    }
+}
+
+/// `text` made safe for a table cell.
+fn escape(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixture_tree;
+
+    /// A service with a base config and a production override.
+    fn fixture() -> tempfile::TempDir {
+        fixture_tree(&[
+            (
+                "config.yaml",
+                "name: api\nserver:\n  host: 0.0.0.0\n  port: 80\n  hosts: [a, b]\n\
+                 db:\n  user: app\n  password: hunter2\n  pool: {min: 1, max: 4}\nfeatures: {}\n",
+            ),
+            (
+                "prod/config.yaml",
+                "server:\n  port: 8080\n  motd: 'a | b'\ndb:\n  pool: {max: 16}\n  password: s3cret\n",
+            ),
+        ])
+    }
+
+    #[test]
+    fn test_docs_match_golden() {
+        let dir = fixture();
+        let docs = generate_docs(dir.path(), &dir.path().join("prod"), &MergeOptions::default()).unwrap();
+        assert_eq!(docs, include_str!("../../tests/golden/docs.md"));
+        assert!(!docs.contains("s3cret"));
+    }
+
+    #[test]
+    fn test_empty_hierarchy_documents_the_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = MergeOptions {
+            defaults: Some(serde_yaml::from_str("log: {level: info}").unwrap()),
+            ..MergeOptions::default()
+        };
+        assert_eq!(
+            generate_docs(dir.path(), dir.path(), &options).unwrap(),
+            "# Configuration\n\nNo config files.\n\n## `log`\n\n\
+             | Key path | Value | Defined in | Set by |\n| --- | --- | --- | --- |\n\
+             | `log.level` | `info` | `defaults` |  |\n"
+        );
+    }
+}
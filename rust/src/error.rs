@@ -0,0 +1,186 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::report::ReportEntry;
+
+/// Error of the public functions of the crate. Internally errors travel as
+/// `anyhow::Error`, from which a `ConfigError` is recovered with
+/// `downcast_ref::<ConfigError>()`; the ones without a variant of their own
+/// arrive as [`ConfigError::Other`].
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    /// A file or directory could not be read or resolved. `context` says
+    /// what was attempted, e.g. `Failed to read file`.
+    #[error("{context}: {}", path.display())]
+    Io {
+        context: &'static str,
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// A config file is not valid YAML. `line` and `column` are 1-based, when
+    /// the parser reports a location.
+    #[error("Failed to parse YAML: {}", path.display())]
+    Parse {
+        path: PathBuf,
+        line: Option<usize>,
+        column: Option<usize>,
+        #[source]
+        source: serde_yaml::Error,
+    },
+
+    /// An entry of a `defaults` list names a config that does not exist.
+    /// `file` is the file listing it; `expected` where it was looked for.
+    #[error("Config '{entry}' listed in the defaults of {} not found at {}", file.display(), expected.display())]
+    MissingDefault {
+        entry: String,
+        file: PathBuf,
+        expected: PathBuf,
+    },
+
+    /// The target path does not lie within the base directory.
+    #[error("Target path {} is not within base directory {}", target.display(), base.display())]
+    OutsideBase { base: PathBuf, target: PathBuf },
+
+    /// No YAML file was found and `fail_on_empty` is set.
+    #[error("No YAML files found in hierarchy from {} to {}", base.display(), target.display())]
+    EmptyHierarchy { base: PathBuf, target: PathBuf },
+
+    /// A `$VAR` (or `~`) in a path argument could not be expanded.
+    #[error("Cannot expand path {}: environment variable '{variable}' is not set", path.display())]
+    UnresolvedVariable { variable: String, path: PathBuf },
+
+    /// Files of one layer define the same key and `collision_policy` is
+    /// `Error`.
+    #[error("Found {} key collision(s): {}", entries.len(), join_messages(entries))]
+    Collision { entries: Vec<ReportEntry> },
+
+    /// A conflict resolver failed for the value at the dotted key `path`.
+    #[error("Failed to resolve conflict at '{path}'")]
+    Conflict {
+        path: String,
+        #[source]
+        source: anyhow::Error,
+    },
+
+    /// A `${...}` reference depends on itself; `chain` lists the dotted
+    /// paths involved, starting and ending with the same one.
+    #[error("Reference cycle: {}", chain.join(" -> "))]
+    ReferenceCycle { chain: Vec<String> },
+
+    /// A string override does not parse into the type of the value it
+    /// overrides and `coercion_failure` is `Error`.
+    #[error("{message}")]
+    TypeConflict {
+        path: String,
+        file: Option<PathBuf>,
+        message: String,
+    },
+
+    /// An index patch patches no item and `index_out_of_range` is `Error`.
+    #[error("{message}")]
+    IndexPatch {
+        path: String,
+        file: Option<PathBuf>,
+        message: String,
+    },
+
+    /// A remote layer could not be fetched while `strict` is set.
+    #[error("Failed to fetch {url}: {reason}")]
+    Remote { url: String, reason: String },
+
+    /// A file, or the defaults, defines the key reserved for the metadata of
+    /// [`crate::MergeOptions::embed_metadata`].
+    #[error("Reserved metadata key '{key}' is defined in {}", file.display())]
+    ReservedKey { key: String, file: PathBuf },
+
+    /// A conditional value of [`crate::WHEN_KEY`], at the dotted key `path`
+    /// of `file`, names a selector that is neither built in nor in
+    /// [`crate::MergeOptions::selectors`].
+    #[error("Unknown selector '{selector}' in the condition at '{path}' in {}", file.display())]
+    UnknownSelector { selector: String, file: PathBuf, path: String },
+
+    /// The merged config outgrew [`crate::MergeOptions::max_merged_nodes`]
+    /// or [`crate::MergeOptions::max_merged_bytes_estimate`], named by
+    /// `limit`, once `file` was merged.
+    #[error("Merged config exceeds {limit} ({actual} > {max}) while merging {}", file.display())]
+    LimitExceeded {
+        limit: &'static str,
+        max: usize,
+        actual: usize,
+        file: PathBuf,
+    },
+
+    /// The [`crate::MergeOptions::cancel`] token was cancelled, or the
+    /// [`crate::MergeOptions::timeout`] given in `timeout` elapsed, before the
+    /// merge was done.
+    #[error("Merge cancelled{}", timeout.map(|timeout| format!(" after the timeout of {timeout:?}")).unwrap_or_default())]
+    Cancelled { timeout: Option<Duration> },
+
+    /// The merge reported warnings and `strict` is set.
+    #[error("Merge produced {} warning(s) in strict mode: {}", entries.len(), join_messages(entries))]
+    Strict { entries: Vec<ReportEntry> },
+
+    /// A validator of [`crate::ConfigBuilder::validate`] rejected the config.
+    #[error("Config validation failed")]
+    Validation {
+        #[source]
+        source: anyhow::Error,
+    },
+
+    /// A [`crate::Config`] has no value at the dotted key `path`.
+    #[error("No value at '{path}'")]
+    MissingKey { path: String },
+
+    /// The value of a [`crate::Config`] at the dotted key `path` is not of
+    /// the `expected` type; `source` says why, when deserialization failed.
+    #[error("Expected {expected} at '{path}'")]
+    WrongType {
+        path: String,
+        expected: String,
+        #[source]
+        source: Option<serde_yaml::Error>,
+    },
+
+    /// Any other failure, such as an invalid key path or a config that
+    /// cannot be serialized.
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl ConfigError {
+    /// Maps an IO error on `path` to [`ConfigError::Io`], for `map_err`.
+    pub(crate) fn io<'a>(context: &'static str, path: &'a Path) -> impl FnOnce(io::Error) -> ConfigError + 'a {
+        move |source| ConfigError::Io {
+            context,
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+}
+
+/// Recovers the `ConfigError` an internal error is, unless context was added
+/// to it: that error stays whole as [`ConfigError::Other`], keeping its
+/// message.
+impl From<anyhow::Error> for ConfigError {
+    fn from(error: anyhow::Error) -> Self {
+        if !(*error).is::<ConfigError>() {
+            return ConfigError::Other(error);
+        }
+        match error.downcast::<ConfigError>() {
+            Ok(error) => error,
+            Err(error) => ConfigError::Other(error),
+        }
+    }
+}
+
+fn join_messages(entries: &[ReportEntry]) -> String {
+    entries
+        .iter()
+        .map(|entry| entry.message.as_str())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
@@ -0,0 +1,187 @@
+//! Sections merged only for the features a caller enables, see
+//! [`IF_FEATURE_KEY`].
+
+use std::path::Path;
+
+use anyhow::{bail, Result};
+
+use crate::keypath::{format_key_path, key_to_string, PathSegment};
+use crate::options::MergeOptions;
+use crate::report::{ReportEntry, ReportKind};
+use crate::ConfigValue;
+
+/// Key making the mapping holding it part of a feature: the mapping is kept
+/// when the feature it names is among [`MergeOptions::features`] and left
+/// out otherwise, with a [`ReportKind::DisabledFeature`] entry. The key
+/// itself is always removed. A file whose top-level mapping is left out
+/// merges like an empty file.
+///
+/// Features are resolved in every file as it is loaded, after its
+/// [`crate::WHEN_KEY`] conditions, so collisions are only found between the
+/// sections kept.
+///
+/// ```yaml
+/// tracing:
+///   __if_feature__: otel
+///   endpoint: http://collector:4317
+/// ```
+pub const IF_FEATURE_KEY: &str = "__if_feature__";
+
+/// `config`, read from `path`, without the sections of the features not
+/// enabled, adding an entry for each section left out to `entries`. Fails on
+/// a feature that is not named by a string.
+pub(crate) fn drop_disabled_features(
+    path: &Path,
+    config: ConfigValue,
+    options: &MergeOptions,
+    entries: &mut Vec<ReportEntry>,
+) -> Result<ConfigValue> {
+    Ok(filter(path, config, options, &mut Vec::new(), entries)?.unwrap_or(ConfigValue::Null))
+}
+
+/// `value` without the sections of disabled features, `None` when it is one.
+fn filter(
+    file: &Path,
+    value: ConfigValue,
+    options: &MergeOptions,
+    path: &mut Vec<PathSegment>,
+    entries: &mut Vec<ReportEntry>,
+) -> Result<Option<ConfigValue>> {
+    match value {
+        ConfigValue::Mapping(mut map) => {
+            if let Some(feature) = map.remove(IF_FEATURE_KEY) {
+                let ConfigValue::String(feature) = feature else {
+                    bail!(
+                        "Invalid {IF_FEATURE_KEY} at '{}' in {}: expected a feature name",
+                        format_key_path(path),
+                        file.display()
+                    );
+                };
+                if !options.features.contains(&feature) {
+                    let section = format_key_path(path);
+                    entries.push(ReportEntry {
+                        kind: ReportKind::DisabledFeature,
+                        message: match section.is_empty() {
+                            true => format!("Left out {}: feature '{feature}' is not enabled", file.display()),
+                            false => format!("Left out '{section}' of {}: feature '{feature}' is not enabled", file.display()),
+                        },
+                        key_path: (!section.is_empty()).then_some(section),
+                        files: vec![file.to_path_buf()],
+                    });
+                    return Ok(None);
+                }
+            }
+            let mut kept = serde_yaml::Mapping::with_capacity(map.len());
+            for (key, item) in map {
+                path.push(PathSegment::Key(key_to_string(&key)));
+                let item = filter(file, item, options, path, entries)?;
+                path.pop();
+                if let Some(item) = item {
+                    kept.insert(key, item);
+                }
+            }
+            Ok(Some(ConfigValue::Mapping(kept)))
+        }
+        ConfigValue::Sequence(items) => {
+            let mut kept = Vec::with_capacity(items.len());
+            for (index, item) in items.into_iter().enumerate() {
+                path.push(PathSegment::Index(index));
+                kept.extend(filter(file, item, options, path, entries)?);
+                path.pop();
+            }
+            Ok(Some(ConfigValue::Sequence(kept)))
+        }
+        value => Ok(Some(value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixture_tree;
+
+    fn yaml(text: &str) -> ConfigValue {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    fn with_features(features: &[&str]) -> MergeOptions {
+        MergeOptions {
+            features: features.iter().map(|feature| feature.to_string()).collect(),
+            ..MergeOptions::default()
+        }
+    }
+
+    const TRACING: &str = "name: api\ntracing: {__if_feature__: otel, endpoint: 'http://collector:4317'}\n";
+
+    #[test]
+    fn test_enabled_feature_keeps_its_section() {
+        let dir = fixture_tree(&[("config.yaml", TRACING)]);
+        let (config, report) = crate::merge_hierarchy(dir.path(), dir.path(), &with_features(&["otel"])).unwrap();
+        assert_eq!(config, yaml("name: api\ntracing: {endpoint: 'http://collector:4317'}\n"));
+        assert!(report.entries.is_empty(), "{:?}", report.entries);
+    }
+
+    #[test]
+    fn test_disabled_feature_drops_its_section_before_collisions() {
+        let dir = fixture_tree(&[
+            ("config.yaml", "name: base\n"),
+            ("prod/a.yaml", TRACING),
+            ("prod/b.yaml", "tracing: {endpoint: other}\n"),
+        ]);
+        let prod = dir.path().join("prod");
+        let (config, report) = crate::merge_hierarchy(dir.path(), &prod, &MergeOptions::default()).unwrap();
+        assert_eq!(config, yaml("name: api\ntracing: {endpoint: other}\n"));
+        // No collision on tracing.endpoint: a.yaml's section was left out.
+        let file = dir.path().canonicalize().unwrap().join("prod/a.yaml");
+        let entries: Vec<_> = report.entries.iter().map(|entry| (entry.kind, entry.key_path.as_deref())).collect();
+        assert_eq!(entries, [(ReportKind::DisabledFeature, Some("tracing"))]);
+        assert_eq!(
+            report.entries[0].message,
+            format!("Left out 'tracing' of {}: feature 'otel' is not enabled", file.display())
+        );
+        assert!(!report.entries[0].kind.is_warning());
+
+        let enabled = with_features(&["otel"]);
+        let (_, report) = crate::merge_hierarchy(dir.path(), &prod, &enabled).unwrap();
+        assert_eq!(report.entries[0].kind, ReportKind::Collision);
+        let mut merger = crate::HierarchyMerger::new(dir.path(), MergeOptions::default());
+        let (_, cached) = merger.merge_with_report(&prod).unwrap();
+        assert_eq!(cached.entries.len(), 1);
+        assert_eq!(cached.entries[0].kind, ReportKind::DisabledFeature);
+    }
+
+    #[test]
+    fn test_nested_and_top_level_sections() {
+        let text = "a: {__if_feature__: outer, b: {__if_feature__: inner, c: 1}, d: 2}\n\
+                    list: [{__if_feature__: inner, e: 3}, 4]\n";
+        let resolved = |features: &[&str]| {
+            let mut entries = Vec::new();
+            let config = drop_disabled_features(Path::new("f.yaml"), yaml(text), &with_features(features), &mut entries);
+            (config.unwrap(), entries.into_iter().map(|entry| entry.message).collect::<Vec<_>>())
+        };
+        assert_eq!(resolved(&["outer", "inner"]), (yaml("a: {b: {c: 1}, d: 2}\nlist: [{e: 3}, 4]\n"), vec![]));
+        assert_eq!(
+            resolved(&["outer"]),
+            (
+                yaml("a: {d: 2}\nlist: [4]\n"),
+                vec![
+                    "Left out 'a.b' of f.yaml: feature 'inner' is not enabled".to_string(),
+                    "Left out 'list[0]' of f.yaml: feature 'inner' is not enabled".to_string(),
+                ]
+            )
+        );
+        // Inner sections of a section left out are not reported.
+        assert_eq!(resolved(&["inner"]).0, yaml("list: [{e: 3}, 4]\n"));
+        assert_eq!(resolved(&["inner"]).1.len(), 1);
+
+        let mut entries = Vec::new();
+        let file = yaml("__if_feature__: beta\nkey: 1\n");
+        let config = drop_disabled_features(Path::new("f.yaml"), file, &MergeOptions::default(), &mut entries).unwrap();
+        assert_eq!(config, ConfigValue::Null);
+        assert_eq!(entries[0].message, "Left out f.yaml: feature 'beta' is not enabled");
+        assert_eq!(entries[0].key_path, None);
+
+        let err = drop_disabled_features(Path::new("f.yaml"), yaml("a: {__if_feature__: [x]}\n"), &MergeOptions::default(), &mut entries);
+        assert!(err.unwrap_err().to_string().contains("expected a feature name"));
+    }
+}
@@ -0,0 +1,264 @@
+//! C bindings, built with the `ffi` feature, declared in
+//! `include/hierarchical_config_merging.h`. The build generates the header
+//! anew into `OUT_DIR`, and a test checks that the one checked in matches it.
+//!
+//! Every string crossing the boundary is NUL-terminated UTF-8 (paths may be
+//! any bytes on Unix). Strings returned to the caller are owned by it and must
+//! be released with [`hcm_free`].
+
+use std::ffi::{CStr, CString, c_char};
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::ptr;
+
+use crate::{merge_hierarchy, to_json, ConfigError, MergeOptions};
+
+/// Outcome of an `hcm_*` call.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HcmStatus {
+    Ok = 0,
+    /// A required pointer argument was NULL.
+    NullArgument = 1,
+    /// A string argument was not valid UTF-8.
+    InvalidUtf8 = 2,
+    /// A config file is not valid YAML.
+    ParseError = 3,
+    /// The base or target path is missing, unexpandable, or the target lies
+    /// outside the base.
+    HierarchyError = 4,
+    /// Keys collided with a collision policy of `error`, or a strict merge
+    /// reported warnings.
+    CollisionError = 5,
+    /// Any other failure, such as an unreadable file.
+    Error = 6,
+    /// The merge panicked; nothing was returned.
+    Panic = 7,
+}
+
+impl HcmStatus {
+    fn of(e: &ConfigError) -> Self {
+        match e {
+            ConfigError::Parse { .. } => HcmStatus::ParseError,
+            ConfigError::OutsideBase { .. }
+            | ConfigError::EmptyHierarchy { .. }
+            | ConfigError::UnresolvedVariable { .. } => HcmStatus::HierarchyError,
+            ConfigError::Io { source, .. } if source.kind() == io::ErrorKind::NotFound => HcmStatus::HierarchyError,
+            ConfigError::Collision { .. } | ConfigError::Strict { .. } => HcmStatus::CollisionError,
+            ConfigError::Other(e) if is_not_found(e) => HcmStatus::HierarchyError,
+            ConfigError::Io { .. }
+            | ConfigError::Remote { .. }
+            | ConfigError::ReservedKey { .. }
+            | ConfigError::UnknownSelector { .. }
+            | ConfigError::LimitExceeded { .. }
+            | ConfigError::Cancelled { .. }
+            | ConfigError::Conflict { .. }
+            | ConfigError::MissingDefault { .. }
+            | ConfigError::ReferenceCycle { .. }
+            | ConfigError::TypeConflict { .. }
+            | ConfigError::IndexPatch { .. }
+            | ConfigError::Validation { .. }
+            | ConfigError::MissingKey { .. }
+            | ConfigError::WrongType { .. }
+            | ConfigError::Other(_) => HcmStatus::Error,
+        }
+    }
+}
+
+fn is_not_found(e: &anyhow::Error) -> bool {
+    e.chain()
+        .filter_map(|cause| cause.downcast_ref::<io::Error>())
+        .any(|error| error.kind() == io::ErrorKind::NotFound)
+}
+
+/// A caller-owned copy of `text`. NUL bytes, which C strings cannot hold, are
+/// dropped.
+fn to_c_string(text: String) -> *mut c_char {
+    let text = CString::new(text).unwrap_or_else(|e| {
+        let mut bytes = e.into_vec();
+        bytes.retain(|byte| *byte != 0);
+        CString::new(bytes).expect("NUL bytes were removed")
+    });
+    text.into_raw()
+}
+
+/// Stores `value` in `out` unless the caller passed NULL for it.
+///
+/// # Safety
+///
+/// `out` must be NULL or valid for writes.
+unsafe fn store(out: *mut *mut c_char, value: *mut c_char) {
+    if out.is_null() {
+        // SAFETY: `value` is NULL or was just returned by `to_c_string`.
+        unsafe { hcm_free(value) };
+    } else {
+        // SAFETY: non-null and valid for writes per the contract above.
+        unsafe { *out = value };
+    }
+}
+
+/// A failed call: its status and error message.
+type Failure = (HcmStatus, String);
+
+/// # Safety
+///
+/// `path` must be a valid NUL-terminated string.
+unsafe fn path_arg(path: *const c_char) -> Result<PathBuf, Failure> {
+    // SAFETY: checked for NULL by the caller; valid per the contract above.
+    let bytes = unsafe { CStr::from_ptr(path) }.to_bytes();
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        Ok(PathBuf::from(std::ffi::OsStr::from_bytes(bytes)))
+    }
+    #[cfg(not(unix))]
+    {
+        std::str::from_utf8(bytes)
+            .map(PathBuf::from)
+            .map_err(|e| (HcmStatus::InvalidUtf8, format!("Invalid path argument: {e}")))
+    }
+}
+
+/// Merges the hierarchy from `base_dir` down to `target_path` with the
+/// default options.
+///
+/// On success, `*out_json` receives the merged config as a JSON object and
+/// `*out_warnings` a JSON array of warning strings. On failure, `*out_err`
+/// receives the error message and the other outputs stay NULL. `out_warnings`
+/// and `out_err` may be NULL when the caller does not want them.
+///
+/// # Safety
+///
+/// `base_dir` and `target_path` must be NULL or valid NUL-terminated strings;
+/// each `out_*` pointer must be NULL or valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hcm_merge(
+    base_dir: *const c_char,
+    target_path: *const c_char,
+    out_json: *mut *mut c_char,
+    out_warnings: *mut *mut c_char,
+    out_err: *mut *mut c_char,
+) -> HcmStatus {
+    // SAFETY: every `out_*` pointer is NULL or valid for writes.
+    unsafe {
+        store(out_json, ptr::null_mut());
+        store(out_warnings, ptr::null_mut());
+        store(out_err, ptr::null_mut());
+    }
+    if base_dir.is_null() || target_path.is_null() || out_json.is_null() {
+        let message = "base_dir, target_path and out_json must not be NULL".to_string();
+        // SAFETY: as above.
+        unsafe { store(out_err, to_c_string(message)) };
+        return HcmStatus::NullArgument;
+    }
+
+    let merged = panic::catch_unwind(AssertUnwindSafe(|| -> Result<(String, String), Failure> {
+        // SAFETY: both strings are non-null and NUL-terminated.
+        let base_dir = unsafe { path_arg(base_dir) }?;
+        let target_path = unsafe { path_arg(target_path) }?;
+        let merge = || -> Result<(String, String), ConfigError> {
+            let (config, report) = merge_hierarchy(&base_dir, &target_path, &MergeOptions::default())?;
+            let warnings = serde_json::to_string(&report.warnings()).map_err(anyhow::Error::from)?;
+            Ok((to_json(&config, false)?, warnings))
+        };
+        merge().map_err(|e| (HcmStatus::of(&e), format!("{e:#}")))
+    }));
+
+    let (status, message) = match merged {
+        Ok(Ok((json, warnings))) => {
+            // SAFETY: as above.
+            unsafe {
+                store(out_json, to_c_string(json));
+                store(out_warnings, to_c_string(warnings));
+            }
+            return HcmStatus::Ok;
+        }
+        Ok(Err(failure)) => failure,
+        Err(_) => (HcmStatus::Panic, "The merge panicked".to_string()),
+    };
+    // SAFETY: as above.
+    unsafe { store(out_err, to_c_string(message)) };
+    status
+}
+
+/// Releases a string returned by this library. NULL is ignored.
+///
+/// # Safety
+///
+/// `text` must be NULL or a string returned by an `hcm_*` function that was
+/// not freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hcm_free(text: *mut c_char) {
+    if !text.is_null() {
+        // SAFETY: allocated by `CString::into_raw` per the contract above.
+        drop(unsafe { CString::from_raw(text) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Calls `hcm_merge`, returning its status and outputs as Rust strings.
+    fn merge(base_dir: &CStr, target_path: &CStr) -> (HcmStatus, Option<String>, Option<String>, Option<String>) {
+        let mut outputs = [ptr::null_mut(); 3];
+        let [json, warnings, err] = &mut outputs;
+        let status = unsafe { hcm_merge(base_dir.as_ptr(), target_path.as_ptr(), json, warnings, err) };
+        let [json, warnings, err] = outputs.map(|text| {
+            (!text.is_null()).then(|| {
+                let owned = unsafe { CStr::from_ptr(text) }.to_str().unwrap().to_string();
+                unsafe { hcm_free(text) };
+                owned
+            })
+        });
+        (status, json, warnings, err)
+    }
+
+    #[test]
+    fn test_merge_returns_json_and_warnings() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("prod")).unwrap();
+        fs::write(dir.path().join("a.yaml"), "port: 80\n").unwrap();
+        fs::write(dir.path().join("b.yaml"), "port: 81\n").unwrap();
+        fs::write(dir.path().join("prod/config.yaml"), "host: prod\n").unwrap();
+        let base = CString::new(dir.path().to_str().unwrap()).unwrap();
+        let target = CString::new(dir.path().join("prod").to_str().unwrap()).unwrap();
+
+        let (status, json, warnings, err) = merge(&base, &target);
+        assert_eq!(status, HcmStatus::Ok);
+        assert_eq!(json.as_deref(), Some(r#"{"port":81,"host":"prod"}"#));
+        assert!(warnings.unwrap().contains("Key collision at depth"));
+        assert_eq!(err, None);
+
+        let missing = CString::new(dir.path().join("missing").to_str().unwrap()).unwrap();
+        let (status, json, _, err) = merge(&base, &missing);
+        assert_eq!(status, HcmStatus::HierarchyError);
+        assert_eq!(json, None);
+        assert!(err.unwrap().contains("Failed to resolve path"));
+
+        fs::write(dir.path().join("b.yaml"), "port: [81\n").unwrap();
+        assert_eq!(merge(&base, &target).0, HcmStatus::ParseError);
+    }
+
+    #[test]
+    fn test_null_arguments_are_rejected() {
+        let mut json = ptr::null_mut();
+        let status = unsafe { hcm_merge(ptr::null(), c"x".as_ptr(), &mut json, ptr::null_mut(), ptr::null_mut()) };
+        assert_eq!(status, HcmStatus::NullArgument);
+        assert!(json.is_null());
+        unsafe { hcm_free(ptr::null_mut()) };
+    }
+
+    #[test]
+    fn test_checked_in_header_is_current() {
+        let generated = include_str!(concat!(env!("OUT_DIR"), "/hierarchical_config_merging.h"));
+        let checked_in = include_str!("../include/hierarchical_config_merging.h");
+        assert!(
+            generated == checked_in,
+            "include/hierarchical_config_merging.h is out of date; copy it from {}",
+            concat!(env!("OUT_DIR"), "/hierarchical_config_merging.h")
+        );
+    }
+}
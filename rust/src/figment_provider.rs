@@ -0,0 +1,217 @@
+//! A [`figment`] provider, built with the `figment` feature.
+
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use anyhow::Result;
+use figment::value::{Dict, Map, Value};
+use figment::{Error, Metadata, Profile, Provider, Source};
+
+use crate::diff::{diff, Change};
+use crate::keypath::set_segments;
+use crate::options::MergeOptions;
+use crate::{
+    canonicalize_hierarchy, discover_extra_roots, discover_yaml_files, merge_hierarchy, select_hierarchy_files,
+    ConfigValue,
+};
+
+/// The merged hierarchy from `base_dir` down to `target_path` as a figment
+/// provider.
+///
+/// ```no_run
+/// # use figment::{Figment, providers::{Env, Serialized}};
+/// # use hierarchical_config_merging::HierarchicalConfig;
+/// # #[derive(serde::Deserialize, serde::Serialize, Default)]
+/// # struct App { port: u16 }
+/// let app: App = Figment::from(Serialized::defaults(App::default()))
+///     .merge(HierarchicalConfig::new("configs", "configs/prod/eu"))
+///     .merge(Env::prefixed("APP_"))
+///     .select("prod")
+///     .extract()?;
+/// # Ok::<(), figment::Error>(())
+/// ```
+///
+/// The default profile holds the merge without profile files. Every profile
+/// named by a file of the hierarchy, such as `config@prod.yaml`, becomes a
+/// figment profile holding what its files add or change; keys that only a
+/// profile would delete (with `null_deletes`) are kept.
+///
+/// The merge runs whenever figment asks for the data. Warnings fail it, unless
+/// [`HierarchicalConfig::options`] turns `strict` off.
+#[derive(Debug, Clone)]
+pub struct HierarchicalConfig {
+    base_dir: PathBuf,
+    target_path: PathBuf,
+    options: MergeOptions,
+}
+
+impl HierarchicalConfig {
+    pub fn new(base_dir: impl Into<PathBuf>, target_path: impl Into<PathBuf>) -> Self {
+        Self {
+            base_dir: base_dir.into(),
+            target_path: target_path.into(),
+            options: MergeOptions {
+                strict: true,
+                ..MergeOptions::default()
+            },
+        }
+    }
+
+    /// Options of the merge. `profiles` lists profiles merged into the
+    /// default profile as well.
+    pub fn options(mut self, options: MergeOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Names of the profiles the hierarchy has files for, in name order.
+    fn profile_names(&self) -> Result<BTreeSet<String>> {
+        let base_dir = self.options.input_path(&self.base_dir)?;
+        let target_path = self.options.input_path(&self.target_path)?;
+        let (base_dir, target_path) = canonicalize_hierarchy(&base_dir, &target_path)?;
+        let discovered = discover_yaml_files(&base_dir, &self.options, &mut Vec::new())?;
+        let extra_root_files = discover_extra_roots(&self.options, &mut Vec::new())?;
+        let files = select_hierarchy_files(&base_dir, &target_path, &discovered)
+            .into_iter()
+            .chain(extra_root_files.into_iter().map(|(path, _)| path));
+
+        Ok(files
+            .filter_map(|path| {
+                let stem = path.file_stem()?.to_str()?;
+                Some(stem.rsplit_once('@')?.1.to_string())
+            })
+            .filter(|profile| !self.options.profiles.contains(profile))
+            .collect())
+    }
+
+    fn merge(&self, options: &MergeOptions) -> Result<ConfigValue> {
+        let (config, _) = merge_hierarchy(&self.base_dir, &self.target_path, options)?;
+        Ok(config)
+    }
+
+    fn profile_data(&self) -> Result<Map<Profile, Dict>> {
+        let default = self.merge(&self.options)?;
+        let mut data = Map::new();
+        for name in self.profile_names()? {
+            let mut options = self.options.clone();
+            options.profiles.push(name.clone());
+            let changes = profile_changes(&default, &self.merge(&options)?)?;
+            data.insert(Profile::new(&name), to_dict(&changes)?);
+        }
+        data.insert(Profile::Default, to_dict(&default)?);
+        Ok(data)
+    }
+}
+
+fn to_dict(config: &ConfigValue) -> Result<Dict> {
+    match Value::serialize(config).map_err(|e| anyhow::anyhow!("{e}"))? {
+        Value::Dict(_, dict) => Ok(dict),
+        _ => Ok(Dict::new()),
+    }
+}
+
+/// What `profiled` adds to or changes in `default`.
+fn profile_changes(default: &ConfigValue, profiled: &ConfigValue) -> Result<ConfigValue> {
+    let mut changes = ConfigValue::Mapping(serde_yaml::Mapping::new());
+    for entry in diff(default, profiled) {
+        match entry.change {
+            Change::Added(value) | Change::Modified { new: value, .. } => {
+                set_segments(&mut changes, &entry.path, value)?
+            }
+            Change::Removed(_) => {}
+        }
+    }
+    Ok(changes)
+}
+
+impl Provider for HierarchicalConfig {
+    fn metadata(&self) -> Metadata {
+        let hierarchy = format!("{} -> {}", self.base_dir.display(), self.target_path.display());
+        Metadata::named("hierarchical config").source(Source::Custom(hierarchy))
+    }
+
+    fn data(&self) -> Result<Map<Profile, Dict>, Error> {
+        self.profile_data().map_err(|e| Error::from(format!("{e:#}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use figment::providers::{Env, Serialized};
+    use figment::Figment;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
+    struct Server {
+        host: String,
+        port: u16,
+        workers: u8,
+    }
+
+    #[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
+    struct App {
+        server: Server,
+        debug: bool,
+    }
+
+    fn hierarchy() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("svc")).unwrap();
+        fs::write(dir.path().join("config.yaml"), "server:\n  host: base\n  port: 80\n").unwrap();
+        fs::write(dir.path().join("svc/config.yaml"), "server:\n  port: 8080\n").unwrap();
+        fs::write(dir.path().join("svc/config@dev.yaml"), "server:\n  host: localhost\ndebug: true\n").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_extracts_defaults_hierarchy_and_env() {
+        let dir = hierarchy();
+        let figment = || {
+            Figment::from(Serialized::defaults(App {
+                server: Server {
+                    workers: 4,
+                    ..Server::default()
+                },
+                ..App::default()
+            }))
+            .merge(HierarchicalConfig::new(dir.path(), dir.path().join("svc")))
+            .merge(Env::prefixed("HCM_FIGMENT_TEST_").split("__"))
+        };
+
+        // SAFETY: the variable name is unique to this test.
+        unsafe { std::env::set_var("HCM_FIGMENT_TEST_SERVER__WORKERS", "8") };
+        let app: App = figment().extract().unwrap();
+        assert_eq!(
+            app,
+            App {
+                server: Server {
+                    host: "base".to_string(),
+                    port: 8080,
+                    workers: 8,
+                },
+                debug: false,
+            }
+        );
+
+        let app: App = figment().select("dev").extract().unwrap();
+        assert_eq!((app.server.host.as_str(), app.server.port, app.debug), ("localhost", 8080, true));
+        assert_eq!(app.server.workers, 8);
+    }
+
+    #[test]
+    fn test_warnings_and_failures_are_figment_errors() {
+        let dir = hierarchy();
+        fs::write(dir.path().join("other.yaml"), "server: {}\n").unwrap();
+
+        let provider = HierarchicalConfig::new(dir.path(), dir.path().join("svc"));
+        let err = Figment::from(provider.clone()).extract::<App>().unwrap_err();
+        assert!(err.to_string().contains("Key collision at depth"), "{err}");
+
+        let lenient = provider.options(MergeOptions::default());
+        assert!(Figment::from(lenient).extract_inner::<u16>("server.port").is_ok());
+
+        let missing = HierarchicalConfig::new(dir.path(), dir.path().join("missing"));
+        assert!(Figment::from(missing).extract::<App>().is_err());
+    }
+}
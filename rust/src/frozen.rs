@@ -0,0 +1,198 @@
+//! Read-only configs whose subtrees are shared rather than copied, see
+//! [`FrozenConfig`].
+
+use std::sync::Arc;
+
+use indexmap::IndexMap;
+
+use crate::error::ConfigError;
+use crate::keypath::{parse_key_path, PathSegment};
+use crate::ConfigValue;
+
+/// A config that no longer changes, handed out to many readers.
+///
+/// Every mapping, sequence and scalar sits behind an `Arc`, so cloning a
+/// `FrozenConfig` or taking one of its subtrees with
+/// [`FrozenConfig::subtree`] copies no values: the subtree shares its nodes
+/// with the config it was taken from. [`FrozenConfig::to_value`] builds an
+/// owned [`ConfigValue`] again when one is needed. Tagged values are kept
+/// whole, as scalars.
+///
+/// ```
+/// # use hierarchical_config_merging::{ConfigValue, FrozenConfig};
+/// let merged: ConfigValue = serde_yaml::from_str("db: {host: a, pool: {size: 4}}\n").unwrap();
+/// let config = FrozenConfig::new(merged);
+/// let db = config.subtree("db").unwrap().unwrap();
+/// assert_eq!(db.get_path("pool.size").unwrap().and_then(|size| size.scalar()), Some(&ConfigValue::from(4)));
+/// assert!(FrozenConfig::ptr_eq(db.get_path("pool").unwrap().unwrap(), config.get_path("db.pool").unwrap().unwrap()));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrozenConfig(Arc<Node>);
+
+#[derive(Debug, PartialEq)]
+enum Node {
+    Mapping(IndexMap<ConfigValue, FrozenConfig>),
+    Sequence(Vec<FrozenConfig>),
+    Scalar(ConfigValue),
+}
+
+impl FrozenConfig {
+    /// Freezes `value` in a single pass, moving its scalars rather than
+    /// copying them.
+    pub fn new(value: ConfigValue) -> Self {
+        let node = match value {
+            ConfigValue::Mapping(map) => Node::Mapping(map.into_iter().map(|(key, item)| (key, Self::new(item))).collect()),
+            ConfigValue::Sequence(items) => Node::Sequence(items.into_iter().map(Self::new).collect()),
+            value => Node::Scalar(value),
+        };
+        Self(Arc::new(node))
+    }
+
+    /// The node at `segments`, if any.
+    pub fn get_segments(&self, segments: &[PathSegment]) -> Option<&FrozenConfig> {
+        segments.iter().try_fold(self, |node, segment| match (segment, node.0.as_ref()) {
+            (PathSegment::Key(key), Node::Mapping(map)) => map.get(&ConfigValue::String(key.clone())),
+            (PathSegment::Index(index), Node::Sequence(items)) => items.get(*index),
+            _ => None,
+        })
+    }
+
+    /// The node at a dotted key path such as `service.ports[0]`, as
+    /// [`crate::keypath::get_path`] finds it.
+    pub fn get_path(&self, path: &str) -> Result<Option<&FrozenConfig>, ConfigError> {
+        Ok(self.get_segments(&parse_key_path(path)?))
+    }
+
+    /// The subtree at `path`, sharing its nodes with this config.
+    pub fn subtree(&self, path: &str) -> Result<Option<FrozenConfig>, ConfigError> {
+        Ok(self.get_path(path)?.cloned())
+    }
+
+    /// The value of a node that is neither a mapping nor a sequence.
+    pub fn scalar(&self) -> Option<&ConfigValue> {
+        match self.0.as_ref() {
+            Node::Scalar(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The entries of a mapping node, in order.
+    pub fn entries(&self) -> impl Iterator<Item = (&ConfigValue, &FrozenConfig)> {
+        let map = match self.0.as_ref() {
+            Node::Mapping(map) => Some(map),
+            _ => None,
+        };
+        map.into_iter().flatten()
+    }
+
+    /// The items of a sequence node.
+    pub fn items(&self) -> &[FrozenConfig] {
+        match self.0.as_ref() {
+            Node::Sequence(items) => items,
+            _ => &[],
+        }
+    }
+
+    /// An owned copy of the config.
+    pub fn to_value(&self) -> ConfigValue {
+        match self.0.as_ref() {
+            Node::Mapping(map) => ConfigValue::Mapping(map.iter().map(|(key, item)| (key.clone(), item.to_value())).collect()),
+            Node::Sequence(items) => ConfigValue::Sequence(items.iter().map(Self::to_value).collect()),
+            Node::Scalar(value) => value.clone(),
+        }
+    }
+
+    /// Whether `a` and `b` are the same node, not merely equal ones.
+    pub fn ptr_eq(a: &FrozenConfig, b: &FrozenConfig) -> bool {
+        Arc::ptr_eq(&a.0, &b.0)
+    }
+
+    /// Handles to this node, those of the configs it is part of included.
+    #[cfg(test)]
+    fn strong_count(&self) -> usize {
+        Arc::strong_count(&self.0)
+    }
+}
+
+impl From<ConfigValue> for FrozenConfig {
+    fn from(value: ConfigValue) -> Self {
+        Self::new(value)
+    }
+}
+
+impl From<&FrozenConfig> for ConfigValue {
+    fn from(config: &FrozenConfig) -> Self {
+        config.to_value()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn yaml(text: &str) -> ConfigValue {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    const CONFIG: &str = "service: {name: api, ports: [{name: http, port: 80}, {name: grpc, port: 9090}]}\n\
+                          db: {host: db.internal, pool: {size: 4, timeout: 30}}\n\
+                          tags: !labels {team: core}\n\
+                          1: one\n";
+
+    #[test]
+    fn test_round_trip_and_lookups() {
+        let value = yaml(CONFIG);
+        let config = FrozenConfig::new(value.clone());
+        assert_eq!(config.to_value(), value);
+        assert_eq!(ConfigValue::from(&config), value);
+
+        let port = config.get_path("service.ports[1].port").unwrap().unwrap();
+        assert_eq!(port.scalar(), Some(&ConfigValue::from(9090)));
+        assert_eq!(config.get_path("db.pool").unwrap().unwrap().to_value(), yaml("{size: 4, timeout: 30}"));
+        assert_eq!(config.get_path("").unwrap(), Some(&config));
+        assert_eq!(config.get_path("service.ports[2]").unwrap(), None);
+        assert_eq!(config.get_path("db.host.name").unwrap(), None);
+        assert!(config.get_path("service[").is_err());
+        // Tagged values are scalars.
+        assert_eq!(config.get_path("tags").unwrap().unwrap().scalar(), value.get("tags"));
+        assert_eq!(config.get_path("tags.team").unwrap(), None);
+        assert_eq!(config.entries().count(), 4);
+        assert_eq!(config.subtree("service.ports").unwrap().unwrap().items().len(), 2);
+        assert!(port.items().is_empty() && port.entries().next().is_none() && config.scalar().is_none());
+
+        let keys: Vec<_> = config.entries().map(|(key, _)| key.clone()).collect();
+        assert_eq!(keys, ["service", "db", "tags"].map(ConfigValue::from).into_iter().chain([ConfigValue::from(1)]).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_subtrees_share_their_nodes() {
+        let config = FrozenConfig::new(yaml(CONFIG));
+        let pool = config.get_path("db.pool").unwrap().unwrap();
+        assert_eq!(pool.strong_count(), 1);
+
+        let db = config.subtree("db").unwrap().unwrap();
+        let clone = config.clone();
+        assert_eq!(config.strong_count(), 2);
+        // Taking a subtree adds a handle to its root only.
+        assert_eq!(db.strong_count(), 2);
+        assert_eq!(pool.strong_count(), 1);
+        let from_db = db.subtree("pool").unwrap().unwrap();
+        assert!(FrozenConfig::ptr_eq(&from_db, pool));
+        assert!(FrozenConfig::ptr_eq(db.get_path("pool.size").unwrap().unwrap(), clone.get_path("db.pool.size").unwrap().unwrap()));
+        assert_eq!(pool.strong_count(), 2);
+
+        // Subtrees outlive the config they were taken from.
+        drop((config, clone));
+        assert_eq!(db.strong_count(), 1);
+        assert_eq!(from_db.strong_count(), 2);
+        assert_eq!(db.to_value(), yaml("{host: db.internal, pool: {size: 4, timeout: 30}}"));
+        drop(db);
+        assert_eq!(from_db.strong_count(), 1);
+        assert_eq!(from_db.to_value(), yaml("{size: 4, timeout: 30}"));
+
+        // Equal configs frozen apart are equal without sharing nodes.
+        let (a, b) = (FrozenConfig::new(yaml("{a: [1]}")), FrozenConfig::from(yaml("{a: [1]}")));
+        assert_eq!(a, b);
+        assert!(!FrozenConfig::ptr_eq(&a, &b));
+    }
+}
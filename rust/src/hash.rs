@@ -0,0 +1,132 @@
+//! Content hash of a config, independent of how its files were written.
+
+use crate::source::sha256_hex;
+use crate::ConfigValue;
+
+/// Hex SHA-256 of the canonical form of `config`.
+///
+/// Mapping keys are sorted, so key order does not matter, and scalars are
+/// hashed by value rather than spelling: `16`, `0x10` and `+16` hash alike,
+/// as do `1.0` and `1.00`, or a quoted and a plain string. Integers and
+/// floats stay distinct (`1` is not `1.0`), and tags are part of the hash.
+///
+/// ```
+/// # use hierarchical_config_merging::config_hash;
+/// let a = serde_yaml::from_str("{port: 0x50, hosts: [a, b]}").unwrap();
+/// let b = serde_yaml::from_str("hosts:\n  - 'a'\n  - b\nport: 80\n").unwrap();
+/// assert_eq!(config_hash(&a), config_hash(&b));
+/// ```
+pub fn config_hash(config: &ConfigValue) -> String {
+    let mut bytes = Vec::new();
+    write_canonical(config, &mut bytes);
+    sha256_hex(&bytes)
+}
+
+/// Appends the canonical encoding of `value`: a type marker followed by the
+/// value, with lengths in front of strings and collections so that no two
+/// values share an encoding.
+fn write_canonical(value: &ConfigValue, bytes: &mut Vec<u8>) {
+    match value {
+        ConfigValue::Null => bytes.push(b'n'),
+        ConfigValue::Bool(b) => bytes.push(if *b { b't' } else { b'f' }),
+        ConfigValue::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                write_prefixed(b'i', i.to_string().as_bytes(), bytes);
+            } else if let Some(u) = n.as_u64() {
+                write_prefixed(b'i', u.to_string().as_bytes(), bytes);
+            } else {
+                let f = n.as_f64().unwrap_or(f64::NAN);
+                // One spelling for every NaN, and 0.0 for -0.0.
+                let f = if f.is_nan() { f64::NAN } else { f + 0.0 };
+                write_prefixed(b'd', format!("{f:?}").as_bytes(), bytes);
+            }
+        }
+        ConfigValue::String(s) => write_prefixed(b's', s.as_bytes(), bytes),
+        ConfigValue::Sequence(items) => {
+            write_prefixed(b'l', items.len().to_string().as_bytes(), bytes);
+            for item in items {
+                write_canonical(item, bytes);
+            }
+        }
+        ConfigValue::Mapping(map) => {
+            let mut entries: Vec<(Vec<u8>, Vec<u8>)> = map
+                .iter()
+                .map(|(key, item)| {
+                    let (mut key_bytes, mut item_bytes) = (Vec::new(), Vec::new());
+                    write_canonical(key, &mut key_bytes);
+                    write_canonical(item, &mut item_bytes);
+                    (key_bytes, item_bytes)
+                })
+                .collect();
+            entries.sort();
+            write_prefixed(b'm', entries.len().to_string().as_bytes(), bytes);
+            for (key, item) in entries {
+                bytes.extend(key);
+                bytes.extend(item);
+            }
+        }
+        ConfigValue::Tagged(tagged) => {
+            write_prefixed(b'!', tagged.tag.to_string().as_bytes(), bytes);
+            write_canonical(&tagged.value, bytes);
+        }
+    }
+}
+
+fn write_prefixed(marker: u8, content: &[u8], bytes: &mut Vec<u8>) {
+    bytes.push(marker);
+    bytes.extend(content.len().to_string().as_bytes());
+    bytes.push(b':');
+    bytes.extend(content);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn yaml(text: &str) -> ConfigValue {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn test_hash_ignores_formatting_and_file_order() {
+        let block = tempfile::tempdir().unwrap();
+        fs::create_dir(block.path().join("svc")).unwrap();
+        fs::write(block.path().join("config.yaml"), "server:\n  port: 80\n  hosts:\n    - a\n    - b\nratio: 1.50\n").unwrap();
+        fs::write(block.path().join("svc/config.yaml"), "name: svc\nsecret: !vault db\n").unwrap();
+
+        let flow = tempfile::tempdir().unwrap();
+        fs::create_dir(flow.path().join("svc")).unwrap();
+        fs::write(flow.path().join("config.yaml"), "{ratio: 1.5, server: {hosts: ['a', \"b\"], port: 0x50}}\n").unwrap();
+        fs::write(flow.path().join("svc/config.yaml"), "# leaf\nsecret: !vault 'db'\nname: \"svc\"\n").unwrap();
+
+        let merge = |dir: &tempfile::TempDir| {
+            let options = Default::default();
+            crate::merge_hierarchy(dir.path(), &dir.path().join("svc"), &options).unwrap().0
+        };
+        let (block_config, flow_config) = (merge(&block), merge(&flow));
+        assert_eq!(config_hash(&block_config), config_hash(&flow_config));
+        assert_eq!(config_hash(&block_config).len(), 64);
+
+        fs::write(flow.path().join("svc/config.yaml"), "secret: !vault 'db'\nname: \"svc2\"\n").unwrap();
+        assert_ne!(config_hash(&block_config), config_hash(&merge(&flow)));
+    }
+
+    #[test]
+    fn test_hash_tells_types_and_tags_apart() {
+        let hashes = [
+            config_hash(&yaml("a: 1")),
+            config_hash(&yaml("a: 1.0")),
+            config_hash(&yaml("a: '1'")),
+            config_hash(&yaml("a: !int 1")),
+            config_hash(&yaml("a: [1]")),
+            config_hash(&yaml("a: null")),
+            config_hash(&yaml("a: ''")),
+        ];
+        for (index, hash) in hashes.iter().enumerate() {
+            assert!(!hashes[index + 1..].contains(hash), "collision for value {index}");
+        }
+        assert_eq!(config_hash(&yaml("a: -0.0")), config_hash(&yaml("a: 0.0")));
+        assert_eq!(config_hash(&yaml("{a: 1, b: 2}")), config_hash(&yaml("{b: 2, a: 1}")));
+    }
+}
@@ -0,0 +1,219 @@
+//! Overrides of single sequence items by index, see
+//! [`MergeOptions::index_patches`].
+
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::error::ConfigError;
+use crate::keypath::{format_key_path, key_to_string, PathSegment};
+use crate::options::{IndexOutOfRange, MergeMode, MergeOptions};
+use crate::report::{ReportEntry, ReportKind};
+use crate::ConfigValue;
+
+/// Tag of the second form of an index patch, whose keys may be plain
+/// integers:
+///
+/// ```yaml
+/// servers: !patch-index
+///   2: {port: 9090}
+/// ```
+pub const PATCH_INDEX_TAG: &str = "patch-index";
+
+/// The patches of `value`, in order, when it is an index patch: a mapping
+/// whose keys are all of the form `[N]`, or a [`PATCH_INDEX_TAG`] mapping
+/// whose keys are `[N]` or non-negative integers. Anything else, such as a
+/// mapping mixing `[N]` with other keys, is an ordinary value.
+pub(crate) fn index_patches(value: &ConfigValue) -> Option<Vec<(usize, &ConfigValue)>> {
+    let (map, tagged) = match value {
+        ConfigValue::Mapping(map) if !map.is_empty() => (map, false),
+        ConfigValue::Tagged(tagged) if tagged.tag == PATCH_INDEX_TAG => (tagged.value.as_mapping()?, true),
+        _ => return None,
+    };
+    map.iter()
+        .map(|(key, patch)| {
+            let index = match key {
+                ConfigValue::String(key) => key.strip_prefix('[')?.strip_suffix(']')?,
+                ConfigValue::Number(index) if tagged => return Some((usize::try_from(index.as_u64()?).ok()?, patch)),
+                _ => return None,
+            };
+            if !index.bytes().all(|byte| byte.is_ascii_digit()) {
+                return None;
+            }
+            Some((index.parse().ok()?, patch))
+        })
+        .collect()
+}
+
+/// Checks the index patches of `r#override`, about to be merged onto
+/// `base`, adding a [`ReportKind::IndexOutOfRange`] entry for each index
+/// beyond the end of its sequence and each patch of a value that is no
+/// sequence, or failing on the first with [`IndexOutOfRange::Error`]. `file`
+/// names the overriding file, if any, in the entries.
+pub(crate) fn check_index_patches(
+    base: &ConfigValue,
+    r#override: &ConfigValue,
+    file: Option<&Path>,
+    options: &MergeOptions,
+    entries: &mut Vec<ReportEntry>,
+) -> Result<()> {
+    if !options.index_patches || options.mode != MergeMode::Deep {
+        return Ok(());
+    }
+    let mut checker = Checker { file, options, entries };
+    checker.check(Some(base), r#override, &mut Vec::new())
+}
+
+struct Checker<'e, 'f> {
+    file: Option<&'f Path>,
+    options: &'e MergeOptions,
+    entries: &'e mut Vec<ReportEntry>,
+}
+
+impl Checker<'_, '_> {
+    fn check(&mut self, base: Option<&ConfigValue>, value: &ConfigValue, path: &mut Vec<PathSegment>) -> Result<()> {
+        if let Some(patches) = index_patches(value) {
+            let Some(ConfigValue::Sequence(items)) = base else {
+                let message = format!("Index patch at '{}'{} patches no sequence", format_key_path(path), self.in_file());
+                return self.out_of_range(path, message);
+            };
+            for (index, patch) in patches {
+                path.push(PathSegment::Index(index));
+                match items.get(index) {
+                    Some(item) => self.check(Some(item), patch, path)?,
+                    None => {
+                        let message = format!(
+                            "Index patch at '{}'{} is out of range: the sequence has {} item(s)",
+                            format_key_path(path),
+                            self.in_file(),
+                            items.len()
+                        );
+                        self.out_of_range(path, message)?;
+                    }
+                }
+                path.pop();
+            }
+        } else if let ConfigValue::Mapping(map) = value {
+            let base = base.and_then(ConfigValue::as_mapping);
+            for (key, item) in map {
+                path.push(PathSegment::Key(key_to_string(key)));
+                self.check(base.and_then(|base| base.get(key)), item, path)?;
+                path.pop();
+            }
+        }
+        Ok(())
+    }
+
+    fn out_of_range(&mut self, path: &[PathSegment], message: String) -> Result<()> {
+        if self.options.index_out_of_range == IndexOutOfRange::Error {
+            return Err(ConfigError::IndexPatch {
+                path: format_key_path(path),
+                file: self.file.map(Path::to_path_buf),
+                message,
+            }
+            .into());
+        }
+        self.entries.push(ReportEntry {
+            kind: ReportKind::IndexOutOfRange,
+            key_path: Some(format_key_path(path)),
+            files: self.file.map(Path::to_path_buf).into_iter().collect(),
+            message: format!("{message}; left out"),
+        });
+        Ok(())
+    }
+
+    fn in_file(&self) -> String {
+        self.file.map(|file| format!(" in {}", file.display())).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixture_tree;
+    use crate::{deep_merge_with, CollisionPolicy, SequenceStrategy};
+
+    fn yaml(text: &str) -> ConfigValue {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    fn patching() -> MergeOptions {
+        MergeOptions {
+            index_patches: true,
+            ..MergeOptions::default()
+        }
+    }
+
+    const SERVERS: &str = "servers: [{host: a, port: 80}, {host: b, port: 80}, {host: c, port: 80}]\n";
+
+    #[test]
+    fn test_patch_merges_into_the_item_at_its_index() {
+        let base = yaml(SERVERS);
+        let expected = yaml("servers: [{host: a, port: 80}, {host: b, port: 80}, {host: c, port: 9090}]\n");
+        for patch in ["servers: {'[2]': {port: 9090}}", "servers: !patch-index {2: {port: 9090}}"] {
+            assert_eq!(deep_merge_with(&base, &yaml(patch), &patching()), expected, "{patch}");
+        }
+        // Whatever the sequence strategy, and with several or nested indexes.
+        let append = MergeOptions {
+            sequence_strategy: SequenceStrategy::Append,
+            ..patching()
+        };
+        let nested = yaml("{a: [[1, 2], [3, {x: 4}]]}");
+        assert_eq!(deep_merge_with(&nested, &yaml("a: {'[1]': {'[1]': {y: 5}}, '[0]': [9]}"), &append), yaml("{a: [[1, 2, 9], [3, {x: 4, y: 5}]]}"));
+
+        // Without the option, or mixed with other keys, the mapping is a value.
+        let plain = yaml("servers: {'[2]': {port: 9090}}");
+        assert_eq!(deep_merge_with(&base, &plain, &MergeOptions::default()), plain);
+        let mixed = yaml("servers: {'[2]': {port: 9090}, name: x}");
+        assert_eq!(deep_merge_with(&base, &mixed, &patching()), mixed);
+        assert!(index_patches(&yaml("{'[-1]': 1}")).is_none());
+        assert!(index_patches(&yaml("{'[1x]': 1}")).is_none());
+        assert!(index_patches(&yaml("!patch-index {a: 1}")).is_none());
+        assert!(index_patches(&yaml("{}")).is_none());
+    }
+
+    #[test]
+    fn test_out_of_range_and_non_sequence_patches_are_left_out() {
+        let dir = fixture_tree(&[
+            ("config.yaml", &format!("{SERVERS}name: api\n")),
+            ("prod/config.yaml", "servers: {'[1]': {port: 443}, '[5]': {port: 1}}\nname: {'[0]': x}\nextra: {'[0]': y}\n"),
+        ]);
+        let prod = dir.path().join("prod");
+        let (config, report) = crate::merge_hierarchy(dir.path(), &prod, &patching()).unwrap();
+        assert_eq!(
+            config,
+            yaml("servers: [{host: a, port: 80}, {host: b, port: 443}, {host: c, port: 80}]\nname: api\n")
+        );
+        let file = dir.path().canonicalize().unwrap().join("prod/config.yaml");
+        let entries: Vec<_> = report.entries.iter().map(|entry| (entry.kind, entry.key_path.as_deref(), entry.message.clone())).collect();
+        assert_eq!(
+            entries,
+            [
+                (
+                    ReportKind::IndexOutOfRange,
+                    Some("servers[5]"),
+                    format!("Index patch at 'servers[5]' in {} is out of range: the sequence has 3 item(s); left out", file.display())
+                ),
+                (ReportKind::IndexOutOfRange, Some("name"), format!("Index patch at 'name' in {} patches no sequence; left out", file.display())),
+                (ReportKind::IndexOutOfRange, Some("extra"), format!("Index patch at 'extra' in {} patches no sequence; left out", file.display())),
+            ]
+        );
+        assert!(report.entries.iter().all(|entry| entry.kind.is_warning() && entry.files == [file.clone()]));
+
+        let mut merger = crate::HierarchyMerger::new(dir.path(), patching());
+        assert_eq!(merger.merge_with_report(&prod).unwrap(), (std::sync::Arc::new(config), report));
+
+        let error = MergeOptions {
+            index_out_of_range: IndexOutOfRange::Error,
+            collision_policy: CollisionPolicy::Error,
+            ..patching()
+        };
+        let err = crate::merge_hierarchy(dir.path(), &prod, &error).unwrap_err();
+        let ConfigError::IndexPatch { path, file: Some(failed), message } = err else {
+            panic!("Expected IndexPatch, got {err:?}");
+        };
+        assert_eq!((path.as_str(), failed), ("servers[5]", file.clone()));
+        assert!(message.ends_with("is out of range: the sequence has 3 item(s)"), "{message}");
+        assert_eq!("error".parse::<IndexOutOfRange>(), Ok(IndexOutOfRange::Error));
+    }
+}
@@ -0,0 +1,256 @@
+//! Merging with mapping keys interned, see [`merge_interned`].
+//!
+//! A [`ConfigValue`] owns a separate allocation for every mapping key, so a
+//! hierarchy repeating `enabled` or `timeout` thousands of times holds as many
+//! copies, and a merge copying a mapping copies all its keys. Here configs
+//! are converted to a tree whose string keys are shared `Arc<str>`s, one per
+//! distinct name, merged in that form and converted back once at the end.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use indexmap::IndexMap;
+
+use crate::keypath::key_to_string;
+use crate::mergeable::{merge_values, Mergeable, ValueKind};
+use crate::options::MergeOptions;
+use crate::ConfigValue;
+
+/// A mapping key: a shared string, or any other YAML key as is.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum InternedKey {
+    Name(Arc<str>),
+    Value(ConfigValue),
+}
+
+/// A [`ConfigValue`] whose mappings have [`InternedKey`]s. Scalars, nulls and
+/// tagged values are kept as they are.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum InternedValue {
+    Mapping(IndexMap<InternedKey, InternedValue>),
+    Sequence(Vec<InternedValue>),
+    Plain(ConfigValue),
+}
+
+/// The distinct key names seen so far.
+#[derive(Debug, Default)]
+pub(crate) struct Interner {
+    names: HashSet<Arc<str>>,
+}
+
+impl Interner {
+    /// The shared copy of `name`.
+    fn name(&mut self, name: &str) -> Arc<str> {
+        if let Some(shared) = self.names.get(name) {
+            return shared.clone();
+        }
+        let shared: Arc<str> = Arc::from(name);
+        self.names.insert(shared.clone());
+        shared
+    }
+
+    /// `value` with its string keys replaced by their shared copies.
+    pub(crate) fn intern(&mut self, value: &ConfigValue) -> InternedValue {
+        match value {
+            ConfigValue::Mapping(map) => InternedValue::Mapping(
+                map.iter()
+                    .map(|(key, item)| {
+                        let key = match key {
+                            ConfigValue::String(name) => InternedKey::Name(self.name(name)),
+                            key => InternedKey::Value(key.clone()),
+                        };
+                        (key, self.intern(item))
+                    })
+                    .collect(),
+            ),
+            ConfigValue::Sequence(items) => InternedValue::Sequence(items.iter().map(|item| self.intern(item)).collect()),
+            value => InternedValue::Plain(value.clone()),
+        }
+    }
+}
+
+impl InternedValue {
+    /// The value as a [`ConfigValue`], allocating each key once more.
+    pub(crate) fn into_config(self) -> ConfigValue {
+        match self {
+            InternedValue::Mapping(map) => ConfigValue::Mapping(
+                map.into_iter()
+                    .map(|(key, item)| {
+                        let key = match key {
+                            InternedKey::Name(name) => ConfigValue::String(name.to_string()),
+                            InternedKey::Value(key) => key,
+                        };
+                        (key, item.into_config())
+                    })
+                    .collect(),
+            ),
+            InternedValue::Sequence(items) => {
+                ConfigValue::Sequence(items.into_iter().map(InternedValue::into_config).collect())
+            }
+            InternedValue::Plain(value) => value,
+        }
+    }
+}
+
+impl Mergeable for InternedValue {
+    type Key = InternedKey;
+    type Map = IndexMap<InternedKey, InternedValue>;
+
+    fn kind(&self) -> ValueKind {
+        match self {
+            InternedValue::Mapping(_) => ValueKind::Mapping,
+            InternedValue::Sequence(_) => ValueKind::Sequence,
+            InternedValue::Plain(ConfigValue::Null) => ValueKind::Null,
+            InternedValue::Plain(_) => ValueKind::Scalar,
+        }
+    }
+
+    fn as_map(&self) -> Option<&Self::Map> {
+        match self {
+            InternedValue::Mapping(map) => Some(map),
+            _ => None,
+        }
+    }
+
+    fn as_sequence(&self) -> Option<&[Self]> {
+        match self {
+            InternedValue::Sequence(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    fn from_map(map: Self::Map) -> Self {
+        InternedValue::Mapping(map)
+    }
+
+    fn from_sequence(items: Vec<Self>) -> Self {
+        InternedValue::Sequence(items)
+    }
+
+    fn null() -> Self {
+        InternedValue::Plain(ConfigValue::Null)
+    }
+
+    fn new_map() -> Self::Map {
+        IndexMap::new()
+    }
+
+    fn map_iter<'a>(map: &'a Self::Map) -> impl Iterator<Item = (&'a Self::Key, &'a Self)>
+    where
+        Self: 'a,
+    {
+        map.iter()
+    }
+
+    fn map_get<'a>(map: &'a Self::Map, key: &Self::Key) -> Option<&'a Self> {
+        map.get(key)
+    }
+
+    fn map_insert(map: &mut Self::Map, key: Self::Key, value: Self) {
+        map.insert(key, value);
+    }
+
+    fn map_remove(map: &mut Self::Map, key: &Self::Key) {
+        // Like `serde_yaml::Mapping::remove`, which moves the last entry
+        // into the place of the removed one.
+        map.swap_remove(key);
+    }
+
+    fn key_name(key: &Self::Key) -> String {
+        match key {
+            InternedKey::Name(name) => name.to_string(),
+            InternedKey::Value(key) => key_to_string(key),
+        }
+    }
+}
+
+/// Merges `configs` in order, each on top of the ones before it, like a fold
+/// of [`crate::deep_merge_with`], with the mapping keys of all of them
+/// interned for the length of the merge. Worth it for many configs repeating
+/// the same key names. The strategic merge patch mode merges deeply here, as
+/// for every [`Mergeable`] value other than [`ConfigValue`].
+///
+/// ```
+/// # use hierarchical_config_merging::{merge_interned, ConfigValue, MergeOptions};
+/// let base: ConfigValue = serde_yaml::from_str("server: {port: 80, host: a}").unwrap();
+/// let prod: ConfigValue = serde_yaml::from_str("server: {port: 8080}").unwrap();
+/// let merged = merge_interned([&base, &prod], &MergeOptions::default());
+/// assert_eq!(merged, serde_yaml::from_str::<ConfigValue>("server: {port: 8080, host: a}").unwrap());
+/// ```
+pub fn merge_interned<'a, I>(configs: I, options: &MergeOptions) -> ConfigValue
+where
+    I: IntoIterator<Item = &'a ConfigValue>,
+{
+    let mut interner = Interner::default();
+    let mut merged: Option<InternedValue> = None;
+    for config in configs {
+        let config = interner.intern(config);
+        merged = Some(match merged {
+            Some(merged) => merge_values(&merged, &config, options),
+            None => config,
+        });
+    }
+    merged.map_or(ConfigValue::Null, InternedValue::into_config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::{MergeMode, SequenceStrategy};
+
+    #[test]
+    fn test_interned_merge_matches_deep_merge() {
+        let configs: Vec<ConfigValue> = [
+            "a: {b: 1, c: [1, 2], d: x}\ne: [1]\nf: !t {g: 1}\n1: one\n",
+            "a: {b: ~, c: [2, 3], h: {i: ~, j: 1}}\ne: [2, 1]\nf: {g: 2}\n1: uno\n",
+            "a: {d: ~, k: {l: 1}}\ne: ~\nm: [a]\n",
+        ]
+        .iter()
+        .map(|text| serde_yaml::from_str(text).unwrap())
+        .collect();
+
+        let mut options_list = Vec::new();
+        for sequence_strategy in
+            [SequenceStrategy::Replace, SequenceStrategy::Append, SequenceStrategy::Prepend, SequenceStrategy::Union]
+        {
+            for null_deletes in [false, true] {
+                options_list.push(MergeOptions {
+                    sequence_strategy,
+                    null_deletes,
+                    ..MergeOptions::default()
+                });
+            }
+        }
+        options_list.push(MergeOptions {
+            mode: MergeMode::JsonMergePatch,
+            ..MergeOptions::default()
+        });
+
+        for options in &options_list {
+            let folded = configs[1..]
+                .iter()
+                .fold(configs[0].clone(), |merged, config| crate::deep_merge_with(&merged, config, options));
+            assert_eq!(merge_interned(&configs, options), folded, "{options:?}");
+        }
+        assert_eq!(merge_interned([], &MergeOptions::default()), ConfigValue::Null);
+    }
+
+    #[test]
+    fn test_keys_are_shared() {
+        let config: ConfigValue = serde_yaml::from_str("a: {enabled: true}\nb: {enabled: false}\n").unwrap();
+        let mut interner = Interner::default();
+        let InternedValue::Mapping(map) = interner.intern(&config) else {
+            panic!("Expected a mapping");
+        };
+        let names: Vec<_> = map
+            .values()
+            .flat_map(|section| section.as_map().unwrap().keys())
+            .map(|key| match key {
+                InternedKey::Name(name) => name.clone(),
+                InternedKey::Value(key) => panic!("Unexpected key {key:?}"),
+            })
+            .collect();
+        assert!(Arc::ptr_eq(&names[0], &names[1]));
+        assert_eq!(interner.names.len(), 3);
+    }
+}
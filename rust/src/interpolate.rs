@@ -0,0 +1,323 @@
+//! `${dotted.path}` references between the values of a merged config, see
+//! [`MergeOptions::interpolate`].
+
+use std::collections::HashMap;
+use anyhow::Result;
+
+use crate::error::ConfigError;
+use crate::keypath::{format_key_path, get_segments, key_to_string, parse_key_path, PathSegment};
+use crate::options::MergeOptions;
+use crate::report::{ReportEntry, ReportKind};
+use crate::ConfigValue;
+
+/// `config` with the references in its strings resolved against itself, and
+/// an entry for every reference that could not be resolved.
+///
+/// A string holding nothing but one `${path}` takes the referenced value as
+/// it is, mappings and sequences included; elsewhere in a string the
+/// referenced scalar is written as text. Referenced values are resolved
+/// first, so references may chain. `$${` writes a literal `${`. A reference
+/// to a missing path, or to a mapping or sequence inside a longer string,
+/// is left as written and reported. Fails with
+/// [`ConfigError::ReferenceCycle`] when a value depends on itself. Tagged
+/// values are left alone.
+///
+/// ```
+/// # use hierarchical_config_merging::interpolate;
+/// let config = serde_yaml::from_str("paths: {base: /srv}\nlog_dir: ${paths.base}/logs\n").unwrap();
+/// let (resolved, entries) = interpolate(&config).unwrap();
+/// assert_eq!(resolved["log_dir"], "/srv/logs");
+/// assert!(entries.is_empty());
+/// ```
+pub fn interpolate(config: &ConfigValue) -> Result<(ConfigValue, Vec<ReportEntry>), ConfigError> {
+    let mut resolver = Resolver {
+        root: config,
+        resolved: HashMap::new(),
+        stack: Vec::new(),
+        entries: Vec::new(),
+    };
+    let resolved = resolver.resolve(config, &mut Vec::new())?;
+    Ok((resolved, resolver.entries))
+}
+
+/// [`interpolate`] when the options ask for it, adding its entries to
+/// `entries`.
+pub(crate) fn interpolate_merged(
+    config: ConfigValue,
+    options: &MergeOptions,
+    entries: &mut Vec<ReportEntry>,
+) -> Result<ConfigValue> {
+    if !options.interpolate {
+        return Ok(config);
+    }
+    let (resolved, unresolved) = interpolate(&config)?;
+    entries.extend(unresolved);
+    Ok(resolved)
+}
+
+struct Resolver<'a> {
+    root: &'a ConfigValue,
+    /// Referenced values resolved so far, by dotted path.
+    resolved: HashMap<String, ConfigValue>,
+    /// Dotted paths being resolved, outermost first.
+    stack: Vec<String>,
+    entries: Vec<ReportEntry>,
+}
+
+impl Resolver<'_> {
+    /// `value`, found at `path`, with every reference below it resolved.
+    fn resolve(&mut self, value: &ConfigValue, path: &mut Vec<PathSegment>) -> Result<ConfigValue> {
+        let key = format_key_path(path);
+        if let Some(start) = self.stack.iter().position(|entered| *entered == key) {
+            let mut chain = self.stack[start..].to_vec();
+            chain.push(key);
+            return Err(ConfigError::ReferenceCycle { chain }.into());
+        }
+        self.stack.push(key);
+        let resolved = match value {
+            ConfigValue::Mapping(map) => {
+                let mut resolved = serde_yaml::Mapping::with_capacity(map.len());
+                for (key, item) in map {
+                    path.push(PathSegment::Key(key_to_string(key)));
+                    let item = self.resolve(item, path)?;
+                    path.pop();
+                    resolved.insert(key.clone(), item);
+                }
+                ConfigValue::Mapping(resolved)
+            }
+            ConfigValue::Sequence(items) => {
+                let mut resolved = Vec::with_capacity(items.len());
+                for (index, item) in items.iter().enumerate() {
+                    path.push(PathSegment::Index(index));
+                    resolved.push(self.resolve(item, path)?);
+                    path.pop();
+                }
+                ConfigValue::Sequence(resolved)
+            }
+            ConfigValue::String(text) if text.contains("${") => self.substitute(text, path)?,
+            _ => value.clone(),
+        };
+        self.stack.pop();
+        Ok(resolved)
+    }
+
+    /// The resolved value at the dotted `reference`, `None` when nothing is
+    /// there.
+    fn lookup(&mut self, reference: &str) -> Result<Option<ConfigValue>> {
+        let Ok(mut segments) = parse_key_path(reference) else {
+            return Ok(None);
+        };
+        let canonical = format_key_path(&segments);
+        if let Some(value) = self.resolved.get(&canonical) {
+            return Ok(Some(value.clone()));
+        }
+        let Some(value) = get_segments(self.root, &segments) else {
+            return Ok(None);
+        };
+        let value = self.resolve(value, &mut segments)?;
+        self.resolved.insert(canonical, value.clone());
+        Ok(Some(value))
+    }
+
+    fn substitute(&mut self, text: &str, path: &[PathSegment]) -> Result<ConfigValue> {
+        let tokens = tokenize(text);
+        if let [Token::Reference(reference)] = tokens.as_slice() {
+            return match self.lookup(reference)? {
+                Some(value) => Ok(value),
+                None => {
+                    self.unresolved(reference, path, "");
+                    Ok(ConfigValue::String(text.to_string()))
+                }
+            };
+        }
+
+        let mut resolved = String::with_capacity(text.len());
+        for token in tokens {
+            match token {
+                Token::Literal(literal) => resolved.push_str(&literal),
+                Token::Reference(reference) => match self.lookup(&reference)?.as_ref().map(scalar_text) {
+                    Some(Some(value)) => resolved.push_str(&value),
+                    found => {
+                        let hint = if found.is_some() { " (not a scalar)" } else { "" };
+                        self.unresolved(&reference, path, hint);
+                        resolved.push_str(&format!("${{{reference}}}"));
+                    }
+                },
+            }
+        }
+        Ok(ConfigValue::String(resolved))
+    }
+
+    fn unresolved(&mut self, reference: &str, path: &[PathSegment], hint: &str) {
+        let key_path = format_key_path(path);
+        self.entries.push(ReportEntry {
+            kind: ReportKind::UnresolvedReference,
+            message: format!("Unresolved reference '${{{reference}}}' at '{key_path}'{hint}"),
+            key_path: Some(key_path),
+            files: Vec::new(),
+        });
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Literal(String),
+    Reference(String),
+}
+
+/// Splits `text` into literal runs and `${...}` references; `$${` is a
+/// literal `${`, and a `${` without its `}` is literal text.
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut literal = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        if rest[..start].ends_with('$') {
+            literal.push_str(&rest[..start - 1]);
+            literal.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        literal.push_str(&rest[..start]);
+        if !literal.is_empty() {
+            tokens.push(Token::Literal(std::mem::take(&mut literal)));
+        }
+        tokens.push(Token::Reference(rest[start + 2..start + end].trim().to_string()));
+        rest = &rest[start + end + 1..];
+    }
+    literal.push_str(rest);
+    if !literal.is_empty() {
+        tokens.push(Token::Literal(literal));
+    }
+    tokens
+}
+
+/// A scalar as it is written into a string; `None` for mappings and
+/// sequences.
+fn scalar_text(value: &ConfigValue) -> Option<String> {
+    match value {
+        ConfigValue::Null => Some("null".to_string()),
+        ConfigValue::Bool(b) => Some(b.to_string()),
+        ConfigValue::Number(n) => Some(n.to_string()),
+        ConfigValue::String(s) => Some(s.clone()),
+        ConfigValue::Tagged(tagged) => scalar_text(&tagged.value),
+        ConfigValue::Sequence(_) | ConfigValue::Mapping(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn yaml(text: &str) -> ConfigValue {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    fn resolved(text: &str) -> ConfigValue {
+        let (config, entries) = interpolate(&yaml(text)).unwrap();
+        assert!(entries.is_empty(), "{entries:?}");
+        config
+    }
+
+    #[test]
+    fn test_tokenize_escapes_and_unterminated_references() {
+        assert_eq!(
+            tokenize("a ${x.y} $${lit} ${ z }${"),
+            vec![
+                Token::Literal("a ".to_string()),
+                Token::Reference("x.y".to_string()),
+                Token::Literal(" ${lit} ".to_string()),
+                Token::Reference("z".to_string()),
+                Token::Literal("${".to_string()),
+            ]
+        );
+        assert_eq!(tokenize("plain"), vec![Token::Literal("plain".to_string())]);
+    }
+
+    #[test]
+    fn test_references_chain_and_keep_types() {
+        assert_eq!(
+            resolved(
+                "paths: {base: /srv, data: '${paths.base}/data'}\n\
+                 log_dir: ${paths.data}/logs\n\
+                 port: 80\n\
+                 url: http://host:${port}/\n\
+                 copy: ${port}\n\
+                 tree: ${paths}\n\
+                 literal: $${paths.base}\n"
+            ),
+            yaml(
+                "paths: {base: /srv, data: /srv/data}\n\
+                 log_dir: /srv/data/logs\n\
+                 port: 80\n\
+                 url: 'http://host:80/'\n\
+                 copy: 80\n\
+                 tree: {base: /srv, data: /srv/data}\n\
+                 literal: '${paths.base}'\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_references_into_sequences() {
+        assert_eq!(
+            resolved("hosts: [{name: a, port: 1}, {name: '${hosts[0].name}-2', port: 2}]\nfirst: ${hosts[1].name}:${hosts[0].port}\n"),
+            yaml("hosts: [{name: a, port: 1}, {name: a-2, port: 2}]\nfirst: a-2:1\n")
+        );
+    }
+
+    #[test]
+    fn test_cycles_report_the_chain() {
+        let chain = |text: &str| match interpolate(&yaml(text)).unwrap_err() {
+            ConfigError::ReferenceCycle { chain } => chain,
+            other => panic!("Expected ReferenceCycle, got {other:?}"),
+        };
+        assert_eq!(chain("a: ${b}\nb: ${c}\nc: x${a}\n"), ["a", "b", "c", "a"]);
+        assert_eq!(chain("a: {b: '${a}'}\n"), ["a", "a.b", "a"]);
+        assert_eq!(chain("self: ${self}\n"), ["self", "self"]);
+        let err = interpolate(&yaml("a: ${b}\nb: ${a}\n")).unwrap_err();
+        assert_eq!(err.to_string(), "Reference cycle: a -> b -> a");
+    }
+
+    #[test]
+    fn test_unresolved_references_are_reported() {
+        let (config, entries) = interpolate(&yaml("a: ${missing}\nb: x-${list}\nlist: [1]\nc: ${bad[}\n")).unwrap();
+        assert_eq!(config, yaml("a: ${missing}\nb: x-${list}\nlist: [1]\nc: ${bad[}\n"));
+        let messages: Vec<_> = entries.iter().map(|entry| entry.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "Unresolved reference '${missing}' at 'a'",
+                "Unresolved reference '${list}' at 'b' (not a scalar)",
+                "Unresolved reference '${bad[}' at 'c'",
+            ]
+        );
+        assert!(entries.iter().all(|entry| entry.kind == ReportKind::UnresolvedReference));
+    }
+
+    #[test]
+    fn test_merge_resolves_after_all_layers() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("prod")).unwrap();
+        std::fs::write(dir.path().join("config.yaml"), "paths: {base: /tmp}\nlog_dir: ${paths.base}/logs\n").unwrap();
+        std::fs::write(dir.path().join("prod/config.yaml"), "paths: {base: /srv}\nextra: ${nope}\n").unwrap();
+        let target = dir.path().join("prod");
+
+        let (config, _) = crate::merge_hierarchy(dir.path(), &target, &MergeOptions::default()).unwrap();
+        assert_eq!(config["log_dir"], "${paths.base}/logs");
+
+        let options = MergeOptions {
+            interpolate: true,
+            ..MergeOptions::default()
+        };
+        let (config, report) = crate::merge_hierarchy(dir.path(), &target, &options).unwrap();
+        assert_eq!(config["log_dir"], "/srv/logs");
+        assert_eq!(report.entries[0].kind, ReportKind::UnresolvedReference);
+
+        let strict = MergeOptions { strict: true, ..options };
+        assert!(crate::merge_hierarchy(dir.path(), &target, &strict).is_err());
+    }
+}
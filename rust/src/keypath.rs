@@ -0,0 +1,409 @@
+use std::fmt;
+use anyhow::{anyhow, Context, Result};
+
+use crate::error::ConfigError;
+use crate::ConfigValue;
+
+/// One step of a key path: a mapping key or a sequence index.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+impl fmt::Display for PathSegment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathSegment::Key(key) => f.write_str(key),
+            PathSegment::Index(index) => write!(f, "[{index}]"),
+        }
+    }
+}
+
+/// Parses a dotted key path such as `service.ports[0].name`. An empty path
+/// addresses the root. A backslash escapes the next character, so `a\.b`
+/// names the key `a.b`.
+pub fn parse_key_path(path: &str) -> Result<Vec<PathSegment>, ConfigError> {
+    let mut segments = Vec::new();
+    if path.is_empty() {
+        return Ok(segments);
+    }
+
+    let mut rest = Some(path);
+    while let Some(remaining) = rest {
+        let part = match find_unescaped(remaining, '.') {
+            Some(end) => {
+                rest = Some(&remaining[end + 1..]);
+                &remaining[..end]
+            }
+            None => {
+                rest = None;
+                remaining
+            }
+        };
+        let (key, mut indices) = match find_unescaped(part, '[') {
+            Some(start) => (&part[..start], &part[start..]),
+            None => (part, ""),
+        };
+        // Only a leading segment may omit the key (`[0].name` on a sequence).
+        if key.is_empty() && (indices.is_empty() || !segments.is_empty()) {
+            return Err(anyhow!("Invalid key path '{path}': empty segment").into());
+        }
+        if !key.is_empty() {
+            segments.push(PathSegment::Key(unescape_key(key)));
+        }
+
+        while !indices.is_empty() {
+            let index = indices
+                .strip_prefix('[')
+                .and_then(|rest| rest.split_once(']'))
+                .and_then(|(index, rest)| Some((index.parse::<usize>().ok()?, rest)));
+            let Some((index, rest)) = index else {
+                return Err(anyhow!("Invalid key path '{path}': malformed index in '{part}'").into());
+            };
+            segments.push(PathSegment::Index(index));
+            indices = rest;
+        }
+    }
+
+    Ok(segments)
+}
+
+/// Renders segments back into the dotted form accepted by [`parse_key_path`],
+/// escaping `.`, `[` and `\` in keys.
+pub fn format_key_path(segments: &[PathSegment]) -> String {
+    let mut path = String::new();
+    push_segments(&mut path, segments);
+    path
+}
+
+fn push_segments(path: &mut String, segments: &[PathSegment]) {
+    for segment in segments {
+        match segment {
+            PathSegment::Key(key) => {
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                for c in key.chars() {
+                    if matches!(c, '.' | '[' | '\\') {
+                        path.push('\\');
+                    }
+                    path.push(c);
+                }
+            }
+            PathSegment::Index(index) => path.push_str(&format!("[{index}]")),
+        }
+    }
+}
+
+/// Byte offset of the first `target` in `text` not escaped by a backslash.
+fn find_unescaped(text: &str, target: char) -> Option<usize> {
+    let mut escaped = false;
+    for (offset, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            c if c == target => return Some(offset),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn unescape_key(key: &str) -> String {
+    let mut unescaped = String::with_capacity(key.len());
+    let mut chars = key.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Looks up the value at `segments`, if any.
+pub fn get_segments<'a>(value: &'a ConfigValue, segments: &[PathSegment]) -> Option<&'a ConfigValue> {
+    segments.iter().try_fold(value, |value, segment| match (segment, value) {
+        (PathSegment::Key(key), ConfigValue::Mapping(map)) => map.get(key.as_str()),
+        (PathSegment::Index(index), ConfigValue::Sequence(items)) => items.get(*index),
+        _ => None,
+    })
+}
+
+/// Looks up the value at a dotted key path such as `service.ports[0]`.
+pub fn get_path<'a>(value: &'a ConfigValue, path: &str) -> Result<Option<&'a ConfigValue>, ConfigError> {
+    Ok(get_segments(value, &parse_key_path(path)?))
+}
+
+/// Sets the value at `segments`, replacing whatever was there.
+///
+/// Missing mappings along the way are created, and a key segment replaces a
+/// scalar in its way with a mapping. An index must address an existing item
+/// or the end of its sequence, which appends.
+pub fn set_segments(value: &mut ConfigValue, segments: &[PathSegment], new_value: ConfigValue) -> Result<(), ConfigError> {
+    let Some((segment, rest)) = segments.split_first() else {
+        *value = new_value;
+        return Ok(());
+    };
+
+    let child = match segment {
+        PathSegment::Key(key) => {
+            if !value.is_mapping() {
+                *value = ConfigValue::Mapping(serde_yaml::Mapping::new());
+            }
+            let ConfigValue::Mapping(map) = value else {
+                unreachable!()
+            };
+            map.entry(ConfigValue::String(key.clone())).or_insert(ConfigValue::Null)
+        }
+        PathSegment::Index(index) => {
+            let ConfigValue::Sequence(items) = value else {
+                return Err(anyhow!("Cannot index into a non-sequence value with [{index}]").into());
+            };
+            if *index == items.len() {
+                items.push(ConfigValue::Null);
+            }
+            let len = items.len();
+            items
+                .get_mut(*index)
+                .ok_or_else(|| anyhow!("Index [{index}] is out of range for a sequence of {len} items"))?
+        }
+    };
+    set_segments(child, rest, new_value)
+}
+
+/// Sets the value at a dotted key path; see [`set_segments`].
+pub fn set_path(value: &mut ConfigValue, path: &str, new_value: ConfigValue) -> Result<(), ConfigError> {
+    Ok(set_segments(value, &parse_key_path(path)?, new_value).with_context(|| format!("Cannot set '{path}'"))?)
+}
+
+/// Removes and returns the value at `segments`, if any. Sequence items
+/// after a removed one move up.
+pub fn remove_segments(value: &mut ConfigValue, segments: &[PathSegment]) -> Option<ConfigValue> {
+    let (last, parents) = segments.split_last()?;
+    let parent = parents.iter().try_fold(value, |value, segment| match (segment, value) {
+        (PathSegment::Key(key), ConfigValue::Mapping(map)) => map.get_mut(key.as_str()),
+        (PathSegment::Index(index), ConfigValue::Sequence(items)) => items.get_mut(*index),
+        _ => None,
+    })?;
+    match (last, parent) {
+        (PathSegment::Key(key), ConfigValue::Mapping(map)) => map.shift_remove(key.as_str()),
+        (PathSegment::Index(index), ConfigValue::Sequence(items)) if *index < items.len() => {
+            Some(items.remove(*index))
+        }
+        _ => None,
+    }
+}
+
+/// String form of a mapping key as used in key paths. Scalar keys use their
+/// YAML spelling; complex keys are rendered as inline YAML.
+pub fn key_to_string(key: &ConfigValue) -> String {
+    match key {
+        ConfigValue::String(s) => s.clone(),
+        ConfigValue::Number(n) => n.to_string(),
+        ConfigValue::Bool(b) => b.to_string(),
+        ConfigValue::Null => "null".to_string(),
+        other => serde_yaml::to_string(other)
+            .map(|s| s.trim_end().to_string())
+            .unwrap_or_default(),
+    }
+}
+
+/// Every leaf of `value` with its dotted key path, as [`format_key_path`]
+/// renders it, depth first in document order. Leaves are the values other
+/// than non-empty mappings and sequences; a leaf root has the empty path.
+///
+/// ```
+/// # use hierarchical_config_merging::{leaf_paths, ConfigValue};
+/// let config: ConfigValue = serde_yaml::from_str("a.b: 1\nhosts: [{name: x}]\nempty: {}").unwrap();
+/// let paths: Vec<String> = leaf_paths(&config).map(|(path, _)| path).collect();
+/// assert_eq!(paths, ["a\\.b", "hosts[0].name", "empty"]);
+/// ```
+pub fn leaf_paths(value: &ConfigValue) -> impl Iterator<Item = (String, &ConfigValue)> {
+    LeafPaths {
+        root: Some(value),
+        frames: Vec::new(),
+        path: Vec::new(),
+    }
+}
+
+/// Calls `visit` with the segments and value of every leaf of `value`, in
+/// the order of [`leaf_paths`] but without formatting a path per leaf.
+pub fn visit_leaves<'a>(value: &'a ConfigValue, visit: &mut impl FnMut(&[PathSegment], &'a ConfigValue)) {
+    visit_below(value, &mut Vec::new(), visit);
+}
+
+fn visit_below<'a>(
+    value: &'a ConfigValue,
+    path: &mut Vec<PathSegment>,
+    visit: &mut impl FnMut(&[PathSegment], &'a ConfigValue),
+) {
+    match Children::of(value) {
+        Some(mut children) => {
+            while let Some((segment, child)) = children.next() {
+                path.push(segment);
+                visit_below(child, path, visit);
+                path.pop();
+            }
+        }
+        None => visit(path, value),
+    }
+}
+
+/// The items of a non-empty mapping or sequence, with their segments.
+enum Children<'a> {
+    Mapping(serde_yaml::mapping::Iter<'a>),
+    Sequence(std::iter::Enumerate<std::slice::Iter<'a, ConfigValue>>),
+}
+
+impl<'a> Children<'a> {
+    fn of(value: &'a ConfigValue) -> Option<Self> {
+        match value {
+            ConfigValue::Mapping(map) if !map.is_empty() => Some(Children::Mapping(map.iter())),
+            ConfigValue::Sequence(items) if !items.is_empty() => Some(Children::Sequence(items.iter().enumerate())),
+            _ => None,
+        }
+    }
+
+    fn next(&mut self) -> Option<(PathSegment, &'a ConfigValue)> {
+        match self {
+            Children::Mapping(items) => items.next().map(|(key, child)| (PathSegment::Key(key_to_string(key)), child)),
+            Children::Sequence(items) => items.next().map(|(index, child)| (PathSegment::Index(index), child)),
+        }
+    }
+}
+
+/// Depth-first walk of the leaves of a value, see [`leaf_paths`].
+struct LeafPaths<'a> {
+    /// The value walked, until the first call.
+    root: Option<&'a ConfigValue>,
+    /// Children left to visit at each level of `path`, the innermost last.
+    frames: Vec<Children<'a>>,
+    path: Vec<PathSegment>,
+}
+
+impl<'a> Iterator for LeafPaths<'a> {
+    type Item = (String, &'a ConfigValue);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(root) = self.root.take() {
+            match Children::of(root) {
+                Some(children) => self.frames.push(children),
+                None => return Some((String::new(), root)),
+            }
+        }
+        loop {
+            let Some((segment, child)) = self.frames.last_mut()?.next() else {
+                self.frames.pop();
+                self.path.pop();
+                continue;
+            };
+            self.path.push(segment);
+            match Children::of(child) {
+                Some(children) => self.frames.push(children),
+                None => {
+                    let path = format_key_path(&self.path);
+                    self.path.pop();
+                    return Some((path, child));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_key_path() {
+        assert_eq!(
+            parse_key_path("service.ports[1][0].name").unwrap(),
+            vec![
+                PathSegment::Key("service".to_string()),
+                PathSegment::Key("ports".to_string()),
+                PathSegment::Index(1),
+                PathSegment::Index(0),
+                PathSegment::Key("name".to_string()),
+            ]
+        );
+        assert_eq!(parse_key_path("[2]").unwrap(), vec![PathSegment::Index(2)]);
+        assert!(parse_key_path("").unwrap().is_empty());
+        assert!(parse_key_path("a..b").is_err());
+        assert!(parse_key_path("a[x]").is_err());
+        assert!(parse_key_path("a[1").is_err());
+    }
+
+    #[test]
+    fn test_get_path_and_format_round_trip() {
+        let config: ConfigValue =
+            serde_yaml::from_str("service:\n  ports:\n    - name: http\n      port: 80\n").unwrap();
+
+        assert_eq!(
+            get_path(&config, "service.ports[0].port").unwrap(),
+            Some(&ConfigValue::from(80))
+        );
+        assert_eq!(get_path(&config, "service.ports[3]").unwrap(), None);
+        assert_eq!(get_path(&config, "service.ports.name").unwrap(), None);
+
+        let segments = parse_key_path("service.ports[0].port").unwrap();
+        assert_eq!(format_key_path(&segments), "service.ports[0].port");
+    }
+
+    #[test]
+    fn test_leaf_paths_escape_keys_with_dots() {
+        let config: ConfigValue =
+            serde_yaml::from_str("\"example.com\": {port: 80}\n\"a[0]\": x\n\"back\\\\slash\": y\n").unwrap();
+        let paths: Vec<String> = leaf_paths(&config).map(|(path, _)| path).collect();
+        assert_eq!(paths, ["example\\.com.port", "a\\[0]", "back\\\\slash"]);
+
+        // Every path reads back to its leaf.
+        for (path, value) in leaf_paths(&config) {
+            assert_eq!(get_path(&config, &path).unwrap(), Some(value), "{path}");
+        }
+        assert_eq!(
+            parse_key_path("example\\.com.port").unwrap(),
+            [PathSegment::Key("example.com".to_string()), PathSegment::Key("port".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_leaf_paths_of_empty_mappings_and_sequences_of_mappings() {
+        let config: ConfigValue = serde_yaml::from_str(
+            "servers:\n  - {name: a, tags: []}\n  - {name: b, env: {}}\nempty: {}\nnested: {inner: {}}\n",
+        )
+        .unwrap();
+        let leaves: Vec<(String, &ConfigValue)> = leaf_paths(&config).collect();
+        let paths: Vec<&str> = leaves.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(
+            paths,
+            ["servers[0].name", "servers[0].tags", "servers[1].name", "servers[1].env", "empty", "nested.inner"]
+        );
+        assert_eq!(leaves[4].1, &ConfigValue::Mapping(Default::default()));
+
+        let empty = ConfigValue::Mapping(Default::default());
+        assert_eq!(leaf_paths(&empty).collect::<Vec<_>>(), [(String::new(), &empty)]);
+
+        // The visitor sees the same leaves, as segments.
+        let mut visited = Vec::new();
+        visit_leaves(&config, &mut |segments, value| visited.push((format_key_path(segments), value)));
+        assert_eq!(visited, leaves);
+    }
+
+    #[test]
+    fn test_set_path_creates_and_replaces() {
+        let mut config: ConfigValue = serde_yaml::from_str("a: 1
+list: [x]
+").unwrap();
+
+        set_path(&mut config, "a.b.c", ConfigValue::from(2)).unwrap();
+        set_path(&mut config, "list[0]", ConfigValue::from("y")).unwrap();
+        set_path(&mut config, "list[1]", ConfigValue::from("z")).unwrap();
+
+        let expected: ConfigValue = serde_yaml::from_str("a:\n  b:\n    c: 2\nlist: [y, z]\n").unwrap();
+        assert_eq!(config, expected);
+        assert!(set_path(&mut config, "list[5]", ConfigValue::Null).is_err());
+        assert!(set_path(&mut config, "a[0]", ConfigValue::Null).is_err());
+    }
+}
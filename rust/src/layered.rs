@@ -0,0 +1,256 @@
+//! Merging of configs the caller has already parsed and ordered, see
+//! [`merge_layered`].
+
+use std::borrow::Cow;
+use std::path::Path;
+
+use crate::cancel::{arm, check_cancelled};
+use crate::deprecation::has_deprecated_tags;
+use crate::error::ConfigError;
+use crate::limits::measure;
+use crate::metadata::embed_metadata;
+use crate::options::{CollisionPolicy, MergeMode, MergeOptions};
+use crate::priority::{file_priority, PRIORITY_KEY};
+use crate::report::MergeReport;
+use crate::{collect_depth_collisions, deep_merge_into, merge_layers_with_report, trace, ConfigValue};
+
+/// A parsed config and its place in a [`merge_layered`] merge.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layer {
+    /// Stands for the layer where a merge of files would name a file: in
+    /// report entries and in provenance.
+    pub name: String,
+    /// Layers merge in ascending priority.
+    pub priority: i64,
+    pub value: ConfigValue,
+}
+
+impl Layer {
+    pub fn new(name: impl Into<String>, priority: i64, value: ConfigValue) -> Self {
+        Self {
+            name: name.into(),
+            priority,
+            value,
+        }
+    }
+}
+
+/// Merges `layers` in ascending priority, without touching the filesystem.
+/// Layers of equal priority are merged by name and checked for collisions
+/// like the files of one directory, `__priority__` key included; the
+/// collision messages give the priority as depth. Everything else, from
+/// the options to the report, is as in [`crate::merge_hierarchy`].
+///
+/// ```
+/// # use hierarchical_config_merging::{merge_layered, ConfigValue, Layer, MergeOptions};
+/// let yaml = |text| serde_yaml::from_str::<ConfigValue>(text).unwrap();
+/// let layers = vec![
+///     Layer::new("overrides", 10, yaml("port: 9090")),
+///     Layer::new("defaults", -1, yaml("port: 80\nhost: localhost")),
+/// ];
+/// let (config, _) = merge_layered(layers, &MergeOptions::default()).unwrap();
+/// assert_eq!(config, yaml("port: 9090\nhost: localhost"));
+/// ```
+pub fn merge_layered(layers: Vec<Layer>, options: &MergeOptions) -> Result<(ConfigValue, MergeReport), ConfigError> {
+    let options = &arm(options);
+    let _span = trace::merge_span(layers.len());
+    if !merges_plainly(options) || layers.iter().any(|layer| has_deprecated_tags(&layer.value)) {
+        let configs = layers
+            .iter()
+            .map(|layer| ((layer.priority, 0), Path::new(&layer.name), &layer.value));
+        let (merged_config, report) = merge_layers_with_report(configs, options)?;
+        options.check_report(&report)?;
+        let merged_config = embed_metadata(merged_config, None, None, &report, options);
+        return Ok((merged_config, report));
+    }
+
+    // Nothing but collisions to report: the values move into the merged
+    // config instead of being copied.
+    let mut layers: Vec<_> = layers
+        .into_iter()
+        .map(|layer| (file_priority(&layer.value), layer))
+        .collect();
+    layers.sort_by(|(a_priority, a), (b_priority, b)| {
+        (a.priority, a_priority, Path::new(&a.name)).cmp(&(b.priority, b_priority, Path::new(&b.name)))
+    });
+
+    let mut merged_config = options.initial_config();
+    let mut report = MergeReport::default();
+    for group in layers.chunk_by_mut(|(_, a), (_, b)| a.priority == b.priority) {
+        check_cancelled(options)?;
+        let priority = group[0].1.priority;
+        if options.collision_policy != CollisionPolicy::Ignore {
+            let configs: Vec<_> = group.iter().map(|(_, layer)| (Path::new(&layer.name), &layer.value)).collect();
+            collect_depth_collisions(priority, &configs, options, &mut report.entries);
+        }
+        for (_, layer) in group.iter_mut() {
+            let mut value = std::mem::take(&mut layer.value);
+            if let ConfigValue::Mapping(map) = &mut value {
+                map.remove(PRIORITY_KEY);
+            }
+            deep_merge_into(&mut merged_config, Cow::Owned(value), options);
+        }
+        trace::merged_layer(priority, group.len());
+    }
+    report.stats = measure(&merged_config);
+    options.check_report(&report)?;
+    Ok((merged_config, report))
+}
+
+/// Whether `options` leave a merge nothing to do besides merging deeply and
+/// reporting collisions.
+///
+/// Every option is named, so that a new one does not build until it is
+/// classified here.
+fn merges_plainly(options: &MergeOptions) -> bool {
+    let MergeOptions {
+        // Handled by the plain merge as well.
+        fail_on_empty: _,
+        strict: _,
+        sequence_strategy: _,
+        collision_policy: _,
+        null_deletes: _,
+        redaction: _,
+        coercion_failure: _,
+        index_out_of_range: _,
+        defaults: _,
+        max_merge_depth: _,
+        lock_exempt_sections: _,
+        collision_exempt_paths: _,
+        report_exempt_collisions: _,
+        metadata_key: _,
+        cancel: _,
+        timeout: _,
+        // Only read by merges of files.
+        extra_roots: _,
+        expand_paths: _,
+        extensions: _,
+        filename: _,
+        profiles: _,
+        selectors: _,
+        features: _,
+        compose_defaults: _,
+        skip_unreadable: _,
+        #[cfg(feature = "http")]
+        remote_layers: _,
+        #[cfg(feature = "schema")]
+        directory_schemas: _,
+        type_check: _,
+        target_mode: _,
+        // Left to the general merge.
+        mode,
+        aliases,
+        deprecated_paths,
+        coerce_types,
+        index_patches,
+        report_duplicates,
+        report_shadowed_files,
+        lock_top_level,
+        embed_metadata,
+        interpolate,
+        units,
+        prune_paths,
+        normalize,
+        key_case,
+        max_merged_nodes,
+        max_merged_bytes_estimate,
+    } = options;
+    *mode == MergeMode::Deep
+        && aliases.is_empty()
+        && deprecated_paths.is_empty()
+        && !coerce_types
+        && !index_patches
+        && !report_duplicates
+        && !report_shadowed_files
+        && !lock_top_level
+        && !embed_metadata
+        && !interpolate
+        && units.is_empty()
+        && prune_paths.is_empty()
+        && normalize.is_none()
+        && key_case.is_none()
+        && max_merged_nodes.is_none()
+        && max_merged_bytes_estimate.is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::ReportKind;
+
+    fn yaml(text: &str) -> ConfigValue {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    /// Both paths of [`merge_layered`]: moving the values, and the general
+    /// one, taken for any option beyond plain merging.
+    fn both_paths(layers: &[Layer]) -> Vec<(ConfigValue, MergeReport)> {
+        let traced = MergeOptions {
+            report_duplicates: true,
+            ..MergeOptions::default()
+        };
+        [MergeOptions::default(), traced]
+            .iter()
+            .map(|options| merge_layered(layers.to_vec(), options).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_negative_priorities_merge_first() {
+        let layers = [
+            Layer::new("app", 0, yaml("port: 8080\nname: app\n")),
+            Layer::new("site", -5, yaml("port: 80\nname: site\ntls: true\n")),
+            Layer::new("env", 3, yaml("name: env\n")),
+            Layer::new("builtin", -10, yaml("port: 1\ntls: false\nlog: info\n")),
+        ];
+        for (config, report) in both_paths(&layers) {
+            assert_eq!(config, yaml("port: 8080\nname: env\ntls: true\nlog: info\n"));
+            assert!(report.entries.is_empty(), "{:?}", report.entries);
+            assert_eq!(report.stats.nodes, 5);
+        }
+    }
+
+    #[test]
+    fn test_ties_merge_by_name_and_collide() {
+        let layers = [
+            Layer::new("b", 1, yaml("port: 2\nhost: b\n")),
+            Layer::new("a", 1, yaml("port: 1\n")),
+            Layer::new("c", 2, yaml("host: c\n")),
+        ];
+        for (config, report) in both_paths(&layers) {
+            assert_eq!(config, yaml("port: 2\nhost: c\n"));
+            let collisions: Vec<_> = report
+                .entries
+                .iter()
+                .filter(|entry| entry.kind == ReportKind::Collision)
+                .map(|entry| (entry.key_path.as_deref(), entry.files.clone(), entry.message.as_str()))
+                .collect();
+            assert_eq!(
+                collisions,
+                [(Some("port"), vec!["a".into(), "b".into()], "Key collision at depth 1: 'port' found in both a and b")]
+            );
+        }
+
+        // A value's own priority orders it within its tie.
+        let layers = [
+            Layer::new("a", 0, yaml("__priority__: 1\nport: 1\n")),
+            Layer::new("b", 0, yaml("port: 2\n")),
+        ];
+        for (config, report) in both_paths(&layers) {
+            assert_eq!(config, yaml("port: 1\n"));
+            assert!(report.entries.iter().all(|entry| entry.kind != ReportKind::Collision));
+        }
+    }
+
+    #[test]
+    fn test_names_feed_provenance() {
+        let layers = vec![
+            Layer::new("defaults", 0, yaml("server: {port: 80, host: a}\n")),
+            Layer::new("cli", 1, yaml("server: {port: 9090}\n")),
+        ];
+        let (config, _, provenance) = crate::merge_layered_with_provenance(layers, &MergeOptions::default()).unwrap();
+        assert_eq!(config, yaml("server: {port: 9090, host: a}\n"));
+        assert_eq!(provenance.source("server.port").unwrap(), Some(Path::new("cli")));
+        assert_eq!(provenance.source("server.host").unwrap(), Some(Path::new("defaults")));
+    }
+}
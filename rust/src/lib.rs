@@ -1,196 +1,1161 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::path::{Path, PathBuf};
 use std::fs;
-use anyhow::{Context, Result};
-use serde_yaml;
+use std::path::{Component, Path, PathBuf};
+use anyhow::Result;
 
+pub mod builder;
+pub mod cache;
+pub mod cancel;
+pub mod casing;
+pub mod cli;
+mod coerce;
+mod compose;
+pub mod conditional;
+pub mod config;
+#[cfg(feature = "config-rs")]
+pub mod config_source;
+mod deprecation;
+pub mod diff;
+pub mod docs;
+pub mod error;
+pub mod feature;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "figment")]
+pub mod figment_provider;
+pub mod frozen;
+pub mod hash;
+pub mod index_patch;
+pub mod interpolate;
+pub mod intern;
+pub mod keypath;
+pub mod layered;
+mod limits;
+pub mod lockfile;
+pub mod memory;
+pub mod merge_patch;
+pub mod mergeable;
+pub mod merger;
+pub mod metadata;
+pub mod normalize;
+pub mod options;
+pub mod output;
+pub mod overlay;
+pub mod paths;
+pub mod priority;
+pub mod provenance;
+pub mod prune;
+#[cfg(all(feature = "python", not(target_arch = "wasm32")))]
 pub mod python_bindings;
+pub mod redact;
+#[cfg(feature = "http")]
+pub mod remote;
+pub mod report;
+pub mod resolve;
+pub mod review;
+#[cfg(feature = "schema")]
+pub mod schema;
+mod shadow;
+pub mod source;
+pub mod strategic;
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing;
+pub mod tree;
+pub mod types;
+pub mod units;
+mod trace;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "watch")]
+pub mod watch;
+
+pub use builder::{ConfigBuilder, LayerSource};
+pub use cache::{CacheStats, ParseCache};
+pub use cancel::CancelToken;
+pub use casing::{convert_key_case, KeyCase};
+pub use conditional::WHEN_KEY;
+pub use config::{merge_hierarchy_config, Config};
+#[cfg(feature = "config-rs")]
+pub use config_source::HierarchySource;
+pub use diff::{compute_override, diff, diff_as_json_patch, semantic_diff, semantic_eq, semantic_eq_with, Change, DiffEntry, Equivalence, PatchOp};
+pub use docs::generate_docs;
+pub use error::ConfigError;
+pub use feature::IF_FEATURE_KEY;
+#[cfg(feature = "figment")]
+pub use figment_provider::HierarchicalConfig;
+pub use frozen::FrozenConfig;
+pub use hash::config_hash;
+pub use index_patch::PATCH_INDEX_TAG;
+pub use interpolate::interpolate;
+pub use intern::merge_interned;
+pub use layered::{merge_layered, Layer};
+pub use keypath::{get_path, leaf_paths, parse_key_path, set_path, visit_leaves, PathSegment};
+pub use lockfile::{render_lockfile, write_lockfile};
+pub use memory::merge_yaml_strings;
+pub use merge_patch::apply_merge_patch;
+pub use mergeable::{merge_values, merge_values_resolving, Mergeable, ValueKind, ValueResolver};
+pub use merger::{merge_many, HierarchyMerger};
+pub use metadata::DEFAULT_METADATA_KEY;
+pub use normalize::{normalize, NormalizeRules};
+pub use options::{
+    CoercionFailure, CollisionPolicy, IndexOutOfRange, MergeMode, MergeOptions, SequenceStrategy, TargetMode, TypeCheck,
+    Unit, UnknownOptionValue,
+};
+pub use output::{to_env_exports, to_json, to_properties_string, to_properties_string_with, to_yaml, EnvOptions, PropertiesOptions};
+pub use overlay::merge_with_overlay;
+pub use paths::{expand_path, resolve_target_within_base, standard_layers, RelativeTarget};
+pub use priority::PRIORITY_KEY;
+pub use provenance::{merge_hierarchy_with_provenance, merge_layered_with_provenance, Provenance};
+pub use prune::prune;
+pub use redact::{redact, Redaction, DEFAULT_REDACT_PATTERNS, REDACTED};
+#[cfg(feature = "http")]
+pub use remote::RemoteLayer;
+pub use report::{ContributingFile, MergeReport, ReportEntry, ReportKind, ReportSummary, MergeStats, Severity};
+pub use resolve::{deep_merge_resolving, merge_hierarchy_resolving, ConflictResolver};
+pub use review::{render_html_report, MergeOutcome};
+pub use source::{parse_yaml_file, ConfigSource, Fingerprint, FsSource, ParsedFile};
+pub use tree::{render_tree, DisplayTree, TreeOptions};
+pub use types::{ExpectedType, TypeMismatch, TypeRules};
+pub use units::{parse_byte_size, parse_duration};
+#[cfg(feature = "watch")]
+pub use watch::{ChangeEvent, ConfigHandle, PathChange, PathSubscription, Watcher};
+
+use cancel::{arm, check_cancelled};
+use casing::case_merged_keys;
+use coerce::coerce_override;
+use deprecation::{apply_layer_deprecations, has_deprecated_tags, strip_deprecated_tags};
+use index_patch::{check_index_patches, index_patches};
+use interpolate::interpolate_merged;
+use limits::{measure, SizeGuard};
+use metadata::{check_reserved_key, embed_metadata};
+use normalize::normalize_merged;
+use priority::{sort_by_priority, strip_layer_priorities};
+use prune::prune_merged;
+use shadow::collect_shadowed_files;
+use units::normalize_units;
+use compose::load_config_file;
 
 /// Type alias for ConfigValue - we use serde_yaml::Value directly
 pub type ConfigValue = serde_yaml::Value;
 
-pub fn find_yaml_files_in_hierarchy(base_dir: &Path, target_path: &Path) -> Result<Vec<PathBuf>> {
-    let base_dir = base_dir.canonicalize()?;
-    let target_path = target_path.canonicalize()?;
+pub fn find_yaml_files_in_hierarchy(base_dir: &Path, target_path: &Path) -> Result<Vec<PathBuf>, ConfigError> {
+    let (base_dir, target_path) = canonicalize_hierarchy(base_dir, target_path)?;
+    let yaml_files = discover_yaml_files(&base_dir, &MergeOptions::default(), &mut Vec::new())?;
+    Ok(select_hierarchy_files(&base_dir, &target_path, &yaml_files))
+}
+
+/// Canonicalizes both paths and ensures the target lies within the base:
+/// lexically first, see [`paths::resolve_target_within_base`], whether the
+/// target exists or not, then once links are resolved.
+pub(crate) fn canonicalize_hierarchy(base_dir: &Path, target_path: &Path) -> Result<(PathBuf, PathBuf)> {
+    let canonical_base = base_dir
+        .canonicalize()
+        .map_err(ConfigError::io("Failed to resolve path", base_dir))?;
+    // Relative paths are relative to the working directory here, not the
+    // target to the base. A target may be spelled out from the base as
+    // given or from its canonical form, which a link to the base differs
+    // from.
+    let absolute = |path: &Path| std::path::absolute(path).map_err(ConfigError::io("Failed to resolve path", path));
+    let target = absolute(target_path)?;
+    if paths::resolve_target_within_base(&absolute(base_dir)?, &target).is_err() {
+        paths::resolve_target_within_base(&canonical_base, &target)?;
+    }
+    let base_dir = canonical_base;
+    let target_path = target_path
+        .canonicalize()
+        .map_err(ConfigError::io("Failed to resolve path", target_path))?;
 
     // Ensure target_path is within base_dir
-    if !target_path.starts_with(&base_dir) {
-        return Err(anyhow::anyhow!(
-            "Target path {} is not within base directory {}",
-            target_path.display(),
-            base_dir.display()
-        ));
+    if !paths::is_within(&target_path, &base_dir) {
+        return Err(ConfigError::OutsideBase {
+            base: base_dir,
+            target: target_path,
+        }
+        .into());
     }
 
-    let mut yaml_files = Vec::new();
+    Ok((base_dir, target_path))
+}
 
-    // Get relative path from base to target
-    let target_relative = target_path.strip_prefix(&base_dir)?;
-    let target_parts: Vec<&str> = target_relative
-        .components()
-        .filter_map(|c| c.as_os_str().to_str())
-        .collect();
+/// Lists every config file below the (canonical) `base_dir`, in walk order.
+/// With [`MergeOptions::skip_unreadable`], what cannot be walked is left out
+/// with an entry in `unreadable`.
+pub(crate) fn discover_yaml_files(
+    base_dir: &Path,
+    options: &MergeOptions,
+    unreadable: &mut Vec<ReportEntry>,
+) -> Result<Vec<PathBuf>> {
+    let mut yaml_files = Vec::new();
 
     // Walk through the directory tree
-    for entry in walkdir::WalkDir::new(&base_dir)
+    for entry in walkdir::WalkDir::new(base_dir)
         .follow_links(true)
+        .sort_by_file_name()
     {
-        let entry = entry?;
+        check_cancelled(options)?;
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) if options.skip_unreadable && e.io_error().is_some() => {
+                let error = e.io_error().map(ToString::to_string).unwrap_or_default();
+                unreadable.push(unreadable_entry(e.path().unwrap_or(base_dir), &error));
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
         let path = entry.path();
 
-        if !path.is_file() {
+        // Links are followed, so this is the type of their target: a
+        // directory named like `templates.yaml` is walked, never merged.
+        if !entry.file_type().is_file() {
             continue;
         }
 
         // Check if it's a YAML file
-        if let Some(ext) = path.extension() {
-            if ext == "yaml" || ext == "yml" {
-                let root_relative = path.strip_prefix(&base_dir)?;
-                let root_parts: Vec<&str> = root_relative
-                    .components()
-                    .filter_map(|c| c.as_os_str().to_str())
-                    .collect();
-
-                // Remove the filename from parts
-                let root_dir_parts = if root_parts.len() > 0 {
-                    &root_parts[..root_parts.len() - 1]
-                } else {
-                    &[]
-                };
-
-                // Check if this directory is included in target hierarchy
-                if root_dir_parts.is_empty() || 
-                   (root_dir_parts.len() <= target_parts.len() && 
-                    root_dir_parts == &target_parts[..root_dir_parts.len()])
-                {
-                    yaml_files.push(path.to_path_buf());
-                }
-            }
+        if options.is_config_file(path) {
+            yaml_files.push(path.to_path_buf());
         }
     }
 
     Ok(yaml_files)
 }
 
-pub fn parse_yaml_configs(yaml_files: &[PathBuf]) -> Result<HashMap<String, ConfigValue>> {
-    let mut configs = HashMap::new();
+/// Keeps the discovered files whose directory is the base, the target, or a
+/// directory in between.
+pub(crate) fn select_hierarchy_files(
+    base_dir: &Path,
+    target_path: &Path,
+    yaml_files: &[PathBuf],
+) -> Vec<PathBuf> {
+    // Get relative path from base to target
+    let target_parts: Vec<Component> = paths::relative_to(target_path, base_dir)
+        .unwrap_or(Path::new(""))
+        .components()
+        .collect();
+
+    yaml_files
+        .iter()
+        .filter(|path| {
+            // The directory of the file, relative to the base
+            let root_dir_parts: Vec<Component> = paths::relative_to(path, base_dir)
+                .unwrap_or(path)
+                .parent()
+                .map(|dir| dir.components().collect())
+                .unwrap_or_default();
 
-    for yaml_file in yaml_files {
-        let content = fs::read_to_string(yaml_file)
-            .with_context(|| format!("Failed to read file: {}", yaml_file.display()))?;
+            // Check if this directory is included in target hierarchy
+            root_dir_parts.len() <= target_parts.len()
+                && root_dir_parts
+                    .iter()
+                    .zip(&target_parts)
+                    .all(|(part, target_part)| paths::same_component(*part, *target_part))
+        })
+        .cloned()
+        .collect()
+}
+
+/// The files among `discovered` merged for `target_path`: those selected by
+/// [`select_hierarchy_files`] and, with [`TargetMode::AllDescendants`], the
+/// ones in subdirectories of the target.
+pub(crate) fn select_target_files(
+    base_dir: &Path,
+    target_path: &Path,
+    discovered: &[PathBuf],
+    options: &MergeOptions,
+) -> Vec<PathBuf> {
+    let mut selected = select_hierarchy_files(base_dir, target_path, discovered);
+    if options.target_mode == TargetMode::AllDescendants {
+        selected.extend(discovered.iter().filter(|path| in_subdirectory(path, target_path)).cloned());
+    }
+    selected
+}
 
-        let config_value: ConfigValue = serde_yaml::from_str(&content)
-            .with_context(|| format!("Failed to parse YAML: {}", yaml_file.display()))?;
+/// Whether `path` is a file in a subdirectory of `dir`, at any depth.
+fn in_subdirectory(path: &Path, dir: &Path) -> bool {
+    path.parent()
+        .and_then(|parent| paths::relative_to(parent, dir))
+        .is_some_and(|relative| !relative.as_os_str().is_empty())
+}
 
-        configs.insert(yaml_file.to_string_lossy().to_string(), config_value);
+/// With [`TargetMode::Exact`] and the base as target, an entry counting the
+/// files among `discovered` left out for being in subdirectories.
+pub(crate) fn ignored_descendants(
+    base_dir: &Path,
+    target_path: &Path,
+    discovered: &[PathBuf],
+    options: &MergeOptions,
+) -> Option<ReportEntry> {
+    let is_base = paths::relative_to(target_path, base_dir).is_some_and(|relative| relative.as_os_str().is_empty());
+    if options.target_mode != TargetMode::Exact || !is_base {
+        return None;
     }
+    let ignored = discovered.iter().filter(|path| in_subdirectory(path, base_dir)).count();
+    (ignored > 0).then(|| ReportEntry {
+        kind: ReportKind::IgnoredDescendants,
+        key_path: None,
+        files: Vec::new(),
+        message: format!(
+            "The target is the base directory: {ignored} file(s) in its subdirectories were not merged, \
+             see TargetMode::AllDescendants"
+        ),
+    })
+}
+
+/// Parses every file of `yaml_files`, keyed by its path.
+pub fn parse_config_files(yaml_files: &[PathBuf]) -> Result<HashMap<PathBuf, ConfigValue>, ConfigError> {
+    let files = read_config_files(&FsSource, yaml_files, false)?;
+    Ok(files.into_iter().map(|file| (file.path, file.value)).collect())
+}
 
-    Ok(configs)
+/// Reads every file of `yaml_files` once through `source`, in order, with
+/// its SHA-256 when `hash` is set, see [`ParsedFile::read`].
+pub fn read_config_files(
+    source: &dyn ConfigSource,
+    yaml_files: &[PathBuf],
+    hash: bool,
+) -> Result<Vec<ParsedFile>, ConfigError> {
+    yaml_files.iter().map(|path| ParsedFile::read(source, path, hash)).collect()
+}
+
+/// [`parse_config_files`] keyed by the lossy string form of each path, which
+/// mangles paths that are not valid UTF-8 and can make two of them collide.
+#[deprecated(since = "0.2.0", note = "use `parse_config_files`, which keys configs by `PathBuf`")]
+pub fn parse_yaml_configs(yaml_files: &[PathBuf]) -> Result<HashMap<String, ConfigValue>, ConfigError> {
+    let configs = parse_config_files(yaml_files)?;
+    Ok(configs
+        .into_iter()
+        .map(|(path, config)| (path.to_string_lossy().into_owned(), config))
+        .collect())
 }
 
 pub fn deep_merge(base: &ConfigValue, r#override: &ConfigValue) -> ConfigValue {
-    match (base, r#override) {
-        (ConfigValue::Mapping(base_map), ConfigValue::Mapping(override_map)) => {
-            let mut result = base_map.clone();
-            for (key, value) in override_map {
-                if let Some(base_value) = result.get(key) {
-                    // Recursively merge if both are mappings
-                    result.insert(key.clone(), deep_merge(base_value, value));
-                } else {
-                    // Insert new value
-                    result.insert(key.clone(), value.clone());
+    deep_merge_with(base, r#override, &MergeOptions::default())
+}
+
+/// [`deep_merge`] honouring the `mode`, `sequence_strategy`,
+/// `null_deletes` and `index_patches` options. Index patches of no item are
+/// left out without a word.
+pub fn deep_merge_with(base: &ConfigValue, r#override: &ConfigValue, options: &MergeOptions) -> ConfigValue {
+    if let MergeMode::StrategicMergePatch { default_key } = &options.mode {
+        return strategic::merge_patch(base, r#override, default_key, options, None).0;
+    }
+    if options.index_patches && options.mode == MergeMode::Deep {
+        let mut merged = base.clone();
+        deep_merge_into(&mut merged, Cow::Borrowed(r#override), options);
+        return merged;
+    }
+    mergeable::merge_values(base, r#override, options)
+}
+
+/// [`deep_merge_with`] into `base`, in place. Only the values of `r#override`
+/// that are new to `base` are copied, or moved when it is owned, rather than
+/// `base` being rebuilt.
+pub(crate) fn deep_merge_into(base: &mut ConfigValue, r#override: Cow<'_, ConfigValue>, options: &MergeOptions) {
+    if options.mode != MergeMode::Deep {
+        *base = deep_merge_with(base, &r#override, options);
+        return;
+    }
+    if options.index_patches
+        && let Some(patches) = index_patches(&r#override)
+    {
+        // Only the items patched change; a value that is no sequence stays.
+        if let ConfigValue::Sequence(items) = base {
+            for (index, patch) in patches {
+                if let Some(item) = items.get_mut(index) {
+                    deep_merge_into(item, Cow::Borrowed(patch), options);
                 }
             }
-            ConfigValue::Mapping(result)
         }
-        _ => r#override.clone(), // Override with new value
+        return;
+    }
+    match (&mut *base, r#override) {
+        (ConfigValue::Mapping(base_map), Cow::Owned(ConfigValue::Mapping(map))) => {
+            for (key, value) in map {
+                merge_entry_into(base_map, Cow::Owned(key), Cow::Owned(value), options);
+            }
+        }
+        (ConfigValue::Mapping(base_map), Cow::Borrowed(ConfigValue::Mapping(map))) => {
+            for (key, value) in map {
+                merge_entry_into(base_map, Cow::Borrowed(key), Cow::Borrowed(value), options);
+            }
+        }
+        (ConfigValue::Sequence(items), r#override) if r#override.is_sequence() => {
+            let override_items = match r#override {
+                Cow::Owned(ConfigValue::Sequence(override_items)) => override_items,
+                r#override => r#override.as_sequence().cloned().unwrap_or_default(),
+            };
+            match options.sequence_strategy {
+                SequenceStrategy::Replace => *items = override_items,
+                SequenceStrategy::Append => items.extend(override_items),
+                SequenceStrategy::Prepend => {
+                    let below = std::mem::replace(items, override_items);
+                    items.extend(below);
+                }
+                SequenceStrategy::Union => {
+                    for item in override_items {
+                        if !items.contains(&item) {
+                            items.push(item);
+                        }
+                    }
+                }
+            }
+        }
+        (base, r#override) => *base = r#override.into_owned(),
     }
 }
 
-pub fn merge_configs_by_depth(
-    configs: &HashMap<String, ConfigValue>
-) -> Result<(ConfigValue, Vec<String>)> {
-    if configs.is_empty() {
-        return Ok((ConfigValue::Mapping(serde_yaml::Mapping::new()), Vec::new()));
+/// Merges the entry `key: value` of an override into `map`, see
+/// [`deep_merge_into`].
+fn merge_entry_into(
+    map: &mut serde_yaml::Mapping,
+    key: Cow<'_, ConfigValue>,
+    value: Cow<'_, ConfigValue>,
+    options: &MergeOptions,
+) {
+    if options.null_deletes && value.is_null() {
+        map.remove(key.as_ref());
+    } else if let Some(existing) = map.get_mut(key.as_ref()) {
+        deep_merge_into(existing, value, options);
+    } else if options.index_patches && index_patches(&value).is_some() {
+        // An index patch of nothing is left out.
+    } else if options.null_deletes && value.is_mapping() {
+        // Drop the nulls nested in the new value too
+        let mut fresh = ConfigValue::Mapping(serde_yaml::Mapping::new());
+        deep_merge_into(&mut fresh, value, options);
+        map.insert(key.into_owned(), fresh);
+    } else {
+        map.insert(key.into_owned(), value.into_owned());
     }
+}
 
-    // Group configs by depth (directory level)
-    let mut depth_groups: HashMap<usize, Vec<(&String, &ConfigValue)>> = HashMap::new();
+/// Directory depth of a config file, used to order hierarchy layers.
+pub(crate) fn config_depth(path: &Path) -> usize {
+    path.components().count()
+}
 
-    for (file_path, config) in configs {
-        let path = Path::new(file_path);
-        let depth = path.components().count();
-        depth_groups.entry(depth).or_default().push((file_path, config));
+/// A config file scheduled for merging and the depth of the layer it joins.
+///
+/// Hierarchy files use their path's component count as depth, like
+/// [`merge_configs_by_depth`], so collision messages quote the same numbers.
+/// Files from other sources are placed on that same scale. Profile files
+/// (see [`MergeOptions::profiles`]) form extra layers at their depth, one per
+/// `rank`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) struct LayerFile {
+    pub depth: i64,
+    pub rank: u32,
+    pub path: PathBuf,
+}
+
+/// Identifies a layer: a depth and a profile rank within it.
+pub(crate) type LayerKey = (i64, u32);
+
+impl LayerFile {
+    pub fn layer(&self) -> LayerKey {
+        (self.depth, self.rank)
     }
+}
 
-    let mut merged_config = ConfigValue::Mapping(serde_yaml::Mapping::new());
-    let mut errors = Vec::new();
+/// Depth on the [`LayerFile`] scale of the files directly in the (canonical)
+/// base directory.
+pub(crate) fn base_layer_depth(base_dir: &Path) -> i64 {
+    config_depth(base_dir) as i64 + 1
+}
 
-    // Process configs from shallowest to deepest
-    let mut depths: Vec<_> = depth_groups.keys().collect();
-    depths.sort();
+/// Walks every extra root of `options`, pairing its files with the root's
+/// depth offset.
+pub(crate) fn discover_extra_roots(
+    options: &MergeOptions,
+    unreadable: &mut Vec<ReportEntry>,
+) -> Result<Vec<(PathBuf, i32)>> {
+    let mut files = Vec::new();
+    for (root, offset) in &options.extra_roots {
+        let root = options.input_path(root)?;
+        let root = root
+            .canonicalize()
+            .map_err(ConfigError::io("Failed to resolve extra root", &root))?;
+        files.extend(discover_yaml_files(&root, options, unreadable)?.into_iter().map(|file| (file, *offset)));
+    }
+    Ok(files)
+}
 
-    for depth in depths {
-        let depth_configs = depth_groups.get(depth).unwrap();
+/// The entries of `unreadable`, found walking a base directory, for paths
+/// that would hold layers of the (canonical) `target_path`: the directories
+/// on the way to it and the config files in them, and with
+/// [`TargetMode::AllDescendants`] everything below it.
+pub(crate) fn unreadable_in_hierarchy(
+    unreadable: &[ReportEntry],
+    target_path: &Path,
+    options: &MergeOptions,
+) -> Vec<ReportEntry> {
+    unreadable
+        .iter()
+        .filter(|entry| {
+            entry.files.first().is_some_and(|path| {
+                paths::is_within(target_path, path)
+                    || (options.target_mode == TargetMode::AllDescendants && paths::is_within(path, target_path))
+                    || (options.is_config_file(path) && path.parent().is_some_and(|dir| paths::is_within(target_path, dir)))
+            })
+        })
+        .cloned()
+        .collect()
+}
 
-        // Check for key collisions at the same depth
-        let mut all_keys_at_depth = std::collections::HashSet::new();
-        let mut key_sources = HashMap::new();
-
-        for (file_path, config) in depth_configs {
-            if let ConfigValue::Mapping(map) = config {
-                for (key, _) in map {
-                    if let ConfigValue::String(key_str) = key {
-                        if all_keys_at_depth.contains(key_str) {
-                            // Collision at same depth
-                            let existing_source = key_sources.get(key_str).unwrap();
-                            errors.push(format!(
-                                "Key collision at depth {}: '{}' found in both {} and {}",
-                                depth, key_str, existing_source, file_path
-                            ));
-                        } else {
-                            all_keys_at_depth.insert(key_str.clone());
-                            key_sources.insert(key_str.clone(), (*file_path).clone());
+/// Entry for a file or directory left out by [`MergeOptions::skip_unreadable`].
+pub(crate) fn unreadable_entry(path: &Path, error: &str) -> ReportEntry {
+    ReportEntry {
+        kind: ReportKind::Unreadable,
+        key_path: None,
+        files: vec![path.to_path_buf()],
+        message: format!("Skipped unreadable {}: {error}", path.display()),
+    }
+}
+
+/// The entry leaving out a file whose loading failed with `error`, when that
+/// is a read failure and [`MergeOptions::skip_unreadable`] is set, or when
+/// the path is not a regular file anymore, such as a file replaced by a
+/// directory since discovery.
+pub(crate) fn skipped_read(error: &anyhow::Error, options: &MergeOptions) -> Option<ReportEntry> {
+    let Some(ConfigError::Io { path, source, .. }) = error.downcast_ref::<ConfigError>() else {
+        return None;
+    };
+    if fs::metadata(path).is_ok_and(|metadata| !metadata.is_file()) {
+        return Some(ReportEntry {
+            kind: ReportKind::NotAFile,
+            key_path: None,
+            files: vec![path.clone()],
+            message: format!("Skipped {}: not a regular file", path.display()),
+        });
+    }
+    options.skip_unreadable.then(|| unreadable_entry(path, &source.to_string()))
+}
+
+/// Files to merge for `target_path`, in merge order: the target files among
+/// `discovered`, see [`select_target_files`], plus the extra root files, each at its layer depth. Files of
+/// inactive profiles are left out.
+pub(crate) fn layer_files(
+    base_dir: &Path,
+    target_path: &Path,
+    discovered: &[PathBuf],
+    extra_root_files: &[(PathBuf, i32)],
+    options: &MergeOptions,
+) -> Vec<LayerFile> {
+    let base_depth = base_layer_depth(base_dir);
+    let mut files: Vec<LayerFile> = select_target_files(base_dir, target_path, discovered, options)
+        .into_iter()
+        .map(|path| (config_depth(&path) as i64, path))
+        .chain(
+            extra_root_files
+                .iter()
+                .map(|(path, offset)| (base_depth + i64::from(*offset), path.clone())),
+        )
+        .filter_map(|(depth, path)| {
+            let rank = options.profile_rank(&path)?;
+            Some(LayerFile { depth, rank, path })
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+/// Removes the files at or below [`MergeOptions::max_merge_depth`] levels
+/// under the base, returning an entry for each. `base_depth` is the depth of
+/// the base directory's files.
+pub(crate) fn skip_deep_files(files: &mut Vec<LayerFile>, base_depth: i64, options: &MergeOptions) -> Vec<ReportEntry> {
+    let Some(max_depth) = options.max_merge_depth else {
+        return Vec::new();
+    };
+    let mut skipped = Vec::new();
+    files.retain(|file| {
+        let depth = file.depth - base_depth;
+        if depth < max_depth as i64 {
+            return true;
+        }
+        skipped.push(ReportEntry {
+            kind: ReportKind::Skipped,
+            key_path: None,
+            files: vec![file.path.clone()],
+            message: format!(
+                "Skipped {} at depth {depth}: only {max_depth} level(s) are merged",
+                file.path.display()
+            ),
+        });
+        false
+    });
+    skipped
+}
+
+/// Canonical base and layer files of `target_path`, for already expanded
+/// paths. Entries for what could not be read are added to `unreadable`, as is
+/// the [`ignored_descendants`] entry when there are files to merge.
+pub(crate) fn discover_layer_files(
+    base_dir: &Path,
+    target_path: &Path,
+    options: &MergeOptions,
+    unreadable: &mut Vec<ReportEntry>,
+) -> Result<(PathBuf, Vec<LayerFile>)> {
+    let _span = trace::discover_span(base_dir, target_path);
+    let (canonical_base, canonical_target) = canonicalize_hierarchy(base_dir, target_path)?;
+    let mut walked = Vec::new();
+    let discovered = discover_yaml_files(&canonical_base, options, &mut walked)?;
+    unreadable.extend(unreadable_in_hierarchy(&walked, &canonical_target, options));
+    let extra_root_files = discover_extra_roots(options, unreadable)?;
+    let files = layer_files(&canonical_base, &canonical_target, &discovered, &extra_root_files, options);
+    if !files.is_empty() {
+        unreadable.extend(ignored_descendants(&canonical_base, &canonical_target, &discovered, options));
+    }
+    Ok((canonical_base, files))
+}
+
+/// The files [`merge_hierarchy`] would merge for `target_path`, in merge
+/// order: shallowest layer first and by path within a layer.
+pub fn find_layer_files(base_dir: &Path, target_path: &Path, options: &MergeOptions) -> Result<Vec<PathBuf>, ConfigError> {
+    let base_dir = options.input_path(base_dir)?;
+    let target_path = options.input_path(target_path)?;
+    let (canonical_base, mut files) = discover_layer_files(&base_dir, &target_path, options, &mut Vec::new())?;
+    skip_deep_files(&mut files, base_layer_depth(&canonical_base), options);
+    Ok(files.into_iter().map(|file| file.path).collect())
+}
+
+/// Groups configs by layer (directory level, then profile rank), shallowest
+/// first. Files within a layer are sorted by priority, then by path so that
+/// the merge order is deterministic.
+pub(crate) fn group_by_depth<'a, I>(configs: I) -> Vec<(LayerKey, Vec<(&'a Path, &'a ConfigValue)>)>
+where
+    I: IntoIterator<Item = (LayerKey, &'a Path, &'a ConfigValue)>,
+{
+    let mut depth_groups: HashMap<LayerKey, Vec<(&Path, &ConfigValue)>> = HashMap::new();
+
+    for (depth, file_path, config) in configs {
+        depth_groups.entry(depth).or_default().push((file_path, config));
+    }
+
+    let mut groups: Vec<_> = depth_groups.into_iter().collect();
+    groups.sort_by_key(|(depth, _)| *depth);
+    for (_, group) in &mut groups {
+        group.sort_by(|a, b| a.0.cmp(b.0));
+        sort_by_priority(group);
+    }
+    groups
+}
+
+/// Records an entry for every pair of files at the same depth and priority
+/// defining the same top-level keys. Below each such key the collision is
+/// placed at the deepest paths both files define: the leaves they both set,
+/// or the key itself when their mappings share no key. Paths matching
+/// [`MergeOptions::collision_exempt_paths`] are exempt. The entry's key path
+/// is the first colliding path, and its message names them all. Files of
+/// different priorities merge in an explicit order and never collide.
+pub(crate) fn collect_depth_collisions(
+    depth: i64,
+    depth_configs: &[(&Path, &ConfigValue)],
+    options: &MergeOptions,
+    entries: &mut Vec<ReportEntry>,
+) {
+    let mut key_sources: HashMap<(&str, i64), (&Path, &ConfigValue)> = HashMap::new();
+    // Colliding paths of each kind and pair of files, in order of appearance
+    let mut groups: Vec<(ReportKind, &Path, &Path, Vec<String>)> = Vec::new();
+
+    for (file_path, config) in depth_configs {
+        let priority = priority::file_priority(config);
+        if let ConfigValue::Mapping(map) = config {
+            for (key, value) in map {
+                if let ConfigValue::String(key_str) = key {
+                    if key_str == PRIORITY_KEY {
+                        continue;
+                    }
+                    if let Some((existing_source, existing)) = key_sources.get(&(key_str.as_str(), priority)) {
+                        // Collision at same depth
+                        let mut paths = Vec::new();
+                        colliding_paths(existing, value, &mut vec![PathSegment::Key(key_str.clone())], &mut paths);
+                        for path in paths {
+                            let kind = match options.is_collision_exempt(&path) {
+                                false => ReportKind::Collision,
+                                true if options.report_exempt_collisions => ReportKind::ExemptCollision,
+                                true => continue,
+                            };
+                            let path = keypath::format_key_path(&path);
+                            let group = (kind, *existing_source, *file_path);
+                            match groups.iter_mut().find(|(kind, first, second, _)| (*kind, *first, *second) == group) {
+                                Some((_, _, _, paths)) => paths.push(path),
+                                None => groups.push((kind, group.1, group.2, vec![path])),
+                            }
                         }
+                    } else {
+                        key_sources.insert((key_str, priority), (file_path, value));
                     }
                 }
             }
         }
+    }
 
-        // Merge configs at this depth
-        for (_, config) in depth_configs {
-            merged_config = deep_merge(&merged_config, config);
+    // One entry per pair of files, naming the first path as its own
+    for (kind, existing_source, file_path, paths) in groups {
+        let quoted: Vec<String> = paths.iter().map(|path| format!("'{path}'")).collect();
+        entries.push(ReportEntry {
+            kind,
+            key_path: Some(paths[0].clone()),
+            files: vec![existing_source.to_path_buf(), file_path.to_path_buf()],
+            message: format!(
+                "Key collision at depth {}: {} found in both {} and {}",
+                depth,
+                quoted.join(", "),
+                existing_source.display(),
+                file_path.display()
+            ),
+        });
+    }
+}
+
+/// Adds to `paths` the deepest paths below `path` that both `a` and `b`
+/// define: `path` itself unless both are mappings sharing keys.
+fn colliding_paths(a: &ConfigValue, b: &ConfigValue, path: &mut Vec<PathSegment>, paths: &mut Vec<Vec<PathSegment>>) {
+    if let (ConfigValue::Mapping(a), ConfigValue::Mapping(b)) = (a, b)
+        && a.keys().any(|key| b.contains_key(key))
+    {
+        for (key, item) in a {
+            if let Some(other) = b.get(key) {
+                path.push(PathSegment::Key(keypath::key_to_string(key)));
+                colliding_paths(item, other, path, paths);
+                path.pop();
+            }
+        }
+    } else {
+        paths.push(path.clone());
+    }
+}
+
+/// Records an entry for every top-level key of `depth_configs` that is not in
+/// `merged` yet nor exempt, when `lock_top_level` is set and the layer is not
+/// the `first`.
+pub(crate) fn collect_lock_violations(
+    merged: &ConfigValue,
+    depth_configs: &[(&Path, &ConfigValue)],
+    first: bool,
+    options: &MergeOptions,
+    entries: &mut Vec<ReportEntry>,
+) {
+    if !options.lock_top_level || first {
+        return;
+    }
+    let existing = merged.as_mapping();
+    for (file_path, config) in depth_configs {
+        let Some(map) = config.as_mapping() else {
+            continue;
+        };
+        for key in map.keys() {
+            let section = keypath::key_to_string(key);
+            if existing.is_some_and(|existing| existing.contains_key(key)) || options.lock_exempt_sections.contains(&section) {
+                continue;
+            }
+            entries.push(ReportEntry {
+                kind: ReportKind::LockViolation,
+                message: format!(
+                    "New top-level section '{}' in {}: only the first layer may add sections",
+                    section,
+                    file_path.display()
+                ),
+                key_path: Some(section),
+                files: vec![file_path.to_path_buf()],
+            });
+        }
+    }
+}
+
+/// Records an entry for every set of files whose configs are identical, as
+/// compared by [`config_hash`], naming the files in merge order.
+pub(crate) fn collect_duplicates<'a>(
+    configs: impl IntoIterator<Item = (&'a Path, &'a ConfigValue)>,
+    entries: &mut Vec<ReportEntry>,
+) {
+    let mut by_hash: Vec<(String, Vec<&Path>)> = Vec::new();
+    for (file_path, config) in configs {
+        let hash = config_hash(config);
+        match by_hash.iter_mut().find(|(seen, _)| *seen == hash) {
+            Some((_, files)) => files.push(file_path),
+            None => by_hash.push((hash, vec![file_path])),
         }
     }
+    for (_, files) in by_hash.into_iter().filter(|(_, files)| files.len() > 1) {
+        let names: Vec<_> = files.iter().map(|file| file.display().to_string()).collect();
+        entries.push(ReportEntry {
+            kind: ReportKind::Duplicate,
+            key_path: None,
+            files: files.into_iter().map(Path::to_path_buf).collect(),
+            message: format!("Identical configs in {}", names.join(", ")),
+        });
+    }
+}
+
+/// Merges every config of one layer on top of `merged_config`, adding the
+/// unknown `$patch` directives of a strategic merge and the type coercions
+/// to `entries`. Fails once `guard` finds the merged config too large, and
+/// when the merge is cancelled before the layer.
+pub(crate) fn merge_layer(
+    mut merged_config: ConfigValue,
+    depth_configs: &[(&Path, &ConfigValue)],
+    options: &MergeOptions,
+    entries: &mut Vec<ReportEntry>,
+    guard: &mut SizeGuard,
+) -> Result<ConfigValue> {
+    check_cancelled(options)?;
+    for (path, config) in depth_configs {
+        let config = coerce_override(&merged_config, config, Some(path), options, entries)?;
+        check_index_patches(&merged_config, &config, Some(path), options, entries)?;
+        match &options.mode {
+            MergeMode::StrategicMergePatch { default_key } => {
+                let (patched, directives) =
+                    strategic::merge_patch(&merged_config, &config, default_key, options, Some(path));
+                entries.extend(directives);
+                guard.add(&config);
+                merged_config = patched;
+            }
+            _ => {
+                guard.add(&config);
+                deep_merge_into(&mut merged_config, config, options);
+            }
+        }
+        guard.check(&merged_config, path, options)?;
+    }
+    Ok(merged_config)
+}
+
+/// Merges configs keyed by path, as [`parse_config_files`] returns them,
+/// layered by the component count of each path: a [`merge_layered`] merge of
+/// layers named by path. String keys are accepted as well, for maps from the
+/// deprecated [`parse_yaml_configs`].
+///
+/// The configs are consumed: their values move into the merged config
+/// instead of being copied.
+pub fn merge_configs_by_depth<K: AsRef<Path>>(
+    configs: HashMap<K, ConfigValue>
+) -> Result<(ConfigValue, Vec<String>), ConfigError> {
+    let layers = configs
+        .into_iter()
+        .map(|(path, config)| {
+            let path = path.as_ref();
+            Layer::new(path.to_string_lossy(), config_depth(path) as i64, config)
+        })
+        .collect();
+    let (merged_config, report) = merge_layered(layers, &MergeOptions::default())?;
+    Ok((merged_config, report.warnings()))
+}
+
+/// Checks the `files` of a merge, in merge order, before their layers merge:
+/// for the reserved metadata key, duplicated and shadowed files.
+pub(crate) fn check_merge_files(
+    files: &[(&Path, &ConfigValue)],
+    options: &MergeOptions,
+    entries: &mut Vec<ReportEntry>,
+) -> Result<()> {
+    check_reserved_key(files.iter().copied(), options)?;
+    if options.report_duplicates {
+        collect_duplicates(files.iter().copied(), entries);
+    }
+    collect_shadowed_files(files, options, entries);
+    Ok(())
+}
 
-    Ok((merged_config, errors))
+/// The passes over a config once the layers of `files` merged into it, from
+/// stripping deprecation tags to respelling keys. Every merge of files runs
+/// them, in this order.
+pub(crate) fn finish_merge(
+    config: ConfigValue,
+    files: &[(&Path, &ConfigValue)],
+    options: &MergeOptions,
+    entries: &mut Vec<ReportEntry>,
+) -> Result<ConfigValue> {
+    let config = strip_deprecated_tags(config, files, entries);
+    let config = interpolate_merged(config, options, entries)?;
+    let config = normalize_units(config, files, options, entries);
+    let config = prune_merged(config, options, entries);
+    let config = normalize_merged(config, options);
+    Ok(case_merged_keys(config, options, entries))
+}
+
+/// Whether [`finish_merge`] may change `config`, for merges that would
+/// rather not copy it for nothing.
+pub(crate) fn finishes(config: &ConfigValue, options: &MergeOptions) -> bool {
+    options.interpolate
+        || !options.units.is_empty()
+        || !options.prune_paths.is_empty()
+        || options.normalize.is_some()
+        || options.key_case.is_some()
+        || has_deprecated_tags(config)
+}
+
+/// Layer-ordered merge of already parsed configs, collecting collisions, with
+/// references resolved when [`MergeOptions::interpolate`] is set.
+pub(crate) fn merge_layers_with_report<'a, I>(configs: I, options: &MergeOptions) -> Result<(ConfigValue, MergeReport)>
+where
+    I: IntoIterator<Item = (LayerKey, &'a Path, &'a ConfigValue)>,
+{
+    let mut merged_config = options.initial_config();
+    let mut report = MergeReport::default();
+
+    let groups = group_by_depth(configs);
+
+    let files: Vec<_> = groups.iter().flat_map(|(_, group)| group.iter().copied()).collect();
+    check_merge_files(&files, options, &mut report.entries)?;
+    let mut guard = SizeGuard::new(&merged_config, options);
+
+    // Process configs from shallowest to deepest
+    for (index, ((depth, _), depth_configs)) in groups.into_iter().enumerate() {
+        let renamed = apply_layer_deprecations(&depth_configs, options, &mut report.entries)?;
+        let depth_configs: Vec<_> = renamed.iter().map(|(path, config)| (*path, config.as_ref())).collect();
+
+        // Check for key collisions at the same depth
+        if options.collision_policy != CollisionPolicy::Ignore {
+            collect_depth_collisions(depth, &depth_configs, options, &mut report.entries);
+        }
+        let stripped = strip_layer_priorities(&depth_configs);
+        let depth_configs: Vec<_> = stripped.iter().map(|(path, config)| (*path, config.as_ref())).collect();
+        collect_lock_violations(&merged_config, &depth_configs, index == 0, options, &mut report.entries);
+
+        // Merge configs at this depth
+        merged_config = merge_layer(merged_config, &depth_configs, options, &mut report.entries, &mut guard)?;
+        trace::merged_layer(depth, depth_configs.len());
+    }
+
+    let merged_config = finish_merge(merged_config, &files, options, &mut report.entries)?;
+    report.stats = measure(&merged_config);
+    Ok((merged_config, report))
 }
 
 pub fn merge_hierarchical_configs(
     base_dir: &Path,
     target_path: &Path,
-) -> Result<(ConfigValue, Vec<String>)> {
+) -> Result<(ConfigValue, Vec<String>), ConfigError> {
+    let (merged_config, report) = merge_hierarchy(base_dir, target_path, &MergeOptions::default())?;
+    Ok((merged_config, report.warnings()))
+}
+
+/// Merges the hierarchy from `base_dir` down to `target_path`, returning the
+/// merged config with a structured report.
+pub fn merge_hierarchy(
+    base_dir: &Path,
+    target_path: &Path,
+    options: &MergeOptions,
+) -> Result<(ConfigValue, MergeReport), ConfigError> {
+    merge_hierarchy_from(&FsSource, base_dir, target_path, options)
+}
+
+/// [`merge_hierarchy`], reading the discovered files through `source`.
+pub(crate) fn merge_hierarchy_from(
+    source: &dyn ConfigSource,
+    base_dir: &Path,
+    target_path: &Path,
+    options: &MergeOptions,
+) -> Result<(ConfigValue, MergeReport), ConfigError> {
+    let options = &arm(options);
+    let base_dir = &options.input_path(base_dir)?;
+    let target_path = &options.input_path(target_path)?;
+
+    let Some(loaded) = load_hierarchy(source, base_dir, target_path, options)? else {
+        return empty_merge(base_dir, target_path, options);
+    };
+
+    // Merge configs by depth
+    let (merged_config, mut report) = {
+        let _span = trace::merge_span(loaded.configs.len());
+        merge_layers_with_report(loaded.layers(), options)?
+    };
+    loaded.check_types(&merged_config, &mut report);
+    loaded.complete_report(&mut report);
+    options.check_report(&report)?;
+    let merged_config = embed_metadata(merged_config, Some(base_dir), Some(target_path), &report, options);
+    Ok((merged_config, report))
+}
+
+/// Merges explicitly selected files with the layer and collision semantics of
+/// [`merge_hierarchy`]. A file paired with a priority joins the layer of that
+/// depth; otherwise its depth is its directory level below `base_dir` (0 for
+/// the files directly in it). Priorities are on that same scale. Without
+/// `base_dir`, files without a priority take their path's component count,
+/// like [`merge_configs_by_depth`].
+pub fn merge_files(
+    files: &[(PathBuf, Option<i64>)],
+    base_dir: Option<&Path>,
+    options: &MergeOptions,
+) -> Result<(ConfigValue, MergeReport), ConfigError> {
+    let options = &arm(options);
+    let base_dir = match base_dir {
+        Some(base_dir) => {
+            let base_dir = options.input_path(base_dir)?;
+            let canonical = base_dir
+                .canonicalize()
+                .map_err(ConfigError::io("Failed to resolve base directory", &base_dir))?;
+            Some(canonical)
+        }
+        None => None,
+    };
+    let base_depth = base_dir.as_deref().map_or(0, base_layer_depth);
+
+    let _span = trace::parse_span(files.len());
+    let mut configs = Vec::with_capacity(files.len());
+    let mut report = MergeReport::default();
+    let mut unreadable = Vec::new();
+    for (path, priority) in files {
+        let path = options.input_path(path)?;
+        let path = path
+            .canonicalize()
+            .map_err(ConfigError::io("Failed to resolve config file", &path))?;
+        let depth = match (priority, &base_dir) {
+            (Some(priority), _) => base_depth + priority,
+            (None, Some(base_dir)) if paths::is_within(&path, base_dir) => config_depth(&path) as i64,
+            (None, Some(base_dir)) => {
+                return Err(ConfigError::OutsideBase {
+                    base: base_dir.clone(),
+                    target: path,
+                });
+            }
+            (None, None) => config_depth(&path) as i64,
+        };
+        let (config, sha256) = match load_config_file(&FsSource, &path, options, &mut unreadable) {
+            Ok(loaded) => loaded,
+            Err(e) => match skipped_read(&e, options) {
+                Some(entry) => {
+                    unreadable.push(entry);
+                    continue;
+                }
+                None => return Err(e.into()),
+            },
+        };
+        report.files.push(ContributingFile {
+            path: path.clone(),
+            depth: depth - base_depth,
+            sha256,
+        });
+        configs.push((LayerFile { depth, rank: 0, path }, config));
+    }
+    // Merge order: shallowest layer first and by path within a layer
+    report.files.sort_by(|a, b| (a.depth, &a.path).cmp(&(b.depth, &b.path)));
+
+    let (merged_config, layer_report) = {
+        let _span = trace::merge_span(configs.len());
+        let layers = configs.iter().map(|(file, config)| (file.layer(), file.path.as_path(), config));
+        merge_layers_with_report(layers, options)?
+    };
+    report.entries = unreadable;
+    report.entries.extend(layer_report.entries);
+    report.stats = layer_report.stats;
+    options.check_report(&report)?;
+    let merged_config = embed_metadata(merged_config, base_dir.as_deref(), None, &report, options);
+    Ok((merged_config, report))
+}
+
+/// The parsed files of one merge, in merge order.
+pub(crate) struct LoadedHierarchy {
+    pub configs: Vec<(LayerFile, ConfigValue)>,
+    pub files: Vec<ContributingFile>,
+    /// Entries for the files left out by [`MergeOptions::max_merge_depth`]
+    /// and [`MergeOptions::skip_unreadable`], then for what loading each
+    /// file left out of it, schema violations and the type mismatches of
+    /// single files.
+    pub skipped: Vec<ReportEntry>,
+    /// The rules checking the merged config, see [`TypeCheck::Merged`].
+    pub types: Option<TypeRules>,
+}
+
+impl LoadedHierarchy {
+    pub fn layers(&self) -> impl Iterator<Item = (LayerKey, &Path, &ConfigValue)> {
+        self.configs
+            .iter()
+            .map(|(file, config)| (file.layer(), file.path.as_path(), config))
+    }
+
+    /// Adds the type mismatches of the merged `config` to `report`.
+    pub fn check_types(&self, config: &ConfigValue, report: &mut MergeReport) {
+        if let Some(rules) = &self.types {
+            let files: Vec<(&Path, &ConfigValue)> = self.layers().map(|(_, path, config)| (path, config)).collect();
+            types::check_merged(rules, config, &files, &mut report.entries);
+        }
+    }
+
+    /// Adds the contributing and skipped files to the report of the merge.
+    pub fn complete_report(self, report: &mut MergeReport) {
+        report.files = self.files;
+        report.entries.splice(0..0, self.skipped);
+    }
+}
+
+/// Discovers the files of a merge, for already expanded paths, and parses
+/// them as read through `source`. `None` when the hierarchy has no YAML file.
+pub(crate) fn load_hierarchy(
+    source: &dyn ConfigSource,
+    base_dir: &Path,
+    target_path: &Path,
+    options: &MergeOptions,
+) -> Result<Option<LoadedHierarchy>> {
     // Find YAML files in hierarchy
-    let yaml_files = find_yaml_files_in_hierarchy(base_dir, target_path)?;
-
-    if yaml_files.is_empty() {
-        return Ok((
-            ConfigValue::Mapping(serde_yaml::Mapping::new()),
-            vec![format!(
-                "No YAML files found in hierarchy from {} to {}",
-                base_dir.display(),
-                target_path.display()
-            )],
-        ));
+    let mut unreadable = Vec::new();
+    let (canonical_base, mut yaml_files) = discover_layer_files(base_dir, target_path, options, &mut unreadable)?;
+    if yaml_files.is_empty() && unreadable.is_empty() && !options.has_remote_layers() {
+        return Ok(None);
+    }
+    let rules = types::load_rules(source, &canonical_base, options)?;
+    if rules.is_some() {
+        let types_file = canonical_base.join(types::TYPES_FILE);
+        yaml_files.retain(|file| file.path != types_file);
     }
+    let base_depth = base_layer_depth(&canonical_base);
+    let mut skipped = skip_deep_files(&mut yaml_files, base_depth, options);
+    skipped.append(&mut unreadable);
 
     // Parse YAML configs
-    let configs = parse_yaml_configs(&yaml_files)?;
-
-    // Merge configs by depth
-    let (merged_config, errors) = merge_configs_by_depth(&configs)?;
+    let _span = trace::parse_span(yaml_files.len());
+    let mut configs = Vec::with_capacity(yaml_files.len());
+    let mut files = Vec::with_capacity(yaml_files.len());
+    for yaml_file in yaml_files {
+        let (config, sha256) = match load_config_file(source, &yaml_file.path, options, &mut skipped) {
+            Ok(loaded) => loaded,
+            Err(e) => match skipped_read(&e, options) {
+                Some(entry) => {
+                    skipped.push(entry);
+                    continue;
+                }
+                None => return Err(e),
+            },
+        };
+        if let Some(rules) = &rules
+            && options.type_check == TypeCheck::EachLayer
+        {
+            types::check_layer(rules, &yaml_file.path, &config, &mut skipped);
+        }
+        files.push(ContributingFile {
+            path: yaml_file.path.clone(),
+            depth: yaml_file.depth - base_depth,
+            sha256,
+        });
+        configs.push((yaml_file, config));
+    }
+    #[cfg(feature = "schema")]
+    {
+        let files: Vec<_> = configs.iter().map(|(file, config)| (file.path.as_path(), config)).collect();
+        schema::check_directory_schemas(source, &files, options, &mut skipped)?;
+    }
+    #[cfg(feature = "http")]
+    remote::load_remote_layers(options, base_depth, &mut configs, &mut files, &mut skipped)?;
+    let types = rules.filter(|_| options.type_check == TypeCheck::Merged);
+    Ok(Some(LoadedHierarchy {
+        configs,
+        files,
+        skipped,
+        types,
+    }))
+}
 
-    Ok((merged_config, errors))
+/// Result of merging a hierarchy without any YAML file.
+pub(crate) fn empty_merge(
+    base_dir: &Path,
+    target_path: &Path,
+    options: &MergeOptions,
+) -> Result<(ConfigValue, MergeReport), ConfigError> {
+    check_reserved_key([], options)?;
+    let report = options.empty_hierarchy_report(base_dir, target_path)?;
+    options.check_report(&report)?;
+    let config = embed_metadata(options.initial_config(), Some(base_dir), Some(target_path), &report, options);
+    Ok((config, report))
 }
 
 #[cfg(test)]
@@ -220,17 +1185,17 @@ mod tests {
         let result = deep_merge(&ConfigValue::Mapping(base), &ConfigValue::Mapping(r#override));
 
         if let ConfigValue::Mapping(result_map) = result {
-            assert_eq!(result_map.get(&ConfigValue::String("a".to_string())), Some(&ConfigValue::Number(serde_yaml::Number::from(1))));
-            assert_eq!(result_map.get(&ConfigValue::String("b".to_string())), Some(&ConfigValue::Number(serde_yaml::Number::from(3))));
-            assert_eq!(result_map.get(&ConfigValue::String("d".to_string())), Some(&ConfigValue::Number(serde_yaml::Number::from(4))));
+            assert_eq!(result_map.get(ConfigValue::String("a".to_string())), Some(&ConfigValue::Number(serde_yaml::Number::from(1))));
+            assert_eq!(result_map.get(ConfigValue::String("b".to_string())), Some(&ConfigValue::Number(serde_yaml::Number::from(3))));
+            assert_eq!(result_map.get(ConfigValue::String("d".to_string())), Some(&ConfigValue::Number(serde_yaml::Number::from(4))));
 
-            if let Some(ConfigValue::Mapping(c_map)) = result_map.get(&ConfigValue::String("c".to_string())) {
+            if let Some(ConfigValue::Mapping(c_map)) = result_map.get(ConfigValue::String("c".to_string())) {
                 assert_eq!(
-                    c_map.get(&ConfigValue::String("nested".to_string())),
+                    c_map.get(ConfigValue::String("nested".to_string())),
                     Some(&ConfigValue::String("override".to_string()))
                 );
                 assert_eq!(
-                    c_map.get(&ConfigValue::String("new".to_string())),
+                    c_map.get(ConfigValue::String("new".to_string())),
                     Some(&ConfigValue::String("value".to_string()))
                 );
             } else {
@@ -261,29 +1226,872 @@ mod tests {
         configs.insert("/base/level1/config.yaml".to_string(), ConfigValue::Mapping(level1_config));
         configs.insert("/base/level1/level2/config.yaml".to_string(), ConfigValue::Mapping(level2_config));
 
-        let (merged_config, errors) = merge_configs_by_depth(&configs).unwrap();
+        let (merged_config, errors) = merge_configs_by_depth(configs).unwrap();
 
         assert!(errors.is_empty());
 
         if let ConfigValue::Mapping(merged_map) = merged_config {
             assert_eq!(
-                merged_map.get(&ConfigValue::String("key1".to_string())),
+                merged_map.get(ConfigValue::String("key1".to_string())),
                 Some(&ConfigValue::String("base_value".to_string()))
             );
             assert_eq!(
-                merged_map.get(&ConfigValue::String("key2".to_string())),
+                merged_map.get(ConfigValue::String("key2".to_string())),
                 Some(&ConfigValue::String("level1_value".to_string()))
             );
             assert_eq!(
-                merged_map.get(&ConfigValue::String("key3".to_string())),
+                merged_map.get(ConfigValue::String("key3".to_string())),
                 Some(&ConfigValue::String("level2_value".to_string()))
             );
             assert_eq!(
-                merged_map.get(&ConfigValue::String("key4".to_string())),
+                merged_map.get(ConfigValue::String("key4".to_string())),
                 Some(&ConfigValue::String("level2_value4".to_string()))
             );
         } else {
             panic!("Expected merged config to be a map");
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_report_lists_contributing_files_in_merge_order() {
+        let dir = crate::testing::fixture_tree(&[
+            ("a/b/config.yaml", "key: leaf\n"),
+            ("a/config.yaml", "key: mid\n"),
+            ("z.yaml", "other: 1\n"),
+            ("config.yaml", "key: root\n"),
+        ]);
+        let target = dir.path().join("a/b");
+
+        let (_, report) = merge_hierarchy(dir.path(), &target, &MergeOptions::default()).unwrap();
+
+        let base = dir.path().canonicalize().unwrap();
+        let listed: Vec<(PathBuf, i64)> = report.files.iter().map(|f| (f.path.clone(), f.depth)).collect();
+        assert_eq!(
+            listed,
+            vec![
+                (base.join("config.yaml"), 0),
+                (base.join("z.yaml"), 0),
+                (base.join("a/config.yaml"), 1),
+                (base.join("a/b/config.yaml"), 2),
+            ]
+        );
+        assert!(report.files.iter().all(|f| f.sha256.len() == 64));
+
+        std::fs::write(dir.path().join("a/config.yaml"), "key: changed\n").unwrap();
+        let (_, changed) = merge_hierarchy(dir.path(), &target, &MergeOptions::default()).unwrap();
+        assert_eq!(changed.files[0].sha256, report.files[0].sha256);
+        assert_ne!(changed.files[2].sha256, report.files[2].sha256);
+    }
+
+    #[test]
+    fn test_defaults_lie_beneath_every_file() {
+        #[derive(serde::Serialize)]
+        struct Server {
+            port: u16,
+            workers: u32,
+        }
+        #[derive(serde::Serialize)]
+        struct Defaults {
+            server: Server,
+        }
+
+        let dir = crate::testing::fixture_tree(&[
+            ("config.yaml", "server: {host: localhost}\n"),
+            ("a/config.yaml", "server: {port: 8080}\n"),
+            ("a/other.yaml", "other: 1\n"),
+        ]);
+        let options = MergeOptions {
+            collision_policy: CollisionPolicy::Error,
+            ..MergeOptions::default()
+        }
+        .with_defaults_from(&Defaults {
+            server: Server { port: 80, workers: 4 },
+        })
+        .unwrap();
+
+        let (config, report) = merge_hierarchy(dir.path(), &dir.path().join("a"), &options).unwrap();
+        assert_eq!(config["server"]["workers"], ConfigValue::from(4));
+        assert_eq!(config["server"]["port"], ConfigValue::from(8080));
+        assert_eq!(config["server"]["host"], ConfigValue::from("localhost"));
+        assert!(report.is_empty());
+
+        let empty = tempfile::tempdir().unwrap();
+        let (config, _) = merge_hierarchy(empty.path(), empty.path(), &options).unwrap();
+        assert_eq!(config, options.defaults.unwrap());
+    }
+
+    #[test]
+    fn test_max_merge_depth_stops_above_the_leaf() {
+        let dir = crate::testing::fixture_tree(&[
+            ("config.yaml", "level: org\norg: 1\n"),
+            ("team/config.yaml", "level: team\nteam: 1\n"),
+            ("team/svc/config.yaml", "level: svc\nsvc: 1\n"),
+        ]);
+        let target = dir.path().join("team/svc");
+        let options = MergeOptions {
+            max_merge_depth: Some(2),
+            ..MergeOptions::default()
+        };
+
+        let (config, report) = merge_hierarchy(dir.path(), &target, &options).unwrap();
+        assert_eq!(config, serde_yaml::from_str::<ConfigValue>("level: team\norg: 1\nteam: 1\n").unwrap());
+        let leaf = dir.path().canonicalize().unwrap().join("team/svc/config.yaml");
+        assert_eq!(report.entries.len(), 1);
+        assert_eq!(report.entries[0].kind, ReportKind::Skipped);
+        assert_eq!(report.entries[0].files, vec![leaf]);
+        assert_eq!(report.files.len(), 2);
+        assert!(report.warnings().is_empty());
+        assert_eq!(find_layer_files(dir.path(), &target, &options).unwrap().len(), 2);
+
+        let (cached, cached_report) = HierarchyMerger::new(dir.path(), options.clone()).merge_with_report(&target).unwrap();
+        assert_eq!(*cached, config);
+        assert_eq!(cached_report, report);
+    }
+
+    #[test]
+    fn test_identical_files_are_reported_when_asked() {
+        let dir = crate::testing::fixture_tree(&[
+            ("config.yaml", "port: 80\nhosts: [a, b]\n"),
+            ("a/config.yaml", "key: mid\n"),
+            ("a/b/config.yaml", "hosts: ['a', b]\nport: 0x50\n"),
+        ]);
+        let target = dir.path().join("a/b");
+        let options = MergeOptions {
+            report_duplicates: true,
+            ..MergeOptions::default()
+        };
+
+        let (_, report) = merge_hierarchy(dir.path(), &target, &options).unwrap();
+        let base = dir.path().canonicalize().unwrap();
+        assert_eq!(report.entries.len(), 1);
+        assert_eq!(report.entries[0].kind, ReportKind::Duplicate);
+        assert_eq!(report.entries[0].files, vec![base.join("config.yaml"), base.join("a/b/config.yaml")]);
+        assert!(report.warnings().is_empty());
+
+        let (_, report) = merge_hierarchy(dir.path(), &target, &MergeOptions::default()).unwrap();
+        assert!(report.is_empty());
+    }
+
+    #[test]
+    fn test_io_and_parse_failures_are_typed() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing");
+        let err = merge_hierarchy(&missing, &missing, &MergeOptions::default()).unwrap_err();
+        match &err {
+            ConfigError::Io { context, path, source } => {
+                assert_eq!(*context, "Failed to resolve path");
+                assert_eq!(path, &missing);
+                assert_eq!(source.kind(), std::io::ErrorKind::NotFound);
+            }
+            other => panic!("Expected Io, got {other:?}"),
+        }
+        assert_eq!(err.to_string(), format!("Failed to resolve path: {}", missing.display()));
+
+        std::fs::write(dir.path().join("config.yaml"), "a: [1\n").unwrap();
+        let err = merge_hierarchy(dir.path(), dir.path(), &MergeOptions::default()).unwrap_err();
+        match &err {
+            ConfigError::Parse { path, line, .. } => {
+                assert!(path.ends_with("config.yaml"));
+                assert!(line.is_some());
+            }
+            other => panic!("Expected Parse, got {other:?}"),
+        }
+
+        let err = set_path(&mut ConfigValue::Null, "a..b", ConfigValue::Null).unwrap_err();
+        assert!(matches!(err, ConfigError::Other(_)));
+        assert_eq!(err.to_string(), "Invalid key path 'a..b': empty segment");
+    }
+
+    #[test]
+    fn test_fail_on_empty_returns_typed_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("sub");
+        std::fs::create_dir(&target).unwrap();
+
+        let (config, report) = merge_hierarchy(dir.path(), &target, &MergeOptions::default()).unwrap();
+        assert_eq!(config, ConfigValue::Mapping(serde_yaml::Mapping::new()));
+        assert_eq!(report.entries[0].kind, ReportKind::EmptyHierarchy);
+
+        let options = MergeOptions {
+            fail_on_empty: true,
+            ..MergeOptions::default()
+        };
+        let err = merge_hierarchy(dir.path(), &target, &options).unwrap_err();
+        match &err {
+            ConfigError::EmptyHierarchy { base, target: failed_target } => {
+                assert_eq!(base, dir.path());
+                assert_eq!(failed_target, &target);
+            }
+            other => panic!("Expected EmptyHierarchy, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_strict_promotes_warnings_to_error() {
+        let dir = crate::testing::fixture_tree(&[("one.yaml", "key: 1\n"), ("two.yaml", "key: 2\n")]);
+        let options = MergeOptions {
+            strict: true,
+            ..MergeOptions::default()
+        };
+
+        let err = merge_hierarchy(dir.path(), dir.path(), &options).unwrap_err();
+        match &err {
+            ConfigError::Strict { entries } => {
+                assert_eq!(entries.len(), 1);
+                assert_eq!(entries[0].kind, ReportKind::Collision);
+            }
+            other => panic!("Expected Strict, got {other:?}"),
+        }
+
+        std::fs::remove_file(dir.path().join("two.yaml")).unwrap();
+        assert!(merge_hierarchy(dir.path(), dir.path(), &options).is_ok());
+    }
+
+    #[test]
+    fn test_locked_top_level_rejects_new_sections_below_the_root() {
+        let dir = crate::testing::fixture_tree(&[
+            ("config.yaml", "server:\n  port: 80\n"),
+            ("svc/config.yaml", "server:\n  port: 8080\n  workers: 4\nexperimental: {x: 1}\n"),
+        ]);
+        let target = dir.path().join("svc");
+        let mut options = MergeOptions {
+            lock_top_level: true,
+            ..MergeOptions::default()
+        };
+
+        let (config, report) = merge_hierarchy(dir.path(), &target, &options).unwrap();
+        assert_eq!(config["server"]["port"], ConfigValue::from(8080));
+        assert_eq!(report.entries.len(), 1);
+        let entry = &report.entries[0];
+        assert_eq!(entry.kind, ReportKind::LockViolation);
+        assert_eq!(entry.key_path.as_deref(), Some("experimental"));
+        assert_eq!(entry.files, [target.canonicalize().unwrap().join("config.yaml")]);
+        assert!(entry.message.contains("'experimental'"), "{}", entry.message);
+
+        // Other merge paths report the same.
+        let (_, traced, _) = merge_hierarchy_with_provenance(dir.path(), &target, &options).unwrap();
+        assert_eq!(traced.entries, report.entries);
+        let merged = HierarchyMerger::new(dir.path(), options.clone()).merge_with_report(&target).unwrap().1;
+        assert_eq!(merged.entries, report.entries);
+
+        options.lock_exempt_sections = vec!["experimental".to_string()];
+        assert!(merge_hierarchy(dir.path(), &target, &options).unwrap().1.entries.is_empty());
+
+        options.lock_exempt_sections.clear();
+        options.strict = true;
+        assert!(matches!(merge_hierarchy(dir.path(), &target, &options), Err(ConfigError::Strict { .. })));
+    }
+
+    #[test]
+    fn test_extra_root_priority_offset_decides_winner() {
+        let dir = crate::testing::fixture_tree(&[
+            ("configs/config.yaml", "log_level: info\n"),
+            ("configs/svc/config.yaml", "name: svc\n"),
+            ("common/logging.yaml", "log_level: debug\nformat: json\n"),
+        ]);
+        let base = dir.path().join("configs");
+        let common = dir.path().join("common");
+        let target = base.join("svc");
+
+        let merge_with_offset = |offset| {
+            let options = MergeOptions {
+                extra_roots: vec![(common.clone(), offset)],
+                ..MergeOptions::default()
+            };
+            merge_hierarchy(&base, &target, &options).unwrap()
+        };
+
+        // Below the base layer: the hierarchy wins.
+        let (config, report) = merge_with_offset(-1);
+        assert_eq!(config["log_level"], ConfigValue::from("info"));
+        assert_eq!(config["format"], ConfigValue::from("json"));
+        assert!(report.entries.is_empty());
+        assert_eq!(report.files[0].depth, -1);
+
+        // Above the base layer: the extra root wins.
+        let (config, _) = merge_with_offset(1);
+        assert_eq!(config["log_level"], ConfigValue::from("debug"));
+
+        // Same layer as the base files: reported as a collision.
+        let (_, report) = merge_with_offset(0);
+        assert_eq!(report.entries.len(), 1);
+        assert_eq!(report.entries[0].kind, ReportKind::Collision);
+        assert!(report.entries[0].message.contains("logging.yaml"));
+        assert_eq!(report.entries[0].key_path.as_deref(), Some("log_level"));
+        assert_eq!(report.entries[0].files.len(), 2);
+    }
+
+    #[test]
+    fn test_sequence_strategy_and_null_deletes() {
+        let base: ConfigValue = serde_yaml::from_str("list: [a, b]\nkeep: 1\ndrop: 2\nnested: {x: 1}\n").unwrap();
+        let over: ConfigValue =
+            serde_yaml::from_str("list: [b, c]\ndrop: null\nnested: {x: null, y: 2}\nnew: {z: null}\n").unwrap();
+        let merge = |sequence_strategy, null_deletes| {
+            let options = MergeOptions {
+                sequence_strategy,
+                null_deletes,
+                ..MergeOptions::default()
+            };
+            deep_merge_with(&base, &over, &options)
+        };
+        let yaml = |text: &str| serde_yaml::from_str::<ConfigValue>(text).unwrap();
+
+        assert_eq!(merge(SequenceStrategy::Replace, false), deep_merge(&base, &over));
+        assert_eq!(merge(SequenceStrategy::Append, false)["list"], yaml("[a, b, b, c]"));
+        assert_eq!(merge(SequenceStrategy::Prepend, false)["list"], yaml("[b, c, a, b]"));
+        assert_eq!(
+            merge(SequenceStrategy::Union, true),
+            yaml("list: [a, b, c]\nkeep: 1\nnested: {y: 2}\nnew: {}\n")
+        );
+    }
+
+    #[test]
+    fn test_profiles_and_collision_policy() {
+        let dir = crate::testing::fixture_tree(&[
+            ("config.yaml", "env: base\nreplicas: 1\n"),
+            ("config@prod.yaml", "env: prod\n"),
+            ("config@eu.yaml", "env: eu\nregion: eu\n"),
+            ("settings.json", "{\"replicas\": 3}"),
+        ]);
+        let base = dir.path();
+
+        let merge = |options: MergeOptions| merge_hierarchy(base, base, &options);
+
+        let (config, report) = merge(MergeOptions::default()).unwrap();
+        assert_eq!(config["env"], ConfigValue::from("base"));
+        assert!(report.entries.is_empty());
+
+        // Distinct layers: no collision with the regular file.
+        let (config, report) = merge(MergeOptions {
+            profiles: vec!["eu".to_string(), "prod".to_string()],
+            ..MergeOptions::default()
+        })
+        .unwrap();
+        assert_eq!(config["env"], ConfigValue::from("prod"));
+        assert_eq!(config["region"], ConfigValue::from("eu"));
+        assert!(report.entries.is_empty());
+
+        let json = MergeOptions {
+            extensions: Some(vec![".yaml".to_string(), ".json".to_string()]),
+            ..MergeOptions::default()
+        };
+        let (config, report) = merge(json.clone()).unwrap();
+        assert_eq!(config["replicas"], ConfigValue::from(3));
+        assert_eq!(report.entries[0].kind, ReportKind::Collision);
+
+        let (_, report) = merge(MergeOptions {
+            collision_policy: CollisionPolicy::Ignore,
+            ..json.clone()
+        })
+        .unwrap();
+        assert!(report.entries.is_empty());
+
+        let err = merge(MergeOptions {
+            collision_policy: CollisionPolicy::Error,
+            ..json
+        })
+        .unwrap_err();
+        assert!(matches!(&err, ConfigError::Collision { entries } if entries.len() == 1));
+    }
+
+    #[test]
+    fn test_expand_paths_resolves_environment_variables() {
+        let dir = crate::testing::fixture_tree(&[("envs/prod/config.yaml", "env: prod\n")]);
+        // SAFETY: the variable name is unique to this test.
+        unsafe { std::env::set_var("HCM_TEST_CONFIG_ROOT", dir.path()) };
+        let base = Path::new("$HCM_TEST_CONFIG_ROOT");
+        let target = Path::new("${HCM_TEST_CONFIG_ROOT}/envs/prod");
+
+        assert!(merge_hierarchy(base, target, &MergeOptions::default()).is_err());
+
+        let options = MergeOptions {
+            expand_paths: true,
+            ..MergeOptions::default()
+        };
+        let (config, _) = merge_hierarchy(base, target, &options).unwrap();
+        assert_eq!(config["env"], ConfigValue::from("prod"));
+    }
+
+    #[test]
+    fn test_merge_files_uses_priorities_and_relative_depth() {
+        let dir = crate::testing::fixture_tree(&[
+            ("config.yaml", "env: base\nreplicas: 1\n"),
+            ("svc/config.yaml", "env: svc\n"),
+            ("shared.yaml", "replicas: 2\n"),
+        ]);
+        let base = dir.path();
+        let files = [
+            (base.join("svc/config.yaml"), None),
+            (base.join("config.yaml"), None),
+            (base.join("shared.yaml"), Some(1)),
+        ];
+
+        let (config, report) = merge_files(&files, Some(base), &MergeOptions::default()).unwrap();
+        assert_eq!(config["env"], ConfigValue::from("svc"));
+        assert_eq!(config["replicas"], ConfigValue::from(2));
+        let depths: Vec<_> = report.files.iter().map(|file| file.depth).collect();
+        assert_eq!(depths, [0, 1, 1]);
+
+        // Same layer as svc/config.yaml: reported as a collision.
+        std::fs::write(base.join("shared.yaml"), "env: shared\n").unwrap();
+        let (_, report) = merge_files(&files, Some(base), &MergeOptions::default()).unwrap();
+        assert_eq!(report.entries[0].kind, ReportKind::Collision);
+
+        let err = merge_files(&[(base.join("config.yaml"), None)], Some(&base.join("svc")), &MergeOptions::default())
+            .unwrap_err();
+        assert!(matches!(err, ConfigError::OutsideBase { .. }));
+    }
+
+    #[test]
+    fn test_deep_merge_into_agrees_with_deep_merge_with() {
+        let base: ConfigValue = serde_yaml::from_str("a: {b: 1, c: [1, 2]}\nd: x\ne: [1]\nf: !t {g: 1}\n").unwrap();
+        let r#override: ConfigValue =
+            serde_yaml::from_str("a: {b: ~, c: [2, 3], h: {i: ~, j: 1}}\nd: [y]\ne: [2, 1]\nf: {g: 2}\n").unwrap();
+        for sequence_strategy in [
+            SequenceStrategy::Replace,
+            SequenceStrategy::Append,
+            SequenceStrategy::Prepend,
+            SequenceStrategy::Union,
+        ] {
+            for null_deletes in [false, true] {
+                let options = MergeOptions {
+                    sequence_strategy,
+                    null_deletes,
+                    ..MergeOptions::default()
+                };
+                let expected = deep_merge_with(&base, &r#override, &options);
+                let mut borrowed = base.clone();
+                deep_merge_into(&mut borrowed, Cow::Borrowed(&r#override), &options);
+                assert_eq!(borrowed, expected, "{sequence_strategy:?}, null_deletes: {null_deletes}");
+                let mut owned = base.clone();
+                deep_merge_into(&mut owned, Cow::Owned(r#override.clone()), &options);
+                assert_eq!(owned, expected, "{sequence_strategy:?}, null_deletes: {null_deletes}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_merge_configs_by_depth_matches_golden() {
+        // Captured from the merge that copied the accumulated config at every layer.
+        let configs = testing::synthetic_configs(5, 60);
+        let (config, warnings) = merge_configs_by_depth(configs.clone()).unwrap();
+        assert_eq!(serde_yaml::to_string(&config).unwrap(), include_str!("../../tests/golden/synthetic_merge.yaml"));
+        assert_eq!(warnings.len(), 5);
+        assert_eq!(
+            warnings[4],
+            "Key collision at depth 5: 'section0' found in both level1/level2/level3/level4/config.yaml and \
+             level1/level2/level3/level4/extra.yaml"
+        );
+
+        // The borrowing merge of the hierarchy functions agrees.
+        let layers = configs.iter().map(|(path, config)| ((config_depth(path) as i64, 0), path.as_path(), config));
+        let (borrowed, _) = merge_layers_with_report(layers, &MergeOptions::default()).unwrap();
+        assert_eq!(borrowed, config);
+    }
+
+    #[test]
+    fn test_relative_mixed_case_inputs_select_the_same_files() {
+        let dir = crate::testing::fixture_tree(&[
+            ("config.yaml", "name: base\n"),
+            ("Prod/config.yaml", "name: prod\n"),
+            ("Prod/EU/config.yaml", "region: eu\n"),
+            ("prod-old/config.yaml", "name: old\n"),
+        ]);
+        // The temporary directory reached from the working directory through `..`.
+        let cwd = std::env::current_dir().unwrap();
+        let up: PathBuf = cwd
+            .components()
+            .filter(|component| matches!(component, Component::Normal(_)))
+            .map(|_| Component::ParentDir)
+            .collect();
+        let base = up.join(dir.path().strip_prefix("/").unwrap_or(dir.path()));
+
+        let expected = find_yaml_files_in_hierarchy(dir.path(), &dir.path().join("Prod/EU")).unwrap();
+        assert_eq!(expected.len(), 3);
+        for target in ["Prod/EU", "./Prod/../Prod/EU/.", "prod-old/../Prod/EU"] {
+            let files = find_yaml_files_in_hierarchy(&base.join("."), &base.join(target)).unwrap();
+            assert_eq!(files, expected, "{target}");
+        }
+
+        let (config, _) = merge_hierarchy(&base, &base.join("Prod/./EU"), &MergeOptions::default()).unwrap();
+        assert_eq!(config, serde_yaml::from_str::<ConfigValue>("name: prod\nregion: eu\n").unwrap());
+
+        // A sibling sharing the target's prefix as a string is still outside it.
+        let err = find_yaml_files_in_hierarchy(&base.join("Prod"), &base.join("prod-old")).unwrap_err();
+        assert!(matches!(err, ConfigError::OutsideBase { .. }), "{err}");
+    }
+
+    #[test]
+    fn test_filename_restricts_the_hierarchy_to_one_file_per_level() {
+        let dir = crate::testing::fixture_tree(&[
+            ("settings.yaml", "port: 80\nname: base\n"),
+            ("other.yaml", "port: 1\n"),
+            ("prod/settings.yml", "port: 8080\n"),
+            ("prod/other.yaml", "port: 2\nname: other\n"),
+            ("prod/settings@eu.yaml", "region: eu\n"),
+            ("prod/my-settings.yaml", "region: us\n"),
+        ]);
+        let prod = dir.path().join("prod");
+
+        let (_, report) = merge_hierarchy(dir.path(), &prod, &MergeOptions::default()).unwrap();
+        assert!(report.entries.iter().any(|entry| entry.kind == ReportKind::Collision));
+
+        let options = MergeOptions {
+            filename: Some("settings.yaml".to_string()),
+            profiles: vec!["eu".to_string()],
+            ..MergeOptions::default()
+        };
+        let (config, report) = merge_hierarchy(dir.path(), &prod, &options).unwrap();
+        assert_eq!(config, serde_yaml::from_str::<ConfigValue>("port: 8080\nname: base\nregion: eu\n").unwrap());
+        assert!(report.entries.is_empty(), "{:?}", report.entries);
+        let names: Vec<_> = find_layer_files(dir.path(), &prod, &options)
+            .unwrap()
+            .iter()
+            .map(|path| path.strip_prefix(dir.path().canonicalize().unwrap()).unwrap().to_path_buf())
+            .collect();
+        assert_eq!(
+            names,
+            [PathBuf::from("settings.yaml"), PathBuf::from("prod/settings.yml"), PathBuf::from("prod/settings@eu.yaml")]
+        );
+
+        // A name with an extension outside the configured ones still matches itself.
+        fs::create_dir(prod.join("eu")).unwrap();
+        fs::write(prod.join("eu/settings.json"), "{\"port\": 9090}").unwrap();
+        let json = MergeOptions {
+            filename: Some("settings.json".to_string()),
+            ..MergeOptions::default()
+        };
+        let (config, _) = merge_hierarchy(dir.path(), &prod.join("eu"), &json).unwrap();
+        assert_eq!(config, serde_yaml::from_str::<ConfigValue>("port: 9090\nname: base\n").unwrap());
+    }
+
+    #[test]
+    fn test_collisions_between_two_files_are_grouped() {
+        let dir = crate::testing::fixture_tree(&[
+            ("a.yaml", "host: a\nport: 1\nname: a\n"),
+            ("b.yaml", "port: 2\nhost: b\nname: b\n"),
+            ("c.yaml", "port: 3\n"),
+        ]);
+        let (_, report) = merge_hierarchy(dir.path(), dir.path(), &MergeOptions::default()).unwrap();
+        let entries: Vec<_> = report
+            .entries
+            .iter()
+            .map(|entry| {
+                let names: Vec<_> = entry.files.iter().map(|file| file.file_name().unwrap().to_str().unwrap()).collect();
+                (entry.kind, entry.key_path.as_deref().unwrap(), names)
+            })
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (ReportKind::Collision, "port", vec!["a.yaml", "b.yaml"]),
+                (ReportKind::Collision, "port", vec!["a.yaml", "c.yaml"]),
+            ]
+        );
+        let files = &report.entries[0].files;
+        assert_eq!(
+            report.entries[0].message,
+            format!(
+                "Key collision at depth {}: 'port', 'host', 'name' found in both {} and {}",
+                config_depth(&files[0]),
+                files[0].display(),
+                files[1].display()
+            )
+        );
+
+        let summary = report.summary();
+        assert_eq!(summary.kinds, [(ReportKind::Collision, 2)]);
+        assert_eq!((summary.count(Severity::Warning), summary.total()), (2, 2));
+        assert_eq!(summary.to_string(), "2 warnings (collision: 2)");
+        assert_eq!(MergeReport::default().summary().to_string(), "no entries");
+    }
+
+    #[test]
+    fn test_target_modes_with_the_base_as_target() {
+        let dir = crate::testing::fixture_tree(&[
+            ("config.yaml", "name: base\nport: 80\n"),
+            ("prod/config.yaml", "port: 8080\n"),
+            ("prod/eu/config.yaml", "region: eu\n"),
+            ("staging/config.yaml", "port: 8081\n"),
+        ]);
+        let base = dir.path();
+
+        let (config, report) = merge_hierarchy(base, base, &MergeOptions::default()).unwrap();
+        assert_eq!(config, serde_yaml::from_str::<ConfigValue>("name: base\nport: 80\n").unwrap());
+        assert_eq!(report.files.len(), 1);
+        assert_eq!(report.entries.len(), 1);
+        assert_eq!(report.entries[0].kind, ReportKind::IgnoredDescendants);
+        assert_eq!(report.entries[0].kind.severity(), Severity::Info);
+        assert!(report.entries[0].message.contains(": 3 file(s)"), "{}", report.entries[0].message);
+        let strict = MergeOptions {
+            strict: true,
+            ..MergeOptions::default()
+        };
+        assert!(merge_hierarchy(base, base, &strict).is_ok());
+        // Only a target that is the base is reported.
+        let (_, report) = merge_hierarchy(base, &base.join("prod"), &MergeOptions::default()).unwrap();
+        assert!(report.entries.is_empty());
+
+        let options = MergeOptions {
+            target_mode: TargetMode::AllDescendants,
+            ..MergeOptions::default()
+        };
+        let (config, report) = merge_hierarchy(base, base, &options).unwrap();
+        assert_eq!(config, serde_yaml::from_str::<ConfigValue>("name: base\nport: 8081\nregion: eu\n").unwrap());
+        assert_eq!(report.files.len(), 4);
+        // Sibling directories share a layer.
+        let kinds: Vec<_> = report.entries.iter().map(|entry| (entry.kind, entry.key_path.as_deref())).collect();
+        assert_eq!(kinds, [(ReportKind::Collision, Some("port"))]);
+        assert_eq!(find_layer_files(base, base, &options).unwrap().len(), 4);
+
+        // Below another target, the files of its subdirectories join it.
+        let (config, _) = merge_hierarchy(base, &base.join("prod"), &options).unwrap();
+        assert_eq!(config, serde_yaml::from_str::<ConfigValue>("name: base\nport: 8080\nregion: eu\n").unwrap());
+        let results = merge_many(base, &[base.to_path_buf()], &options).unwrap();
+        assert_eq!(results[base].0["port"], 8081);
+
+        let files: Vec<(PathBuf, String)> = [("config.yaml", "a: 1\n"), ("x/config.yaml", "a: 2\n")]
+            .iter()
+            .map(|(path, text)| (PathBuf::from(path), text.to_string()))
+            .collect();
+        let (config, report) = merge_yaml_strings(&files, Path::new(""), &MergeOptions::default()).unwrap();
+        assert_eq!((config["a"].as_i64(), report.entries[0].kind), (Some(1), ReportKind::IgnoredDescendants));
+        let (config, _) = merge_yaml_strings(&files, Path::new("."), &options).unwrap();
+        assert_eq!(config["a"], 2);
+    }
+
+    #[test]
+    fn test_directories_named_like_yaml_files() {
+        let dir = crate::testing::fixture_tree(&[
+            ("config.yaml", "name: base\n"),
+            ("foo.yaml/config.yaml", "name: foo\nport: 80\n"),
+        ]);
+        let base = dir.path();
+        let foo = base.join("foo.yaml");
+        let (config, report) = merge_hierarchy(base, &foo, &MergeOptions::default()).unwrap();
+        assert_eq!(config, serde_yaml::from_str::<ConfigValue>("name: foo\nport: 80\n").unwrap());
+        assert!(report.entries.is_empty());
+        assert_eq!(report.files.len(), 2);
+
+        // A link to the directory is walked like it.
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&foo, base.join("bar.yml")).unwrap();
+            let (config, report) = merge_hierarchy(base, &base.join("bar.yml"), &MergeOptions::default()).unwrap();
+            assert_eq!(config["name"], "foo");
+            assert!(report.entries.is_empty());
+        }
+
+        // A file that turned into a directory after discovery is skipped with a warning.
+        let error = anyhow::Error::from(ConfigError::io("Failed to read file", &foo)(std::io::Error::other("is a directory")));
+        let entry = skipped_read(&error, &MergeOptions::default()).unwrap();
+        assert_eq!(entry.kind, ReportKind::NotAFile);
+        assert_eq!(entry.files, std::slice::from_ref(&foo));
+        assert_eq!(entry.message, format!("Skipped {}: not a regular file", foo.display()));
+        let missing = anyhow::Error::from(ConfigError::io("Failed to read file", &base.join("gone.yaml"))(
+            std::io::Error::other("not found"),
+        ));
+        assert!(skipped_read(&missing, &MergeOptions::default()).is_none());
+
+        let (config, report) = merge_files(&[(base.join("config.yaml"), None), (foo.clone(), None)], None, &MergeOptions::default()).unwrap();
+        assert_eq!(config["name"], "base");
+        assert_eq!(report.entries.iter().map(|entry| entry.kind).collect::<Vec<_>>(), [ReportKind::NotAFile]);
+    }
+
+    #[test]
+    fn test_exempt_collisions_are_left_out_or_informational() {
+        let dir = crate::testing::fixture_tree(&[
+            ("eu.yaml", "schema_version: 2\nregion: eu\n"),
+            ("us.yaml", "schema_version: 2\nregion: us\n"),
+        ]);
+        let base = dir.path();
+        let mut options = MergeOptions {
+            collision_exempt_paths: vec!["schema_*".to_string()],
+            collision_policy: CollisionPolicy::Error,
+            ..MergeOptions::default()
+        };
+
+        let err = merge_hierarchy(base, base, &options).unwrap_err();
+        let ConfigError::Collision { entries } = err else {
+            panic!("Expected a collision error, got {err:?}");
+        };
+        let paths: Vec<_> = entries.iter().map(|entry| entry.key_path.as_deref()).collect();
+        assert_eq!(paths, [Some("region")]);
+
+        options.collision_policy = CollisionPolicy::Warn;
+        options.report_exempt_collisions = true;
+        let (_, report) = merge_hierarchy(base, base, &options).unwrap();
+        let kinds: Vec<_> = report.entries.iter().map(|entry| (entry.kind, entry.key_path.as_deref())).collect();
+        assert_eq!(
+            kinds,
+            [(ReportKind::ExemptCollision, Some("schema_version")), (ReportKind::Collision, Some("region"))]
+        );
+        assert_eq!(report.warnings().len(), 1);
+    }
+
+    #[test]
+    fn test_nested_exempt_paths_match_colliding_leaves() {
+        let dir = crate::testing::fixture_tree(&[
+            ("eu.yaml", "db: {version: 1, host: a, pool: {size: 4}}\ncache: {ttl: 5}\n"),
+            ("us.yaml", "db: {version: 2, pool: {size: 8}}\ncache: {size: 1}\n"),
+        ]);
+        let options = MergeOptions {
+            collision_exempt_paths: vec!["db.version".to_string()],
+            ..MergeOptions::default()
+        };
+        let (_, report) = merge_hierarchy(dir.path(), dir.path(), &options).unwrap();
+        let entries: Vec<_> = report.entries.iter().map(|entry| (entry.kind, entry.key_path.as_deref())).collect();
+        assert_eq!(entries, [(ReportKind::Collision, Some("db.pool.size"))]);
+        // Sections sharing no key still collide as a whole.
+        assert!(report.entries[0].message.contains("'db.pool.size', 'cache' found in both"), "{}", report.entries[0].message);
+
+        let options = MergeOptions {
+            collision_exempt_paths: vec!["db.*".to_string()],
+            ..options
+        };
+        let (_, report) = merge_hierarchy(dir.path(), dir.path(), &options).unwrap();
+        assert_eq!(report.entries.iter().map(|entry| entry.key_path.as_deref()).collect::<Vec<_>>(), [Some("cache")]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_paths_are_merged_and_reported() {
+        use std::ffi::OsString;
+        use std::os::unix::ffi::OsStringExt;
+
+        let dir = crate::testing::fixture_tree(&[("config.yaml", "port: 80\nname: base\n")]);
+        let team = dir.path().join(OsString::from_vec(b"t\xe9am".to_vec()));
+        std::fs::create_dir(&team).unwrap();
+        let file = team.join(OsString::from_vec(b"caf\xe9.yaml".to_vec()));
+        std::fs::write(&file, "port: 8080\n").unwrap();
+
+        let (config, report) = merge_hierarchy(dir.path(), &team, &MergeOptions::default()).unwrap();
+        assert_eq!(config["port"], 8080);
+        assert_eq!(config["name"], "base");
+        assert_eq!(report.files.len(), 2);
+        assert_eq!(report.files[1].path, file.canonicalize().unwrap());
+
+        let configs = parse_config_files(&[dir.path().join("config.yaml"), file.clone()]).unwrap();
+        assert_eq!(configs[&file], serde_yaml::from_str::<ConfigValue>("port: 8080").unwrap());
+        let (config, warnings) = merge_configs_by_depth(configs).unwrap();
+        assert_eq!(config["port"], 8080);
+        assert!(warnings.is_empty());
+    }
+
+    /// The paths of the [`ReportKind::Unreadable`] entries of `report`,
+    /// relative to `base`.
+    fn unreadable_paths(report: &MergeReport, base: &Path) -> Vec<PathBuf> {
+        let base = base.canonicalize().unwrap();
+        report
+            .entries
+            .iter()
+            .filter(|entry| entry.kind == ReportKind::Unreadable)
+            .map(|entry| entry.files[0].strip_prefix(&base).unwrap().to_path_buf())
+            .collect()
+    }
+
+    #[test]
+    fn test_skip_unreadable_reports_and_merges_the_rest() {
+        use crate::testing::FaultInjector;
+        use std::io::ErrorKind;
+
+        let dir = crate::testing::fixture_tree(&[
+            ("config.yaml", "name: base\nport: 80\n"),
+            ("svc/config.yaml", "port: 8080\n"),
+            ("svc/api/config.yaml", "debug: true\n"),
+            ("svc/api/private/config.yaml", "secret: 1\n"),
+            ("other/config.yaml", "other: 1\n"),
+        ]);
+        let target = dir.path().join("svc/api");
+        let source = || {
+            FaultInjector::default()
+                .fail(dir.path().join("svc/config.yaml"), ErrorKind::PermissionDenied)
+                .fail(dir.path().join("svc/api/private/config.yaml"), ErrorKind::PermissionDenied)
+                .fail(dir.path().join("other/config.yaml"), ErrorKind::PermissionDenied)
+        };
+        let merge = |options: &MergeOptions| merge_hierarchy_from(&source(), dir.path(), &target, options);
+        let merger = |options: &MergeOptions| merger::HierarchyMerger::with_source(dir.path(), options.clone(), source());
+        let yaml = |text: &str| serde_yaml::from_str::<ConfigValue>(text).unwrap();
+
+        let err = merge(&MergeOptions::default()).unwrap_err();
+        assert!(matches!(&err, ConfigError::Io { source, .. } if source.kind() == ErrorKind::PermissionDenied), "{err:?}");
+        let err = merger(&MergeOptions::default()).merge(&target).unwrap_err();
+        assert!(matches!(&err, ConfigError::Io { source, .. } if source.kind() == ErrorKind::PermissionDenied), "{err:?}");
+
+        let options = MergeOptions {
+            skip_unreadable: true,
+            ..MergeOptions::default()
+        };
+        let (config, report) = merge(&options).unwrap();
+        assert_eq!(config, yaml("name: base\nport: 80\ndebug: true\n"));
+        // Files off the way to the target are not read, let alone reported.
+        assert_eq!(unreadable_paths(&report, dir.path()), [Path::new("svc/config.yaml")]);
+        assert!(report.warnings()[0].contains("permission denied"), "{:?}", report.warnings());
+
+        // The caching merger agrees, from the memo too.
+        let mut lenient = merger(&options);
+        let (merged, merged_report) = lenient.merge_with_report(&target).unwrap();
+        assert_eq!((&*merged, &merged_report), (&config, &report));
+        let (cached, cached_report) = lenient.merge_with_report(&target).unwrap();
+        assert_eq!((cached, cached_report), (merged, merged_report));
+
+        let strict = MergeOptions {
+            strict: true,
+            ..options.clone()
+        };
+        assert!(matches!(merge(&strict), Err(ConfigError::Strict { .. })));
+        assert!(matches!(merger(&strict).merge(&target), Err(ConfigError::Strict { .. })));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_skip_unreadable_directories() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = crate::testing::fixture_tree(&[
+            ("config.yaml", "name: base\nport: 80\n"),
+            ("svc/api/config.yaml", "debug: true\n"),
+            ("svc/api/private/config.yaml", "secret: 1\n"),
+            ("other/config.yaml", "other: 1\n"),
+        ]);
+        let chmod = |path: &str, mode: u32| {
+            std::fs::set_permissions(dir.path().join(path), std::fs::Permissions::from_mode(mode)).unwrap()
+        };
+        chmod("svc/api/private", 0o000);
+        chmod("other", 0o000);
+        let target = dir.path().join("svc/api");
+        let options = MergeOptions {
+            skip_unreadable: true,
+            ..MergeOptions::default()
+        };
+
+        // Permissions do not keep root from listing.
+        if std::fs::read_dir(dir.path().join("other")).is_err() {
+            // Directories off the way to the target are not reported.
+            let (config, report) = merge_hierarchy(dir.path(), &target, &options).unwrap();
+            assert_eq!(config, serde_yaml::from_str::<ConfigValue>("name: base\nport: 80\ndebug: true\n").unwrap());
+            assert!(report.entries.is_empty(), "{:?}", report.entries);
+
+            // A directory on the way that cannot be listed leaves out its files.
+            chmod("svc/api", 0o311);
+            let (config, report) = merge_hierarchy(dir.path(), &target, &options).unwrap();
+            assert_eq!(config, serde_yaml::from_str::<ConfigValue>("name: base\nport: 80\n").unwrap());
+            assert_eq!(unreadable_paths(&report, dir.path()), [Path::new("svc/api")]);
+            assert!(merge_hierarchy(dir.path(), &target, &MergeOptions::default()).is_err());
+            chmod("svc/api", 0o755);
+        }
+        for path in ["svc/api/private", "other"] {
+            chmod(path, 0o755);
+        }
+    }
+
+    /// The library proper merges from disk without the Python extension.
+    #[cfg(not(feature = "python"))]
+    #[test]
+    fn test_merges_without_the_python_feature() {
+        let dir = crate::testing::fixture_tree(&[("config.yaml", "port: 80\n"), ("svc/config.yaml", "port: 8080\n")]);
+        let (config, report) = merge_hierarchy(dir.path(), &dir.path().join("svc"), &MergeOptions::default()).unwrap();
+        assert_eq!(config["port"], 8080);
+        assert_eq!(report.files.len(), 2);
+    }
+}
@@ -0,0 +1,175 @@
+//! Bounds on the size of a merged config, see
+//! [`MergeOptions::max_merged_nodes`].
+
+use std::path::Path;
+
+use crate::error::ConfigError;
+use crate::options::MergeOptions;
+use crate::report::MergeStats;
+use crate::ConfigValue;
+
+/// Estimated bytes of a scalar other than a string.
+const SCALAR_BYTES: usize = 8;
+
+/// The size of `value`, counted in one pass.
+pub(crate) fn measure(value: &ConfigValue) -> MergeStats {
+    let mut stats = MergeStats::default();
+    add(value, &mut stats);
+    stats
+}
+
+fn add(value: &ConfigValue, stats: &mut MergeStats) {
+    stats.nodes += 1;
+    match value {
+        ConfigValue::Mapping(map) => {
+            for (key, item) in map {
+                stats.bytes_estimate += match key {
+                    ConfigValue::String(key) => key.len(),
+                    _ => SCALAR_BYTES,
+                };
+                add(item, stats);
+            }
+        }
+        ConfigValue::Sequence(items) => items.iter().for_each(|item| add(item, stats)),
+        ConfigValue::Tagged(tagged) => {
+            stats.nodes -= 1;
+            stats.bytes_estimate += tagged.tag.to_string().len();
+            add(&tagged.value, stats);
+        }
+        ConfigValue::String(text) => stats.bytes_estimate += text.len(),
+        ConfigValue::Null => {}
+        ConfigValue::Bool(_) | ConfigValue::Number(_) => stats.bytes_estimate += SCALAR_BYTES,
+    }
+}
+
+/// Checks the merged config against the limits of the options as files are
+/// merged into it. A merge never outgrows the two configs merged, so the
+/// guard keeps an upper bound, adding the size of each file, and only counts
+/// the merged config itself once the bound passes a limit.
+pub(crate) struct SizeGuard {
+    bound: Option<MergeStats>,
+}
+
+impl SizeGuard {
+    /// A guard for a merge starting from `merged`, doing nothing when no
+    /// limit is set.
+    pub(crate) fn new(merged: &ConfigValue, options: &MergeOptions) -> Self {
+        let limited = options.max_merged_nodes.is_some() || options.max_merged_bytes_estimate.is_some();
+        Self {
+            bound: limited.then(|| measure(merged)),
+        }
+    }
+
+    /// Accounts for `config` being merged.
+    pub(crate) fn add(&mut self, config: &ConfigValue) {
+        if let Some(bound) = &mut self.bound {
+            let added = measure(config);
+            bound.nodes += added.nodes;
+            bound.bytes_estimate += added.bytes_estimate;
+        }
+    }
+
+    /// Fails when `merged`, into which the config read from `file` was just
+    /// merged, is over a limit.
+    pub(crate) fn check(&mut self, merged: &ConfigValue, file: &Path, options: &MergeOptions) -> Result<(), ConfigError> {
+        let Some(bound) = &mut self.bound else {
+            return Ok(());
+        };
+        if exceeded(bound, options).is_none() {
+            return Ok(());
+        }
+        *bound = measure(merged);
+        match exceeded(bound, options) {
+            Some((limit, max, actual)) => Err(ConfigError::LimitExceeded {
+                limit,
+                max,
+                actual,
+                file: file.to_path_buf(),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The name, value and actual size of the first limit `stats` is over.
+fn exceeded(stats: &MergeStats, options: &MergeOptions) -> Option<(&'static str, usize, usize)> {
+    let limits = [
+        ("max_merged_nodes", options.max_merged_nodes, stats.nodes),
+        ("max_merged_bytes_estimate", options.max_merged_bytes_estimate, stats.bytes_estimate),
+    ];
+    limits
+        .into_iter()
+        .find_map(|(limit, max, actual)| max.filter(|max| actual > *max).map(|max| (limit, max, actual)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixture_tree;
+
+    /// A hierarchy of `levels` directories, each adding `keys` keys.
+    fn generated(levels: usize, keys: usize) -> (tempfile::TempDir, std::path::PathBuf) {
+        let mut files = Vec::new();
+        let mut dir = String::new();
+        for level in 0..levels {
+            let text: String = (0..keys).map(|key| format!("key_{level}_{key}: value\n")).collect();
+            files.push((format!("{dir}config.yaml"), text));
+            dir.push_str(&format!("level{level}/"));
+        }
+        let files: Vec<(&str, &str)> = files.iter().map(|(path, text)| (path.as_str(), text.as_str())).collect();
+        let tree = fixture_tree(&files);
+        let target = tree.path().join(dir.trim_end_matches('/')).parent().unwrap().to_path_buf();
+        (tree, target)
+    }
+
+    #[test]
+    fn test_node_limit_names_the_file_that_passed_it() {
+        let (dir, target) = generated(4, 10);
+        let options = MergeOptions {
+            max_merged_nodes: Some(25),
+            ..MergeOptions::default()
+        };
+        let err = crate::merge_hierarchy(dir.path(), &target, &options).unwrap_err();
+        // The root mapping and 10 values per file: the third file makes 31.
+        let third = dir.path().canonicalize().unwrap().join("level0/level1/config.yaml");
+        assert!(
+            matches!(&err, ConfigError::LimitExceeded { limit: "max_merged_nodes", max: 25, actual: 31, file } if *file == third),
+            "{err:?}"
+        );
+        assert_eq!(
+            err.to_string(),
+            format!("Merged config exceeds max_merged_nodes (31 > 25) while merging {}", third.display())
+        );
+
+        let err = crate::merge_hierarchy_with_provenance(dir.path(), &target, &options).unwrap_err();
+        assert!(matches!(err, ConfigError::LimitExceeded { actual: 31, .. }), "{err:?}");
+        let mut merger = crate::HierarchyMerger::new(dir.path(), options.clone());
+        let err = merger.merge(&target).unwrap_err();
+        assert!(matches!(err, ConfigError::LimitExceeded { actual: 31, .. }), "{err:?}");
+    }
+
+    #[test]
+    fn test_stats_are_reported_without_limits() {
+        let (dir, target) = generated(4, 10);
+        let (config, report) = crate::merge_hierarchy(dir.path(), &target, &MergeOptions::default()).unwrap();
+        assert_eq!(report.stats, measure(&config));
+        let files: Vec<_> = report.files.iter().map(|file| (file.path.clone(), None)).collect();
+        let (_, merged) = crate::merge_files(&files, Some(dir.path()), &MergeOptions::default()).unwrap();
+        assert_eq!(merged.stats, report.stats);
+        // "key_L_K" and "value" per key.
+        assert_eq!(report.stats, MergeStats { nodes: 41, bytes_estimate: 40 * 12 });
+
+        let options = MergeOptions {
+            max_merged_bytes_estimate: Some(40 * 12 - 1),
+            ..MergeOptions::default()
+        };
+        let err = crate::merge_hierarchy(dir.path(), &target, &options).unwrap_err();
+        assert!(matches!(err, ConfigError::LimitExceeded { limit: "max_merged_bytes_estimate", .. }), "{err:?}");
+        let options = MergeOptions {
+            max_merged_nodes: Some(41),
+            max_merged_bytes_estimate: Some(40 * 12),
+            ..MergeOptions::default()
+        };
+        assert!(crate::merge_hierarchy(dir.path(), &target, &options).is_ok());
+    }
+}
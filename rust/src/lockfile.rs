@@ -0,0 +1,150 @@
+//! The merged config as a generated, reviewable YAML file, see
+//! [`write_lockfile`].
+
+use std::path::Path;
+
+use crate::error::ConfigError;
+use crate::keypath::{key_to_string, PathSegment};
+use crate::options::MergeOptions;
+use crate::provenance::{merge_hierarchy_with_provenance, Provenance};
+use crate::report::MergeReport;
+use crate::{merge_hierarchy, to_yaml, ConfigValue};
+
+const HEADER: &str = "# Generated by hierarchical-config-merging. Do not edit: change the sources and regenerate.\n";
+
+/// Merges `target_path` like [`crate::merge_hierarchy`] and writes the result
+/// to `out_path` as [`render_lockfile`] does, returning the report. With
+/// `provenance`, each top-level key notes the files its values come from.
+///
+/// The file only depends on the merged config and its sources: re-running
+/// with unchanged inputs writes the same bytes. Keep `out_path` out of the
+/// hierarchy, or it becomes one of the sources.
+pub fn write_lockfile(
+    base_dir: &Path,
+    target_path: &Path,
+    out_path: &Path,
+    options: &MergeOptions,
+    provenance: bool,
+) -> Result<MergeReport, ConfigError> {
+    let (config, report, provenance) = if provenance {
+        let (config, report, provenance) = merge_hierarchy_with_provenance(base_dir, target_path, options)?;
+        (config, report, Some(provenance))
+    } else {
+        let (config, report) = merge_hierarchy(base_dir, target_path, options)?;
+        (config, report, None)
+    };
+    let base_dir = options.input_path(base_dir)?;
+    let base_dir = base_dir.canonicalize().unwrap_or_else(|_| base_dir.into_owned());
+    let text = render_lockfile(&config, &report, provenance.as_ref(), &base_dir)?;
+    std::fs::write(out_path, text).map_err(ConfigError::io("Failed to write lockfile", out_path))?;
+    Ok(report)
+}
+
+/// `config` as a YAML document headed by comments listing the files of
+/// `report` with their SHA-256, paths relative to `base_dir` where possible.
+///
+/// With a `provenance`, the line of each top-level key ends with a comment
+/// naming the files its values come from, in merge order. Comments aside, the
+/// document reads back as `config`.
+pub fn render_lockfile(
+    config: &ConfigValue,
+    report: &MergeReport,
+    provenance: Option<&Provenance>,
+    base_dir: &Path,
+) -> Result<String, ConfigError> {
+    let display = |path: &Path| path.strip_prefix(base_dir).unwrap_or(path).display().to_string();
+
+    let mut text = String::from(HEADER);
+    text.push_str("# Sources, in merge order:\n");
+    for file in &report.files {
+        text.push_str(&format!("#   {} sha256:{}\n", display(&file.path), file.sha256));
+    }
+
+    let (ConfigValue::Mapping(map), Some(provenance)) = (config, provenance) else {
+        text.push_str(&to_yaml(config)?);
+        return Ok(text);
+    };
+    if map.is_empty() {
+        text.push_str(&to_yaml(config)?);
+        return Ok(text);
+    }
+    for (key, value) in map {
+        let mut entry = serde_yaml::Mapping::new();
+        entry.insert(key.clone(), value.clone());
+        let yaml = to_yaml(&ConfigValue::Mapping(entry))?;
+        let (first, rest) = yaml.split_once('\n').unwrap_or((&yaml, ""));
+        text.push_str(first);
+
+        let sources = sources_of(provenance, report, &PathSegment::Key(key_to_string(key)));
+        if !sources.is_empty() {
+            let sources: Vec<String> = sources.iter().map(|source| display(source)).collect();
+            text.push_str(&format!("  # from {}", sources.join(", ")));
+        }
+        text.push('\n');
+        text.push_str(rest);
+    }
+    Ok(text)
+}
+
+/// The distinct files defining values below the top-level `key`, in merge
+/// order; sources that are not merged files, such as the defaults, first.
+fn sources_of<'a>(provenance: &'a Provenance, report: &MergeReport, key: &PathSegment) -> Vec<&'a Path> {
+    let mut sources: Vec<&Path> = Vec::new();
+    for (segments, source) in provenance.leaves() {
+        if segments.first() == Some(key) && !sources.contains(&source) {
+            sources.push(source);
+        }
+    }
+    sources.sort_by_key(|source| report.files.iter().position(|file| file.path == *source));
+    sources
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixture_tree;
+    use std::fs;
+
+    fn hierarchy() -> tempfile::TempDir {
+        fixture_tree(&[
+            ("config.yaml", "server:\n  host: base\n  port: 80\nname: api\nnotes: |\n  line one\n  line two\n"),
+            ("prod/config.yaml", "server:\n  port: 8080\ntags: [a, b]\nempty: {}\n"),
+        ])
+    }
+
+    #[test]
+    fn test_lockfile_matches_golden() {
+        let dir = hierarchy();
+        let out_dir = tempfile::tempdir().unwrap();
+        let out = out_dir.path().join("effective.yaml");
+        write_lockfile(dir.path(), &dir.path().join("prod"), &out, &MergeOptions::default(), true).unwrap();
+        let text = fs::read_to_string(&out).unwrap();
+        assert_eq!(text, include_str!("../../tests/golden/lockfile.yaml"));
+    }
+
+    #[test]
+    fn test_reruns_write_the_same_bytes() {
+        let dir = hierarchy();
+        let out_dir = tempfile::tempdir().unwrap();
+        let out = out_dir.path().join("effective.yaml");
+        let options = MergeOptions::default();
+        for provenance in [true, false] {
+            let report = write_lockfile(dir.path(), &dir.path().join("prod"), &out, &options, provenance).unwrap();
+            let first = fs::read(&out).unwrap();
+            write_lockfile(dir.path(), &dir.path().join("prod"), &out, &options, provenance).unwrap();
+            assert_eq!(fs::read(&out).unwrap(), first);
+
+            // Comments aside, the lockfile is the merged config.
+            let (config, _) = merge_hierarchy(dir.path(), &dir.path().join("prod"), &options).unwrap();
+            let text = String::from_utf8(first).unwrap();
+            assert_eq!(serde_yaml::from_str::<ConfigValue>(&text).unwrap(), config);
+            assert_eq!(text.contains("# from"), provenance);
+            assert!(text.contains(&report.files[1].sha256));
+        }
+
+        fs::write(dir.path().join("prod/config.yaml"), "server:\n  port: 8081\n").unwrap();
+        let before = fs::read(&out).unwrap();
+        write_lockfile(dir.path(), &dir.path().join("prod"), &out, &options, false).unwrap();
+        assert_ne!(fs::read(&out).unwrap(), before);
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+
+use crate::cancel::arm;
+use crate::error::ConfigError;
+use crate::metadata::embed_metadata;
+use crate::options::MergeOptions;
+use crate::paths::resolve_target_within_base;
+use crate::report::{ContributingFile, MergeReport};
+use crate::compose::load_config_file;
+use crate::source::{ConfigSource, Fingerprint};
+use crate::{
+    base_layer_depth, config_depth, ignored_descendants, merge_layers_with_report, select_target_files, skip_deep_files, trace, ConfigValue,
+    LayerFile,
+};
+
+/// Serves the contents of an in-memory hierarchy.
+struct MemorySource<'a> {
+    files: HashMap<&'a Path, &'a str>,
+}
+
+impl ConfigSource for MemorySource<'_> {
+    fn read_to_string(&self, path: &Path) -> Result<String> {
+        self.files
+            .get(path)
+            .map(|content| content.to_string())
+            .with_context(|| format!("No such file: {}", path.display()))
+    }
+
+    fn fingerprint(&self, path: &Path) -> Result<Fingerprint> {
+        Ok(Fingerprint {
+            modified: None,
+            len: self.read_to_string(path)?.len() as u64,
+        })
+    }
+}
+
+/// Merges a hierarchy held in memory, without touching the filesystem.
+///
+/// `files` pairs paths relative to the base directory, such as
+/// `prod/config.yaml`, with their YAML text; `target_path` is relative to the
+/// base directory too. Files are selected, layered and checked for collisions
+/// like the files of [`crate::merge_hierarchy`].
+pub fn merge_yaml_strings(
+    files: &[(PathBuf, String)],
+    target_path: &Path,
+    options: &MergeOptions,
+) -> Result<(ConfigValue, MergeReport), ConfigError> {
+    let options = &arm(options);
+    let relative = |path: &Path| resolve_target_within_base(Path::new(""), path).map(|target| target.relative);
+    let target = relative(target_path)?;
+    let mut paths = Vec::with_capacity(files.len());
+    for (path, _) in files {
+        paths.push(relative(path)?);
+    }
+    let source = MemorySource {
+        files: paths
+            .iter()
+            .zip(files)
+            .map(|(path, (_, content))| (path.as_path(), content.as_str()))
+            .collect(),
+    };
+
+    let candidates: Vec<PathBuf> = paths.iter().filter(|path| options.is_config_file(path)).cloned().collect();
+    let mut selected: Vec<LayerFile> = select_target_files(Path::new(""), &target, &candidates, options)
+        .into_iter()
+        .filter_map(|path| {
+            let rank = options.profile_rank(&path)?;
+            Some(LayerFile { depth: config_depth(&path) as i64, rank, path })
+        })
+        .collect();
+    selected.sort();
+    let base_depth = base_layer_depth(Path::new(""));
+    let mut skipped = skip_deep_files(&mut selected, base_depth, options);
+    if selected.is_empty() && skipped.is_empty() {
+        let report = options.empty_hierarchy_report(Path::new(""), &target)?;
+        options.check_report(&report)?;
+        return Ok((embed_metadata(options.initial_config(), None, None, &report, options), report));
+    }
+    skipped.extend(ignored_descendants(Path::new(""), &target, &candidates, options));
+
+    let _span = trace::parse_span(selected.len());
+    let mut configs = Vec::with_capacity(selected.len());
+    let mut contributing = Vec::with_capacity(selected.len());
+    for file in selected {
+        let (config, sha256) = load_config_file(&source, &file.path, options, &mut skipped)?;
+        contributing.push(ContributingFile {
+            path: file.path.clone(),
+            depth: file.depth - base_depth,
+            sha256,
+        });
+        configs.push((file, config));
+    }
+
+    let (merged_config, mut report) = {
+        let _span = trace::merge_span(configs.len());
+        let layers = configs.iter().map(|(file, config)| (file.layer(), file.path.as_path(), config));
+        merge_layers_with_report(layers, options)?
+    };
+    report.files = contributing;
+    report.entries.splice(0..0, skipped);
+    options.check_report(&report)?;
+    let merged_config = embed_metadata(merged_config, None, None, &report, options);
+    Ok((merged_config, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::ReportKind;
+
+    fn files(pairs: &[(&str, &str)]) -> Vec<(PathBuf, String)> {
+        pairs
+            .iter()
+            .map(|(path, content)| (PathBuf::from(path), content.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_merges_like_a_hierarchy_on_disk() {
+        let files = files(&[
+            ("config.yaml", "env: base\nreplicas: 1\n"),
+            ("./prod/config.yaml", "env: prod\n"),
+            ("prod/eu/config.yaml", "region: eu\n"),
+            ("dev/config.yaml", "env: dev\n"),
+            ("prod/notes.txt", "not: merged\n"),
+        ]);
+
+        let (config, report) = merge_yaml_strings(&files, Path::new("prod/eu"), &MergeOptions::default()).unwrap();
+        let expected: ConfigValue = serde_yaml::from_str("env: prod\nreplicas: 1\nregion: eu\n").unwrap();
+        assert_eq!(config, expected);
+        let depths: Vec<_> = report.files.iter().map(|file| (file.path.to_str().unwrap(), file.depth)).collect();
+        assert_eq!(depths, [("config.yaml", 0), ("prod/config.yaml", 1), ("prod/eu/config.yaml", 2)]);
+        assert!(report.entries.is_empty());
+    }
+
+    #[test]
+    fn test_collisions_and_bad_paths() {
+        let files = files(&[("a.yaml", "key: a\n"), ("b.yaml", "key: b\n")]);
+        let (config, report) = merge_yaml_strings(&files, Path::new(""), &MergeOptions::default()).unwrap();
+        assert_eq!(config["key"], ConfigValue::from("b"));
+        assert_eq!(report.entries[0].kind, ReportKind::Collision);
+
+        for target in ["../elsewhere", "a/../../elsewhere", "/etc"] {
+            let err = merge_yaml_strings(&files, Path::new(target), &MergeOptions::default()).unwrap_err();
+            assert!(matches!(err, ConfigError::OutsideBase { .. }), "{target}");
+        }
+        let (dotted, _) = merge_yaml_strings(&files, Path::new("a/../"), &MergeOptions::default()).unwrap();
+        assert_eq!(dotted, config);
+
+        let bad = self::files(&[("config.yaml", "key: [unclosed\n")]);
+        let err = merge_yaml_strings(&bad, Path::new("."), &MergeOptions::default()).unwrap_err();
+        assert!(matches!(err, ConfigError::Parse { line: Some(_), .. }));
+    }
+}
@@ -0,0 +1,66 @@
+//! RFC 7386 JSON Merge Patch, used by
+//! [`MergeMode::JsonMergePatch`](crate::MergeMode::JsonMergePatch).
+
+use crate::mergeable::merge_patch_values;
+use crate::ConfigValue;
+
+/// Applies the JSON Merge Patch `patch` to `base`: mappings merge key by key,
+/// a `null` removes the key, and anything else, sequences included, replaces
+/// the value. Other merge options do not apply.
+///
+/// ```
+/// # use hierarchical_config_merging::apply_merge_patch;
+/// let base = serde_yaml::from_str("{title: Goodbye!, tags: [example, sample]}").unwrap();
+/// let patch = serde_yaml::from_str("{title: Hello!, tags: [example]}").unwrap();
+/// assert_eq!(apply_merge_patch(&base, &patch), serde_yaml::from_str::<serde_yaml::Value>("{title: Hello!, tags: [example]}").unwrap());
+/// ```
+pub fn apply_merge_patch(base: &ConfigValue, patch: &ConfigValue) -> ConfigValue {
+    merge_patch_values(base, patch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json(text: &str) -> ConfigValue {
+        serde_json::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn test_rfc_7386_appendix_a() {
+        let vectors = [
+            (r#"{"a":"b"}"#, r#"{"a":"c"}"#, r#"{"a":"c"}"#),
+            (r#"{"a":"b"}"#, r#"{"b":"c"}"#, r#"{"a":"b","b":"c"}"#),
+            (r#"{"a":"b"}"#, r#"{"a":null}"#, r#"{}"#),
+            (r#"{"a":"b","b":"c"}"#, r#"{"a":null}"#, r#"{"b":"c"}"#),
+            (r#"{"a":["b"]}"#, r#"{"a":"c"}"#, r#"{"a":"c"}"#),
+            (r#"{"a":"c"}"#, r#"{"a":["b"]}"#, r#"{"a":["b"]}"#),
+            (r#"{"a":{"b":"c"}}"#, r#"{"a":{"b":"d","c":null}}"#, r#"{"a":{"b":"d"}}"#),
+            (r#"{"a":[{"b":"c"}]}"#, r#"{"a":[1]}"#, r#"{"a":[1]}"#),
+            (r#"["a","b"]"#, r#"["c","d"]"#, r#"["c","d"]"#),
+            (r#"{"a":"b"}"#, r#"["c"]"#, r#"["c"]"#),
+            (r#"{"a":"foo"}"#, r#"null"#, r#"null"#),
+            (r#"{"a":"foo"}"#, r#""bar""#, r#""bar""#),
+            (r#"{"e":null}"#, r#"{"a":1}"#, r#"{"e":null,"a":1}"#),
+            (r#"[1,2]"#, r#"{"a":"b","c":null}"#, r#"{"a":"b"}"#),
+            (r#"{}"#, r#"{"a":{"bb":{"ccc":null}}}"#, r#"{"a":{"bb":{}}}"#),
+        ];
+        for (base, patch, expected) in vectors {
+            assert_eq!(apply_merge_patch(&json(base), &json(patch)), json(expected), "{base} + {patch}");
+        }
+    }
+
+    #[test]
+    fn test_rfc_7386_example() {
+        let base = json(
+            r#"{"title":"Goodbye!","author":{"givenName":"John","familyName":"Doe"},"tags":["example","sample"],"content":"This will be unchanged"}"#,
+        );
+        let patch = json(r#"{"title":"Hello!","phoneNumber":"+01-123-456-7890","author":{"familyName":null},"tags":["example"]}"#);
+        assert_eq!(
+            apply_merge_patch(&base, &patch),
+            json(
+                r#"{"title":"Hello!","author":{"givenName":"John"},"tags":["example"],"content":"This will be unchanged","phoneNumber":"+01-123-456-7890"}"#
+            )
+        );
+    }
+}
@@ -0,0 +1,349 @@
+//! The merge semantics of [`crate::deep_merge_with`] for any value tree, such
+//! as `serde_json::Value` with the `json` feature.
+
+use anyhow::Result;
+
+use crate::error::ConfigError;
+use crate::keypath::{format_key_path, key_to_string, PathSegment};
+use crate::options::{MergeMode, MergeOptions};
+use crate::ConfigValue;
+
+/// What a value is, as far as merging is concerned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ValueKind {
+    Null,
+    Mapping,
+    Sequence,
+    /// Any other value, tagged ones included: it replaces what it overrides.
+    Scalar,
+}
+
+/// A value tree that can be merged with [`merge_values`].
+pub trait Mergeable: Clone + PartialEq {
+    type Key: Clone;
+    type Map: Clone;
+
+    fn kind(&self) -> ValueKind;
+
+    /// The entries, when the value is a mapping.
+    fn as_map(&self) -> Option<&Self::Map>;
+    /// The items, when the value is a sequence.
+    fn as_sequence(&self) -> Option<&[Self]>;
+    fn from_map(map: Self::Map) -> Self;
+    fn from_sequence(items: Vec<Self>) -> Self;
+    fn null() -> Self;
+
+    fn new_map() -> Self::Map;
+    fn map_iter<'a>(map: &'a Self::Map) -> impl Iterator<Item = (&'a Self::Key, &'a Self)>
+    where
+        Self: 'a;
+    fn map_get<'a>(map: &'a Self::Map, key: &Self::Key) -> Option<&'a Self>;
+    fn map_insert(map: &mut Self::Map, key: Self::Key, value: Self);
+    fn map_remove(map: &mut Self::Map, key: &Self::Key);
+    /// `key` as a key path segment.
+    fn key_name(key: &Self::Key) -> String;
+}
+
+impl Mergeable for ConfigValue {
+    type Key = ConfigValue;
+    type Map = serde_yaml::Mapping;
+
+    fn kind(&self) -> ValueKind {
+        match self {
+            ConfigValue::Null => ValueKind::Null,
+            ConfigValue::Mapping(_) => ValueKind::Mapping,
+            ConfigValue::Sequence(_) => ValueKind::Sequence,
+            _ => ValueKind::Scalar,
+        }
+    }
+
+    fn as_map(&self) -> Option<&Self::Map> {
+        self.as_mapping()
+    }
+
+    fn as_sequence(&self) -> Option<&[Self]> {
+        ConfigValue::as_sequence(self).map(Vec::as_slice)
+    }
+
+    fn from_map(map: Self::Map) -> Self {
+        ConfigValue::Mapping(map)
+    }
+
+    fn from_sequence(items: Vec<Self>) -> Self {
+        ConfigValue::Sequence(items)
+    }
+
+    fn null() -> Self {
+        ConfigValue::Null
+    }
+
+    fn new_map() -> Self::Map {
+        serde_yaml::Mapping::new()
+    }
+
+    fn map_iter<'a>(map: &'a Self::Map) -> impl Iterator<Item = (&'a Self::Key, &'a Self)>
+    where
+        Self: 'a,
+    {
+        map.iter()
+    }
+
+    fn map_get<'a>(map: &'a Self::Map, key: &Self::Key) -> Option<&'a Self> {
+        map.get(key)
+    }
+
+    fn map_insert(map: &mut Self::Map, key: Self::Key, value: Self) {
+        map.insert(key, value);
+    }
+
+    fn map_remove(map: &mut Self::Map, key: &Self::Key) {
+        map.remove(key);
+    }
+
+    fn key_name(key: &Self::Key) -> String {
+        key_to_string(key)
+    }
+}
+
+#[cfg(feature = "json")]
+impl Mergeable for serde_json::Value {
+    type Key = String;
+    type Map = serde_json::Map<String, serde_json::Value>;
+
+    fn kind(&self) -> ValueKind {
+        match self {
+            serde_json::Value::Null => ValueKind::Null,
+            serde_json::Value::Object(_) => ValueKind::Mapping,
+            serde_json::Value::Array(_) => ValueKind::Sequence,
+            _ => ValueKind::Scalar,
+        }
+    }
+
+    fn as_map(&self) -> Option<&Self::Map> {
+        self.as_object()
+    }
+
+    fn as_sequence(&self) -> Option<&[Self]> {
+        self.as_array().map(Vec::as_slice)
+    }
+
+    fn from_map(map: Self::Map) -> Self {
+        serde_json::Value::Object(map)
+    }
+
+    fn from_sequence(items: Vec<Self>) -> Self {
+        serde_json::Value::Array(items)
+    }
+
+    fn null() -> Self {
+        serde_json::Value::Null
+    }
+
+    fn new_map() -> Self::Map {
+        serde_json::Map::new()
+    }
+
+    fn map_iter<'a>(map: &'a Self::Map) -> impl Iterator<Item = (&'a Self::Key, &'a Self)>
+    where
+        Self: 'a,
+    {
+        map.iter()
+    }
+
+    fn map_get<'a>(map: &'a Self::Map, key: &Self::Key) -> Option<&'a Self> {
+        map.get(key)
+    }
+
+    fn map_insert(map: &mut Self::Map, key: Self::Key, value: Self) {
+        map.insert(key, value);
+    }
+
+    fn map_remove(map: &mut Self::Map, key: &Self::Key) {
+        map.remove(key);
+    }
+
+    fn key_name(key: &Self::Key) -> String {
+        key.clone()
+    }
+}
+
+/// Merges `r#override` into `base` like [`crate::deep_merge_with`], honouring
+/// `sequence_strategy`, `null_deletes` and the deep and JSON Merge Patch
+/// modes. The strategic merge patch mode only exists for [`ConfigValue`];
+/// other values merge deeply under it.
+pub fn merge_values<V: Mergeable>(base: &V, r#override: &V, options: &MergeOptions) -> V {
+    if options.mode == MergeMode::JsonMergePatch {
+        return merge_patch_values(base, r#override);
+    }
+    match (base.as_map(), r#override.as_map()) {
+        (Some(base_map), Some(override_map)) => {
+            let mut result = base_map.clone();
+            for (key, value) in V::map_iter(override_map) {
+                if options.null_deletes && value.kind() == ValueKind::Null {
+                    V::map_remove(&mut result, key);
+                } else if let Some(base_value) = V::map_get(&result, key) {
+                    // Recursively merge if both are mappings
+                    let merged = merge_values(base_value, value, options);
+                    V::map_insert(&mut result, key.clone(), merged);
+                } else if options.null_deletes && value.kind() == ValueKind::Mapping {
+                    // Drop the nulls nested in the new value too
+                    let merged = merge_values(&V::from_map(V::new_map()), value, options);
+                    V::map_insert(&mut result, key.clone(), merged);
+                } else {
+                    // Insert new value
+                    V::map_insert(&mut result, key.clone(), value.clone());
+                }
+            }
+            V::from_map(result)
+        }
+        _ => match (base.as_sequence(), r#override.as_sequence()) {
+            (Some(base_items), Some(override_items)) => {
+                V::from_sequence(options.sequence_strategy.combine(base_items, override_items))
+            }
+            _ => r#override.clone(), // Override with new value
+        },
+    }
+}
+
+/// [`crate::apply_merge_patch`] for any [`Mergeable`] value.
+pub fn merge_patch_values<V: Mergeable>(base: &V, patch: &V) -> V {
+    let Some(patch_map) = patch.as_map() else {
+        return patch.clone();
+    };
+    let mut result = base.as_map().cloned().unwrap_or_else(V::new_map);
+    for (key, value) in V::map_iter(patch_map) {
+        if value.kind() == ValueKind::Null {
+            V::map_remove(&mut result, key);
+        } else {
+            let merged = merge_patch_values(V::map_get(&result, key).unwrap_or(&V::null()), value);
+            V::map_insert(&mut result, key.clone(), merged);
+        }
+    }
+    V::from_map(result)
+}
+
+/// Decides the value of a key defined on both sides of a merge, see
+/// [`crate::ConflictResolver`].
+pub type ValueResolver<'a, V> = dyn FnMut(&[PathSegment], &V, &V) -> Result<V> + 'a;
+
+/// [`merge_values`] asking `resolve` for every conflicting value, like
+/// [`crate::deep_merge_resolving`]. A failing resolver aborts the merge with
+/// [`ConfigError::Conflict`].
+pub fn merge_values_resolving<V: Mergeable>(
+    base: &V,
+    r#override: &V,
+    options: &MergeOptions,
+    resolve: &mut ValueResolver<V>,
+) -> Result<V, ConfigError> {
+    Ok(merge_into(base, r#override, options, &mut Vec::new(), resolve)?)
+}
+
+fn merge_into<V: Mergeable>(
+    base: &V,
+    r#override: &V,
+    options: &MergeOptions,
+    path: &mut Vec<PathSegment>,
+    resolve: &mut ValueResolver<V>,
+) -> Result<V> {
+    let (Some(base_map), Some(override_map)) = (base.as_map(), r#override.as_map()) else {
+        return resolve(path, base, r#override).map_err(|source| {
+            ConfigError::Conflict {
+                path: format_key_path(path),
+                source,
+            }
+            .into()
+        });
+    };
+
+    let mut result = base_map.clone();
+    for (key, value) in V::map_iter(override_map) {
+        if options.null_deletes && value.kind() == ValueKind::Null {
+            V::map_remove(&mut result, key);
+        } else if let Some(base_value) = V::map_get(&result, key) {
+            path.push(PathSegment::Key(V::key_name(key)));
+            let merged = merge_into(base_value, value, options, path, resolve)?;
+            path.pop();
+            V::map_insert(&mut result, key.clone(), merged);
+        } else if options.null_deletes && value.kind() == ValueKind::Mapping {
+            let merged = merge_values(&V::from_map(V::new_map()), value, options);
+            V::map_insert(&mut result, key.clone(), merged);
+        } else {
+            V::map_insert(&mut result, key.clone(), value.clone());
+        }
+    }
+    Ok(V::from_map(result))
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use super::*;
+    use crate::options::SequenceStrategy;
+
+    /// The same document as YAML and JSON values.
+    fn both(json: &str) -> (ConfigValue, serde_json::Value) {
+        (serde_yaml::from_str(json).unwrap(), serde_json::from_str(json).unwrap())
+    }
+
+    fn as_json(value: &ConfigValue) -> serde_json::Value {
+        serde_json::to_value(value).unwrap()
+    }
+
+    const BASE: &str = r#"{"server": {"port": 80, "hosts": ["a", "b"], "tls": null}, "name": "api", "tags": [1, 2]}"#;
+    const OVERRIDE: &str = r#"{"server": {"port": 8080, "hosts": ["b", "c"], "extra": {"x": null, "y": 1}}, "tags": null, "name": {"first": "api"}}"#;
+
+    #[test]
+    fn test_json_merges_like_yaml() {
+        let (yaml_base, json_base) = both(BASE);
+        let (yaml_override, json_override) = both(OVERRIDE);
+        let mut options_list = Vec::new();
+        for strategy in [SequenceStrategy::Replace, SequenceStrategy::Append, SequenceStrategy::Prepend, SequenceStrategy::Union] {
+            for null_deletes in [false, true] {
+                options_list.push(MergeOptions {
+                    sequence_strategy: strategy,
+                    null_deletes,
+                    ..MergeOptions::default()
+                });
+            }
+        }
+        options_list.push(MergeOptions {
+            mode: MergeMode::JsonMergePatch,
+            ..MergeOptions::default()
+        });
+
+        for options in &options_list {
+            let from_yaml = crate::deep_merge_with(&yaml_base, &yaml_override, options);
+            let from_json = merge_values(&json_base, &json_override, options);
+            assert_eq!(as_json(&from_yaml), from_json, "{options:?}");
+        }
+    }
+
+    #[test]
+    fn test_resolvers_see_the_same_conflicts() {
+        let (yaml_base, json_base) = both(BASE);
+        let (yaml_override, json_override) = both(OVERRIDE);
+        let options = MergeOptions::default();
+
+        let mut yaml_paths = Vec::new();
+        let from_yaml = crate::deep_merge_resolving(&yaml_base, &yaml_override, &options, &mut |path, base, _| {
+            yaml_paths.push(format_key_path(path));
+            Ok(base.clone())
+        })
+        .unwrap();
+        let mut json_paths = Vec::new();
+        let from_json = merge_values_resolving(&json_base, &json_override, &options, &mut |path, base, _| {
+            json_paths.push(format_key_path(path));
+            Ok(base.clone())
+        })
+        .unwrap();
+
+        // JSON objects iterate in key order.
+        yaml_paths.sort();
+        json_paths.sort();
+        assert_eq!(yaml_paths, json_paths);
+        assert_eq!(as_json(&from_yaml), from_json);
+
+        let err = merge_values_resolving(&json_base, &json_override, &options, &mut |_, _, _| anyhow::bail!("no"))
+            .unwrap_err();
+        assert!(matches!(err, ConfigError::Conflict { path, .. } if path == "name"));
+    }
+}
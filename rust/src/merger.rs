@@ -0,0 +1,686 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use anyhow::Result;
+
+use crate::cache::ParseCache;
+use crate::cancel::arm;
+use crate::error::ConfigError;
+use crate::compose::load_config_file;
+use crate::deprecation::apply_layer_deprecations;
+use crate::limits::{measure, SizeGuard};
+use crate::metadata::{check_reserved_key, embed_metadata};
+use crate::priority::{sort_by_priority, strip_layer_priorities};
+use crate::options::{CollisionPolicy, MergeOptions, TypeCheck};
+use crate::report::{ContributingFile, MergeReport, ReportEntry};
+use crate::source::{ConfigSource, Fingerprint, FsSource};
+#[cfg(feature = "schema")]
+use crate::schema;
+use crate::trace;
+use crate::types::{self, TypeRules, TYPES_FILE};
+use crate::{
+    base_layer_depth, canonicalize_hierarchy, check_merge_files, collect_depth_collisions, collect_lock_violations,
+    discover_extra_roots, discover_layer_files, discover_yaml_files, finish_merge, finishes, ignored_descendants,
+    layer_files, merge_layer, skip_deep_files, skipped_read, unreadable_in_hierarchy, ConfigValue, LayerFile,
+};
+
+/// Files contributing to a merge, in merge order, with the fingerprint they had
+/// when they were read.
+type FileSet = Vec<(LayerFile, Fingerprint)>;
+
+/// Result of merging the first k depth layers of a hierarchy.
+#[derive(Clone)]
+struct Prefix {
+    config: Arc<ConfigValue>,
+    entries: Vec<ReportEntry>,
+    files: Vec<ContributingFile>,
+}
+
+/// A parsed file and the hash of the contents it was parsed from.
+struct ParsedEntry {
+    fingerprint: Fingerprint,
+    config: Arc<ConfigValue>,
+    sha256: String,
+    /// Entries of loading the file, such as sections left out.
+    entries: Vec<ReportEntry>,
+}
+
+struct MemoEntry {
+    files: FileSet,
+    /// Fingerprint of the types file checked against, if any.
+    types: Option<Fingerprint>,
+    /// Schema files checked against, see [`MergeOptions::directory_schemas`].
+    schemas: Vec<(PathBuf, Fingerprint)>,
+    config: Arc<ConfigValue>,
+    report: MergeReport,
+}
+
+/// Merges many targets under one base directory, reusing work between calls.
+///
+/// Three caches are kept:
+/// - parsed files, keyed by path and validated against the file fingerprint;
+/// - merge results of the first k depth layers, shared by every target whose
+///   hierarchy starts with the same (unchanged) files;
+/// - the final merge result per target.
+///
+/// A cached result is only returned while every contributing file still has
+/// the fingerprint it was merged with, so edits are picked up without calling
+/// [`HierarchyMerger::invalidate`]. Discovery runs on every call so that added
+/// or removed files are noticed too.
+///
+/// When a file changes, only that file is parsed again, and only its layer
+/// and the deeper ones are merged again, on top of the cached merge of the
+/// layers above; the result is the same as that of a merge from scratch. The
+/// layer merges the old contents took part in are dropped.
+///
+/// Files are parsed again when they change. Mergers given one
+/// [`ParseCache`] also parse contents read by another only once.
+///
+/// Results are those of [`crate::merge_hierarchy`], types files and directory
+/// schemas checked alike, except that remote layers are never fetched: a
+/// merger given [`MergeOptions::remote_layers`] fails every merge.
+pub struct HierarchyMerger {
+    base_dir: PathBuf,
+    options: MergeOptions,
+    source: Box<dyn ConfigSource>,
+    parse_cache: Option<Arc<ParseCache>>,
+    parsed: HashMap<PathBuf, ParsedEntry>,
+    prefixes: HashMap<FileSet, Prefix>,
+    merged: HashMap<PathBuf, MemoEntry>,
+    /// Rules of the types file, with the fingerprint it was read with.
+    types: Option<(Fingerprint, Arc<TypeRules>)>,
+}
+
+impl HierarchyMerger {
+    pub fn new(base_dir: impl Into<PathBuf>, options: MergeOptions) -> Self {
+        Self::with_source(base_dir, options, FsSource)
+    }
+
+    pub fn with_source(
+        base_dir: impl Into<PathBuf>,
+        options: MergeOptions,
+        source: impl ConfigSource + 'static,
+    ) -> Self {
+        Self {
+            base_dir: base_dir.into(),
+            options,
+            source: Box::new(source),
+            parse_cache: None,
+            parsed: HashMap::new(),
+            prefixes: HashMap::new(),
+            merged: HashMap::new(),
+            types: None,
+        }
+    }
+
+    /// Parses files through `cache`, which other mergers may share.
+    pub fn with_parse_cache(mut self, cache: Arc<ParseCache>) -> Self {
+        self.parse_cache = Some(cache);
+        self
+    }
+
+    pub fn parse_cache(&self) -> Option<&Arc<ParseCache>> {
+        self.parse_cache.as_ref()
+    }
+
+    pub fn base_dir(&self) -> &Path {
+        &self.base_dir
+    }
+
+    pub fn options(&self) -> &MergeOptions {
+        &self.options
+    }
+
+    /// Returns the merged config for `target_path`, recomputing only what
+    /// changed since the last call.
+    pub fn merge(&mut self, target_path: &Path) -> Result<Arc<ConfigValue>, ConfigError> {
+        Ok(self.merge_with_report(target_path)?.0)
+    }
+
+    /// Like [`HierarchyMerger::merge`], also returning the merge report.
+    pub fn merge_with_report(&mut self, target_path: &Path) -> Result<(Arc<ConfigValue>, MergeReport), ConfigError> {
+        if self.options.timeout.is_none() {
+            return self.merge_armed(target_path);
+        }
+        // The merge runs with options of its own, see `arm`.
+        let options = self.options.clone();
+        let armed = arm(&options);
+        let options = std::mem::replace(&mut self.options, MergeOptions::clone(&armed));
+        let merged = self.merge_armed(target_path);
+        self.options = options;
+        merged
+    }
+
+    fn merge_armed(&mut self, target_path: &Path) -> Result<(Arc<ConfigValue>, MergeReport), ConfigError> {
+        let base_dir = self.options.input_path(&self.base_dir)?.into_owned();
+        let target_path = &self.options.input_path(target_path)?;
+        let mut unreadable = Vec::new();
+        let (_, files) = discover_layer_files(&base_dir, target_path, &self.options, &mut unreadable)?;
+        Ok(self.merge_discovered(&base_dir, target_path, files, unreadable)?)
+    }
+
+    /// Drops everything cached for `path`, which may be a config file or a
+    /// target directory.
+    pub fn invalidate(&mut self, path: &Path) {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let uses_path = |files: &FileSet| files.iter().any(|(file, _)| file.path == path);
+
+        self.parsed.remove(&path);
+        if path.file_name() == Some(TYPES_FILE.as_ref()) {
+            self.types = None;
+        }
+        self.prefixes.retain(|files, _| !uses_path(files));
+        self.merged
+            .retain(|target, entry| *target != path && !uses_path(&entry.files));
+    }
+
+    /// Drops everything cached by this merger, but not the entries of its
+    /// [`ParseCache`].
+    pub fn clear(&mut self) {
+        self.parsed.clear();
+        self.prefixes.clear();
+        self.merged.clear();
+        self.types = None;
+    }
+
+    /// Drops the layer merges of `path` as it was parsed, when it no longer
+    /// has that fingerprint: no merge can use them again.
+    fn forget_changed(&mut self, path: &Path, fingerprint: Fingerprint) {
+        let Some(parsed) = self.parsed.get(path).map(|entry| entry.fingerprint) else {
+            return;
+        };
+        if parsed != fingerprint {
+            self.prefixes
+                .retain(|files, _| !files.iter().any(|(file, fingerprint)| file.path == path && *fingerprint == parsed));
+        }
+    }
+
+    /// Merges the already discovered layer files of `target_path`, given in
+    /// merge order, next to the `unreadable` entries of their discovery.
+    fn merge_discovered(
+        &mut self,
+        base_dir: &Path,
+        target_path: &Path,
+        mut files: Vec<LayerFile>,
+        unreadable: Vec<ReportEntry>,
+    ) -> Result<(Arc<ConfigValue>, MergeReport)> {
+        #[cfg(feature = "http")]
+        if let Some(layer) = self.options.remote_layers.first() {
+            anyhow::bail!("HierarchyMerger does not fetch remote layers such as {}; merge with merge_hierarchy", layer.url);
+        }
+        let memo_key = target_path.canonicalize()?;
+        let canonical_base = base_dir.canonicalize()?;
+        let types = self.type_rules(&canonical_base)?;
+        if types.is_some() {
+            let types_file = canonical_base.join(TYPES_FILE);
+            files.retain(|file| file.path != types_file);
+        }
+        let mut skipped = if files.is_empty() {
+            Vec::new()
+        } else {
+            skip_deep_files(&mut files, base_layer_depth(&canonical_base), &self.options)
+        };
+        skipped.extend(unreadable);
+        let mut fingerprinted = FileSet::with_capacity(files.len());
+        for file in files {
+            let fingerprint = self.source.fingerprint(&file.path)?;
+            self.forget_changed(&file.path, fingerprint);
+            // Unreadable files are found by reading them; the parse is cached.
+            if self.options.skip_unreadable
+                && let Err(e) = self.parse(&file.path, fingerprint, &mut Vec::new())
+            {
+                skipped.push(skipped_read(&e, &self.options).ok_or(e)?);
+                continue;
+            }
+            fingerprinted.push((file, fingerprint));
+        }
+        let files = fingerprinted;
+
+        let types_fingerprint = types.as_ref().map(|(fingerprint, _)| *fingerprint);
+        #[cfg(feature = "schema")]
+        let schemas = schema::schema_files(self.source.as_ref(), files.iter().map(|(file, _)| file.path.as_path()), &self.options)?;
+        #[cfg(not(feature = "schema"))]
+        let schemas = Vec::new();
+        if let Some(entry) = self.merged.get(&memo_key)
+            && entry.files == files
+            && entry.types == types_fingerprint
+            && entry.schemas == schemas
+        {
+            let mut report = entry.report.clone();
+            report.entries.splice(0..0, skipped);
+            self.options.check_report(&report)?;
+            return Ok((entry.config.clone(), report));
+        }
+
+        let (config, mut report) = if files.is_empty() && skipped.is_empty() && types.is_none() {
+            check_reserved_key([], &self.options)?;
+            (
+                Arc::new(self.options.initial_config()),
+                self.options.empty_hierarchy_report(base_dir, target_path)?,
+            )
+        } else {
+            let mut prefix = self.merge_files(base_dir, &files)?;
+            let parsed: Vec<(&Path, &ConfigValue)> = files
+                .iter()
+                .filter_map(|(file, _)| Some((file.path.as_path(), self.parsed.get(&file.path)?.config.as_ref())))
+                .collect();
+            check_merge_files(&parsed, &self.options, &mut prefix.entries)?;
+            let config = if finishes(&prefix.config, &self.options) {
+                let config = ConfigValue::clone(&prefix.config);
+                Arc::new(finish_merge(config, &parsed, &self.options, &mut prefix.entries)?)
+            } else {
+                prefix.config
+            };
+            // Checks of single files, ahead of the entries of merging them as
+            // in `merge_hierarchy`, which checks files as it loads them.
+            let mut checks = Vec::new();
+            if let Some((_, rules)) = &types {
+                if self.options.type_check == TypeCheck::EachLayer {
+                    for (path, config) in &parsed {
+                        types::check_layer(rules, path, config, &mut checks);
+                    }
+                } else {
+                    types::check_merged(rules, &config, &parsed, &mut prefix.entries);
+                }
+            }
+            #[cfg(feature = "schema")]
+            schema::check_directory_schemas(self.source.as_ref(), &parsed, &self.options, &mut checks)?;
+            prefix.entries.splice(0..0, checks);
+            let stats = measure(&config);
+            (
+                config,
+                MergeReport {
+                    entries: prefix.entries,
+                    files: prefix.files,
+                    stats,
+                },
+            )
+        };
+
+        let config = self.embed_metadata(config, base_dir, target_path, &report);
+        self.merged.insert(
+            memo_key,
+            MemoEntry {
+                files,
+                types: types_fingerprint,
+                schemas,
+                config: config.clone(),
+                report: report.clone(),
+            },
+        );
+        // Skipped files are not part of the memo key, so not of its report.
+        report.entries.splice(0..0, skipped);
+        self.options.check_report(&report)?;
+        Ok((config, report))
+    }
+
+    /// `config` with its metadata, memoized with it: a merge served from the
+    /// memo returns the same snapshot, carrying the time of the merge that
+    /// computed it, so that watchers see no change when no file changed.
+    fn embed_metadata(
+        &self,
+        config: Arc<ConfigValue>,
+        base_dir: &Path,
+        target_path: &Path,
+        report: &MergeReport,
+    ) -> Arc<ConfigValue> {
+        if !self.options.embed_metadata {
+            return config;
+        }
+        let config = ConfigValue::clone(&config);
+        Arc::new(embed_metadata(config, Some(base_dir), Some(target_path), report, &self.options))
+    }
+
+    /// The rules of the types file of `canonical_base` when
+    /// [`MergeOptions::type_check`] is on, read again only when the file
+    /// changed.
+    fn type_rules(&mut self, canonical_base: &Path) -> Result<Option<(Fingerprint, Arc<TypeRules>)>> {
+        let Some(path) = types::rules_path(canonical_base, &self.options) else {
+            return Ok(None);
+        };
+        let fingerprint = self.source.fingerprint(&path)?;
+        if let Some((read, rules)) = &self.types
+            && *read == fingerprint
+        {
+            return Ok(Some((fingerprint, rules.clone())));
+        }
+        let rules = Arc::new(TypeRules::read(self.source.as_ref(), &path)?);
+        self.types = Some((fingerprint, rules.clone()));
+        Ok(Some((fingerprint, rules)))
+    }
+
+    fn merge_files(&mut self, base_dir: &Path, files: &[(LayerFile, Fingerprint)]) -> Result<Prefix> {
+        let _span = trace::merge_span(files.len());
+        let base_depth = base_layer_depth(&base_dir.canonicalize()?);
+        let mut merged = Prefix {
+            config: Arc::new(self.options.initial_config()),
+            entries: Vec::new(),
+            files: Vec::new(),
+        };
+        let mut prefix = FileSet::new();
+
+        for layer in files.chunk_by(|(a, _), (b, _)| a.layer() == b.layer()) {
+            prefix.extend_from_slice(layer);
+            if let Some(cached) = self.prefixes.get(&prefix) {
+                merged = cached.clone();
+                continue;
+            }
+
+            let first = merged.files.is_empty();
+            let mut parsed = Vec::with_capacity(layer.len());
+            for (file, fingerprint) in layer {
+                let (config, sha256) = self.parse(&file.path, *fingerprint, &mut merged.entries)?;
+                merged.files.push(ContributingFile {
+                    path: file.path.clone(),
+                    depth: file.depth - base_depth,
+                    sha256,
+                });
+                parsed.push(config);
+            }
+            let mut depth_configs: Vec<(&Path, &ConfigValue)> = layer
+                .iter()
+                .zip(&parsed)
+                .map(|((file, _), config)| (file.path.as_path(), config.as_ref()))
+                .collect();
+            sort_by_priority(&mut depth_configs);
+            let renamed = apply_layer_deprecations(&depth_configs, &self.options, &mut merged.entries)?;
+            let depth_configs: Vec<_> = renamed.iter().map(|(path, config)| (*path, config.as_ref())).collect();
+
+            if self.options.collision_policy != CollisionPolicy::Ignore {
+                collect_depth_collisions(layer[0].0.depth, &depth_configs, &self.options, &mut merged.entries);
+            }
+            let stripped = strip_layer_priorities(&depth_configs);
+            let depth_configs: Vec<_> = stripped.iter().map(|(path, config)| (*path, config.as_ref())).collect();
+            collect_lock_violations(&merged.config, &depth_configs, first, &self.options, &mut merged.entries);
+            let below = ConfigValue::clone(&merged.config);
+            let mut guard = SizeGuard::new(&below, &self.options);
+            let config = merge_layer(below, &depth_configs, &self.options, &mut merged.entries, &mut guard)?;
+            merged.config = Arc::new(config);
+            trace::merged_layer(layer[0].0.depth, layer.len());
+            self.prefixes.insert(prefix.clone(), merged.clone());
+        }
+
+        Ok(merged)
+    }
+
+    /// The parsed `path`, adding the entries of loading it to `entries`.
+    fn parse(
+        &mut self,
+        path: &Path,
+        fingerprint: Fingerprint,
+        entries: &mut Vec<ReportEntry>,
+    ) -> Result<(Arc<ConfigValue>, String)> {
+        if let Some(entry) = self.parsed.get(path)
+            && entry.fingerprint == fingerprint
+        {
+            entries.extend(entry.entries.iter().cloned());
+            return Ok((entry.config.clone(), entry.sha256.clone()));
+        }
+
+        let mut loaded = Vec::new();
+        let (config, sha256) = match &self.parse_cache {
+            Some(cache) => cache.load(self.source.as_ref(), path, &self.options, &mut loaded)?,
+            None => {
+                let (config, sha256) = load_config_file(self.source.as_ref(), path, &self.options, &mut loaded)?;
+                (Arc::new(config), sha256)
+            }
+        };
+        entries.extend(loaded.iter().cloned());
+        self.parsed.insert(
+            path.to_path_buf(),
+            ParsedEntry {
+                fingerprint,
+                config: config.clone(),
+                sha256: sha256.clone(),
+                entries: loaded,
+            },
+        );
+        Ok((config, sha256))
+    }
+}
+
+/// Merges several targets of one base directory in a single pass.
+///
+/// The base directory is walked once, every file is parsed at most once, and
+/// targets sharing a directory chain reuse the merge result of their common
+/// layers. Each result is identical to what [`crate::merge_hierarchy`] returns
+/// for that target alone; as with [`HierarchyMerger`], remote layers make the
+/// merge fail instead.
+pub fn merge_many(
+    base_dir: &Path,
+    targets: &[PathBuf],
+    options: &MergeOptions,
+) -> Result<HashMap<PathBuf, (ConfigValue, MergeReport)>, ConfigError> {
+    let options = &arm(options);
+    let base_dir = options.input_path(base_dir)?;
+    let (mut walked, mut extra_unreadable) = (Vec::new(), Vec::new());
+    let (canonical_base, discovered, extra_root_files) = {
+        let _span = trace::discover_span(&base_dir, &base_dir);
+        let canonical_base = base_dir
+            .canonicalize()
+            .map_err(ConfigError::io("Failed to resolve path", &base_dir))?;
+        let discovered = discover_yaml_files(&canonical_base, options, &mut walked)?;
+        (canonical_base, discovered, discover_extra_roots(options, &mut extra_unreadable)?)
+    };
+    let mut merger = HierarchyMerger::new(base_dir.as_ref(), MergeOptions::clone(options));
+    let mut results = HashMap::with_capacity(targets.len());
+
+    for target in targets {
+        let target_path = options.input_path(target)?;
+        let (canonical_base, canonical_target) = canonicalize_hierarchy(&canonical_base, &target_path)?;
+        let files = layer_files(&canonical_base, &canonical_target, &discovered, &extra_root_files, options);
+        let mut unreadable = unreadable_in_hierarchy(&walked, &canonical_target, options);
+        unreadable.extend(extra_unreadable.iter().cloned());
+        if !files.is_empty() {
+            unreadable.extend(ignored_descendants(&canonical_base, &canonical_target, &discovered, options));
+        }
+        let (config, report) = merger.merge_discovered(&base_dir, &target_path, files, unreadable)?;
+        results.insert(target.clone(), (ConfigValue::clone(&config), report));
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::{Duration, SystemTime};
+    use crate::testing::{CountingSource, fixture_tree};
+
+    const TREE: &[(&str, &str)] = &[
+        ("config.yaml", "name: root\nlevel: 0\n"),
+        ("a/config.yaml", "level: 1\n"),
+        ("a/b/config.yaml", "level: 2\nleaf: b\n"),
+        ("a/c/config.yaml", "level: 2\nleaf: c\n"),
+    ];
+
+    #[test]
+    fn test_merge_many_matches_single_target_merges() {
+        // A same-depth collision in a shared layer must be reported per target.
+        let dir = fixture_tree(&[TREE, &[("a/other.yaml", "level: 10\n"), ("d/config.yaml", "leaf: d\n")]].concat());
+        let targets = vec![
+            dir.path().join("a/b"),
+            dir.path().join("a/c"),
+            dir.path().join("a"),
+            dir.path().join("d"),
+        ];
+
+        let results = merge_many(dir.path(), &targets, &MergeOptions::default()).unwrap();
+
+        assert_eq!(results.len(), targets.len());
+        for target in &targets {
+            let expected = crate::merge_hierarchy(dir.path(), target, &MergeOptions::default()).unwrap();
+            let (config, report) = &results[target];
+            assert_eq!(
+                serde_yaml::to_string(config).unwrap(),
+                serde_yaml::to_string(&expected.0).unwrap()
+            );
+            assert_eq!(report, &expected.1);
+        }
+        assert_eq!(results[&targets[0]].1.entries.len(), 1);
+        assert!(results[&targets[3]].1.is_empty());
+    }
+
+    fn get<'a>(config: &'a ConfigValue, key: &str) -> &'a ConfigValue {
+        config.get(key).unwrap_or_else(|| panic!("missing key {key}"))
+    }
+
+    #[test]
+    fn test_second_merge_is_served_from_memo() {
+        let dir = fixture_tree(TREE);
+        let source = CountingSource::default();
+        let mut merger = HierarchyMerger::with_source(dir.path(), MergeOptions::default(), source.clone());
+
+        let first = merger.merge(&dir.path().join("a/b")).unwrap();
+        assert_eq!(source.reads(), 3);
+
+        let second = merger.merge(&dir.path().join("a/b")).unwrap();
+        assert_eq!(source.reads(), 3);
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(get(&second, "leaf"), &ConfigValue::from("b"));
+    }
+
+    #[test]
+    fn test_each_file_is_read_once_for_hash_and_parse() {
+        let dir = fixture_tree(TREE);
+        let files = crate::find_yaml_files_in_hierarchy(dir.path(), &dir.path().join("a/b")).unwrap();
+        let source = CountingSource::default();
+
+        let parsed = crate::read_config_files(&source, &files, true).unwrap();
+        assert_eq!(source.reads(), files.len());
+        for file in &parsed {
+            let bytes = fs::read(&file.path).unwrap();
+            assert_eq!(file.hash.as_deref(), Some(crate::source::sha256_hex(&bytes).as_str()));
+            assert_eq!(file.size, bytes.len() as u64);
+            assert_eq!(file.value, crate::parse_yaml_file(&FsSource, &file.path).unwrap());
+        }
+
+        let mut merger = HierarchyMerger::with_source(dir.path(), MergeOptions::default(), source.clone());
+        let (_, report) = merger.merge_with_report(&dir.path().join("a/b")).unwrap();
+        assert_eq!(source.reads(), 2 * files.len());
+        for file in &report.files {
+            let read = parsed.iter().find(|parsed| parsed.path == file.path).unwrap();
+            assert_eq!(read.hash.as_ref(), Some(&file.sha256));
+        }
+    }
+
+    #[test]
+    fn test_siblings_reuse_shared_prefix() {
+        let dir = fixture_tree(TREE);
+        let source = CountingSource::default();
+        let mut merger = HierarchyMerger::with_source(dir.path(), MergeOptions::default(), source.clone());
+
+        merger.merge(&dir.path().join("a/b")).unwrap();
+        let sibling = merger.merge(&dir.path().join("a/c")).unwrap();
+
+        // Only the sibling's own leaf file is read for the second target.
+        assert_eq!(source.reads(), 4);
+        assert_eq!(get(&sibling, "name"), &ConfigValue::from("root"));
+        assert_eq!(get(&sibling, "leaf"), &ConfigValue::from("c"));
+    }
+
+    #[test]
+    fn test_changed_file_is_recomputed() {
+        let dir = fixture_tree(TREE);
+        let source = CountingSource::default();
+        let mut merger = HierarchyMerger::with_source(dir.path(), MergeOptions::default(), source.clone());
+        merger.merge(&dir.path().join("a/b")).unwrap();
+
+        let changed = dir.path().join("a/config.yaml");
+        fs::write(&changed, "level: 1\nextra: true\n").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&changed)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+
+        let merged = merger.merge(&dir.path().join("a/b")).unwrap();
+        // The root prefix and the leaf's parse are still valid, so only the
+        // changed file is read again.
+        assert_eq!(source.reads(), 4);
+        assert_eq!(get(&merged, "extra"), &ConfigValue::from(true));
+    }
+
+    #[test]
+    fn test_incremental_merges_match_full_merges() {
+        let dir = fixture_tree(&[TREE, &[("a/other.yaml", "level: 10\n"), ("a/b/extra.yaml", "leaf: extra\n")]].concat());
+        let files = ["config.yaml", "a/config.yaml", "a/other.yaml", "a/b/config.yaml", "a/b/extra.yaml", "a/c/config.yaml"];
+        let targets = ["", "a", "a/b", "a/c"].map(|target| dir.path().join(target));
+        let options = MergeOptions {
+            report_duplicates: true,
+            ..MergeOptions::default()
+        };
+        let mut merger = HierarchyMerger::new(dir.path(), options.clone());
+
+        // A fixed xorshift sequence, so that a failing iteration reproduces.
+        let mut state = 0x9E37_79B9_7F4A_7C15_u64;
+        let mut next = move |bound: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % bound as u64) as usize
+        };
+        let value = |next: &mut dyn FnMut(usize) -> usize| match next(5) {
+            0 => next(100).to_string(),
+            1 => format!("text{}", next(3)),
+            2 => format!("{{port: {}, host: h{}}}", next(3), next(3)),
+            3 => format!("[{}, {}]", next(3), next(3)),
+            _ => "null".to_string(),
+        };
+
+        for iteration in 0..300 {
+            let file = dir.path().join(files[next(files.len())]);
+            let mut content = String::new();
+            for key in ["level", "leaf", "server", "list", "name"] {
+                if next(2) == 0 {
+                    content += &format!("{key}: {}\n", value(&mut next));
+                }
+            }
+            fs::write(&file, &content).unwrap();
+            // Every edit gets a fingerprint of its own, even at the same length.
+            fs::File::options()
+                .write(true)
+                .open(&file)
+                .unwrap()
+                .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(iteration + 1))
+                .unwrap();
+
+            for target in [&targets[next(targets.len())], &targets[next(targets.len())]] {
+                let (config, report) = merger.merge_with_report(target).unwrap();
+                let (full_config, full_report) = crate::merge_hierarchy(dir.path(), target, &options).unwrap();
+                assert_eq!(
+                    serde_yaml::to_string(&*config).unwrap(),
+                    serde_yaml::to_string(&full_config).unwrap(),
+                    "iteration {iteration}, {}",
+                    target.display()
+                );
+                assert_eq!(report, full_report, "iteration {iteration}, {}", target.display());
+            }
+        }
+
+        // Once every target merged the current files, only the merges of
+        // their layers are left: the root, a, a/b and a/c.
+        for target in &targets {
+            merger.merge(target).unwrap();
+        }
+        assert_eq!(merger.prefixes.len(), 4);
+    }
+
+    #[test]
+    fn test_invalidate_and_clear_force_rereads() {
+        let dir = fixture_tree(TREE);
+        let source = CountingSource::default();
+        let mut merger = HierarchyMerger::with_source(dir.path(), MergeOptions::default(), source.clone());
+        merger.merge(&dir.path().join("a/b")).unwrap();
+
+        merger.invalidate(&dir.path().join("a/b/config.yaml"));
+        merger.merge(&dir.path().join("a/b")).unwrap();
+        assert_eq!(source.reads(), 4);
+
+        merger.clear();
+        merger.merge(&dir.path().join("a/b")).unwrap();
+        assert_eq!(source.reads(), 7);
+    }
+}
@@ -0,0 +1,205 @@
+//! Where a merged config came from, embedded in it under a reserved key, see
+//! [`MergeOptions::embed_metadata`].
+
+use std::borrow::Cow;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_yaml::Mapping;
+
+use crate::error::ConfigError;
+use crate::options::MergeOptions;
+use crate::provenance::DEFAULTS_SOURCE;
+use crate::report::MergeReport;
+use crate::ConfigValue;
+
+/// The top-level key of the metadata unless [`MergeOptions::metadata_key`]
+/// names another.
+pub const DEFAULT_METADATA_KEY: &str = "__meta__";
+
+/// Fails with [`ConfigError::ReservedKey`] when one of `files`, or the
+/// defaults, defines the metadata key at its top level while metadata is
+/// embedded.
+pub(crate) fn check_reserved_key<'a, I>(files: I, options: &MergeOptions) -> Result<(), ConfigError>
+where
+    I: IntoIterator<Item = (&'a Path, &'a ConfigValue)>,
+{
+    if !options.embed_metadata {
+        return Ok(());
+    }
+    let key = options.metadata_key();
+    let defines = |config: &ConfigValue| config.as_mapping().is_some_and(|map| map.contains_key(key));
+    let defaults = options.defaults.as_ref().filter(|config| defines(config));
+    let file = match defaults {
+        Some(_) => Some(Path::new(DEFAULTS_SOURCE)),
+        None => files.into_iter().find(|(_, config)| defines(config)).map(|(file, _)| file),
+    };
+    match file {
+        Some(file) => Err(ConfigError::ReservedKey {
+            key: key.to_string(),
+            file: file.to_path_buf(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// `config` with the metadata of its merge under the metadata key, when
+/// [`MergeOptions::embed_metadata`] is set: the base directory and target,
+/// when known, the files of `report` with their SHA-256, the crate version
+/// and the time of the merge. A config that is neither a mapping nor empty
+/// is returned as is.
+pub(crate) fn embed_metadata(
+    config: ConfigValue,
+    base_dir: Option<&Path>,
+    target_path: Option<&Path>,
+    report: &MergeReport,
+    options: &MergeOptions,
+) -> ConfigValue {
+    if !options.embed_metadata {
+        return config;
+    }
+    let mut map = match config {
+        ConfigValue::Mapping(map) => map,
+        ConfigValue::Null => Mapping::new(),
+        config => return config,
+    };
+
+    let display = |path: &Path| {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        ConfigValue::from(path.to_string_lossy().into_owned())
+    };
+    let mut meta = Mapping::new();
+    if let Some(base_dir) = base_dir {
+        meta.insert("base_dir".into(), display(base_dir));
+    }
+    if let Some(target_path) = target_path {
+        meta.insert("target".into(), display(target_path));
+    }
+    let files = report
+        .files
+        .iter()
+        .map(|file| {
+            let mut entry = Mapping::new();
+            entry.insert("path".into(), file.path.to_string_lossy().into_owned().into());
+            entry.insert("sha256".into(), file.sha256.clone().into());
+            ConfigValue::Mapping(entry)
+        })
+        .collect();
+    meta.insert("files".into(), ConfigValue::Sequence(files));
+    meta.insert("version".into(), env!("CARGO_PKG_VERSION").into());
+    meta.insert("generated_at".into(), rfc3339(SystemTime::now()).into());
+
+    map.insert(options.metadata_key().into(), ConfigValue::Mapping(meta));
+    ConfigValue::Mapping(map)
+}
+
+/// `config` without the metadata key, for checks that should not see it.
+pub(crate) fn without_metadata<'a>(config: &'a ConfigValue, options: &MergeOptions) -> Cow<'a, ConfigValue> {
+    match config {
+        ConfigValue::Mapping(map) if options.embed_metadata && map.contains_key(options.metadata_key()) => {
+            let mut map = map.clone();
+            map.remove(options.metadata_key());
+            Cow::Owned(ConfigValue::Mapping(map))
+        }
+        config => Cow::Borrowed(config),
+    }
+}
+
+/// `time` in UTC as `YYYY-MM-DDTHH:MM:SSZ`.
+fn rfc3339(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs());
+    let (days, seconds) = (seconds / 86_400, seconds % 86_400);
+    // Civil date from days since 1970-01-01, after Howard Hinnant.
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixture_tree;
+    use std::time::Duration;
+
+    fn options() -> MergeOptions {
+        MergeOptions {
+            embed_metadata: true,
+            ..MergeOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_metadata_describes_the_merge() {
+        let dir = fixture_tree(&[("config.yaml", "port: 80\n"), ("prod/config.yaml", "port: 8080\n")]);
+        let prod = dir.path().join("prod");
+        let (config, report) = crate::merge_hierarchy(dir.path(), &prod, &options()).unwrap();
+
+        assert_eq!(config["port"], 8080);
+        let meta = &config[DEFAULT_METADATA_KEY];
+        assert_eq!(meta["base_dir"], dir.path().canonicalize().unwrap().to_str().unwrap());
+        assert_eq!(meta["target"], prod.canonicalize().unwrap().to_str().unwrap());
+        assert_eq!(meta["version"], env!("CARGO_PKG_VERSION"));
+        let files = meta["files"].as_sequence().unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[1]["path"], report.files[1].path.to_str().unwrap());
+        assert_eq!(files[1]["sha256"], report.files[1].sha256.as_str());
+        let generated_at = meta["generated_at"].as_str().unwrap();
+        assert_eq!((generated_at.len(), &generated_at[10..11]), (20, "T"));
+        assert!(report.entries.is_empty());
+
+        // Validators see the config without it.
+        let built = crate::ConfigBuilder::new()
+            .options(options())
+            .hierarchy(dir.path(), &prod)
+            .validate(|config| match config.get(DEFAULT_METADATA_KEY) {
+                Some(_) => anyhow::bail!("metadata reached the validator"),
+                None => Ok(()),
+            })
+            .build();
+        assert!(built.unwrap().0.get(DEFAULT_METADATA_KEY).is_some());
+
+        // The key is configurable and strips like any other path.
+        let options = MergeOptions {
+            metadata_key: Some("_origin".to_string()),
+            ..options()
+        };
+        let (mut config, _) = crate::merge_hierarchy(dir.path(), &prod, &options).unwrap();
+        assert!(config.get("_origin").is_some());
+        assert_eq!(crate::prune(&mut config, &["_origin"]), ["_origin"]);
+        assert_eq!(config, serde_yaml::from_str::<ConfigValue>("port: 8080").unwrap());
+    }
+
+    #[test]
+    fn test_files_may_not_define_the_reserved_key() {
+        let dir = fixture_tree(&[("config.yaml", "port: 80\n"), ("prod/config.yaml", "__meta__: {mine: true}\n")]);
+        let err = crate::merge_hierarchy(dir.path(), &dir.path().join("prod"), &options()).unwrap_err();
+        match err {
+            ConfigError::ReservedKey { key, file } => {
+                assert_eq!(key, DEFAULT_METADATA_KEY);
+                assert!(file.ends_with("prod/config.yaml"));
+            }
+            other => panic!("Expected ReservedKey, got {other:?}"),
+        }
+        // Without metadata the key is ordinary data.
+        assert!(crate::merge_hierarchy(dir.path(), &dir.path().join("prod"), &MergeOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn test_rfc3339() {
+        assert_eq!(rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(rfc3339(UNIX_EPOCH + Duration::from_secs(951_782_400 + 3_661)), "2000-02-29T01:01:01Z");
+        assert_eq!(rfc3339(UNIX_EPOCH + Duration::from_secs(1_792_022_399)), "2026-10-14T23:59:59Z");
+    }
+}
@@ -0,0 +1,199 @@
+//! Canonical forms for the values of a merged config, see [`normalize`].
+
+use crate::keypath::{format_key_path, key_to_string, PathSegment};
+use crate::options::MergeOptions;
+use crate::redact::glob_match;
+use crate::ConfigValue;
+
+/// Which rewrites [`normalize`] applies. All are off by default.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NormalizeRules {
+    /// Strip leading and trailing whitespace from strings.
+    pub trim_strings: bool,
+    /// Turn null values into empty mappings.
+    pub null_as_empty_mapping: bool,
+    /// Key path patterns, matched like [`MergeOptions::prune_paths`], whose
+    /// strings become numbers when they read as one, such as `"8080"` or
+    /// `" 0.5 "`.
+    pub numeric_paths: Vec<String>,
+    /// Remove the keys whose value is an empty mapping, including mappings
+    /// left empty by the removal. The root is kept.
+    pub drop_empty_mappings: bool,
+}
+
+/// Rewrites `config` in place into the canonical form `rules` describe.
+/// Tagged values are left alone. Normalizing twice gives the same config as
+/// normalizing once.
+///
+/// ```
+/// # use hierarchical_config_merging::{normalize, ConfigValue, NormalizeRules};
+/// let mut config: ConfigValue = serde_yaml::from_str("name: ' api '\nport: '80'\nextra: ~\n").unwrap();
+/// let rules = NormalizeRules {
+///     trim_strings: true,
+///     null_as_empty_mapping: true,
+///     numeric_paths: vec!["port".to_string()],
+///     drop_empty_mappings: true,
+/// };
+/// normalize(&mut config, &rules);
+/// assert_eq!(config, serde_yaml::from_str::<ConfigValue>("name: api\nport: 80\n").unwrap());
+/// ```
+pub fn normalize(config: &mut ConfigValue, rules: &NormalizeRules) {
+    normalize_below(config, rules, &mut Vec::new());
+}
+
+fn normalize_below(value: &mut ConfigValue, rules: &NormalizeRules, path: &mut Vec<PathSegment>) {
+    match value {
+        ConfigValue::Null if rules.null_as_empty_mapping => *value = ConfigValue::Mapping(serde_yaml::Mapping::new()),
+        ConfigValue::Mapping(map) => {
+            for (key, item) in map.iter_mut() {
+                path.push(PathSegment::Key(key_to_string(key)));
+                normalize_below(item, rules, path);
+                path.pop();
+            }
+            if rules.drop_empty_mappings {
+                map.retain(|_, item| !matches!(item, ConfigValue::Mapping(map) if map.is_empty()));
+            }
+        }
+        ConfigValue::Sequence(items) => {
+            for (index, item) in items.iter_mut().enumerate() {
+                path.push(PathSegment::Index(index));
+                normalize_below(item, rules, path);
+                path.pop();
+            }
+        }
+        ConfigValue::String(text) => {
+            if rules.trim_strings && text.trim().len() != text.len() {
+                *text = text.trim().to_string();
+            }
+            if !rules.numeric_paths.is_empty() {
+                let dotted = format_key_path(path);
+                let numeric = rules
+                    .numeric_paths
+                    .iter()
+                    .any(|pattern| glob_match(pattern.as_bytes(), dotted.as_bytes()));
+                if let Some(number) = numeric.then(|| parse_number(text)).flatten() {
+                    *value = number;
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// `text`, surrounding whitespace aside, as an integer or a finite float.
+fn parse_number(text: &str) -> Option<ConfigValue> {
+    let text = text.trim();
+    if let Ok(integer) = text.parse::<i64>() {
+        return Some(integer.into());
+    }
+    if let Ok(integer) = text.parse::<u64>() {
+        return Some(integer.into());
+    }
+    let float = text.parse::<f64>().ok()?;
+    (float.is_finite() && text.contains(|c: char| c.is_ascii_digit())).then(|| float.into())
+}
+
+/// [`normalize`] with [`MergeOptions::normalize`], if set.
+pub(crate) fn normalize_merged(mut config: ConfigValue, options: &MergeOptions) -> ConfigValue {
+    if let Some(rules) = &options.normalize {
+        normalize(&mut config, rules);
+    }
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn yaml(text: &str) -> ConfigValue {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    fn normalized(text: &str, rules: &NormalizeRules) -> ConfigValue {
+        let mut config = yaml(text);
+        normalize(&mut config, rules);
+        config
+    }
+
+    #[test]
+    fn test_trim_strings() {
+        let rules = NormalizeRules {
+            trim_strings: true,
+            ..NormalizeRules::default()
+        };
+        assert_eq!(
+            normalized("name: \"api \"\nhosts: [' a', b]\nport: ' 80'\nkept: !tag ' x '\n", &rules),
+            yaml("name: api\nhosts: [a, b]\nport: '80'\nkept: !tag ' x '\n")
+        );
+    }
+
+    #[test]
+    fn test_null_as_empty_mapping() {
+        let rules = NormalizeRules {
+            null_as_empty_mapping: true,
+            ..NormalizeRules::default()
+        };
+        assert_eq!(normalized("a: ~\nb: null\nc: {d: ~}\ne: [~]\n", &rules), yaml("a: {}\nb: {}\nc: {d: {}}\ne: [{}]\n"));
+    }
+
+    #[test]
+    fn test_numeric_paths() {
+        let rules = NormalizeRules {
+            numeric_paths: vec!["server.*".to_string(), "ratio".to_string()],
+            ..NormalizeRules::default()
+        };
+        assert_eq!(
+            normalized(
+                "server: {port: '8080', host: localhost, big: '18446744073709551615', neg: '-1'}\n\
+                 ratio: ' 0.5 '\nother: '1'\nodd: 'inf'\n",
+                &rules
+            ),
+            yaml(
+                "server: {port: 8080, host: localhost, big: 18446744073709551615, neg: -1}\n\
+                 ratio: 0.5\nother: '1'\nodd: 'inf'\n"
+            )
+        );
+        assert_eq!(normalized("ratio: inf\n", &rules), yaml("ratio: inf\n"));
+    }
+
+    #[test]
+    fn test_drop_empty_mappings() {
+        let rules = NormalizeRules {
+            drop_empty_mappings: true,
+            ..NormalizeRules::default()
+        };
+        assert_eq!(
+            normalized("a: {}\nb: {c: {d: {}}}\ne: ~\nf: []\ng: [{}]\nh: {i: 1, j: {}}\n", &rules),
+            yaml("e: ~\nf: []\ng: [{}]\nh: {i: 1}\n")
+        );
+        assert_eq!(normalized("{}", &rules), yaml("{}"));
+    }
+
+    #[test]
+    fn test_normalizing_twice_changes_nothing() {
+        let rules = NormalizeRules {
+            trim_strings: true,
+            null_as_empty_mapping: true,
+            numeric_paths: vec!["limits.*".to_string()],
+            drop_empty_mappings: true,
+        };
+        let once = normalized(
+            "name: ' api  '\nlimits: {cpu: ' 2 ', memory: '512'}\nextra: {nested: ~, empty: {}}\n\
+             hosts: [' a ', ~]\n",
+            &rules,
+        );
+        assert_eq!(once, yaml("name: api\nlimits: {cpu: 2, memory: 512}\nhosts: [a, {}]\n"));
+        let mut twice = once.clone();
+        normalize(&mut twice, &rules);
+        assert_eq!(twice, once);
+
+        // The high-level merges normalize last.
+        let dir = crate::testing::fixture_tree(&[("config.yaml", "limits: {cpu: '2'}\nextra: {}\n")]);
+        let options = MergeOptions {
+            normalize: Some(rules),
+            ..MergeOptions::default()
+        };
+        let (config, _) = crate::merge_hierarchy(dir.path(), dir.path(), &options).unwrap();
+        assert_eq!(config, yaml("limits: {cpu: 2}\n"));
+    }
+}
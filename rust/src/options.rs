@@ -0,0 +1,665 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::error::ConfigError;
+use crate::keypath::{format_key_path, PathSegment};
+use crate::paths::expand_path;
+use crate::redact::{glob_match, Redaction};
+use crate::report::{MergeReport, ReportKind};
+use crate::trace;
+use crate::ConfigValue;
+
+/// How a sequence overriding another sequence is combined with it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum SequenceStrategy {
+    /// The overriding sequence replaces the base one.
+    #[default]
+    Replace,
+    /// Base items first, then the overriding items.
+    Append,
+    /// Overriding items first, then the base items.
+    Prepend,
+    /// Base items, then the overriding items not already present.
+    Union,
+}
+
+impl SequenceStrategy {
+    pub const NAMES: &'static [&'static str] = &["replace", "append", "prepend", "union"];
+
+    pub(crate) fn combine<V: Clone + PartialEq>(self, base: &[V], r#override: &[V]) -> Vec<V> {
+        match self {
+            SequenceStrategy::Replace => r#override.to_vec(),
+            SequenceStrategy::Append => base.iter().chain(r#override).cloned().collect(),
+            SequenceStrategy::Prepend => r#override.iter().chain(base).cloned().collect(),
+            SequenceStrategy::Union => {
+                let mut items = base.to_vec();
+                for item in r#override {
+                    if !items.contains(item) {
+                        items.push(item.clone());
+                    }
+                }
+                items
+            }
+        }
+    }
+}
+
+/// How an overriding config is merged into the config below it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub enum MergeMode {
+    /// Mappings merge key by key; any other value replaces the one below it,
+    /// sequences combining by [`SequenceStrategy`].
+    #[default]
+    Deep,
+    /// RFC 7386 JSON Merge Patch, see [`crate::apply_merge_patch`]: a `null`
+    /// removes the key and sequences always replace.
+    JsonMergePatch,
+    /// Kubernetes-style strategic merge patch, see [`crate::strategic`].
+    /// Sequences whose items are all mappings with a `default_key` entry merge
+    /// item by item, matched on that key.
+    StrategicMergePatch { default_key: String },
+}
+
+impl MergeMode {
+    /// Names of the modes that can be parsed; the strategic merge patch mode
+    /// needs a merge key.
+    pub const NAMES: &'static [&'static str] = &["deep", "json_merge_patch"];
+}
+
+/// What happens when files of one layer define the same top-level key.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum CollisionPolicy {
+    /// Report a collision entry; the file later in path order wins.
+    #[default]
+    Warn,
+    /// Let the later file win silently.
+    Ignore,
+    /// Fail with [`crate::ConfigError::Collision`].
+    Error,
+}
+
+impl CollisionPolicy {
+    pub const NAMES: &'static [&'static str] = &["warn", "ignore", "error"];
+}
+
+/// What happens to a string override that does not parse into the type of
+/// the value it overrides, see [`MergeOptions::coerce_types`]. A
+/// type-conflict entry is reported unless the merge fails.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum CoercionFailure {
+    /// The string overrides the value as it is.
+    #[default]
+    UseString,
+    /// The overridden value is kept.
+    KeepBase,
+    /// Fail with [`crate::ConfigError::TypeConflict`].
+    Error,
+}
+
+impl CoercionFailure {
+    pub const NAMES: &'static [&'static str] = &["use_string", "keep_base", "error"];
+}
+
+/// What happens to an index patch, see [`MergeOptions::index_patches`], whose
+/// index is beyond the end of its sequence, or that patches a value that is
+/// no sequence.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum IndexOutOfRange {
+    /// The patch is left out and reported as
+    /// [`crate::ReportKind::IndexOutOfRange`].
+    #[default]
+    Warn,
+    /// Fail with [`crate::ConfigError::IndexPatch`].
+    Error,
+}
+
+impl IndexOutOfRange {
+    pub const NAMES: &'static [&'static str] = &["warn", "error"];
+}
+
+/// Error of parsing an option value from its name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownOptionValue {
+    pub option: &'static str,
+    pub value: String,
+    pub allowed: &'static [&'static str],
+}
+
+impl fmt::Display for UnknownOptionValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid {} '{}': expected one of {}",
+            self.option,
+            self.value,
+            self.allowed.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for UnknownOptionValue {}
+
+pub(crate) fn parse_named<T: Clone>(
+    option: &'static str,
+    allowed: &'static [&'static str],
+    values: &[T],
+    value: &str,
+) -> Result<T, UnknownOptionValue> {
+    allowed
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(value))
+        .map(|index| values[index].clone())
+        .ok_or_else(|| UnknownOptionValue {
+            option,
+            value: value.to_string(),
+            allowed,
+        })
+}
+
+impl FromStr for SequenceStrategy {
+    type Err = UnknownOptionValue;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        use SequenceStrategy::*;
+        parse_named("sequence_strategy", Self::NAMES, &[Replace, Append, Prepend, Union], value)
+    }
+}
+
+impl FromStr for CollisionPolicy {
+    type Err = UnknownOptionValue;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        use CollisionPolicy::*;
+        parse_named("collision_policy", Self::NAMES, &[Warn, Ignore, Error], value)
+    }
+}
+
+/// Whether and how values are checked against the [`crate::types::TYPES_FILE`]
+/// of the base directory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum TypeCheck {
+    /// The types file is an ordinary config file.
+    #[default]
+    Off,
+    /// The merged config is checked; each mismatch names the last file
+    /// defining the value.
+    Merged,
+    /// Every file is checked on its own as it is loaded, so that a bad value
+    /// is reported even where a deeper file overrides it.
+    EachLayer,
+}
+
+impl TypeCheck {
+    pub const NAMES: &'static [&'static str] = &["off", "merged", "each_layer"];
+}
+
+impl FromStr for TypeCheck {
+    type Err = UnknownOptionValue;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        use TypeCheck::*;
+        parse_named("type_check", Self::NAMES, &[Off, Merged, EachLayer], value)
+    }
+}
+
+/// Which files below the target directory a hierarchy merge takes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum TargetMode {
+    /// Only the files of the target and of the directories above it, up to
+    /// the base. A target that is the base merges the base's own files; how
+    /// many deeper files that leaves out is reported as
+    /// [`crate::ReportKind::IgnoredDescendants`].
+    #[default]
+    Exact,
+    /// Every file below the target as well, deeper files overriding
+    /// shallower ones. Files at the same depth form one layer, so sibling
+    /// directories defining the same key collide. With the base as target,
+    /// the whole tree is merged.
+    AllDescendants,
+}
+
+impl TargetMode {
+    pub const NAMES: &'static [&'static str] = &["exact", "all_descendants"];
+}
+
+impl FromStr for TargetMode {
+    type Err = UnknownOptionValue;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        use TargetMode::*;
+        parse_named("target_mode", Self::NAMES, &[Exact, AllDescendants], value)
+    }
+}
+
+/// What the strings at the key paths of [`MergeOptions::units`] are read
+/// as, and what replaces them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Unit {
+    /// A duration, see [`crate::parse_duration`], as an integer count of
+    /// milliseconds. Durations in fractions of a millisecond do not read.
+    Millis,
+    /// A duration as a `{secs, nanos}` mapping, the form serde gives
+    /// [`std::time::Duration`].
+    Duration,
+    /// A byte size, see [`crate::parse_byte_size`], as an integer count of
+    /// bytes.
+    Bytes,
+}
+
+impl Unit {
+    pub const NAMES: &'static [&'static str] = &["millis", "duration", "bytes"];
+
+    /// What values of this unit are, for messages.
+    pub(crate) fn description(self) -> &'static str {
+        match self {
+            Unit::Millis | Unit::Duration => "a duration",
+            Unit::Bytes => "a byte size",
+        }
+    }
+}
+
+impl FromStr for Unit {
+    type Err = UnknownOptionValue;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        use Unit::*;
+        parse_named("unit", Self::NAMES, &[Millis, Duration, Bytes], value)
+    }
+}
+
+impl FromStr for CoercionFailure {
+    type Err = UnknownOptionValue;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        use CoercionFailure::*;
+        parse_named("coercion_failure", Self::NAMES, &[UseString, KeepBase, Error], value)
+    }
+}
+
+impl FromStr for IndexOutOfRange {
+    type Err = UnknownOptionValue;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        use IndexOutOfRange::*;
+        parse_named("index_out_of_range", Self::NAMES, &[Warn, Error], value)
+    }
+}
+
+impl FromStr for MergeMode {
+    type Err = UnknownOptionValue;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        use MergeMode::*;
+        parse_named("mode", Self::NAMES, &[Deep, JsonMergePatch], value)
+    }
+}
+
+/// Knobs for the hierarchical merge. `MergeOptions::default()` reproduces the
+/// behaviour of [`crate::merge_hierarchical_configs`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MergeOptions {
+    /// Fail with [`crate::ConfigError::EmptyHierarchy`] instead of returning
+    /// an empty mapping when no YAML file is found. Likely to become the
+    /// default in the next breaking release.
+    pub fail_on_empty: bool,
+    /// Fail with [`crate::ConfigError::Strict`] when the merge reports any
+    /// warning.
+    pub strict: bool,
+    /// Directories outside the base whose YAML files (recursively) join the
+    /// merge as one layer each. The offset places that layer relative to the
+    /// hierarchy: 0 merges it together with the files of the base directory,
+    /// 1 with the first sub-directory level, -1 below the base, and so on.
+    /// Within a layer files merge in path order and same-key collisions are
+    /// reported like any other.
+    pub extra_roots: Vec<(PathBuf, i32)>,
+    /// Expand a leading `~` and `$VAR` / `${VAR}` in the base, target and
+    /// extra root paths before resolving them. Off by default in the Rust API;
+    /// the Python bindings turn it on.
+    pub expand_paths: bool,
+    /// How sequences present in both a base and an overriding file combine.
+    pub sequence_strategy: SequenceStrategy,
+    /// What to do about same-key collisions within a layer.
+    pub collision_policy: CollisionPolicy,
+    /// File extensions considered config files, with or without the leading
+    /// dot. `None` means `.yaml` and `.yml`. Every file is parsed as YAML,
+    /// which also accepts JSON.
+    pub extensions: Option<Vec<String>>,
+    /// Take only files of this name, such as `settings.yaml`, into a
+    /// hierarchy, so that other files of a level cannot collide with it.
+    /// Variants with another of the extensions (`settings.yml`) or a profile
+    /// (`settings@prod.yaml`) count as the same file; without an extension,
+    /// the name matches every configured one.
+    pub filename: Option<String>,
+    /// Active profiles. A profile file is named `<name>@<profile>.<ext>`, e.g.
+    /// `config@prod.yaml`; it is only merged while its profile is active, in
+    /// a layer of its own right after the other files of its directory.
+    /// Profiles listed later take precedence.
+    pub profiles: Vec<String>,
+    /// Selectors, as `(name, value)` pairs, that the conditional values of
+    /// [`crate::WHEN_KEY`] are resolved against, besides the built-in
+    /// `profile` and `os`. A pair named `profile` or `os` replaces the
+    /// built-in one.
+    pub selectors: Vec<(String, String)>,
+    /// Names of the enabled features: the sections of any other feature are
+    /// left out of the files, see [`crate::IF_FEATURE_KEY`].
+    pub features: HashSet<String>,
+    /// A `null` in an overriding file removes the key instead of setting it
+    /// to null.
+    pub null_deletes: bool,
+    /// Renamed keys as `(old, canonical)` dotted key paths. After parsing, a
+    /// file's value at the old path moves to the canonical path, deep-merged
+    /// below the value already there, and a deprecation entry names the file.
+    pub aliases: Vec<(String, String)>,
+    /// Dotted key paths whose use adds a deprecation entry naming the file,
+    /// without renaming.
+    pub deprecated_paths: Vec<String>,
+    /// Key paths whose values are masked in report messages. Defaults to
+    /// [`crate::redact::DEFAULT_REDACT_PATTERNS`].
+    pub redaction: Redaction,
+    /// Compose files from the Hydra-style `defaults` list at their top
+    /// level: each `group: name` entry merges `<group>/<name>.yaml` from the
+    /// file's directory under the `group` key, before the file's own content
+    /// unless `_self_` is listed. The list itself is removed.
+    pub compose_defaults: bool,
+    /// Resolve `${dotted.path}` references in string values against the
+    /// merged config once every layer is merged, see [`crate::interpolate`].
+    /// Unresolved references are reported; a reference cycle fails the merge.
+    pub interpolate: bool,
+    /// Parse a string that overrides a number or a bool, in any layer, into
+    /// that type: `"8080"` over `80` merges as `8080`. Each coercion is
+    /// reported as information; strings that do not parse are handled by
+    /// `coercion_failure`.
+    pub coerce_types: bool,
+    /// What happens to a string that does not parse while `coerce_types` is
+    /// set.
+    pub coercion_failure: CoercionFailure,
+    /// Merge a mapping whose keys are all of the form `[N]`, such as
+    /// `servers: {"[2]": {port: 9090}}`, or a `!patch-index` mapping, into
+    /// the items at those indexes of the sequence it overrides, leaving the
+    /// other items as they are. Such a mapping never merges as a mapping:
+    /// a patch of an index beyond the end of the sequence, or of a value that
+    /// is no sequence, is handled by `index_out_of_range`. Deep merges only.
+    pub index_patches: bool,
+    /// What happens to an index patch that patches no item.
+    pub index_out_of_range: IndexOutOfRange,
+    /// Report files whose parsed configs are identical, ignoring key order
+    /// and scalar spelling as [`crate::config_hash`] does. The entries are
+    /// information.
+    pub report_duplicates: bool,
+    /// Report files that define no key, or whose every key is overridden by
+    /// the files merged after them, as [`crate::ReportKind::ShadowedFile`]
+    /// information. Such a file usually has the wrong name or sits in the
+    /// wrong directory.
+    pub report_shadowed_files: bool,
+    /// Compiled-in defaults, merged beneath every file as the lowest layer.
+    /// They take no part in collision detection and are attributed to
+    /// [`crate::provenance::DEFAULTS_SOURCE`] in provenance.
+    pub defaults: Option<ConfigValue>,
+    /// Merge only the files of the first `n` directory levels, the base
+    /// directory being the first. Deeper files are reported as skipped.
+    pub max_merge_depth: Option<usize>,
+    /// Fail the merge with [`ConfigError::LimitExceeded`] once the merged
+    /// config holds more nodes than this: mappings, sequences and scalars,
+    /// keys aside. Checked as each file is merged, so a runaway hierarchy
+    /// stops before it is converted or serialized. The count of every merge
+    /// is in [`crate::MergeReport::stats`].
+    pub max_merged_nodes: Option<usize>,
+    /// Like `max_merged_nodes`, for a rough size in bytes: the length of
+    /// every string and key, and 8 bytes for any other scalar.
+    pub max_merged_bytes_estimate: Option<usize>,
+    /// Key path patterns of subtrees removed from the merged config once
+    /// references are resolved, see [`crate::prune`]. Each removal is
+    /// reported as information.
+    pub prune_paths: Vec<String>,
+    /// Only the first layer merged, normally the files of the base
+    /// directory, may add top-level keys; a deeper file adding one is
+    /// reported as a lock violation. Deeper files may still add keys below
+    /// the existing ones.
+    pub lock_top_level: bool,
+    /// Top-level keys any layer may add despite `lock_top_level`.
+    pub lock_exempt_sections: Vec<String>,
+    /// Key path patterns, matched like [`MergeOptions::prune_paths`], of
+    /// keys that files of one layer may all define, such as a
+    /// `schema_version` every region file declares, or a `db.version` in each
+    /// of their `db` sections. A pattern is matched against the paths where
+    /// the files collide, the leaves they both set. Their collisions are not
+    /// reported, see `report_exempt_collisions`.
+    pub collision_exempt_paths: Vec<String>,
+    /// Report the collisions of `collision_exempt_paths` as
+    /// [`crate::ReportKind::ExemptCollision`], for information, instead of
+    /// leaving them out.
+    pub report_exempt_collisions: bool,
+    /// Leave out the config files and hierarchy directories that cannot be
+    /// read, such as root-owned leftovers in a shared tree, reporting each as
+    /// [`crate::ReportKind::Unreadable`] instead of failing the merge. With
+    /// `strict`, those warnings still fail it.
+    pub skip_unreadable: bool,
+    /// Configs fetched over HTTP by [`crate::merge_hierarchy`] and the merges
+    /// built on it, each merged as a layer at its priority. The caching
+    /// [`crate::HierarchyMerger`] and [`crate::merge_many`] fail instead of
+    /// fetching them.
+    #[cfg(feature = "http")]
+    pub remote_layers: Vec<crate::remote::RemoteLayer>,
+    /// Check the files of each level of [`crate::merge_hierarchy`], the
+    /// merges built on it and [`crate::HierarchyMerger`] against the `_schema.yaml` of their directory,
+    /// reporting each violation as [`crate::ReportKind::SchemaViolation`].
+    /// Only the keys a file defines are checked, never the merged config.
+    /// Schema files are not merged.
+    #[cfg(feature = "schema")]
+    pub directory_schemas: bool,
+    /// Add where the merged config came from under [`MergeOptions::metadata_key`]:
+    /// the base directory, target, contributing files with their SHA-256, the
+    /// crate version and the time of the merge. A file or the defaults
+    /// defining that key fail the merge with [`ConfigError::ReservedKey`];
+    /// drop the key with [`crate::prune`] before handing the config on.
+    pub embed_metadata: bool,
+    /// Top-level key of the embedded metadata, [`crate::DEFAULT_METADATA_KEY`]
+    /// when `None`.
+    pub metadata_key: Option<String>,
+    /// Check values against the [`crate::types::TYPES_FILE`] of the base
+    /// directory in [`crate::merge_hierarchy`], the caching
+    /// [`crate::HierarchyMerger`] and the merges built on them, reporting
+    /// each mismatch as [`crate::ReportKind::TypeMismatch`]. The types file
+    /// is then not merged.
+    pub type_check: TypeCheck,
+    /// Key path patterns, matched like [`MergeOptions::prune_paths`], with
+    /// the unit their string values are read in: a merged string at a
+    /// matching path, such as `30s` or `512MB`, is replaced by its value in
+    /// that unit. The first matching pattern applies. Strings that do not
+    /// read are kept and reported as [`crate::ReportKind::InvalidQuantity`].
+    pub units: Vec<(String, Unit)>,
+    /// Rewrites applied to the merged config after pruning, see
+    /// [`crate::normalize`].
+    pub normalize: Option<crate::normalize::NormalizeRules>,
+    /// Respell every key of the merged config in one convention, last, see
+    /// [`crate::convert_key_case`]. Keys that become the same key are
+    /// collisions, reported and failing the merge as `collision_policy`
+    /// says. Provenance keeps the keys as the files write them.
+    pub key_case: Option<crate::casing::KeyCase>,
+    /// Whether [`crate::merge_hierarchy`] and the merges built on it take the
+    /// files below the target too.
+    pub target_mode: TargetMode,
+    /// How overriding files merge into the files below them. Provenance
+    /// tracking and conflict resolvers always merge deeply.
+    pub mode: MergeMode,
+    /// Fail the merge with [`ConfigError::Cancelled`] once this token is
+    /// cancelled, from another thread or by the source reading the files.
+    pub cancel: Option<crate::cancel::CancelToken>,
+    /// Cancel each merge still running once this much time has passed since
+    /// it started, as if by `cancel`, which it leaves untouched. The timeout
+    /// is kept by a thread the merge starts.
+    pub timeout: Option<std::time::Duration>,
+}
+
+impl MergeOptions {
+    /// These options with `defaults` serialized into [`MergeOptions::defaults`].
+    pub fn with_defaults_from<T: Serialize>(mut self, defaults: &T) -> Result<Self, ConfigError> {
+        let defaults = serde_yaml::to_value(defaults).map_err(|e| ConfigError::Other(e.into()))?;
+        self.defaults = Some(defaults);
+        Ok(self)
+    }
+
+    /// The config the first layer merges into: the defaults, if any.
+    pub(crate) fn initial_config(&self) -> ConfigValue {
+        self.defaults
+            .clone()
+            .unwrap_or_else(|| ConfigValue::Mapping(serde_yaml::Mapping::new()))
+    }
+
+    /// Whether `path` has one of the configured extensions and, with
+    /// [`MergeOptions::filename`], that name.
+    pub(crate) fn is_config_file(&self, path: &Path) -> bool {
+        let Some(ext) = path.extension() else {
+            return false;
+        };
+        #[cfg(feature = "schema")]
+        if self.directory_schemas && crate::schema::is_schema_file(path) {
+            return false;
+        }
+        if let Some(filename) = &self.filename {
+            let filename = Path::new(filename);
+            let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or_default();
+            let name = stem.rsplit_once('@').map_or(stem, |(name, _)| name);
+            if filename.file_stem().is_none_or(|wanted| wanted != name) {
+                return false;
+            }
+            if filename.extension() == Some(ext) {
+                return true;
+            }
+        }
+        match &self.extensions {
+            None => ext == "yaml" || ext == "yml",
+            Some(extensions) => extensions
+                .iter()
+                .any(|wanted| ext == wanted.strip_prefix('.').unwrap_or(wanted)),
+        }
+    }
+
+    /// Merge order rank of a file within its directory: 0 for regular files,
+    /// `n` for a file of the n-th active profile, `None` for a file of an
+    /// inactive profile.
+    pub(crate) fn profile_rank(&self, path: &Path) -> Option<u32> {
+        let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or_default();
+        let Some((_, profile)) = stem.rsplit_once('@') else {
+            return Some(0);
+        };
+        self.profiles
+            .iter()
+            .rposition(|active| active == profile)
+            .map(|index| index as u32 + 1)
+    }
+
+    /// `path` as given, or expanded when `expand_paths` is set.
+    pub(crate) fn input_path<'a>(&self, path: &'a Path) -> Result<Cow<'a, Path>, ConfigError> {
+        if self.expand_paths {
+            Ok(Cow::Owned(expand_path(path)?))
+        } else {
+            Ok(Cow::Borrowed(path))
+        }
+    }
+
+    /// Report for a hierarchy without any YAML file, or the error asked for by
+    /// `fail_on_empty`.
+    pub(crate) fn empty_hierarchy_report(
+        &self,
+        base_dir: &Path,
+        target_path: &Path,
+    ) -> Result<MergeReport, ConfigError> {
+        if self.fail_on_empty {
+            return Err(ConfigError::EmptyHierarchy {
+                base: base_dir.to_path_buf(),
+                target: target_path.to_path_buf(),
+            });
+        }
+        Ok(MergeReport::empty_hierarchy(base_dir, target_path))
+    }
+
+    /// The top-level key [`MergeOptions::embed_metadata`] writes to.
+    pub fn metadata_key(&self) -> &str {
+        self.metadata_key.as_deref().unwrap_or(crate::metadata::DEFAULT_METADATA_KEY)
+    }
+
+    /// Whether a collision at `path` is exempt, see
+    /// [`MergeOptions::collision_exempt_paths`].
+    pub(crate) fn is_collision_exempt(&self, path: &[PathSegment]) -> bool {
+        if self.collision_exempt_paths.is_empty() {
+            return false;
+        }
+        let path = format_key_path(path);
+        self.collision_exempt_paths
+            .iter()
+            .any(|pattern| glob_match(pattern.as_bytes(), path.as_bytes()))
+    }
+
+    /// Whether the merge fetches [`MergeOptions::remote_layers`].
+    pub(crate) fn has_remote_layers(&self) -> bool {
+        #[cfg(feature = "http")]
+        return !self.remote_layers.is_empty();
+        #[cfg(not(feature = "http"))]
+        false
+    }
+
+    /// Applies `collision_policy = Error` and `strict` to a finished report,
+    /// once its entries have been emitted as trace events.
+    pub(crate) fn check_report(&self, report: &MergeReport) -> Result<(), ConfigError> {
+        trace::report_entries(&report.entries);
+        if self.collision_policy == CollisionPolicy::Error {
+            let collisions: Vec<_> = report
+                .entries
+                .iter()
+                .filter(|entry| entry.kind == ReportKind::Collision)
+                .cloned()
+                .collect();
+            if !collisions.is_empty() {
+                return Err(ConfigError::Collision { entries: collisions });
+            }
+        }
+        if self.strict && report.entries.iter().any(|entry| entry.kind.is_warning()) {
+            return Err(ConfigError::Strict {
+                entries: report.entries.iter().filter(|entry| entry.kind.is_warning()).cloned().collect(),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_option_values_parse_by_name() {
+        assert_eq!("append".parse::<SequenceStrategy>(), Ok(SequenceStrategy::Append));
+        assert_eq!("Error".parse::<CollisionPolicy>(), Ok(CollisionPolicy::Error));
+
+        let err = "extend".parse::<SequenceStrategy>().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid sequence_strategy 'extend': expected one of replace, append, prepend, union"
+        );
+    }
+
+    #[test]
+    fn test_profile_rank_and_extensions() {
+        let options = MergeOptions {
+            profiles: vec!["prod".to_string(), "eu".to_string()],
+            extensions: Some(vec!["json".to_string(), ".yaml".to_string()]),
+            ..MergeOptions::default()
+        };
+
+        assert_eq!(options.profile_rank(Path::new("a/config.yaml")), Some(0));
+        assert_eq!(options.profile_rank(Path::new("a/config@prod.yaml")), Some(1));
+        assert_eq!(options.profile_rank(Path::new("a/config@eu.yaml")), Some(2));
+        assert_eq!(options.profile_rank(Path::new("a/config@dev.yaml")), None);
+
+        assert!(options.is_config_file(Path::new("a.json")));
+        assert!(options.is_config_file(Path::new("a.yaml")));
+        assert!(!options.is_config_file(Path::new("a.yml")));
+        assert!(MergeOptions::default().is_config_file(Path::new("a.yml")));
+    }
+}
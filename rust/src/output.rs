@@ -0,0 +1,309 @@
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::error::ConfigError;
+use crate::keypath::{format_key_path, key_to_string, PathSegment};
+use crate::ConfigValue;
+
+/// The config as a YAML document, keys in merge order.
+pub fn to_yaml(config: &ConfigValue) -> Result<String, ConfigError> {
+    Ok(serde_yaml::to_string(config).context("Failed to serialize config as YAML")?)
+}
+
+/// The config as JSON, indented when `pretty`, keys in merge order.
+///
+/// Integer, float and boolean mapping keys become strings; null and non-scalar
+/// keys are an error. Tagged values become a single-entry object keyed by the
+/// tag, e.g. `{"!secret": "db"}`, and NaN and the infinities become `null`.
+pub fn to_json(config: &ConfigValue, pretty: bool) -> Result<String, ConfigError> {
+    let json = if pretty {
+        serde_json::to_string_pretty(config)
+    } else {
+        serde_json::to_string(config)
+    };
+    Ok(json.context("Failed to serialize config as JSON")?)
+}
+
+/// How [`to_properties_string_with`] writes what `.properties` files have no
+/// syntax for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PropertiesOptions {
+    /// Write sequences of scalars as one comma-joined value, `tags=a,b`,
+    /// instead of one indexed key per item, `tags[0]=a`. Commas within items
+    /// are not escaped. Other sequences always use indexed keys.
+    pub join_sequences: bool,
+    /// Write nulls as empty values instead of leaving their keys out.
+    pub empty_nulls: bool,
+}
+
+/// The config flattened to Java `.properties` text.
+pub fn to_properties_string(config: &ConfigValue) -> Result<String, ConfigError> {
+    to_properties_string_with(config, &PropertiesOptions::default())
+}
+
+/// The config flattened to Java `.properties` text, one `key=value` line per
+/// leaf, in merge order.
+///
+/// Keys are dotted key paths such as `server.ports[0]`. Keys and values are
+/// escaped like `java.util.Properties::store` does: backslashes, `=`, `:`,
+/// `#` and `!` get a backslash, tabs and line breaks their escape sequences,
+/// and characters outside printable ASCII a `\uXXXX` escape. Spaces are
+/// escaped throughout keys and at the start of values. Tags are dropped and
+/// empty mappings and sequences write nothing. The config must be a mapping.
+pub fn to_properties_string_with(config: &ConfigValue, options: &PropertiesOptions) -> Result<String, ConfigError> {
+    if !matches!(config, ConfigValue::Mapping(_)) {
+        return Err(anyhow!("Failed to serialize config as properties: the top level is not a mapping").into());
+    }
+    let mut text = String::new();
+    write_properties(config, &mut Vec::new(), options, &mut text);
+    Ok(text)
+}
+
+fn write_properties(value: &ConfigValue, path: &mut Vec<PathSegment>, options: &PropertiesOptions, text: &mut String) {
+    let (key, value) = match value {
+        ConfigValue::Mapping(map) => {
+            for (key, item) in map {
+                path.push(PathSegment::Key(key_to_string(key)));
+                write_properties(item, path, options, text);
+                path.pop();
+            }
+            return;
+        }
+        ConfigValue::Sequence(items)
+            if options.join_sequences && !items.is_empty() && items.iter().all(|item| scalar(item).is_some()) =>
+        {
+            let joined: Vec<_> = items.iter().filter_map(scalar).collect();
+            (format_key_path(path), joined.join(","))
+        }
+        ConfigValue::Sequence(items) => {
+            for (index, item) in items.iter().enumerate() {
+                path.push(PathSegment::Index(index));
+                write_properties(item, path, options, text);
+                path.pop();
+            }
+            return;
+        }
+        ConfigValue::Tagged(tagged) => return write_properties(&tagged.value, path, options, text),
+        ConfigValue::Null if !options.empty_nulls => return,
+        scalar_value => (format_key_path(path), scalar(scalar_value).unwrap_or_default()),
+    };
+    escape_properties(&key, true, text);
+    text.push('=');
+    escape_properties(&value, false, text);
+    text.push('\n');
+}
+
+/// The text of a scalar, or of a tagged scalar; `None` for anything else.
+fn scalar(value: &ConfigValue) -> Option<String> {
+    match value {
+        ConfigValue::Null => Some(String::new()),
+        ConfigValue::Bool(b) => Some(b.to_string()),
+        ConfigValue::Number(n) => Some(n.to_string()),
+        ConfigValue::String(s) => Some(s.clone()),
+        ConfigValue::Tagged(tagged) => scalar(&tagged.value),
+        ConfigValue::Sequence(_) | ConfigValue::Mapping(_) => None,
+    }
+}
+
+fn escape_properties(raw: &str, is_key: bool, text: &mut String) {
+    for (index, c) in raw.chars().enumerate() {
+        match c {
+            ' ' if is_key || index == 0 => text.push_str("\\ "),
+            '\\' | '=' | ':' | '#' | '!' => {
+                text.push('\\');
+                text.push(c);
+            }
+            '\t' => text.push_str("\\t"),
+            '\n' => text.push_str("\\n"),
+            '\r' => text.push_str("\\r"),
+            '\u{0c}' => text.push_str("\\f"),
+            ' '..='~' => text.push(c),
+            _ => {
+                let mut units = [0; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    text.push_str(&format!("\\u{unit:04X}"));
+                }
+            }
+        }
+    }
+}
+
+/// Options of [`to_env_exports`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvOptions {
+    /// Prepended to every variable name, followed by the separator, e.g.
+    /// `APP` for `APP_SERVER_PORT`. Empty for none.
+    pub prefix: String,
+    /// Joins the key path segments of a name. `_` by default.
+    pub separator: String,
+    /// Fail on values that have no shell form, such as sequences of mappings,
+    /// instead of leaving them out.
+    pub strict: bool,
+}
+
+impl Default for EnvOptions {
+    fn default() -> Self {
+        Self {
+            prefix: String::new(),
+            separator: "_".to_string(),
+            strict: false,
+        }
+    }
+}
+
+/// The config flattened to `export NAME='value'` lines for a POSIX shell, in
+/// merge order.
+///
+/// Names join the prefix and the key path segments with the separator,
+/// uppercased, e.g. `server.ports[0]` with prefix `APP` becomes
+/// `APP_SERVER_PORTS_0`; characters not allowed in shell names become `_`.
+/// Values are always single-quoted, so spaces, `$` and backticks are kept
+/// as they are. Nulls become empty values and sequences of scalars one
+/// comma-joined value. Sequences holding mappings or sequences cannot be
+/// represented: they are left out, or an error with `strict`. Tags are
+/// dropped. The config must be a mapping.
+pub fn to_env_exports(config: &ConfigValue, options: &EnvOptions) -> Result<String, ConfigError> {
+    if !matches!(config, ConfigValue::Mapping(_)) {
+        return Err(anyhow!("Failed to serialize config as env exports: the top level is not a mapping").into());
+    }
+    let mut text = String::new();
+    write_env(config, &mut Vec::new(), options, &mut text)?;
+    Ok(text)
+}
+
+fn write_env(value: &ConfigValue, path: &mut Vec<PathSegment>, options: &EnvOptions, text: &mut String) -> Result<()> {
+    let value = match value {
+        ConfigValue::Mapping(map) => {
+            for (key, item) in map {
+                path.push(PathSegment::Key(key_to_string(key)));
+                write_env(item, path, options, text)?;
+                path.pop();
+            }
+            return Ok(());
+        }
+        ConfigValue::Sequence(items) => match items.iter().map(scalar).collect::<Option<Vec<_>>>() {
+            Some(items) => items.join(","),
+            None if options.strict => bail!(
+                "Failed to serialize config as env exports: '{}' holds a sequence item that is not a scalar",
+                format_key_path(path)
+            ),
+            None => return Ok(()),
+        },
+        ConfigValue::Tagged(tagged) => return write_env(&tagged.value, path, options, text),
+        scalar_value => scalar(scalar_value).unwrap_or_default(),
+    };
+    text.push_str("export ");
+    text.push_str(&env_name(path, options));
+    text.push_str("='");
+    text.push_str(&value.replace('\'', "'\\''"));
+    text.push_str("'\n");
+    Ok(())
+}
+
+fn env_name(path: &[PathSegment], options: &EnvOptions) -> String {
+    let segments = path.iter().map(|segment| match segment {
+        PathSegment::Key(key) => key.clone(),
+        PathSegment::Index(index) => index.to_string(),
+    });
+    let name = (!options.prefix.is_empty())
+        .then(|| options.prefix.clone())
+        .into_iter()
+        .chain(segments)
+        .collect::<Vec<_>>()
+        .join(&options.separator);
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c.to_ascii_uppercase() } else { '_' })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{name}")
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_keeps_key_order_and_stringifies_scalar_keys() {
+        let config: ConfigValue =
+            serde_yaml::from_str("zeta: 1\nalpha: {80: http, true: on}\nsecret: !vault db\nlimit: .inf\n").unwrap();
+
+        assert_eq!(
+            to_json(&config, false).unwrap(),
+            r#"{"zeta":1,"alpha":{"80":"http","true":"on"},"secret":{"!vault":"db"},"limit":null}"#
+        );
+        assert!(to_json(&config, true).unwrap().starts_with("{\n  \"zeta\": 1,\n"));
+        assert_eq!(
+            to_yaml(&config).unwrap(),
+            "zeta: 1\nalpha:\n  80: http\n  true: on\nsecret: !vault db\nlimit: .inf\n"
+        );
+
+        let null_key: ConfigValue = serde_yaml::from_str("~: x\n").unwrap();
+        assert!(to_json(&null_key, false).is_err());
+    }
+
+    #[test]
+    fn test_properties_golden_file() {
+        let config: ConfigValue = serde_yaml::from_str(include_str!("../../tests/golden/tricky.yaml")).unwrap();
+        assert_eq!(
+            to_properties_string(&config).unwrap(),
+            include_str!("../../tests/golden/tricky.properties")
+        );
+        let options = PropertiesOptions {
+            join_sequences: true,
+            empty_nulls: true,
+        };
+        assert_eq!(
+            to_properties_string_with(&config, &options).unwrap(),
+            include_str!("../../tests/golden/tricky.joined.properties")
+        );
+        assert!(to_properties_string(&serde_yaml::from_str("[1, 2]").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_env_exports_quote_values() {
+        let config: ConfigValue = serde_yaml::from_str(
+            r#"
+server:
+  port: 9090
+  motd: "it's $HOME, `whoami` and \"quotes\""
+  name: two words
+log-level: ~
+tags: [web, api]
+80: http
+routes: [{path: /}]
+"#,
+        )
+        .unwrap();
+        let options = EnvOptions {
+            prefix: "APP".to_string(),
+            ..EnvOptions::default()
+        };
+        assert_eq!(
+            to_env_exports(&config, &options).unwrap(),
+            concat!(
+                "export APP_SERVER_PORT='9090'\n",
+                "export APP_SERVER_MOTD='it'\\''s $HOME, `whoami` and \"quotes\"'\n",
+                "export APP_SERVER_NAME='two words'\n",
+                "export APP_LOG_LEVEL=''\n",
+                "export APP_TAGS='web,api'\n",
+                "export APP_80='http'\n",
+            )
+        );
+
+        let options = EnvOptions {
+            separator: "__".to_string(),
+            strict: true,
+            ..EnvOptions::default()
+        };
+        let err = to_env_exports(&config, &options).unwrap_err();
+        assert!(err.to_string().contains("'routes'"), "{err}");
+        let nested: ConfigValue = serde_yaml::from_str("server: {port: 1}\n80: x\n").unwrap();
+        assert_eq!(
+            to_env_exports(&nested, &options).unwrap(),
+            "export SERVER__PORT='1'\nexport _80='x'\n"
+        );
+    }
+}
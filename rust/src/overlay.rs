@@ -0,0 +1,184 @@
+//! Kustomize-style overlays: a tree mirroring part of a base hierarchy whose
+//! files patch it.
+
+use std::path::Path;
+use anyhow::Result;
+
+use crate::cancel::arm;
+use crate::compose::load_config_file;
+use crate::metadata::embed_metadata;
+use crate::options::MergeOptions;
+use crate::paths::resolve_target_within_base;
+use crate::provenance::{merge_layers_traced, Provenance};
+use crate::report::{ContributingFile, MergeReport};
+use crate::source::FsSource;
+use crate::{
+    base_layer_depth, config_depth, discover_layer_files, discover_yaml_files, empty_merge, select_hierarchy_files,
+    skipped_read, trace, unreadable_in_hierarchy, ConfigError, ConfigValue, LayerFile,
+};
+
+/// Merges the hierarchy of `base_dir` for `target_relative`, a path relative
+/// to both trees, with the hierarchy of `overlay_dir` for the same path on
+/// top.
+///
+/// Layers merge shallowest first as usual; at each directory level the
+/// overlay files come after the base files (profile files included), so they
+/// outrank them without colliding with them. A deeper base file still
+/// outranks a shallower overlay file. The overlay only needs the directories
+/// it patches: the target does not have to exist in it. Extra roots join the
+/// base hierarchy.
+///
+/// Report files carry their depth below their own tree. Provenance names the
+/// file of each value, telling base values from overlay values by the tree
+/// the file lies in.
+pub fn merge_with_overlay(
+    base_dir: &Path,
+    overlay_dir: &Path,
+    target_relative: &Path,
+    options: &MergeOptions,
+) -> Result<(ConfigValue, MergeReport, Provenance), ConfigError> {
+    let options = &arm(options);
+    let base_dir = &options.input_path(base_dir)?;
+    let overlay_dir = &options.input_path(overlay_dir)?;
+    // Within both trees, whatever their names.
+    let target_relative = &match resolve_target_within_base(Path::new(""), target_relative) {
+        Ok(target) => target.relative,
+        Err(_) => {
+            return Err(ConfigError::OutsideBase {
+                base: base_dir.to_path_buf(),
+                target: base_dir.join(target_relative),
+            });
+        }
+    };
+    let target_path = base_dir.join(target_relative);
+
+    let mut unreadable = Vec::new();
+    let (canonical_base, mut files) = discover_layer_files(base_dir, &target_path, options, &mut unreadable)?;
+    let canonical_overlay = overlay_dir
+        .canonicalize()
+        .map_err(ConfigError::io("Failed to resolve path", overlay_dir))?;
+    let overlay_target = canonical_overlay.join(target_relative);
+    let mut walked = Vec::new();
+    let overlay_files = select_hierarchy_files(
+        &canonical_overlay,
+        &overlay_target,
+        &discover_yaml_files(&canonical_overlay, options, &mut walked)?,
+    );
+    unreadable.extend(unreadable_in_hierarchy(&walked, &overlay_target, options));
+
+    // Overlay files join the base scale, in ranks after every base profile.
+    let base_depth = base_layer_depth(&canonical_base);
+    let overlay_depth = base_layer_depth(&canonical_overlay);
+    let overlay_rank = options.profiles.len() as u32 + 1;
+    let overlay_files = overlay_files
+        .into_iter()
+        .filter_map(|path| {
+            let rank = overlay_rank + options.profile_rank(&path)?;
+            let depth = config_depth(&path) as i64 - overlay_depth + base_depth;
+            Some(LayerFile { depth, rank, path })
+        });
+    files.extend(overlay_files);
+    files.sort();
+    if files.is_empty() && unreadable.is_empty() {
+        let (config, report) = empty_merge(base_dir, &target_path, options)?;
+        return Ok((config, report, Provenance::default()));
+    }
+
+    let _span = trace::parse_span(files.len());
+    let mut configs = Vec::with_capacity(files.len());
+    let mut contributing = Vec::with_capacity(files.len());
+    for file in files {
+        let (config, sha256) = match load_config_file(&FsSource, &file.path, options, &mut unreadable) {
+            Ok(loaded) => loaded,
+            Err(e) => match skipped_read(&e, options) {
+                Some(entry) => {
+                    unreadable.push(entry);
+                    continue;
+                }
+                None => return Err(e.into()),
+            },
+        };
+        contributing.push(ContributingFile {
+            path: file.path.clone(),
+            depth: file.depth - base_depth,
+            sha256,
+        });
+        configs.push((file, config));
+    }
+
+    let (config, mut report, provenance) = {
+        let _span = trace::merge_span(configs.len());
+        let layers = configs.iter().map(|(file, config)| (file.layer(), file.path.as_path(), config));
+        merge_layers_traced(layers, options)?
+    };
+    report.files = contributing;
+    report.entries.splice(0..0, unreadable);
+    options.check_report(&report)?;
+    let config = embed_metadata(config, Some(base_dir), Some(&target_path), &report, options);
+    Ok((config, report, provenance))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn write(path: &Path, text: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, text).unwrap();
+    }
+
+    #[test]
+    fn test_overlay_patches_mid_level_and_leaf() {
+        let dir = tempfile::tempdir().unwrap();
+        let (base, overlay) = (dir.path().join("base"), dir.path().join("overlays/prod"));
+        write(&base.join("config.yaml"), "replicas: 1\nimage: app:1\n");
+        write(&base.join("eu/config.yaml"), "region: eu\nreplicas: 2\nlog: {level: info}\n");
+        write(&base.join("eu/web/config.yaml"), "port: 80\n");
+        write(&overlay.join("config.yaml"), "image: app:2\nreplicas: 9\n");
+        write(&overlay.join("eu/config.yaml"), "log: {level: warn}\n");
+        write(&overlay.join("eu/web/config.yaml"), "tls: true\n");
+        // Same name, other branch: not part of the target's hierarchy.
+        write(&overlay.join("us/config.yaml"), "region: us\n");
+
+        let (config, report, provenance) =
+            merge_with_overlay(&base, &overlay, Path::new("eu/web"), &MergeOptions::default()).unwrap();
+        assert_eq!(
+            config,
+            serde_yaml::from_str::<ConfigValue>(
+                "replicas: 2\nimage: app:2\nregion: eu\nlog: {level: warn}\nport: 80\ntls: true\n"
+            )
+            .unwrap()
+        );
+        assert!(report.entries.is_empty());
+        assert_eq!(
+            report.files.iter().map(|file| file.depth).collect::<Vec<_>>(),
+            vec![0, 0, 1, 1, 2, 2]
+        );
+
+        let (base, overlay) = (base.canonicalize().unwrap(), overlay.canonicalize().unwrap());
+        assert_eq!(provenance.source("log.level").unwrap(), Some(overlay.join("eu/config.yaml").as_path()));
+        assert_eq!(provenance.source("tls").unwrap(), Some(overlay.join("eu/web/config.yaml").as_path()));
+        assert_eq!(provenance.source("region").unwrap(), Some(base.join("eu/config.yaml").as_path()));
+        assert_eq!(provenance.source("replicas").unwrap(), Some(base.join("eu/config.yaml").as_path()));
+    }
+
+    #[test]
+    fn test_overlay_without_target_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let (base, overlay) = (dir.path().join("base"), dir.path().join("overlay"));
+        write(&base.join("app/config.yaml"), "c: 1\n");
+        write(&base.join("app/leaf/config.yaml"), "a: 1\nb: 1\n");
+        write(&overlay.join("app/config.yaml"), "b: 2\nc: 2\n");
+
+        let (config, _, _) = merge_with_overlay(&base, &overlay, Path::new("app/leaf"), &MergeOptions::default()).unwrap();
+        assert_eq!(config, serde_yaml::from_str::<ConfigValue>("c: 2\na: 1\nb: 1\n").unwrap());
+
+        let (config, _, _) = merge_with_overlay(&base, &overlay, Path::new("app/./leaf/x/.."), &MergeOptions::default()).unwrap();
+        assert_eq!(config["c"], 2);
+        for target in ["../base", "app/../../base", "/app"] {
+            let err = merge_with_overlay(&base, &overlay, Path::new(target), &MergeOptions::default()).unwrap_err();
+            assert!(matches!(err, ConfigError::OutsideBase { .. }), "{target}: {err:?}");
+        }
+    }
+}
@@ -0,0 +1,458 @@
+use std::collections::HashSet;
+use std::env;
+use std::ffi::OsStr;
+use std::path::{Component, Components, Path, PathBuf, Prefix};
+
+use crate::error::ConfigError;
+
+/// Expands a leading `~` to the home directory and `$VAR` / `${VAR}` to the
+/// value of the environment variable. `~user` and a `$` not followed by a
+/// variable name are left untouched; paths that are not valid UTF-8 are
+/// returned unchanged.
+pub fn expand_path(path: &Path) -> Result<PathBuf, ConfigError> {
+    expand_path_with(path, |name| env::var(name).ok())
+}
+
+/// [`expand_path`] with a custom variable lookup. The home directory is looked
+/// up as `HOME` (or `USERPROFILE` when `HOME` is unset).
+pub(crate) fn expand_path_with(
+    path: &Path,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<PathBuf, ConfigError> {
+    let Some(raw) = path.to_str() else {
+        return Ok(path.to_path_buf());
+    };
+    let unresolved = |variable: &str| ConfigError::UnresolvedVariable {
+        variable: variable.to_string(),
+        path: path.to_path_buf(),
+    };
+
+    let mut expanded = String::with_capacity(raw.len());
+    let mut rest = raw;
+
+    if let Some(after_tilde) = rest.strip_prefix('~')
+        && (after_tilde.is_empty() || after_tilde.starts_with(['/', std::path::MAIN_SEPARATOR]))
+    {
+        let home = lookup("HOME")
+            .or_else(|| lookup("USERPROFILE"))
+            .ok_or_else(|| unresolved("HOME"))?;
+        expanded.push_str(&home);
+        rest = after_tilde;
+    }
+
+    while let Some(dollar) = rest.find('$') {
+        expanded.push_str(&rest[..dollar]);
+        let after = &rest[dollar + 1..];
+
+        let (name, consumed) = if let Some(braced) = after.strip_prefix('{') {
+            match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => return Err(unresolved(braced)),
+            }
+        } else {
+            let end = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            (&after[..end], end)
+        };
+
+        if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+            // Not a variable reference: keep the `$` literally.
+            expanded.push('$');
+            rest = after;
+            continue;
+        }
+
+        expanded.push_str(&lookup(name).ok_or_else(|| unresolved(name))?);
+        rest = &after[consumed..];
+    }
+    expanded.push_str(rest);
+
+    Ok(PathBuf::from(expanded))
+}
+
+/// The system and user config directories of `app_name`, lowest precedence
+/// first, following the XDG Base Directory layout: `/etc/<app>`, then
+/// `<dir>/<app>` for each directory of `$XDG_CONFIG_DIRS` (`/etc/xdg` when
+/// unset), the last listed first, then `$XDG_CONFIG_HOME/<app>`
+/// (`~/.config/<app>` when unset). Relative entries are ignored, as the
+/// specification requires. The directories need not exist.
+///
+/// ```
+/// # use hierarchical_config_merging::standard_layers;
+/// let layers = standard_layers("myapp");
+/// assert_eq!(layers[0], std::path::Path::new("/etc/myapp"));
+/// ```
+pub fn standard_layers(app_name: &str) -> Vec<PathBuf> {
+    standard_layers_with(app_name, |name| env::var_os(name).map(PathBuf::from))
+}
+
+/// [`standard_layers`] with a custom variable lookup.
+pub(crate) fn standard_layers_with(app_name: &str, lookup: impl Fn(&str) -> Option<PathBuf>) -> Vec<PathBuf> {
+    let set = |name: &str| lookup(name).filter(|value| !value.as_os_str().is_empty());
+    let mut layers = vec![Path::new("/etc").join(app_name)];
+
+    let config_dirs = set("XDG_CONFIG_DIRS").unwrap_or_else(|| PathBuf::from("/etc/xdg"));
+    let mut config_dirs: Vec<PathBuf> = env::split_paths(&config_dirs).filter(|dir| dir.is_absolute()).collect();
+    config_dirs.reverse();
+    layers.extend(config_dirs.into_iter().map(|dir| dir.join(app_name)));
+
+    let config_home = set("XDG_CONFIG_HOME")
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| set("HOME").map(|home| home.join(".config")));
+    layers.extend(config_home.map(|dir| dir.join(app_name)));
+
+    let mut seen = HashSet::new();
+    layers.retain(|layer| seen.insert(layer.clone()));
+    layers
+}
+
+/// A target path resolved within its base directory by
+/// [`resolve_target_within_base`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelativeTarget {
+    /// The base directory, without `.` and `..` components.
+    pub base: PathBuf,
+    /// The target relative to `base`, made of plain names only; empty for
+    /// the base itself.
+    pub relative: PathBuf,
+}
+
+impl RelativeTarget {
+    /// The target, as `base` joined with `relative`.
+    pub fn path(&self) -> PathBuf {
+        match self.relative.as_os_str().is_empty() {
+            true => self.base.clone(),
+            false => self.base.join(&self.relative),
+        }
+    }
+}
+
+/// Resolves `target` against `base` lexically, without looking at the
+/// filesystem: a relative target is relative to the base, `.` components
+/// are dropped and `..` ones remove the name before them. Fails with
+/// [`ConfigError::OutsideBase`] when the result escapes the base, such as
+/// `../other` or an absolute path elsewhere.
+///
+/// Targets that do not exist, or do not live on a filesystem at all, are
+/// resolved the same way. Links are not followed: the filesystem entry
+/// points also check the canonical target, so that a link within the base
+/// pointing outside it is rejected too.
+///
+/// ```
+/// # use std::path::Path;
+/// # use hierarchical_config_merging::resolve_target_within_base;
+/// let target = resolve_target_within_base(Path::new("/srv/configs"), Path::new("prod/../eu/")).unwrap();
+/// assert_eq!(target.relative, Path::new("eu"));
+/// assert!(resolve_target_within_base(Path::new("/srv/configs"), Path::new("prod/../../secrets")).is_err());
+/// ```
+pub fn resolve_target_within_base(base: &Path, target: &Path) -> Result<RelativeTarget, ConfigError> {
+    let outside = || ConfigError::OutsideBase {
+        base: base.to_path_buf(),
+        target: target.to_path_buf(),
+    };
+    let normalized_base = normalize(base);
+    let normalized_target = normalize(&normalized_base.join(target));
+    let relative = relative_to(&normalized_target, &normalized_base).ok_or_else(outside)?;
+    // What is left of `..` climbs above a relative base.
+    if !relative.components().all(|component| matches!(component, Component::Normal(_))) {
+        return Err(outside());
+    }
+    Ok(RelativeTarget {
+        relative: relative.to_path_buf(),
+        base: normalized_base,
+    })
+}
+
+/// `path` without `.` components and with each `..` removing the name
+/// before it. A `..` at the root stays there, as the system resolves it,
+/// and those leading a relative path are kept.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    let mut names = 0;
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if names > 0 => {
+                normalized.pop();
+                names -= 1;
+            }
+            Component::ParentDir if normalized.has_root() => {}
+            Component::Normal(name) => {
+                normalized.push(name);
+                names += 1;
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Whether the platform compares path names regardless of case, as Windows
+/// does.
+const FOLD_CASE: bool = cfg!(windows);
+
+/// `path` relative to `base`, when it lies within it. Paths are compared
+/// component by component the way the platform compares them: a leading `.`
+/// is ignored, verbatim prefixes (`\\?\C:`, as returned by `canonicalize`
+/// on Windows) match their plain form, and on Windows names compare without
+/// regard to case.
+pub(crate) fn relative_to<'a>(path: &'a Path, base: &Path) -> Option<&'a Path> {
+    relative_to_with(path, base, FOLD_CASE)
+}
+
+/// Whether `path` is `base` or lies within it, see [`relative_to`].
+pub(crate) fn is_within(path: &Path, base: &Path) -> bool {
+    relative_to(path, base).is_some()
+}
+
+/// Whether two components name the same thing, see [`relative_to`].
+pub(crate) fn same_component(a: Component<'_>, b: Component<'_>) -> bool {
+    same_component_with(a, b, FOLD_CASE)
+}
+
+fn relative_to_with<'a>(path: &'a Path, base: &Path, fold_case: bool) -> Option<&'a Path> {
+    let mut components = without_cur_dir(path);
+    for wanted in without_cur_dir(base) {
+        if !same_component_with(components.next()?, wanted, fold_case) {
+            return None;
+        }
+    }
+    Some(components.as_path())
+}
+
+/// The components of `path`, less the `.` it may start with.
+fn without_cur_dir(path: &Path) -> Components<'_> {
+    let mut components = path.components();
+    if components.clone().next() == Some(Component::CurDir) {
+        components.next();
+    }
+    components
+}
+
+fn same_component_with(a: Component<'_>, b: Component<'_>, fold_case: bool) -> bool {
+    match (a, b) {
+        (Component::Prefix(a), Component::Prefix(b)) => same_prefix(a.kind(), b.kind()),
+        (Component::Normal(a), Component::Normal(b)) => same_name(a, b, fold_case),
+        (a, b) => a == b,
+    }
+}
+
+/// Drive letters and server names never depend on case, and a verbatim
+/// prefix matches the plain one it stands for.
+fn same_prefix(a: Prefix<'_>, b: Prefix<'_>) -> bool {
+    let plain = |prefix| match prefix {
+        Prefix::VerbatimDisk(drive) => Prefix::Disk(drive),
+        Prefix::VerbatimUNC(server, share) => Prefix::UNC(server, share),
+        prefix => prefix,
+    };
+    match (plain(a), plain(b)) {
+        (Prefix::Disk(a), Prefix::Disk(b)) => a.eq_ignore_ascii_case(&b),
+        (Prefix::UNC(server_a, share_a), Prefix::UNC(server_b, share_b)) => {
+            same_name(server_a, server_b, true) && same_name(share_a, share_b, true)
+        }
+        (a, b) => a == b,
+    }
+}
+
+fn same_name(a: &OsStr, b: &OsStr, fold_case: bool) -> bool {
+    if !fold_case {
+        return a == b;
+    }
+    match (a.to_str(), b.to_str()) {
+        (Some(a), Some(b)) => a == b || a.to_lowercase() == b.to_lowercase(),
+        _ => a.eq_ignore_ascii_case(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "HOME" => Some("/home/user".to_string()),
+            "CONFIG_ROOT" => Some("/srv/configs".to_string()),
+            "ENV" => Some("prod".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_expands_tilde_and_variables() {
+        let expand = |raw: &str| expand_path_with(Path::new(raw), lookup).unwrap();
+
+        assert_eq!(expand("~/configs"), PathBuf::from("/home/user/configs"));
+        assert_eq!(expand("~"), PathBuf::from("/home/user"));
+        assert_eq!(expand("$CONFIG_ROOT/envs/${ENV}"), PathBuf::from("/srv/configs/envs/prod"));
+        assert_eq!(expand("${CONFIG_ROOT}_old"), PathBuf::from("/srv/configs_old"));
+        assert_eq!(expand("~other/x"), PathBuf::from("~other/x"));
+        assert_eq!(expand("cost$/5$"), PathBuf::from("cost$/5$"));
+    }
+
+    #[test]
+    fn test_unresolved_variable_is_named() {
+        let err = expand_path_with(Path::new("$MISSING/x"), lookup).unwrap_err();
+        assert!(matches!(&err, ConfigError::UnresolvedVariable { variable, .. } if variable == "MISSING"));
+        assert!(err.to_string().contains("MISSING"));
+
+        let err = expand_path_with(Path::new("${ENV"), lookup).unwrap_err();
+        assert!(matches!(err, ConfigError::UnresolvedVariable { .. }));
+    }
+
+    #[test]
+    fn test_expands_from_process_environment() {
+        // SAFETY: the variable name is unique to this test.
+        unsafe { env::set_var("HCM_TEST_EXPAND_ROOT", "/tmp/hcm-root") };
+        assert_eq!(
+            expand_path(Path::new("$HCM_TEST_EXPAND_ROOT/a")).unwrap(),
+            PathBuf::from("/tmp/hcm-root/a")
+        );
+    }
+
+    #[test]
+    fn test_relative_to_compares_components() {
+        let relative = |path: &str, base: &str, fold_case: bool| {
+            relative_to_with(Path::new(path), Path::new(base), fold_case).map(Path::to_path_buf)
+        };
+
+        assert_eq!(relative("/srv/configs/prod/a.yaml", "/srv/configs", false), Some(PathBuf::from("prod/a.yaml")));
+        assert_eq!(relative("/srv/configs", "/srv/configs/", false), Some(PathBuf::new()));
+        assert_eq!(relative("/srv/configs-old/a.yaml", "/srv/configs", false), None);
+        assert_eq!(relative("./Prod/EU/a.yaml", "Prod", false), Some(PathBuf::from("EU/a.yaml")));
+        assert_eq!(relative("prod/a.yaml", "./Prod", false), None);
+        assert_eq!(relative("prod/eu", "", false), Some(PathBuf::from("prod/eu")));
+
+        // As on Windows: the remainder keeps the case of `path`.
+        assert_eq!(relative("Srv/CONFIGS/Prod/a.yaml", "./srv/configs", true), Some(PathBuf::from("Prod/a.yaml")));
+        assert_eq!(relative("Srv/Ärger/a.yaml", "srv/ärger", true), Some(PathBuf::from("a.yaml")));
+        assert_eq!(relative("srv/a.yaml", "srv/b", true), None);
+    }
+
+    #[test]
+    fn test_resolve_target_within_base() {
+        let resolve = |base: &str, target: &str| {
+            resolve_target_within_base(Path::new(base), Path::new(target)).map(|target| target.relative)
+        };
+        let within = |base: &str, target: &str| resolve(base, target).unwrap();
+
+        assert_eq!(within("/srv/base", "/srv/base/../base/x"), PathBuf::from("x"));
+        assert_eq!(within("/srv/base", "../base/x"), PathBuf::from("x"));
+        assert_eq!(within("/srv/base/", "prod/eu/"), PathBuf::from("prod/eu"));
+        assert_eq!(within("/srv/base", "/srv/base/"), PathBuf::new());
+        assert_eq!(within("/srv/./base", "./prod/./eu/.."), PathBuf::from("prod"));
+        assert_eq!(within("/srv/base", "/srv/base/prod/not/yet/created"), PathBuf::from("prod/not/yet/created"));
+        // Relative bases, and no base at all, as for a hierarchy in memory.
+        assert_eq!(within("../configs", "prod"), PathBuf::from("prod"));
+        assert_eq!(within("", "a/../b"), PathBuf::from("b"));
+        assert_eq!(within("", ""), PathBuf::new());
+
+        for (base, target) in [
+            ("/srv/base", ".."),
+            ("/srv/base", "prod/../../other"),
+            ("/srv/base", "/srv/base-old/x"),
+            ("/srv/base", "/srv"),
+            ("/srv/base", "/"),
+            ("/srv/base/", "/srv/base/../../../../etc"),
+            ("../configs", "../x"),
+            ("..", "../.."),
+            ("", "../elsewhere"),
+            ("", "/etc"),
+        ] {
+            let err = resolve(base, target).unwrap_err();
+            assert!(
+                matches!(&err, ConfigError::OutsideBase { base: b, target: t } if b == Path::new(base) && t == Path::new(target)),
+                "{base} {target}: {err:?}"
+            );
+        }
+
+        let target = resolve_target_within_base(Path::new("/srv/./base/"), Path::new("prod/..")).unwrap();
+        assert_eq!(target.path(), PathBuf::from("/srv/base"));
+        let target = resolve_target_within_base(Path::new("/srv/base"), Path::new("prod")).unwrap();
+        assert_eq!(target.path(), PathBuf::from("/srv/base/prod"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinked_target_component_pointing_outside() {
+        let dir = crate::testing::fixture_tree(&[("base/config.yaml", "a: 1\n"), ("outside/config.yaml", "secret: 1\n")]);
+        let base = dir.path().join("base");
+        std::os::unix::fs::symlink(dir.path().join("outside"), base.join("link")).unwrap();
+        let options = crate::MergeOptions::default();
+
+        // Lexically within the base, the target is outside once the link is
+        // followed.
+        assert!(resolve_target_within_base(&base, Path::new("link")).is_ok());
+        let err = crate::merge_hierarchy(&base, &base.join("link"), &options).unwrap_err();
+        assert!(matches!(err, ConfigError::OutsideBase { .. }), "{err:?}");
+        // `..` cannot climb out, whether the target exists or not.
+        for target in [base.join("../outside"), base.join("../missing"), base.join("link/../../outside")] {
+            let err = crate::merge_hierarchy(&base, &target, &options).unwrap_err();
+            assert!(matches!(err, ConfigError::OutsideBase { .. }), "{}: {err:?}", target.display());
+        }
+        // Nor does it get in the way when it stays within.
+        let (config, _) = crate::merge_hierarchy(&base, &base.join("../base/"), &options).unwrap();
+        assert_eq!(config["a"], 1);
+        // The base may be a link itself, with the target spelled from the
+        // directory it points to.
+        std::os::unix::fs::symlink(&base, dir.path().join("base-link")).unwrap();
+        assert!(crate::merge_hierarchy(&dir.path().join("base-link"), &base, &options).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_names_that_are_not_utf8_compare_bytewise() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let base = Path::new(OsStr::from_bytes(b"Conf\xff"));
+        let path = Path::new(OsStr::from_bytes(b"conf\xff/a.yaml"));
+        assert_eq!(relative_to_with(path, base, true), Some(Path::new("a.yaml")));
+        assert_eq!(relative_to_with(path, base, false), None);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_verbatim_and_mixed_case_windows_paths() {
+        let relative = |path: &str, base: &str| relative_to(Path::new(path), Path::new(base)).map(Path::to_path_buf);
+
+        assert_eq!(relative(r"\\?\C:\Configs\Prod\a.yaml", r"c:\configs"), Some(PathBuf::from(r"Prod\a.yaml")));
+        assert_eq!(relative(r"C:\Configs\Prod", r"\\?\c:\CONFIGS"), Some(PathBuf::from("Prod")));
+        assert_eq!(relative(r"\\?\UNC\Server\Share\prod", r"\\server\SHARE"), Some(PathBuf::from("prod")));
+        assert_eq!(relative(r"D:\configs\prod", r"C:\configs"), None);
+        assert!(is_within(Path::new(r"\\?\C:\configs"), Path::new(r"C:\Configs")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_standard_layers_precedence() {
+        let vars = |pairs: &'static [(&'static str, &'static str)]| {
+            move |name: &str| pairs.iter().find(|(key, _)| *key == name).map(|(_, value)| PathBuf::from(value))
+        };
+
+        assert_eq!(
+            standard_layers_with("app", vars(&[("HOME", "/home/me")])),
+            [PathBuf::from("/etc/app"), PathBuf::from("/etc/xdg/app"), PathBuf::from("/home/me/.config/app")]
+        );
+        assert_eq!(
+            standard_layers_with(
+                "app",
+                vars(&[
+                    ("XDG_CONFIG_DIRS", "/opt/first:relative:/opt/second:/etc"),
+                    ("XDG_CONFIG_HOME", "/xdg/home"),
+                    ("HOME", "/home/me"),
+                ])
+            ),
+            [
+                PathBuf::from("/etc/app"),
+                PathBuf::from("/opt/second/app"),
+                PathBuf::from("/opt/first/app"),
+                PathBuf::from("/xdg/home/app"),
+            ]
+        );
+        // No home to find the user directory in.
+        assert_eq!(
+            standard_layers_with("app", vars(&[("XDG_CONFIG_DIRS", ""), ("XDG_CONFIG_HOME", "rel")])),
+            [PathBuf::from("/etc/app"), PathBuf::from("/etc/xdg/app")]
+        );
+    }
+}
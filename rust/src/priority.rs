@@ -0,0 +1,124 @@
+//! Explicit merge order within a layer, set by a file's [`PRIORITY_KEY`].
+
+use std::borrow::Cow;
+use std::path::Path;
+
+use anyhow::{bail, Result};
+
+use crate::ConfigValue;
+
+/// Top-level key setting the priority of a file: `__priority__: 50`. Files of
+/// a layer merge in ascending priority, 0 when unset, then by path. The key
+/// itself is never merged.
+pub const PRIORITY_KEY: &str = "__priority__";
+
+/// The priority `config` sets, 0 when it sets none or one that is not an
+/// integer.
+pub(crate) fn file_priority(config: &ConfigValue) -> i64 {
+    config.get(PRIORITY_KEY).and_then(ConfigValue::as_i64).unwrap_or(0)
+}
+
+/// Fails when `config`, read from `path`, sets a priority that is not an
+/// integer.
+pub(crate) fn check_priority(path: &Path, config: &ConfigValue) -> Result<()> {
+    match config.get(PRIORITY_KEY) {
+        Some(priority) if priority.as_i64().is_none() => {
+            bail!("'{PRIORITY_KEY}' in {} must be an integer", path.display())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Sorts the files of a layer, given by path, by ascending priority. The sort
+/// is stable, so equal priorities keep their order.
+pub(crate) fn sort_by_priority(depth_configs: &mut [(&Path, &ConfigValue)]) {
+    depth_configs.sort_by_key(|(_, config)| file_priority(config));
+}
+
+/// The configs of one layer without their priority key.
+pub(crate) fn strip_layer_priorities<'a, 'b>(
+    depth_configs: &[(&'a Path, &'b ConfigValue)],
+) -> Vec<(&'a Path, Cow<'b, ConfigValue>)> {
+    depth_configs
+        .iter()
+        .map(|(path, config)| {
+            let config = match config {
+                ConfigValue::Mapping(map) if map.contains_key(PRIORITY_KEY) => {
+                    let mut map = map.clone();
+                    map.remove(PRIORITY_KEY);
+                    Cow::Owned(ConfigValue::Mapping(map))
+                }
+                config => Cow::Borrowed(*config),
+            };
+            (*path, config)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::MergeOptions;
+    use crate::report::ReportKind;
+    use crate::testing::fixture_tree;
+
+    #[test]
+    fn test_priority_beats_filename_order() {
+        let dir = fixture_tree(&[
+            ("a.yaml", "__priority__: 10\nport: 1\nname: a\n"),
+            ("b.yaml", "port: 2\nname: b\n"),
+            ("c.yaml", "port: 3\n"),
+        ]);
+        let (config, report) = crate::merge_hierarchy(dir.path(), dir.path(), &MergeOptions::default()).unwrap();
+
+        // a.yaml merges last despite its name, and its priority is not merged.
+        assert_eq!(config, serde_yaml::from_str::<ConfigValue>("port: 1\nname: a\n").unwrap());
+        // b.yaml and c.yaml share the default priority and still collide.
+        let collisions: Vec<_> = report
+            .entries
+            .iter()
+            .map(|entry| (entry.kind, entry.key_path.as_deref().unwrap(), entry.files.len()))
+            .collect();
+        assert_eq!(collisions, [(ReportKind::Collision, "port", 2)]);
+        assert!(report.entries[0].files[1].ends_with("c.yaml"));
+    }
+
+    #[test]
+    fn test_priority_key_is_stripped_everywhere() {
+        let dir = fixture_tree(&[
+            ("config.yaml", "__priority__: -1\nport: 80\n"),
+            ("prod/x.yaml", "__priority__: 2\nport: 8080\n"),
+            ("prod/y.yaml", "__priority__: 1\nport: 9090\n"),
+        ]);
+        let prod = dir.path().join("prod");
+        let options = MergeOptions {
+            lock_top_level: true,
+            ..MergeOptions::default()
+        };
+        let (config, report) = crate::merge_hierarchy(dir.path(), &prod, &options).unwrap();
+        assert_eq!(config, serde_yaml::from_str::<ConfigValue>("port: 8080\n").unwrap());
+        assert!(report.entries.is_empty(), "{:?}", report.entries);
+
+        let (traced, _, provenance) = crate::merge_hierarchy_with_provenance(dir.path(), &prod, &options).unwrap();
+        assert_eq!(traced, config);
+        assert!(provenance.iter().all(|(path, _)| path != PRIORITY_KEY));
+
+        let mut merger = crate::HierarchyMerger::new(dir.path(), options.clone());
+        assert_eq!(*merger.merge(&prod).unwrap(), config);
+
+        let configs = std::collections::HashMap::from([
+            ("a/one.yaml", serde_yaml::from_str::<ConfigValue>("__priority__: 1\nport: 1\n").unwrap()),
+            ("a/two.yaml", serde_yaml::from_str::<ConfigValue>("port: 2\n").unwrap()),
+        ]);
+        let (merged, warnings) = crate::merge_configs_by_depth(configs).unwrap();
+        assert_eq!(merged, serde_yaml::from_str::<ConfigValue>("port: 1\n").unwrap());
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_priority_must_be_an_integer() {
+        let dir = fixture_tree(&[("config.yaml", "__priority__: high\n")]);
+        let err = crate::merge_hierarchy(dir.path(), dir.path(), &MergeOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("'__priority__'"), "{err}");
+    }
+}
@@ -0,0 +1,345 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+
+use crate::cancel::{arm, check_cancelled};
+use crate::coerce::coerce_override;
+use crate::error::ConfigError;
+use crate::deprecation::apply_layer_deprecations;
+use crate::limits::{measure, SizeGuard};
+use crate::metadata::embed_metadata;
+use crate::priority::strip_layer_priorities;
+use crate::layered::Layer;
+use crate::keypath::{format_key_path, key_to_string, parse_key_path, PathSegment};
+use crate::options::{CollisionPolicy, MergeOptions};
+use crate::report::MergeReport;
+use crate::{
+    check_merge_files, collect_depth_collisions, collect_lock_violations, empty_merge, finish_merge, group_by_depth, load_hierarchy, trace, FsSource, ConfigValue, LayerKey,
+};
+
+/// Source of the values taken from [`MergeOptions::defaults`].
+pub const DEFAULTS_SOURCE: &str = "defaults";
+
+/// The file each value of a merged config comes from.
+///
+/// Entries are kept for leaves: scalars, sequences (which merge as a whole)
+/// and empty mappings. A value that was overridden or replaced by a non-mapping
+/// has no entry left.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Provenance {
+    sources: BTreeMap<Vec<PathSegment>, PathBuf>,
+}
+
+impl Provenance {
+    /// File that defined the value at a dotted key path. Paths inside a leaf,
+    /// such as an item of a sequence, resolve to the leaf's file.
+    pub fn source(&self, path: &str) -> Result<Option<&Path>, ConfigError> {
+        Ok(self.source_of(&parse_key_path(path)?))
+    }
+
+    /// Like [`Provenance::source`], for parsed segments.
+    pub fn source_of(&self, segments: &[PathSegment]) -> Option<&Path> {
+        (0..=segments.len())
+            .rev()
+            .find_map(|len| self.sources.get(&segments[..len]))
+            .map(PathBuf::as_path)
+    }
+
+    /// Every leaf as a dotted key path with its file, in key path order.
+    pub fn iter(&self) -> impl Iterator<Item = (String, &Path)> {
+        self.sources
+            .iter()
+            .map(|(segments, source)| (format_key_path(segments), source.as_path()))
+    }
+
+    /// Every leaf as parsed segments with its file, in key path order.
+    pub(crate) fn leaves(&self) -> impl Iterator<Item = (&[PathSegment], &Path)> {
+        self.sources.iter().map(|(segments, source)| (segments.as_slice(), source.as_path()))
+    }
+
+    /// The files of the leaves at or below `segments`.
+    pub(crate) fn sources_below<'a>(&'a self, segments: &'a [PathSegment]) -> impl Iterator<Item = &'a Path> {
+        self.sources
+            .range(segments.to_vec()..)
+            .take_while(move |(path, _)| path.starts_with(segments))
+            .map(|(_, source)| source.as_path())
+    }
+
+    pub fn len(&self) -> usize {
+        self.sources.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+
+    /// The leaves of [`MergeOptions::defaults`], attributed to
+    /// [`DEFAULTS_SOURCE`].
+    pub(crate) fn of_defaults(options: &MergeOptions) -> Self {
+        let mut provenance = Provenance::default();
+        if let Some(defaults) = &options.defaults {
+            provenance.record(&mut Vec::new(), defaults, Path::new(DEFAULTS_SOURCE));
+        }
+        provenance
+    }
+
+    /// Forgets `path` and everything below it, then records the leaves of
+    /// `value` as coming from `source`.
+    fn replace(&mut self, path: &mut Vec<PathSegment>, value: &ConfigValue, source: &Path) {
+        self.forget(path);
+        self.record(path, value, source);
+    }
+
+    fn forget(&mut self, path: &[PathSegment]) {
+        let below: Vec<_> = self
+            .sources
+            .range(path.to_vec()..)
+            .take_while(|(key, _)| key.starts_with(path))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in below {
+            self.sources.remove(&key);
+        }
+    }
+
+    fn record(&mut self, path: &mut Vec<PathSegment>, value: &ConfigValue, source: &Path) {
+        match value {
+            ConfigValue::Mapping(map) if !map.is_empty() => {
+                for (key, item) in map {
+                    path.push(PathSegment::Key(key_to_string(key)));
+                    self.record(path, item, source);
+                    path.pop();
+                }
+            }
+            _ => {
+                self.sources.insert(path.clone(), source.to_path_buf());
+            }
+        }
+    }
+}
+
+/// [`crate::deep_merge_with`] that also updates `provenance` for the values
+/// taken from `r#override`, which was read from `source`. A sequence combined
+/// by a non-replacing strategy is attributed to its last contributor.
+pub(crate) fn deep_merge_traced(
+    base: &ConfigValue,
+    r#override: &ConfigValue,
+    options: &MergeOptions,
+    source: &Path,
+    path: &mut Vec<PathSegment>,
+    provenance: &mut Provenance,
+) -> ConfigValue {
+    match (base, r#override) {
+        (ConfigValue::Mapping(base_map), ConfigValue::Mapping(override_map)) => {
+            // An empty base mapping was a leaf; it is now merged into.
+            provenance.sources.remove(path.as_slice());
+            let mut result = base_map.clone();
+            for (key, value) in override_map {
+                path.push(PathSegment::Key(key_to_string(key)));
+                if options.null_deletes && value.is_null() {
+                    provenance.forget(path);
+                    result.remove(key);
+                    path.pop();
+                    continue;
+                }
+                let base_value = match result.get(key) {
+                    Some(base_value) => base_value,
+                    None if options.null_deletes && value.is_mapping() => {
+                        &ConfigValue::Mapping(serde_yaml::Mapping::new())
+                    }
+                    None => {
+                        provenance.replace(path, value, source);
+                        result.insert(key.clone(), value.clone());
+                        path.pop();
+                        continue;
+                    }
+                };
+                let merged = deep_merge_traced(base_value, value, options, source, path, provenance);
+                result.insert(key.clone(), merged);
+                path.pop();
+            }
+            if result.is_empty() && !path.is_empty() {
+                provenance.sources.insert(path.clone(), source.to_path_buf());
+            }
+            ConfigValue::Mapping(result)
+        }
+        (ConfigValue::Sequence(base_items), ConfigValue::Sequence(override_items)) => {
+            let merged = ConfigValue::Sequence(options.sequence_strategy.combine(base_items, override_items));
+            provenance.replace(path, &merged, source);
+            merged
+        }
+        _ => {
+            provenance.replace(path, r#override, source);
+            r#override.clone()
+        }
+    }
+}
+
+/// Like [`crate::merge_hierarchy`], also returning where every value of the
+/// merged config was defined.
+pub fn merge_hierarchy_with_provenance(
+    base_dir: &Path,
+    target_path: &Path,
+    options: &MergeOptions,
+) -> Result<(ConfigValue, MergeReport, Provenance), ConfigError> {
+    let options = &arm(options);
+    let base_dir = &options.input_path(base_dir)?;
+    let target_path = &options.input_path(target_path)?;
+
+    let Some(loaded) = load_hierarchy(&FsSource, base_dir, target_path, options)? else {
+        let (config, report) = empty_merge(base_dir, target_path, options)?;
+        return Ok((config, report, Provenance::of_defaults(options)));
+    };
+
+    let (merged_config, mut report, provenance) = {
+        let _span = trace::merge_span(loaded.configs.len());
+        merge_layers_traced(loaded.layers(), options)?
+    };
+    loaded.check_types(&merged_config, &mut report);
+    loaded.complete_report(&mut report);
+    options.check_report(&report)?;
+    let merged_config = embed_metadata(merged_config, Some(base_dir), Some(target_path), &report, options);
+    Ok((merged_config, report, provenance))
+}
+
+/// Like [`crate::merge_layered`], also returning where every value of the
+/// merged config was defined, by layer name.
+pub fn merge_layered_with_provenance(
+    layers: Vec<Layer>,
+    options: &MergeOptions,
+) -> Result<(ConfigValue, MergeReport, Provenance), ConfigError> {
+    let options = &arm(options);
+    let (merged_config, report, provenance) = {
+        let _span = trace::merge_span(layers.len());
+        let configs = layers
+            .iter()
+            .map(|layer| ((layer.priority, 0), Path::new(&layer.name), &layer.value));
+        merge_layers_traced(configs, options)?
+    };
+    options.check_report(&report)?;
+    let merged_config = embed_metadata(merged_config, None, None, &report, options);
+    Ok((merged_config, report, provenance))
+}
+
+/// [`crate::merge_layers_with_report`] tracking provenance.
+pub(crate) fn merge_layers_traced<'a, I>(
+    configs: I,
+    options: &MergeOptions,
+) -> Result<(ConfigValue, MergeReport, Provenance)>
+where
+    I: IntoIterator<Item = (LayerKey, &'a Path, &'a ConfigValue)>,
+{
+    let mut merged_config = options.initial_config();
+    let mut report = MergeReport::default();
+    let mut provenance = Provenance::of_defaults(options);
+
+    let groups = group_by_depth(configs);
+    let files: Vec<_> = groups.iter().flat_map(|(_, group)| group.iter().copied()).collect();
+    check_merge_files(&files, options, &mut report.entries)?;
+    let mut guard = SizeGuard::new(&merged_config, options);
+    for (index, ((depth, _), depth_configs)) in groups.into_iter().enumerate() {
+        check_cancelled(options)?;
+        let renamed = apply_layer_deprecations(&depth_configs, options, &mut report.entries)?;
+        let depth_configs: Vec<_> = renamed.iter().map(|(path, config)| (*path, config.as_ref())).collect();
+        if options.collision_policy != CollisionPolicy::Ignore {
+            collect_depth_collisions(depth, &depth_configs, options, &mut report.entries);
+        }
+        let stripped = strip_layer_priorities(&depth_configs);
+        let depth_configs: Vec<_> = stripped.iter().map(|(path, config)| (*path, config.as_ref())).collect();
+        collect_lock_violations(&merged_config, &depth_configs, index == 0, options, &mut report.entries);
+        for (source, config) in &depth_configs {
+            let config = &coerce_override(&merged_config, config, Some(source), options, &mut report.entries)?;
+            merged_config =
+                deep_merge_traced(&merged_config, config, options, source, &mut Vec::new(), &mut provenance);
+            guard.add(config);
+            guard.check(&merged_config, source, options)?;
+        }
+        trace::merged_layer(depth, depth_configs.len());
+    }
+    let merged_config = finish_merge(merged_config, &files, options, &mut report.entries)?;
+    report.stats = measure(&merged_config);
+    Ok((merged_config, report, provenance))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_provenance_names_winning_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let leaf = dir.path().join("prod");
+        fs::create_dir_all(&leaf).unwrap();
+        fs::write(
+            dir.path().join("config.yaml"),
+            "server:\n  host: base\n  port: 80\nlogging:\n  level: info\n  file: app.log\nlist: [1, 2]\n",
+        )
+        .unwrap();
+        fs::write(leaf.join("config.yaml"), "server:\n  port: 8080\nlogging: off\n").unwrap();
+
+        let (config, _, provenance) =
+            merge_hierarchy_with_provenance(dir.path(), &leaf, &MergeOptions::default()).unwrap();
+        let base_file = dir.path().canonicalize().unwrap().join("config.yaml");
+        let leaf_file = leaf.canonicalize().unwrap().join("config.yaml");
+
+        assert_eq!(config["server"]["port"], 8080);
+        assert_eq!(
+            provenance.iter().collect::<Vec<_>>(),
+            vec![
+                ("list".to_string(), base_file.as_path()),
+                ("logging".to_string(), leaf_file.as_path()),
+                ("server.host".to_string(), base_file.as_path()),
+                ("server.port".to_string(), leaf_file.as_path()),
+            ]
+        );
+        assert_eq!(provenance.source("list[1]").unwrap(), Some(base_file.as_path()));
+        assert_eq!(provenance.source("logging.level").unwrap(), Some(leaf_file.as_path()));
+        assert_eq!(provenance.source("missing").unwrap(), None);
+    }
+
+    #[test]
+    fn test_empty_mapping_is_a_leaf_until_merged_into() {
+        let mut provenance = Provenance::default();
+        let empty: ConfigValue = serde_yaml::from_str("a: {}").unwrap();
+        let filled: ConfigValue = serde_yaml::from_str("a: {b: 1}").unwrap();
+
+        let merged = deep_merge_traced(
+            &ConfigValue::Mapping(Default::default()),
+            &empty,
+            &MergeOptions::default(),
+            Path::new("one.yaml"),
+            &mut Vec::new(),
+            &mut provenance,
+        );
+        assert_eq!(provenance.source("a").unwrap(), Some(Path::new("one.yaml")));
+
+        deep_merge_traced(
+            &merged,
+            &filled,
+            &MergeOptions::default(),
+            Path::new("two.yaml"),
+            &mut Vec::new(),
+            &mut provenance,
+        );
+        assert_eq!(
+            provenance.iter().collect::<Vec<_>>(),
+            vec![("a.b".to_string(), Path::new("two.yaml"))]
+        );
+    }
+
+    #[test]
+    fn test_defaults_are_a_source() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("config.yaml"), "server: {port: 8080}\n").unwrap();
+        let options = MergeOptions {
+            defaults: Some(serde_yaml::from_str("server: {port: 80, workers: 4}").unwrap()),
+            ..MergeOptions::default()
+        };
+
+        let (_, _, provenance) = merge_hierarchy_with_provenance(dir.path(), dir.path(), &options).unwrap();
+        let file = dir.path().canonicalize().unwrap().join("config.yaml");
+        assert_eq!(provenance.source("server.port").unwrap(), Some(file.as_path()));
+        assert_eq!(provenance.source("server.workers").unwrap(), Some(Path::new(DEFAULTS_SOURCE)));
+    }
+}
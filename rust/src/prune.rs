@@ -0,0 +1,152 @@
+//! Removal of subtrees from a merged config, see
+//! [`MergeOptions::prune_paths`].
+
+use crate::keypath::{format_key_path, key_to_string, PathSegment};
+use crate::options::MergeOptions;
+use crate::redact::glob_match;
+use crate::report::{ReportEntry, ReportKind};
+use crate::ConfigValue;
+
+/// Removes the values at key paths matching one of `patterns` from `config`,
+/// returning the dotted paths removed, in document order.
+///
+/// A pattern is matched against the whole dotted key path, such as
+/// `internal.token` or `services[0].debug`: `*` matches any run of
+/// characters, dots included, and `?` one character. Unlike redaction,
+/// matching is case-sensitive. Nothing below a removed value is visited, and
+/// the root itself is never removed.
+///
+/// ```
+/// # use hierarchical_config_merging::{prune, ConfigValue};
+/// let mut config: ConfigValue = serde_yaml::from_str("internal: {a: 1}\nsecrets: x\nport: 80").unwrap();
+/// let pruned = prune(&mut config, &["internal.*", "secrets"]);
+/// assert_eq!(pruned, ["internal.a", "secrets"]);
+/// assert_eq!(config, serde_yaml::from_str::<ConfigValue>("internal: {}\nport: 80").unwrap());
+/// ```
+pub fn prune<S: AsRef<str>>(config: &mut ConfigValue, patterns: &[S]) -> Vec<String> {
+    let mut pruned = Vec::new();
+    if !patterns.is_empty() {
+        prune_below(config, patterns, &mut Vec::new(), &mut pruned);
+    }
+    pruned
+}
+
+fn prune_below<S: AsRef<str>>(
+    value: &mut ConfigValue,
+    patterns: &[S],
+    path: &mut Vec<PathSegment>,
+    pruned: &mut Vec<String>,
+) {
+    match value {
+        ConfigValue::Mapping(map) => {
+            let mut kept = serde_yaml::Mapping::with_capacity(map.len());
+            for (key, mut item) in std::mem::take(map) {
+                path.push(PathSegment::Key(key_to_string(&key)));
+                if !matches(patterns, path, pruned) {
+                    prune_below(&mut item, patterns, path, pruned);
+                    kept.insert(key, item);
+                }
+                path.pop();
+            }
+            *map = kept;
+        }
+        ConfigValue::Sequence(items) => {
+            let mut kept = Vec::with_capacity(items.len());
+            for (index, mut item) in std::mem::take(items).into_iter().enumerate() {
+                path.push(PathSegment::Index(index));
+                if !matches(patterns, path, pruned) {
+                    prune_below(&mut item, patterns, path, pruned);
+                    kept.push(item);
+                }
+                path.pop();
+            }
+            *items = kept;
+        }
+        _ => {}
+    }
+}
+
+/// Whether `path` matches one of `patterns`; if so it is added to `pruned`.
+fn matches<S: AsRef<str>>(patterns: &[S], path: &[PathSegment], pruned: &mut Vec<String>) -> bool {
+    let dotted = format_key_path(path);
+    let matched = patterns
+        .iter()
+        .any(|pattern| glob_match(pattern.as_ref().as_bytes(), dotted.as_bytes()));
+    if matched {
+        pruned.push(dotted);
+    }
+    matched
+}
+
+/// [`prune`] with [`MergeOptions::prune_paths`], adding an entry for every
+/// removed path to `entries`.
+pub(crate) fn prune_merged(mut config: ConfigValue, options: &MergeOptions, entries: &mut Vec<ReportEntry>) -> ConfigValue {
+    for path in prune(&mut config, &options.prune_paths) {
+        entries.push(ReportEntry {
+            kind: ReportKind::Pruned,
+            message: format!("Pruned '{path}'"),
+            key_path: Some(path),
+            files: Vec::new(),
+        });
+    }
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn yaml(text: &str) -> ConfigValue {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn test_wildcards_reach_into_sequences_of_mappings() {
+        let mut config = yaml(
+            "services:\n  - {name: api, debug: true, internal: {id: 1}}\n  - {name: web, debug: false}\n\
+             internal: {token: x, nested: {a: 1}}\nsecrets: [a, b]\nInternal: kept\n",
+        );
+        let pruned = prune(&mut config, &["services[*].debug", "*.internal", "internal.*", "secrets"]);
+        assert_eq!(
+            pruned,
+            ["services[0].debug", "services[0].internal", "services[1].debug", "internal.token", "internal.nested", "secrets"]
+        );
+        assert_eq!(
+            config,
+            yaml("services:\n  - {name: api}\n  - {name: web}\ninternal: {}\nInternal: kept\n")
+        );
+
+        let mut items = yaml("items: [a, b, c]\n");
+        assert_eq!(prune(&mut items, &["items[1]"]), ["items[1]"]);
+        assert_eq!(items, yaml("items: [a, c]\n"));
+    }
+
+    #[test]
+    fn test_pattern_matching_nothing_changes_nothing() {
+        let original = yaml("server: {port: 80}\nlist: [{a: 1}]\n");
+        let mut config = original.clone();
+        assert!(prune(&mut config, &["missing.*", "server.port.deeper", "list[1]"]).is_empty());
+        assert!(prune(&mut config, &[] as &[&str]).is_empty());
+        assert_eq!(config, original);
+    }
+
+    #[test]
+    fn test_merges_prune_after_interpolation() {
+        let dir = crate::testing::fixture_tree(&[(
+            "config.yaml",
+            "internal: {host: db.local}\nurl: 'postgres://${internal.host}'\n",
+        )]);
+        let options = MergeOptions {
+            interpolate: true,
+            prune_paths: vec!["internal".to_string()],
+            ..MergeOptions::default()
+        };
+
+        let (config, report) = crate::merge_hierarchy(dir.path(), dir.path(), &options).unwrap();
+        assert_eq!(config, yaml("url: postgres://db.local\n"));
+        assert_eq!(report.entries.len(), 1);
+        assert_eq!(report.entries[0].kind, ReportKind::Pruned);
+        assert_eq!(report.entries[0].key_path.as_deref(), Some("internal"));
+        assert!(report.warnings().is_empty());
+    }
+}
@@ -1,68 +1,1856 @@
+// pyo3 0.20's macro expansions predate edition 2024's `unsafe_op_in_unsafe_fn`
+// lint and the `non_local_definitions` lint.
+#![allow(unsafe_op_in_unsafe_fn, non_local_definitions)]
+
+use pyo3::create_exception;
+use pyo3::exceptions::{PyFileNotFoundError, PyKeyError, PyRuntimeError, PyTypeError, PyValueError};
 use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyDate, PyDateTime, PyDelta, PyDict, PyFloat, PyList, PyLong, PyString, PyTuple, PyTzInfo};
 use pyo3::wrap_pyfunction;
-use std::path::PathBuf;
-use crate::{merge_hierarchical_configs, ConfigValue};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use crate::cli;
+use crate::coerce::coerce_override;
+use crate::interpolate::interpolate_merged;
+use crate::normalize::normalize_merged;
+use crate::casing::case_merged_keys;
+use crate::prune::prune_merged;
+use crate::units::normalize_units;
+use crate::keypath::{format_key_path, get_segments, key_to_string, parse_key_path, PathSegment};
+use crate::report::{MergeReport, ReportEntry, ReportKind};
+use crate::{
+    compute_override, config_hash, generate_docs, deep_merge_resolving, deep_merge_with, find_layer_files, merge_files, merge_hierarchy, merge_hierarchy_resolving,
+    merge_hierarchy_with_provenance, redact, write_lockfile, to_env_exports, to_json, to_properties_string_with, to_yaml, ConfigError,
+    ConfigValue, DisplayTree, EnvOptions, HierarchyMerger, MergeOptions, PropertiesOptions, Redaction, TreeOptions, UnknownOptionValue,
+};
+
+create_exception!(
+    hierarchical_config_merging,
+    HierarchicalConfigError,
+    PyRuntimeError,
+    "Base class of the errors raised by the merge functions. Attributes: report_json, the failure as a JSON report."
+);
+create_exception!(
+    hierarchical_config_merging,
+    ParseError,
+    HierarchicalConfigError,
+    "A config file is not valid YAML. Attributes: path, line, column."
+);
+create_exception!(
+    hierarchical_config_merging,
+    HierarchyError,
+    HierarchicalConfigError,
+    "The base or target path cannot be merged. Attributes: base_dir, target_path (also as base, target)."
+);
+create_exception!(
+    hierarchical_config_merging,
+    CollisionError,
+    HierarchicalConfigError,
+    "Keys collided with collision_policy='error', or a strict merge reported warnings. Attributes: entries, collisions."
+);
+create_exception!(
+    hierarchical_config_merging,
+    ConflictError,
+    HierarchicalConfigError,
+    "The on_conflict callback failed; its exception is the __cause__. Attributes: path."
+);
+
+/// Keyword arguments of the functions merging a hierarchy.
+const MERGE_OPTIONS: &[&str] = &[
+    "expand_paths",
+    "strict",
+    "fail_on_empty",
+    "sequence_strategy",
+    "collision_policy",
+    "extensions",
+    "filename",
+    "profiles",
+    "selectors",
+    "features",
+    "null_deletes",
+    "mode",
+    "aliases",
+    "deprecated_paths",
+    "redact_patterns",
+    "interpolate",
+    "compose_defaults",
+    "coerce_types",
+    "coercion_failure",
+    "index_patches",
+    "index_out_of_range",
+    "report_duplicates",
+    "report_shadowed_files",
+    "defaults",
+    "max_merge_depth",
+    "max_merged_nodes",
+    "max_merged_bytes_estimate",
+    "timeout",
+    "prune_paths",
+    "lock_top_level",
+    "lock_exempt_sections",
+    "collision_exempt_paths",
+    "report_exempt_collisions",
+    "skip_unreadable",
+    "embed_metadata",
+    "metadata_key",
+    "type_check",
+    "units",
+    "key_case",
+    "target_mode",
+];
+
+/// Keyword arguments of `rust_merge_files`, besides `base_dir`.
+const FILES_OPTIONS: &[&str] = &[
+    "expand_paths",
+    "strict",
+    "sequence_strategy",
+    "collision_policy",
+    "selectors",
+    "features",
+    "null_deletes",
+    "mode",
+    "aliases",
+    "deprecated_paths",
+    "redact_patterns",
+    "interpolate",
+    "compose_defaults",
+    "coerce_types",
+    "coercion_failure",
+    "index_patches",
+    "index_out_of_range",
+    "report_duplicates",
+    "report_shadowed_files",
+    "defaults",
+    "max_merged_nodes",
+    "max_merged_bytes_estimate",
+    "timeout",
+    "prune_paths",
+    "lock_top_level",
+    "lock_exempt_sections",
+    "collision_exempt_paths",
+    "report_exempt_collisions",
+    "skip_unreadable",
+    "embed_metadata",
+    "metadata_key",
+    "units",
+    "key_case",
+];
+
+/// Keyword arguments of `rust_deep_merge`.
+const DEEP_MERGE_OPTIONS: &[&str] = &["sequence_strategy", "null_deletes", "mode"];
+
+/// Keyword arguments of the functions returning a config.
+const CONVERSION_OPTIONS: &[&str] = &["parse_datetimes", "preserve_tags"];
+
+/// Keyword arguments of the functions returning a report.
+const REPORT_OPTIONS: &[&str] = &["log_warnings"];
+
+/// Name of the `logging` logger used with `log_warnings=True`. Stable, and
+/// exported as `LOGGER_NAME`.
+const LOGGER_NAME: &str = "hierarchical_config_merging";
+
+/// How a config is converted to Python objects.
+#[derive(Debug, Clone, Copy, Default)]
+struct Conversion {
+    /// Strings in YAML timestamp format become `datetime.datetime`, dates
+    /// such as `2024-01-01` become `datetime.date`.
+    parse_datetimes: bool,
+    /// Tagged values become `TaggedValue` objects instead of their inner
+    /// value. Tags on mapping keys are always dropped.
+    preserve_tags: bool,
+}
+
+/// Keyword arguments handled by the bindings rather than the merge.
+#[derive(Debug, Clone, Copy, Default)]
+struct BindingOptions {
+    conversion: Conversion,
+    /// Every report entry is logged at WARNING (INFO for informational
+    /// entries) as the merge completes, and a failed merge at ERROR, besides
+    /// being returned or raised.
+    log_warnings: bool,
+}
+
+/// Options for the Python entry points, built from their keyword arguments.
+/// Unlike the Rust API, `~` and `$VAR` in path arguments are expanded unless
+/// `expand_paths=False` is passed. Unknown names raise `TypeError`, invalid
+/// values `ValueError`.
+fn python_options(
+    function: &str,
+    kwargs: Option<&PyDict>,
+    allowed: &[&[&str]],
+) -> PyResult<(MergeOptions, BindingOptions)> {
+    let mut options = MergeOptions {
+        expand_paths: true,
+        ..MergeOptions::default()
+    };
+    let mut binding = BindingOptions::default();
+    let Some(kwargs) = kwargs else {
+        return Ok((options, binding));
+    };
+
+    for (name, value) in kwargs {
+        let name: &str = name.extract()?;
+        if !allowed.iter().any(|group| group.contains(&name)) {
+            return Err(PyTypeError::new_err(format!(
+                "{function}() got an unexpected keyword argument '{name}'; expected one of {}",
+                allowed.concat().join(", ")
+            )));
+        }
+        match name {
+            "expand_paths" => options.expand_paths = value.extract()?,
+            "strict" => options.strict = value.extract()?,
+            "fail_on_empty" => options.fail_on_empty = value.extract()?,
+            "sequence_strategy" => options.sequence_strategy = parse_choice(value)?,
+            "collision_policy" => options.collision_policy = parse_choice(value)?,
+            "filename" => options.filename = value.extract()?,
+            "extensions" => {
+                let extensions: Vec<String> = value.extract()?;
+                if extensions.is_empty() || extensions.iter().any(|ext| ext.trim_start_matches('.').is_empty()) {
+                    return Err(PyValueError::new_err(
+                        "Invalid extensions: expected a non-empty list of extensions such as ['.yaml', '.json']",
+                    ));
+                }
+                options.extensions = Some(extensions);
+            }
+            "profiles" => options.profiles = value.extract()?,
+            "selectors" => {
+                let selectors: std::collections::BTreeMap<String, String> = value.extract()?;
+                options.selectors = selectors.into_iter().collect();
+            }
+            "features" => options.features = value.extract()?,
+            "null_deletes" => options.null_deletes = value.extract()?,
+            "mode" => options.mode = parse_choice(value)?,
+            "aliases" => options.aliases = value.extract()?,
+            "deprecated_paths" => options.deprecated_paths = value.extract()?,
+            "redact_patterns" => options.redaction.patterns = value.extract()?,
+            "interpolate" => options.interpolate = value.extract()?,
+            "compose_defaults" => options.compose_defaults = value.extract()?,
+            "coerce_types" => options.coerce_types = value.extract()?,
+            "coercion_failure" => options.coercion_failure = parse_choice(value)?,
+            "index_patches" => options.index_patches = value.extract()?,
+            "index_out_of_range" => options.index_out_of_range = parse_choice(value)?,
+            "report_duplicates" => options.report_duplicates = value.extract()?,
+            "report_shadowed_files" => options.report_shadowed_files = value.extract()?,
+            "defaults" => options.defaults = Some(python_to_config(value, &mut Vec::new())?),
+            "max_merge_depth" => options.max_merge_depth = value.extract()?,
+            "max_merged_nodes" => options.max_merged_nodes = value.extract()?,
+            "max_merged_bytes_estimate" => options.max_merged_bytes_estimate = value.extract()?,
+            "timeout" => {
+                let seconds: Option<f64> = value.extract()?;
+                options.timeout = seconds
+                    .map(Duration::try_from_secs_f64)
+                    .transpose()
+                    .map_err(|e| PyValueError::new_err(format!("Invalid timeout: {e}")))?;
+            }
+            "prune_paths" => options.prune_paths = value.extract()?,
+            "lock_top_level" => options.lock_top_level = value.extract()?,
+            "lock_exempt_sections" => options.lock_exempt_sections = value.extract()?,
+            "collision_exempt_paths" => options.collision_exempt_paths = value.extract()?,
+            "report_exempt_collisions" => options.report_exempt_collisions = value.extract()?,
+            "skip_unreadable" => options.skip_unreadable = value.extract()?,
+            "embed_metadata" => options.embed_metadata = value.extract()?,
+            "metadata_key" => options.metadata_key = value.extract()?,
+            "type_check" => options.type_check = parse_choice(value)?,
+            "target_mode" => options.target_mode = parse_choice(value)?,
+            "key_case" => options.key_case = (!value.is_none()).then(|| parse_choice(value)).transpose()?,
+            "units" => {
+                let units: Vec<(String, &PyAny)> = value.extract()?;
+                options.units = units
+                    .into_iter()
+                    .map(|(pattern, unit)| Ok((pattern, parse_choice(unit)?)))
+                    .collect::<PyResult<_>>()?;
+            }
+            "parse_datetimes" => binding.conversion.parse_datetimes = value.extract()?,
+            "preserve_tags" => binding.conversion.preserve_tags = value.extract()?,
+            "log_warnings" => binding.log_warnings = value.extract()?,
+            _ => unreachable!("option '{name}' is allowed but not handled"),
+        }
+    }
+    Ok((options, binding))
+}
+
+fn parse_choice<T: FromStr<Err = UnknownOptionValue>>(value: &PyAny) -> PyResult<T> {
+    let name: &str = value.extract()?;
+    name.parse().map_err(|e: UnknownOptionValue| PyValueError::new_err(e.to_string()))
+}
+
+fn entries_to_python(entries: &[ReportEntry], py: Python) -> PyResult<PyObject> {
+    let list = PyList::empty(py);
+    for entry in entries {
+        let item = PyDict::new(py);
+        item.set_item("kind", entry.kind.name())?;
+        item.set_item("message", &entry.message)?;
+        list.append(item)?;
+    }
+    Ok(list.to_object(py))
+}
+
+/// The collisions among `entries` as `(key_path, files)` tuples.
+fn collisions_to_python(entries: &[ReportEntry], py: Python) -> PyResult<PyObject> {
+    let list = PyList::empty(py);
+    for entry in entries.iter().filter(|entry| entry.kind == ReportKind::Collision) {
+        list.append((&entry.key_path, &entry.files).to_object(py))?;
+    }
+    Ok(list.to_object(py))
+}
+
+fn log(py: Python, level: &str, message: &str) -> PyResult<()> {
+    let logger = py.import("logging")?.call_method1("getLogger", (LOGGER_NAME,))?;
+    logger.call_method1(level, ("%s", message))?;
+    Ok(())
+}
+
+/// Logs the entries of a completed merge when `log_warnings` is set.
+fn log_report(py: Python, binding: BindingOptions, report: &MergeReport) -> PyResult<()> {
+    if binding.log_warnings {
+        for entry in &report.entries {
+            log(py, if entry.kind.is_warning() { "warning" } else { "info" }, &entry.message)?;
+        }
+    }
+    Ok(())
+}
+
+/// [`merge_error_to_python`], logging the error first when `log_warnings` is
+/// set.
+fn merge_failed(
+    py: Python,
+    binding: BindingOptions,
+    e: impl Into<anyhow::Error>,
+    base_dir: &Path,
+    target_path: &Path,
+) -> PyErr {
+    let err = merge_error_to_python(py, e.into(), base_dir, target_path);
+    if binding.log_warnings
+        && let Err(log_err) = log(py, "error", &err.value(py).to_string())
+    {
+        return log_err;
+    }
+    err
+}
+
+/// Maps a failed merge onto the exception hierarchy. Missing or unexpandable
+/// paths raise `HierarchyError` with the paths as given; errors without a more
+/// specific class, such as unreadable files, raise `HierarchicalConfigError`.
+fn merge_error_to_python(py: Python, e: anyhow::Error, base_dir: &Path, target_path: &Path) -> PyErr {
+    let e = unwrap_other(e);
+    let message = e.to_string();
+    let report_json = match e.downcast_ref::<ConfigError>() {
+        Some(error) => MergeReport::failure_json(error),
+        None => MergeReport::failure_json(&ConfigError::Other(anyhow::anyhow!(message.clone()))),
+    };
+    let paths = |base: &Path, target: &Path| {
+        vec![
+            ("base", base.to_object(py)),
+            ("target", target.to_object(py)),
+            ("base_dir", base.to_object(py)),
+            ("target_path", target.to_object(py)),
+        ]
+    };
+    let (err, mut attributes) = match e.downcast_ref::<ConfigError>() {
+        Some(ConfigError::Parse { path, line, column, .. }) => (
+            ParseError::new_err(message),
+            vec![
+                ("path", path.to_object(py)),
+                ("line", line.to_object(py)),
+                ("column", column.to_object(py)),
+            ],
+        ),
+        Some(ConfigError::OutsideBase { base, target } | ConfigError::EmptyHierarchy { base, target }) => {
+            (HierarchyError::new_err(message), paths(base, target))
+        }
+        Some(ConfigError::Strict { entries } | ConfigError::Collision { entries }) => {
+            match (entries_to_python(entries, py), collisions_to_python(entries, py)) {
+                (Ok(entries), Ok(collisions)) => (
+                    CollisionError::new_err(message),
+                    vec![("entries", entries), ("collisions", collisions)],
+                ),
+                (Err(err), _) | (_, Err(err)) => return err,
+            }
+        }
+        Some(ConfigError::Conflict { path, source }) => {
+            let err = ConflictError::new_err(format!("{e:#}"));
+            if let Some(cause) = source.downcast_ref::<PyErr>() {
+                err.set_cause(py, Some(cause.clone_ref(py)));
+            }
+            (err, vec![("path", path.to_object(py))])
+        }
+        Some(ConfigError::MissingDefault { entry, file, .. }) => (
+            HierarchicalConfigError::new_err(message),
+            vec![("path", file.to_object(py)), ("entry", entry.to_object(py))],
+        ),
+        Some(ConfigError::ReferenceCycle { chain }) => {
+            (HierarchicalConfigError::new_err(message), vec![("chain", chain.to_object(py))])
+        }
+        Some(ConfigError::TypeConflict { path, file, .. } | ConfigError::IndexPatch { path, file, .. }) => (
+            HierarchicalConfigError::new_err(message),
+            vec![("key_path", path.to_object(py)), ("path", file.to_object(py))],
+        ),
+        Some(ConfigError::UnknownSelector { selector, file, path }) => (
+            HierarchicalConfigError::new_err(message),
+            vec![
+                ("key_path", path.to_object(py)),
+                ("path", file.to_object(py)),
+                ("selector", selector.to_object(py)),
+            ],
+        ),
+        Some(ConfigError::LimitExceeded { limit, file, .. }) => (
+            HierarchicalConfigError::new_err(message),
+            vec![("path", file.to_object(py)), ("limit", limit.to_object(py))],
+        ),
+        Some(ConfigError::Cancelled { timeout }) => (
+            HierarchicalConfigError::new_err(message),
+            vec![("timeout", timeout.map(|timeout| timeout.as_secs_f64()).to_object(py))],
+        ),
+        Some(ConfigError::MissingKey { path } | ConfigError::WrongType { path, .. }) => {
+            (HierarchicalConfigError::new_err(message), vec![("key_path", path.to_object(py))])
+        }
+        Some(ConfigError::UnresolvedVariable { .. }) => {
+            (HierarchyError::new_err(message), paths(base_dir, target_path))
+        }
+        Some(ConfigError::Io { source, .. }) if source.kind() == io::ErrorKind::NotFound => {
+            (HierarchyError::new_err(message), paths(base_dir, target_path))
+        }
+        Some(ConfigError::Io { path, .. }) => (HierarchicalConfigError::new_err(message), vec![("path", path.to_object(py))]),
+        Some(ConfigError::Remote { url, .. }) => (HierarchicalConfigError::new_err(message), vec![("url", url.to_object(py))]),
+        Some(ConfigError::ReservedKey { file, .. }) => {
+            (HierarchicalConfigError::new_err(message), vec![("path", file.to_object(py))])
+        }
+        Some(ConfigError::Validation { .. } | ConfigError::Other(_)) => {
+            (HierarchicalConfigError::new_err(format!("{e:#}")), Vec::new())
+        }
+        None if is_not_found(&e) => (HierarchyError::new_err(message), paths(base_dir, target_path)),
+        None => (HierarchicalConfigError::new_err(message), Vec::new()),
+    };
+    attributes.push(("report_json", report_json.ok().to_object(py)));
+
+    for (name, value) in attributes {
+        if let Err(setattr_err) = err.value(py).setattr(name, value) {
+            return setattr_err;
+        }
+    }
+    err
+}
+
+/// The error inside a [`ConfigError::Other`], so that the typed error it may
+/// carry is found by `downcast_ref`.
+fn unwrap_other(e: anyhow::Error) -> anyhow::Error {
+    if !(*e).is::<ConfigError>() {
+        return e;
+    }
+    match e.downcast::<ConfigError>() {
+        Ok(ConfigError::Other(inner)) => inner,
+        Ok(error) => error.into(),
+        Err(e) => e,
+    }
+}
+
+fn is_not_found(e: &anyhow::Error) -> bool {
+    e.chain()
+        .filter_map(|cause| cause.downcast_ref::<io::Error>())
+        .any(|error| error.kind() == io::ErrorKind::NotFound)
+}
+
+/// Maps path resolution failures onto the matching builtin exception:
+/// `FileNotFoundError` for a missing base or target, `ValueError` for a target
+/// outside the base or an unexpandable path. Anything else is a `RuntimeError`.
+fn path_error_to_python(e: impl Into<anyhow::Error>) -> PyErr {
+    let e = unwrap_other(e.into());
+    let message = format!("{e:#}");
+    if let Some(error) = e.downcast_ref::<ConfigError>()
+        && matches!(error, ConfigError::OutsideBase { .. } | ConfigError::UnresolvedVariable { .. })
+    {
+        return PyValueError::new_err(message);
+    }
+    if is_not_found(&e) {
+        return PyFileNotFoundError::new_err(message);
+    }
+    PyRuntimeError::new_err(message)
+}
+
+/// A report entry: `kind` is the snake_case name of its
+/// [`crate::ReportKind`], such as `"collision"`, `"type_conflict"` or
+/// `"deprecation"`, see [`crate::ReportKind::name`]; `severity` is that of the
+/// kind, `"info"` or `"warning"` (the entries of a failed merge, also those
+/// of a `CollisionError` or `StrictError`, are `"error"` in its
+/// `report_json` only, see [`crate::Severity`]); `path` is the dotted key path
+/// it is about (or None) and `files` the files involved.
+#[pyclass(name = "ReportEntry", module = "hierarchical_config_merging", frozen, get_all)]
+pub struct PyReportEntry {
+    kind: &'static str,
+    severity: &'static str,
+    path: Option<String>,
+    files: Vec<PathBuf>,
+    message: String,
+}
+
+#[pymethods]
+impl PyReportEntry {
+    fn __repr__(slf: &PyCell<Self>) -> PyResult<String> {
+        let py = slf.py();
+        let entry = slf.get();
+        Ok(format!(
+            "ReportEntry(kind={}, path={}, message={})",
+            entry.kind.to_object(py).as_ref(py).repr()?,
+            entry.path.to_object(py).as_ref(py).repr()?,
+            entry.message.to_object(py).as_ref(py).repr()?,
+        ))
+    }
+
+    fn __str__(&self) -> String {
+        self.message.clone()
+    }
+}
+
+impl From<&ReportEntry> for PyReportEntry {
+    fn from(entry: &ReportEntry) -> Self {
+        PyReportEntry {
+            kind: entry.kind.name(),
+            severity: entry.kind.severity().name(),
+            path: entry.key_path.clone(),
+            files: entry.files.clone(),
+            message: entry.message.clone(),
+        }
+    }
+}
+
+/// A path argument: a `str`, `bytes` or any `os.PathLike`, decoded like
+/// `os.fsdecode` so paths that are not valid UTF-8 survive.
+pub struct PathArg(PathBuf);
+
+impl<'source> FromPyObject<'source> for PathArg {
+    fn extract(value: &'source PyAny) -> PyResult<Self> {
+        let decoded = value.py().import("os")?.call_method1("fsdecode", (value,))?;
+        Ok(PathArg(decoded.extract()?))
+    }
+}
+
+/// An item of the `files` argument of `rust_merge_files`: a path, or a
+/// `(path, priority)` tuple.
+pub struct FileArg(PathBuf, Option<i64>);
+
+impl<'source> FromPyObject<'source> for FileArg {
+    fn extract(value: &'source PyAny) -> PyResult<Self> {
+        if value.is_instance_of::<PyTuple>() {
+            let (path, priority): (PathArg, i64) = value.extract()?;
+            return Ok(FileArg(path.0, Some(priority)));
+        }
+        Ok(FileArg(value.extract::<PathArg>()?.0, None))
+    }
+}
+
+/// A YAML value with a tag, such as `!vault secret/path`, as returned with
+/// `preserve_tags=True`. `tag` includes the leading `!`. Accepted back
+/// wherever Python values are converted to config values.
+#[pyclass(name = "TaggedValue", module = "hierarchical_config_merging", frozen, get_all)]
+pub struct PyTaggedValue {
+    tag: String,
+    value: PyObject,
+}
+
+#[pymethods]
+impl PyTaggedValue {
+    #[new]
+    fn new(tag: String, value: PyObject) -> Self {
+        PyTaggedValue { tag, value }
+    }
 
+    fn __repr__(&self, py: Python) -> PyResult<String> {
+        let tag = self.tag.to_object(py);
+        Ok(format!("TaggedValue({}, {})", tag.as_ref(py).repr()?, self.value.as_ref(py).repr()?))
+    }
+
+    fn __eq__(&self, other: &PyAny) -> PyResult<bool> {
+        let Ok(other_cell) = other.downcast::<PyCell<PyTaggedValue>>() else {
+            return Ok(false);
+        };
+        let py = other.py();
+        let other = other_cell.get();
+        Ok(self.tag == other.tag && self.value.as_ref(py).eq(other.value.as_ref(py))?)
+    }
+
+    fn __hash__(&self, py: Python) -> PyResult<isize> {
+        (self.tag.as_str(), self.value.as_ref(py)).to_object(py).as_ref(py).hash()
+    }
+}
+
+/// Arguments of the functions sharing `rust_merge_hierarchical_configs`'
+/// signature.
+struct MergeCall<'py> {
+    function: &'static str,
+    base_dir: PathArg,
+    target_path: PathArg,
+    overrides: Option<&'py PyDict>,
+    on_conflict: Option<&'py PyAny>,
+    kwargs: Option<&'py PyDict>,
+    allowed: &'static [&'static [&'static str]],
+}
+
+/// Merges a hierarchy and the optional `overrides` dict on top of it, asking
+/// `on_conflict` for every conflicting value when given. `finish` turns the
+/// merged config into the result, still without the GIL.
+fn merge_with_overrides<T: Send>(
+    py: Python,
+    call: MergeCall,
+    finish: impl FnOnce(ConfigValue) -> anyhow::Result<T> + Send,
+) -> PyResult<(T, MergeReport, Conversion)> {
+    let base_path = call.base_dir.0;
+    let target_path = call.target_path.0;
+
+    let (options, binding) = python_options(call.function, call.kwargs, call.allowed)?;
+    let overrides = call.overrides.map(|dict| python_to_config(dict, &mut Vec::new())).transpose()?;
+    let on_conflict: Option<PyObject> = match call.on_conflict {
+        Some(callback) if !callback.is_callable() => {
+            return Err(PyTypeError::new_err("on_conflict must be callable"));
+        }
+        callback => callback.map(Into::into),
+    };
+
+    // References resolve, units are read, subtrees are pruned and values
+    // normalized once the overrides are merged too.
+    let late = overrides.is_some()
+        && (options.interpolate
+            || !options.prune_paths.is_empty()
+            || !options.units.is_empty()
+            || options.normalize.is_some()
+            || options.key_case.is_some());
+    let (options, finish_options) = if late {
+        let merge_options = MergeOptions {
+            interpolate: false,
+            prune_paths: Vec::new(),
+            units: Vec::new(),
+            normalize: None,
+            key_case: None,
+            ..options.clone()
+        };
+        (merge_options, Some(options))
+    } else {
+        (options, None)
+    };
+
+    let merged = py.allow_threads(|| {
+        // Entries of the overrides and of interpolation, checked on their own.
+        let mut late = MergeReport::default();
+        let (config, mut report) = match &on_conflict {
+            Some(callback) => {
+                let mut resolve = python_resolver(callback, binding.conversion);
+                let (config, report) = merge_hierarchy_resolving(&base_path, &target_path, &options, &mut resolve)?;
+                let config = match &overrides {
+                    Some(overrides) => {
+                        let overrides = coerce_override(&config, overrides, None, &options, &mut late.entries)?;
+                        deep_merge_resolving(&config, &overrides, &options, &mut resolve)?
+                    }
+                    None => config,
+                };
+                (config, report)
+            }
+            None => {
+                let (config, report) = merge_hierarchy(&base_path, &target_path, &options)?;
+                let config = match &overrides {
+                    Some(overrides) => {
+                        let overrides = coerce_override(&config, overrides, None, &options, &mut late.entries)?;
+                        deep_merge_with(&config, &overrides, &options)
+                    }
+                    None => config,
+                };
+                (config, report)
+            }
+        };
+        let config = match &finish_options {
+            Some(options) => {
+                let config = interpolate_merged(config, options, &mut late.entries)?;
+                let config = normalize_units(config, &[], options, &mut late.entries);
+                let config = prune_merged(config, options, &mut late.entries);
+                let config = normalize_merged(config, options);
+                case_merged_keys(config, options, &mut late.entries)
+            }
+            None => config,
+        };
+        options.check_report(&late)?;
+        report.entries.extend(late.entries);
+        Ok::<_, anyhow::Error>((finish(config)?, report))
+    });
+    match merged {
+        Ok((result, report)) => {
+            log_report(py, binding, &report)?;
+            Ok((result, report, binding.conversion))
+        }
+        Err(e) => Err(merge_failed(py, binding, e, &base_path, &target_path)),
+    }
+}
+
+/// Calls `callback(path, base_value, override_value)` with the GIL held and
+/// converts its result back.
+fn python_resolver(
+    callback: &PyObject,
+    conversion: Conversion,
+) -> impl FnMut(&[PathSegment], &ConfigValue, &ConfigValue) -> anyhow::Result<ConfigValue> + '_ {
+    move |path, base, r#override| {
+        Python::with_gil(|py| {
+            let args = (
+                format_key_path(path),
+                config_to_python(base, py, conversion)?,
+                config_to_python(r#override, py, conversion)?,
+            );
+            let value = callback.as_ref(py).call1(args)?;
+            python_to_config(value, &mut path.to_vec())
+        })
+        .map_err(anyhow::Error::from)
+    }
+}
+
+/// Merges the hierarchy from `base_dir` down to `target_path`. A dict of
+/// `overrides` is deep-merged on top of the deepest file, with the same options;
+/// with `interpolate=True`, `${path}` references resolve after the overrides.
+///
+/// `on_conflict(path, base_value, override_value)` decides every value that
+/// would be overridden, nested dicts aside, and returns the value to keep. An
+/// exception raised by it aborts the merge with `ConflictError`.
 #[pyfunction]
+#[pyo3(signature = (base_dir, target_path, overrides = None, on_conflict = None, **options))]
 pub fn rust_merge_hierarchical_configs(
-    base_dir: String,
-    target_path: String,
+    py: Python,
+    base_dir: PathArg,
+    target_path: PathArg,
+    overrides: Option<&PyDict>,
+    on_conflict: Option<&PyAny>,
+    options: Option<&PyDict>,
+) -> PyResult<(PyObject, Vec<String>)> {
+    let (config, report, conversion) = merge_with_overrides(
+        py,
+        MergeCall {
+            function: "rust_merge_hierarchical_configs",
+            base_dir,
+            target_path,
+            overrides,
+            on_conflict,
+            kwargs: options,
+            allowed: &[MERGE_OPTIONS, CONVERSION_OPTIONS, REPORT_OPTIONS],
+        },
+        Ok,
+    )?;
+    Ok((config_to_python(&config, py, conversion)?, report.warnings()))
+}
+
+/// Awaitable `rust_merge_hierarchical_configs`: the merge runs on a tokio
+/// worker thread and the awaitable resolves to the same `(config, warnings)`
+/// tuple, or raises the same exceptions.
+#[cfg(feature = "async")]
+#[pyfunction]
+#[pyo3(signature = (base_dir, target_path, **options))]
+pub fn rust_merge_hierarchical_configs_async(
+    py: Python,
+    base_dir: PathArg,
+    target_path: PathArg,
+    options: Option<&PyDict>,
+) -> PyResult<PyObject> {
+    let base_path = base_dir.0;
+    let target_path = target_path.0;
+    let allowed = &[MERGE_OPTIONS, CONVERSION_OPTIONS, REPORT_OPTIONS];
+    let (options, binding) = python_options("rust_merge_hierarchical_configs_async", options, allowed)?;
+
+    let awaitable = pyo3_asyncio::tokio::future_into_py(py, async move {
+        let merged = {
+            let (base_path, target_path) = (base_path.clone(), target_path.clone());
+            tokio::task::spawn_blocking(move || merge_hierarchy(&base_path, &target_path, &options))
+                .await
+                .map_err(|e| PyRuntimeError::new_err(format!("Merge task failed: {e}")))?
+        };
+        Python::with_gil(|py| match merged {
+            Ok((config, report)) => {
+                log_report(py, binding, &report)?;
+                let py_config = config_to_python(&config, py, binding.conversion)?;
+                Ok::<PyObject, _>((py_config, report.warnings()).into_py(py))
+            }
+            Err(e) => Err(merge_failed(py, binding, e, &base_path, &target_path)),
+        })
+    })?;
+    Ok(awaitable.into())
+}
+
+/// Mergers of [`rust_get_config_value`], one per base directory and options,
+/// oldest first, so that reading several keys of a target parses and merges
+/// its files once.
+static MERGERS: Mutex<Vec<HierarchyMerger>> = Mutex::new(Vec::new());
+
+/// Mergers kept in [`MERGERS`]; the oldest is dropped for a new one.
+const MAX_MERGERS: usize = 16;
+
+/// Merges `target_path` with the merger of `mergers` for `base_dir` and
+/// `options`, made by `new` when there is none yet.
+fn merge_cached(
+    mergers: &Mutex<Vec<HierarchyMerger>>,
+    base_dir: &Path,
+    target_path: &Path,
+    options: MergeOptions,
+    new: impl FnOnce(&Path, MergeOptions) -> HierarchyMerger,
+) -> Result<(Arc<ConfigValue>, MergeReport), ConfigError> {
+    let mut mergers = mergers.lock().unwrap_or_else(PoisonError::into_inner);
+    let found = mergers.iter().position(|merger| merger.base_dir() == base_dir && *merger.options() == options);
+    let index = match found {
+        Some(index) => index,
+        None => {
+            if mergers.len() == MAX_MERGERS {
+                mergers.remove(0);
+            }
+            mergers.push(new(base_dir, options));
+            mergers.len() - 1
+        }
+    };
+    mergers[index].merge_with_report(target_path)
+}
+
+/// The value at a dotted key path such as `service.image.tag` or
+/// `service.ports[0]` in the merged config, or `default` when nothing is
+/// there. Only that value is converted to Python.
+///
+/// Merges go through a `HierarchyMerger` kept per base directory and
+/// options, so that only the files that changed since the last call are
+/// parsed again.
+#[pyfunction]
+#[pyo3(signature = (base_dir, target_path, key_path, default = None, **options))]
+pub fn rust_get_config_value(
+    py: Python,
+    base_dir: PathArg,
+    target_path: PathArg,
+    key_path: &str,
+    default: Option<PyObject>,
+    options: Option<&PyDict>,
+) -> PyResult<PyObject> {
+    let base_path = base_dir.0;
+    let target_path = target_path.0;
+    let segments = parse_key_path(key_path).map_err(|e| PyValueError::new_err(format!("{e:#}")))?;
+    let allowed = &[MERGE_OPTIONS, CONVERSION_OPTIONS, REPORT_OPTIONS];
+    let (options, binding) = python_options("rust_get_config_value", options, allowed)?;
+
+    let merged = py.allow_threads(|| {
+        let (config, report) = merge_cached(&MERGERS, &base_path, &target_path, options, |base_dir, options| {
+            HierarchyMerger::new(base_dir, options)
+        })?;
+        Ok::<_, ConfigError>((get_segments(&config, &segments).cloned(), report))
+    });
+    match merged {
+        Ok((value, report)) => {
+            log_report(py, binding, &report)?;
+            match value {
+                Some(value) => config_to_python(&value, py, binding.conversion),
+                None => Ok(default.unwrap_or_else(|| py.None())),
+            }
+        }
+        Err(e) => Err(merge_failed(py, binding, e, &base_path, &target_path)),
+    }
+}
+
+/// A merged config, or a mapping inside one, converting values to Python only
+/// when they are accessed. Nested mappings are returned as `Config` views on
+/// the same merged config; every other value is converted on access.
+#[pyclass(name = "Config", module = "hierarchical_config_merging", frozen)]
+pub struct PyConfig {
+    root: Arc<ConfigValue>,
+    /// Keys leading from `root` to the mapping this view shows.
+    keys: Vec<ConfigValue>,
+    conversion: Conversion,
+    /// Values converted so far, shared by all views of `root`.
+    converted: Arc<AtomicUsize>,
+    #[pyo3(get)]
+    warnings: Vec<String>,
+}
+
+impl PyConfig {
+    fn value(&self) -> &ConfigValue {
+        self.keys.iter().fold(self.root.as_ref(), |node, key| &node[key])
+    }
+
+    fn node(&self) -> &serde_yaml::Mapping {
+        self.value().as_mapping().expect("Config views only address mappings")
+    }
+
+    /// `value`, found at `key` below this view (if any), as a view or a
+    /// converted value.
+    fn wrap(&self, py: Python, keys: Option<Vec<ConfigValue>>, value: &ConfigValue) -> PyResult<PyObject> {
+        if let (Some(keys), ConfigValue::Mapping(_)) = (keys, value) {
+            let view = PyConfig {
+                root: Arc::clone(&self.root),
+                keys,
+                conversion: self.conversion,
+                converted: Arc::clone(&self.converted),
+                warnings: Vec::new(),
+            };
+            return Ok(view.into_py(py));
+        }
+        self.convert(py, value)
+    }
+
+    fn convert(&self, py: Python, value: &ConfigValue) -> PyResult<PyObject> {
+        self.converted.fetch_add(count_values(value), Ordering::Relaxed);
+        value_to_python(value, py, self.conversion, &mut self.segments())
+    }
+
+    fn segments(&self) -> Vec<PathSegment> {
+        self.keys.iter().map(|key| PathSegment::Key(key_to_string(key))).collect()
+    }
+
+    /// Keys along a dotted path, when every segment is a mapping key.
+    fn keys_along(&self, segments: &[PathSegment]) -> Option<Vec<ConfigValue>> {
+        let mut keys = self.keys.clone();
+        for segment in segments {
+            match segment {
+                PathSegment::Key(key) => keys.push(ConfigValue::String(key.clone())),
+                PathSegment::Index(_) => return None,
+            }
+        }
+        Some(keys)
+    }
+}
+
+#[pymethods]
+impl PyConfig {
+    fn __getitem__(&self, py: Python, key: &PyAny) -> PyResult<PyObject> {
+        let config_key = python_to_config(key, &mut self.segments())?;
+        match self.node().get(&config_key) {
+            Some(value) => {
+                let mut keys = self.keys.clone();
+                keys.push(config_key);
+                self.wrap(py, Some(keys), value)
+            }
+            None => Err(PyKeyError::new_err(key.to_object(py))),
+        }
+    }
+
+    fn __contains__(&self, key: &PyAny) -> PyResult<bool> {
+        Ok(self.node().contains_key(&python_to_config(key, &mut Vec::new())?))
+    }
+
+    fn __len__(&self) -> usize {
+        self.node().len()
+    }
+
+    fn __iter__(&self, py: Python) -> PyResult<PyObject> {
+        let keys = self.keys(py)?;
+        Ok(keys.as_ref(py).call_method0("__iter__")?.to_object(py))
+    }
+
+    fn __repr__(&self, py: Python) -> PyResult<String> {
+        Ok(format!("Config({})", self.keys(py)?.as_ref(py).repr()?))
+    }
+
+    /// The top-level keys of this view, in merge order.
+    fn keys(&self, py: Python) -> PyResult<Py<PyList>> {
+        let keys = PyList::empty(py);
+        for key in self.node().keys() {
+            keys.append(key_to_python(key, py, self.conversion, &self.segments())?)?;
+        }
+        Ok(keys.into())
+    }
+
+    /// The value at a dotted key path such as `server.port` or
+    /// `server.hosts[0]`, or `default` when nothing is there.
+    #[pyo3(signature = (key_path, default = None))]
+    fn get(&self, py: Python, key_path: &str, default: Option<PyObject>) -> PyResult<PyObject> {
+        let segments = parse_key_path(key_path).map_err(|e| PyValueError::new_err(format!("{e:#}")))?;
+        match get_segments(self.value(), &segments) {
+            Some(value) => self.wrap(py, self.keys_along(&segments), value),
+            None => Ok(default.unwrap_or_else(|| py.None())),
+        }
+    }
+
+    /// Converts the whole view to a plain dict.
+    fn to_dict(&self, py: Python) -> PyResult<PyObject> {
+        self.convert(py, self.value())
+    }
+
+    /// Number of config values (scalars, sequences and mappings) converted to
+    /// Python so far by this config and its views.
+    #[getter]
+    fn converted_values(&self) -> usize {
+        self.converted.load(Ordering::Relaxed)
+    }
+}
+
+fn count_values(value: &ConfigValue) -> usize {
+    1 + match value {
+        ConfigValue::Mapping(map) => map.values().map(count_values).sum(),
+        ConfigValue::Sequence(items) => items.iter().map(count_values).sum(),
+        ConfigValue::Tagged(tagged) => count_values(&tagged.value) - 1,
+        _ => 0,
+    }
+}
+
+/// Like `rust_merge_hierarchical_configs`, returning a `Config` whose values
+/// are converted when accessed. Its `warnings` attribute lists the warnings.
+#[pyfunction]
+#[pyo3(signature = (base_dir, target_path, overrides = None, **options))]
+pub fn rust_merge_as_config(
+    py: Python,
+    base_dir: PathArg,
+    target_path: PathArg,
+    overrides: Option<&PyDict>,
+    options: Option<&PyDict>,
+) -> PyResult<PyConfig> {
+    let (config, report, conversion) = merge_with_overrides(
+        py,
+        MergeCall {
+            function: "rust_merge_as_config",
+            base_dir,
+            target_path,
+            overrides,
+            on_conflict: None,
+            kwargs: options,
+            allowed: &[MERGE_OPTIONS, CONVERSION_OPTIONS, REPORT_OPTIONS],
+        },
+        Ok,
+    )?;
+    let config = match config {
+        ConfigValue::Mapping(_) => config,
+        _ => ConfigValue::Mapping(serde_yaml::Mapping::new()),
+    };
+    Ok(PyConfig {
+        root: Arc::new(config),
+        keys: Vec::new(),
+        conversion,
+        converted: Arc::new(AtomicUsize::new(0)),
+        warnings: report.warnings(),
+    })
+}
+
+/// Like `rust_merge_hierarchical_configs`, returning the report as a list of
+/// `ReportEntry` objects instead of messages.
+#[pyfunction]
+#[pyo3(signature = (base_dir, target_path, overrides = None, **options))]
+pub fn rust_merge_with_report(
+    py: Python,
+    base_dir: PathArg,
+    target_path: PathArg,
+    overrides: Option<&PyDict>,
+    options: Option<&PyDict>,
+) -> PyResult<(PyObject, Vec<PyReportEntry>)> {
+    let (config, report, conversion) = merge_with_overrides(
+        py,
+        MergeCall {
+            function: "rust_merge_with_report",
+            base_dir,
+            target_path,
+            overrides,
+            on_conflict: None,
+            kwargs: options,
+            allowed: &[MERGE_OPTIONS, CONVERSION_OPTIONS, REPORT_OPTIONS],
+        },
+        Ok,
+    )?;
+    let entries = report.entries.iter().map(PyReportEntry::from).collect();
+    Ok((config_to_python(&config, py, conversion)?, entries))
+}
+
+/// Like `rust_merge_hierarchical_configs`, returning the report as JSON text
+/// instead of messages, see `MergeReport::to_json`. A failed merge raises with
+/// its JSON report in the `report_json` attribute.
+#[pyfunction]
+#[pyo3(signature = (base_dir, target_path, overrides = None, **options))]
+pub fn rust_merge_report_json(
+    py: Python,
+    base_dir: PathArg,
+    target_path: PathArg,
+    overrides: Option<&PyDict>,
+    options: Option<&PyDict>,
+) -> PyResult<(PyObject, String)> {
+    let (config, report, conversion) = merge_with_overrides(
+        py,
+        MergeCall {
+            function: "rust_merge_report_json",
+            base_dir,
+            target_path,
+            overrides,
+            on_conflict: None,
+            kwargs: options,
+            allowed: &[MERGE_OPTIONS, CONVERSION_OPTIONS, REPORT_OPTIONS],
+        },
+        Ok,
+    )?;
+    let json = report.to_json().map_err(|e| PyValueError::new_err(e.to_string()))?;
+    Ok((config_to_python(&config, py, conversion)?, json))
+}
+
+/// Like `rust_merge_hierarchical_configs`, returning the merged config as
+/// YAML text serialized on the Rust side, with the warnings.
+#[pyfunction]
+#[pyo3(signature = (base_dir, target_path, overrides = None, **options))]
+pub fn rust_merge_to_yaml(
+    py: Python,
+    base_dir: PathArg,
+    target_path: PathArg,
+    overrides: Option<&PyDict>,
+    options: Option<&PyDict>,
+) -> PyResult<(String, Vec<String>)> {
+    let (text, report, _) = merge_with_overrides(
+        py,
+        MergeCall {
+            function: "rust_merge_to_yaml",
+            base_dir,
+            target_path,
+            overrides,
+            on_conflict: None,
+            kwargs: options,
+            allowed: &[MERGE_OPTIONS, REPORT_OPTIONS],
+        },
+        |config| Ok(to_yaml(&config)?),
+    )?;
+    Ok((text, report.warnings()))
+}
+
+/// Like `rust_merge_to_yaml`, returning JSON text, indented when `pretty`.
+#[pyfunction]
+#[pyo3(signature = (base_dir, target_path, overrides = None, pretty = false, **options))]
+pub fn rust_merge_to_json(
+    py: Python,
+    base_dir: PathArg,
+    target_path: PathArg,
+    overrides: Option<&PyDict>,
+    pretty: bool,
+    options: Option<&PyDict>,
+) -> PyResult<(String, Vec<String>)> {
+    let (text, report, _) = merge_with_overrides(
+        py,
+        MergeCall {
+            function: "rust_merge_to_json",
+            base_dir,
+            target_path,
+            overrides,
+            on_conflict: None,
+            kwargs: options,
+            allowed: &[MERGE_OPTIONS, REPORT_OPTIONS],
+        },
+        |config| Ok(to_json(&config, pretty)?),
+    )?;
+    Ok((text, report.warnings()))
+}
+
+/// Like `rust_merge_to_yaml`, returning Java `.properties` text; see
+/// `rust_to_properties` for `join_sequences` and `empty_nulls`.
+#[pyfunction]
+#[pyo3(signature = (base_dir, target_path, overrides = None, join_sequences = false, empty_nulls = false, **options))]
+pub fn rust_merge_to_properties(
+    py: Python,
+    base_dir: PathArg,
+    target_path: PathArg,
+    overrides: Option<&PyDict>,
+    join_sequences: bool,
+    empty_nulls: bool,
+    options: Option<&PyDict>,
+) -> PyResult<(String, Vec<String>)> {
+    let properties = PropertiesOptions {
+        join_sequences,
+        empty_nulls,
+    };
+    let (text, report, _) = merge_with_overrides(
+        py,
+        MergeCall {
+            function: "rust_merge_to_properties",
+            base_dir,
+            target_path,
+            overrides,
+            on_conflict: None,
+            kwargs: options,
+            allowed: &[MERGE_OPTIONS, REPORT_OPTIONS],
+        },
+        |config| Ok(to_properties_string_with(&config, &properties)?),
+    )?;
+    Ok((text, report.warnings()))
+}
+
+/// Like `rust_merge_to_yaml`, returning `export NAME='value'` lines; see
+/// `rust_to_env` for `prefix`, `separator` and `strict_values`.
+#[pyfunction]
+#[pyo3(signature = (base_dir, target_path, overrides = None, prefix = "", separator = "_", strict_values = false, **options))]
+#[allow(clippy::too_many_arguments)]
+pub fn rust_merge_to_env(
+    py: Python,
+    base_dir: PathArg,
+    target_path: PathArg,
+    overrides: Option<&PyDict>,
+    prefix: &str,
+    separator: &str,
+    strict_values: bool,
+    options: Option<&PyDict>,
+) -> PyResult<(String, Vec<String>)> {
+    let env = EnvOptions {
+        prefix: prefix.to_string(),
+        separator: separator.to_string(),
+        strict: strict_values,
+    };
+    let (text, report, _) = merge_with_overrides(
+        py,
+        MergeCall {
+            function: "rust_merge_to_env",
+            base_dir,
+            target_path,
+            overrides,
+            on_conflict: None,
+            kwargs: options,
+            allowed: &[MERGE_OPTIONS, REPORT_OPTIONS],
+        },
+        |config| Ok(to_env_exports(&config, &env)?),
+    )?;
+    Ok((text, report.warnings()))
+}
+
+/// Like `rust_merge_to_yaml`, returning the hex SHA-256 of the merged config
+/// instead, as computed by `rust_config_hash` but without the round trip
+/// through Python (tags always count).
+#[pyfunction]
+#[pyo3(signature = (base_dir, target_path, overrides = None, **options))]
+pub fn rust_merge_config_hash(
+    py: Python,
+    base_dir: PathArg,
+    target_path: PathArg,
+    overrides: Option<&PyDict>,
+    options: Option<&PyDict>,
+) -> PyResult<(String, Vec<String>)> {
+    let (hash, report, _) = merge_with_overrides(
+        py,
+        MergeCall {
+            function: "rust_merge_config_hash",
+            base_dir,
+            target_path,
+            overrides,
+            on_conflict: None,
+            kwargs: options,
+            allowed: &[MERGE_OPTIONS, REPORT_OPTIONS],
+        },
+        |config| Ok(config_hash(&config)),
+    )?;
+    Ok((hash, report.warnings()))
+}
+
+/// Like `rust_merge_hierarchical_configs`, also returning the files that were
+/// merged as a list of `{"path", "depth", "sha256"}` dicts, in merge order.
+#[pyfunction]
+#[pyo3(signature = (base_dir, target_path, **options))]
+pub fn rust_merge_with_files(
+    py: Python,
+    base_dir: PathArg,
+    target_path: PathArg,
+    options: Option<&PyDict>,
+) -> PyResult<(PyObject, Vec<String>, PyObject)> {
+    let base_path = base_dir.0;
+    let target_path = target_path.0;
+
+    let allowed = &[MERGE_OPTIONS, CONVERSION_OPTIONS, REPORT_OPTIONS];
+    let (options, binding) = python_options("rust_merge_with_files", options, allowed)?;
+
+    match py.allow_threads(|| merge_hierarchy(&base_path, &target_path, &options)) {
+        Ok((config, report)) => {
+            log_report(py, binding, &report)?;
+            let py_config = config_to_python(&config, py, binding.conversion)?;
+            let files = PyList::empty(py);
+            for file in &report.files {
+                let entry = PyDict::new(py);
+                entry.set_item("path", &file.path)?;
+                entry.set_item("depth", file.depth)?;
+                entry.set_item("sha256", &file.sha256)?;
+                files.append(entry)?;
+            }
+            Ok((py_config, report.warnings(), files.to_object(py)))
+        }
+        Err(e) => Err(merge_failed(py, binding, e, &base_path, &target_path)),
+    }
+}
+
+/// Merges the given files, returning `(config, warnings)`. Each item of
+/// `files` is a path, placed by its directory level below `base_dir`, or a
+/// `(path, priority)` tuple whose priority is used as its level. Files of the
+/// same level are checked for collisions and merged in path order. Without
+/// `base_dir`, paths are placed by their number of components.
+#[pyfunction]
+#[pyo3(signature = (files, base_dir = None, **options))]
+pub fn rust_merge_files(
+    py: Python,
+    files: Vec<FileArg>,
+    base_dir: Option<PathArg>,
+    options: Option<&PyDict>,
 ) -> PyResult<(PyObject, Vec<String>)> {
-    let base_path = PathBuf::from(base_dir);
-    let target_path = PathBuf::from(target_path);
+    let files: Vec<_> = files.into_iter().map(|FileArg(path, priority)| (path, priority)).collect();
+    let base_path = base_dir.map(|base_dir| base_dir.0);
+
+    let allowed = &[FILES_OPTIONS, CONVERSION_OPTIONS, REPORT_OPTIONS];
+    let (options, binding) = python_options("rust_merge_files", options, allowed)?;
+
+    match py.allow_threads(|| merge_files(&files, base_path.as_deref(), &options)) {
+        Ok((config, report)) => {
+            log_report(py, binding, &report)?;
+            Ok((config_to_python(&config, py, binding.conversion)?, report.warnings()))
+        }
+        Err(e) => {
+            let base_path = base_path.unwrap_or_default();
+            Err(merge_failed(py, binding, e, &base_path, Path::new("")))
+        }
+    }
+}
+
+/// Like `rust_merge_hierarchical_configs`, returning `(config, provenance,
+/// warnings)` where provenance maps the dotted key path of every leaf value
+/// to the file it was taken from.
+#[pyfunction]
+#[pyo3(signature = (base_dir, target_path, **options))]
+pub fn rust_merge_with_provenance(
+    py: Python,
+    base_dir: PathArg,
+    target_path: PathArg,
+    options: Option<&PyDict>,
+) -> PyResult<(PyObject, PyObject, Vec<String>)> {
+    let base_path = base_dir.0;
+    let target_path = target_path.0;
+
+    let allowed = &[MERGE_OPTIONS, CONVERSION_OPTIONS, REPORT_OPTIONS];
+    let (options, binding) = python_options("rust_merge_with_provenance", options, allowed)?;
+
+    match py.allow_threads(|| merge_hierarchy_with_provenance(&base_path, &target_path, &options)) {
+        Ok((config, report, provenance)) => {
+            log_report(py, binding, &report)?;
+            let py_config = config_to_python(&config, py, binding.conversion)?;
+            let sources = PyDict::new(py);
+            for (path, source) in provenance.iter() {
+                sources.set_item(path, source)?;
+            }
+            Ok((py_config, sources.to_object(py), report.warnings()))
+        }
+        Err(e) => Err(merge_failed(py, binding, e, &base_path, &target_path)),
+    }
+}
+
+/// Merges like `rust_merge_hierarchical_configs` and writes the merged config
+/// to `out_path` as YAML headed by its source files and their SHA-256. With
+/// `provenance`, each top-level key notes the files its values come from.
+/// Returns the warnings. Unchanged inputs write the same bytes.
+#[pyfunction]
+#[pyo3(signature = (base_dir, target_path, out_path, provenance = true, **options))]
+pub fn rust_write_lockfile(
+    py: Python,
+    base_dir: PathArg,
+    target_path: PathArg,
+    out_path: PathArg,
+    provenance: bool,
+    options: Option<&PyDict>,
+) -> PyResult<Vec<String>> {
+    let base_path = base_dir.0;
+    let target_path = target_path.0;
+
+    let allowed = &[MERGE_OPTIONS, REPORT_OPTIONS];
+    let (options, binding) = python_options("rust_write_lockfile", options, allowed)?;
+
+    match py.allow_threads(|| write_lockfile(&base_path, &target_path, &out_path.0, &options, provenance)) {
+        Ok(report) => {
+            log_report(py, binding, &report)?;
+            Ok(report.warnings())
+        }
+        Err(e) => Err(merge_failed(py, binding, e, &base_path, &target_path)),
+    }
+}
+
+/// Markdown documentation of the merge of `target_path`: a section per
+/// top-level key with a table of key paths, effective values (redacted per
+/// `redact_patterns`), the defining file and every file setting them.
+#[pyfunction]
+#[pyo3(signature = (base_dir, target_path, **options))]
+pub fn rust_generate_docs(
+    py: Python,
+    base_dir: PathArg,
+    target_path: PathArg,
+    options: Option<&PyDict>,
+) -> PyResult<String> {
+    let base_path = base_dir.0;
+    let target_path = target_path.0;
+
+    let allowed = &[MERGE_OPTIONS, REPORT_OPTIONS];
+    let (options, binding) = python_options("rust_generate_docs", options, allowed)?;
+
+    py.allow_threads(|| generate_docs(&base_path, &target_path, &options))
+        .map_err(|e| merge_failed(py, binding, e, &base_path, &target_path))
+}
+
+/// The YAML files a merge of `target_path` would use, in merge order.
+#[pyfunction]
+#[pyo3(signature = (base_dir, target_path, **options))]
+pub fn rust_find_yaml_files(
+    py: Python,
+    base_dir: PathArg,
+    target_path: PathArg,
+    options: Option<&PyDict>,
+) -> PyResult<Vec<PathBuf>> {
+    let base_path = base_dir.0;
+    let target_path = target_path.0;
 
-    match merge_hierarchical_configs(&base_path, &target_path) {
-        Ok((config, errors)) => {
-            Python::with_gil(|py| {
-                let py_config = config_to_python(&config, py)?;
-                Ok((py_config, errors))
-            })
+    let (options, _) = python_options("rust_find_yaml_files", options, &[MERGE_OPTIONS])?;
+
+    py.allow_threads(|| find_layer_files(&base_path, &target_path, &options))
+        .map_err(path_error_to_python)
+}
+
+/// Deep-merges two dicts with the crate's merge semantics: nested dicts merge
+/// key by key, anything else in `override` replaces the base value. Accepts
+/// the `sequence_strategy`, `null_deletes` and `mode` keyword arguments;
+/// `mode="json_merge_patch"` applies `override` as an RFC 7386 merge patch.
+#[pyfunction]
+#[pyo3(signature = (base, r#override, **options))]
+pub fn rust_deep_merge(
+    py: Python,
+    base: &PyDict,
+    r#override: &PyDict,
+    options: Option<&PyDict>,
+) -> PyResult<PyObject> {
+    let (options, binding) = python_options("rust_deep_merge", options, &[DEEP_MERGE_OPTIONS, CONVERSION_OPTIONS])?;
+    let base = python_to_config(base, &mut Vec::new())?;
+    let r#override = python_to_config(r#override, &mut Vec::new())?;
+    let merged = py.allow_threads(|| deep_merge_with(&base, &r#override, &options));
+    config_to_python(&merged, py, binding.conversion)
+}
+
+/// The smallest dict that `rust_deep_merge(base, result, **options)` turns
+/// into `desired`, for writing new override files. Accepts the keyword
+/// arguments of `rust_deep_merge`; raises `ValueError` naming the key path
+/// when no override can give `desired`, e.g. a removed key without
+/// `null_deletes=True`.
+#[pyfunction]
+#[pyo3(signature = (base, desired, **options))]
+pub fn rust_compute_override(
+    py: Python,
+    base: &PyDict,
+    desired: &PyDict,
+    options: Option<&PyDict>,
+) -> PyResult<PyObject> {
+    let (options, binding) =
+        python_options("rust_compute_override", options, &[DEEP_MERGE_OPTIONS, CONVERSION_OPTIONS])?;
+    let base = python_to_config(base, &mut Vec::new())?;
+    let desired = python_to_config(desired, &mut Vec::new())?;
+    let minimal = compute_override(&base, &desired, &options).map_err(|e| PyValueError::new_err(format!("{e:#}")))?;
+    config_to_python(&minimal, py, binding.conversion)
+}
+
+/// Flattens a config dict to Java `.properties` text with dotted keys.
+/// Sequences of scalars become comma-joined values with `join_sequences`,
+/// indexed keys such as `tags[0]` otherwise; `None` values are left out
+/// unless `empty_nulls`.
+#[pyfunction]
+#[pyo3(signature = (config, join_sequences = false, empty_nulls = false))]
+pub fn rust_to_properties(config: &PyDict, join_sequences: bool, empty_nulls: bool) -> PyResult<String> {
+    let config = python_to_config(config, &mut Vec::new())?;
+    let options = PropertiesOptions {
+        join_sequences,
+        empty_nulls,
+    };
+    to_properties_string_with(&config, &options).map_err(|e| PyValueError::new_err(format!("{e:#}")))
+}
+
+/// Flattens a config dict to `export NAME='value'` shell lines. Names join
+/// `prefix` and the uppercased keys with `separator`; values are
+/// single-quoted. Sequences holding dicts or lists are left out, or raise
+/// `ValueError` with `strict_values`.
+#[pyfunction]
+#[pyo3(signature = (config, prefix = "", separator = "_", strict_values = false))]
+pub fn rust_to_env(config: &PyDict, prefix: &str, separator: &str, strict_values: bool) -> PyResult<String> {
+    let config = python_to_config(config, &mut Vec::new())?;
+    let options = EnvOptions {
+        prefix: prefix.to_string(),
+        separator: separator.to_string(),
+        strict: strict_values,
+    };
+    to_env_exports(&config, &options).map_err(|e| PyValueError::new_err(format!("{e:#}")))
+}
+
+/// A copy of a config dict that is safe to log: values at key paths matching
+/// one of `patterns` (by default `*password*`, `*secret*` and `*token*`)
+/// become `"<redacted>"`.
+#[pyfunction]
+#[pyo3(signature = (config, patterns = None))]
+pub fn rust_redact(py: Python, config: &PyDict, patterns: Option<Vec<String>>) -> PyResult<PyObject> {
+    let config = python_to_config(config, &mut Vec::new())?;
+    let patterns = patterns.unwrap_or_else(|| Redaction::default().patterns);
+    config_to_python(&redact(&config, &patterns), py, Conversion::default())
+}
+
+/// Runs the `hier-config` command line `args`, program name left out, and
+/// returns its exit status. Output goes to `sys.stdout` and the report and
+/// errors to `sys.stderr`, once the command is done. The console script of
+/// the package calls it with `sys.argv[1:]`.
+#[pyfunction]
+pub fn rust_cli_main(py: Python, args: Vec<String>) -> PyResult<i32> {
+    let (mut out, mut err) = (Vec::new(), Vec::new());
+    let status = py.allow_threads(|| cli::run(&args, &mut out, &mut err));
+    let sys = py.import("sys")?;
+    for (stream, bytes) in [("stdout", out), ("stderr", err)] {
+        if !bytes.is_empty() {
+            sys.getattr(stream)?.call_method1("write", (String::from_utf8_lossy(&bytes),))?;
         }
-        Err(e) => Err(pyo3::exceptions::PyRuntimeError::new_err(e.to_string())),
     }
+    Ok(status)
 }
 
-fn config_to_python(value: &ConfigValue, py: Python) -> PyResult<PyObject> {
+/// An indented rendering of a config dict for logs: one `key: value` line per
+/// node with its type, truncated past `max_depth` levels, `max_items` items
+/// per collection, `max_string_len` characters per string and `max_nodes`
+/// lines. Values at key paths matching `patterns`, by default those of
+/// `rust_redact`, are masked.
+#[pyfunction]
+#[pyo3(signature = (config, max_depth = 8, max_items = 20, max_string_len = 60, max_nodes = 500, patterns = None))]
+pub fn rust_render_tree(
+    config: &PyDict,
+    max_depth: usize,
+    max_items: usize,
+    max_string_len: usize,
+    max_nodes: usize,
+    patterns: Option<Vec<String>>,
+) -> PyResult<String> {
+    let config = python_to_config(config, &mut Vec::new())?;
+    let options = TreeOptions {
+        max_depth,
+        max_items,
+        max_string_len,
+        max_nodes,
+        redaction: patterns.map(|patterns| Redaction { patterns }).unwrap_or_default(),
+    };
+    Ok(DisplayTree::with_options(&config, options).to_string())
+}
+
+/// Hex SHA-256 of a config dict that does not depend on key order: equal
+/// configs hash alike however their files were written.
+#[pyfunction]
+pub fn rust_config_hash(config: &PyDict) -> PyResult<String> {
+    Ok(config_hash(&python_to_config(config, &mut Vec::new())?))
+}
+
+/// Converts dicts with string keys, lists, str, int, float, bool and None.
+/// Anything else is a `TypeError` naming its key path.
+fn python_to_config(value: &PyAny, path: &mut Vec<PathSegment>) -> PyResult<ConfigValue> {
+    let unsupported = |what: String, path: &[PathSegment]| {
+        let at = if path.is_empty() {
+            "the top level".to_string()
+        } else {
+            format!("'{}'", format_key_path(path))
+        };
+        PyTypeError::new_err(format!("Cannot convert {what} at {at} to a config value"))
+    };
+
+    if value.is_none() {
+        Ok(ConfigValue::Null)
+    } else if let Ok(b) = value.downcast::<PyBool>() {
+        Ok(ConfigValue::Bool(b.is_true()))
+    } else if value.is_instance_of::<PyLong>() {
+        if let Ok(i) = value.extract::<i64>() {
+            Ok(ConfigValue::from(i))
+        } else if let Ok(u) = value.extract::<u64>() {
+            Ok(ConfigValue::from(u))
+        } else {
+            Err(unsupported(format!("int {value} (out of 64-bit range)"), path))
+        }
+    } else if let Ok(f) = value.downcast::<PyFloat>() {
+        Ok(ConfigValue::from(f.value()))
+    } else if let Ok(s) = value.downcast::<PyString>() {
+        Ok(ConfigValue::String(s.to_str()?.to_string()))
+    } else if let Ok(list) = value.downcast::<PyList>() {
+        let mut items = Vec::with_capacity(list.len());
+        for (index, item) in list.iter().enumerate() {
+            path.push(PathSegment::Index(index));
+            items.push(python_to_config(item, path)?);
+            path.pop();
+        }
+        Ok(ConfigValue::Sequence(items))
+    } else if let Ok(tagged) = value.downcast::<PyCell<PyTaggedValue>>() {
+        let tagged = tagged.get();
+        Ok(ConfigValue::Tagged(Box::new(serde_yaml::value::TaggedValue {
+            tag: serde_yaml::value::Tag::new(tagged.tag.as_str()),
+            value: python_to_config(tagged.value.as_ref(value.py()), path)?,
+        })))
+    } else if let Ok(dict) = value.downcast::<PyDict>() {
+        let mut map = serde_yaml::Mapping::new();
+        for (key, item) in dict {
+            let Ok(key) = key.downcast::<PyString>() else {
+                let what = format!("dict key {} of type '{}'", key.repr()?, key.get_type().name()?);
+                return Err(unsupported(what, path));
+            };
+            let key = key.to_str()?.to_string();
+            path.push(PathSegment::Key(key.clone()));
+            let item = python_to_config(item, path)?;
+            path.pop();
+            map.insert(ConfigValue::String(key), item);
+        }
+        Ok(ConfigValue::Mapping(map))
+    } else {
+        Err(unsupported(format!("value of type '{}'", value.get_type().name()?), path))
+    }
+}
+
+/// Converts a config to Python objects. Mapping keys become str, int, float,
+/// bool or None; a sequence or mapping used as a key raises `TypeError`.
+fn config_to_python(value: &ConfigValue, py: Python, conversion: Conversion) -> PyResult<PyObject> {
+    value_to_python(value, py, conversion, &mut Vec::new())
+}
+
+fn value_to_python(
+    value: &ConfigValue,
+    py: Python,
+    conversion: Conversion,
+    path: &mut Vec<PathSegment>,
+) -> PyResult<PyObject> {
     match value {
-        ConfigValue::String(s) => Ok(s.to_object(py)),
+        ConfigValue::String(s) => {
+            if conversion.parse_datetimes
+                && let Some(timestamp) = parse_timestamp(s)
+                && let Ok(datetime) = timestamp.to_python(py)
+            {
+                return Ok(datetime);
+            }
+            Ok(s.to_object(py))
+        }
         ConfigValue::Number(n) => {
             if let Some(i) = n.as_i64() {
                 Ok(i.to_object(py))
+            } else if let Some(u) = n.as_u64() {
+                Ok(u.to_object(py))
             } else if let Some(f) = n.as_f64() {
+                // NaN and the infinities convert to the matching Python floats.
                 Ok(f.to_object(py))
             } else {
-                Ok(0.to_object(py))
+                Ok(py.get_type::<PyLong>().call1((n.to_string(),))?.to_object(py))
             }
         }
         ConfigValue::Bool(b) => Ok(b.to_object(py)),
         ConfigValue::Null => Ok(py.None()),
         ConfigValue::Mapping(m) => {
-            let dict = pyo3::types::PyDict::new(py);
+            let dict = PyDict::new(py);
             for (k, v) in m {
-                if let ConfigValue::String(key_str) = k {
-                    let py_value = config_to_python(v, py)?;
-                    dict.set_item(key_str, py_value)?;
-                }
+                let py_key = key_to_python(k, py, conversion, path)?;
+                path.push(PathSegment::Key(key_to_string(k)));
+                let py_value = value_to_python(v, py, conversion, path)?;
+                path.pop();
+                dict.set_item(py_key, py_value)?;
             }
             Ok(dict.to_object(py))
         }
         ConfigValue::Sequence(s) => {
-            let list = pyo3::types::PyList::empty(py);
-            for item in s {
-                let py_item = config_to_python(item, py)?;
+            let list = PyList::empty(py);
+            for (index, item) in s.iter().enumerate() {
+                path.push(PathSegment::Index(index));
+                let py_item = value_to_python(item, py, conversion, path)?;
+                path.pop();
                 list.append(py_item)?;
             }
             Ok(list.to_object(py))
         }
         ConfigValue::Tagged(t) => {
-            // Handle tagged values by converting the inner value
-            config_to_python(&t.value, py)
+            let value = value_to_python(&t.value, py, conversion, path)?;
+            if !conversion.preserve_tags {
+                return Ok(value);
+            }
+            let tagged = PyTaggedValue {
+                tag: t.tag.to_string(),
+                value,
+            };
+            Ok(tagged.into_py(py))
+        }
+    }
+}
+
+/// A scalar mapping key as its natural Python type. `path` is the mapping's.
+fn key_to_python(
+    key: &ConfigValue,
+    py: Python,
+    conversion: Conversion,
+    path: &[PathSegment],
+) -> PyResult<PyObject> {
+    let what = match key {
+        ConfigValue::Sequence(_) => "sequence",
+        ConfigValue::Mapping(_) => "mapping",
+        ConfigValue::Tagged(t) => return key_to_python(&t.value, py, conversion, path),
+        scalar => return value_to_python(scalar, py, conversion, &mut Vec::new()),
+    };
+    let at = if path.is_empty() {
+        "the top level".to_string()
+    } else {
+        format!("'{}'", format_key_path(path))
+    };
+    Err(PyTypeError::new_err(format!("Cannot convert {what} key at {at} to a Python dict key")))
+}
+
+/// A YAML 1.1 timestamp: a bare `2024-01-01` date, or a date and time with an
+/// optional fraction and zone such as `2024-01-01T10:00:00.5+02:00`.
+#[derive(Debug, PartialEq)]
+struct Timestamp {
+    year: i32,
+    month: u8,
+    day: u8,
+    /// Hour, minute, second and microsecond, absent for a bare date.
+    time: Option<(u8, u8, u8, u32)>,
+    /// Seconds east of UTC, absent for a naive time.
+    offset: Option<i32>,
+}
+
+impl Timestamp {
+    /// A `datetime.date` or `datetime.datetime`; fails for out of range
+    /// fields such as February 30th.
+    fn to_python(&self, py: Python) -> PyResult<PyObject> {
+        let Some((hour, minute, second, microsecond)) = self.time else {
+            return Ok(PyDate::new(py, self.year, self.month, self.day)?.to_object(py));
+        };
+        let tzinfo = match self.offset {
+            Some(offset) => {
+                let delta = PyDelta::new(py, 0, offset, 0, true)?;
+                let timezone = py.import("datetime")?.getattr("timezone")?.call1((delta,))?;
+                Some(timezone.downcast::<PyTzInfo>()?)
+            }
+            None => None,
+        };
+        let datetime = PyDateTime::new(
+            py, self.year, self.month, self.day, hour, minute, second, microsecond, tzinfo,
+        )?;
+        Ok(datetime.to_object(py))
+    }
+}
+
+fn parse_timestamp(s: &str) -> Option<Timestamp> {
+    let mut rest = s;
+    let year = take_digits(&mut rest, 4, 4)?;
+    take_char(&mut rest, |c| c == '-')?;
+    let month = take_digits(&mut rest, 1, 2)?;
+    take_char(&mut rest, |c| c == '-')?;
+    let day = take_digits(&mut rest, 1, 2)?;
+    let mut timestamp = Timestamp {
+        year: year as i32,
+        month: month as u8,
+        day: day as u8,
+        time: None,
+        offset: None,
+    };
+    if rest.is_empty() {
+        // A bare date needs two digit months and days.
+        return (s.len() == 10).then_some(timestamp);
+    }
+
+    if take_char(&mut rest, |c| c == 'T' || c == 't').is_none() {
+        take_char(&mut rest, |c| c == ' ' || c == '\t')?;
+        rest = rest.trim_start_matches([' ', '\t']);
+    }
+    let hour = take_digits(&mut rest, 1, 2)?;
+    take_char(&mut rest, |c| c == ':')?;
+    let minute = take_digits(&mut rest, 2, 2)?;
+    take_char(&mut rest, |c| c == ':')?;
+    let second = take_digits(&mut rest, 2, 2)?;
+    let mut microsecond = 0;
+    if take_char(&mut rest, |c| c == '.').is_some() {
+        let fraction = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if fraction == 0 {
+            return None;
         }
+        let digits = format!("{:0<6}", &rest[..fraction.min(6)]);
+        microsecond = digits.parse().ok()?;
+        rest = &rest[fraction..];
     }
+    timestamp.time = Some((hour as u8, minute as u8, second as u8, microsecond));
+
+    rest = rest.trim_start_matches([' ', '\t']);
+    if let Some(sign) = take_char(&mut rest, |c| c == '+' || c == '-') {
+        let hours = take_digits(&mut rest, 1, 2)? as i32;
+        let minutes = match take_char(&mut rest, |c| c == ':') {
+            Some(_) => take_digits(&mut rest, 2, 2)? as i32,
+            None => 0,
+        };
+        let offset = hours * 3600 + minutes * 60;
+        timestamp.offset = Some(if sign == '-' { -offset } else { offset });
+    } else if take_char(&mut rest, |c| c == 'Z').is_some() {
+        timestamp.offset = Some(0);
+    }
+    rest.is_empty().then_some(timestamp)
+}
+
+fn take_char(rest: &mut &str, accept: impl Fn(char) -> bool) -> Option<char> {
+    let c = rest.chars().next().filter(|&c| accept(c))?;
+    *rest = &rest[c.len_utf8()..];
+    Some(c)
+}
+
+fn take_digits(rest: &mut &str, min: usize, max: usize) -> Option<u32> {
+    let len = rest.bytes().take(max).take_while(u8::is_ascii_digit).count();
+    if len < min {
+        return None;
+    }
+    let value = rest[..len].parse().ok()?;
+    *rest = &rest[len..];
+    Some(value)
 }
 
 #[pymodule]
-pub fn hierarchical_config_merging(_py: Python, m: &PyModule) -> PyResult<()> {
+pub fn hierarchical_config_merging(py: Python, m: &PyModule) -> PyResult<()> {
+    m.add("LOGGER_NAME", LOGGER_NAME)?;
+    m.add("HierarchicalConfigError", py.get_type::<HierarchicalConfigError>())?;
+    m.add("ParseError", py.get_type::<ParseError>())?;
+    m.add("HierarchyError", py.get_type::<HierarchyError>())?;
+    m.add("CollisionError", py.get_type::<CollisionError>())?;
+    m.add("ConflictError", py.get_type::<ConflictError>())?;
+    m.add_class::<PyReportEntry>()?;
+    m.add_class::<PyTaggedValue>()?;
+    m.add_class::<PyConfig>()?;
     m.add_function(wrap_pyfunction!(rust_merge_hierarchical_configs, m)?)?;
+    #[cfg(feature = "async")]
+    m.add_function(wrap_pyfunction!(rust_merge_hierarchical_configs_async, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_merge_with_report, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_merge_report_json, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_get_config_value, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_merge_as_config, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_merge_to_yaml, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_merge_to_json, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_merge_to_properties, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_merge_to_env, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_merge_config_hash, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_merge_with_files, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_merge_files, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_find_yaml_files, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_deep_merge, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_compute_override, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_to_properties, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_to_env, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_redact, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_render_tree, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_config_hash, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_merge_with_provenance, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_write_lockfile, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_generate_docs, m)?)?;
+    m.add_function(wrap_pyfunction!(rust_cli_main, m)?)?;
     Ok(())
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{fixture_tree, CountingSource};
+
+    #[test]
+    fn test_cached_merges_parse_each_file_once() {
+        let dir = fixture_tree(&[("config.yaml", "port: 80\n"), ("prod/config.yaml", "port: 8080\nname: api\n")]);
+        let prod = dir.path().join("prod");
+        let source = CountingSource::default();
+        let mergers = Mutex::new(Vec::new());
+        let merge = |options: MergeOptions| {
+            let new = |base_dir: &Path, options| HierarchyMerger::with_source(base_dir, options, source.clone());
+            merge_cached(&mergers, dir.path(), &prod, options, new).unwrap().0
+        };
+
+        assert_eq!(merge(MergeOptions::default())["port"], 8080);
+        assert_eq!(source.reads(), 2);
+        // A second lookup reuses the merge.
+        assert_eq!(merge(MergeOptions::default())["name"], "api");
+        assert_eq!(source.reads(), 2);
+
+        // Other options merge apart.
+        let strict = MergeOptions {
+            strict: true,
+            ..MergeOptions::default()
+        };
+        assert_eq!(merge(strict.clone())["port"], 8080);
+        assert_eq!((source.reads(), mergers.lock().unwrap().len()), (4, 2));
+
+        // Changed files are read again, and only they.
+        std::fs::write(prod.join("config.yaml"), "port: 9090\nname: web\n").unwrap();
+        assert_eq!(merge(strict)["name"], "web");
+        assert_eq!(source.reads(), 5);
+    }
+}
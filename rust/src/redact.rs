@@ -0,0 +1,139 @@
+//! Masking of sensitive values in human-readable output.
+
+use crate::keypath::{format_key_path, key_to_string, PathSegment};
+use crate::ConfigValue;
+
+/// What redacted values are replaced with.
+pub const REDACTED: &str = "<redacted>";
+
+/// Patterns of [`Redaction::default`].
+pub const DEFAULT_REDACT_PATTERNS: &[&str] = &["*password*", "*secret*", "*token*"];
+
+/// Key path patterns whose values are masked in report messages and other
+/// human-readable output; the merged config itself is never changed.
+///
+/// A pattern is matched against the whole dotted key path, such as
+/// `db.password` or `tokens[0]`, ignoring case. `*` matches any run of
+/// characters, dots included, and `?` one character.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Redaction {
+    pub patterns: Vec<String>,
+}
+
+impl Default for Redaction {
+    fn default() -> Self {
+        Self {
+            patterns: DEFAULT_REDACT_PATTERNS.iter().map(|pattern| pattern.to_string()).collect(),
+        }
+    }
+}
+
+impl Redaction {
+    /// No redaction at all.
+    pub fn none() -> Self {
+        Self { patterns: Vec::new() }
+    }
+
+    /// Whether the value at `path` is masked.
+    pub fn matches(&self, path: &[PathSegment]) -> bool {
+        if self.patterns.is_empty() {
+            return false;
+        }
+        let path = format_key_path(path).to_lowercase();
+        self.patterns
+            .iter()
+            .any(|pattern| glob_match(pattern.to_lowercase().as_bytes(), path.as_bytes()))
+    }
+
+    /// `value`, found at `path`, with every masked value below it replaced
+    /// by [`REDACTED`].
+    pub(crate) fn apply(&self, value: &ConfigValue, path: &mut Vec<PathSegment>) -> ConfigValue {
+        if self.matches(path) {
+            return ConfigValue::String(REDACTED.to_string());
+        }
+        match value {
+            ConfigValue::Mapping(map) => ConfigValue::Mapping(
+                map.iter()
+                    .map(|(key, item)| {
+                        path.push(PathSegment::Key(key_to_string(key)));
+                        let item = self.apply(item, path);
+                        path.pop();
+                        (key.clone(), item)
+                    })
+                    .collect(),
+            ),
+            ConfigValue::Sequence(items) => ConfigValue::Sequence(
+                items
+                    .iter()
+                    .enumerate()
+                    .map(|(index, item)| {
+                        path.push(PathSegment::Index(index));
+                        let item = self.apply(item, path);
+                        path.pop();
+                        item
+                    })
+                    .collect(),
+            ),
+            _ => value.clone(),
+        }
+    }
+}
+
+/// A copy of `config` that is safe to log: the values at key paths matching
+/// one of `patterns` are replaced by [`REDACTED`]. See [`Redaction`] for the
+/// pattern syntax.
+pub fn redact<S: AsRef<str>>(config: &ConfigValue, patterns: &[S]) -> ConfigValue {
+    let redaction = Redaction {
+        patterns: patterns.iter().map(|pattern| pattern.as_ref().to_string()).collect(),
+    };
+    redaction.apply(config, &mut Vec::new())
+}
+
+pub(crate) fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|skip| glob_match(rest, &text[skip..])),
+        Some((b'?', rest)) => !text.is_empty() && glob_match(rest, &text[1..]),
+        Some((c, rest)) => text.first() == Some(c) && glob_match(rest, &text[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn yaml(text: &str) -> ConfigValue {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn test_redact_masks_matching_paths_only() {
+        let config = yaml(
+            "db: {host: db, password: hunter2, auth: {Secret_Key: abc}}\ntokens: [a, b]\nuser: {name: x}\nport: 80\n",
+        );
+        let redacted = redact(&config, DEFAULT_REDACT_PATTERNS);
+        assert_eq!(
+            redacted,
+            yaml(
+                "db: {host: db, password: <redacted>, auth: {Secret_Key: <redacted>}}\ntokens: <redacted>\nuser: {name: x}\nport: 80\n"
+            )
+        );
+        assert_eq!(redact(&config, &["user.*", "port"]), yaml(
+            "db: {host: db, password: hunter2, auth: {Secret_Key: abc}}\ntokens: [a, b]\nuser: {name: <redacted>}\nport: <redacted>\n"
+        ));
+        assert_eq!(redact(&config, &[] as &[&str]), config);
+        assert!(!Redaction::none().matches(&[PathSegment::Key("password".to_string())]));
+    }
+
+    #[test]
+    fn test_collision_messages_carry_no_values() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.yaml"), "api_token: hunter2\n").unwrap();
+        std::fs::write(dir.path().join("b.yaml"), "api_token: swordfish\n").unwrap();
+
+        let (config, report) = crate::merge_hierarchy(dir.path(), dir.path(), &Default::default()).unwrap();
+        assert_eq!(config["api_token"], "swordfish");
+        let message = &report.entries[0].message;
+        assert!(message.contains("'api_token'") && !message.contains("hunter2") && !message.contains("swordfish"));
+    }
+}
@@ -0,0 +1,252 @@
+//! Config layers fetched over HTTP, see [`MergeOptions::remote_layers`].
+//! Built with the `http` feature.
+//!
+//! Requests are made with `ureq`, which fetches `https://` URLs over rustls
+//! and follows redirects.
+
+use std::error::Error;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::error::ConfigError;
+use crate::options::MergeOptions;
+use crate::report::{ContributingFile, ReportEntry, ReportKind};
+use crate::source::{parse_yaml_content, sha256_hex};
+use crate::{ConfigValue, LayerFile};
+
+/// A config fetched from `url` and merged as a layer of its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteLayer {
+    /// The `http://` or `https://` URL of a YAML document. It stands for the layer in
+    /// reports and provenance.
+    pub url: String,
+    /// Depth of the layer on the scale of [`crate::merge_files`] priorities:
+    /// 0 merges it with the files of the base directory, 1 with the first
+    /// sub-directory level, -1 below the base, and so on. Within its layer
+    /// it merges after the files.
+    pub priority: i64,
+    /// Time allowed to connect, then for each read and write.
+    pub timeout: Duration,
+    /// Largest response body accepted, in bytes.
+    pub max_bytes: u64,
+}
+
+impl RemoteLayer {
+    /// A layer for `url` at `priority`, with a 10 second timeout and a
+    /// 1 MiB limit.
+    pub fn new(url: impl Into<String>, priority: i64) -> Self {
+        Self {
+            url: url.into(),
+            priority,
+            timeout: Duration::from_secs(10),
+            max_bytes: 1024 * 1024,
+        }
+    }
+}
+
+/// Fetches and parses the remote layers of `options`, adding each to
+/// `configs` and `files`, which are kept in merge order. `base_depth` is the
+/// depth of the base directory's files.
+///
+/// A layer that cannot be fetched fails the merge with
+/// [`ConfigError::Remote`] under [`MergeOptions::strict`]; otherwise it is
+/// left out with an entry in `entries`. A body that is not YAML always fails.
+pub(crate) fn load_remote_layers(
+    options: &MergeOptions,
+    base_depth: i64,
+    configs: &mut Vec<(LayerFile, ConfigValue)>,
+    files: &mut Vec<ContributingFile>,
+    entries: &mut Vec<ReportEntry>,
+) -> Result<(), ConfigError> {
+    if options.remote_layers.is_empty() {
+        return Ok(());
+    }
+    for layer in &options.remote_layers {
+        let source = PathBuf::from(&layer.url);
+        let body = match fetch(layer) {
+            Ok(body) => body,
+            Err(reason) if options.strict => {
+                return Err(ConfigError::Remote {
+                    url: layer.url.clone(),
+                    reason,
+                });
+            }
+            Err(reason) => {
+                entries.push(ReportEntry {
+                    kind: ReportKind::RemoteUnavailable,
+                    key_path: None,
+                    message: format!("Skipped remote layer {}: {reason}", layer.url),
+                    files: vec![source],
+                });
+                continue;
+            }
+        };
+        let config = parse_yaml_content(&source, &body)?;
+        files.push(ContributingFile {
+            path: source.clone(),
+            depth: layer.priority,
+            sha256: sha256_hex(body.as_bytes()),
+        });
+        let layer_file = LayerFile {
+            depth: base_depth + layer.priority,
+            rank: 0,
+            path: source,
+        };
+        configs.push((layer_file, config));
+    }
+
+    configs.sort_by(|(a, _), (b, _)| a.cmp(b));
+    files.sort_by_key(|file| configs.iter().position(|(layer, _)| layer.path == file.path));
+    Ok(())
+}
+
+/// The body of `layer.url`, or why it could not be fetched.
+fn fetch(layer: &RemoteLayer) -> Result<String, String> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(layer.timeout)
+        .timeout_read(layer.timeout)
+        .timeout_write(layer.timeout)
+        .build();
+    let response = match agent.get(&layer.url).set("Accept", "application/yaml, text/yaml, */*").call() {
+        Ok(response) => response,
+        Err(ureq::Error::Status(code, response)) => {
+            return Err(format!("server answered {code} {}", response.status_text()));
+        }
+        Err(ureq::Error::Transport(transport)) => return Err(describe(&transport, layer.timeout)),
+    };
+    if response
+        .header("Content-Length")
+        .and_then(|length| length.trim().parse::<u64>().ok())
+        .is_some_and(|length| length > layer.max_bytes)
+    {
+        return Err(format!("body larger than {} bytes", layer.max_bytes));
+    }
+
+    let mut body = Vec::new();
+    response
+        .into_reader()
+        .take(layer.max_bytes + 1)
+        .read_to_end(&mut body)
+        .map_err(|e| describe(&e, layer.timeout))?;
+    if body.len() as u64 > layer.max_bytes {
+        return Err(format!("body larger than {} bytes", layer.max_bytes));
+    }
+    String::from_utf8(body).map_err(|_| "body is not UTF-8".to_string())
+}
+
+/// The reason for a failed request, naming the timeout if the connection or
+/// a read ran out of time.
+fn describe(error: &(dyn Error + 'static), timeout: Duration) -> String {
+    let mut cause = Some(error);
+    while let Some(current) = cause {
+        if let Some(io_error) = current.downcast_ref::<io::Error>()
+            && matches!(io_error.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut)
+        {
+            return format!("timed out after {timeout:?}");
+        }
+        cause = current.source();
+    }
+    error.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provenance::merge_hierarchy_with_provenance;
+    use crate::testing::fixture_tree;
+    use std::path::Path;
+
+    const YAML: &str = "application/yaml";
+
+    fn options(layers: Vec<RemoteLayer>) -> MergeOptions {
+        MergeOptions {
+            remote_layers: layers,
+            ..MergeOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_remote_layer_merges_at_its_priority() {
+        let dir = fixture_tree(&[
+            ("config.yaml", "name: api\nport: 80\n"),
+            ("prod/config.yaml", "replicas: 3\n"),
+        ]);
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("GET", "/config.yaml")
+            .with_header("content-type", YAML)
+            .with_body("port: 8080\n")
+            .create();
+        let url = format!("{}/config.yaml", server.url());
+        let options = options(vec![RemoteLayer::new(&url, 1)]);
+
+        let (config, report, provenance) =
+            merge_hierarchy_with_provenance(dir.path(), &dir.path().join("prod"), &options).unwrap();
+        assert_eq!(config, serde_yaml::from_str::<ConfigValue>("name: api\nport: 8080\nreplicas: 3\n").unwrap());
+        assert!(report.entries.is_empty(), "{:?}", report.entries);
+        let files: Vec<_> = report.files.iter().map(|file| (file.depth, file.path.clone())).collect();
+        assert_eq!(files[1..], [(1, dir.path().join("prod/config.yaml").canonicalize().unwrap()), (1, PathBuf::from(&url))]);
+        assert_eq!(report.files[2].sha256, sha256_hex(b"port: 8080\n"));
+        assert_eq!(provenance.source_of(&crate::parse_key_path("port").unwrap()), Some(Path::new(&url)));
+
+        // The caching merges fail rather than leave the layer out.
+        let err = crate::HierarchyMerger::new(dir.path(), options.clone()).merge(&dir.path().join("prod")).unwrap_err();
+        assert!(err.to_string().contains(&format!("does not fetch remote layers such as {url}")), "{err}");
+        assert!(crate::merge_many(dir.path(), &[dir.path().join("prod")], &options).is_err());
+        mock.assert();
+    }
+
+    #[test]
+    fn test_fetch_failures_warn_or_fail_when_strict() {
+        let dir = fixture_tree(&[("config.yaml", "port: 80\n")]);
+        let mut server = mockito::Server::new();
+        let missing = server.mock("GET", "/config.yaml").with_status(404).expect(2).create();
+        server
+            .mock("GET", "/large.yaml")
+            .with_header("content-type", YAML)
+            .with_body("x".repeat(64))
+            .create();
+        let url = format!("{}/config.yaml", server.url());
+
+        let (config, report) = crate::merge_hierarchy(dir.path(), dir.path(), &options(vec![RemoteLayer::new(&url, 1)])).unwrap();
+        assert_eq!(config["port"], 80);
+        assert_eq!(report.entries.len(), 1);
+        assert_eq!(report.entries[0].kind, ReportKind::RemoteUnavailable);
+        assert_eq!(
+            report.entries[0].message,
+            format!("Skipped remote layer {url}: server answered 404 Not Found")
+        );
+
+        let strict = MergeOptions {
+            strict: true,
+            ..options(vec![RemoteLayer::new(&url, 1)])
+        };
+        let err = crate::merge_hierarchy(dir.path(), dir.path(), &strict).unwrap_err();
+        assert!(matches!(&err, ConfigError::Remote { url: failed, .. } if *failed == url), "{err}");
+        missing.assert();
+
+        let small = RemoteLayer {
+            max_bytes: 16,
+            ..RemoteLayer::new(format!("{}/large.yaml", server.url()), 1)
+        };
+        assert_eq!(fetch(&small).unwrap_err(), "body larger than 16 bytes");
+    }
+
+    #[test]
+    fn test_silent_server_times_out() {
+        let mut server = mockito::Server::new();
+        server
+            .mock("GET", "/config.yaml")
+            .with_chunked_body(|body| {
+                std::thread::sleep(Duration::from_millis(500));
+                body.write_all(b"port: 80\n")
+            })
+            .create();
+        let layer = RemoteLayer {
+            timeout: Duration::from_millis(50),
+            ..RemoteLayer::new(format!("{}/config.yaml", server.url()), 0)
+        };
+        assert_eq!(fetch(&layer).unwrap_err(), "timed out after 50ms");
+    }
+}
@@ -0,0 +1,470 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Serializer};
+
+use crate::error::ConfigError;
+
+/// What a report entry is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReportKind {
+    /// The same key is defined by several files at one depth.
+    Collision,
+    /// A collision of a key of [`crate::MergeOptions::collision_exempt_paths`],
+    /// see [`crate::MergeOptions::report_exempt_collisions`]. Informational:
+    /// not a warning.
+    ExemptCollision,
+    /// No YAML file was found between the base and the target.
+    EmptyHierarchy,
+    /// A strategic merge patch has a `$patch` directive of unknown value; it
+    /// was merged as `$patch: merge`.
+    PatchDirective,
+    /// A file uses a renamed or deprecated key, see
+    /// [`crate::MergeOptions::aliases`].
+    Deprecation,
+    /// A `${...}` reference could not be resolved, see
+    /// [`crate::MergeOptions::interpolate`].
+    UnresolvedReference,
+    /// A string override was parsed into the type of the value it overrides,
+    /// see [`crate::MergeOptions::coerce_types`]. Informational: not a
+    /// warning.
+    Coercion,
+    /// A string override could not be parsed into the type of the value it
+    /// overrides, see [`crate::MergeOptions::coercion_failure`].
+    TypeConflict,
+    /// Several files hold identical configs, see
+    /// [`crate::MergeOptions::report_duplicates`]. Informational: not a
+    /// warning.
+    Duplicate,
+    /// A file was left out by [`crate::MergeOptions::max_merge_depth`].
+    /// Informational: not a warning.
+    Skipped,
+    /// A subtree was removed by [`crate::MergeOptions::prune_paths`].
+    /// Informational: not a warning.
+    Pruned,
+    /// A file below the first layer adds a top-level key, see
+    /// [`crate::MergeOptions::lock_top_level`].
+    LockViolation,
+    /// A file or directory could not be read and was left out, see
+    /// [`crate::MergeOptions::skip_unreadable`].
+    Unreadable,
+    /// A remote layer could not be fetched and was left out.
+    RemoteUnavailable,
+    /// A file breaks the schema of its directory, see
+    /// [`crate::MergeOptions::directory_schemas`].
+    SchemaViolation,
+    /// A value is not of the kind the types file expects, see
+    /// [`crate::MergeOptions::type_check`].
+    TypeMismatch,
+    /// A string at a path of [`crate::MergeOptions::units`] is not a
+    /// duration or byte size; it was kept as is.
+    InvalidQuantity,
+    /// A discovered path was no longer a regular file when it was read, and
+    /// was left out.
+    NotAFile,
+    /// The target is the base directory, whose subdirectories hold files
+    /// that were not merged, see [`crate::TargetMode`].
+    IgnoredDescendants,
+    /// A file contributes nothing to the merged config, see
+    /// [`crate::MergeOptions::report_shadowed_files`].
+    ShadowedFile,
+    /// A section was left out of a file because its feature is not enabled,
+    /// see [`crate::IF_FEATURE_KEY`]. Informational: not a warning.
+    DisabledFeature,
+    /// An index patch patches no item and was left out, see
+    /// [`crate::MergeOptions::index_patches`].
+    IndexOutOfRange,
+}
+
+impl ReportKind {
+    /// Every kind, in the order they are declared.
+    pub const ALL: &'static [ReportKind] = &[
+        ReportKind::Collision,
+        ReportKind::ExemptCollision,
+        ReportKind::EmptyHierarchy,
+        ReportKind::PatchDirective,
+        ReportKind::Deprecation,
+        ReportKind::UnresolvedReference,
+        ReportKind::Coercion,
+        ReportKind::TypeConflict,
+        ReportKind::Duplicate,
+        ReportKind::Skipped,
+        ReportKind::Pruned,
+        ReportKind::LockViolation,
+        ReportKind::Unreadable,
+        ReportKind::RemoteUnavailable,
+        ReportKind::SchemaViolation,
+        ReportKind::TypeMismatch,
+        ReportKind::InvalidQuantity,
+        ReportKind::NotAFile,
+        ReportKind::IgnoredDescendants,
+        ReportKind::ShadowedFile,
+        ReportKind::DisabledFeature,
+        ReportKind::IndexOutOfRange,
+    ];
+
+    /// The snake_case name of the kind, as in the JSON report.
+    pub fn name(self) -> &'static str {
+        match self {
+            ReportKind::Collision => "collision",
+            ReportKind::ExemptCollision => "exempt_collision",
+            ReportKind::EmptyHierarchy => "empty_hierarchy",
+            ReportKind::PatchDirective => "patch_directive",
+            ReportKind::Deprecation => "deprecation",
+            ReportKind::UnresolvedReference => "unresolved_reference",
+            ReportKind::Coercion => "coercion",
+            ReportKind::TypeConflict => "type_conflict",
+            ReportKind::Duplicate => "duplicate",
+            ReportKind::Skipped => "skipped",
+            ReportKind::Pruned => "pruned",
+            ReportKind::LockViolation => "lock_violation",
+            ReportKind::Unreadable => "unreadable",
+            ReportKind::RemoteUnavailable => "remote_unavailable",
+            ReportKind::SchemaViolation => "schema_violation",
+            ReportKind::TypeMismatch => "type_mismatch",
+            ReportKind::InvalidQuantity => "invalid_quantity",
+            ReportKind::NotAFile => "not_a_file",
+            ReportKind::IgnoredDescendants => "ignored_descendants",
+            ReportKind::ShadowedFile => "shadowed_file",
+            ReportKind::DisabledFeature => "disabled_feature",
+            ReportKind::IndexOutOfRange => "index_out_of_range",
+        }
+    }
+
+    /// How serious entries of this kind are in a merge that succeeded.
+    pub fn severity(self) -> Severity {
+        match self {
+            ReportKind::Coercion
+            | ReportKind::Duplicate
+            | ReportKind::Skipped
+            | ReportKind::Pruned
+            | ReportKind::ExemptCollision
+            | ReportKind::IgnoredDescendants
+            | ReportKind::ShadowedFile
+            | ReportKind::DisabledFeature => Severity::Info,
+            _ => Severity::Warning,
+        }
+    }
+
+    /// Whether entries of this kind are warnings, as opposed to information.
+    pub fn is_warning(self) -> bool {
+        self.severity() == Severity::Warning
+    }
+}
+
+/// How serious a report entry is. Entries of a merge that failed, see
+/// [`MergeReport::failure_json`], are errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+/// One finding collected while merging.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportEntry {
+    pub kind: ReportKind,
+    /// Dotted key path the entry is about, if any.
+    pub key_path: Option<String>,
+    /// Files involved, in merge order.
+    pub files: Vec<PathBuf>,
+    pub message: String,
+}
+
+/// An entry in the JSON report. The field names are a stable schema: fields
+/// may be added, never renamed or removed.
+#[derive(Serialize)]
+pub(crate) struct JsonEntry<'a> {
+    pub(crate) kind: &'a str,
+    pub(crate) severity: Severity,
+    pub(crate) path: Option<&'a str>,
+    pub(crate) files: &'a [PathBuf],
+    pub(crate) message: &'a str,
+}
+
+#[derive(Serialize)]
+struct FailureReport<'a> {
+    entries: Vec<JsonEntry<'a>>,
+    files: &'a [ContributingFile],
+}
+
+impl ReportEntry {
+    pub(crate) fn json(&self, severity: Severity) -> JsonEntry<'_> {
+        JsonEntry {
+            kind: self.kind.name(),
+            severity,
+            path: self.key_path.as_deref(),
+            files: &self.files,
+            message: &self.message,
+        }
+    }
+}
+
+/// `{"kind", "severity", "path", "files", "message"}`, `kind` and `severity`
+/// by name and `path` the dotted key path or null.
+impl Serialize for ReportEntry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.json(self.kind.severity()).serialize(serializer)
+    }
+}
+
+/// A file that was parsed and merged.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ContributingFile {
+    pub path: PathBuf,
+    /// Directory levels below the base directory (0 for files in the base).
+    /// Files from extra roots carry their root's depth offset.
+    pub depth: i64,
+    /// Hex SHA-256 of the file contents.
+    pub sha256: String,
+}
+
+/// Everything noteworthy that happened during a merge, besides the merged
+/// config itself.
+///
+/// Serializes as `{"entries": [...], "files": [{"path", "depth", "sha256"}],
+/// "stats": {"nodes", "bytes_estimate"}}`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MergeReport {
+    pub entries: Vec<ReportEntry>,
+    /// Files in the order they were merged.
+    pub files: Vec<ContributingFile>,
+    /// The size of the merged config.
+    pub stats: MergeStats,
+}
+
+/// The size of a merged config, see [`crate::MergeOptions::max_merged_nodes`]
+/// for what is counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct MergeStats {
+    pub nodes: usize,
+    pub bytes_estimate: usize,
+}
+
+impl MergeReport {
+    pub(crate) fn empty_hierarchy(base_dir: &Path, target_path: &Path) -> Self {
+        Self {
+            entries: vec![ReportEntry {
+                kind: ReportKind::EmptyHierarchy,
+                key_path: None,
+                files: Vec::new(),
+                message: format!(
+                    "No YAML files found in hierarchy from {} to {}",
+                    base_dir.display(),
+                    target_path.display()
+                ),
+            }],
+            files: Vec::new(),
+            stats: MergeStats::default(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The entries as plain messages, as returned by
+    /// [`crate::merge_hierarchical_configs`].
+    /// Informational entries are left out.
+    pub fn warnings(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.kind.is_warning())
+            .map(|entry| entry.message.clone())
+            .collect()
+    }
+
+    /// How many entries the report has of each kind and severity.
+    ///
+    /// ```
+    /// # use hierarchical_config_merging::{MergeReport, ReportEntry, ReportKind, Severity};
+    /// let entry = |kind| ReportEntry { kind, key_path: None, files: Vec::new(), message: String::new() };
+    /// let report = MergeReport {
+    ///     entries: vec![entry(ReportKind::Skipped), entry(ReportKind::Collision), entry(ReportKind::Collision)],
+    ///     ..MergeReport::default()
+    /// };
+    /// let summary = report.summary();
+    /// assert_eq!(summary.count(Severity::Warning), 2);
+    /// assert_eq!(summary.to_string(), "2 warnings (collision: 2), 1 info (skipped: 1)");
+    /// ```
+    pub fn summary(&self) -> ReportSummary {
+        let mut kinds: Vec<(ReportKind, usize)> = Vec::new();
+        for entry in &self.entries {
+            match kinds.iter_mut().find(|(kind, _)| *kind == entry.kind) {
+                Some((_, count)) => *count += 1,
+                None => kinds.push((entry.kind, 1)),
+            }
+        }
+        kinds.sort_by_key(|(kind, _)| std::cmp::Reverse(kind.severity()));
+        ReportSummary { kinds }
+    }
+
+    /// The report as pretty-printed JSON. Fails on a path that is not valid
+    /// UTF-8.
+    pub fn to_json(&self) -> Result<String, ConfigError> {
+        serde_json::to_string_pretty(self).map_err(|e| ConfigError::Other(e.into()))
+    }
+
+    /// The JSON report of a merge that failed with `error`, in the schema of
+    /// [`MergeReport::to_json`]: the entries that failed a collision or strict
+    /// check, or else one entry of kind `"error"` with the error's message,
+    /// all of severity `error`.
+    pub fn failure_json(error: &ConfigError) -> Result<String, ConfigError> {
+        let message = error.to_string();
+        let report = FailureReport {
+            entries: Self::failure_entries(error, &message),
+            files: &[],
+        };
+        serde_json::to_string_pretty(&report).map_err(|e| ConfigError::Other(e.into()))
+    }
+
+    /// The entries of [`MergeReport::failure_json`], `message` being the
+    /// message of `error`.
+    pub(crate) fn failure_entries<'a>(error: &'a ConfigError, message: &'a str) -> Vec<JsonEntry<'a>> {
+        match error {
+            ConfigError::Collision { entries } | ConfigError::Strict { entries } => {
+                entries.iter().map(|entry| entry.json(Severity::Error)).collect()
+            }
+            _ => vec![JsonEntry {
+                kind: "error",
+                severity: Severity::Error,
+                path: None,
+                files: &[],
+                message,
+            }],
+        }
+    }
+}
+
+/// Entry counts of a report, see [`MergeReport::summary`]. Displays as
+/// `2 warnings (collision: 2), 1 info (skipped: 1)`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReportSummary {
+    /// The number of entries of each kind present, most severe kinds first
+    /// and otherwise in the order of their first entry.
+    pub kinds: Vec<(ReportKind, usize)>,
+}
+
+impl ReportSummary {
+    /// The number of entries of `severity`.
+    pub fn count(&self, severity: Severity) -> usize {
+        self.kinds
+            .iter()
+            .filter(|(kind, _)| kind.severity() == severity)
+            .map(|(_, count)| count)
+            .sum()
+    }
+
+    /// The number of entries.
+    pub fn total(&self) -> usize {
+        self.kinds.iter().map(|(_, count)| count).sum()
+    }
+}
+
+impl fmt::Display for ReportSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.kinds.is_empty() {
+            return f.write_str("no entries");
+        }
+        let mut first = true;
+        for severity in [Severity::Error, Severity::Warning, Severity::Info] {
+            let count = self.count(severity);
+            if count == 0 {
+                continue;
+            }
+            let plural = if count > 1 && severity != Severity::Info { "s" } else { "" };
+            let kinds: Vec<String> = self
+                .kinds
+                .iter()
+                .filter(|(kind, _)| kind.severity() == severity)
+                .map(|(kind, count)| format!("{}: {count}", kind.name()))
+                .collect();
+            let separator = if first { "" } else { ", " };
+            write!(f, "{separator}{count} {}{plural} ({})", severity.name(), kinds.join(", "))?;
+            first = false;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> MergeReport {
+        MergeReport {
+            entries: vec![
+                ReportEntry {
+                    kind: ReportKind::Collision,
+                    key_path: Some("server.port".to_string()),
+                    files: vec![PathBuf::from("a/one.yaml"), PathBuf::from("a/two.yaml")],
+                    message: "Key collision at depth 1 for 'server.port' between a/one.yaml and a/two.yaml".to_string(),
+                },
+                ReportEntry {
+                    kind: ReportKind::Coercion,
+                    key_path: Some("workers".to_string()),
+                    files: vec![PathBuf::from("a/two.yaml")],
+                    message: r#"Coerced "8" at 'workers' in a/two.yaml to an integer"#.to_string(),
+                },
+            ],
+            files: vec![ContributingFile {
+                path: PathBuf::from("config.yaml"),
+                depth: 0,
+                sha256: "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855".to_string(),
+            }],
+            stats: MergeStats {
+                nodes: 4,
+                bytes_estimate: 26,
+            },
+        }
+    }
+
+    #[test]
+    fn test_json_report_matches_golden() {
+        assert_eq!(
+            sample().to_json().unwrap() + "\n",
+            include_str!("../../tests/golden/report.json")
+        );
+    }
+
+    #[test]
+    fn test_json_schema_is_stable() {
+        let report: serde_json::Value = serde_json::from_str(&sample().to_json().unwrap()).unwrap();
+        for entry in report["entries"].as_array().unwrap() {
+            for field in ["kind", "severity", "path", "files", "message"] {
+                assert!(entry.get(field).is_some(), "missing {field} in {entry}");
+            }
+        }
+        for file in report["files"].as_array().unwrap() {
+            for field in ["path", "depth", "sha256"] {
+                assert!(file.get(field).is_some(), "missing {field} in {file}");
+            }
+        }
+        for field in ["nodes", "bytes_estimate"] {
+            assert!(report["stats"].get(field).is_some(), "missing {field} in stats");
+        }
+
+        let failure = ConfigError::Collision { entries: sample().entries[..1].to_vec() };
+        let report: serde_json::Value = serde_json::from_str(&MergeReport::failure_json(&failure).unwrap()).unwrap();
+        assert_eq!(report["entries"][0]["kind"], "collision");
+        assert_eq!(report["entries"][0]["severity"], "error");
+        assert_eq!(report["files"], serde_json::json!([]));
+
+        let failure = ConfigError::ReferenceCycle { chain: vec!["a".into(), "a".into()] };
+        let report: serde_json::Value = serde_json::from_str(&MergeReport::failure_json(&failure).unwrap()).unwrap();
+        assert_eq!(report["entries"][0]["kind"], "error");
+        assert_eq!(report["entries"][0]["path"], serde_json::Value::Null);
+        assert_eq!(report["entries"][0]["message"], "Reference cycle: a -> a");
+    }
+}
@@ -0,0 +1,133 @@
+use std::path::Path;
+use anyhow::Result;
+
+use crate::cancel::{arm, check_cancelled};
+use crate::coerce::coerce_override;
+use crate::error::ConfigError;
+use crate::limits::{measure, SizeGuard};
+use crate::metadata::embed_metadata;
+use crate::priority::strip_layer_priorities;
+use crate::mergeable::{merge_values_resolving, ValueResolver};
+use crate::options::{CollisionPolicy, MergeOptions};
+use crate::report::MergeReport;
+use crate::{
+    check_merge_files, collect_depth_collisions, collect_lock_violations, empty_merge, finish_merge, group_by_depth, load_hierarchy, trace, FsSource,
+    ConfigValue,
+};
+
+/// Decides the value of a key defined on both sides of a merge, unless both
+/// values are mappings (those merge key by key). Called with the key path, the
+/// base value and the override value; the returned value is used as is, so
+/// `sequence_strategy` does not apply to it.
+pub type ConflictResolver<'a> = ValueResolver<'a, ConfigValue>;
+
+/// [`crate::deep_merge_with`] asking `resolve` for every conflicting value.
+/// A failing resolver aborts the merge with [`ConfigError::Conflict`].
+pub fn deep_merge_resolving(
+    base: &ConfigValue,
+    r#override: &ConfigValue,
+    options: &MergeOptions,
+    resolve: &mut ConflictResolver,
+) -> Result<ConfigValue, ConfigError> {
+    merge_values_resolving(base, r#override, options, resolve)
+}
+
+/// Like [`crate::merge_hierarchy`], letting `resolve` decide every value that
+/// a file would override.
+pub fn merge_hierarchy_resolving(
+    base_dir: &Path,
+    target_path: &Path,
+    options: &MergeOptions,
+    resolve: &mut ConflictResolver,
+) -> Result<(ConfigValue, MergeReport), ConfigError> {
+    let options = &arm(options);
+    let base_dir = &options.input_path(base_dir)?;
+    let target_path = &options.input_path(target_path)?;
+
+    let Some(loaded) = load_hierarchy(&FsSource, base_dir, target_path, options)? else {
+        return empty_merge(base_dir, target_path, options);
+    };
+
+    let _span = trace::merge_span(loaded.configs.len());
+    let mut merged_config = options.initial_config();
+    let mut report = MergeReport::default();
+
+    let groups = group_by_depth(loaded.layers());
+    let files: Vec<_> = groups.iter().flat_map(|(_, group)| group.iter().copied()).collect();
+    check_merge_files(&files, options, &mut report.entries)?;
+    let mut guard = SizeGuard::new(&merged_config, options);
+    for (index, ((depth, _), depth_configs)) in groups.into_iter().enumerate() {
+        check_cancelled(options)?;
+        if options.collision_policy != CollisionPolicy::Ignore {
+            collect_depth_collisions(depth, &depth_configs, options, &mut report.entries);
+        }
+        let stripped = strip_layer_priorities(&depth_configs);
+        let depth_configs: Vec<_> = stripped.iter().map(|(path, config)| (*path, config.as_ref())).collect();
+        collect_lock_violations(&merged_config, &depth_configs, index == 0, options, &mut report.entries);
+        for (source, config) in &depth_configs {
+            let config = coerce_override(&merged_config, config, Some(source), options, &mut report.entries)?;
+            merged_config = deep_merge_resolving(&merged_config, &config, options, resolve)?;
+            guard.add(&config);
+            guard.check(&merged_config, source, options)?;
+        }
+        trace::merged_layer(depth, depth_configs.len());
+    }
+
+    let merged_config = finish_merge(merged_config, &files, options, &mut report.entries)?;
+    report.stats = measure(&merged_config);
+    loaded.check_types(&merged_config, &mut report);
+    loaded.complete_report(&mut report);
+    options.check_report(&report)?;
+    let merged_config = embed_metadata(merged_config, Some(base_dir), Some(target_path), &report, options);
+    Ok((merged_config, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ConfigError;
+    use crate::keypath::format_key_path;
+    use std::fs;
+
+    fn yaml(text: &str) -> ConfigValue {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn test_resolver_sees_leaf_conflicts_only() {
+        let base = yaml("server: {port: 80, host: a}\nlist: [1]\n");
+        let over = yaml("server: {port: 8080}\nlist: [2]\nnew: x\n");
+        let mut seen = Vec::new();
+
+        let merged = deep_merge_resolving(&base, &over, &MergeOptions::default(), &mut |path, base, over| {
+            seen.push(format_key_path(path));
+            Ok(if path.len() == 2 { base.clone() } else { over.clone() })
+        })
+        .unwrap();
+
+        assert_eq!(seen, ["server.port", "list"]);
+        assert_eq!(merged, yaml("server: {port: 80, host: a}\nlist: [2]\nnew: x\n"));
+    }
+
+    #[test]
+    fn test_failing_resolver_names_the_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let leaf = dir.path().join("app");
+        fs::create_dir_all(&leaf).unwrap();
+        fs::write(dir.path().join("config.yaml"), "limits: {cpu: 2}\n").unwrap();
+        fs::write(leaf.join("config.yaml"), "limits: {cpu: 4}\n").unwrap();
+
+        let err = merge_hierarchy_resolving(dir.path(), &leaf, &MergeOptions::default(), &mut |_, _, _| {
+            Err(anyhow::anyhow!("no way"))
+        })
+        .unwrap_err();
+
+        match &err {
+            ConfigError::Conflict { path, source } => {
+                assert_eq!(path, "limits.cpu");
+                assert_eq!(source.to_string(), "no way");
+            }
+            other => panic!("unexpected error: {other:?}"),
+        }
+    }
+}
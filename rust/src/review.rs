@@ -0,0 +1,304 @@
+//! A self-contained HTML page for reviewing a merge, see
+//! [`render_html_report`].
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use crate::diff::{diff, Change};
+use crate::keypath::{key_to_string, PathSegment};
+use crate::provenance::Provenance;
+use crate::report::{MergeReport, Severity};
+use crate::ConfigValue;
+
+/// The page [`render_html_report`] fills in: `{{name}}` placeholders, with
+/// the CSS and the script inline.
+const TEMPLATE: &str = include_str!("../templates/review.html");
+
+/// Everything a merge with provenance returns, as
+/// [`crate::merge_hierarchy_with_provenance`] does.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MergeOutcome {
+    pub config: ConfigValue,
+    pub report: MergeReport,
+    pub provenance: Provenance,
+}
+
+impl From<(ConfigValue, MergeReport, Provenance)> for MergeOutcome {
+    fn from((config, report, provenance): (ConfigValue, MergeReport, Provenance)) -> Self {
+        Self {
+            config,
+            report,
+            provenance,
+        }
+    }
+}
+
+/// The merge of `current` as a single HTML page, for a release review: the
+/// files merged, the report entries, and the merged config as a tree of
+/// collapsible mappings whose values each name the file defining them.
+///
+/// With a `previous` merged config, every node is also marked as added,
+/// modified or unchanged against it, mappings holding a change as changed,
+/// and the keys removed since are shown struck through; a switch hides the
+/// unchanged nodes. The page loads nothing: its CSS and script are inline.
+///
+/// Values are shown as they are; see [`crate::redact`] for masking them
+/// first.
+///
+/// ```
+/// # use hierarchical_config_merging::{merge_layered_with_provenance, render_html_report, ConfigValue, Layer, MergeOptions, MergeOutcome};
+/// let layers = vec![Layer::new("defaults", 0, serde_yaml::from_str("port: 80").unwrap())];
+/// let outcome: MergeOutcome = merge_layered_with_provenance(layers, &MergeOptions::default()).unwrap().into();
+/// let previous: ConfigValue = serde_yaml::from_str("port: 8080").unwrap();
+/// let html = render_html_report(&outcome, Some(&previous));
+/// assert!(html.contains(r#"<li class="leaf modified">"#));
+/// ```
+pub fn render_html_report(current: &MergeOutcome, previous: Option<&ConfigValue>) -> String {
+    let entries = previous.map(|previous| diff(previous, &current.config));
+    let changes = entries
+        .as_ref()
+        .map(|entries| entries.iter().map(|entry| (entry.path.clone(), &entry.change)).collect());
+    let tree = Tree {
+        provenance: &current.provenance,
+        changes,
+    };
+
+    let mut html = String::from("<ul class=\"tree\">\n");
+    match &current.config {
+        ConfigValue::Mapping(map) if !map.is_empty() => tree.write_entries(&mut html, map, &mut Vec::new(), None),
+        value => tree.write_leaf(&mut html, "", value, &[], None),
+    }
+    html.push_str("</ul>");
+
+    fill(TEMPLATE, &[("title", "Merge review"), ("summary", &summary(&current.report, tree.changes.as_ref())), ("tree", &html)])
+}
+
+/// The files merged, the changes and the report entries.
+fn summary(report: &MergeReport, changes: Option<&BTreeMap<Vec<PathSegment>, &Change>>) -> String {
+    let mut html = String::new();
+    if !report.files.is_empty() {
+        html.push_str("<h2>Merged from, in order</h2>\n<ol class=\"files\">\n");
+        for file in &report.files {
+            html.push_str(&format!("<li>{}</li>\n", escape(&file.path.display().to_string())));
+        }
+        html.push_str("</ol>\n");
+    }
+    if let Some(changes) = changes {
+        let count = |wanted: fn(&Change) -> bool| changes.values().filter(|change| wanted(change)).count();
+        let counts = [
+            (count(|change| matches!(change, Change::Added(_))), "added"),
+            (count(|change| matches!(change, Change::Modified { .. })), "modified"),
+            (count(|change| matches!(change, Change::Removed(_))), "removed"),
+        ];
+        let counts: Vec<String> = counts.iter().map(|(count, status)| format!("{count} {status}")).collect();
+        html.push_str(&format!("<h2>Changes</h2>\n<p class=\"changes\">{}</p>\n", counts.join(", ")));
+    }
+    if !report.is_empty() {
+        html.push_str(&format!("<h2>Report</h2>\n<p>{}</p>\n<ul class=\"report\">\n", escape(&report.summary().to_string())));
+        for entry in &report.entries {
+            let class = match entry.kind.severity() {
+                Severity::Info => "info",
+                _ => "warning",
+            };
+            html.push_str(&format!("<li class=\"{class}\">{}: {}</li>\n", entry.kind.name(), escape(&entry.message)));
+        }
+        html.push_str("</ul>\n");
+    }
+    html
+}
+
+/// What the nodes of the merged config are annotated with.
+struct Tree<'a> {
+    provenance: &'a Provenance,
+    /// The changes since the previous config, by path, if one was given.
+    changes: Option<BTreeMap<Vec<PathSegment>, &'a Change>>,
+}
+
+impl Tree<'_> {
+    /// One node per entry of the mapping at `path`, followed by the keys
+    /// removed from it. `inherited` is the status of a mapping that is new
+    /// as a whole.
+    fn write_entries(&self, html: &mut String, map: &serde_yaml::Mapping, path: &mut Vec<PathSegment>, inherited: Option<&str>) {
+        for (key, value) in map {
+            let key = key_to_string(key);
+            path.push(PathSegment::Key(key.clone()));
+            match value {
+                ConfigValue::Mapping(map) if !map.is_empty() => {
+                    let status = inherited.or_else(|| self.status(path));
+                    html.push_str(&format!(
+                        "<li class=\"node {}\"><details open><summary><span class=\"key\">{}</span>{}{}</summary>\n<ul>\n",
+                        status.unwrap_or("unchanged"),
+                        escape(&key),
+                        self.badge(status, path),
+                        self.sources(path),
+                    ));
+                    // The keys of a mapping that is new, or replaces another
+                    // value, are all new.
+                    let replaces = matches!(status, Some("added" | "modified"));
+                    self.write_entries(html, map, path, inherited.or(replaces.then_some("added")));
+                    html.push_str("</ul>\n</details></li>\n");
+                }
+                value => self.write_leaf(html, &key, value, path, inherited),
+            }
+            path.pop();
+        }
+        if let Some(changes) = &self.changes {
+            let removed = changes.range(path.clone()..).take_while(|(changed, _)| changed.starts_with(path));
+            for (changed, change) in removed {
+                if let (Change::Removed(old), [PathSegment::Key(key)]) = (change, &changed[path.len()..]) {
+                    html.push_str(&format!(
+                        "<li class=\"leaf removed\"><span class=\"key\">{}</span>: <span class=\"value\">{}</span>{}</li>\n",
+                        escape(key),
+                        escape(&inline(old)),
+                        badge("removed", None)
+                    ));
+                }
+            }
+        }
+    }
+
+    /// A value merged as a whole: a scalar, a sequence or an empty mapping.
+    fn write_leaf(&self, html: &mut String, key: &str, value: &ConfigValue, path: &[PathSegment], inherited: Option<&str>) {
+        let status = inherited.or_else(|| self.status(path));
+        html.push_str(&format!("<li class=\"leaf {}\">", status.unwrap_or("unchanged")));
+        if !key.is_empty() {
+            html.push_str(&format!("<span class=\"key\">{}</span>: ", escape(key)));
+        }
+        html.push_str(&format!("<span class=\"value\">{}</span>", escape(&inline(value))));
+        html.push_str(&self.badge(status, path));
+        html.push_str(&self.sources(path));
+        html.push_str("</li>\n");
+    }
+
+    /// `None` without a previous config; otherwise how the value at `path`
+    /// changed: added, modified, changed below or unchanged.
+    fn status(&self, path: &[PathSegment]) -> Option<&'static str> {
+        let changes = self.changes.as_ref()?;
+        Some(match changes.get(path) {
+            Some(Change::Added(_)) => "added",
+            Some(_) => "modified",
+            None if changes.range(path.to_vec()..).next().is_some_and(|(changed, _)| changed.starts_with(path)) => "changed",
+            None => "unchanged",
+        })
+    }
+
+    /// The badge of the node at `path` unless it is unchanged, giving the
+    /// value a modified node replaced.
+    fn badge(&self, status: Option<&str>, path: &[PathSegment]) -> String {
+        match (status, self.changes.as_ref().and_then(|changes| changes.get(path))) {
+            (None | Some("unchanged"), _) => String::new(),
+            (Some(status), Some(Change::Modified { old, .. })) => badge(status, Some(old)),
+            (Some(status), _) => badge(status, None),
+        }
+    }
+
+    /// The file defining the values at or below `path`, or how many files
+    /// do.
+    fn sources(&self, path: &[PathSegment]) -> String {
+        let files: BTreeSet<&Path> = match self.provenance.source_of(path) {
+            Some(file) => BTreeSet::from([file]),
+            None => self.provenance.sources_below(path).collect(),
+        };
+        match files.len() {
+            0 => String::new(),
+            1 => format!("<span class=\"source\">{}</span>", escape(&files.first().unwrap().display().to_string())),
+            count => format!("<span class=\"source\">{count} files</span>"),
+        }
+    }
+}
+
+/// The badge of a node changed as `status`, with the value it replaced.
+fn badge(status: &str, old: Option<&ConfigValue>) -> String {
+    match old {
+        Some(old) => format!("<span class=\"badge\">{status}</span><span class=\"was\">was {}</span>", escape(&inline(old))),
+        None => format!("<span class=\"badge\">{status}</span>"),
+    }
+}
+
+/// `value` on one line, as JSON.
+fn inline(value: &ConfigValue) -> String {
+    serde_json::to_string(value).unwrap_or_else(|_| "?".to_string())
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// `template` with each `{{name}}` replaced by its value, in one pass so
+/// that values are never taken for placeholders.
+fn fill(template: &str, values: &[(&str, &str)]) -> String {
+    let mut filled = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let value = rest[start + 2..].split_once("}}").and_then(|(name, _)| {
+            let (_, value) = values.iter().find(|(wanted, _)| *wanted == name)?;
+            Some((name.len(), *value))
+        });
+        filled.push_str(&rest[..start]);
+        match value {
+            Some((len, value)) => {
+                filled.push_str(value);
+                rest = &rest[start + len + 4..];
+            }
+            None => {
+                filled.push_str("{{");
+                rest = &rest[start + 2..];
+            }
+        }
+    }
+    filled.push_str(rest);
+    filled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{merge_layered_with_provenance, Layer, MergeOptions};
+
+    fn yaml(text: &str) -> ConfigValue {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    fn outcome() -> MergeOutcome {
+        let layers = vec![
+            Layer::new("defaults.yaml", 0, yaml("name: '<{{tree}}>'\nserver: {host: localhost, port: 80}\nfeatures: [a]\n")),
+            Layer::new("prod.yaml", 1, yaml("server: {port: 8080}\ndb: {pool: 5, options: {}}\n")),
+            Layer::new("site.yaml", 1, yaml("db: {pool: 10}\n")),
+        ];
+        merge_layered_with_provenance(layers, &MergeOptions::default()).unwrap().into()
+    }
+
+    #[test]
+    fn test_html_report_matches_golden() {
+        let previous = yaml("name: '<{{tree}}>'\nserver: {host: localhost, port: 80}\nfeatures: [a]\nlegacy: true\n");
+        let html = render_html_report(&outcome(), Some(&previous));
+        assert_eq!(html, include_str!("../../tests/golden/review.html"));
+    }
+
+    #[test]
+    fn test_html_report_without_previous_config() {
+        let html = render_html_report(&outcome(), None);
+        assert!(!html.contains("class=\"badge\""));
+        assert!(!html.contains("<h2>Changes</h2>"));
+        assert!(html.contains("<li class=\"leaf unchanged\"><span class=\"key\">port</span>: <span class=\"value\">8080</span><span class=\"source\">prod.yaml</span></li>"));
+        assert!(html.contains("<span class=\"key\">db</span><span class=\"source\">2 files</span>"));
+
+        let scalar = MergeOutcome {
+            config: yaml("42"),
+            ..MergeOutcome::default()
+        };
+        let html = render_html_report(&scalar, Some(&yaml("{a: 1}")));
+        assert!(html.contains("<li class=\"leaf modified\"><span class=\"value\">42</span><span class=\"badge\">modified</span><span class=\"was\">was {&quot;a&quot;:1}</span></li>"));
+    }
+}
@@ -0,0 +1,359 @@
+//! Per-directory JSON Schemas for the files of each level, see
+//! [`MergeOptions::directory_schemas`]. Built with the `schema` feature.
+//!
+//! The crate checks a subset of JSON Schema, without dependencies: `type`,
+//! `enum`, `const`, `properties`, `additionalProperties`, `items`, the
+//! `minimum`/`maximum` bounds, their exclusive forms, and `minLength`,
+//! `maxLength`, `minItems` and `maxItems`. Other keywords are ignored.
+//! `required` is too: a level only sets the keys it overrides, so a file is
+//! never expected to hold the whole config.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use anyhow::{bail, Context, Result};
+
+use crate::keypath::{format_key_path, key_to_string, PathSegment};
+use crate::options::MergeOptions;
+use crate::report::{ReportEntry, ReportKind};
+use crate::source::{parse_yaml_file, ConfigSource, Fingerprint};
+use crate::ConfigValue;
+
+/// Name of the schema file of a directory.
+pub const SCHEMA_FILE: &str = "_schema.yaml";
+
+/// Validates each of `files` against the [`SCHEMA_FILE`] of its directory,
+/// if there is one, read through `source`, adding an entry to `entries` for
+/// every violation.
+pub(crate) fn check_directory_schemas(
+    source: &dyn ConfigSource,
+    files: &[(&Path, &ConfigValue)],
+    options: &MergeOptions,
+    entries: &mut Vec<ReportEntry>,
+) -> Result<()> {
+    if !options.directory_schemas {
+        return Ok(());
+    }
+    let mut schemas: HashMap<PathBuf, Option<ConfigValue>> = HashMap::new();
+    for (file, config) in files {
+        let Some(dir) = file.parent() else {
+            continue;
+        };
+        if !schemas.contains_key(dir) {
+            let schema_path = dir.join(SCHEMA_FILE);
+            let schema = match schema_path.is_file() {
+                true => Some(parse_yaml_file(source, &schema_path)?),
+                false => None,
+            };
+            schemas.insert(dir.to_path_buf(), schema);
+        }
+        let Some(schema) = &schemas[dir] else {
+            continue;
+        };
+
+        let mut violations = Vec::new();
+        validate(schema, config, &mut Vec::new(), &mut violations)
+            .with_context(|| format!("Invalid schema {}", dir.join(SCHEMA_FILE).display()))?;
+        for (path, problem) in violations {
+            let shown = if path.is_empty() { "the document" } else { &path };
+            entries.push(ReportEntry {
+                kind: ReportKind::SchemaViolation,
+                message: format!("Schema violation in {} at '{shown}': {problem}", file.display()),
+                key_path: Some(path),
+                files: vec![file.to_path_buf()],
+            });
+        }
+    }
+    Ok(())
+}
+
+/// The schema files of the directories of `files`, with their fingerprints,
+/// when [`MergeOptions::directory_schemas`] is set: what the checks of
+/// [`check_directory_schemas`] depend on besides the files themselves.
+pub(crate) fn schema_files<'a>(
+    source: &dyn ConfigSource,
+    files: impl IntoIterator<Item = &'a Path>,
+    options: &MergeOptions,
+) -> Result<Vec<(PathBuf, Fingerprint)>> {
+    let mut schemas: Vec<(PathBuf, Fingerprint)> = Vec::new();
+    if !options.directory_schemas {
+        return Ok(schemas);
+    }
+    for dir in files.into_iter().filter_map(Path::parent) {
+        let path = dir.join(SCHEMA_FILE);
+        if path.is_file() && !schemas.iter().any(|(schema, _)| *schema == path) {
+            let fingerprint = source.fingerprint(&path)?;
+            schemas.push((path, fingerprint));
+        }
+    }
+    Ok(schemas)
+}
+
+/// Whether `path` names a schema file rather than a config.
+pub(crate) fn is_schema_file(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name == SCHEMA_FILE)
+}
+
+/// Checks `value`, found at `path`, against `schema`, adding the dotted path
+/// and problem of every violation to `violations`. Fails on a malformed
+/// schema.
+fn validate(
+    schema: &ConfigValue,
+    value: &ConfigValue,
+    path: &mut Vec<PathSegment>,
+    violations: &mut Vec<(String, String)>,
+) -> Result<()> {
+    let schema = match schema {
+        // `true` accepts everything and `false` nothing.
+        ConfigValue::Bool(true) => return Ok(()),
+        ConfigValue::Bool(false) => {
+            violations.push((format_key_path(path), "no value is allowed here".to_string()));
+            return Ok(());
+        }
+        ConfigValue::Mapping(schema) => schema,
+        _ => bail!("a schema at '{}' is neither a mapping nor a boolean", format_key_path(path)),
+    };
+    let keyword = |name: &str| schema.get(name);
+    let mut violation = |problem: String| violations.push((format_key_path(path), problem));
+
+    if let Some(expected) = keyword("type") {
+        let names: Vec<&str> = match expected {
+            ConfigValue::String(name) => vec![name],
+            ConfigValue::Sequence(names) => names.iter().filter_map(ConfigValue::as_str).collect(),
+            _ => bail!("'type' must be a name or a list of names"),
+        };
+        if !names.iter().any(|name| has_type(value, name)) {
+            violation(format!("expected {}, found {}", names.join(" or "), type_name(value)));
+            return Ok(());
+        }
+    }
+    if let Some(allowed) = keyword("enum") {
+        let Some(allowed) = allowed.as_sequence() else {
+            bail!("'enum' must be a list");
+        };
+        if !allowed.contains(value) {
+            violation(format!("{} is not one of {}", inline(value), inline(&ConfigValue::Sequence(allowed.clone()))));
+        }
+    }
+    if let Some(expected) = keyword("const")
+        && expected != value
+    {
+        violation(format!("expected {}, found {}", inline(expected), inline(value)));
+    }
+
+    if let Some(number) = value.as_f64() {
+        let bound = |name: &str| -> Result<Option<f64>> {
+            match keyword(name) {
+                None => Ok(None),
+                Some(bound) => Ok(Some(bound.as_f64().with_context(|| format!("'{name}' must be a number"))?)),
+            }
+        };
+        if let Some(minimum) = bound("minimum")?
+            && number < minimum
+        {
+            violation(format!("{} is less than the minimum of {minimum}", inline(value)));
+        }
+        if let Some(maximum) = bound("maximum")?
+            && number > maximum
+        {
+            violation(format!("{} is greater than the maximum of {maximum}", inline(value)));
+        }
+        if let Some(minimum) = bound("exclusiveMinimum")?
+            && number <= minimum
+        {
+            violation(format!("{} is not greater than {minimum}", inline(value)));
+        }
+        if let Some(maximum) = bound("exclusiveMaximum")?
+            && number >= maximum
+        {
+            violation(format!("{} is not less than {maximum}", inline(value)));
+        }
+    }
+
+    let limit = |name: &str| -> Result<Option<usize>> {
+        match keyword(name) {
+            None => Ok(None),
+            Some(limit) => match limit.as_u64() {
+                Some(limit) => Ok(Some(limit as usize)),
+                None => bail!("'{name}' must be a non-negative integer"),
+            },
+        }
+    };
+    if let Some(text) = value.as_str() {
+        let length = text.chars().count();
+        if let Some(min) = limit("minLength")?
+            && length < min
+        {
+            violation(format!("expected at least {min} characters, found {length}"));
+        }
+        if let Some(max) = limit("maxLength")?
+            && length > max
+        {
+            violation(format!("expected at most {max} characters, found {length}"));
+        }
+    }
+
+    match value {
+        ConfigValue::Sequence(items) => {
+            if let Some(min) = limit("minItems")?
+                && items.len() < min
+            {
+                violation(format!("expected at least {min} items, found {}", items.len()));
+            }
+            if let Some(max) = limit("maxItems")?
+                && items.len() > max
+            {
+                violation(format!("expected at most {max} items, found {}", items.len()));
+            }
+            if let Some(item_schema) = keyword("items") {
+                for (index, item) in items.iter().enumerate() {
+                    path.push(PathSegment::Index(index));
+                    validate(item_schema, item, path, violations)?;
+                    path.pop();
+                }
+            }
+        }
+        ConfigValue::Mapping(map) => {
+            let properties = match keyword("properties") {
+                None => None,
+                Some(ConfigValue::Mapping(properties)) => Some(properties),
+                Some(_) => bail!("'properties' must be a mapping"),
+            };
+            for (key, item) in map {
+                let name = key_to_string(key);
+                let property = properties.and_then(|properties| properties.get(name.as_str()));
+                path.push(PathSegment::Key(name));
+                match (property, keyword("additionalProperties")) {
+                    (Some(property), _) => validate(property, item, path, violations)?,
+                    (None, Some(additional)) => validate(additional, item, path, violations)?,
+                    (None, None) => {}
+                }
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Whether `value` is of the JSON Schema type `name`.
+fn has_type(value: &ConfigValue, name: &str) -> bool {
+    match name {
+        "integer" => value.as_f64().is_some_and(|number| number.fract() == 0.0),
+        "number" => value.is_number(),
+        name => type_name(value) == name,
+    }
+}
+
+/// The JSON Schema type of `value`; `integer` for whole numbers.
+fn type_name(value: &ConfigValue) -> &'static str {
+    match value {
+        ConfigValue::Null => "null",
+        ConfigValue::Bool(_) => "boolean",
+        ConfigValue::Number(number) if number.as_f64().is_some_and(|number| number.fract() == 0.0) => "integer",
+        ConfigValue::Number(_) => "number",
+        ConfigValue::String(_) => "string",
+        ConfigValue::Sequence(_) => "array",
+        ConfigValue::Mapping(_) => "object",
+        ConfigValue::Tagged(tagged) => type_name(&tagged.value),
+    }
+}
+
+fn inline(value: &ConfigValue) -> String {
+    serde_json::to_string(value).unwrap_or_else(|_| "?".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixture_tree;
+
+    const SCHEMA: &str = "type: object\nproperties:\n  port: {type: integer, minimum: 1, maximum: 65535}\n  \
+                          replicas: {type: integer}\n  tags: {type: array, items: {type: string}}\n  \
+                          zones: {type: array, items: {type: string}}\n\
+                          additionalProperties: false\n";
+
+    fn options() -> MergeOptions {
+        MergeOptions {
+            directory_schemas: true,
+            ..MergeOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_each_file_is_checked_against_its_directory_schema() {
+        let dir = fixture_tree(&[
+            ("config.yaml", "name: api\nport: 80\n"),
+            ("prod/_schema.yaml", SCHEMA),
+            ("prod/a.yaml", "port: 8080\ntags: [blue]\n"),
+            ("prod/b.yaml", "replicas: two\nzones: [eu, 3]\nname: web\n"),
+        ]);
+        let prod = dir.path().join("prod");
+        let (config, report) = crate::merge_hierarchy(dir.path(), &prod, &options()).unwrap();
+
+        // The base level has no schema, and the schema itself is not merged.
+        assert_eq!(config["name"], "web");
+        assert!(config.get("type").is_none());
+        assert_eq!(report.files.len(), 3);
+
+        let bad = prod.canonicalize().unwrap().join("b.yaml");
+        let found: Vec<_> = report
+            .entries
+            .iter()
+            .map(|entry| (entry.kind, entry.key_path.as_deref().unwrap(), entry.files.clone()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (ReportKind::SchemaViolation, "replicas", vec![bad.clone()]),
+                (ReportKind::SchemaViolation, "zones[1]", vec![bad.clone()]),
+                (ReportKind::SchemaViolation, "name", vec![bad.clone()]),
+            ]
+        );
+        assert_eq!(
+            report.entries[0].message,
+            format!("Schema violation in {} at 'replicas': expected integer, found string", bad.display())
+        );
+
+        let strict = MergeOptions {
+            strict: true,
+            ..options()
+        };
+        assert!(matches!(
+            crate::merge_hierarchy(dir.path(), &prod, &strict),
+            Err(crate::ConfigError::Strict { entries }) if entries.len() == 3
+        ));
+
+        // The merger checks alike, and again once the schema changes.
+        let mut merger = crate::HierarchyMerger::new(dir.path(), options());
+        assert_eq!(merger.merge_with_report(&prod).unwrap(), (std::sync::Arc::new(config), report.clone()));
+        let many = crate::merge_many(dir.path(), std::slice::from_ref(&prod), &options()).unwrap();
+        assert_eq!(many[&prod].1, report);
+        std::fs::write(prod.join("_schema.yaml"), "type: object\n").unwrap();
+        let (_, report) = merger.merge_with_report(&prod).unwrap();
+        assert_eq!(report, crate::merge_hierarchy(dir.path(), &prod, &options()).unwrap().1);
+        assert!(report.entries.is_empty(), "{:?}", report.entries);
+    }
+
+    #[test]
+    fn test_keywords() {
+        let check = |schema: &str, value: &str| {
+            let schema: ConfigValue = serde_yaml::from_str(schema).unwrap();
+            let mut violations = Vec::new();
+            validate(&schema, &serde_yaml::from_str(value).unwrap(), &mut Vec::new(), &mut violations).unwrap();
+            violations.into_iter().map(|(_, problem)| problem).collect::<Vec<_>>()
+        };
+        assert!(check("{type: [string, 'null']}", "~").is_empty());
+        assert_eq!(check("{type: integer}", "1.5"), ["expected integer, found number"]);
+        assert!(check("{type: number, exclusiveMinimum: 0}", "2").is_empty());
+        assert_eq!(check("{enum: [a, b]}", "c"), [r#""c" is not one of ["a","b"]"#]);
+        assert_eq!(check("{maxLength: 2}", "abc"), ["expected at most 2 characters, found 3"]);
+        assert_eq!(check("{minItems: 1}", "[]"), ["expected at least 1 items, found 0"]);
+        assert_eq!(check("{const: 1}", "2"), ["expected 1, found 2"]);
+        assert_eq!(check("{properties: {a: false}}", "{a: 1, b: 2}"), ["no value is allowed here"]);
+        // Keys a file leaves out are not required.
+        assert!(check("{properties: {a: {type: string}}, required: [a]}", "{}").is_empty());
+
+        let schema: ConfigValue = serde_yaml::from_str("{type: 3}").unwrap();
+        assert!(validate(&schema, &ConfigValue::Null, &mut Vec::new(), &mut Vec::new()).is_err());
+    }
+}
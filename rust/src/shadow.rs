@@ -0,0 +1,206 @@
+//! Files that leave no trace in a merge, see
+//! [`MergeOptions::report_shadowed_files`].
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::keypath::key_to_string;
+use crate::options::{MergeOptions, SequenceStrategy};
+use crate::priority::PRIORITY_KEY;
+use crate::report::{ReportEntry, ReportKind};
+use crate::ConfigValue;
+
+/// The kinds of value later files define at one key path.
+#[derive(Debug, Clone, Copy, Default)]
+struct Defined {
+    mapping: bool,
+    sequence: bool,
+    other: bool,
+}
+
+/// Records an entry for every file of `files`, given in merge order, that
+/// defines no key, or whose every leaf is overridden by the files after it.
+/// A leaf is overridden when a later file defines its key path, or replaces
+/// a mapping above it with another kind of value; sequences merged by a
+/// strategy other than [`SequenceStrategy::Replace`] are not overridden by
+/// later sequences. The files are walked once, last to first.
+pub(crate) fn collect_shadowed_files(
+    files: &[(&Path, &ConfigValue)],
+    options: &MergeOptions,
+    entries: &mut Vec<ReportEntry>,
+) {
+    if !options.report_shadowed_files {
+        return;
+    }
+    let mut later: HashMap<Vec<String>, Defined> = HashMap::new();
+    let mut found = Vec::new();
+
+    for (file, config) in files.iter().rev() {
+        let mut counts = (0, 0);
+        if let ConfigValue::Mapping(map) = config {
+            let mut path = Vec::new();
+            for (key, value) in map {
+                let key = key_to_string(key);
+                if key == PRIORITY_KEY {
+                    continue;
+                }
+                path.push(key);
+                count_leaves(value, &mut path, &later, options, &mut counts);
+                path.pop();
+            }
+        }
+        let (leaves, shadowed) = counts;
+        if leaves == 0 {
+            found.push(ReportEntry {
+                kind: ReportKind::ShadowedFile,
+                key_path: None,
+                files: vec![file.to_path_buf()],
+                message: format!("{} defines no keys", file.display()),
+            });
+        } else if shadowed == leaves {
+            found.push(ReportEntry {
+                kind: ReportKind::ShadowedFile,
+                key_path: None,
+                files: vec![file.to_path_buf()],
+                message: format!("All {leaves} key(s) of {} are overridden by later files", file.display()),
+            });
+        }
+        if let ConfigValue::Mapping(map) = config {
+            for (key, value) in map {
+                record(value, &mut vec![key_to_string(key)], &mut later);
+            }
+        }
+    }
+
+    found.reverse();
+    entries.extend(found);
+}
+
+/// Adds the leaves below `value`, at `path`, to the first count and those of
+/// them overridden by `later` files to the second.
+fn count_leaves(
+    value: &ConfigValue,
+    path: &mut Vec<String>,
+    later: &HashMap<Vec<String>, Defined>,
+    options: &MergeOptions,
+    counts: &mut (usize, usize),
+) {
+    let defined = later.get(path.as_slice()).copied().unwrap_or_default();
+    match value {
+        ConfigValue::Mapping(map) if !map.is_empty() => {
+            if defined.sequence || defined.other {
+                // Replaced as a whole, with every leaf below
+                let mut all = (0, 0);
+                count_leaves(value, path, &HashMap::new(), options, &mut all);
+                *counts = (counts.0 + all.0, counts.1 + all.0);
+                return;
+            }
+            for (key, item) in map {
+                path.push(key_to_string(key));
+                count_leaves(item, path, later, options, counts);
+                path.pop();
+            }
+        }
+        ConfigValue::Sequence(_) => {
+            counts.0 += 1;
+            let replaced = defined.sequence && options.sequence_strategy == SequenceStrategy::Replace;
+            if defined.mapping || defined.other || replaced {
+                counts.1 += 1;
+            }
+        }
+        _ => {
+            counts.0 += 1;
+            if defined.mapping || defined.sequence || defined.other {
+                counts.1 += 1;
+            }
+        }
+    }
+}
+
+/// Records the kind of `value` at `path`, and of everything below it.
+fn record(value: &ConfigValue, path: &mut Vec<String>, later: &mut HashMap<Vec<String>, Defined>) {
+    let defined = later.entry(path.clone()).or_default();
+    match value {
+        ConfigValue::Mapping(map) => {
+            defined.mapping = true;
+            for (key, item) in map {
+                path.push(key_to_string(key));
+                record(item, path, later);
+                path.pop();
+            }
+        }
+        ConfigValue::Sequence(_) => defined.sequence = true,
+        _ => defined.other = true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixture_tree;
+
+    #[test]
+    fn test_fully_overridden_file_is_reported_once() {
+        let dir = fixture_tree(&[
+            ("config.yaml", "name: base\nserver: {port: 80, host: a}\n"),
+            ("prod/config.yaml", "server: {port: 8080}\n"),
+            ("prod/eu/config.yaml", "server: {port: 9090, host: b}\n"),
+        ]);
+        let options = MergeOptions {
+            report_shadowed_files: true,
+            ..MergeOptions::default()
+        };
+        let eu = dir.path().join("prod/eu");
+        let (_, report) = crate::merge_hierarchy(dir.path(), &eu, &options).unwrap();
+        let file = dir.path().canonicalize().unwrap().join("prod/config.yaml");
+        assert_eq!(report.entries.len(), 1, "{:?}", report.entries);
+        assert_eq!(report.entries[0].kind, ReportKind::ShadowedFile);
+        assert_eq!(report.entries[0].files, std::slice::from_ref(&file));
+        assert_eq!(
+            report.entries[0].message,
+            format!("All 1 key(s) of {} are overridden by later files", file.display())
+        );
+
+        let (_, traced, _) = crate::merge_hierarchy_with_provenance(dir.path(), &eu, &options).unwrap();
+        assert_eq!(traced.entries, report.entries);
+        let mut merger = crate::HierarchyMerger::new(dir.path(), options.clone());
+        assert_eq!(merger.merge_with_report(&eu).unwrap().1.entries, report.entries);
+        assert!(crate::merge_hierarchy(dir.path(), &eu, &MergeOptions::default()).unwrap().1.entries.is_empty());
+    }
+
+    #[test]
+    fn test_what_counts_as_overridden() {
+        let shadowed = |texts: &[&str], options: &MergeOptions| {
+            let configs: Vec<ConfigValue> = texts.iter().map(|text| serde_yaml::from_str(text).unwrap()).collect();
+            let names: Vec<String> = (0..texts.len()).map(|index| format!("{index}.yaml")).collect();
+            let files: Vec<_> = names.iter().map(Path::new).zip(&configs).collect();
+            let mut entries = Vec::new();
+            collect_shadowed_files(&files, options, &mut entries);
+            entries.iter().map(|entry| entry.message.clone()).collect::<Vec<_>>()
+        };
+        let options = MergeOptions {
+            report_shadowed_files: true,
+            ..MergeOptions::default()
+        };
+
+        // A scalar replacing a mapping overrides all of it.
+        assert_eq!(
+            shadowed(&["a: {b: 1, c: {d: 2}}\n", "a: off\n"], &options),
+            ["All 2 key(s) of 0.yaml are overridden by later files"]
+        );
+        // Keys the later file does not set survive.
+        assert!(shadowed(&["a: {b: 1, c: 2}\n", "a: {b: 3}\n"], &options).is_empty());
+        // Empty files, and files holding only their priority.
+        assert_eq!(
+            shadowed(&["", "__priority__: 1\n", "a: 1\n"], &options),
+            ["0.yaml defines no keys", "1.yaml defines no keys"]
+        );
+        // Appended sequences contribute their items.
+        assert_eq!(shadowed(&["a: [1]\n", "a: [2]\n"], &options), ["All 1 key(s) of 0.yaml are overridden by later files"]);
+        let append = MergeOptions {
+            sequence_strategy: SequenceStrategy::Append,
+            ..options.clone()
+        };
+        assert!(shadowed(&["a: [1]\n", "a: [2]\n"], &append).is_empty());
+    }
+}
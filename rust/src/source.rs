@@ -0,0 +1,175 @@
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+
+use crate::error::ConfigError;
+use crate::trace;
+use crate::ConfigValue;
+
+/// Cheap identity of a file's contents, used to decide whether a cached parse
+/// is still valid without reading the file again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fingerprint {
+    pub modified: Option<SystemTime>,
+    pub len: u64,
+}
+
+/// Where config file contents come from.
+///
+/// Discovery still walks the real filesystem; a source only decides how the
+/// discovered files are read and fingerprinted, which lets callers wrap or
+/// replace file access (e.g. to count reads in tests).
+pub trait ConfigSource: Send + Sync {
+    fn read_to_string(&self, path: &Path) -> Result<String>;
+
+    fn fingerprint(&self, path: &Path) -> Result<Fingerprint>;
+
+    /// The raw contents of `path`. Defaults to [`Self::read_to_string`];
+    /// sources of files that may not be UTF-8 override it.
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        Ok(self.read_to_string(path)?.into_bytes())
+    }
+}
+
+/// Reads config files straight from the local filesystem.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FsSource;
+
+impl ConfigSource for FsSource {
+    fn read_to_string(&self, path: &Path) -> Result<String> {
+        Ok(fs::read_to_string(path).map_err(ConfigError::io("Failed to read file", path))?)
+    }
+
+    fn fingerprint(&self, path: &Path) -> Result<Fingerprint> {
+        let metadata = fs::metadata(path).map_err(ConfigError::io("Failed to read metadata", path))?;
+        Ok(Fingerprint {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+        })
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        Ok(fs::read(path).map_err(ConfigError::io("Failed to read file", path))?)
+    }
+}
+
+/// A config file read once and parsed, with what later stages need to know
+/// about its contents.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedFile {
+    pub path: PathBuf,
+    /// Hex SHA-256 of the bytes of the file, when asked for.
+    pub hash: Option<String>,
+    pub value: ConfigValue,
+    /// Size of the file in bytes.
+    pub size: u64,
+}
+
+impl ParsedFile {
+    /// Reads `path` through `source` once, hashing the bytes when `hash` is
+    /// set and parsing them as UTF-8, or as UTF-16 when they start with its
+    /// byte order mark. A UTF-8 byte order mark is skipped.
+    pub fn read(source: &dyn ConfigSource, path: &Path, hash: bool) -> Result<ParsedFile, ConfigError> {
+        let timer = trace::Timer::start();
+        let bytes = source.read(path)?;
+        let value = parse_yaml_bytes(path, &bytes)?;
+        trace::parsed_file(path, bytes.len(), timer);
+        Ok(ParsedFile {
+            path: path.to_path_buf(),
+            hash: hash.then(|| sha256_hex(&bytes)),
+            value,
+            size: bytes.len() as u64,
+        })
+    }
+}
+
+/// The config in `bytes`, the contents of `path`, decoded as in
+/// [`ParsedFile::read`].
+pub(crate) fn parse_yaml_bytes(path: &Path, bytes: &[u8]) -> Result<ConfigValue> {
+    parse_yaml_content(path, &decode(path, bytes)?)
+}
+
+/// The text of a file from its bytes, see [`ParsedFile::read`].
+fn decode<'a>(path: &Path, bytes: &'a [u8]) -> Result<Cow<'a, str>, ConfigError> {
+    let utf16 = |bytes: &[u8], unit: fn([u8; 2]) -> u16| {
+        let pairs = bytes.chunks_exact(2);
+        if !pairs.remainder().is_empty() {
+            return None;
+        }
+        let units: Vec<u16> = pairs.map(|pair| unit([pair[0], pair[1]])).collect();
+        String::from_utf16(&units).ok()
+    };
+    let text = match bytes {
+        [0xEF, 0xBB, 0xBF, rest @ ..] => std::str::from_utf8(rest).ok().map(Cow::Borrowed),
+        [0xFF, 0xFE, rest @ ..] => utf16(rest, u16::from_le_bytes).map(Cow::Owned),
+        [0xFE, 0xFF, rest @ ..] => utf16(rest, u16::from_be_bytes).map(Cow::Owned),
+        bytes => std::str::from_utf8(bytes).ok().map(Cow::Borrowed),
+    };
+    text.ok_or_else(|| {
+        let error = match bytes {
+            [0xFF, 0xFE, ..] | [0xFE, 0xFF, ..] => "stream did not contain valid UTF-16",
+            _ => "stream did not contain valid UTF-8",
+        };
+        ConfigError::io("Failed to read file", path)(io::Error::new(io::ErrorKind::InvalidData, error))
+    })
+}
+
+/// Reads and parses a single YAML file through `source`.
+pub fn parse_yaml_file(source: &dyn ConfigSource, path: &Path) -> Result<ConfigValue, ConfigError> {
+    Ok(ParsedFile::read(source, path, false)?.value)
+}
+
+/// Like [`parse_yaml_file`], also returning the hex SHA-256 of the contents.
+pub(crate) fn load_yaml_file(source: &dyn ConfigSource, path: &Path) -> Result<(ConfigValue, String)> {
+    let file = ParsedFile::read(source, path, true)?;
+    Ok((file.value, file.hash.unwrap_or_default()))
+}
+
+pub(crate) fn parse_yaml_content(path: &Path, content: &str) -> Result<ConfigValue> {
+    serde_yaml::from_str(content).map_err(|source| {
+        let location = source.location();
+        ConfigError::Parse {
+            path: path.to_path_buf(),
+            line: location.as_ref().map(|l| l.line()),
+            column: location.as_ref().map(|l| l.column()),
+            source,
+        }
+        .into()
+    })
+}
+
+pub(crate) fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_byte_order_marks() {
+        let path = Path::new("config.yaml");
+        let utf16 = |text: &str, unit: fn(u16) -> [u8; 2], bom: [u8; 2]| {
+            let mut bytes = bom.to_vec();
+            bytes.extend(text.encode_utf16().flat_map(unit));
+            bytes
+        };
+        assert_eq!(decode(path, b"port: 80").unwrap(), "port: 80");
+        assert_eq!(decode(path, b"\xEF\xBB\xBFport: 80").unwrap(), "port: 80");
+        assert_eq!(decode(path, &utf16("name: \u{e9}t\u{e9}", u16::to_le_bytes, [0xFF, 0xFE])).unwrap(), "name: \u{e9}t\u{e9}");
+        assert_eq!(decode(path, &utf16("port: 80", u16::to_be_bytes, [0xFE, 0xFF])).unwrap(), "port: 80");
+
+        let err = decode(path, b"port: \xFF").unwrap_err();
+        assert_eq!(err.to_string(), "Failed to read file: config.yaml");
+        let source = std::error::Error::source(&err).unwrap();
+        assert_eq!(source.to_string(), "stream did not contain valid UTF-8");
+        assert!(decode(path, b"\xFF\xFEp").is_err());
+    }
+}
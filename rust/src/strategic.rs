@@ -0,0 +1,347 @@
+//! Kubernetes-style strategic merge patches, used by
+//! [`MergeMode::StrategicMergePatch`](crate::MergeMode::StrategicMergePatch).
+//!
+//! Without a schema, every sequence of mappings carrying the merge key is
+//! merged by that key; other sequences combine by the `sequence_strategy`.
+//! The overriding config may hold `$patch` directives:
+//!
+//! - `$patch: replace` in a mapping replaces the mapping below it instead of
+//!   merging into it; as an item of a sequence, the other items of that
+//!   sequence replace the sequence below it.
+//! - `$patch: delete` in a mapping removes that mapping; as a sequence item
+//!   with a merge key, it removes the item with the same key.
+//! - `$patch: merge` is the default behaviour.
+//!
+//! A `null` removes the key, as in Kubernetes. Directives never reach the
+//! merged config.
+
+use std::path::Path;
+
+use crate::keypath::{format_key_path, key_to_string, PathSegment};
+use crate::options::MergeOptions;
+use crate::redact::REDACTED;
+use crate::report::{ReportEntry, ReportKind};
+use crate::ConfigValue;
+
+const PATCH: &str = "$patch";
+
+/// A `$patch` directive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Directive {
+    Merge,
+    Replace,
+    Delete,
+}
+
+struct Patcher<'a> {
+    merge_key: ConfigValue,
+    options: &'a MergeOptions,
+    /// Key paths and values of the unknown directives met so far.
+    unknown: Vec<(String, String)>,
+}
+
+/// Applies `patch` to `base`, returning the patched config and the unknown
+/// directives found in `patch`, as report entries naming `source`.
+pub(crate) fn merge_patch(
+    base: &ConfigValue,
+    patch: &ConfigValue,
+    merge_key: &str,
+    options: &MergeOptions,
+    source: Option<&Path>,
+) -> (ConfigValue, Vec<ReportEntry>) {
+    let mut patcher = Patcher {
+        merge_key: ConfigValue::String(merge_key.to_string()),
+        options,
+        unknown: Vec::new(),
+    };
+    let merged = patcher
+        .merge(Some(base), patch, &mut Vec::new())
+        .unwrap_or_else(|| ConfigValue::Mapping(serde_yaml::Mapping::new()));
+
+    let entries = patcher
+        .unknown
+        .into_iter()
+        .map(|(key_path, value)| ReportEntry {
+            kind: ReportKind::PatchDirective,
+            message: match source {
+                Some(source) => format!(
+                    "Unknown $patch directive '{value}' at '{key_path}' in {}",
+                    source.display()
+                ),
+                None => format!("Unknown $patch directive '{value}' at '{key_path}'"),
+            },
+            key_path: Some(key_path),
+            files: source.into_iter().map(Path::to_path_buf).collect(),
+        })
+        .collect();
+    (merged, entries)
+}
+
+impl Patcher<'_> {
+    fn directive(&mut self, map: &serde_yaml::Mapping, path: &[PathSegment]) -> Directive {
+        let Some(value) = map.get(PATCH) else {
+            return Directive::Merge;
+        };
+        match value.as_str() {
+            Some("merge") => Directive::Merge,
+            Some("replace") => Directive::Replace,
+            Some("delete") => Directive::Delete,
+            _ => {
+                let value = match value {
+                    _ if self.options.redaction.matches(path) => REDACTED.to_string(),
+                    ConfigValue::String(text) => text.clone(),
+                    other => serde_yaml::to_string(other).unwrap_or_default().trim_end().to_string(),
+                };
+                self.unknown.push((format_key_path(path), value));
+                Directive::Merge
+            }
+        }
+    }
+
+    /// `patch` applied to `base`, `None` when the patch deletes the value.
+    fn merge(
+        &mut self,
+        base: Option<&ConfigValue>,
+        patch: &ConfigValue,
+        path: &mut Vec<PathSegment>,
+    ) -> Option<ConfigValue> {
+        match patch {
+            ConfigValue::Null => None,
+            ConfigValue::Mapping(patch_map) => {
+                let base_map = match (self.directive(patch_map, path), base) {
+                    (Directive::Delete, _) => return None,
+                    (Directive::Merge, Some(ConfigValue::Mapping(base_map))) => base_map.clone(),
+                    _ => serde_yaml::Mapping::new(),
+                };
+                Some(ConfigValue::Mapping(self.merge_mapping(base_map, patch_map, path)))
+            }
+            ConfigValue::Sequence(patch_items) => {
+                let base_items = match base {
+                    Some(ConfigValue::Sequence(base_items)) => base_items.as_slice(),
+                    _ => &[],
+                };
+                Some(ConfigValue::Sequence(self.merge_sequence(base_items, patch_items, path)))
+            }
+            _ => Some(patch.clone()),
+        }
+    }
+
+    fn merge_mapping(
+        &mut self,
+        mut result: serde_yaml::Mapping,
+        patch: &serde_yaml::Mapping,
+        path: &mut Vec<PathSegment>,
+    ) -> serde_yaml::Mapping {
+        for (key, value) in patch {
+            if key.as_str() == Some(PATCH) {
+                continue;
+            }
+            path.push(PathSegment::Key(key_to_string(key)));
+            match self.merge(result.get(key), value, path) {
+                Some(merged) => {
+                    result.insert(key.clone(), merged);
+                }
+                None => {
+                    result.remove(key);
+                }
+            }
+            path.pop();
+        }
+        result
+    }
+
+    fn is_keyed(&self, item: &ConfigValue) -> bool {
+        item.as_mapping().is_some_and(|map| map.contains_key(&self.merge_key))
+    }
+
+    fn merge_sequence(
+        &mut self,
+        base: &[ConfigValue],
+        patch: &[ConfigValue],
+        path: &mut Vec<PathSegment>,
+    ) -> Vec<ConfigValue> {
+        let replace = patch.iter().position(|item| {
+            item.as_mapping()
+                .is_some_and(|map| map.len() == 1 && map.get(PATCH).and_then(ConfigValue::as_str) == Some("replace"))
+        });
+        if let Some(index) = replace {
+            let rest: Vec<_> = patch.iter().enumerate().filter(|(i, _)| *i != index).map(|(_, item)| item).collect();
+            return self.merge_items(&[], rest, path);
+        }
+
+        let keyed = !patch.is_empty() && patch.iter().chain(base).all(|item| self.is_keyed(item));
+        if keyed {
+            return self.merge_items(base, patch.iter().collect(), path);
+        }
+        let patch: Vec<_> = patch
+            .iter()
+            .enumerate()
+            .filter_map(|(index, item)| {
+                path.push(PathSegment::Index(index));
+                let item = self.merge(None, item, path);
+                path.pop();
+                item
+            })
+            .collect();
+        self.options.sequence_strategy.combine(base, &patch)
+    }
+
+    /// Merges keyed `patch` items into `base`, matching items by merge key.
+    fn merge_items(
+        &mut self,
+        base: &[ConfigValue],
+        patch: Vec<&ConfigValue>,
+        path: &mut Vec<PathSegment>,
+    ) -> Vec<ConfigValue> {
+        let mut result = base.to_vec();
+        for item in patch {
+            let key = item.as_mapping().and_then(|map| map.get(&self.merge_key));
+            let position = key.and_then(|key| {
+                result
+                    .iter()
+                    .position(|existing| existing.as_mapping().and_then(|map| map.get(&self.merge_key)) == Some(key))
+            });
+            path.push(PathSegment::Index(position.unwrap_or(result.len())));
+            let merged = self.merge(position.map(|index| &result[index]), item, path);
+            path.pop();
+            match (position, merged) {
+                (Some(index), Some(merged)) => result[index] = merged,
+                (Some(index), None) => {
+                    result.remove(index);
+                }
+                (None, Some(merged)) => result.push(merged),
+                (None, None) => {}
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::options::MergeMode;
+
+    fn yaml(text: &str) -> ConfigValue {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    fn patch(base: &str, patch: &str) -> (ConfigValue, Vec<ReportEntry>) {
+        merge_patch(&yaml(base), &yaml(patch), "name", &MergeOptions::default(), None)
+    }
+
+    const DEPLOYMENT: &str = "
+spec:
+  replicas: 2
+  template:
+    spec:
+      containers:
+        - name: patch-demo-ctr
+          image: nginx
+          ports: [{containerPort: 80}]
+      tolerations:
+        - effect: NoSchedule
+          key: dedicated
+          value: test-team
+";
+
+    #[test]
+    fn test_containers_merge_by_name() {
+        // From "Update API Objects in Place Using kubectl patch".
+        let (merged, entries) = patch(
+            DEPLOYMENT,
+            "
+spec:
+  template:
+    spec:
+      containers:
+        - name: patch-demo-ctr-2
+          image: redis
+        - name: patch-demo-ctr
+          image: nginx:1.25
+",
+        );
+        assert!(entries.is_empty());
+        assert_eq!(
+            merged["spec"]["template"]["spec"]["containers"],
+            yaml("
+- {name: patch-demo-ctr, image: 'nginx:1.25', ports: [{containerPort: 80}]}
+- {name: patch-demo-ctr-2, image: redis}
+")
+        );
+        // Items without the merge key are replaced, as without a schema.
+        assert_eq!(
+            merged["spec"]["template"]["spec"]["tolerations"],
+            yaml("[{effect: NoSchedule, key: dedicated, value: test-team}]")
+        );
+        assert_eq!(merged["spec"]["replicas"], 2);
+    }
+
+    #[test]
+    fn test_replace_and_delete_directives() {
+        let (merged, _) = patch(
+            DEPLOYMENT,
+            "
+spec:
+  template:
+    spec:
+      containers:
+        - $patch: replace
+        - name: patch-demo-ctr-3
+          image: gcr.io/google-samples/node-hello:1.0
+      tolerations:
+        - effect: NoSchedule
+          key: disktype
+          value: ssd
+",
+        );
+        let spec = &merged["spec"]["template"]["spec"];
+        assert_eq!(spec["containers"], yaml("[{name: patch-demo-ctr-3, image: 'gcr.io/google-samples/node-hello:1.0'}]"));
+        assert_eq!(spec["tolerations"], yaml("[{effect: NoSchedule, key: disktype, value: ssd}]"));
+
+        let (merged, _) = patch(
+            DEPLOYMENT,
+            "
+spec:
+  replicas: null
+  template:
+    $patch: replace
+    spec:
+      containers:
+        - {name: patch-demo-ctr, $patch: delete}
+",
+        );
+        assert_eq!(merged, yaml("spec: {template: {spec: {containers: []}}}"));
+
+        let (merged, _) = patch("a: {b: 1}\nc: 2\n", "a: {$patch: delete}\nd: {e: {$patch: replace, f: 1}}\n");
+        assert_eq!(merged, yaml("c: 2\nd: {e: {f: 1}}\n"));
+    }
+
+    #[test]
+    fn test_unknown_directives_are_reported() {
+        let (merged, entries) = patch("a: {b: 1}\n", "a: {$patch: remove, c: 2}\n");
+        assert_eq!(merged, yaml("a: {b: 1, c: 2}\n"));
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].key_path.as_deref(), Some("a"));
+        assert_eq!(entries[0].message, "Unknown $patch directive 'remove' at 'a'");
+        let (_, entries) = patch("{}", "db_secret: {$patch: hunter2}\n");
+        assert_eq!(entries[0].message, "Unknown $patch directive '<redacted>' at 'db_secret'");
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("svc")).unwrap();
+        fs::write(dir.path().join("config.yaml"), "ports: [{name: http, port: 80}]\n").unwrap();
+        fs::write(dir.path().join("svc/config.yaml"), "ports: [{name: http, port: 8080, $patch: drop}]\n").unwrap();
+        let options = MergeOptions {
+            mode: MergeMode::StrategicMergePatch {
+                default_key: "name".to_string(),
+            },
+            ..MergeOptions::default()
+        };
+        let (config, report) = crate::merge_hierarchy(dir.path(), &dir.path().join("svc"), &options).unwrap();
+        assert_eq!(config, yaml("ports: [{name: http, port: 8080}]\n"));
+        assert_eq!(report.entries[0].kind, ReportKind::PatchDirective);
+        assert_eq!(report.entries[0].key_path.as_deref(), Some("ports[0]"));
+        assert!(report.entries[0].message.ends_with("svc/config.yaml"));
+    }
+}
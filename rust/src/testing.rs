@@ -0,0 +1,500 @@
+//! Helpers for tests of code built on this crate: hierarchies on disk from a
+//! list of files, snapshots of merged configs, reads that go wrong on demand
+//! or are counted, and made-up report entries. Built with the `test-util`
+//! feature.
+//!
+//! ```
+//! # use hierarchical_config_merging::testing::fixture_tree;
+//! # use hierarchical_config_merging::{merge_hierarchy, MergeOptions};
+//! let dir = fixture_tree(&[("config.yaml", "port: 80"), ("prod/config.yaml", "port: 8080")]);
+//! let (config, _) = merge_hierarchy(dir.path(), &dir.path().join("prod"), &MergeOptions::default()).unwrap();
+//! assert_eq!(config["port"], 8080);
+//! ```
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use anyhow::Result;
+use serde_yaml::Mapping;
+use tempfile::TempDir;
+
+use crate::error::ConfigError;
+use crate::keypath::key_to_string;
+use crate::options::MergeOptions;
+use crate::report::{ContributingFile, MergeReport, ReportEntry, ReportKind};
+use crate::source::{ConfigSource, Fingerprint, FsSource};
+use crate::{merge_hierarchy, ConfigValue};
+
+/// Environment variable that makes [`assert_merge_snapshot`] write snapshots
+/// instead of comparing against them, when set to anything but `0`.
+pub const UPDATE_SNAPSHOTS_ENV: &str = "UPDATE_SNAPSHOTS";
+
+/// A temporary directory holding `files`, given as `(relative path, text)`.
+/// Parent directories are created as needed. Panics when a file cannot be
+/// written.
+pub fn fixture_tree(files: &[(&str, &str)]) -> TempDir {
+    let dir = tempfile::tempdir().expect("Failed to create a temporary directory");
+    for (path, text) in files {
+        let path = dir.path().join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).unwrap_or_else(|e| panic!("Failed to create {}: {e}", parent.display()));
+        }
+        fs::write(&path, text).unwrap_or_else(|e| panic!("Failed to write {}: {e}", path.display()));
+    }
+    dir
+}
+
+/// A synthetic hierarchy of `levels` nested directories, keyed by relative
+/// path as [`crate::merge_configs_by_depth`] takes it. Each level has a
+/// `config.yaml` of about `keys_per_level` leaves, sections of ten keys
+/// mixing scalars, sequences, nested mappings and nulls, most of them
+/// overriding the level above, and an `extra.yaml` colliding with it on one
+/// section. The same arguments always give the same configs.
+pub fn synthetic_configs(levels: usize, keys_per_level: usize) -> HashMap<PathBuf, ConfigValue> {
+    let mut configs = HashMap::new();
+    let mut dir = PathBuf::new();
+    for level in 0..levels {
+        let mut config = Mapping::new();
+        for section in 0..keys_per_level.div_ceil(10) {
+            let mut keys = Mapping::new();
+            for key in 0..10 {
+                let (name, value) = match key % 6 {
+                    0 => (format!("k{key}"), ConfigValue::from(level * 1000 + section * 10 + key)),
+                    1 => (format!("k{key}"), format!("v{level}-{section}-{key}").into()),
+                    2 => (format!("k{key}"), ConfigValue::Sequence(vec![level.into(), key.into()])),
+                    3 => {
+                        let mut nested = Mapping::new();
+                        nested.insert("level".into(), level.into());
+                        nested.insert(format!("only{level}").into(), key.into());
+                        (format!("k{key}"), ConfigValue::Mapping(nested))
+                    }
+                    4 if level % 2 == 1 => (format!("k{key}"), ConfigValue::Null),
+                    4 => (format!("k{key}"), (section % 2 == 0).into()),
+                    _ => (format!("k{key}_{level}"), format!("new{level}").into()),
+                };
+                keys.insert(name.into(), value);
+            }
+            config.insert(format!("section{section}").into(), ConfigValue::Mapping(keys));
+        }
+        configs.insert(dir.join("config.yaml"), ConfigValue::Mapping(config));
+
+        let mut extra = Mapping::new();
+        let mut section = Mapping::new();
+        section.insert("extra".into(), level.into());
+        extra.insert("section0".into(), ConfigValue::Mapping(section));
+        extra.insert(format!("extra{level}").into(), level.into());
+        configs.insert(dir.join("extra.yaml"), ConfigValue::Mapping(extra));
+
+        dir.push(format!("level{}", level + 1));
+    }
+    configs
+}
+
+/// `config` as YAML with the keys of every mapping sorted, so that equal
+/// configs serialize alike whatever their key order.
+pub fn canonical_yaml(config: &ConfigValue) -> String {
+    serde_yaml::to_string(&sorted(config)).expect("Failed to serialize config as YAML")
+}
+
+fn sorted(value: &ConfigValue) -> ConfigValue {
+    match value {
+        ConfigValue::Mapping(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by_key(|(key, _)| key_to_string(key));
+            ConfigValue::Mapping(entries.into_iter().map(|(key, item)| (key.clone(), sorted(item))).collect())
+        }
+        ConfigValue::Sequence(items) => ConfigValue::Sequence(items.iter().map(sorted).collect()),
+        ConfigValue::Tagged(tagged) => {
+            let mut tagged = tagged.clone();
+            tagged.value = sorted(&tagged.value);
+            ConfigValue::Tagged(tagged)
+        }
+        value => value.clone(),
+    }
+}
+
+/// Merges `target_path` and compares the merged config, as
+/// [`canonical_yaml`], against the file at `snapshot_path`. Panics with a
+/// line diff when they differ, or when the merge fails.
+///
+/// With [`UPDATE_SNAPSHOTS_ENV`] set, the snapshot is written instead,
+/// parent directories included.
+#[track_caller]
+pub fn assert_merge_snapshot(base_dir: &Path, target_path: &Path, options: &MergeOptions, snapshot_path: &Path) {
+    let (config, _) = merge_hierarchy(base_dir, target_path, options)
+        .unwrap_or_else(|e| panic!("Failed to merge {}: {e}", target_path.display()));
+    let update = std::env::var_os(UPDATE_SNAPSHOTS_ENV).is_some_and(|value| value != "0");
+    if let Err(message) = check_snapshot(&canonical_yaml(&config), snapshot_path, update) {
+        panic!("{message}");
+    }
+}
+
+/// Compares `actual` with the snapshot, or writes it when `update`.
+fn check_snapshot(actual: &str, snapshot_path: &Path, update: bool) -> Result<(), String> {
+    if update {
+        if let Some(parent) = snapshot_path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {e}", parent.display()))?;
+        }
+        return fs::write(snapshot_path, actual).map_err(|e| format!("Failed to write {}: {e}", snapshot_path.display()));
+    }
+    let expected = fs::read_to_string(snapshot_path).map_err(|e| {
+        format!(
+            "Failed to read snapshot {}: {e}\nRun with {UPDATE_SNAPSHOTS_ENV}=1 to create it.",
+            snapshot_path.display()
+        )
+    })?;
+    if expected == actual {
+        return Ok(());
+    }
+    Err(format!(
+        "Merged config differs from snapshot {} (-snapshot +merged):\n{}Run with {UPDATE_SNAPSHOTS_ENV}=1 to update it.",
+        snapshot_path.display(),
+        line_diff(&expected, actual)
+    ))
+}
+
+/// Lines of `old` and `new` prefixed with `-`, `+` or a space, along their
+/// longest common subsequence.
+fn line_diff(old: &str, new: &str) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    // common[i][j]: length of the longest common subsequence of old[i..] and new[j..]
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            diff.push_str(&format!(" {}\n", old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            diff.push_str(&format!("-{}\n", old[i]));
+            i += 1;
+        } else {
+            diff.push_str(&format!("+{}\n", new[j]));
+            j += 1;
+        }
+    }
+    diff
+}
+
+/// How a [`FaultInjector`] makes the read of a file go wrong.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// The read fails with an I/O error of that kind, like that of a file
+    /// that cannot be read.
+    Fail(io::ErrorKind),
+    /// The read returns these contents instead of the file's.
+    Corrupt(String),
+    /// The read takes that much longer.
+    Delay(Duration),
+}
+
+/// A [`ConfigSource`] reading through another one, by default the
+/// filesystem, with faults programmed for chosen files. Hand it to
+/// [`HierarchyMerger::with_source`](crate::HierarchyMerger::with_source) to
+/// test how code built on the crate copes with unreadable, broken or slow
+/// files without crafting such a tree.
+///
+/// The faults of a file apply in the order they were programmed: a delay
+/// programmed before a failure delays it, one programmed after it never
+/// happens. Fingerprints come from the inner source unchanged.
+///
+/// ```
+/// # use std::io::ErrorKind;
+/// # use hierarchical_config_merging::testing::{fixture_tree, FaultInjector};
+/// # use hierarchical_config_merging::{ConfigError, HierarchyMerger, MergeOptions, ReportKind};
+/// let dir = fixture_tree(&[("config.yaml", "port: 80"), ("prod/config.yaml", "port: 8080")]);
+/// let prod = dir.path().join("prod");
+/// let source = FaultInjector::default().fail(prod.join("config.yaml"), ErrorKind::PermissionDenied);
+/// let options = MergeOptions { skip_unreadable: true, ..MergeOptions::default() };
+/// let (config, report) = HierarchyMerger::with_source(dir.path(), options, source).merge_with_report(&prod).unwrap();
+/// assert_eq!(config["port"], 80);
+/// assert_eq!(report.entries[0].kind, ReportKind::Unreadable);
+///
+/// let source = FaultInjector::default().corrupt(prod.join("config.yaml"), "port: [8080");
+/// let err = HierarchyMerger::with_source(dir.path(), MergeOptions::default(), source).merge(&prod).unwrap_err();
+/// assert!(matches!(err, ConfigError::Parse { .. }));
+/// ```
+#[derive(Debug, Clone)]
+pub struct FaultInjector<S = FsSource> {
+    inner: S,
+    /// Faults by file, `None` standing for every file.
+    faults: Vec<(Option<PathBuf>, Fault)>,
+}
+
+/// Reads from the filesystem.
+impl Default for FaultInjector {
+    fn default() -> Self {
+        Self::new(FsSource)
+    }
+}
+
+impl<S: ConfigSource> FaultInjector<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            faults: Vec::new(),
+        }
+    }
+
+    /// Reads of `path` fail with an I/O error of `kind`.
+    pub fn fail(self, path: impl AsRef<Path>, kind: io::ErrorKind) -> Self {
+        self.inject(Some(path.as_ref()), Fault::Fail(kind))
+    }
+
+    /// Reads of `path` return `contents` instead of what the file holds.
+    pub fn corrupt(self, path: impl AsRef<Path>, contents: impl Into<String>) -> Self {
+        self.inject(Some(path.as_ref()), Fault::Corrupt(contents.into()))
+    }
+
+    /// Reads of `path` take `delay` longer.
+    pub fn delay(self, path: impl AsRef<Path>, delay: Duration) -> Self {
+        self.inject(Some(path.as_ref()), Fault::Delay(delay))
+    }
+
+    /// Every read takes `delay` longer.
+    pub fn delay_every_read(self, delay: Duration) -> Self {
+        self.inject(None, Fault::Delay(delay))
+    }
+
+    /// Programs `fault` for `path`, canonical when it exists, as merges read
+    /// files by their canonical paths.
+    pub fn inject(mut self, path: Option<&Path>, fault: Fault) -> Self {
+        let path = path.map(|path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf()));
+        self.faults.push((path, fault));
+        self
+    }
+
+    /// The contents a read of `path` returns instead of the file's, if any,
+    /// once its delays are over; fails for an injected failure.
+    fn apply(&self, path: &Path) -> Result<Option<&str>> {
+        let faults = self.faults.iter().filter(|(faulty, _)| faulty.as_deref().is_none_or(|faulty| faulty == path));
+        for (_, fault) in faults {
+            match fault {
+                Fault::Delay(delay) => thread::sleep(*delay),
+                Fault::Fail(kind) => return Err(ConfigError::io("Failed to read file", path)(io::Error::from(*kind)).into()),
+                Fault::Corrupt(contents) => return Ok(Some(contents)),
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<S: ConfigSource> ConfigSource for FaultInjector<S> {
+    fn read_to_string(&self, path: &Path) -> Result<String> {
+        match self.apply(path)? {
+            Some(contents) => Ok(contents.to_string()),
+            None => self.inner.read_to_string(path),
+        }
+    }
+
+    fn fingerprint(&self, path: &Path) -> Result<Fingerprint> {
+        self.inner.fingerprint(path)
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        match self.apply(path)? {
+            Some(contents) => Ok(contents.as_bytes().to_vec()),
+            None => self.inner.read(path),
+        }
+    }
+}
+
+/// A [`ConfigSource`] reading from the filesystem and counting the files it
+/// reads, for testing that code built on the crate parses each file once.
+/// Clones share the count.
+#[derive(Debug, Clone, Default)]
+pub struct CountingSource {
+    reads: Arc<AtomicUsize>,
+}
+
+impl CountingSource {
+    /// Files read so far.
+    pub fn reads(&self) -> usize {
+        self.reads.load(Ordering::SeqCst)
+    }
+}
+
+impl ConfigSource for CountingSource {
+    fn read_to_string(&self, path: &Path) -> Result<String> {
+        self.reads.fetch_add(1, Ordering::SeqCst);
+        FsSource.read_to_string(path)
+    }
+
+    fn fingerprint(&self, path: &Path) -> Result<Fingerprint> {
+        FsSource.fingerprint(path)
+    }
+}
+
+/// An entry of `kind` worded as merges word it, about the files
+/// `/configs/a.yaml` and `/configs/b.yaml` and the key path `server.port`
+/// where it has them, for testing code that handles reports without merging
+/// anything.
+pub fn fabricated_entry(kind: ReportKind) -> ReportEntry {
+    let (a, b) = (Path::new("/configs/a.yaml"), Path::new("/configs/b.yaml"));
+    let (a_shown, b_shown) = (a.display(), b.display());
+    let (key_path, files, message) = match kind {
+        ReportKind::Collision => (true, vec![a, b], format!("Key collision at depth 0: 'server.port' found in both {a_shown} and {b_shown}")),
+        ReportKind::ExemptCollision => (true, vec![a, b], format!("Key collision at depth 0: 'server.port' found in both {a_shown} and {b_shown}")),
+        ReportKind::EmptyHierarchy => (false, vec![], "No YAML files found in hierarchy from /configs to /configs/prod".to_string()),
+        ReportKind::PatchDirective => (true, vec![b], format!("Unknown $patch directive 'merge-all' at 'server.port' in {b_shown}")),
+        ReportKind::Deprecation => (true, vec![b], format!("Deprecated key 'server.port' in {b_shown}")),
+        ReportKind::UnresolvedReference => (true, vec![], "Unresolved reference '${server.host}' at 'server.port'".to_string()),
+        ReportKind::Coercion => (true, vec![b], format!(r#"Coerced "8080" at 'server.port' in {b_shown} to an integer"#)),
+        ReportKind::TypeConflict => (true, vec![b], r#"Type conflict at 'server.port': "http" is not an integer; using the string"#.to_string()),
+        ReportKind::Duplicate => (false, vec![a, b], format!("Identical configs in {a_shown}, {b_shown}")),
+        ReportKind::Skipped => (false, vec![b], format!("Skipped {b_shown} at depth 2: only 1 level(s) are merged")),
+        ReportKind::Pruned => (true, vec![], "Pruned 'server.port'".to_string()),
+        ReportKind::LockViolation => (false, vec![b], format!("New top-level section 'server' in {b_shown}: only the first layer may add sections")),
+        ReportKind::Unreadable => (false, vec![b], format!("Skipped unreadable {b_shown}: permission denied")),
+        ReportKind::RemoteUnavailable => (false, vec![], "Skipped remote layer https://config.example/app.yaml: connection refused".to_string()),
+        ReportKind::SchemaViolation => (true, vec![b], format!("Schema violation in {b_shown} at 'server.port': expected an integer")),
+        ReportKind::TypeMismatch => (true, vec![b], format!("Expected int at 'server.port', found str set in {b_shown}")),
+        ReportKind::InvalidQuantity => (true, vec![b], format!("Cannot read 'fast' at 'server.port' from {b_shown} as a duration")),
+        ReportKind::NotAFile => (false, vec![b], format!("Skipped {b_shown}: not a regular file")),
+        ReportKind::IgnoredDescendants => (
+            false,
+            vec![],
+            "The target is the base directory: 2 file(s) in its subdirectories were not merged, see TargetMode::AllDescendants".to_string(),
+        ),
+        ReportKind::ShadowedFile => (false, vec![a], format!("All 1 key(s) of {a_shown} are overridden by later files")),
+        ReportKind::DisabledFeature => (true, vec![b], format!("Left out 'server.port' of {b_shown}: feature 'tls' is not enabled")),
+        ReportKind::IndexOutOfRange => (
+            false,
+            vec![b],
+            format!("Index patch at 'server.hosts[3]' in {b_shown} is out of range: the sequence has 2 item(s); left out"),
+        ),
+    };
+    ReportEntry {
+        kind,
+        key_path: key_path.then(|| "server.port".to_string()),
+        files: files.into_iter().map(Path::to_path_buf).collect(),
+        message,
+    }
+}
+
+/// A report with a [`fabricated_entry`] of every kind, in the order of
+/// [`ReportKind::ALL`], and `/configs/a.yaml` and `/configs/b.yaml` as the
+/// files merged.
+pub fn fabricated_report() -> MergeReport {
+    let files = ["/configs/a.yaml", "/configs/b.yaml"].map(|path| ContributingFile {
+        path: PathBuf::from(path),
+        depth: 0,
+        sha256: crate::source::sha256_hex(path.as_bytes()),
+    });
+    MergeReport {
+        entries: ReportKind::ALL.iter().copied().map(fabricated_entry).collect(),
+        files: files.to_vec(),
+        ..MergeReport::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canonical_yaml_sorts_keys() {
+        let config: ConfigValue = serde_yaml::from_str("b: {y: 1, x: [{d: 1, c: 2}]}\na: 1\n").unwrap();
+        assert_eq!(canonical_yaml(&config), "a: 1\nb:\n  x:\n  - c: 2\n    d: 1\n  y: 1\n");
+    }
+
+    #[test]
+    fn test_snapshots_match_or_show_a_diff() {
+        let dir = fixture_tree(&[
+            ("config.yaml", "server: {port: 80, host: a}\n"),
+            ("prod/config.yaml", "server: {port: 8080}\n"),
+        ]);
+        let snapshots = tempfile::tempdir().unwrap();
+        let snapshot = snapshots.path().join("nested/prod.yaml");
+        let prod = dir.path().join("prod");
+
+        let err = check_snapshot("server:\n  port: 1\n", &snapshot, false).unwrap_err();
+        assert!(err.contains("UPDATE_SNAPSHOTS=1 to create it"), "{err}");
+
+        let (config, _) = merge_hierarchy(dir.path(), &prod, &MergeOptions::default()).unwrap();
+        check_snapshot(&canonical_yaml(&config), &snapshot, true).unwrap();
+        assert_eq!(fs::read_to_string(&snapshot).unwrap(), "server:\n  host: a\n  port: 8080\n");
+        assert_merge_snapshot(dir.path(), &prod, &MergeOptions::default(), &snapshot);
+
+        let err = check_snapshot("server:\n  host: b\n  port: 8080\n", &snapshot, false).unwrap_err();
+        assert!(
+            err.ends_with(" server:\n-  host: a\n+  host: b\n   port: 8080\nRun with UPDATE_SNAPSHOTS=1 to update it."),
+            "{err}"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "differs from snapshot")]
+    fn test_mismatch_panics() {
+        let dir = fixture_tree(&[("config.yaml", "port: 80\n")]);
+        let snapshots = fixture_tree(&[("root.yaml", "port: 81\n")]);
+        assert_merge_snapshot(dir.path(), dir.path(), &MergeOptions::default(), &snapshots.path().join("root.yaml"));
+    }
+
+    #[test]
+    fn test_fault_injector_faults_apply_in_order() {
+        let dir = fixture_tree(&[("a.yaml", "a: 1\n"), ("b.yaml", "b: 1\n")]);
+        let (a, b) = (dir.path().join("a.yaml"), dir.path().join("b.yaml"));
+        let source = FaultInjector::default()
+            .delay(&a, Duration::from_millis(30))
+            .fail(&a, io::ErrorKind::NotFound)
+            .corrupt(&a, "never read")
+            .corrupt(&b, "b: [2\n");
+
+        let started = std::time::Instant::now();
+        let err = source.read_to_string(&a.canonicalize().unwrap()).unwrap_err();
+        assert!(started.elapsed() >= Duration::from_millis(30));
+        match err.downcast_ref::<ConfigError>() {
+            Some(ConfigError::Io { source, .. }) => assert_eq!(source.kind(), io::ErrorKind::NotFound),
+            other => panic!("Expected Io, got {other:?}"),
+        }
+        let b = b.canonicalize().unwrap();
+        assert_eq!(source.read(&b).unwrap(), b"b: [2\n");
+        assert_eq!(source.fingerprint(&b).unwrap(), FsSource.fingerprint(&b).unwrap());
+        let err = crate::HierarchyMerger::with_source(dir.path(), MergeOptions::default(), source).merge(dir.path()).unwrap_err();
+        assert!(matches!(err, ConfigError::Io { .. }), "{err:?}");
+
+        // Other files read through.
+        let source = FaultInjector::default().delay_every_read(Duration::from_millis(1)).corrupt(&b, "b: 2\n");
+        let mut merger = crate::HierarchyMerger::with_source(dir.path(), MergeOptions::default(), source);
+        let config = merger.merge(dir.path()).unwrap();
+        assert_eq!(*config, serde_yaml::from_str::<ConfigValue>("a: 1\nb: 2\n").unwrap());
+    }
+
+    #[test]
+    fn test_fabricated_report_holds_every_kind() {
+        let report = fabricated_report();
+        let kinds: Vec<ReportKind> = report.entries.iter().map(|entry| entry.kind).collect();
+        assert_eq!(kinds, ReportKind::ALL);
+        let names: std::collections::HashSet<&str> = kinds.iter().map(|kind| kind.name()).collect();
+        assert_eq!(names.len(), kinds.len());
+        assert_eq!(report.summary().total(), ReportKind::ALL.len());
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(json["entries"][0]["kind"], "collision");
+        assert_eq!(json["entries"][0]["path"], "server.port");
+        assert_eq!(json["files"][1]["path"], "/configs/b.yaml");
+        let strict = MergeOptions {
+            strict: true,
+            ..MergeOptions::default()
+        };
+        assert!(matches!(strict.check_report(&report), Err(ConfigError::Strict { .. })));
+    }
+}
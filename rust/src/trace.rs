@@ -0,0 +1,224 @@
+//! `tracing` instrumentation, compiled in with the `tracing` feature.
+//!
+//! Everything is emitted with the target `hierarchical_config_merging`. The
+//! names below are stable:
+//!
+//! | kind  | name       | level | fields                          |
+//! |-------|------------|-------|---------------------------------|
+//! | span  | `discover` | info  | `base`, `target`                |
+//! | span  | `parse`    | info  | `files`                         |
+//! | span  | `merge`    | info  | `files`                         |
+//! | event | parse      | debug | `path`, `bytes`, `duration_us`  |
+//! | event | layer      | debug | `depth`, `files`                |
+//! | event | report     | warn  | `kind`, `message`               |
+//!
+//! Events are told apart by the `event` field (`"parse"`, `"layer"` or
+//! `"report"`). A layer's `depth` is the number quoted in the collision
+//! messages. [`crate::HierarchyMerger`] parses files lazily while merging, so
+//! its parse events are emitted inside the `merge` span and it has no `parse`
+//! span.
+//!
+//! Without the feature every hook is an empty inline function.
+
+use std::path::Path;
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+
+use crate::report::ReportEntry;
+
+#[cfg(feature = "tracing")]
+const TARGET: &str = "hierarchical_config_merging";
+
+/// Keeps a phase span entered until dropped.
+pub(crate) struct PhaseGuard {
+    #[cfg(feature = "tracing")]
+    _span: tracing::span::EnteredSpan,
+}
+
+/// Measures how long a file took to parse.
+pub(crate) struct Timer {
+    #[cfg(feature = "tracing")]
+    started: Instant,
+}
+
+impl Timer {
+    #[inline]
+    pub(crate) fn start() -> Self {
+        Timer {
+            #[cfg(feature = "tracing")]
+            started: Instant::now(),
+        }
+    }
+}
+
+#[inline]
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn discover_span(base_dir: &Path, target_path: &Path) -> PhaseGuard {
+    PhaseGuard {
+        #[cfg(feature = "tracing")]
+        _span: tracing::info_span!(
+            target: TARGET,
+            "discover",
+            base = %base_dir.display(),
+            target = %target_path.display()
+        )
+        .entered(),
+    }
+}
+
+#[inline]
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn parse_span(files: usize) -> PhaseGuard {
+    PhaseGuard {
+        #[cfg(feature = "tracing")]
+        _span: tracing::info_span!(target: TARGET, "parse", files).entered(),
+    }
+}
+
+#[inline]
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn merge_span(files: usize) -> PhaseGuard {
+    PhaseGuard {
+        #[cfg(feature = "tracing")]
+        _span: tracing::info_span!(target: TARGET, "merge", files).entered(),
+    }
+}
+
+#[inline]
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn parsed_file(path: &Path, bytes: usize, timer: Timer) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(
+        target: TARGET,
+        event = "parse",
+        path = %path.display(),
+        bytes,
+        duration_us = timer.started.elapsed().as_micros() as u64,
+    );
+}
+
+#[inline]
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn merged_layer(depth: i64, files: usize) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(target: TARGET, event = "layer", depth, files);
+}
+
+#[inline]
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn report_entries(entries: &[ReportEntry]) {
+    #[cfg(feature = "tracing")]
+    for entry in entries {
+        tracing::warn!(
+            target: TARGET,
+            event = "report",
+            kind = ?entry.kind,
+            message = %entry.message,
+        );
+    }
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use std::fmt;
+    use std::fs;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    use crate::{merge_hierarchy, MergeOptions};
+
+    /// Records span names and the fields of every event of this crate.
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Captured>);
+
+    #[derive(Default)]
+    struct Captured {
+        next_id: AtomicU64,
+        spans: Mutex<Vec<String>>,
+        events: Mutex<Vec<Vec<(String, String)>>>,
+    }
+
+    struct Fields(Vec<(String, String)>);
+
+    impl Visit for Fields {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0.push((field.name().to_string(), format!("{value:?}")));
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.push((field.name().to_string(), value.to_string()));
+        }
+    }
+
+    impl Subscriber for Capture {
+        fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+            metadata.target() == super::TARGET
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            self.0.spans.lock().unwrap().push(span.metadata().name().to_string());
+            Id::from_u64(self.0.next_id.fetch_add(1, Ordering::SeqCst) + 1)
+        }
+
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let mut fields = Fields(Vec::new());
+            event.record(&mut fields);
+            self.0.events.lock().unwrap().push(fields.0);
+        }
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    fn field<'a>(event: &'a [(String, String)], name: &str) -> Option<&'a str> {
+        event.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+
+    #[test]
+    fn test_merge_emits_phase_spans_and_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let leaf = dir.path().join("app");
+        fs::create_dir_all(&leaf).unwrap();
+        fs::write(dir.path().join("a.yaml"), "key: a\n").unwrap();
+        fs::write(dir.path().join("b.yaml"), "key: b\n").unwrap();
+        fs::write(leaf.join("config.yaml"), "key: leaf\n").unwrap();
+
+        let capture = Capture::default();
+        tracing::subscriber::with_default(capture.clone(), || {
+            merge_hierarchy(dir.path(), &leaf, &MergeOptions::default()).unwrap();
+        });
+
+        assert_eq!(*capture.0.spans.lock().unwrap(), ["discover", "parse", "merge"]);
+
+        let events = capture.0.events.lock().unwrap();
+        let of_kind = |kind: &str| -> Vec<&Vec<(String, String)>> {
+            events.iter().filter(|event| field(event, "event") == Some(kind)).collect()
+        };
+
+        let parses = of_kind("parse");
+        assert_eq!(parses.len(), 3);
+        assert!(parses.iter().all(|event| field(event, "bytes").is_some()
+            && field(event, "duration_us").is_some()
+            && field(event, "path").is_some()));
+
+        let layers = of_kind("layer");
+        assert_eq!(
+            layers.iter().map(|event| field(event, "files").unwrap()).collect::<Vec<_>>(),
+            ["2", "1"]
+        );
+
+        let reports = of_kind("report");
+        assert_eq!(reports.len(), 1);
+        assert_eq!(field(reports[0], "kind"), Some("Collision"));
+        assert!(field(reports[0], "message").unwrap().contains("'key'"));
+    }
+}
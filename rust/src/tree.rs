@@ -0,0 +1,268 @@
+//! Indented, truncated rendering of a config for logs, see [`DisplayTree`].
+
+use std::fmt::{self, Write};
+
+use crate::ConfigValue;
+use crate::keypath::{PathSegment, key_to_string};
+use crate::redact::{REDACTED, Redaction};
+
+/// Limits of a [`DisplayTree`]. Whatever goes beyond them is elided with a
+/// `...` line, so that rendering a huge config stays cheap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeOptions {
+    /// Levels of nesting shown below the top level; deeper collections show
+    /// their size only.
+    pub max_depth: usize,
+    /// Items shown per mapping or sequence.
+    pub max_items: usize,
+    /// Characters shown per string.
+    pub max_string_len: usize,
+    /// Lines rendered in total.
+    pub max_nodes: usize,
+    /// Values at matching key paths render as [`REDACTED`].
+    pub redaction: Redaction,
+}
+
+impl Default for TreeOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: 8,
+            max_items: 20,
+            max_string_len: 60,
+            max_nodes: 500,
+            redaction: Redaction::default(),
+        }
+    }
+}
+
+/// Renders a config as one `key: value` line per node, indented by two
+/// spaces per level: scalars with their type, collections with their size,
+/// sequence items by `[index]`.
+///
+/// ```
+/// # use hierarchical_config_merging::tree::DisplayTree;
+/// let config = serde_yaml::from_str("server: {port: 8080, hosts: [a]}\npassword: x").unwrap();
+/// assert_eq!(
+///     DisplayTree::new(&config).to_string(),
+///     "server: mapping (2)\n  port: 8080 (int)\n  hosts: sequence (1)\n    [0]: \"a\" (str)\npassword: <redacted>\n"
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct DisplayTree<'a> {
+    value: &'a ConfigValue,
+    options: TreeOptions,
+}
+
+impl<'a> DisplayTree<'a> {
+    pub fn new(value: &'a ConfigValue) -> Self {
+        Self::with_options(value, TreeOptions::default())
+    }
+
+    pub fn with_options(value: &'a ConfigValue, options: TreeOptions) -> Self {
+        Self { value, options }
+    }
+}
+
+impl fmt::Display for DisplayTree<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut renderer = Renderer {
+            options: &self.options,
+            out: f,
+            nodes: 0,
+        };
+        match self.value {
+            ConfigValue::Mapping(map) if !map.is_empty() => renderer.children(self.value, &mut Vec::new(), 0).map(drop),
+            ConfigValue::Sequence(items) if !items.is_empty() => {
+                renderer.children(self.value, &mut Vec::new(), 0).map(drop)
+            }
+            value => writeln!(renderer.out, "{}", renderer.describe(value)),
+        }
+    }
+}
+
+/// [`DisplayTree`] with the default limits but `max_depth` and `max_items`.
+pub fn render_tree(value: &ConfigValue, max_depth: usize, max_items: usize) -> String {
+    let options = TreeOptions {
+        max_depth,
+        max_items,
+        ..TreeOptions::default()
+    };
+    DisplayTree::with_options(value, options).to_string()
+}
+
+struct Renderer<'o, 'f, 'w> {
+    options: &'o TreeOptions,
+    out: &'f mut fmt::Formatter<'w>,
+    /// Lines written so far.
+    nodes: usize,
+}
+
+impl Renderer<'_, '_, '_> {
+    /// Writes the items of a non-empty collection at `depth`. Returns `false`
+    /// once the node budget is spent, after saying so.
+    fn children(&mut self, value: &ConfigValue, path: &mut Vec<PathSegment>, depth: usize) -> Result<bool, fmt::Error> {
+        let items: Box<dyn Iterator<Item = (PathSegment, &ConfigValue)>> = match value {
+            ConfigValue::Mapping(map) => Box::new(map.iter().map(|(key, item)| (PathSegment::Key(key_to_string(key)), item))),
+            ConfigValue::Sequence(items) => {
+                Box::new(items.iter().enumerate().map(|(index, item)| (PathSegment::Index(index), item)))
+            }
+            _ => return Ok(true),
+        };
+        let len = match value {
+            ConfigValue::Mapping(map) => map.len(),
+            ConfigValue::Sequence(items) => items.len(),
+            _ => 0,
+        };
+        let indent = "  ".repeat(depth);
+
+        for (shown, (segment, item)) in items.enumerate() {
+            if shown == self.options.max_items {
+                writeln!(self.out, "{indent}... {} more", len - shown)?;
+                break;
+            }
+            if self.nodes == self.options.max_nodes {
+                writeln!(self.out, "{indent}... truncated after {} nodes", self.options.max_nodes)?;
+                return Ok(false);
+            }
+            self.nodes += 1;
+            path.push(segment);
+            let label = path.last().map(ToString::to_string).unwrap_or_default();
+            let go_on = if self.options.redaction.matches(path) {
+                writeln!(self.out, "{indent}{label}: {REDACTED}")?;
+                true
+            } else {
+                writeln!(self.out, "{indent}{label}: {}", self.describe(item))?;
+                depth >= self.options.max_depth || self.children(item, path, depth + 1)?
+            };
+            path.pop();
+            if !go_on {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// One-line description of `value`: a scalar with its type, or the kind
+    /// and size of a collection.
+    fn describe(&self, value: &ConfigValue) -> String {
+        match value {
+            ConfigValue::Null => "null".to_string(),
+            ConfigValue::Bool(b) => format!("{b} (bool)"),
+            ConfigValue::Number(n) if n.is_f64() => format!("{n} (float)"),
+            ConfigValue::Number(n) => format!("{n} (int)"),
+            ConfigValue::String(s) => self.string(s),
+            ConfigValue::Mapping(map) if map.is_empty() => "{}".to_string(),
+            ConfigValue::Mapping(map) => format!("mapping ({})", map.len()),
+            ConfigValue::Sequence(items) if items.is_empty() => "[]".to_string(),
+            ConfigValue::Sequence(items) => format!("sequence ({})", items.len()),
+            ConfigValue::Tagged(tagged) => {
+                format!("{} {}", tagged.tag, self.describe(&tagged.value))
+            }
+        }
+    }
+
+    fn string(&self, s: &str) -> String {
+        let len = s.chars().count();
+        if len <= self.options.max_string_len {
+            return format!("{} (str)", quote(s));
+        }
+        let shown: String = s.chars().take(self.options.max_string_len).collect();
+        let mut text = quote(&shown);
+        text.insert_str(text.len() - 1, "...");
+        let _ = write!(text, " (str, {len} chars)");
+        text
+    }
+}
+
+fn quote(s: &str) -> String {
+    serde_json::to_string(s).unwrap_or_else(|_| format!("\"{s}\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> ConfigValue {
+        let mut text = String::from(
+            "service:\n  name: api\n  replicas: 3\n  ratio: 0.5\n  debug: false\n  tags: [a, b, c, d]\n  \
+             db: {host: db.local, password: hunter2, pool: {min: 1, max: 8}}\n\
+             description: A service whose description is far longer than anything a log line should carry\n\
+             empty: {}\nnothing: null\nversion: !semver 1.2.3\nlimits:\n",
+        );
+        for i in 0..30 {
+            text.push_str(&format!("  k{i}: {i}\n"));
+        }
+        serde_yaml::from_str(&text).unwrap()
+    }
+
+    #[test]
+    fn test_default_rendering_matches_golden() {
+        let options = TreeOptions {
+            max_items: 3,
+            ..TreeOptions::default()
+        };
+        assert_eq!(
+            DisplayTree::with_options(&sample(), options).to_string(),
+            "\
+service: mapping (6)
+  name: \"api\" (str)
+  replicas: 3 (int)
+  ratio: 0.5 (float)
+  ... 3 more
+description: \"A service whose description is far longer than anything a lo...\" (str, 79 chars)
+empty: {}
+... 3 more
+"
+        );
+    }
+
+    #[test]
+    fn test_tight_limits_match_golden() {
+        let options = TreeOptions {
+            max_depth: 1,
+            max_items: 10,
+            max_string_len: 10,
+            max_nodes: 12,
+            ..TreeOptions::default()
+        };
+        assert_eq!(
+            DisplayTree::with_options(&sample(), options).to_string(),
+            "\
+service: mapping (6)
+  name: \"api\" (str)
+  replicas: 3 (int)
+  ratio: 0.5 (float)
+  debug: false (bool)
+  tags: sequence (4)
+  db: mapping (3)
+description: \"A service ...\" (str, 79 chars)
+empty: {}
+nothing: null
+version: !semver \"1.2.3\" (str)
+limits: mapping (30)
+  ... truncated after 12 nodes
+"
+        );
+    }
+
+    #[test]
+    fn test_redaction_and_scalars() {
+        let config = sample();
+        let text = render_tree(&config, 8, 50);
+        assert!(text.contains("    password: <redacted>\n"), "{text}");
+        assert!(text.contains("    pool: mapping (2)\n      min: 1 (int)\n"), "{text}");
+        assert!(text.contains("  tags: sequence (4)\n    [0]: \"a\" (str)\n"), "{text}");
+        assert!(!text.contains("hunter2"));
+
+        let options = TreeOptions {
+            redaction: Redaction::none(),
+            ..TreeOptions::default()
+        };
+        assert!(
+            DisplayTree::with_options(&config, options)
+                .to_string()
+                .contains("hunter2")
+        );
+        assert_eq!(DisplayTree::new(&ConfigValue::from(1)).to_string(), "1 (int)\n");
+    }
+}
@@ -0,0 +1,383 @@
+//! Expected kinds of values, declared in a [`TYPES_FILE`] at the base
+//! directory, see [`MergeOptions::type_check`].
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::error::ConfigError;
+use crate::keypath::{format_key_path, get_segments, key_to_string, parse_key_path, PathSegment};
+use crate::options::{MergeOptions, TypeCheck};
+use crate::report::{ReportEntry, ReportKind};
+use crate::source::{parse_yaml_file, ConfigSource, FsSource};
+use crate::ConfigValue;
+
+/// Name of the file declaring the expected types, in the base directory.
+pub const TYPES_FILE: &str = "types.yaml";
+
+/// The kind a value is expected to have.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExpectedType {
+    Int,
+    /// A number, integers included.
+    Float,
+    Bool,
+    Str,
+    Null,
+    Map,
+    /// A sequence, of items of the given kind when there is one.
+    List(Option<Box<ExpectedType>>),
+}
+
+impl ExpectedType {
+    /// Whether `value` is of this kind. Tagged values are judged by what
+    /// they tag.
+    pub fn matches(&self, value: &ConfigValue) -> bool {
+        match (self, value) {
+            (_, ConfigValue::Tagged(tagged)) => self.matches(&tagged.value),
+            (ExpectedType::Int, ConfigValue::Number(number)) => number.is_i64() || number.is_u64(),
+            (ExpectedType::Float, ConfigValue::Number(_))
+            | (ExpectedType::Bool, ConfigValue::Bool(_))
+            | (ExpectedType::Str, ConfigValue::String(_))
+            | (ExpectedType::Null, ConfigValue::Null)
+            | (ExpectedType::Map, ConfigValue::Mapping(_))
+            | (ExpectedType::List(None), ConfigValue::Sequence(_)) => true,
+            (ExpectedType::List(Some(item)), ConfigValue::Sequence(items)) => items.iter().all(|value| item.matches(value)),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for ExpectedType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExpectedType::Int => f.write_str("int"),
+            ExpectedType::Float => f.write_str("float"),
+            ExpectedType::Bool => f.write_str("bool"),
+            ExpectedType::Str => f.write_str("str"),
+            ExpectedType::Null => f.write_str("null"),
+            ExpectedType::Map => f.write_str("map"),
+            ExpectedType::List(None) => f.write_str("list"),
+            ExpectedType::List(Some(item)) => write!(f, "list[{item}]"),
+        }
+    }
+}
+
+impl FromStr for ExpectedType {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        let name = name.trim();
+        if let Some(item) = name.strip_prefix("list[").and_then(|rest| rest.strip_suffix(']')) {
+            return Ok(ExpectedType::List(Some(Box::new(item.parse()?))));
+        }
+        Ok(match name {
+            "int" => ExpectedType::Int,
+            "float" => ExpectedType::Float,
+            "bool" => ExpectedType::Bool,
+            "str" => ExpectedType::Str,
+            "null" => ExpectedType::Null,
+            "map" => ExpectedType::Map,
+            "list" => ExpectedType::List(None),
+            _ => bail!("Unknown type '{name}': expected int, float, bool, str, null, map, list or list[<type>]"),
+        })
+    }
+}
+
+/// The kind of `value`, in the names of [`ExpectedType`].
+fn kind_of(value: &ConfigValue) -> &'static str {
+    match value {
+        ConfigValue::Null => "null",
+        ConfigValue::Bool(_) => "bool",
+        ConfigValue::Number(number) if number.is_f64() => "float",
+        ConfigValue::Number(_) => "int",
+        ConfigValue::String(_) => "str",
+        ConfigValue::Sequence(_) => "list",
+        ConfigValue::Mapping(_) => "map",
+        ConfigValue::Tagged(tagged) => kind_of(&tagged.value),
+    }
+}
+
+/// A value whose kind is not the expected one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeMismatch {
+    /// Dotted key path of the value.
+    pub path: String,
+    pub expected: ExpectedType,
+    /// Kind of the value found, such as `str`.
+    pub actual: &'static str,
+    /// The file that set the value, when known.
+    pub file: Option<PathBuf>,
+}
+
+impl fmt::Display for TypeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Expected {} at '{}', found {}", self.expected, self.path, self.actual)?;
+        match &self.file {
+            Some(file) => write!(f, " set in {}", file.display()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Expected kinds of values by key path.
+///
+/// Written as a mapping of dotted key paths to type names, such as
+/// `server.port: int`, `features.*: bool` or `hosts: list[str]`. A `*`
+/// segment matches every key of a mapping and every item of a sequence.
+/// Paths without a value are not checked: the rules say what a value must
+/// be, not that it must be there.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TypeRules {
+    rules: Vec<(Vec<PathSegment>, ExpectedType)>,
+}
+
+impl TypeRules {
+    /// Reads the rules from a YAML mapping.
+    pub fn from_value(value: &ConfigValue) -> Result<Self, ConfigError> {
+        let map = match value {
+            ConfigValue::Mapping(map) => map,
+            ConfigValue::Null => return Ok(Self::default()),
+            _ => return Err(anyhow!("Type rules must be a mapping of key paths to types").into()),
+        };
+        let mut rules = Vec::with_capacity(map.len());
+        for (path, name) in map {
+            let path = key_to_string(path);
+            let name = name.as_str().with_context(|| format!("The type of '{path}' is not a name"))?;
+            let expected = name.parse().with_context(|| format!("Invalid type of '{path}'"))?;
+            rules.push((parse_key_path(&path)?, expected));
+        }
+        Ok(Self { rules })
+    }
+
+    /// Reads the rules from the YAML file at `path`.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        Self::read(&FsSource, path)
+    }
+
+    /// Reads the rules from the YAML file at `path` through `source`.
+    pub(crate) fn read(source: &dyn ConfigSource, path: &Path) -> Result<Self, ConfigError> {
+        let value = parse_yaml_file(source, path)?;
+        Ok(Self::from_value(&value).with_context(|| format!("Invalid types file {}", path.display()))?)
+    }
+
+    /// Every value of `config` breaking a rule, rule by rule in document
+    /// order.
+    pub fn check(&self, config: &ConfigValue) -> Vec<TypeMismatch> {
+        let mut mismatches = Vec::new();
+        for (pattern, expected) in &self.rules {
+            let mut found = Vec::new();
+            matching(config, pattern, &mut Vec::new(), &mut found);
+            for (path, value) in found {
+                if !expected.matches(value) {
+                    mismatches.push(TypeMismatch {
+                        path: format_key_path(&path),
+                        expected: expected.clone(),
+                        actual: kind_of(value),
+                        file: None,
+                    });
+                }
+            }
+        }
+        mismatches
+    }
+}
+
+/// Collects the values of `value` at paths matching `pattern`.
+fn matching<'a>(
+    value: &'a ConfigValue,
+    pattern: &[PathSegment],
+    path: &mut Vec<PathSegment>,
+    found: &mut Vec<(Vec<PathSegment>, &'a ConfigValue)>,
+) {
+    let Some((segment, rest)) = pattern.split_first() else {
+        found.push((path.clone(), value));
+        return;
+    };
+    let wildcard = *segment == PathSegment::Key("*".to_string());
+    match value {
+        ConfigValue::Mapping(map) => {
+            for (key, item) in map {
+                let key = key_to_string(key);
+                if wildcard || *segment == PathSegment::Key(key.clone()) {
+                    path.push(PathSegment::Key(key));
+                    matching(item, rest, path, found);
+                    path.pop();
+                }
+            }
+        }
+        ConfigValue::Sequence(items) => {
+            for (index, item) in items.iter().enumerate() {
+                if wildcard || *segment == PathSegment::Index(index) {
+                    path.push(PathSegment::Index(index));
+                    matching(item, rest, path, found);
+                    path.pop();
+                }
+            }
+        }
+        ConfigValue::Tagged(tagged) => matching(&tagged.value, pattern, path, found),
+        _ => {}
+    }
+}
+
+/// The path of the types file of `base_dir` when [`MergeOptions::type_check`]
+/// is on and the file is there.
+pub(crate) fn rules_path(base_dir: &Path, options: &MergeOptions) -> Option<PathBuf> {
+    let path = base_dir.join(TYPES_FILE);
+    (options.type_check != TypeCheck::Off && path.is_file()).then_some(path)
+}
+
+/// The rules of `base_dir` when [`MergeOptions::type_check`] is on, read
+/// through `source`.
+pub(crate) fn load_rules(source: &dyn ConfigSource, base_dir: &Path, options: &MergeOptions) -> Result<Option<TypeRules>> {
+    match rules_path(base_dir, options) {
+        Some(path) => Ok(Some(TypeRules::read(source, &path)?)),
+        None => Ok(None),
+    }
+}
+
+/// Checks the merged `config`, naming for each mismatch the last of `files`,
+/// in merge order, that defines the value.
+pub(crate) fn check_merged(
+    rules: &TypeRules,
+    config: &ConfigValue,
+    files: &[(&Path, &ConfigValue)],
+    entries: &mut Vec<ReportEntry>,
+) {
+    for mut mismatch in rules.check(config) {
+        let path = parse_key_path(&mismatch.path).unwrap_or_default();
+        mismatch.file = files
+            .iter()
+            .rev()
+            .find(|(_, config)| get_segments(config, &path).is_some())
+            .map(|(file, _)| file.to_path_buf());
+        entries.push(mismatch_entry(mismatch));
+    }
+}
+
+/// Checks the config of a single file.
+pub(crate) fn check_layer(rules: &TypeRules, file: &Path, config: &ConfigValue, entries: &mut Vec<ReportEntry>) {
+    for mut mismatch in rules.check(config) {
+        mismatch.file = Some(file.to_path_buf());
+        entries.push(mismatch_entry(mismatch));
+    }
+}
+
+fn mismatch_entry(mismatch: TypeMismatch) -> ReportEntry {
+    ReportEntry {
+        kind: ReportKind::TypeMismatch,
+        message: mismatch.to_string(),
+        key_path: Some(mismatch.path),
+        files: mismatch.file.into_iter().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixture_tree;
+
+    fn yaml(text: &str) -> ConfigValue {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn test_wildcard_rules() {
+        let rules = TypeRules::from_value(&yaml("features.*: bool\nhosts: list[str]\nlimits.*.max: int\n")).unwrap();
+        let config = yaml(
+            "features: {a: true, b: 'yes', c: false}\nhosts: [x, 2]\n\
+             limits: {cpu: {max: 4}, memory: {max: 1.5}}\nother: 1\n",
+        );
+        let found: Vec<String> = rules.check(&config).iter().map(ToString::to_string).collect();
+        assert_eq!(
+            found,
+            [
+                "Expected bool at 'features.b', found str",
+                "Expected list[str] at 'hosts', found list",
+                "Expected int at 'limits.memory.max', found float",
+            ]
+        );
+        assert!(rules.check(&yaml("unrelated: 1\n")).is_empty());
+        assert!(TypeRules::from_value(&yaml("port: integer\n")).is_err());
+    }
+
+    #[test]
+    fn test_violation_of_the_deepest_layer_names_its_file() {
+        let dir = fixture_tree(&[
+            ("types.yaml", "server.port: int\nserver.host: str\n"),
+            ("config.yaml", "server: {host: 0.0.0.0, port: 80}\n"),
+            ("prod/config.yaml", "server: {replicas: 2}\n"),
+            ("prod/eu/config.yaml", "server: {port: '8080'}\n"),
+        ]);
+        let target = dir.path().join("prod/eu");
+        let leaf = target.canonicalize().unwrap().join("config.yaml");
+        for type_check in [TypeCheck::Merged, TypeCheck::EachLayer] {
+            let options = MergeOptions {
+                type_check,
+                ..MergeOptions::default()
+            };
+            let (config, report) = crate::merge_hierarchy(dir.path(), &target, &options).unwrap();
+            assert_eq!(config["server"]["port"], "8080");
+            assert_eq!(report.files.len(), 3, "types.yaml is not merged");
+            assert_eq!(report.entries.len(), 1, "{:?}", report.entries);
+            let entry = &report.entries[0];
+            assert_eq!(entry.kind, ReportKind::TypeMismatch);
+            assert_eq!(entry.key_path.as_deref(), Some("server.port"));
+            assert_eq!(entry.files, std::slice::from_ref(&leaf));
+            assert_eq!(
+                entry.message,
+                format!("Expected int at 'server.port', found str set in {}", leaf.display())
+            );
+
+            // The caching merger and the merges built on it agree.
+            let mut merger = crate::HierarchyMerger::new(dir.path(), options.clone());
+            let merged = (std::sync::Arc::new(config), report);
+            assert_eq!(merger.merge_with_report(&target).unwrap(), merged);
+            assert_eq!(merger.merge_with_report(&target).unwrap(), merged);
+            let many = crate::merge_many(dir.path(), std::slice::from_ref(&target), &options).unwrap();
+            assert_eq!(many[&target], (ConfigValue::clone(&merged.0), merged.1));
+        }
+
+        let (_, report) = crate::merge_hierarchy(dir.path(), &dir.path().join("prod"), &MergeOptions {
+            type_check: TypeCheck::Merged,
+            ..MergeOptions::default()
+        })
+        .unwrap();
+        assert!(report.entries.is_empty());
+    }
+
+    #[test]
+    fn test_merger_reads_the_types_file_again_when_it_changes() {
+        let dir = fixture_tree(&[
+            ("types.yaml", "port: int
+"),
+            ("config.yaml", "port: 80
+"),
+            ("prod/config.yaml", "port: high
+"),
+        ]);
+        let prod = dir.path().join("prod");
+        let options = MergeOptions {
+            type_check: TypeCheck::Merged,
+            ..MergeOptions::default()
+        };
+        let mut merger = crate::HierarchyMerger::new(dir.path(), options.clone());
+        let (_, report) = merger.merge_with_report(&prod).unwrap();
+        let kinds: Vec<_> = report.entries.iter().map(|entry| (entry.kind, entry.key_path.as_deref())).collect();
+        assert_eq!(kinds, [(ReportKind::TypeMismatch, Some("port"))]);
+        assert!(report.files.iter().all(|file| !file.path.ends_with(TYPES_FILE)));
+        assert_eq!(report, crate::merge_hierarchy(dir.path(), &prod, &options).unwrap().1);
+
+        std::fs::write(dir.path().join(TYPES_FILE), "port: str\nname: str\n").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(dir.path().join(TYPES_FILE))
+            .unwrap()
+            .set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(60))
+            .unwrap();
+        let (_, report) = merger.merge_with_report(&prod).unwrap();
+        assert!(report.entries.is_empty(), "{:?}", report.entries);
+        assert_eq!(report, crate::merge_hierarchy(dir.path(), &prod, &options).unwrap().1);
+    }
+}
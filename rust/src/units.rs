@@ -0,0 +1,300 @@
+//! Durations and byte sizes written as strings, such as `30s` or `512MB`,
+//! turned into numbers, see [`MergeOptions::units`].
+
+use std::path::Path;
+use std::time::Duration;
+
+use crate::keypath::{format_key_path, get_segments, key_to_string, PathSegment};
+use crate::options::{MergeOptions, Unit};
+use crate::redact::{glob_match, REDACTED};
+use crate::report::{ReportEntry, ReportKind};
+use crate::ConfigValue;
+
+const NANOS_PER_UNIT: &[(&str, u128)] = &[
+    ("ms", 1_000_000),
+    ("s", 1_000_000_000),
+    ("m", 60_000_000_000),
+    ("h", 3_600_000_000_000),
+];
+
+const BYTES_PER_UNIT: &[(&str, u128)] = &[
+    ("b", 1),
+    ("kb", 1_000),
+    ("mb", 1_000_000),
+    ("gb", 1_000_000_000),
+    ("tb", 1_000_000_000_000),
+    ("kib", 1 << 10),
+    ("mib", 1 << 20),
+    ("gib", 1 << 30),
+    ("tib", 1 << 40),
+];
+
+/// Parses a duration such as `30s`, `1.5h` or `1h30m`: numbers, decimals
+/// allowed, each followed by `ms`, `s`, `m` or `h`, in any case. `None`
+/// for anything else, and for a duration that is not a whole number of
+/// nanoseconds.
+///
+/// ```
+/// # use hierarchical_config_merging::parse_duration;
+/// # use std::time::Duration;
+/// assert_eq!(parse_duration("1m30s"), Some(Duration::from_secs(90)));
+/// assert_eq!(parse_duration("250MS"), Some(Duration::from_millis(250)));
+/// assert_eq!(parse_duration("30"), None);
+/// ```
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    let mut nanos: u128 = 0;
+    let mut rest = text.trim();
+    if rest.is_empty() {
+        return None;
+    }
+    while !rest.is_empty() {
+        let (amount, unit, after) = quantity(rest)?;
+        let per_unit = NANOS_PER_UNIT.iter().find(|(name, _)| name.eq_ignore_ascii_case(unit))?.1;
+        nanos = nanos.checked_add(amount.scale(per_unit)?)?;
+        rest = after.trim_start();
+    }
+    let secs = u64::try_from(nanos / 1_000_000_000).ok()?;
+    Some(Duration::new(secs, (nanos % 1_000_000_000) as u32))
+}
+
+/// Parses a byte size such as `512MB`, `1.5 GiB` or `1024`: a number,
+/// decimals allowed, followed by `B`, a decimal unit (`KB`, `MB`, `GB`,
+/// `TB`, powers of 1000) or a binary one (`KiB`, `MiB`, `GiB`, `TiB`,
+/// powers of 1024), in any case. A bare number counts bytes. `None` for
+/// anything else, and for a size that is not a whole number of bytes.
+///
+/// ```
+/// # use hierarchical_config_merging::parse_byte_size;
+/// assert_eq!(parse_byte_size("512MB"), Some(512_000_000));
+/// assert_eq!(parse_byte_size("1.5kib"), Some(1536));
+/// assert_eq!(parse_byte_size("lots"), None);
+/// ```
+pub fn parse_byte_size(text: &str) -> Option<u64> {
+    let (amount, unit, rest) = quantity(text.trim())?;
+    if !rest.trim().is_empty() {
+        return None;
+    }
+    let per_unit = match unit {
+        "" => 1,
+        unit => BYTES_PER_UNIT.iter().find(|(name, _)| name.eq_ignore_ascii_case(unit))?.1,
+    };
+    u64::try_from(amount.scale(per_unit)?).ok()
+}
+
+/// A decimal number as its digits and the count of those after the point.
+struct Amount {
+    digits: u128,
+    decimals: u32,
+}
+
+impl Amount {
+    /// The amount times `per_unit`, if that is whole.
+    fn scale(&self, per_unit: u128) -> Option<u128> {
+        let scaled = self.digits.checked_mul(per_unit)?;
+        let divisor = 10u128.checked_pow(self.decimals)?;
+        (scaled % divisor == 0).then_some(scaled / divisor)
+    }
+}
+
+/// The leading number of `text`, the letters of its unit and what follows.
+fn quantity(text: &str) -> Option<(Amount, &str, &str)> {
+    let number_end = text.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(text.len());
+    let (number, rest) = text.split_at(number_end);
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    if whole.is_empty() && fraction.is_empty() || fraction.contains('.') {
+        return None;
+    }
+    let digits = format!("{whole}{fraction}").parse().ok()?;
+    let rest = rest.trim_start();
+    let unit_end = rest.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(rest.len());
+    let (unit, rest) = rest.split_at(unit_end);
+    let amount = Amount {
+        digits,
+        decimals: fraction.len() as u32,
+    };
+    Some((amount, unit, rest))
+}
+
+/// `config` with the strings at key paths matching [`MergeOptions::units`]
+/// replaced by their value in the unit of the first matching pattern. A
+/// string that does not parse is kept and reported in `entries`, naming the
+/// last of `files`, in merge order, that defines it.
+pub(crate) fn normalize_units(
+    mut config: ConfigValue,
+    files: &[(&Path, &ConfigValue)],
+    options: &MergeOptions,
+    entries: &mut Vec<ReportEntry>,
+) -> ConfigValue {
+    if !options.units.is_empty() {
+        normalize_below(&mut config, &mut Vec::new(), files, options, entries);
+    }
+    config
+}
+
+fn normalize_below(
+    value: &mut ConfigValue,
+    path: &mut Vec<PathSegment>,
+    files: &[(&Path, &ConfigValue)],
+    options: &MergeOptions,
+    entries: &mut Vec<ReportEntry>,
+) {
+    match value {
+        ConfigValue::Mapping(map) => {
+            for (key, item) in map.iter_mut() {
+                path.push(PathSegment::Key(key_to_string(key)));
+                normalize_below(item, path, files, options, entries);
+                path.pop();
+            }
+        }
+        ConfigValue::Sequence(items) => {
+            for (index, item) in items.iter_mut().enumerate() {
+                path.push(PathSegment::Index(index));
+                normalize_below(item, path, files, options, entries);
+                path.pop();
+            }
+        }
+        ConfigValue::String(text) => {
+            let dotted = format_key_path(path);
+            let Some((_, unit)) = options
+                .units
+                .iter()
+                .find(|(pattern, _)| glob_match(pattern.as_bytes(), dotted.as_bytes()))
+            else {
+                return;
+            };
+            match convert(text, *unit) {
+                Some(converted) => *value = converted,
+                None => {
+                    let file = files.iter().rev().find(|(_, config)| get_segments(config, path).is_some());
+                    let origin = file.map(|(file, _)| format!(" in {}", file.display())).unwrap_or_default();
+                    let shown = if options.redaction.matches(path) { REDACTED } else { text.as_str() };
+                    entries.push(ReportEntry {
+                        kind: ReportKind::InvalidQuantity,
+                        message: format!("Cannot read '{shown}' at '{dotted}'{origin} as {}", unit.description()),
+                        key_path: Some(dotted),
+                        files: file.map(|(file, _)| file.to_path_buf()).into_iter().collect(),
+                    });
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// `text` in `unit`, as the value replacing it.
+fn convert(text: &str, unit: Unit) -> Option<ConfigValue> {
+    match unit {
+        Unit::Millis => {
+            let duration = parse_duration(text)?;
+            if duration.subsec_nanos() % 1_000_000 != 0 {
+                return None;
+            }
+            u64::try_from(duration.as_millis()).ok().map(ConfigValue::from)
+        }
+        Unit::Duration => {
+            let duration = parse_duration(text)?;
+            let mut map = serde_yaml::Mapping::new();
+            map.insert("secs".into(), duration.as_secs().into());
+            map.insert("nanos".into(), duration.subsec_nanos().into());
+            Some(ConfigValue::Mapping(map))
+        }
+        Unit::Bytes => parse_byte_size(text).map(ConfigValue::from),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixture_tree;
+
+    #[test]
+    fn test_parse_units() {
+        assert_eq!(parse_duration("1.5h"), Some(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("2H 15M"), Some(Duration::from_secs(8100)));
+        assert_eq!(parse_duration("0.5ms"), Some(Duration::from_micros(500)));
+        assert_eq!(parse_duration(" 10 s "), Some(Duration::from_secs(10)));
+        for invalid in ["", "s", "10", "10 parsecs", "1.2.3s", "-1s", "5d"] {
+            assert_eq!(parse_duration(invalid), None, "{invalid}");
+        }
+
+        assert_eq!(parse_byte_size("100b"), Some(100));
+        assert_eq!(parse_byte_size("2 Kb"), Some(2_000));
+        assert_eq!(parse_byte_size("1GiB"), Some(1 << 30));
+        assert_eq!(parse_byte_size("1.5tb"), Some(1_500_000_000_000));
+        assert_eq!(parse_byte_size("64"), Some(64));
+        for invalid in ["", "MB", "0.5b", "1 MB 2 KB", "12 bits"] {
+            assert_eq!(parse_byte_size(invalid), None, "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_merges_normalize_matching_strings() {
+        let dir = fixture_tree(&[
+            ("config.yaml", "timeouts: {read: 30s, write: 1m}\ncache: {size: 512MB}\nname: 10s\n"),
+            ("prod/config.yaml", "timeouts: {write: 1.5S}\ncache: {size: 2 GiB}\nretry: {backoff: 250ms}\n"),
+        ]);
+        let options = MergeOptions {
+            units: vec![
+                ("timeouts.*".to_string(), Unit::Millis),
+                ("retry.backoff".to_string(), Unit::Duration),
+                ("cache.size".to_string(), Unit::Bytes),
+            ],
+            ..MergeOptions::default()
+        };
+        let (config, report) = crate::merge_hierarchy(dir.path(), &dir.path().join("prod"), &options).unwrap();
+        assert_eq!(
+            config,
+            serde_yaml::from_str::<ConfigValue>(
+                "timeouts: {read: 30000, write: 1500}\ncache: {size: 2147483648}\nname: 10s\n\
+                 retry: {backoff: {secs: 0, nanos: 250000000}}\n"
+            )
+            .unwrap()
+        );
+        assert!(report.entries.is_empty());
+        let backoff: Duration = serde_yaml::from_value(config["retry"]["backoff"].clone()).unwrap();
+        assert_eq!(backoff, Duration::from_millis(250));
+    }
+
+    #[test]
+    fn test_unparseable_values_are_reported() {
+        let dir = fixture_tree(&[("config.yaml", "cache: {size: 1GB}\n"), ("prod/config.yaml", "cache: {size: huge}\n")]);
+        let options = MergeOptions {
+            units: vec![("cache.size".to_string(), Unit::Bytes)],
+            ..MergeOptions::default()
+        };
+        let prod = dir.path().join("prod");
+        let (config, report) = crate::merge_hierarchy(dir.path(), &prod, &options).unwrap();
+        assert_eq!(config["cache"]["size"], "huge");
+        let file = prod.canonicalize().unwrap().join("config.yaml");
+        assert_eq!(report.entries.len(), 1);
+        assert_eq!(report.entries[0].kind, ReportKind::InvalidQuantity);
+        assert_eq!(report.entries[0].files, std::slice::from_ref(&file));
+        assert_eq!(
+            report.entries[0].message,
+            format!("Cannot read 'huge' at 'cache.size' in {} as a byte size", file.display())
+        );
+
+        let strict = MergeOptions {
+            strict: true,
+            ..options
+        };
+        assert!(matches!(
+            crate::merge_hierarchy(dir.path(), &prod, &strict),
+            Err(crate::ConfigError::Strict { .. })
+        ));
+    }
+
+    #[test]
+    fn test_unparseable_redacted_values_are_masked() {
+        let dir = fixture_tree(&[("config.yaml", "auth: {token_ttl: hunter2-secret-value}
+")]);
+        let options = MergeOptions {
+            units: vec![("auth.*".to_string(), Unit::Millis)],
+            ..MergeOptions::default()
+        };
+        let (_, report) = crate::merge_hierarchy(dir.path(), dir.path(), &options).unwrap();
+        assert_eq!(report.entries.len(), 1);
+        let message = &report.entries[0].message;
+        assert!(message.starts_with("Cannot read '<redacted>' at 'auth.token_ttl'"), "{message}");
+    }
+}
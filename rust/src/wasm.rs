@@ -0,0 +1,74 @@
+//! JavaScript bindings, built with the `wasm` feature. They merge hierarchies
+//! held in memory so that a browser can preview exactly what the backend
+//! would merge.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::{merge_yaml_strings, ConfigValue, MergeOptions};
+
+#[derive(Serialize)]
+struct MergeResult {
+    config: ConfigValue,
+    warnings: Vec<String>,
+}
+
+/// Merges `files`, an object mapping paths relative to the base directory
+/// (such as `prod/config.yaml`) to YAML text, for `targetPath`. Returns
+/// `{config, warnings}`; see [`merge_yaml_strings`].
+///
+/// Throws with the error chain as message when a file does not parse, the
+/// target leaves the base directory, or the config has keys that are not
+/// strings, numbers or booleans.
+#[wasm_bindgen(js_name = mergeHierarchy)]
+pub fn merge_hierarchy(files: JsValue, target_path: &str) -> Result<JsValue, JsError> {
+    let files: BTreeMap<String, String> = serde_wasm_bindgen::from_value(files)?;
+    let files: Vec<(PathBuf, String)> = files
+        .into_iter()
+        .map(|(path, content)| (PathBuf::from(path), content))
+        .collect();
+
+    let (config, report) = merge_yaml_strings(&files, Path::new(target_path), &MergeOptions::default())
+        .map_err(|e| JsError::new(&format!("{e:#}")))?;
+    let result = MergeResult {
+        config,
+        warnings: report.warnings(),
+    };
+    Ok(result.serialize(&serde_wasm_bindgen::Serializer::json_compatible())?)
+}
+
+// Run with `wasm-pack test --node --features wasm`.
+#[cfg(all(test, target_arch = "wasm32"))]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[derive(Deserialize)]
+    struct Merged {
+        config: serde_json::Value,
+        warnings: Vec<String>,
+    }
+
+    fn merge(files: serde_json::Value, target_path: &str) -> Result<Merged, JsValue> {
+        let files = serde_wasm_bindgen::to_value(&files).unwrap();
+        let result = merge_hierarchy(files, target_path).map_err(JsValue::from)?;
+        Ok(serde_wasm_bindgen::from_value(result).unwrap())
+    }
+
+    #[wasm_bindgen_test]
+    fn test_merges_two_layers() {
+        let files = serde_json::json!({
+            "config.yaml": "server:\n  host: base\n  port: 80\n",
+            "prod/config.yaml": "server:\n  port: 8080\n",
+            "dev/config.yaml": "server:\n  port: 3000\n",
+        });
+
+        let merged = merge(files, "prod").unwrap();
+        assert_eq!(merged.config, serde_json::json!({"server": {"host": "base", "port": 8080}}));
+        assert!(merged.warnings.is_empty());
+        assert!(merge(serde_json::json!({"config.yaml": "a: [unclosed\n"}), "").is_err());
+    }
+}
@@ -0,0 +1,419 @@
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use anyhow::{anyhow, Result};
+use serde::de::DeserializeOwned;
+
+use crate::error::ConfigError;
+use crate::diff::{diff, Change, DiffEntry};
+use crate::keypath::{format_key_path, get_path, visit_leaves};
+use crate::redact::glob_match;
+use crate::{ConfigValue, HierarchyMerger, MergeOptions};
+
+/// Sent to subscribers every time the handle's config is replaced.
+#[derive(Debug, Clone)]
+pub struct ChangeEvent {
+    /// What changed, from the previous config to `config`.
+    pub changes: Vec<DiffEntry>,
+    /// The config now returned by [`ConfigHandle::current`].
+    pub config: Arc<ConfigValue>,
+}
+
+/// Sent to the subscribers of [`ConfigHandle::subscribe_path`] for each
+/// changed value under a matching key path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathChange {
+    /// Dotted key path of the change, as in [`DiffEntry::path_string`].
+    pub path: String,
+    /// The value before the update, `None` when it was added.
+    pub old: Option<ConfigValue>,
+    /// The value after the update, `None` when it was removed.
+    pub new: Option<ConfigValue>,
+}
+
+impl PathChange {
+    fn of(entry: &DiffEntry) -> Self {
+        let (old, new) = match &entry.change {
+            Change::Added(value) => (None, Some(value.clone())),
+            Change::Removed(value) => (Some(value.clone()), None),
+            Change::Modified { old, new } => (Some(old.clone()), Some(new.clone())),
+        };
+        PathChange {
+            path: entry.path_string(),
+            old,
+            new,
+        }
+    }
+}
+
+/// The receiving end of [`ConfigHandle::subscribe_path`], dereferencing to
+/// the channel's [`Receiver`]. Dropping it unsubscribes.
+pub struct PathSubscription {
+    receiver: Receiver<PathChange>,
+    _alive: Arc<()>,
+}
+
+impl Deref for PathSubscription {
+    type Target = Receiver<PathChange>;
+
+    fn deref(&self) -> &Receiver<PathChange> {
+        &self.receiver
+    }
+}
+
+struct PathSubscriber {
+    pattern: String,
+    sender: Sender<PathChange>,
+    /// Gone once the subscription is dropped, even if no change was sent
+    /// to it since.
+    alive: Weak<()>,
+}
+
+impl PathSubscriber {
+    /// Whether `entry` is the change of a path matching the pattern, or
+    /// replaces a value with a leaf at such a path.
+    fn matches(&self, entry: &DiffEntry) -> bool {
+        let pattern = self.pattern.as_bytes();
+        if glob_match(pattern, entry.path_string().as_bytes()) {
+            return true;
+        }
+        let values = match &entry.change {
+            Change::Added(value) | Change::Removed(value) => [Some(value), None],
+            Change::Modified { old, new } => [Some(old), Some(new)],
+        };
+        let mut matched = false;
+        for value in values.into_iter().flatten() {
+            visit_leaves(value, &mut |below, _| {
+                if !matched && !below.is_empty() {
+                    let path = format_key_path(&[entry.path.as_slice(), below].concat());
+                    matched = glob_match(pattern, path.as_bytes());
+                }
+            });
+        }
+        matched
+    }
+}
+
+struct HandleInner {
+    current: RwLock<Arc<ConfigValue>>,
+    /// Also serializes publishers so events arrive in swap order.
+    subscribers: Mutex<Vec<Sender<ChangeEvent>>>,
+    /// Locked after `subscribers`.
+    path_subscribers: Mutex<Vec<PathSubscriber>>,
+}
+
+/// Shared, cheaply clonable access to the current merged config.
+///
+/// Readers get an `Arc` snapshot and never observe a partially applied
+/// update: a new config is fully merged before it is swapped in.
+#[derive(Clone)]
+pub struct ConfigHandle {
+    inner: Arc<HandleInner>,
+}
+
+impl ConfigHandle {
+    pub fn new(config: ConfigValue) -> Self {
+        Self::from_arc(Arc::new(config))
+    }
+
+    pub fn from_arc(config: Arc<ConfigValue>) -> Self {
+        Self {
+            inner: Arc::new(HandleInner {
+                current: RwLock::new(config),
+                subscribers: Mutex::new(Vec::new()),
+                path_subscribers: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// Snapshot of the current config.
+    pub fn current(&self) -> Arc<ConfigValue> {
+        self.inner.current.read().unwrap().clone()
+    }
+
+    /// Deserializes the value at a dotted key path of the current config.
+    pub fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, ConfigError> {
+        let config = self.current();
+        let value = get_path(&config, path)?.ok_or_else(|| anyhow!("No value at '{path}'"))?;
+        Ok(serde_yaml::from_value(value.clone())
+            .map_err(|e| anyhow!("Value at '{path}' has an unexpected type: {e}"))?)
+    }
+
+    /// Receives a [`ChangeEvent`] for every later update. Dropping the
+    /// receiver unsubscribes.
+    pub fn subscribe(&self) -> Receiver<ChangeEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.inner.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// Receives a [`PathChange`] for every later change under a key path
+    /// matching `pattern`, such as `database.*`. Patterns are matched against
+    /// dotted key paths like those of [`crate::prune`]: `*` matches any run
+    /// of characters, dots included, and `?` one character.
+    ///
+    /// Changes are the entries of [`diff`]: a mapping added or removed as a
+    /// whole, or a replaced sequence, is one change at its own path, sent
+    /// when the pattern matches it or one of its leaves. Dropping the
+    /// receiver unsubscribes.
+    pub fn subscribe_path(&self, pattern: &str) -> PathSubscription {
+        let (sender, receiver) = mpsc::channel();
+        let alive = Arc::new(());
+        let _publishing = self.inner.subscribers.lock().unwrap();
+        let mut path_subscribers = self.inner.path_subscribers.lock().unwrap();
+        path_subscribers.retain(|subscriber| subscriber.alive.strong_count() > 0);
+        path_subscribers.push(PathSubscriber {
+            pattern: pattern.to_string(),
+            sender,
+            alive: Arc::downgrade(&alive),
+        });
+        PathSubscription {
+            receiver,
+            _alive: alive,
+        }
+    }
+
+    /// Replaces the current config and notifies subscribers, unless `config`
+    /// is the very same snapshot.
+    pub fn store(&self, config: Arc<ConfigValue>) {
+        let mut subscribers = self.inner.subscribers.lock().unwrap();
+        let previous = {
+            let mut current = self.inner.current.write().unwrap();
+            if Arc::ptr_eq(&current, &config) {
+                return;
+            }
+            std::mem::replace(&mut *current, config.clone())
+        };
+
+        let mut path_subscribers = self.inner.path_subscribers.lock().unwrap();
+        path_subscribers.retain(|subscriber| subscriber.alive.strong_count() > 0);
+        if subscribers.is_empty() && path_subscribers.is_empty() {
+            return;
+        }
+        let changes = diff(&previous, &config);
+        path_subscribers.retain(|subscriber| {
+            changes
+                .iter()
+                .filter(|entry| subscriber.matches(entry))
+                .all(|entry| subscriber.sender.send(PathChange::of(entry)).is_ok())
+        });
+        if subscribers.is_empty() {
+            return;
+        }
+        let event = ChangeEvent { changes, config };
+        subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+}
+
+/// Background thread re-merging one target and publishing changes into a
+/// [`ConfigHandle`]. Stops when dropped.
+///
+/// Files are polled: each tick runs [`HierarchyMerger::merge`], which only
+/// re-reads files whose fingerprint changed, merging again from the layer of
+/// the shallowest one down, and returns the cached snapshot when nothing
+/// did. A failed merge (e.g. a file saved half-way with invalid
+/// YAML) keeps the last good config in place.
+pub struct Watcher {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Watcher {
+    /// Merges `target_path` once and starts polling it every `interval`.
+    pub fn spawn(
+        base_dir: &Path,
+        target_path: &Path,
+        options: MergeOptions,
+        interval: Duration,
+    ) -> Result<(ConfigHandle, Watcher)> {
+        let mut merger = HierarchyMerger::new(base_dir, options);
+        let handle = ConfigHandle::from_arc(merger.merge(target_path)?);
+        let watcher = Self::with_merger(merger, target_path.to_path_buf(), interval, handle.clone());
+        Ok((handle, watcher))
+    }
+
+    /// Polls `target_path` with an existing merger, publishing into `handle`.
+    pub fn with_merger(
+        mut merger: HierarchyMerger,
+        target_path: PathBuf,
+        interval: Duration,
+        handle: ConfigHandle,
+    ) -> Watcher {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let thread = thread::spawn(move || {
+            while !thread_stop.load(Ordering::SeqCst) {
+                thread::park_timeout(interval);
+                if thread_stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                if let Ok(config) = merger.merge(&target_path) {
+                    handle.store(config);
+                }
+            }
+        });
+
+        Watcher {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::SystemTime;
+
+    fn yaml(text: &str) -> ConfigValue {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn test_readers_never_see_partial_updates() {
+        let handle = ConfigHandle::new(yaml("a: 0\nb: 0\n"));
+
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let handle = handle.clone();
+                thread::spawn(move || {
+                    loop {
+                        let config = handle.current();
+                        assert_eq!(config["a"], config["b"]);
+                        if config["a"] == 1000 {
+                            break;
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        let writer = {
+            let handle = handle.clone();
+            thread::spawn(move || {
+                for i in 1..=1000 {
+                    handle.store(Arc::new(yaml(&format!("a: {i}\nb: {i}\n"))));
+                }
+            })
+        };
+        writer.join().unwrap();
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        assert_eq!(handle.get::<u64>("b").unwrap(), 1000);
+    }
+
+    #[test]
+    fn test_subscribers_receive_diff() {
+        let handle = ConfigHandle::new(yaml("server:\n  port: 80\n"));
+        let events = handle.subscribe();
+        let dropped = handle.subscribe();
+        drop(dropped);
+
+        handle.store(Arc::new(yaml("server:\n  port: 8080\n")));
+
+        let event = events.recv().unwrap();
+        assert_eq!(event.changes.len(), 1);
+        assert_eq!(event.changes[0].path_string(), "server.port");
+        assert_eq!(event.config["server"]["port"], ConfigValue::from(8080));
+        assert_eq!(handle.inner.subscribers.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_path_subscribers_only_see_matching_changes() {
+        let handle = ConfigHandle::new(yaml("database:\n  host: db1\n  port: 5432\nlog: info\n"));
+        let events = handle.subscribe_path("database.*");
+        drop(handle.subscribe_path("database.*"));
+
+        handle.store(Arc::new(yaml("database:\n  host: db1\n  port: 5432\nlog: debug\n")));
+        assert!(events.try_recv().is_err());
+
+        handle.store(Arc::new(yaml("database:\n  host: db2\n  port: 5432\nlog: debug\n")));
+        let change = events.try_recv().unwrap();
+        assert_eq!(
+            change,
+            PathChange {
+                path: "database.host".to_string(),
+                old: Some("db1".into()),
+                new: Some("db2".into()),
+            }
+        );
+        assert!(events.try_recv().is_err());
+
+        // A section removed as a whole still reaches the subscribers of its leaves.
+        handle.store(Arc::new(yaml("log: debug\n")));
+        assert_eq!(events.try_recv().unwrap().path, "database");
+        assert_eq!(handle.inner.path_subscribers.lock().unwrap().len(), 1);
+        drop(events);
+        handle.store(Arc::new(yaml("log: info\n")));
+        assert!(handle.inner.path_subscribers.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_watcher_publishes_file_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.yaml");
+        fs::write(&config_path, "replicas: 1\n").unwrap();
+
+        let (handle, _watcher) =
+            Watcher::spawn(dir.path(), dir.path(), MergeOptions::default(), Duration::from_millis(10)).unwrap();
+        let events = handle.subscribe();
+        assert_eq!(handle.get::<u32>("replicas").unwrap(), 1);
+
+        fs::write(&config_path, "replicas: 3\nextra: true\n").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&config_path)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+
+        let event = events.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(event.changes.len(), 2);
+        assert_eq!(handle.get::<u32>("replicas").unwrap(), 3);
+    }
+
+    #[test]
+    fn test_watcher_with_metadata_publishes_only_file_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.yaml");
+        fs::write(&config_path, "replicas: 1\n").unwrap();
+        let options = MergeOptions {
+            embed_metadata: true,
+            ..MergeOptions::default()
+        };
+
+        let (handle, _watcher) = Watcher::spawn(dir.path(), dir.path(), options, Duration::from_millis(10)).unwrap();
+        let events = handle.subscribe();
+        // Polls of an unchanged hierarchy keep the snapshot, metadata included.
+        assert!(events.recv_timeout(Duration::from_millis(300)).is_err());
+
+        fs::write(&config_path, "replicas: 3\n").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&config_path)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+
+        let event = events.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(event.changes.iter().any(|change| change.path_string() == "replicas"));
+        assert_eq!(handle.get::<u32>("replicas").unwrap(), 3);
+        assert!(events.recv_timeout(Duration::from_millis(300)).is_err());
+    }
+}
@@ -0,0 +1,16 @@
+#!/bin/sh
+# Builds the library with the `ffi` feature, then compiles and runs the C test
+# against it. Needs a C compiler as `cc` (or `$CC`).
+set -eu
+
+crate_dir=$(cd "$(dirname "$0")/../.." && pwd)
+cargo build --manifest-path "$crate_dir/Cargo.toml" --no-default-features --features ffi
+lib_dir="$crate_dir/target/debug"
+
+work_dir=$(mktemp -d)
+trap 'rm -rf "$work_dir"' EXIT
+mkdir "$work_dir/prod"
+
+"${CC:-cc}" -I "$crate_dir/include" "$crate_dir/tests/ffi/test_merge.c" \
+    -L "$lib_dir" -lhierarchical_config_merging -Wl,-rpath,"$lib_dir" -o "$work_dir/test_merge"
+"$work_dir/test_merge" "$work_dir"
@@ -0,0 +1,49 @@
+/*
+ * Merges a two-layer hierarchy through the C API. Built and run by
+ * tests/ffi/run.sh.
+ */
+#include <assert.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "hierarchical_config_merging.h"
+
+static void write_file(const char *path, const char *content) {
+    FILE *file = fopen(path, "w");
+    assert(file != NULL);
+    fputs(content, file);
+    fclose(file);
+}
+
+int main(int argc, char **argv) {
+    assert(argc == 2);
+    const char *base = argv[1];
+    char target[4096], path[4096];
+    snprintf(target, sizeof target, "%s/prod", base);
+
+    snprintf(path, sizeof path, "%s/config.yaml", base);
+    write_file(path, "server:\n  host: base\n  port: 80\n");
+    snprintf(path, sizeof path, "%s/config.yaml", target);
+    write_file(path, "server:\n  port: 8080\n");
+
+    char *json = NULL, *warnings = NULL, *err = NULL;
+    HcmStatus status = hcm_merge(base, target, &json, &warnings, &err);
+    assert(status == HCM_STATUS_OK);
+    assert(strcmp(json, "{\"server\":{\"host\":\"base\",\"port\":8080}}") == 0);
+    assert(strcmp(warnings, "[]") == 0);
+    assert(err == NULL);
+    hcm_free(json);
+    hcm_free(warnings);
+
+    snprintf(path, sizeof path, "%s/missing", base);
+    status = hcm_merge(base, path, &json, NULL, &err);
+    assert(status == HCM_STATUS_HIERARCHY_ERROR);
+    assert(json == NULL && err != NULL);
+    hcm_free(err);
+
+    assert(hcm_merge(NULL, target, &json, NULL, NULL) == HCM_STATUS_NULL_ARGUMENT);
+
+    puts("ffi test passed");
+    return 0;
+}
@@ -13,10 +13,58 @@ from hierarchical_config_merging.config_merger import (
 # Import the Rust implementation
 # do not pass python implementation on error, we prefer to raise it
 try:
-    from .hierarchical_config_merging import rust_merge_hierarchical_configs
+    from .hierarchical_config_merging import (
+        rust_merge_hierarchical_configs,
+        rust_merge_with_files,
+        rust_merge_files,
+        rust_find_yaml_files,
+        rust_deep_merge,
+        rust_compute_override,
+        rust_merge_with_provenance,
+        rust_write_lockfile,
+        rust_generate_docs,
+        rust_cli_main,
+        rust_merge_with_report,
+        rust_merge_report_json,
+        rust_merge_to_yaml,
+        rust_merge_to_json,
+        rust_merge_to_properties,
+        rust_merge_to_env,
+        rust_merge_config_hash,
+        rust_to_properties,
+        rust_to_env,
+        rust_redact,
+        rust_render_tree,
+        rust_config_hash,
+        rust_get_config_value,
+        rust_merge_as_config,
+        Config,
+        ReportEntry,
+        TaggedValue,
+        LOGGER_NAME,
+        HierarchicalConfigError,
+        ParseError,
+        HierarchyError,
+        CollisionError,
+        ConflictError,
+    )
 except ImportError as e:
    raise e
 
+# Only built with the `async` cargo feature
+try:
+    from .hierarchical_config_merging import rust_merge_hierarchical_configs_async
+except ImportError:
+    pass
+
+
+def cli_main():
+    """Entry point of the `hier-config` console script: the command line of the Rust crate."""
+    import sys
+
+    sys.exit(rust_cli_main(sys.argv[1:]))
+
+
 # Expose all functions at the package level
 __all__ = [
     'find_yaml_files_in_hierarchy',
@@ -24,5 +72,38 @@ __all__ = [
     'merge_configs_by_depth',
     'merge_hierarchical_configs',
     '_deep_merge',
-    'rust_merge_hierarchical_configs'
+    'rust_merge_hierarchical_configs',
+    'rust_merge_with_files',
+    'rust_merge_files',
+    'rust_find_yaml_files',
+    'rust_deep_merge',
+    'rust_compute_override',
+    'rust_merge_with_provenance',
+    'rust_write_lockfile',
+    'rust_generate_docs',
+    'rust_cli_main',
+    'cli_main',
+    'rust_merge_with_report',
+    'rust_merge_report_json',
+    'rust_merge_to_yaml',
+    'rust_merge_to_json',
+    'rust_merge_to_properties',
+    'rust_merge_to_env',
+    'rust_merge_config_hash',
+    'rust_to_properties',
+    'rust_to_env',
+    'rust_redact',
+    'rust_render_tree',
+    'rust_config_hash',
+    'rust_get_config_value',
+    'rust_merge_as_config',
+    'Config',
+    'ReportEntry',
+    'TaggedValue',
+    'LOGGER_NAME',
+    'HierarchicalConfigError',
+    'ParseError',
+    'HierarchyError',
+    'CollisionError',
+    'ConflictError',
 ]
\ No newline at end of file
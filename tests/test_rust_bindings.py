@@ -0,0 +1,1508 @@
+#!/usr/bin/env python3
+"""
+Tests for functionality only exposed by the Rust bindings.
+"""
+
+import asyncio
+import datetime
+import json
+import logging
+import os
+import subprocess
+import tempfile
+import threading
+import time
+import yaml
+import pytest
+import sys
+from pathlib import Path
+
+# Add src to path
+sys.path.append(str(Path(__file__).parent.parent / "src"))
+
+import hierarchical_config_merging as hcm
+
+
+def test_merge_with_files_lists_files_in_merge_order():
+    """Test that contributing files are reported in merge order with hashes."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir)
+        target_dir = base_dir / "a" / "b"
+        target_dir.mkdir(parents=True)
+
+        (base_dir / "config.yaml").write_text("key: root")
+        (base_dir / "a" / "config.yaml").write_text("key: mid")
+        (target_dir / "config.yaml").write_text("key: leaf")
+
+        merged, errors, files = hcm.rust_merge_with_files(str(base_dir), str(target_dir))
+
+        assert merged == {"key": "leaf"}
+        assert errors == []
+        assert [Path(f["path"]).relative_to(base_dir.resolve()) for f in files] == [
+            Path("config.yaml"),
+            Path("a/config.yaml"),
+            Path("a/b/config.yaml"),
+        ]
+        assert [f["depth"] for f in files] == [0, 1, 2]
+
+        (base_dir / "a" / "config.yaml").write_text("key: changed")
+        _, _, changed = hcm.rust_merge_with_files(str(base_dir), str(target_dir))
+
+        assert changed[0]["sha256"] == files[0]["sha256"]
+        assert changed[1]["sha256"] != files[1]["sha256"]
+
+
+def test_paths_are_expanded_by_default():
+    """Test that $VAR in path arguments is expanded unless disabled."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir)
+        (base_dir / "envs" / "prod").mkdir(parents=True)
+        (base_dir / "envs" / "prod" / "config.yaml").write_text("env: prod")
+        os.environ["HCM_PY_TEST_ROOT"] = str(base_dir)
+
+        merged, _ = hcm.rust_merge_hierarchical_configs(
+            "$HCM_PY_TEST_ROOT", "${HCM_PY_TEST_ROOT}/envs/prod"
+        )
+        assert merged == {"env": "prod"}
+
+        with pytest.raises(RuntimeError):
+            hcm.rust_merge_hierarchical_configs(
+                "$HCM_PY_TEST_ROOT", "$HCM_PY_TEST_ROOT/envs/prod", expand_paths=False
+            )
+
+        with pytest.raises(RuntimeError, match="HCM_PY_TEST_UNSET"):
+            hcm.rust_merge_hierarchical_configs("$HCM_PY_TEST_UNSET", str(base_dir))
+
+
+def test_find_yaml_files_in_merge_order():
+    """Test that discovery alone lists the files the merge would use, in order."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir)
+        target_dir = base_dir / "a" / "b"
+        target_dir.mkdir(parents=True)
+        (base_dir / "other").mkdir()
+
+        (base_dir / "z.yaml").write_text("key: z")
+        (base_dir / "b.yml").write_text("key: b")
+        (base_dir / "a" / "config.yaml").write_text("key: a")
+        (target_dir / "config.yaml").write_text("key: leaf")
+        (base_dir / "other" / "config.yaml").write_text("key: other")
+        (base_dir / "notes.txt").write_text("not yaml")
+
+        files = hcm.rust_find_yaml_files(str(base_dir), str(target_dir))
+
+        assert [Path(f).relative_to(base_dir.resolve()) for f in files] == [
+            Path("b.yml"),
+            Path("z.yaml"),
+            Path("a/config.yaml"),
+            Path("a/b/config.yaml"),
+        ]
+        _, _, merged_files = hcm.rust_merge_with_files(str(base_dir), str(target_dir))
+        assert files == [f["path"] for f in merged_files]
+
+
+def test_find_yaml_files_path_errors():
+    """Test that path problems raise FileNotFoundError or ValueError."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir) / "base"
+        base_dir.mkdir()
+        outside = Path(temp_dir) / "outside"
+        outside.mkdir()
+
+        with pytest.raises(FileNotFoundError, match="missing"):
+            hcm.rust_find_yaml_files(str(base_dir), str(base_dir / "missing"))
+
+        with pytest.raises(ValueError, match="Target path .* is not within base directory"):
+            hcm.rust_find_yaml_files(str(base_dir), str(outside))
+
+
+def test_deep_merge_dicts():
+    """Test that rust_deep_merge matches the Rust deep_merge unit test."""
+    base = {"a": 1, "b": 2, "c": {"nested": "base"}}
+    override = {"b": 3, "d": 4, "c": {"nested": "override", "new": "value"}}
+
+    result = hcm.rust_deep_merge(base, override)
+
+    assert result == {"a": 1, "b": 3, "c": {"nested": "override", "new": "value"}, "d": 4}
+    assert result == hcm._deep_merge(base, override)
+    assert base == {"a": 1, "b": 2, "c": {"nested": "base"}}
+
+
+def test_deep_merge_value_types():
+    """Test that every supported type round-trips and lists are replaced."""
+    base = {"list": [1, 2], "flag": True, "ratio": 0.5, "none": None}
+    override = {"list": [{"x": "y"}], "flag": False}
+
+    assert hcm.rust_deep_merge(base, override) == {
+        "list": [{"x": "y"}],
+        "flag": False,
+        "ratio": 0.5,
+        "none": None,
+    }
+
+
+def test_deep_merge_rejects_unsupported_values():
+    """Test that unsupported values raise TypeError naming their key path."""
+    with pytest.raises(TypeError, match="'a.b\\[1\\]'"):
+        hcm.rust_deep_merge({"a": {"b": [1, {2, 3}]}}, {})
+
+    with pytest.raises(TypeError, match="dict key 1"):
+        hcm.rust_deep_merge({}, {"a": {1: "x"}})
+
+    with pytest.raises(TypeError, match="unknown_option"):
+        hcm.rust_deep_merge({}, {}, unknown_option=True)
+
+
+def test_parse_error_carries_location():
+    """Test that invalid YAML raises ParseError with path, line and column."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir)
+        bad_file = base_dir / "config.yaml"
+        bad_file.write_text("good: 1\nbad: [unclosed\n")
+
+        with pytest.raises(hcm.ParseError) as exc_info:
+            hcm.rust_merge_hierarchical_configs(str(base_dir), str(base_dir))
+
+        error = exc_info.value
+        assert isinstance(error, hcm.HierarchicalConfigError)
+        assert isinstance(error, RuntimeError)
+        assert Path(error.path) == bad_file.resolve()
+        assert error.line >= 2
+        assert error.column >= 1
+
+
+def test_hierarchy_error_carries_paths():
+    """Test that a target outside the base raises HierarchyError."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir) / "base"
+        outside = Path(temp_dir) / "outside"
+        base_dir.mkdir()
+        outside.mkdir()
+
+        with pytest.raises(hcm.HierarchyError, match="is not within base directory") as exc_info:
+            hcm.rust_merge_hierarchical_configs(str(base_dir), str(outside))
+        assert Path(exc_info.value.base) == base_dir.resolve()
+        assert Path(exc_info.value.target) == outside.resolve()
+        assert Path(exc_info.value.base_dir) == base_dir.resolve()
+        assert Path(exc_info.value.target_path) == outside.resolve()
+
+        with pytest.raises(hcm.HierarchyError) as exc_info:
+            hcm.rust_merge_with_files(str(base_dir), str(base_dir / "missing"))
+        assert exc_info.value.target == str(base_dir / "missing")
+        assert exc_info.value.base_dir == str(base_dir)
+        assert exc_info.value.target_path == str(base_dir / "missing")
+
+
+def test_strict_collision_raises_collision_error():
+    """Test that strict mode turns collisions into CollisionError."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir)
+        (base_dir / "a.yaml").write_text("key: a")
+        (base_dir / "b.yaml").write_text("key: b")
+
+        _, errors = hcm.rust_merge_hierarchical_configs(str(base_dir), str(base_dir))
+        assert len(errors) == 1
+
+        with pytest.raises(hcm.CollisionError) as exc_info:
+            hcm.rust_merge_hierarchical_configs(str(base_dir), str(base_dir), strict=True)
+        assert exc_info.value.entries == [{"kind": "collision", "message": errors[0]}]
+
+        with pytest.raises(hcm.CollisionError) as exc_info:
+            hcm.rust_merge_hierarchical_configs(str(base_dir), str(base_dir), collision_policy="error")
+        [(key_path, files)] = exc_info.value.collisions
+        assert key_path == "key"
+        assert [Path(file) for file in files] == [base_dir.resolve() / "a.yaml", base_dir.resolve() / "b.yaml"]
+
+
+def test_merge_with_provenance_names_winning_file():
+    """Test that provenance points at the file each value was taken from."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir)
+        target_dir = base_dir / "envs" / "prod"
+        target_dir.mkdir(parents=True)
+
+        (base_dir / "config.yaml").write_text(
+            "server:\n  host: base\n  port: 80\ncache:\n  ttl: 60\n"
+        )
+        (target_dir / "config.yaml").write_text("server:\n  port: 8080\ncache: disabled\n")
+
+        config, provenance, warnings = hcm.rust_merge_with_provenance(
+            str(base_dir), str(target_dir)
+        )
+
+        base_file = str(base_dir.resolve() / "config.yaml")
+        prod_file = str(target_dir.resolve() / "config.yaml")
+        assert config == {"server": {"host": "base", "port": 8080}, "cache": "disabled"}
+        assert provenance == {
+            "server.host": base_file,
+            "server.port": prod_file,
+            "cache": prod_file,
+        }
+        assert "cache.ttl" not in provenance
+        assert warnings == []
+
+
+def test_keyword_options_change_merge_behavior():
+    """Test that sequence_strategy, null_deletes, profiles and extensions apply."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir)
+        target_dir = base_dir / "app"
+        target_dir.mkdir()
+
+        (base_dir / "config.yaml").write_text("plugins: [auth]\ndebug: true\nenv: base\n")
+        (target_dir / "config.yaml").write_text("plugins: [metrics]\ndebug: null\n")
+        (target_dir / "config@prod.yaml").write_text("env: prod\n")
+        (target_dir / "extra.json").write_text('{"region": "eu"}')
+
+        merged, _ = hcm.rust_merge_hierarchical_configs(str(base_dir), str(target_dir))
+        assert merged == {"plugins": ["metrics"], "debug": None, "env": "base"}
+
+        merged, _ = hcm.rust_merge_hierarchical_configs(
+            str(base_dir),
+            str(target_dir),
+            sequence_strategy="append",
+            null_deletes=True,
+            profiles=["prod"],
+            extensions=[".yaml", ".json"],
+        )
+        assert merged == {"plugins": ["auth", "metrics"], "env": "prod", "region": "eu"}
+
+
+def test_aliases_and_deprecated_paths_warn():
+    """Test that aliased keys move to their canonical path with a deprecation warning."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir)
+        (base_dir / "config.yaml").write_text("db: {host: old, port: 5432}\ndatabase: {host: new}\nlegacy: 1\n")
+
+        merged, report = hcm.rust_merge_with_report(
+            base_dir, base_dir, aliases=[("db", "database")], deprecated_paths=["legacy"]
+        )
+        assert merged == {"database": {"host": "new", "port": 5432}, "legacy": 1}
+        assert [(entry.kind, entry.path) for entry in report] == [("deprecation", "db"), ("deprecation", "legacy")]
+
+
+def test_collision_policy_option():
+    """Test that collision_policy can silence or raise on collisions."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir)
+        (base_dir / "a.yaml").write_text("key: a")
+        (base_dir / "b.yaml").write_text("key: b")
+
+        _, errors = hcm.rust_merge_hierarchical_configs(
+            str(base_dir), str(base_dir), collision_policy="ignore"
+        )
+        assert errors == []
+
+        with pytest.raises(hcm.CollisionError) as exc_info:
+            hcm.rust_merge_hierarchical_configs(
+                str(base_dir), str(base_dir), collision_policy="error"
+            )
+        assert len(exc_info.value.entries) == 1
+
+
+def test_invalid_keyword_options_raise():
+    """Test that invalid values and unknown options are rejected eagerly."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        with pytest.raises(ValueError, match="replace, append, prepend, union"):
+            hcm.rust_merge_hierarchical_configs(temp_dir, temp_dir, sequence_strategy="extend")
+
+        with pytest.raises(ValueError, match="warn, ignore, error"):
+            hcm.rust_merge_hierarchical_configs(temp_dir, temp_dir, collision_policy="fail")
+
+        with pytest.raises(ValueError, match="extensions"):
+            hcm.rust_merge_hierarchical_configs(temp_dir, temp_dir, extensions=[])
+
+        with pytest.raises(TypeError, match="sequence_stratgy"):
+            hcm.rust_merge_hierarchical_configs(temp_dir, temp_dir, sequence_stratgy="append")
+
+        with pytest.raises(TypeError, match="profiles"):
+            hcm.rust_deep_merge({}, {}, profiles=["prod"])
+
+    assert hcm.rust_deep_merge({"l": [1]}, {"l": [2]}, sequence_strategy="prepend") == {"l": [2, 1]}
+
+
+def test_json_merge_patch_mode():
+    """Test that mode="json_merge_patch" follows RFC 7386."""
+    base = {"title": "Goodbye!", "author": {"givenName": "John", "familyName": "Doe"}, "tags": ["example"]}
+    patch = {"title": "Hello!", "author": {"familyName": None}, "tags": ["sample"]}
+    assert hcm.rust_deep_merge(base, patch, mode="json_merge_patch", sequence_strategy="append") == {
+        "title": "Hello!",
+        "author": {"givenName": "John"},
+        "tags": ["sample"],
+    }
+
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir)
+        (base_dir / "app").mkdir()
+        (base_dir / "config.yaml").write_text("server: {host: base, port: 80}\n")
+        (base_dir / "app" / "config.yaml").write_text("server: {host: null, port: 8080}\n")
+
+        merged, _ = hcm.rust_merge_hierarchical_configs(base_dir, base_dir / "app", mode="json_merge_patch")
+        assert merged == {"server": {"port": 8080}}
+
+        with pytest.raises(ValueError, match="deep, json_merge_patch"):
+            hcm.rust_merge_hierarchical_configs(base_dir, base_dir, mode="merge")
+
+
+def test_merge_releases_the_gil():
+    """Test that Python threads keep running while a merge reads and parses files."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir)
+        target_dir = base_dir / "app"
+        target_dir.mkdir()
+        content = "".join(f"key_{i}: {{value: {i}, items: [a, b, c]}}\n" for i in range(2000))
+        for name in range(20):
+            (base_dir / f"{name:02}.yaml").write_text(content)
+
+        spans = []
+
+        def merge():
+            started = time.perf_counter()
+            hcm.rust_merge_hierarchical_configs(str(base_dir), str(target_dir), collision_policy="ignore")
+            spans.append((started, time.perf_counter()))
+
+        # With the GIL held for the whole call, the main thread could not tick
+        # while both merges run; with it released, ticks land inside them.
+        ticks = []
+        threads = [threading.Thread(target=merge) for _ in range(2)]
+        for thread in threads:
+            thread.start()
+        while any(thread.is_alive() for thread in threads):
+            ticks.append(time.perf_counter())
+            time.sleep(0.001)
+        for thread in threads:
+            thread.join()
+
+        assert len(spans) == 2
+        (first_start, first_end), (second_start, second_end) = sorted(spans)
+        assert second_start < first_end, "merges did not overlap"
+        assert any(first_start < tick < first_end for tick in ticks)
+
+
+def test_overrides_dict_is_the_final_layer():
+    """Test that an overrides dict beats the deepest file and uses the merge options."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir)
+        target_dir = base_dir / "app"
+        target_dir.mkdir()
+        (base_dir / "config.yaml").write_text("server:\n  host: base\n  port: 80\nplugins: [auth]\n")
+        (target_dir / "config.yaml").write_text("server:\n  port: 8080\n")
+
+        merged, _ = hcm.rust_merge_hierarchical_configs(
+            str(base_dir),
+            str(target_dir),
+            overrides={"server": {"port": 9090}, "plugins": ["debug"]},
+            sequence_strategy="append",
+        )
+        assert merged == {"server": {"host": "base", "port": 9090}, "plugins": ["auth", "debug"]}
+
+        with pytest.raises(TypeError, match="'server.port'"):
+            hcm.rust_merge_hierarchical_configs(
+                str(base_dir), str(target_dir), overrides={"server": {"port": object()}}
+            )
+
+
+def test_non_string_keys_keep_their_type():
+    """Test that integer, boolean and null keys become matching Python keys."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir)
+        (base_dir / "config.yaml").write_text(
+            "ports:\n  80: http\n  443: https\nflags:\n  true: on\n  false: off\n  ~: unset\n"
+            "ratios:\n  0.5: half\n"
+        )
+
+        merged, _ = hcm.rust_merge_hierarchical_configs(str(base_dir), str(base_dir))
+
+        assert merged["ports"] == {80: "http", 443: "https"}
+        assert merged["flags"] == {True: "on", False: "off", None: "unset"}
+        assert merged["ratios"] == {0.5: "half"}
+
+
+def test_complex_keys_raise_type_error():
+    """Test that a sequence used as a key raises instead of being dropped."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir)
+        (base_dir / "config.yaml").write_text("routes:\n  ? [a, b]\n  : both\n")
+
+        with pytest.raises(TypeError, match="sequence key at 'routes'"):
+            hcm.rust_merge_hierarchical_configs(str(base_dir), str(base_dir))
+
+
+def test_large_unsigned_and_special_floats_round_trip():
+    """Test that u64 values above the i64 range and non-finite floats survive a merge."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir)
+        (base_dir / "config.yaml").write_text(
+            "mask: 18446744073709551615\nabove_i64: 9223372036854775808\n"
+            "limit: !!float .inf\nfloor: -.inf\nmissing: .nan\n"
+        )
+
+        merged, _ = hcm.rust_merge_hierarchical_configs(str(base_dir), str(base_dir))
+
+        assert merged["mask"] == 2**64 - 1 and isinstance(merged["mask"], int)
+        assert merged["above_i64"] == 2**63
+        assert merged["limit"] == float("inf")
+        assert merged["floor"] == float("-inf")
+        assert merged["missing"] != merged["missing"]
+
+    assert hcm.rust_deep_merge({"mask": 2**64 - 1}, {}) == {"mask": 2**64 - 1}
+
+
+def test_parse_datetimes_option():
+    """Test that parse_datetimes converts timestamps and dates but leaves other strings alone."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir)
+        (base_dir / "config.yaml").write_text(
+            "created: 2024-01-01T10:00:00Z\n"
+            "updated: 2024-03-05 08:30:15.25 +02:00\n"
+            "naive: 2024-01-01t10:00:00\n"
+            "release: 2024-06-30\n"
+            "build: '20240101'\n"
+            "not_a_day: 2024-02-30\n"
+        )
+
+        merged, _ = hcm.rust_merge_hierarchical_configs(str(base_dir), str(base_dir))
+        assert merged["created"] == "2024-01-01T10:00:00Z"
+
+        merged, _ = hcm.rust_merge_hierarchical_configs(str(base_dir), str(base_dir), parse_datetimes=True)
+        assert merged["created"] == datetime.datetime(2024, 1, 1, 10, 0, 0, tzinfo=datetime.timezone.utc)
+        assert merged["updated"] == datetime.datetime(
+            2024, 3, 5, 8, 30, 15, 250000, tzinfo=datetime.timezone(datetime.timedelta(hours=2))
+        )
+        assert merged["naive"] == datetime.datetime(2024, 1, 1, 10, 0, 0)
+        assert merged["naive"].tzinfo is None
+        assert merged["release"] == datetime.date(2024, 6, 30)
+        assert type(merged["release"]) is datetime.date
+        assert merged["build"] == "20240101"
+        assert merged["not_a_day"] == "2024-02-30"
+
+    assert hcm.rust_deep_merge({}, {"day": "2024-01-02"}, parse_datetimes=True) == {"day": datetime.date(2024, 1, 2)}
+    with pytest.raises(TypeError, match="parse_datetimes"):
+        hcm.rust_find_yaml_files(".", ".", parse_datetimes=True)
+
+
+def test_merge_with_report_returns_structured_entries():
+    """Test that report entries expose kind, key path, files and message."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir)
+        (base_dir / "database.yaml").write_text("database: {host: a}\n")
+        (base_dir / "storage.yaml").write_text("database: {host: b}\n")
+
+        merged, report = hcm.rust_merge_with_report(str(base_dir), str(base_dir))
+        _, warnings = hcm.rust_merge_hierarchical_configs(str(base_dir), str(base_dir))
+
+        assert merged == {"database": {"host": "b"}}
+        assert len(report) == 1
+        entry = report[0]
+        assert isinstance(entry, hcm.ReportEntry)
+        assert entry.kind == "collision"
+        assert entry.path == "database.host"
+        assert [Path(file).name for file in entry.files] == ["database.yaml", "storage.yaml"]
+        assert entry.message == warnings[0] == str(entry)
+        assert repr(entry).startswith("ReportEntry(kind='collision', path='database.host', message=")
+
+        empty_dir = base_dir / "empty"
+        empty_dir.mkdir()
+        _, report = hcm.rust_merge_with_report(str(empty_dir), str(empty_dir))
+        assert [(entry.kind, entry.path, entry.files) for entry in report] == [("empty_hierarchy", None, [])]
+
+
+GOLDEN_DIR = Path(__file__).parent / "golden"
+
+
+def test_merge_to_yaml_and_json_match_golden_files():
+    """Test that the Rust-side serializers reproduce the golden outputs."""
+    base_dir = GOLDEN_DIR / "hierarchy"
+    target_dir = base_dir / "service"
+
+    text, warnings = hcm.rust_merge_to_yaml(str(base_dir), str(target_dir))
+    assert text == (GOLDEN_DIR / "service.yaml").read_text()
+    assert warnings == []
+
+    text, _ = hcm.rust_merge_to_json(str(base_dir), str(target_dir))
+    assert text + "\n" == (GOLDEN_DIR / "service.json").read_text()
+
+    text, _ = hcm.rust_merge_to_json(str(base_dir), str(target_dir), pretty=True)
+    assert text + "\n" == (GOLDEN_DIR / "service.pretty.json").read_text()
+
+    text, _ = hcm.rust_merge_to_properties(str(base_dir), str(target_dir))
+    assert text == (GOLDEN_DIR / "service.properties").read_text()
+
+
+def test_merge_to_json_accepts_overrides_and_options():
+    """Test that the text functions take the same overrides and options as the merge."""
+    base_dir = GOLDEN_DIR / "hierarchy"
+    target_dir = base_dir / "service"
+
+    text, _ = hcm.rust_merge_to_json(
+        str(base_dir), str(target_dir), overrides={"features": ["audit"]}, sequence_strategy="append"
+    )
+    assert '"features":["metrics","tracing","audit"]' in text
+
+    with pytest.raises(TypeError, match="parse_datetimes"):
+        hcm.rust_merge_to_yaml(str(base_dir), str(target_dir), parse_datetimes=True)
+
+
+def test_to_properties_escapes_and_flattens():
+    """Test that rust_to_properties writes dotted, escaped .properties lines."""
+    config = {
+        "server": {"host": " example.com", "url": "http://a?b=c"},
+        "motd": "Grüße\nline two",
+        "tags": ["web", "api"],
+        "unset": None,
+    }
+    assert hcm.rust_to_properties(config) == (
+        "server.host=\\ example.com\n"
+        "server.url=http\\://a?b\\=c\n"
+        "motd=Gr\\u00FC\\u00DFe\\nline two\n"
+        "tags[0]=web\n"
+        "tags[1]=api\n"
+    )
+    text = hcm.rust_to_properties(config, join_sequences=True, empty_nulls=True)
+    assert text.endswith("tags=web,api\nunset=\n")
+
+
+def test_redact_masks_sensitive_values():
+    """Test that rust_redact masks default and custom key path patterns."""
+    config = {"db": {"host": "db", "password": "hunter2"}, "api_token": "t0k", "port": 80}
+    assert hcm.rust_redact(config) == {"db": {"host": "db", "password": "<redacted>"}, "api_token": "<redacted>", "port": 80}
+    assert hcm.rust_redact(config, ["db.*"]) == {"db": {"host": "<redacted>", "password": "<redacted>"}, "api_token": "t0k", "port": 80}
+    assert config["db"]["password"] == "hunter2"
+
+
+def test_config_hash_ignores_formatting():
+    """Test that equal configs hash alike however their files are written."""
+    with tempfile.TemporaryDirectory() as first, tempfile.TemporaryDirectory() as second:
+        Path(first, "config.yaml").write_text("server:\n  port: 80\n  hosts: [a, b]\nname: svc\n")
+        Path(second, "config.yaml").write_text("name: 'svc'\nserver: {hosts: [a, b], port: 0x50}\n")
+
+        first_hash, _ = hcm.rust_merge_config_hash(first, first)
+        second_hash, _ = hcm.rust_merge_config_hash(second, second)
+        assert first_hash == second_hash
+        assert len(first_hash) == 64
+        assert hcm.rust_config_hash({"name": "svc", "server": {"hosts": ["a", "b"], "port": 80}}) == first_hash
+
+        changed_hash, _ = hcm.rust_merge_config_hash(second, second, overrides={"name": "other"})
+        assert changed_hash != first_hash
+
+
+def test_compute_override_round_trips_through_deep_merge():
+    """Test that the computed override deep-merges onto the base to give the desired config."""
+    base = {"server": {"port": 80, "host": "a"}, "tags": ["x"], "old": 1}
+    desired = {"server": {"port": 8080, "host": "a"}, "tags": ["x", "y"]}
+
+    override = hcm.rust_compute_override(base, desired, null_deletes=True)
+    assert override == {"server": {"port": 8080}, "tags": ["x", "y"], "old": None}
+    assert hcm.rust_deep_merge(base, override, null_deletes=True) == desired
+
+    with pytest.raises(ValueError, match="'old'"):
+        hcm.rust_compute_override(base, desired)
+
+
+def test_interpolate_resolves_references_after_overrides():
+    """Test that ${path} references resolve against the merged config, overrides included."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir)
+        (base_dir / "config.yaml").write_text("paths: {base: /tmp}\nlog_dir: ${paths.base}/logs\nlit: $${x}\n")
+
+        merged, _ = hcm.rust_merge_hierarchical_configs(str(base_dir), str(base_dir))
+        assert merged["log_dir"] == "${paths.base}/logs"
+
+        merged, errors = hcm.rust_merge_hierarchical_configs(
+            str(base_dir), str(base_dir), overrides={"paths": {"base": "/srv"}}, interpolate=True
+        )
+        assert merged["log_dir"] == "/srv/logs"
+        assert merged["lit"] == "${x}"
+        assert errors == []
+
+        _, entries = hcm.rust_merge_with_report(str(base_dir), str(base_dir), overrides={"x": "${nope}"}, interpolate=True)
+        assert [entry.kind for entry in entries] == ["unresolved_reference"]
+
+        (base_dir / "config.yaml").write_text("a: ${b}\nb: ${a}\n")
+        with pytest.raises(hcm.HierarchicalConfigError) as excinfo:
+            hcm.rust_merge_hierarchical_configs(str(base_dir), str(base_dir), interpolate=True)
+        assert excinfo.value.chain == ["a", "b", "a"]
+
+
+def test_compose_defaults_selects_group_configs():
+    """Test that a Hydra-style defaults list nests the selected group configs."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir)
+        (base_dir / "optimizer").mkdir()
+        (base_dir / "optimizer" / "adam.yaml").write_text("lr: 0.001\nbeta1: 0.9\n")
+        (base_dir / "config.yaml").write_text("defaults:\n  - optimizer: adam\noptimizer:\n  lr: 0.01\n")
+
+        merged, _ = hcm.rust_merge_hierarchical_configs(str(base_dir), str(base_dir), compose_defaults=True)
+        assert merged == {"optimizer": {"lr": 0.01, "beta1": 0.9}}
+
+        (base_dir / "config.yaml").write_text("defaults:\n  - optimizer: sgd\n")
+        with pytest.raises(hcm.HierarchicalConfigError) as excinfo:
+            hcm.rust_merge_hierarchical_configs(str(base_dir), str(base_dir), compose_defaults=True)
+        assert excinfo.value.entry == "optimizer: sgd"
+        assert Path(excinfo.value.path).name == "config.yaml"
+
+
+def test_coerce_types_parses_string_overrides():
+    """Test that string overrides take the type of the value they override, in files and overrides alike."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir)
+        (base_dir / "env").mkdir()
+        (base_dir / "config.yaml").write_text("port: 80\nratio: 0.5\ndebug: false\n")
+        (base_dir / "env" / "config.yaml").write_text("port: '8080'\n")
+
+        merged, errors = hcm.rust_merge_hierarchical_configs(
+            str(base_dir), str(base_dir / "env"), overrides={"ratio": "0.75", "debug": "yes"}, coerce_types=True
+        )
+        assert merged == {"port": 8080, "ratio": 0.75, "debug": True}
+        assert errors == []
+
+        _, entries = hcm.rust_merge_with_report(str(base_dir), str(base_dir / "env"), coerce_types=True)
+        assert [entry.kind for entry in entries] == ["coercion"]
+
+        (base_dir / "env" / "config.yaml").write_text("port: http\n")
+        merged, errors = hcm.rust_merge_hierarchical_configs(
+            str(base_dir), str(base_dir / "env"), coerce_types=True, coercion_failure="keep_base"
+        )
+        assert merged["port"] == 80
+        assert "Type conflict at 'port'" in errors[0]
+
+        with pytest.raises(hcm.HierarchicalConfigError) as excinfo:
+            hcm.rust_merge_hierarchical_configs(
+                str(base_dir), str(base_dir / "env"), coerce_types=True, coercion_failure="error"
+            )
+        assert excinfo.value.key_path == "port"
+
+
+def test_report_json_and_cli_report_format():
+    """Test the JSON report of a merge, of a failed merge and of the CLI."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir)
+        (base_dir / "a.yaml").write_text("port: 80\n")
+        (base_dir / "b.yaml").write_text("port: 81\n")
+
+        merged, report_json = hcm.rust_merge_report_json(str(base_dir), str(base_dir))
+        report = json.loads(report_json)
+        assert merged == {"port": 81}
+        assert [(entry["kind"], entry["severity"], entry["path"]) for entry in report["entries"]] == [
+            ("collision", "warning", "port")
+        ]
+        assert [Path(file["path"]).name for file in report["files"]] == ["a.yaml", "b.yaml"]
+
+        with pytest.raises(hcm.CollisionError) as excinfo:
+            hcm.rust_merge_report_json(str(base_dir), str(base_dir), collision_policy="error")
+        assert json.loads(excinfo.value.report_json)["entries"][0]["severity"] == "error"
+
+        report_file = base_dir / "report.json"
+        cli = Path(__file__).parent.parent / "cli.py"
+        output = subprocess.run(
+            [sys.executable, str(cli), str(base_dir), str(base_dir), "--implementation", "rust",
+             "--report-format", "json", "--report-file", str(report_file)],
+            capture_output=True, text=True, check=True,
+        )
+        assert json.loads(output.stdout) == {"port": 81}
+        assert json.loads(report_file.read_text()) == report
+
+
+def test_defaults_option_is_the_lowest_layer():
+    """Test that defaults survive where no file sets them and are overridden where one does."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir)
+        (base_dir / "config.yaml").write_text("server:\n  port: 8080\n")
+
+        merged, errors = hcm.rust_merge_hierarchical_configs(
+            str(base_dir), str(base_dir), defaults={"server": {"port": 80, "workers": 4}}
+        )
+        assert merged == {"server": {"port": 8080, "workers": 4}}
+        assert errors == []
+
+
+def test_render_tree_truncates_and_masks():
+    """Test that rust_render_tree renders types, elides and honors redaction."""
+    config = {"db": {"host": "db", "password": "hunter2"}, "tags": ["a", "b", "c"], "note": "x" * 100}
+    text = hcm.rust_render_tree(config, max_items=2, max_string_len=5)
+    assert text == (
+        'db: mapping (2)\n'
+        '  host: "db" (str)\n'
+        '  password: <redacted>\n'
+        'tags: sequence (3)\n'
+        '  [0]: "a" (str)\n'
+        '  [1]: "b" (str)\n'
+        '  ... 1 more\n'
+        '... 1 more\n'
+    )
+    assert "hunter2" in hcm.rust_render_tree(config, patterns=[])
+    assert hcm.rust_render_tree(config, max_nodes=1).endswith("... truncated after 1 nodes\n")
+
+
+def test_lockfile_is_reproducible():
+    """Test that rust_write_lockfile notes sources and rewrites the same bytes."""
+    with tempfile.TemporaryDirectory() as base, tempfile.TemporaryDirectory() as out_dir:
+        Path(base, "prod").mkdir()
+        Path(base, "config.yaml").write_text("server: {host: base, port: 80}\nname: api\n")
+        Path(base, "prod", "config.yaml").write_text("server: {port: 8080}\n")
+        out = Path(out_dir, "effective.yaml")
+
+        assert hcm.rust_write_lockfile(base, Path(base, "prod"), out) == []
+        text = out.read_text()
+        assert "#   prod/config.yaml sha256:" in text
+        assert "server:  # from config.yaml, prod/config.yaml\n" in text
+        assert yaml.safe_load(text) == {"server": {"host": "base", "port": 8080}, "name": "api"}
+
+        hcm.rust_write_lockfile(base, Path(base, "prod"), out)
+        assert out.read_text() == text
+        hcm.rust_write_lockfile(base, Path(base, "prod"), out, provenance=False)
+        assert "# from" not in out.read_text()
+
+
+def test_lock_top_level_reports_new_sections():
+    """Test that lock_top_level reports sections added below the root, except exempt ones."""
+    with tempfile.TemporaryDirectory() as base:
+        Path(base, "svc").mkdir()
+        Path(base, "config.yaml").write_text("server: {port: 80}\n")
+        Path(base, "svc", "config.yaml").write_text("server: {port: 8080}\nexperimental: {x: 1}\n")
+
+        merged, report = hcm.rust_merge_with_report(base, Path(base, "svc"), lock_top_level=True)
+        assert merged == {"server": {"port": 8080}, "experimental": {"x": 1}}
+        assert [(entry.kind, entry.path) for entry in report] == [("lock_violation", "experimental")]
+
+        _, report = hcm.rust_merge_with_report(
+            base, Path(base, "svc"), lock_top_level=True, lock_exempt_sections=["experimental"]
+        )
+        assert report == []
+
+
+def test_generate_docs_tables_every_key():
+    """Test that rust_generate_docs documents values, sources and redaction."""
+    with tempfile.TemporaryDirectory() as base:
+        Path(base, "prod").mkdir()
+        Path(base, "config.yaml").write_text("server: {port: 80, hosts: [a, b]}\ndb: {password: x}\n")
+        Path(base, "prod", "config.yaml").write_text("server: {port: 8080}\n")
+
+        docs = hcm.rust_generate_docs(base, Path(base, "prod"))
+        assert docs.startswith("# Configuration of `prod`\n")
+        assert "## `server`\n" in docs
+        assert "| `server.port` | `8080` | `prod/config.yaml` | `config.yaml`, `prod/config.yaml` |\n" in docs
+        assert "| `server.hosts` | sequence of 2 items |" in docs
+        assert "| `db.password` | `<redacted>` |" in docs
+        assert "| `db.password` | `x` |" in hcm.rust_generate_docs(base, Path(base, "prod"), redact_patterns=[])
+
+
+@pytest.mark.skipif(not hasattr(os, "geteuid") or os.geteuid() == 0, reason="needs file permissions to apply")
+def test_skip_unreadable_files():
+    """Test that skip_unreadable reports an unreadable file and merges the others."""
+    with tempfile.TemporaryDirectory() as base:
+        Path(base, "svc").mkdir()
+        Path(base, "config.yaml").write_text("name: base\nport: 80\n")
+        locked = Path(base, "svc", "config.yaml")
+        locked.write_text("port: 8080\n")
+        locked.chmod(0)
+        try:
+            with pytest.raises(hcm.HierarchicalConfigError):
+                hcm.rust_merge_with_report(base, Path(base, "svc"))
+
+            merged, report = hcm.rust_merge_with_report(base, Path(base, "svc"), skip_unreadable=True)
+            assert merged == {"name": "base", "port": 80}
+            assert [(entry.kind, entry.files) for entry in report] == [("unreadable", [locked.resolve()])]
+        finally:
+            locked.chmod(0o644)
+
+
+def test_embed_metadata_names_the_sources():
+    """Test that embed_metadata adds the merged files under a reserved key."""
+    with tempfile.TemporaryDirectory() as base:
+        Path(base, "prod").mkdir()
+        Path(base, "config.yaml").write_text("port: 80\n")
+        Path(base, "prod", "config.yaml").write_text("port: 8080\n")
+
+        merged, _, files = hcm.rust_merge_with_files(base, Path(base, "prod"), embed_metadata=True)
+        meta = merged.pop("__meta__")
+        assert merged == {"port": 8080}
+        assert meta["target"] == str(Path(base, "prod").resolve())
+        assert [(file["path"], file["sha256"]) for file in meta["files"]] == [(f["path"], f["sha256"]) for f in files]
+
+        merged, _ = hcm.rust_merge_with_report(base, Path(base, "prod"), embed_metadata=True, metadata_key="_origin")
+        assert set(merged) == {"port", "_origin"}
+
+        Path(base, "prod", "config.yaml").write_text("__meta__: {}\n")
+        with pytest.raises(hcm.HierarchicalConfigError, match="__meta__"):
+            hcm.rust_merge_with_report(base, Path(base, "prod"), embed_metadata=True)
+
+
+def test_type_check_reports_the_offending_file():
+    """Test that type_check reports a value of the wrong kind with the file setting it."""
+    with tempfile.TemporaryDirectory() as base:
+        Path(base, "prod").mkdir()
+        Path(base, "types.yaml").write_text("server.port: int\nfeatures.*: bool\n")
+        Path(base, "config.yaml").write_text("server: {port: 80}\nfeatures: {a: true}\n")
+        Path(base, "prod", "config.yaml").write_text("features: {b: 'yes'}\n")
+
+        merged, report = hcm.rust_merge_with_report(base, Path(base, "prod"), type_check="merged")
+        assert merged == {"server": {"port": 80}, "features": {"a": True, "b": "yes"}}
+        assert [(entry.kind, entry.path, entry.files) for entry in report] == [
+            ("type_mismatch", "features.b", [Path(base, "prod", "config.yaml").resolve()])
+        ]
+
+        with pytest.raises(ValueError, match="type_check"):
+            hcm.rust_merge_with_report(base, Path(base, "prod"), type_check="sometimes")
+
+
+def test_units_turn_strings_into_numbers():
+    """Test that units reads durations and byte sizes at the given key paths."""
+    with tempfile.TemporaryDirectory() as base:
+        Path(base, "config.yaml").write_text("timeout: 1m30s\ncache: 512MiB\nlimit: plenty\n")
+        units = [("timeout", "millis"), ("cache", "bytes"), ("limit", "bytes")]
+
+        merged, report = hcm.rust_merge_with_report(base, base, units=units)
+        assert merged == {"timeout": 90000, "cache": 512 * 1024 * 1024, "limit": "plenty"}
+        assert [(entry.kind, entry.path) for entry in report] == [("invalid_quantity", "limit")]
+
+        with pytest.raises(ValueError, match="unit"):
+            hcm.rust_merge_with_report(base, base, units=[("timeout", "fortnights")])
+
+
+def test_exempt_collisions_are_left_out():
+    """Test that collision_exempt_paths drops or downgrades deliberate collisions."""
+    with tempfile.TemporaryDirectory() as base:
+        Path(base, "eu.yaml").write_text("schema_version: 2\nregion: eu\n")
+        Path(base, "us.yaml").write_text("schema_version: 2\nregion: us\n")
+
+        _, report = hcm.rust_merge_with_report(base, base, collision_exempt_paths=["schema_*"])
+        assert [(entry.kind, entry.path) for entry in report] == [("collision", "region")]
+
+        _, report = hcm.rust_merge_with_report(
+            base, base, collision_exempt_paths=["schema_*"], report_exempt_collisions=True
+        )
+        assert [(entry.kind, entry.path) for entry in report] == [
+            ("exempt_collision", "schema_version"),
+            ("collision", "region"),
+        ]
+
+
+def test_cli_validate_exit_code_and_report():
+    """Test that cli.py validate fails on a bad target and reports every target in JSON."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir)
+        (base_dir / "config.yaml").write_text("service: api\n")
+        (base_dir / "good").mkdir()
+        (base_dir / "good" / "config.yaml").write_text("port: 80\n")
+        (base_dir / "bad").mkdir()
+        (base_dir / "bad" / "a.yaml").write_text("port: 80\n")
+        (base_dir / "bad" / "b.yaml").write_text("port: 81\n")
+        report_file = base_dir / "report.json"
+        cli = Path(__file__).parent.parent / "cli.py"
+
+        def validate(*args):
+            return subprocess.run(
+                [sys.executable, str(cli), "validate", "--base", str(base_dir), *args], capture_output=True, text=True
+            )
+
+        result = validate("--all-targets", "--require", "port", "--report-file", str(report_file))
+        assert result.returncode == 1
+        assert f"PASS {base_dir / 'good'}" in result.stdout
+        assert f"FAIL {base_dir / 'bad'}" in result.stdout
+        report = json.loads(report_file.read_text())
+        assert report["passed"] is False
+        assert [(Path(target["target"]).name, target["passed"]) for target in report["targets"]] == [
+            ("bad", False),
+            ("good", True),
+        ]
+        assert [(entry["kind"], entry["path"]) for entry in report["targets"][0]["report"]["entries"]] == [
+            ("collision", "port")
+        ]
+
+        assert validate("--target", str(base_dir / "good"), "--require", "port").returncode == 0
+        result = validate("--target", str(base_dir / "good"), "--require", "server.host")
+        assert result.returncode == 1
+        assert "Required key 'server.host' is not set" in result.stdout
+
+
+def test_cli_merges_with_python_by_default():
+    """Test that cli.py merges with the Python implementation unless --implementation rust is given."""
+    with tempfile.TemporaryDirectory() as base:
+        Path(base, "config.yaml").write_text("name: base\nport: 80\n")
+        cli = Path(__file__).parent.parent / "cli.py"
+
+        def run(*args):
+            return subprocess.run([sys.executable, str(cli), base, base, *args], capture_output=True, text=True)
+
+        result = run()
+        assert result.returncode == 0
+        assert result.stdout == json.dumps({"name": "base", "port": 80}, indent=2) + "\n"
+        assert json.loads(run("--implementation", "rust").stdout) == {"name": "base", "port": 80}
+
+        result = run("--max-depth", "1")
+        assert result.returncode == 2
+        assert "--max-depth 1 needs --implementation rust" in result.stderr
+        assert run("--implementation", "rust", "--max-depth", "1").returncode == 0
+
+
+def test_target_mode_with_the_base_as_target():
+    """Test that target_mode='all_descendants' merges the whole tree and exact mode reports what it leaves out."""
+    with tempfile.TemporaryDirectory() as base:
+        Path(base, "config.yaml").write_text("name: base\nport: 80\n")
+        Path(base, "prod").mkdir()
+        Path(base, "prod", "config.yaml").write_text("port: 8080\n")
+
+        config, report = hcm.rust_merge_with_report(base, base)
+        assert config == {"name": "base", "port": 80}
+        assert [(entry.kind, entry.severity) for entry in report] == [("ignored_descendants", "info")]
+
+        config, report = hcm.rust_merge_with_report(base, base, target_mode="all_descendants")
+        assert config == {"name": "base", "port": 8080}
+        assert list(report) == []
+
+        with pytest.raises(ValueError):
+            hcm.rust_merge_with_report(base, base, target_mode="everything")
+
+
+def test_collisions_between_two_files_are_grouped():
+    """Test that a multi-key collision is one entry and that the CLI prints a summary unless verbose."""
+    with tempfile.TemporaryDirectory() as base:
+        Path(base, "a.yaml").write_text("host: a\nport: 1\n")
+        Path(base, "b.yaml").write_text("host: b\nport: 2\n")
+
+        _, report = hcm.rust_merge_with_report(base, base)
+        assert [(entry.kind, entry.path) for entry in report] == [("collision", "host")]
+        assert "'host', 'port'" in report[0].message
+
+        cli = Path(__file__).parent.parent / "cli.py"
+
+        def run(*args):
+            return subprocess.run(
+                [sys.executable, str(cli), base, base, "--implementation", "rust", *args],
+                capture_output=True, text=True, check=True,
+            )
+
+        assert run().stderr == "Merge report: 1 warning (collision: 1)\n"
+        verbose = run("--verbose").stderr.splitlines()
+        assert verbose[0].endswith(report[0].message)
+        assert verbose[1] == "Merge report: 1 warning (collision: 1)"
+
+
+def test_filename_ignores_other_files():
+    """Test that filename merges one named file per level and ignores conflicting siblings."""
+    with tempfile.TemporaryDirectory() as base:
+        Path(base, "settings.yaml").write_text("port: 80\nname: base\n")
+        Path(base, "other.yaml").write_text("port: 1\n")
+        Path(base, "prod").mkdir()
+        Path(base, "prod", "settings.yaml").write_text("port: 8080\n")
+        Path(base, "prod", "other.yaml").write_text("port: 2\nname: other\n")
+
+        config, report = hcm.rust_merge_with_report(base, Path(base, "prod"), filename="settings.yaml")
+        assert config == {"port": 8080, "name": "base"}
+        assert list(report) == []
+
+        cli = Path(__file__).parent.parent / "cli.py"
+        output = subprocess.run(
+            [sys.executable, str(cli), base, str(Path(base, "prod")), "--implementation", "rust",
+             "--filename", "settings.yaml"],
+            capture_output=True, text=True, check=True,
+        )
+        assert json.loads(output.stdout) == {"port": 8080, "name": "base"}
+        assert output.stderr == ""
+
+
+def test_shadowed_files_are_reported():
+    """Test that report_shadowed_files names a mid-level file the leaf overrides entirely."""
+    with tempfile.TemporaryDirectory() as base:
+        Path(base, "config.yaml").write_text("name: base\nport: 80\n")
+        Path(base, "prod", "eu").mkdir(parents=True)
+        Path(base, "prod", "config.yaml").write_text("port: 8080\n")
+        Path(base, "prod", "eu", "config.yaml").write_text("port: 9090\n")
+        target = Path(base, "prod", "eu")
+
+        _, report = hcm.rust_merge_with_report(base, target, report_shadowed_files=True)
+        assert [(entry.kind, entry.severity) for entry in report] == [("shadowed_file", "info")]
+        assert [Path(file).name for file in report[0].files] == ["config.yaml"]
+        assert Path(report[0].files[0]).parent.name == "prod"
+
+        _, report = hcm.rust_merge_with_report(base, target)
+        assert list(report) == []
+
+
+def test_conditional_values_follow_selectors():
+    """Test that __when__ values resolve against the selectors kwarg, and unknown selectors fail."""
+    with tempfile.TemporaryDirectory() as base:
+        Path(base, "config.yaml").write_text(
+            "replicas: {__when__: {env: prod}, value: 3, else: 1}\n"
+            "debug: {__when__: {env: dev}, value: true}\n"
+        )
+
+        assert hcm.rust_merge(base, base, selectors={"env": "prod"}) == {"replicas": 3}
+        assert hcm.rust_merge(base, base, selectors={"env": "dev"}) == {"replicas": 1, "debug": True}
+
+        with pytest.raises(hcm.HierarchicalConfigError, match="Unknown selector 'env'") as exc_info:
+            hcm.rust_merge(base, base)
+        assert exc_info.value.selector == "env"
+        assert exc_info.value.key_path == "replicas"
+
+
+def test_merged_size_limits():
+    """Test that max_merged_nodes stops the merge at the file that passes it, and the report carries the size."""
+    with tempfile.TemporaryDirectory() as base:
+        Path(base, "config.yaml").write_text("".join(f"base_{i}: {i}\n" for i in range(10)))
+        Path(base, "app").mkdir()
+        Path(base, "app", "config.yaml").write_text("".join(f"app_{i}: {i}\n" for i in range(10)))
+        target = Path(base, "app")
+
+        with pytest.raises(hcm.HierarchicalConfigError, match="max_merged_nodes") as exc_info:
+            hcm.rust_merge(base, target, max_merged_nodes=15)
+        assert exc_info.value.limit == "max_merged_nodes"
+        assert Path(exc_info.value.path).parent.name == "app"
+
+        _, report_json = hcm.rust_merge_report_json(base, target)
+        assert json.loads(report_json)["stats"]["nodes"] == 21
+
+
+def test_timeout_is_taken_in_seconds():
+    """Test that a merge done within its timeout is unaffected, and that a negative timeout is rejected."""
+    with tempfile.TemporaryDirectory() as base:
+        Path(base, "config.yaml").write_text("port: 80\n")
+
+        assert hcm.rust_merge(base, base, timeout=30.0) == {"port": 80}
+        assert hcm.rust_merge_files([Path(base, "config.yaml")], timeout=30) == ({"port": 80}, [])
+        with pytest.raises(ValueError, match="Invalid timeout"):
+            hcm.rust_merge(base, base, timeout=-1.0)
+
+
+def test_key_case_respells_merged_keys():
+    """Test that key_case converts every key, and that keys becoming one are collisions."""
+    with tempfile.TemporaryDirectory() as base:
+        Path(base, "config.yaml").write_text("maxConnections: 10\nretry_policy: {BackoffMs: 5}\n")
+        Path(base, "prod").mkdir()
+        Path(base, "prod", "config.yaml").write_text("max_connections: 20\n")
+        target = Path(base, "prod")
+
+        assert hcm.rust_merge(base, target, key_case="kebab-case") == {
+            "max-connections": 20,
+            "retry-policy": {"backoff-ms": 5},
+        }
+        with pytest.raises(hcm.CollisionError, match="both become 'max_connections'"):
+            hcm.rust_merge(base, target, key_case="snake_case", collision_policy="error")
+        with pytest.raises(ValueError, match="Invalid key_case"):
+            hcm.rust_merge(base, target, key_case="PascalCase")
+
+
+def test_feature_sections_follow_the_enabled_features():
+    """Test that __if_feature__ sections are kept for enabled features and reported when left out."""
+    with tempfile.TemporaryDirectory() as base:
+        Path(base, "config.yaml").write_text(
+            "name: api\ntracing: {__if_feature__: otel, endpoint: collector, sampling: {__if_feature__: debug, rate: 1}}\n"
+        )
+
+        config, report = hcm.rust_merge_with_report(base, base, features={"otel", "debug"})
+        assert config == {"name": "api", "tracing": {"endpoint": "collector", "sampling": {"rate": 1}}}
+        assert list(report) == []
+
+        config, report = hcm.rust_merge_with_report(base, base, features=["otel"])
+        assert config == {"name": "api", "tracing": {"endpoint": "collector"}}
+        assert [(entry.kind, entry.severity) for entry in report] == [("disabled_feature", "info")]
+
+        assert hcm.rust_merge(base, base) == {"name": "api"}
+
+
+def test_index_patches_override_single_items():
+    """Test that index_patches merges a "[N]" mapping into one item, reporting or failing on indexes out of range."""
+    with tempfile.TemporaryDirectory() as base:
+        Path(base, "config.yaml").write_text("servers: [{host: a, port: 80}, {host: b, port: 80}]\n")
+        Path(base, "prod").mkdir()
+        Path(base, "prod", "config.yaml").write_text("servers: {'[1]': {port: 9090}, '[4]': {port: 1}}\n")
+        target = Path(base, "prod")
+
+        config, report = hcm.rust_merge_with_report(base, target, index_patches=True)
+        assert config == {"servers": [{"host": "a", "port": 80}, {"host": "b", "port": 9090}]}
+        assert [(entry.kind, entry.severity) for entry in report] == [("index_out_of_range", "warning")]
+        with pytest.raises(hcm.HierarchicalConfigError, match="out of range") as excinfo:
+            hcm.rust_merge(base, target, index_patches=True, index_out_of_range="error")
+        assert excinfo.value.key_path == "servers[4]"
+        assert hcm.rust_merge(base, target)["servers"] == {"[1]": {"port": 9090}, "[4]": {"port": 1}}
+
+
+def test_cli_main_matches_the_rust_cli(capsys):
+    """Test that rust_cli_main runs hier-config merge/get/explain and writes to sys.stdout and sys.stderr."""
+    with tempfile.TemporaryDirectory() as base:
+        Path(base, "config.yaml").write_text("server: {port: 80, host: a}\n")
+        Path(base, "prod").mkdir()
+        Path(base, "prod", "config.yaml").write_text("server: {port: 8080}\n")
+        target = str(Path(base, "prod"))
+
+        assert hcm.rust_cli_main(["merge", base, target]) == 0
+        assert json.loads(capsys.readouterr().out) == {"server": {"port": 8080, "host": "a"}}
+
+        assert hcm.rust_cli_main(["get", base, target, "server.port"]) == 0
+        assert capsys.readouterr().out == "8080\n"
+
+        assert hcm.rust_cli_main(["explain", base, target, "server.host"]) == 0
+        assert capsys.readouterr().out.startswith("server.host: ")
+
+        assert hcm.rust_cli_main(["merge", base]) == 2
+        assert capsys.readouterr().err.startswith("hier-config: missing base directory and target path")
+
+
+def test_env_exports_survive_the_shell():
+    """Test that rust_to_env quotes values so a shell reads them back unchanged."""
+    config = {"server": {"motd": "it's $HOME `id` \"quoted\"", "name": "two  words"}, "routes": [{"path": "/"}]}
+    text = hcm.rust_to_env(config, prefix="APP")
+    assert "ROUTES" not in text
+
+    script = text + 'printf "%s\\n" "$APP_SERVER_MOTD" "$APP_SERVER_NAME"'
+    output = subprocess.run(["sh", "-c", script], capture_output=True, text=True, check=True).stdout
+    assert output == "it's $HOME `id` \"quoted\"\ntwo  words\n"
+
+    with pytest.raises(ValueError, match="routes"):
+        hcm.rust_to_env(config, strict_values=True)
+
+    text, _ = hcm.rust_merge_to_env(GOLDEN_DIR / "hierarchy", GOLDEN_DIR / "hierarchy" / "service", separator="__")
+    assert "export SERVICE__LABELS__TIER='backend'\n" in text
+
+
+def test_on_conflict_callback_decides_values():
+    """Test that on_conflict can keep the numeric max of both sides."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir)
+        target_dir = base_dir / "app"
+        target_dir.mkdir()
+        (base_dir / "config.yaml").write_text("limits:\n  cpu: 8\n  memory: 512\nname: base\n")
+        (target_dir / "config.yaml").write_text("limits:\n  cpu: 4\n  memory: 1024\nname: app\n")
+
+        calls = []
+
+        def numeric_max(path, base_value, override_value):
+            calls.append(path)
+            if isinstance(base_value, int) and isinstance(override_value, int):
+                return max(base_value, override_value)
+            return override_value
+
+        merged, _ = hcm.rust_merge_hierarchical_configs(
+            str(base_dir), str(target_dir), overrides={"limits": {"cpu": 2}}, on_conflict=numeric_max
+        )
+
+        assert merged == {"limits": {"cpu": 8, "memory": 1024}, "name": "app"}
+        assert calls == ["limits.cpu", "limits.memory", "name", "limits.cpu"]
+
+
+def test_on_conflict_exception_becomes_conflict_error():
+    """Test that an exception from on_conflict aborts the merge with its path."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir)
+        target_dir = base_dir / "app"
+        target_dir.mkdir()
+        (base_dir / "config.yaml").write_text("db:\n  host: a\n")
+        (target_dir / "config.yaml").write_text("db:\n  host: b\n")
+
+        def refuse(path, base_value, override_value):
+            raise ValueError(f"{path} must not change")
+
+        with pytest.raises(hcm.ConflictError, match="db.host must not change") as exc_info:
+            hcm.rust_merge_hierarchical_configs(str(base_dir), str(target_dir), on_conflict=refuse)
+
+        assert isinstance(exc_info.value, hcm.HierarchicalConfigError)
+        assert exc_info.value.path == "db.host"
+        assert isinstance(exc_info.value.__cause__, ValueError)
+
+        with pytest.raises(hcm.ConflictError, match="'db.host'"):
+            hcm.rust_merge_hierarchical_configs(
+                str(base_dir), str(target_dir), on_conflict=lambda path, base, override: object()
+            )
+
+        with pytest.raises(TypeError, match="callable"):
+            hcm.rust_merge_hierarchical_configs(str(base_dir), str(target_dir), on_conflict="max")
+
+
+def test_log_warnings_emits_logging_records(caplog):
+    """Test that log_warnings logs collisions at WARNING and failed merges at ERROR."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir)
+        (base_dir / "database.yaml").write_text("database: {host: a}\n")
+        (base_dir / "storage.yaml").write_text("database: {host: b}\n")
+
+        with caplog.at_level(logging.WARNING, logger=hcm.LOGGER_NAME):
+            hcm.rust_merge_hierarchical_configs(str(base_dir), str(base_dir))
+            assert not [record for record in caplog.records if record.name == hcm.LOGGER_NAME]
+
+            _, warnings = hcm.rust_merge_hierarchical_configs(str(base_dir), str(base_dir), log_warnings=True)
+
+        records = [record for record in caplog.records if record.name == "hierarchical_config_merging"]
+        assert [record.levelno for record in records] == [logging.WARNING]
+        assert records[0].getMessage() == warnings[0]
+        assert "database.yaml" in records[0].getMessage() and "storage.yaml" in records[0].getMessage()
+
+        (base_dir / "broken.yaml").write_text("bad: [unclosed\n")
+        with caplog.at_level(logging.WARNING, logger=hcm.LOGGER_NAME):
+            with pytest.raises(hcm.ParseError):
+                hcm.rust_merge_to_yaml(str(base_dir), str(base_dir), log_warnings=True)
+
+        records = [record for record in caplog.records if record.name == hcm.LOGGER_NAME]
+        assert records[-1].levelno == logging.ERROR
+        assert "broken.yaml" in records[-1].getMessage()
+
+
+def test_path_like_arguments():
+    """Test that pathlib.Path and bytes paths are accepted like strings."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir)
+        target_dir = base_dir / "app"
+        target_dir.mkdir()
+        (base_dir / "config.yaml").write_text("level: base\n")
+        (target_dir / "config.yaml").write_text("level: app\n")
+
+        merged, _ = hcm.rust_merge_hierarchical_configs(base_dir, target_dir)
+        assert merged == {"level": "app"}
+
+        merged, _ = hcm.rust_merge_hierarchical_configs(os.fsencode(base_dir), os.fsencode(target_dir))
+        assert merged == {"level": "app"}
+
+        files = hcm.rust_find_yaml_files(base_dir, target_dir)
+        assert [Path(file) for file in files] == [
+            base_dir.resolve() / "config.yaml",
+            target_dir.resolve() / "config.yaml",
+        ]
+
+        with pytest.raises(TypeError):
+            hcm.rust_merge_hierarchical_configs(42, target_dir)
+
+
+@pytest.mark.skipif(sys.platform != "linux", reason="needs a filesystem accepting arbitrary bytes")
+def test_non_utf8_paths_round_trip():
+    """Test that a directory whose name is not valid UTF-8 can be merged and reported."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = os.fsencode(temp_dir)
+        target_dir = os.path.join(base_dir, b"caf\xe9")
+        os.mkdir(target_dir)
+        with open(os.path.join(target_dir, b"config.yaml"), "w") as f:
+            f.write("name: cafe\n")
+
+        merged, _ = hcm.rust_merge_hierarchical_configs(base_dir, target_dir)
+        assert merged == {"name": "cafe"}
+
+        files = hcm.rust_find_yaml_files(base_dir, target_dir)
+        assert [os.fsencode(file) for file in files] == [os.path.join(os.path.realpath(target_dir), b"config.yaml")]
+
+
+@pytest.mark.asyncio
+@pytest.mark.skipif(
+    not hasattr(hcm, "rust_merge_hierarchical_configs_async"),
+    reason="built without the 'async' cargo feature",
+)
+async def test_async_merge_matches_sync_merge():
+    """Test that the awaitable merge resolves to the sync result and raises the same errors."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir)
+        target_dir = base_dir / "app"
+        target_dir.mkdir()
+        (base_dir / "a.yaml").write_text("key: a\nlist: [1]\n")
+        (base_dir / "b.yaml").write_text("key: b\n")
+        (target_dir / "config.yaml").write_text("list: [2]\n")
+
+        expected = hcm.rust_merge_hierarchical_configs(base_dir, target_dir, sequence_strategy="append")
+        results = await asyncio.gather(
+            hcm.rust_merge_hierarchical_configs_async(base_dir, target_dir, sequence_strategy="append"),
+            hcm.rust_merge_hierarchical_configs_async(str(base_dir), str(target_dir), sequence_strategy="append"),
+        )
+        assert results == [expected, expected]
+        assert expected[0] == {"key": "b", "list": [1, 2]}
+
+        with pytest.raises(hcm.HierarchyError):
+            await hcm.rust_merge_hierarchical_configs_async(base_dir, base_dir / "missing")
+
+
+def test_get_config_value_by_key_path():
+    """Test nested lookups, sequence indexes and defaults for missing paths."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir)
+        target_dir = base_dir / "app"
+        target_dir.mkdir()
+        (base_dir / "config.yaml").write_text(
+            "service:\n  image: {name: api, tag: '1.0'}\n  ports: [80, 443]\n"
+        )
+        (target_dir / "config.yaml").write_text("service:\n  image: {tag: '2.1'}\n")
+
+        assert hcm.rust_get_config_value(base_dir, target_dir, "service.image.tag") == "2.1"
+        assert hcm.rust_get_config_value(base_dir, target_dir, "service.image") == {"name": "api", "tag": "2.1"}
+        assert hcm.rust_get_config_value(base_dir, target_dir, "service.ports[1]") == 443
+
+        assert hcm.rust_get_config_value(base_dir, target_dir, "service.replicas") is None
+        assert hcm.rust_get_config_value(base_dir, target_dir, "service.ports[5]", default=0) == 0
+        assert hcm.rust_get_config_value(base_dir, target_dir, "service.image.tag.major", "n/a") == "n/a"
+
+        with pytest.raises(ValueError):
+            hcm.rust_get_config_value(base_dir, target_dir, "service.ports[x]")
+
+        # Lookups reuse the merge, yet see edited files.
+        (target_dir / "config.yaml").write_text("service:\n  image: {tag: '2.2-rc1'}\n")
+        assert hcm.rust_get_config_value(base_dir, target_dir, "service.image.tag") == "2.2-rc1"
+
+
+def test_preserve_tags_wraps_tagged_values():
+    """Test that preserve_tags returns TaggedValue objects and leaves untagged values alone."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir)
+        (base_dir / "config.yaml").write_text(
+            "db:\n  password: !vault secret/db\n  host: localhost\n"
+            "hosts: !hostlist [a, b]\n"
+            "limits: !env {cpu: 2}\n"
+        )
+
+        merged, _ = hcm.rust_merge_hierarchical_configs(base_dir, base_dir)
+        assert merged["db"]["password"] == "secret/db"
+
+        merged, _ = hcm.rust_merge_hierarchical_configs(base_dir, base_dir, preserve_tags=True)
+        password = merged["db"]["password"]
+        assert isinstance(password, hcm.TaggedValue)
+        assert password.tag == "!vault"
+        assert password.value == "secret/db"
+        assert merged["db"]["host"] == "localhost"
+        assert merged["hosts"] == hcm.TaggedValue("!hostlist", ["a", "b"])
+        assert merged["limits"].tag == "!env" and merged["limits"].value == {"cpu": 2}
+        assert repr(password) == "TaggedValue('!vault', 'secret/db')"
+
+    # TaggedValue objects convert back to tagged values.
+    merged = hcm.rust_deep_merge({"a": hcm.TaggedValue("!vault", "x")}, {}, preserve_tags=True)
+    assert merged == {"a": hcm.TaggedValue("!vault", "x")}
+
+
+def test_merge_as_config_converts_lazily():
+    """Test Config indexing, dotted get, lazy conversion and to_dict equivalence."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir)
+        target_dir = base_dir / "app"
+        target_dir.mkdir()
+        services = "".join(f"  svc{i}: {{replicas: {i}, tags: [a, b]}}\n" for i in range(200))
+        (base_dir / "config.yaml").write_text(
+            "server:\n  host: base\n  port: 80\nservices:\n" + services
+        )
+        (target_dir / "config.yaml").write_text("server:\n  port: 8080\n")
+
+        cfg = hcm.rust_merge_as_config(base_dir, target_dir)
+        assert isinstance(cfg, hcm.Config)
+        assert cfg.warnings == []
+        assert cfg.converted_values == 0
+
+        assert cfg["server"]["port"] == 8080
+        assert cfg.get("server.host") == "base"
+        assert cfg.get("services.svc7.tags[1]") == "b"
+        assert cfg.get("server.tls", "off") == "off"
+        assert "server" in cfg and "missing" not in cfg
+        assert len(cfg) == 2 and len(cfg["services"]) == 200
+        assert list(cfg) == ["server", "services"]
+        assert isinstance(cfg.get("services.svc3"), hcm.Config)
+        with pytest.raises(KeyError):
+            cfg["missing"]
+
+        # Only the values accessed above were converted.
+        assert cfg.converted_values == 3
+
+        merged, _ = hcm.rust_merge_hierarchical_configs(base_dir, target_dir)
+        assert cfg.to_dict() == merged
+        assert cfg["services"]["svc3"].to_dict() == {"replicas": 3, "tags": ["a", "b"]}
+
+
+def test_merge_files_mixes_paths_and_priorities():
+    """Test that plain paths use their depth below base_dir and tuples their priority."""
+    with tempfile.TemporaryDirectory() as temp_dir:
+        base_dir = Path(temp_dir)
+        (base_dir / "svc").mkdir()
+        (base_dir / "config.yaml").write_text("env: base\nreplicas: 1\nowner: ops\n")
+        (base_dir / "svc" / "config.yaml").write_text("env: svc\n")
+        (base_dir / "pinned.yaml").write_text("replicas: 5\n")
+        (base_dir / "early.yaml").write_text("owner: dev\nregion: eu\n")
+
+        files = [
+            base_dir / "svc" / "config.yaml",
+            str(base_dir / "config.yaml"),
+            (base_dir / "pinned.yaml", 2),
+            (str(base_dir / "early.yaml"), -1),
+        ]
+        merged, warnings = hcm.rust_merge_files(files, base_dir=base_dir)
+        assert merged == {"env": "svc", "replicas": 5, "owner": "ops", "region": "eu"}
+        assert warnings == []
+
+        # Same level as svc/config.yaml: a collision on 'env'.
+        merged, warnings = hcm.rust_merge_files(
+            [base_dir / "svc" / "config.yaml", (base_dir / "config.yaml", 1)], base_dir=base_dir
+        )
+        assert len(warnings) == 1 and "'env'" in warnings[0]
+        with pytest.raises(hcm.CollisionError):
+            hcm.rust_merge_files(
+                [base_dir / "svc" / "config.yaml", (base_dir / "config.yaml", 1)],
+                base_dir=base_dir,
+                collision_policy="error",
+            )
+
+        # Without base_dir, deeper paths still win.
+        merged, _ = hcm.rust_merge_files([base_dir / "svc" / "config.yaml", base_dir / "config.yaml"])
+        assert merged["env"] == "svc"
+
+        with pytest.raises(hcm.HierarchyError):
+            hcm.rust_merge_files([base_dir / "config.yaml"], base_dir=base_dir / "svc")
+        with pytest.raises(TypeError):
+            hcm.rust_merge_files([base_dir / "config.yaml"], profiles=["prod"])
+
+
+if __name__ == "__main__":
+    test_merge_with_files_lists_files_in_merge_order()
+    test_paths_are_expanded_by_default()
+    test_find_yaml_files_in_merge_order()
+    test_find_yaml_files_path_errors()
+    test_deep_merge_dicts()
+    test_deep_merge_value_types()
+    test_deep_merge_rejects_unsupported_values()
+    test_parse_error_carries_location()
+    test_hierarchy_error_carries_paths()
+    test_strict_collision_raises_collision_error()
+    test_merge_with_provenance_names_winning_file()
+    test_keyword_options_change_merge_behavior()
+    test_aliases_and_deprecated_paths_warn()
+    test_collision_policy_option()
+    test_invalid_keyword_options_raise()
+    test_json_merge_patch_mode()
+    test_merge_releases_the_gil()
+    test_overrides_dict_is_the_final_layer()
+    test_non_string_keys_keep_their_type()
+    test_complex_keys_raise_type_error()
+    test_large_unsigned_and_special_floats_round_trip()
+    test_parse_datetimes_option()
+    test_merge_with_report_returns_structured_entries()
+    test_merge_to_yaml_and_json_match_golden_files()
+    test_merge_to_json_accepts_overrides_and_options()
+    test_to_properties_escapes_and_flattens()
+    test_report_json_and_cli_report_format()
+    test_defaults_option_is_the_lowest_layer()
+    test_render_tree_truncates_and_masks()
+    test_lockfile_is_reproducible()
+    test_lock_top_level_reports_new_sections()
+    test_generate_docs_tables_every_key()
+    test_skip_unreadable_files()
+    test_embed_metadata_names_the_sources()
+    test_type_check_reports_the_offending_file()
+    test_units_turn_strings_into_numbers()
+    test_exempt_collisions_are_left_out()
+    test_cli_validate_exit_code_and_report()
+    test_target_mode_with_the_base_as_target()
+    test_collisions_between_two_files_are_grouped()
+    test_filename_ignores_other_files()
+    test_shadowed_files_are_reported()
+    test_conditional_values_follow_selectors()
+    test_merged_size_limits()
+    test_timeout_is_taken_in_seconds()
+    test_key_case_respells_merged_keys()
+    test_feature_sections_follow_the_enabled_features()
+    # test_cli_main_matches_the_rust_cli needs pytest's capsys fixture.
+    test_env_exports_survive_the_shell()
+    test_redact_masks_sensitive_values()
+    test_config_hash_ignores_formatting()
+    test_compute_override_round_trips_through_deep_merge()
+    test_interpolate_resolves_references_after_overrides()
+    test_compose_defaults_selects_group_configs()
+    test_coerce_types_parses_string_overrides()
+    test_on_conflict_callback_decides_values()
+    test_on_conflict_exception_becomes_conflict_error()
+    # test_log_warnings_emits_logging_records needs pytest's caplog fixture.
+    test_path_like_arguments()
+    test_non_utf8_paths_round_trip()
+    test_get_config_value_by_key_path()
+    test_preserve_tags_wraps_tagged_values()
+    test_merge_as_config_converts_lazily()
+    test_merge_files_mixes_paths_and_priorities()
+    if hasattr(hcm, "rust_merge_hierarchical_configs_async"):
+        asyncio.run(test_async_merge_matches_sync_merge())